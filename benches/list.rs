@@ -4,6 +4,7 @@ use kv::ds::list::{Deque, List};
 use rand::{Rng, SeedableRng, rngs::ChaCha8Rng};
 use std::hint::black_box;
 
+#[allow(clippy::needless_late_init)]
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("list i32 stairs", |b| {
         b.iter(|| {
@@ -1,10 +1,52 @@
-use criterion::{Criterion, criterion_group, criterion_main};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use kv::{resp::bytes_to_resp, storage::Storage};
-use std::hint::black_box;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
 
-fn set(key: &'static str, value: i64) -> Vec<String> {
-    vec!["SET".to_string(), key.to_string(), value.to_string()]
+use kv::command::{self, CommandArg};
+use kv::storage::{ShardedStorage, Storage};
+
+/// Commands each simulated connection sends per iteration.
+const OPS_PER_CONNECTION: usize = 1_000;
+
+fn set(key: &'static str, value: i64) -> Vec<CommandArg> {
+    vec!["SET".into(), key.into(), value.to_string().into()]
+}
+
+/// A connection's workload: a SET then a GET on keys of its own.
+fn requests(connection: usize) -> Vec<Vec<CommandArg>> {
+    (0..OPS_PER_CONNECTION / 2)
+        .flat_map(|i| {
+            let key = format!("key:{}:{}", connection, i % 100);
+            [
+                vec!["SET".into(), key.clone().into(), i.to_string().into()],
+                vec!["GET".into(), key.into()],
+            ]
+        })
+        .collect()
+}
+
+/// Runs every connection's workload on its own thread, `iters` times,
+/// and returns the wall time.
+fn run_connections<F>(connections: usize, iters: u64, execute: F) -> Duration
+where
+    F: Fn(&[CommandArg]) + Sync,
+{
+    let workloads: Vec<Vec<Vec<CommandArg>>> = (0..connections).map(requests).collect();
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for workload in &workloads {
+            let execute = &execute;
+            scope.spawn(move || {
+                for _ in 0..iters {
+                    for request in workload {
+                        execute(request);
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
@@ -12,6 +54,40 @@ fn criterion_benchmark(c: &mut Criterion) {
         let mut storage = Storage::new();
         b.iter(|| storage.process_command(&set("key", 123)));
     });
+
+    // The single global lock the server used to have, against shards
+    let mut group = c.benchmark_group("Concurrent SET/GET");
+    for connections in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements(
+            (connections * OPS_PER_CONNECTION) as u64,
+        ));
+        group.bench_with_input(
+            BenchmarkId::new("single lock", connections),
+            &connections,
+            |b, &connections| {
+                let storage = Mutex::new(Storage::new());
+                b.iter_custom(|iters| {
+                    run_connections(connections, iters, |request| {
+                        let _ = storage.lock().unwrap().process_command(request);
+                    })
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("sharded", connections),
+            &connections,
+            |b, &connections| {
+                let storage = ShardedStorage::default();
+                b.iter_custom(|iters| {
+                    run_connections(connections, iters, |request| {
+                        let spec = command::resolve(request).unwrap();
+                        let _ = storage.execute(spec, request);
+                    })
+                });
+            },
+        );
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::resp::RESP;
+
+/// Entries kept by ACL LOG, like Redis' default `acllog-max-len`.
+pub const ACL_LOG_MAX_LEN: usize = 128;
+
+/// Repeats of the same denial within this window are folded into one entry.
+const GROUPING_WINDOW_MS: u64 = 60_000;
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Why a request was denied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DenialReason {
+    Command,
+    Key,
+    Channel,
+    Auth,
+}
+
+impl DenialReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DenialReason::Command => "command",
+            DenialReason::Key => "key",
+            DenialReason::Channel => "channel",
+            DenialReason::Auth => "auth",
+        }
+    }
+}
+
+struct AclLogEntry {
+    id: u64,
+    count: u64,
+    reason: DenialReason,
+    /// The command, key or channel that was denied
+    object: String,
+    username: String,
+    client_info: String,
+    created: u64,
+    updated: u64,
+}
+
+/// Recent authentication failures and permission denials, newest first.
+#[derive(Default)]
+pub struct AclLog {
+    entries: VecDeque<AclLogEntry>,
+    next_id: u64,
+}
+
+impl AclLog {
+    pub fn record(
+        &mut self,
+        reason: DenialReason,
+        object: &str,
+        username: &str,
+        client_info: &str,
+    ) {
+        let now = now_ms();
+        let similar = self.entries.iter_mut().find(|e| {
+            e.reason == reason
+                && e.object == object
+                && e.username == username
+                && now - e.updated < GROUPING_WINDOW_MS
+        });
+        if let Some(entry) = similar {
+            entry.count += 1;
+            entry.updated = now;
+            entry.client_info = client_info.to_string();
+            return;
+        }
+        self.entries.push_front(AclLogEntry {
+            id: self.next_id,
+            count: 1,
+            reason,
+            object: object.to_string(),
+            username: username.to_string(),
+            client_info: client_info.to_string(),
+            created: now,
+            updated: now,
+        });
+        self.next_id += 1;
+        self.entries.truncate(ACL_LOG_MAX_LEN);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The ACL LOG reply for the `count` most recent entries.
+    pub fn to_resp(&self, count: usize) -> RESP {
+        let now = now_ms();
+        let entries = self.entries.iter().take(count).map(|e| {
+            let bulk = |s: &str| RESP::BulkString(s.into());
+            RESP::Array(vec![
+                bulk("count"),
+                RESP::Integer(e.count as i64),
+                bulk("reason"),
+                bulk(e.reason.as_str()),
+                bulk("context"),
+                bulk("toplevel"),
+                bulk("object"),
+                bulk(&e.object),
+                bulk("username"),
+                bulk(&e.username),
+                bulk("age-seconds"),
+                bulk(&format!("{:.3}", (now - e.created) as f64 / 1000.0)),
+                bulk("client-info"),
+                bulk(&e.client_info),
+                bulk("entry-id"),
+                RESP::Integer(e.id as i64),
+                bulk("timestamp-created"),
+                RESP::Integer(e.created as i64),
+                bulk("timestamp-last-updated"),
+                RESP::Integer(e.updated as i64),
+            ])
+        });
+        RESP::Array(entries.collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_similar_entries_are_grouped() {
+        let mut log = AclLog::default();
+        log.record(DenialReason::Command, "get", "alice", "id=1");
+        log.record(DenialReason::Command, "get", "alice", "id=2");
+        log.record(DenialReason::Key, "secret", "alice", "id=2");
+        assert_eq!(log.len(), 2);
+        match log.to_resp(1) {
+            RESP::Array(entries) => match &entries[0] {
+                RESP::Array(fields) => {
+                    assert_eq!(fields[3], RESP::BulkString("key".into()));
+                }
+                other => panic!("unexpected entry {:?}", other),
+            },
+            other => panic!("unexpected reply {:?}", other),
+        }
+        log.reset();
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn test_log_is_capped() {
+        let mut log = AclLog::default();
+        for i in 0..ACL_LOG_MAX_LEN + 10 {
+            log.record(DenialReason::Auth, "AUTH", &i.to_string(), "");
+        }
+        assert_eq!(log.len(), ACL_LOG_MAX_LEN);
+    }
+}
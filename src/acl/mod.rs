@@ -0,0 +1,616 @@
+mod log;
+mod user;
+
+use std::collections::BTreeMap;
+use std::fs;
+
+pub use self::log::{AclLog, DenialReason};
+pub use self::user::{User, hash_password};
+use crate::client::Client;
+use crate::command::{self, COMMAND_TABLE, Command, CommandArg, CommandSpec, flags};
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+
+/// ACL categories commands can belong to, as listed by ACL CAT.
+pub const CATEGORIES: [&str; 29] = [
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+    "json",
+    "bloom",
+    "cuckoo",
+    "cms",
+    "topk",
+    "timeseries",
+    "vectorset",
+    "search",
+];
+
+const DEFAULT_USER: &str = "default";
+
+/// Users and the log of what they were denied.
+pub struct Acl {
+    users: BTreeMap<String, User>,
+    log: AclLog,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Acl {
+    pub fn new() -> Self {
+        let mut users = BTreeMap::new();
+        users.insert(DEFAULT_USER.to_string(), User::superuser(DEFAULT_USER));
+        Acl {
+            users,
+            log: AclLog::default(),
+        }
+    }
+
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    /// Whether new connections start out authenticated as `default`.
+    pub fn default_user_nopass(&self) -> bool {
+        self.users
+            .get(DEFAULT_USER)
+            .is_some_and(|user| user.is_enabled() && user.is_nopass())
+    }
+
+    /// `requirepass` is a shorthand for the default user's password.
+    pub fn set_requirepass(&mut self, password: &str) {
+        let default = self
+            .users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(|| User::superuser(DEFAULT_USER));
+        default.apply_rule("resetpass").unwrap();
+        if password.is_empty() {
+            default.apply_rule("nopass").unwrap();
+        } else {
+            default.apply_rule(&format!(">{}", password)).unwrap();
+        }
+    }
+
+    /// Checks a username/password pair, logging failures.
+    pub fn authenticate(&mut self, username: &str, password: &str, client: &Client) -> bool {
+        let ok = self
+            .users
+            .get(username)
+            .is_some_and(|user| user.check_password(password));
+        if !ok {
+            self.log
+                .record(DenialReason::Auth, "AUTH", username, &client.describe());
+        }
+        ok
+    }
+
+    /// Checks that the client's user may run the request `args`, resolved
+    /// to `spec`, logging denials.
+    pub fn check(
+        &mut self,
+        client: &Client,
+        spec: &CommandSpec,
+        args: &[CommandArg],
+    ) -> ServerResult<()> {
+        let username = client.user();
+        let name = spec.name.to_string();
+        let denied_command = || {
+            ServerError::NoPermission(format!(
+                "User {} has no permissions to run the '{}' command",
+                username, name
+            ))
+        };
+        let (reason, object) = match self.users.get(&username) {
+            // Users deleted while logged in lose all permissions
+            None => return Err(denied_command()),
+            Some(user) if !user.can_run(spec) => (DenialReason::Command, name.clone()),
+            Some(user) => {
+                let write = spec.has_flag(flags::WRITE);
+                let pattern = spec.command == Command::PSubscribe;
+                let channels = match spec.command {
+                    Command::Subscribe | Command::PSubscribe => &args[1..],
+                    Command::Publish => &args[1..2],
+                    _ => &[],
+                };
+                if let Some(key) = spec
+                    .keys(args)
+                    .into_iter()
+                    .find(|key| !user.can_access_key(key, write))
+                {
+                    (DenialReason::Key, key.clone())
+                } else if let Some(channel) = channels
+                    .iter()
+                    .find(|channel| !user.can_access_channel(channel, pattern))
+                {
+                    (DenialReason::Channel, channel.to_string())
+                } else {
+                    return Ok(());
+                }
+            }
+        };
+        self.log
+            .record(reason, &object, &username, &client.describe());
+        match reason {
+            DenialReason::Key => Err(ServerError::NoPermission(
+                "No permissions to access a key".to_string(),
+            )),
+            DenialReason::Channel => Err(ServerError::NoPermission(
+                "No permissions to access a channel".to_string(),
+            )),
+            _ => Err(denied_command()),
+        }
+    }
+
+    /// ACL SETUSER. Rules are applied to a copy so a bad one leaves the
+    /// user untouched.
+    pub fn set_user(&mut self, name: &str, rules: &[impl AsRef<str>]) -> Result<(), String> {
+        let mut user = self
+            .users
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| User::new(name));
+        for rule in rules.iter().map(AsRef::as_ref) {
+            user.apply_rule(rule)
+                .map_err(|e| format!("Error in ACL SETUSER modifier '{}': {}", rule, e))?;
+        }
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn delete_users(&mut self, names: &[impl AsRef<str>]) -> Result<usize, String> {
+        if names.iter().any(|name| name.as_ref() == DEFAULT_USER) {
+            return Err("The 'default' user cannot be removed".to_string());
+        }
+        Ok(names
+            .iter()
+            .filter(|name| self.users.remove(name.as_ref()).is_some())
+            .count())
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.users.keys().cloned().collect()
+    }
+
+    /// ACL LIST, one line per user, which is also the ACL file format.
+    pub fn describe(&self) -> Vec<String> {
+        self.users.values().map(|user| user.describe()).collect()
+    }
+
+    /// Parses an ACL file. Every line is `user <name> <rules...>`.
+    pub fn parse(contents: &str) -> Result<BTreeMap<String, User>, String> {
+        let mut users = BTreeMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", i + 1, message);
+            let mut words = line.split_whitespace();
+            if words.next() != Some("user") {
+                return Err(error("should start with user keyword"));
+            }
+            let name = words.next().ok_or_else(|| error("missing username"))?;
+            if users.contains_key(name) {
+                return Err(error(&format!("Duplicate user '{}' found", name)));
+            }
+            let mut user = User::new(name);
+            for rule in words {
+                user.apply_rule(rule)
+                    .map_err(|e| error(&format!("Error in user declaration '{}': {}", rule, e)))?;
+            }
+            users.insert(name.to_string(), user);
+        }
+        users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(|| User::superuser(DEFAULT_USER));
+        Ok(users)
+    }
+
+    /// Replaces every user with the ones in `path`. Nothing changes if the
+    /// file has errors.
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.users = Self::parse(&contents).map_err(|e| format!("{}:{}", path, e))?;
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut contents = self.describe().join("\n");
+        contents.push('\n');
+        // Write then rename so a crash never leaves a truncated file behind
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, contents)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+fn invalid(message: &str) -> ServerError {
+    ServerError::InvalidArgument(message.to_string())
+}
+
+fn bulk_array<I: IntoIterator<Item = String>>(items: I) -> RESP {
+    RESP::Array(
+        items
+            .into_iter()
+            .map(|s| RESP::BulkString(s.into()))
+            .collect(),
+    )
+}
+
+/// Disconnects everyone logged in as one of `users`, since their
+/// permissions no longer exist.
+fn kill_clients_of(server: &Server, users: &[impl AsRef<str>]) {
+    for client in server.clients.list() {
+        if users.iter().any(|user| user.as_ref() == client.user()) {
+            client.kill();
+        }
+    }
+}
+
+fn acl_file(server: &Server) -> ServerResult<String> {
+    let path = server.get_config_value("aclfile");
+    if path.is_empty() {
+        return Err(invalid(
+            "This Redis instance is not configured to use an ACL file. You may want to \
+             specify users via the ACL SETUSER command and then issue a CONFIG REWRITE \
+             (assuming you have a Redis configuration file set) in order to store users \
+             in the Redis configuration.",
+        ));
+    }
+    Ok(path)
+}
+
+fn acl_getuser(acl: &Acl, name: &str) -> RESP {
+    let Some(user) = acl.user(name) else {
+        return RESP::Null;
+    };
+    let bulk = |s: &str| RESP::BulkString(s.into());
+    RESP::Array(vec![
+        bulk("flags"),
+        bulk_array(user.flags().into_iter().map(String::from)),
+        bulk("passwords"),
+        bulk_array(user.passwords().cloned()),
+        bulk("commands"),
+        bulk(&user.describe_commands()),
+        bulk("keys"),
+        bulk(&user.describe_keys()),
+        bulk("channels"),
+        bulk(&user.describe_channels()),
+        bulk("selectors"),
+        RESP::Array(Vec::new()),
+    ])
+}
+
+fn acl_cat(args: &[CommandArg]) -> ServerResult<RESP> {
+    match args {
+        [] => Ok(bulk_array(CATEGORIES.iter().map(|c| c.to_string()))),
+        [category] => {
+            let category = category.to_lowercase();
+            if !CATEGORIES.contains(&category.as_str()) {
+                return Err(ServerError::InvalidArgument(format!(
+                    "Unknown category '{}'",
+                    category
+                )));
+            }
+            Ok(bulk_array(
+                COMMAND_TABLE
+                    .iter()
+                    .flat_map(|spec| spec.with_subcommands())
+                    .filter(|spec| spec.categories.contains(&category.as_str()))
+                    .map(|spec| spec.name.to_string()),
+            ))
+        }
+        _ => Err(invalid("syntax error")),
+    }
+}
+
+fn acl_genpass(args: &[CommandArg]) -> ServerResult<RESP> {
+    let bits = match args.first() {
+        Some(bits) => bits
+            .parse::<usize>()
+            .ok()
+            .filter(|b| (1..=4096).contains(b))
+            .ok_or_else(|| invalid("ACL GENPASS argument must be the number of bits for the output password, a positive number up to 4096"))?,
+        None => 256,
+    };
+    let mut bytes = vec![0u8; bits.div_ceil(8)];
+    rand::fill(&mut bytes[..]);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(RESP::BulkString(hex[..bits.div_ceil(4)].into()))
+}
+
+fn acl_dryrun(acl: &mut Acl, args: &[CommandArg]) -> ServerResult<RESP> {
+    let (username, command) = (&args[0], &args[1..]);
+    let user = acl
+        .user(username)
+        .ok_or_else(|| ServerError::InvalidArgument(format!("User '{}' not found", username)))?;
+    let spec = command::resolve(command).map_err(|e| match e {
+        ServerError::UnknownCommand(_) => {
+            ServerError::InvalidArgument(format!("Command '{}' not found", command[0]))
+        }
+        e => e,
+    })?;
+    let reply = if !user.can_run(spec) {
+        format!(
+            "User {} has no permissions to run the '{}' command",
+            username, spec.name
+        )
+    } else if let Some(key) = spec
+        .keys(command)
+        .into_iter()
+        .find(|key| !user.can_access_key(key, spec.has_flag(flags::WRITE)))
+    {
+        format!(
+            "User {} has no permissions to access the '{}' key",
+            username, key
+        )
+    } else {
+        "OK".to_string()
+    };
+    Ok(RESP::SimpleString(reply))
+}
+
+/// ACL <subcommand> [args], with `args` starting at the subcommand.
+pub fn command_acl(server: &Server, client: &Client, args: &[CommandArg]) -> ServerResult<RESP> {
+    let Some(subcommand) = args.first() else {
+        return Err(invalid("wrong number of arguments for 'acl' command"));
+    };
+    let ok = || Ok(RESP::SimpleString("OK".to_string()));
+    let mut acl = server.acl.lock().unwrap();
+    match (subcommand.to_lowercase().as_str(), args.len()) {
+        ("setuser", n) if n >= 2 => {
+            acl.set_user(&args[1], &args[2..])
+                .map_err(ServerError::InvalidArgument)?;
+            ok()
+        }
+        ("getuser", 2) => Ok(acl_getuser(&acl, &args[1])),
+        ("deluser", n) if n >= 2 => {
+            let deleted = acl
+                .delete_users(&args[1..])
+                .map_err(ServerError::InvalidArgument)?;
+            drop(acl);
+            kill_clients_of(server, &args[1..]);
+            Ok(RESP::Integer(deleted as i64))
+        }
+        ("list", 1) => Ok(bulk_array(acl.describe())),
+        ("users", 1) => Ok(bulk_array(acl.usernames())),
+        ("whoami", 1) => Ok(RESP::BulkString(client.user().into())),
+        ("cat", 1 | 2) => acl_cat(&args[1..]),
+        ("log", 1) => Ok(acl.log.to_resp(10)),
+        ("log", 2) if args[1].eq_ignore_ascii_case("RESET") => {
+            acl.log.reset();
+            ok()
+        }
+        ("log", 2) => {
+            let count = args[1]
+                .parse::<usize>()
+                .map_err(|_| invalid("value is out of range, must be positive"))?;
+            Ok(acl.log.to_resp(count))
+        }
+        ("save", 1) => {
+            let path = acl_file(server)?;
+            acl.save(&path).map_err(ServerError::InvalidArgument)?;
+            ok()
+        }
+        ("load", 1) => {
+            let path = acl_file(server)?;
+            let before = acl.usernames();
+            acl.load(&path).map_err(ServerError::InvalidArgument)?;
+            let removed: Vec<String> = before
+                .into_iter()
+                .filter(|name| acl.user(name).is_none())
+                .collect();
+            drop(acl);
+            kill_clients_of(server, &removed);
+            ok()
+        }
+        ("genpass", 1 | 2) => acl_genpass(&args[1..]),
+        ("dryrun", n) if n >= 3 => acl_dryrun(&mut acl, &args[1..]),
+        ("help", 1) => Ok(RESP::Array(
+            [
+                "ACL <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "CAT [<category>]",
+                "    List all commands that belong to <category>, or all command categories",
+                "    when no category is specified.",
+                "DELUSER <username> [<username> ...]",
+                "    Delete a list of users.",
+                "DRYRUN <username> <command> [<arg> ...]",
+                "    Returns whether the user can execute the given command without executing the command.",
+                "GETUSER <username>",
+                "    Get the user's details.",
+                "GENPASS [<bits>]",
+                "    Generate a secure 256-bit user password. The optional `bits` argument can",
+                "    be used to specify a different size.",
+                "LIST",
+                "    Show users details in config file format.",
+                "LOAD",
+                "    Reload users from the ACL file.",
+                "LOG [<count> | RESET]",
+                "    Show the ACL log entries.",
+                "SAVE",
+                "    Save the current config to the ACL file.",
+                "SETUSER <username> <attribute> [<attribute> ...]",
+                "    Create or modify a user with the specified attributes.",
+                "USERS",
+                "    List all the registered usernames.",
+                "WHOAMI",
+                "    Return the current connection username.",
+                "HELP",
+                "    Print this help.",
+            ]
+            .iter()
+            .map(|line| RESP::SimpleString(line.to_string()))
+            .collect(),
+        )),
+        _ => Err(ServerError::InvalidArgument(format!(
+            "unknown subcommand or wrong number of arguments for '{}'",
+            subcommand
+        ))),
+    }
+}
+
+/// AUTH [username] password
+pub fn command_auth(server: &Server, client: &Client, args: &[CommandArg]) -> ServerResult<RESP> {
+    let mut acl = server.acl.lock().unwrap();
+    let (username, password) = match args {
+        [password] => {
+            if acl.default_user_nopass() {
+                return Err(invalid(
+                    "AUTH <password> called without any password configured for the \
+                     default user. Are you sure your configuration is correct?",
+                ));
+            }
+            (DEFAULT_USER, password)
+        }
+        [username, password] => (username.as_str(), password),
+        _ => return Err(invalid("wrong number of arguments for 'auth' command")),
+    };
+    if !acl.authenticate(username, password, client) {
+        return Err(ServerError::WrongPass);
+    }
+    client.login(username);
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::cmd;
+
+    fn spec(parts: &[&str]) -> &'static CommandSpec {
+        command::resolve(&cmd(parts)).unwrap()
+    }
+
+    fn connect(server: &Server) -> std::sync::Arc<Client> {
+        let client = server
+            .clients
+            .register("127.0.0.1:5000".to_string(), "127.0.0.1:6379".to_string());
+        client.login(DEFAULT_USER);
+        client
+    }
+
+    #[test]
+    fn test_requirepass() {
+        let mut acl = Acl::new();
+        assert!(acl.default_user_nopass());
+        acl.set_requirepass("secret");
+        assert!(!acl.default_user_nopass());
+        assert!(acl.user(DEFAULT_USER).unwrap().check_password("secret"));
+        acl.set_requirepass("");
+        assert!(acl.default_user_nopass());
+    }
+
+    #[test]
+    fn test_auth() {
+        let server = Server::default();
+        let client = connect(&server);
+        assert!(command_auth(&server, &client, &cmd(&["pw"])).is_err());
+        command_acl(
+            &server,
+            &client,
+            &cmd(&["SETUSER", "alice", "on", ">pw", "+get", "~*"]),
+        )
+        .unwrap();
+        assert_eq!(
+            command_auth(&server, &client, &cmd(&["alice", "bad"])),
+            Err(ServerError::WrongPass)
+        );
+        assert_eq!(server.acl.lock().unwrap().log.len(), 1);
+        command_auth(&server, &client, &cmd(&["alice", "pw"])).unwrap();
+        assert_eq!(client.user(), "alice");
+    }
+
+    #[test]
+    fn test_check_permissions() {
+        let server = Server::default();
+        let client = connect(&server);
+        let mut acl = server.acl.lock().unwrap();
+        acl.set_user("bob", &cmd(&["on", "nopass", "+@read", "%R~app:*"]))
+            .unwrap();
+        client.login("bob");
+        assert!(
+            acl.check(&client, spec(&["GET", "app:1"]), &cmd(&["GET", "app:1"]))
+                .is_ok()
+        );
+        assert!(
+            acl.check(&client, spec(&["GET", "other"]), &cmd(&["GET", "other"]))
+                .is_err()
+        );
+        assert!(
+            acl.check(
+                &client,
+                spec(&["SET", "app:1", "v"]),
+                &cmd(&["SET", "app:1", "v"])
+            )
+            .is_err()
+        );
+        assert_eq!(acl.log.len(), 2);
+    }
+
+    #[test]
+    fn test_check_channels() {
+        let server = Server::default();
+        let client = connect(&server);
+        let mut acl = server.acl.lock().unwrap();
+        acl.set_user("eve", &cmd(&["on", "nopass", "+@pubsub", "&news.*"]))
+            .unwrap();
+        client.login("eve");
+        let check = |acl: &mut Acl, parts: &[&str]| acl.check(&client, spec(parts), &cmd(parts));
+        assert!(check(&mut acl, &["SUBSCRIBE", "news.sport"]).is_ok());
+        assert_eq!(
+            check(&mut acl, &["PUBLISH", "alerts", "hi"]),
+            Err(ServerError::NoPermission(
+                "No permissions to access a channel".to_string()
+            ))
+        );
+        // Patterns have to match one of the user's patterns exactly
+        assert!(check(&mut acl, &["PSUBSCRIBE", "news.*"]).is_ok());
+        assert!(check(&mut acl, &["PSUBSCRIBE", "news.s*"]).is_err());
+    }
+
+    #[test]
+    fn test_setuser_is_atomic() {
+        let mut acl = Acl::new();
+        acl.set_user("carol", &cmd(&["on", "+get"])).unwrap();
+        assert!(acl.set_user("carol", &cmd(&["off", "+bogus"])).is_err());
+        assert!(acl.user("carol").unwrap().is_enabled());
+        assert!(acl.delete_users(&cmd(&["default"])).is_err());
+        assert_eq!(acl.delete_users(&cmd(&["carol", "nobody"])), Ok(1));
+    }
+
+    #[test]
+    fn test_parse_acl_file() {
+        let users = Acl::parse("user alice on nopass ~* +@all\n\nuser bob off\n").unwrap();
+        assert_eq!(users.len(), 3);
+        assert!(users.contains_key(DEFAULT_USER));
+        assert!(Acl::parse("alice on").is_err());
+        assert!(
+            Acl::parse("user alice +bogus")
+                .unwrap_err()
+                .starts_with("line 1:")
+        );
+    }
+}
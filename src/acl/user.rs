@@ -0,0 +1,380 @@
+use std::collections::BTreeSet;
+
+use sha2::{Digest, Sha256};
+
+use super::CATEGORIES;
+use crate::command::{self, CommandSpec};
+use crate::glob::glob_match;
+
+/// What a command rule applies to.
+#[derive(Debug, Clone, PartialEq)]
+enum CommandSelector {
+    All,
+    Category(String),
+    Command(String),
+    Subcommand(String, String),
+}
+
+/// A key pattern and the access it grants.
+#[derive(Debug, Clone, PartialEq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+/// Hex encoded SHA-256, which is how passwords are stored and listed.
+pub fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    name: String,
+    enabled: bool,
+    nopass: bool,
+    passwords: BTreeSet<String>,
+    /// Command rules in the order given; the last one that matches wins
+    commands: Vec<(bool, CommandSelector)>,
+    keys: Vec<KeyPattern>,
+    channels: Vec<String>,
+}
+
+impl User {
+    /// A user as created by ACL SETUSER: disabled, no passwords and no
+    /// permissions at all.
+    pub fn new(name: &str) -> Self {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: vec![(false, CommandSelector::All)],
+            keys: Vec::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    /// The `default` user of a fresh server, which can do anything.
+    pub fn superuser(name: &str) -> Self {
+        let mut user = User::new(name);
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            user.apply_rule(rule).unwrap();
+        }
+        user
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_nopass(&self) -> bool {
+        self.nopass
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        self.enabled && (self.nopass || self.passwords.contains(&hash_password(password)))
+    }
+
+    /// Applies one ACL SETUSER rule, returning a description of what's
+    /// wrong with it if it can't be applied.
+    pub fn apply_rule(&mut self, rule: &str) -> Result<(), String> {
+        let lower = rule.to_lowercase();
+        match lower.as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.apply_rule("~*")?,
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.apply_rule("&*")?,
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => self.apply_rule("+@all")?,
+            "nocommands" => self.apply_rule("-@all")?,
+            "reset" => {
+                for rule in ["resetpass", "resetkeys", "resetchannels", "off", "-@all"] {
+                    self.apply_rule(rule)?;
+                }
+            }
+            _ => return self.apply_pattern_rule(rule),
+        }
+        Ok(())
+    }
+
+    fn apply_pattern_rule(&mut self, rule: &str) -> Result<(), String> {
+        if let Some(password) = rule.strip_prefix('>') {
+            self.passwords.insert(hash_password(password));
+            self.nopass = false;
+        } else if let Some(password) = rule.strip_prefix('<') {
+            if !self.passwords.remove(&hash_password(password)) {
+                return Err("no such password".to_string());
+            }
+        } else if let Some(hash) = rule.strip_prefix('#') {
+            if hash.len() != 64 || !hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+                return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string());
+            }
+            self.passwords.insert(hash.to_string());
+            self.nopass = false;
+        } else if let Some(hash) = rule.strip_prefix('!') {
+            if !self.passwords.remove(hash) {
+                return Err("no such password".to_string());
+            }
+        } else if let Some(pattern) = rule.strip_prefix('~') {
+            self.add_key_pattern(pattern, true, true);
+        } else if let Some(rest) = rule.strip_prefix('%') {
+            let (access, pattern) = rest.split_once('~').ok_or("Syntax error")?;
+            let access = access.to_uppercase();
+            if access.is_empty() || !access.chars().all(|c| c == 'R' || c == 'W') {
+                return Err("Syntax error".to_string());
+            }
+            self.add_key_pattern(pattern, access.contains('R'), access.contains('W'));
+        } else if let Some(pattern) = rule.strip_prefix('&') {
+            if !self.channels.iter().any(|p| p == pattern) {
+                self.channels.push(pattern.to_string());
+            }
+        } else if let Some(selector) = rule.strip_prefix('+') {
+            self.add_command_rule(true, selector)?;
+        } else if let Some(selector) = rule.strip_prefix('-') {
+            self.add_command_rule(false, selector)?;
+        } else {
+            return Err("Syntax error".to_string());
+        }
+        Ok(())
+    }
+
+    fn add_key_pattern(&mut self, pattern: &str, read: bool, write: bool) {
+        match self.keys.iter_mut().find(|k| k.pattern == pattern) {
+            Some(existing) => {
+                existing.read |= read;
+                existing.write |= write;
+            }
+            None => self.keys.push(KeyPattern {
+                pattern: pattern.to_string(),
+                read,
+                write,
+            }),
+        }
+    }
+
+    fn add_command_rule(&mut self, allow: bool, selector: &str) -> Result<(), String> {
+        let unknown = || "Unknown command or category name in ACL".to_string();
+        let selector = selector.to_lowercase();
+        let selector = if let Some(category) = selector.strip_prefix('@') {
+            if category == "all" {
+                CommandSelector::All
+            } else if CATEGORIES.contains(&category) {
+                CommandSelector::Category(category.to_string())
+            } else {
+                return Err(unknown());
+            }
+        } else {
+            let (name, subcommand) = match selector.split_once('|') {
+                Some((name, subcommand)) => (name, Some(subcommand)),
+                None => (selector.as_str(), None),
+            };
+            if command::lookup(name).is_none() {
+                return Err(unknown());
+            }
+            match subcommand {
+                Some("") => return Err("Syntax error".to_string()),
+                Some(subcommand) => {
+                    CommandSelector::Subcommand(name.to_string(), subcommand.to_string())
+                }
+                None => CommandSelector::Command(name.to_string()),
+            }
+        };
+        // Later rules override earlier ones, so drop anything they shadow
+        if selector == CommandSelector::All {
+            self.commands.clear();
+        } else {
+            self.commands.retain(|(_, s)| *s != selector);
+        }
+        self.commands.push((allow, selector));
+        Ok(())
+    }
+
+    /// Whether the user may run `spec`, which is a subcommand's own entry
+    /// when the request named one.
+    pub fn can_run(&self, spec: &CommandSpec) -> bool {
+        self.commands
+            .iter()
+            .rev()
+            .find(|(_, selector)| match selector {
+                CommandSelector::All => true,
+                CommandSelector::Category(category) => spec.categories.contains(&category.as_str()),
+                CommandSelector::Command(name) => name == spec.container_name(),
+                CommandSelector::Subcommand(name, sub) => {
+                    spec.name.split_once('|') == Some((name.as_str(), sub.as_str()))
+                }
+            })
+            .is_some_and(|(allow, _)| *allow)
+    }
+
+    pub fn can_access_key(&self, key: &str, write: bool) -> bool {
+        self.keys
+            .iter()
+            .any(|k| (if write { k.write } else { k.read }) && glob_match(&k.pattern, key))
+    }
+
+    /// Whether the user may use a channel, or for PSUBSCRIBE a pattern,
+    /// which has to be one of the user's own patterns, like in Redis.
+    pub fn can_access_channel(&self, channel: &str, pattern: bool) -> bool {
+        self.channels.iter().any(|p| {
+            if pattern {
+                p == "*" || p == channel
+            } else {
+                glob_match(p, channel)
+            }
+        })
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    pub fn passwords(&self) -> impl Iterator<Item = &String> {
+        self.passwords.iter()
+    }
+
+    pub fn describe_commands(&self) -> String {
+        let rules: Vec<String> = self
+            .commands
+            .iter()
+            .map(|(allow, selector)| {
+                let sign = if *allow { '+' } else { '-' };
+                match selector {
+                    CommandSelector::All => format!("{}@all", sign),
+                    CommandSelector::Category(category) => format!("{}@{}", sign, category),
+                    CommandSelector::Command(name) => format!("{}{}", sign, name),
+                    CommandSelector::Subcommand(name, sub) => format!("{}{}|{}", sign, name, sub),
+                }
+            })
+            .collect();
+        rules.join(" ")
+    }
+
+    pub fn describe_keys(&self) -> String {
+        let patterns: Vec<String> = self
+            .keys
+            .iter()
+            .map(|k| match (k.read, k.write) {
+                (true, true) => format!("~{}", k.pattern),
+                (true, false) => format!("%R~{}", k.pattern),
+                _ => format!("%W~{}", k.pattern),
+            })
+            .collect();
+        patterns.join(" ")
+    }
+
+    pub fn describe_channels(&self) -> String {
+        let patterns: Vec<String> = self.channels.iter().map(|p| format!("&{}", p)).collect();
+        patterns.join(" ")
+    }
+
+    /// The rules that recreate this user, as printed by ACL LIST and
+    /// written by ACL SAVE.
+    pub fn describe(&self) -> String {
+        let mut rules: Vec<String> = self.flags().iter().map(|f| f.to_string()).collect();
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        if !self.keys.is_empty() {
+            rules.push(self.describe_keys());
+        }
+        if self.channels.is_empty() {
+            rules.push("resetchannels".to_string());
+        } else {
+            rules.push(self.describe_channels());
+        }
+        rules.push(self.describe_commands());
+        format!("user {} {}", self.name, rules.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::cmd;
+
+    fn user(rules: &[&str]) -> User {
+        let mut user = User::new("alice");
+        for rule in rules {
+            user.apply_rule(rule).unwrap();
+        }
+        user
+    }
+
+    fn spec(parts: &[&str]) -> &'static CommandSpec {
+        command::resolve(&cmd(parts)).unwrap()
+    }
+
+    #[test]
+    fn test_passwords() {
+        let alice = user(&["on", ">secret"]);
+        assert!(alice.check_password("secret"));
+        assert!(!alice.check_password("wrong"));
+        let disabled = user(&[">secret"]);
+        assert!(!disabled.check_password("secret"));
+        let hashed = user(&["on", &format!("#{}", hash_password("pw"))]);
+        assert!(hashed.check_password("pw"));
+        assert!(User::new("x").apply_rule("#abc").is_err());
+        assert!(User::new("x").apply_rule("<missing").is_err());
+    }
+
+    #[test]
+    fn test_command_rules() {
+        let alice = user(&["+@read", "-mget", "+config|get"]);
+        assert!(alice.can_run(spec(&["get", "k"])));
+        assert!(!alice.can_run(spec(&["mget", "k"])));
+        assert!(!alice.can_run(spec(&["set", "k", "v"])));
+        assert!(alice.can_run(spec(&["config", "GET", "x"])));
+        assert!(!alice.can_run(spec(&["config", "SET", "x", "y"])));
+        assert_eq!(alice.describe_commands(), "-@all +@read -mget +config|get");
+
+        let everything = user(&["-get", "+@all"]);
+        assert!(everything.can_run(spec(&["get", "k"])));
+        assert_eq!(everything.describe_commands(), "+@all");
+
+        let mut bad = User::new("x");
+        assert!(bad.apply_rule("+@bogus").is_err());
+        assert!(bad.apply_rule("+bogus").is_err());
+        assert!(bad.apply_rule("+config|").is_err());
+    }
+
+    #[test]
+    fn test_key_patterns() {
+        let alice = user(&["~app:*", "%R~shared:*"]);
+        assert!(alice.can_access_key("app:1", true));
+        assert!(alice.can_access_key("shared:1", false));
+        assert!(!alice.can_access_key("shared:1", true));
+        assert!(!alice.can_access_key("other", false));
+        assert_eq!(alice.describe_keys(), "~app:* %R~shared:*");
+    }
+
+    #[test]
+    fn test_describe_round_trips() {
+        let alice = user(&["on", ">pw", "~k:*", "&news", "+get"]);
+        let line = alice.describe();
+        let mut copy = User::new("alice");
+        for rule in line.split(' ').skip(2) {
+            copy.apply_rule(rule).unwrap();
+        }
+        assert_eq!(copy.describe(), line);
+        assert!(copy.check_password("pw"));
+    }
+}
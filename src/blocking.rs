@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use crate::client::{Client, ClientKind};
+use crate::command::{CommandArg, CommandSpec};
+use crate::resp::RESP;
+use crate::server::{Server, ServerResult};
+use crate::storage::StreamRead;
+
+/// Clients waiting for keys to change, like XREAD BLOCK. Storage reports
+/// every key it writes here, and each client waiting on it is woken to
+/// run its command again.
+#[derive(Default)]
+pub struct Blocking {
+    waiters: Mutex<HashMap<String, Vec<Arc<Notify>>>>,
+    /// Clients currently blocked, so writes skip the lock when there are
+    /// none
+    blocked: AtomicUsize,
+}
+
+/// A client's registration on some keys, dropped once it stops waiting.
+pub struct Waiter<'a> {
+    blocking: &'a Blocking,
+    keys: Vec<String>,
+    notify: Arc<Notify>,
+}
+
+impl Waiter<'_> {
+    /// Waits until one of the keys was written since the last wake up,
+    /// or since registering.
+    pub async fn ready(&self) {
+        self.notify.notified().await;
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let mut waiters = self.blocking.waiters.lock().unwrap();
+        for key in &self.keys {
+            if let Some(list) = waiters.get_mut(key) {
+                list.retain(|notify| !Arc::ptr_eq(notify, &self.notify));
+                if list.is_empty() {
+                    waiters.remove(key);
+                }
+            }
+        }
+        self.blocking.blocked.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Blocking {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clients blocked right now, for INFO.
+    pub fn blocked(&self) -> usize {
+        self.blocked.load(Ordering::Relaxed)
+    }
+
+    /// Registers interest in `keys`. Writes from now on wake the waiter,
+    /// even ones that happen before it starts waiting.
+    pub fn watch(&self, keys: &[String]) -> Waiter<'_> {
+        let notify = Arc::new(Notify::new());
+        let mut waiters = self.waiters.lock().unwrap();
+        for key in keys {
+            waiters
+                .entry(key.to_string())
+                .or_default()
+                .push(notify.clone());
+        }
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+        Waiter {
+            blocking: self,
+            keys: keys.to_vec(),
+            notify,
+        }
+    }
+
+    /// Wakes the clients waiting on `key`.
+    pub fn signal(&self, key: &str) {
+        if self.blocked() == 0 {
+            return;
+        }
+        if let Some(list) = self.waiters.lock().unwrap().get(key) {
+            for notify in list {
+                notify.notify_one();
+            }
+        }
+    }
+}
+
+/// XREAD and XREADGROUP. Each stream is read on its own so they can live
+/// on different shards, and with BLOCK the reads are retried whenever one
+/// of the streams is written to until something comes back or the
+/// timeout passes.
+pub async fn command_xread(
+    server: &Server,
+    client: &Client,
+    spec: &'static CommandSpec,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let mut read = StreamRead::parse(args)?;
+    // The master's reads were already served, replicas only apply them
+    let block = read.block.filter(|_| client.kind() != ClientKind::Master);
+    let keys: Vec<String> = read.streams.iter().map(|(key, _)| key.clone()).collect();
+    let waiter = block.map(|_| server.blocking.watch(&keys));
+    if block.is_some() {
+        // `$` means entries added after the command was sent, so pin it
+        // down before waiting
+        for (key, id) in read.streams.iter_mut().filter(|(_, id)| id == "$") {
+            let last = server.storage.last_stream_id(key).await?;
+            *id = last.unwrap_or_default().to_string();
+        }
+    }
+    let deadline = block
+        .filter(|ms| *ms > 0)
+        .map(|ms| tokio::time::Instant::now() + Duration::from_millis(ms));
+    loop {
+        let mut replies = Vec::new();
+        for stream in &read.streams {
+            let request = read.request(std::slice::from_ref(stream));
+            if let RESP::Array(streams) = server.storage.execute(spec, &request).await? {
+                replies.extend(streams);
+            }
+        }
+        let Some(waiter) = &waiter else {
+            return Ok(reply(replies));
+        };
+        if !replies.is_empty() {
+            return Ok(reply(replies));
+        }
+        let timeout = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = waiter.ready() => (),
+            _ = timeout => return Ok(RESP::NullArray),
+            _ = client.killed() => return Ok(RESP::NullArray),
+        }
+    }
+}
+
+fn reply(streams: Vec<RESP>) -> RESP {
+    if streams.is_empty() {
+        RESP::NullArray
+    } else {
+        RESP::Array(streams)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_support::{exchange, listen};
+
+    #[tokio::test]
+    async fn test_xread_block_times_out_with_a_null_array() {
+        let (_server, mut stream) = listen().await;
+        assert_eq!(
+            exchange(
+                &mut stream,
+                &[b"XREAD", b"BLOCK", b"10", b"STREAMS", b"s", b"$"]
+            )
+            .await,
+            b"*-1\r\n"
+        );
+        exchange(
+            &mut stream,
+            &[b"XGROUP", b"CREATE", b"s", b"g", b"$", b"MKSTREAM"],
+        )
+        .await;
+        assert_eq!(
+            exchange(
+                &mut stream,
+                &[
+                    b"XREADGROUP",
+                    b"GROUP",
+                    b"g",
+                    b"c",
+                    b"BLOCK",
+                    b"10",
+                    b"STREAMS",
+                    b"s",
+                    b">"
+                ]
+            )
+            .await,
+            b"*-1\r\n"
+        );
+    }
+}
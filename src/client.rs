@@ -0,0 +1,867 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use crate::command::{Command, CommandArg, CommandSpec, flags};
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+use crate::tracking::{client_caching, client_getredir, client_tracking, client_trackinginfo};
+use crate::transaction::Transaction;
+
+/// Bytes read from a connection at a time, reported as qbuf + qbuf-free.
+/// Requests that don't fit build up over several reads.
+pub const QUERY_BUFFER_SIZE: usize = 1024;
+/// A connection whose unfinished request grows past this is closed, like
+/// Redis's `client-query-buffer-limit`.
+pub const QUERY_BUFFER_LIMIT: usize = 1 << 30;
+
+/// What the client asked for with CLIENT REPLY.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplyMode {
+    On,
+    Off,
+    /// Set by CLIENT REPLY SKIP, whose own reply is dropped
+    Skip,
+    /// The command after CLIENT REPLY SKIP, whose reply is dropped too
+    SkipNext,
+}
+
+/// Which commands CLIENT PAUSE holds back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseMode {
+    Write,
+    All,
+}
+
+struct Pause {
+    until: Instant,
+    mode: PauseMode,
+}
+
+/// The part a connection plays in replication, as CLIENT LIST and the
+/// TYPE filters see it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientKind {
+    Normal,
+    /// A replica being fed the replication stream
+    Replica,
+    /// Our master's link, applying its stream
+    Master,
+}
+
+impl ClientKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientKind::Normal => "normal",
+            ClientKind::Replica => "replica",
+            ClientKind::Master => "master",
+        }
+    }
+}
+
+/// Mutable per-connection details, updated as commands come in.
+struct ClientState {
+    name: Option<String>,
+    user: String,
+    authenticated: bool,
+    last_interaction: Instant,
+    /// Lowercase name of the last (or current) command
+    cmd: String,
+    /// Bytes of the request being processed, 0 between commands
+    qbuf: usize,
+    /// Bytes of the reply being written, 0 between commands
+    obl: usize,
+    reply: ReplyMode,
+    no_evict: bool,
+    kind: ClientKind,
+    /// Set by ASKING, for the next command only
+    asking: bool,
+    /// Channels and patterns subscribed to
+    sub: usize,
+    psub: usize,
+    net_input_bytes: u64,
+    net_output_bytes: u64,
+    commands: u64,
+}
+
+/// A connected client, shared between its connection task and the table.
+pub struct Client {
+    id: u64,
+    addr: String,
+    laddr: String,
+    created: Instant,
+    state: Mutex<ClientState>,
+    killed: AtomicBool,
+    kill_signal: Notify,
+    /// Out-of-band messages, like tracking invalidations, waiting to be
+    /// written between replies
+    pushes: Mutex<Vec<RESP>>,
+    push_signal: Notify,
+    transaction: Mutex<Transaction>,
+}
+
+impl Client {
+    fn new(id: u64, addr: String, laddr: String) -> Self {
+        let now = Instant::now();
+        Client {
+            id,
+            addr,
+            laddr,
+            created: now,
+            state: Mutex::new(ClientState {
+                name: None,
+                user: "default".to_string(),
+                authenticated: false,
+                last_interaction: now,
+                cmd: "NULL".to_string(),
+                qbuf: 0,
+                obl: 0,
+                reply: ReplyMode::On,
+                no_evict: false,
+                kind: ClientKind::Normal,
+                asking: false,
+                sub: 0,
+                psub: 0,
+                net_input_bytes: 0,
+                net_output_bytes: 0,
+                commands: 0,
+            }),
+            killed: AtomicBool::new(false),
+            kill_signal: Notify::new(),
+            pushes: Mutex::new(Vec::new()),
+            push_signal: Notify::new(),
+            transaction: Mutex::new(Transaction::default()),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.state.lock().unwrap().name.clone()
+    }
+
+    pub fn user(&self) -> String {
+        self.state.lock().unwrap().user.clone()
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.state.lock().unwrap().authenticated
+    }
+
+    /// Switches the connection to `user` after a successful AUTH, or on
+    /// connect when the default user needs no password.
+    pub fn login(&self, user: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.user = user.to_string();
+        state.authenticated = true;
+    }
+
+    pub fn kind(&self) -> ClientKind {
+        self.state.lock().unwrap().kind
+    }
+
+    pub fn set_kind(&self, kind: ClientKind) {
+        self.state.lock().unwrap().kind = kind;
+    }
+
+    /// The type CLIENT LIST and the TYPE filters see: the replication
+    /// role if it has one, then pubsub while subscribed to anything.
+    pub fn client_type(&self) -> &'static str {
+        let state = self.state.lock().unwrap();
+        match state.kind {
+            ClientKind::Normal if state.sub + state.psub > 0 => "pubsub",
+            kind => kind.as_str(),
+        }
+    }
+
+    pub fn set_subscriptions(&self, sub: usize, psub: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.sub = sub;
+        state.psub = psub;
+    }
+
+    /// Whether the connection is in the subscribed state, where only
+    /// pub/sub commands and PING may run.
+    pub fn is_subscribed(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.sub + state.psub > 0
+    }
+
+    pub fn set_asking(&self) {
+        self.state.lock().unwrap().asking = true;
+    }
+
+    /// Whether the previous command was ASKING, clearing it.
+    pub fn take_asking(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().asking)
+    }
+
+    /// Marks the start of a command read from a `qbuf` byte request.
+    pub fn begin_command(&self, name: &str, qbuf: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.last_interaction = Instant::now();
+        state.cmd = name.to_lowercase();
+        state.qbuf = qbuf;
+        state.net_input_bytes += qbuf as u64;
+        state.commands += 1;
+    }
+
+    /// Marks a reply of `obl` bytes as being written.
+    pub fn begin_reply(&self, obl: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.qbuf = 0;
+        state.obl = obl;
+        state.net_output_bytes += obl as u64;
+    }
+
+    pub fn end_reply(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.obl = 0;
+        state.last_interaction = Instant::now();
+    }
+
+    pub fn set_reply_mode(&self, mode: ReplyMode) {
+        self.state.lock().unwrap().reply = mode;
+    }
+
+    /// Whether the reply to the command that just ran should be sent,
+    /// advancing CLIENT REPLY SKIP by one command.
+    pub fn should_reply(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.reply {
+            ReplyMode::On => true,
+            ReplyMode::Off => false,
+            ReplyMode::Skip => {
+                state.reply = ReplyMode::SkipNext;
+                false
+            }
+            ReplyMode::SkipNext => {
+                state.reply = ReplyMode::On;
+                false
+            }
+        }
+    }
+
+    /// Asks the connection to close. A client killing itself still gets the
+    /// reply to CLIENT KILL.
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+        self.kill_signal.notify_one();
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once `kill` has been called.
+    pub async fn killed(&self) {
+        if !self.is_killed() {
+            self.kill_signal.notified().await;
+        }
+    }
+
+    /// Queues a message for the connection to write once it isn't in the
+    /// middle of a reply.
+    pub fn push(&self, message: RESP) {
+        self.pushes.lock().unwrap().push(message);
+        self.push_signal.notify_one();
+    }
+
+    pub fn take_pushes(&self) -> Vec<RESP> {
+        std::mem::take(&mut self.pushes.lock().unwrap())
+    }
+
+    /// Resolves once something may have been pushed since the last wait.
+    pub async fn pushed(&self) {
+        self.push_signal.notified().await;
+    }
+
+    /// The connection's MULTI state and watched keys.
+    pub fn transaction(&self) -> MutexGuard<'_, Transaction> {
+        self.transaction.lock().unwrap()
+    }
+
+    fn flags(state: &ClientState, multi: bool) -> String {
+        let mut flags = String::new();
+        match state.kind {
+            ClientKind::Normal => (),
+            ClientKind::Replica => flags.push('S'),
+            ClientKind::Master => flags.push('M'),
+        }
+        if state.sub + state.psub > 0 {
+            flags.push('P');
+        }
+        if state.no_evict {
+            flags.push('e');
+        }
+        if multi {
+            flags.push('x');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        flags
+    }
+
+    /// One line of CLIENT LIST output, without the trailing newline.
+    pub fn describe(&self) -> String {
+        let multi = self.transaction().queued();
+        let state = self.state.lock().unwrap();
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db=0 sub={} psub={} \
+             multi={} qbuf={} qbuf-free={} obl={} oll=0 omem=0 tot-net-in={} tot-net-out={} \
+             tot-cmds={} events=r cmd={} user={} resp=2",
+            self.id,
+            self.addr,
+            self.laddr,
+            state.name.as_deref().unwrap_or_default(),
+            self.created.elapsed().as_secs(),
+            state.last_interaction.elapsed().as_secs(),
+            Self::flags(&state, multi.is_some()),
+            state.sub,
+            state.psub,
+            multi.map_or(-1, |queued| queued as i64),
+            state.qbuf,
+            QUERY_BUFFER_SIZE.saturating_sub(state.qbuf),
+            state.obl,
+            state.net_input_bytes,
+            state.net_output_bytes,
+            state.commands,
+            state.cmd,
+            state.user,
+        );
+        line
+    }
+}
+
+/// Every connected client, keyed by id.
+pub struct ClientTable {
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+    pause: Mutex<Option<Pause>>,
+    unpaused: Notify,
+}
+
+impl Default for ClientTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientTable {
+    pub fn new() -> Self {
+        ClientTable {
+            next_id: AtomicU64::new(1),
+            clients: Mutex::new(BTreeMap::new()),
+            pause: Mutex::new(None),
+            unpaused: Notify::new(),
+        }
+    }
+
+    pub fn register(&self, addr: String, laddr: String) -> Arc<Client> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let client = Arc::new(Client::new(id, addr, laddr));
+        self.clients.lock().unwrap().insert(id, client.clone());
+        client
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Client>> {
+        self.clients.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn list(&self) -> Vec<Arc<Client>> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn pause(&self, timeout: Duration, mode: PauseMode) {
+        let mut pause = self.pause.lock().unwrap();
+        let until = Instant::now() + timeout;
+        // A pause can be extended or widened, never shortened or narrowed
+        match pause.as_mut() {
+            Some(current) if current.until > Instant::now() => {
+                current.until = current.until.max(until);
+                if mode == PauseMode::All {
+                    current.mode = PauseMode::All;
+                }
+            }
+            _ => *pause = Some(Pause { until, mode }),
+        }
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.lock().unwrap() = None;
+        self.unpaused.notify_waiters();
+    }
+
+    /// When a command would be let through, or None if it isn't paused.
+    pub fn paused_until(&self, write: bool) -> Option<Instant> {
+        let pause = self.pause.lock().unwrap();
+        match pause.as_ref() {
+            Some(p) if p.until > Instant::now() && (write || p.mode == PauseMode::All) => {
+                Some(p.until)
+            }
+            _ => None,
+        }
+    }
+
+    /// Waits out any CLIENT PAUSE that applies to a command.
+    pub async fn wait_unpaused(&self, write: bool) {
+        loop {
+            let unpaused = self.unpaused.notified();
+            tokio::pin!(unpaused);
+            // Register before checking so an UNPAUSE in between isn't missed
+            unpaused.as_mut().enable();
+            let Some(until) = self.paused_until(write) else {
+                return;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(until.into()) => {}
+                _ = &mut unpaused => {}
+            }
+        }
+    }
+}
+
+/// Whether CLIENT PAUSE WRITE holds back `spec`: every write, and like
+/// Redis every script or function call that may write, which is all of
+/// them except the `_RO` variants.
+pub fn held_by_write_pause(spec: &CommandSpec) -> bool {
+    spec.has_flag(flags::WRITE)
+        || matches!(
+            spec.command,
+            Command::Eval | Command::EvalSha | Command::FCall
+        )
+}
+
+fn invalid(message: &str) -> ServerError {
+    ServerError::InvalidArgument(message.to_string())
+}
+
+fn syntax_error() -> ServerError {
+    invalid("syntax error")
+}
+
+fn help(lines: &[&str]) -> RESP {
+    RESP::Array(
+        lines
+            .iter()
+            .map(|line| RESP::SimpleString(line.to_string()))
+            .collect(),
+    )
+}
+
+fn parse_on_off(value: &str) -> ServerResult<bool> {
+    match value.to_lowercase().as_str() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(syntax_error()),
+    }
+}
+
+fn parse_yes_no(value: &str) -> ServerResult<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(syntax_error()),
+    }
+}
+
+fn parse_client_type(value: &str) -> ServerResult<&'static str> {
+    match value.to_lowercase().as_str() {
+        "normal" => Ok("normal"),
+        "master" => Ok("master"),
+        "replica" | "slave" => Ok("replica"),
+        "pubsub" => Ok("pubsub"),
+        _ => Err(ServerError::InvalidArgument(format!(
+            "Unknown client type '{}'",
+            value
+        ))),
+    }
+}
+
+fn parse_id(value: &str) -> ServerResult<u64> {
+    value
+        .parse::<u64>()
+        .map_err(|_| invalid("client-id should be greater than 0"))
+}
+
+/// Filters accepted by the CLIENT KILL <filter> <value> ... form.
+#[derive(Default)]
+struct KillFilter {
+    id: Option<u64>,
+    addr: Option<String>,
+    laddr: Option<String>,
+    user: Option<String>,
+    client_type: Option<&'static str>,
+    maxage: Option<u64>,
+    skipme: bool,
+}
+
+impl KillFilter {
+    fn parse(args: &[CommandArg]) -> ServerResult<Self> {
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(syntax_error());
+        }
+        let mut filter = KillFilter {
+            skipme: true,
+            ..Default::default()
+        };
+        for pair in args.chunks(2) {
+            let value = &pair[1];
+            match pair[0].to_lowercase().as_str() {
+                "id" => filter.id = Some(parse_id(value)?),
+                "addr" => filter.addr = Some(value.to_string()),
+                "laddr" => filter.laddr = Some(value.to_string()),
+                "user" => filter.user = Some(value.to_string()),
+                "type" => filter.client_type = Some(parse_client_type(value)?),
+                "maxage" => {
+                    filter.maxage = Some(value.parse().map_err(|_| syntax_error())?);
+                }
+                "skipme" => filter.skipme = parse_yes_no(value)?,
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, client: &Client, me: &Client) -> bool {
+        if self.skipme && client.id == me.id {
+            return false;
+        }
+        self.id.is_none_or(|id| id == client.id)
+            && self.addr.as_ref().is_none_or(|addr| *addr == client.addr)
+            && self
+                .laddr
+                .as_ref()
+                .is_none_or(|laddr| *laddr == client.laddr)
+            && self.user.as_ref().is_none_or(|user| *user == client.user())
+            && self.client_type.is_none_or(|t| t == client.client_type())
+            && self
+                .maxage
+                .is_none_or(|age| client.created.elapsed().as_secs() >= age)
+    }
+}
+
+fn client_list(server: &Server, args: &[CommandArg]) -> ServerResult<RESP> {
+    let mut clients = server.clients.list();
+    match args.first().map(|arg| arg.to_lowercase()).as_deref() {
+        None => (),
+        Some("type") if args.len() == 2 => {
+            let client_type = parse_client_type(&args[1])?;
+            clients.retain(|client| client.client_type() == client_type);
+        }
+        Some("id") if args.len() >= 2 => {
+            let ids = args[1..]
+                .iter()
+                .map(|id| parse_id(id))
+                .collect::<ServerResult<Vec<u64>>>()?;
+            clients.retain(|client| ids.contains(&client.id));
+        }
+        _ => return Err(syntax_error()),
+    }
+    let mut output = String::new();
+    for client in clients {
+        output.push_str(&client.describe());
+        output.push('\n');
+    }
+    Ok(RESP::BulkString(output.into()))
+}
+
+fn client_kill(server: &Server, client: &Client, args: &[CommandArg]) -> ServerResult<RESP> {
+    // Old style: CLIENT KILL addr:port
+    if args.len() == 1 {
+        let target = server
+            .clients
+            .list()
+            .into_iter()
+            .find(|c| c.addr == args[0].as_str())
+            .ok_or_else(|| invalid("No such client"))?;
+        target.kill();
+        return Ok(RESP::SimpleString("OK".to_string()));
+    }
+    let filter = KillFilter::parse(args)?;
+    let mut killed = 0;
+    for target in server.clients.list() {
+        if filter.matches(&target, client) {
+            target.kill();
+            killed += 1;
+        }
+    }
+    Ok(RESP::Integer(killed))
+}
+
+fn client_pause(server: &Server, args: &[CommandArg]) -> ServerResult<RESP> {
+    let timeout = args
+        .first()
+        .and_then(|t| t.parse::<u64>().ok())
+        .ok_or_else(|| invalid("timeout is not an integer or out of range"))?;
+    let mode = match args.get(1).map(|m| m.to_lowercase()).as_deref() {
+        None | Some("all") if args.len() <= 2 => PauseMode::All,
+        Some("write") if args.len() == 2 => PauseMode::Write,
+        _ => return Err(syntax_error()),
+    };
+    server.clients.pause(Duration::from_millis(timeout), mode);
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+/// CLIENT <subcommand> [args], with `args` starting at the subcommand.
+pub fn command_client(
+    server: &Server,
+    client: &Arc<Client>,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let Some(subcommand) = args.first() else {
+        return Err(invalid("wrong number of arguments for 'client' command"));
+    };
+    let ok = || Ok(RESP::SimpleString("OK".to_string()));
+    match (subcommand.to_lowercase().as_str(), args.len()) {
+        ("id", 1) => Ok(RESP::Integer(client.id as i64)),
+        ("info", 1) => Ok(RESP::BulkString(format!("{}\n", client.describe()).into())),
+        ("list", _) => client_list(server, &args[1..]),
+        ("getname", 1) => Ok(client
+            .name()
+            .map(|s| RESP::BulkString(s.into()))
+            .unwrap_or(RESP::Null)),
+        ("setname", 2) => {
+            let name = &args[1];
+            if name.chars().any(|c| !c.is_ascii_graphic()) {
+                return Err(invalid(
+                    "Client names cannot contain spaces, newlines or special characters.",
+                ));
+            }
+            // An empty name removes it
+            client.state.lock().unwrap().name = (!name.is_empty()).then(|| name.to_string());
+            ok()
+        }
+        ("kill", n) if n >= 2 => client_kill(server, client, &args[1..]),
+        ("pause", 2 | 3) => client_pause(server, &args[1..]),
+        ("unpause", 1) => {
+            server.clients.unpause();
+            ok()
+        }
+        ("no-evict", 2) => {
+            client.state.lock().unwrap().no_evict = parse_on_off(&args[1])?;
+            ok()
+        }
+        ("tracking", n) if n >= 2 => client_tracking(server, client, &args[1..]),
+        ("caching", 2) => client_caching(server, client, &args[1]),
+        ("getredir", 1) => Ok(client_getredir(server, client)),
+        ("trackinginfo", 1) => Ok(client_trackinginfo(server, client)),
+        ("reply", 2) => {
+            let mode = match args[1].to_lowercase().as_str() {
+                "on" => ReplyMode::On,
+                "off" => ReplyMode::Off,
+                "skip" => ReplyMode::Skip,
+                _ => return Err(syntax_error()),
+            };
+            client.set_reply_mode(mode);
+            ok()
+        }
+        ("help", 1) => Ok(help(&[
+            "CLIENT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "GETNAME",
+            "    Return the name of the current connection.",
+            "ID",
+            "    Return the ID of the current connection.",
+            "INFO",
+            "    Return information about the current client connection.",
+            "KILL <ip:port>",
+            "    Kill connection made from <ip:port>.",
+            "KILL <option> <value> [<option> <value> [...]]",
+            "    Kill connections. Options are:",
+            "    * ADDR (<ip:port>|<unixsocket>:0)",
+            "      Kill connections made from the specified address",
+            "    * LADDR (<ip:port>|<unixsocket>:0)",
+            "      Kill connections made to specified local address",
+            "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+            "      Kill connections by type.",
+            "    * USER <username>",
+            "      Kill connections authenticated by <username>.",
+            "    * SKIPME (YES|NO)",
+            "      Skip killing current connection (default: yes).",
+            "    * ID <client-id>",
+            "      Kill connections by client id.",
+            "    * MAXAGE <maxage>",
+            "      Kill connections older than the specified age.",
+            "LIST [options ...]",
+            "    Return information about client connections. Options:",
+            "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+            "      Return clients of specified type.",
+            "    * ID <client-id> [<client-id> ...]",
+            "      Return clients with specified IDs only.",
+            "PAUSE <timeout> [WRITE|ALL]",
+            "    Suspend all, or just write, clients for <timeout> milliseconds.",
+            "UNPAUSE",
+            "    Stop the current client pause, resuming traffic.",
+            "TRACKING (ON|OFF) [REDIRECT <id>] [BCAST] [PREFIX <prefix> [...]]",
+            "         [OPTIN] [OPTOUT]",
+            "    Control server assisted client side caching.",
+            "CACHING (YES|NO)",
+            "    Enable/disable tracking of the keys for next command in OPTIN/OPTOUT modes.",
+            "GETREDIR",
+            "    Return the client ID we are redirecting to when tracking is enabled.",
+            "TRACKINGINFO",
+            "    Report tracking status for the current connection.",
+            "REPLY (ON|OFF|SKIP)",
+            "    Control the replies sent to the current connection.",
+            "SETNAME <name>",
+            "    Assign the name <name> to the current connection.",
+            "NO-EVICT (ON|OFF)",
+            "    Protect current client connection from eviction.",
+            "HELP",
+            "    Print this help.",
+        ])),
+        _ => Err(ServerError::InvalidArgument(format!(
+            "unknown subcommand or wrong number of arguments for '{}'",
+            subcommand
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::cmd;
+
+    fn connect(server: &Server, port: u16) -> Arc<Client> {
+        server
+            .clients
+            .register(format!("127.0.0.1:{}", port), "127.0.0.1:6379".to_string())
+    }
+
+    #[test]
+    fn test_register_assigns_increasing_ids() {
+        let server = Server::default();
+        let a = connect(&server, 5000);
+        let b = connect(&server, 5001);
+        assert!(b.id() > a.id());
+        assert_eq!(server.clients.len(), 2);
+        server.clients.unregister(a.id());
+        assert_eq!(server.clients.len(), 1);
+    }
+
+    #[test]
+    fn test_setname_getname() {
+        let server = Server::default();
+        let client = connect(&server, 5000);
+        let getname = cmd(&["GETNAME"]);
+        assert_eq!(command_client(&server, &client, &getname), Ok(RESP::Null));
+        command_client(&server, &client, &cmd(&["SETNAME", "worker-1"])).unwrap();
+        assert_eq!(
+            command_client(&server, &client, &getname),
+            Ok(RESP::BulkString("worker-1".into()))
+        );
+        assert!(command_client(&server, &client, &cmd(&["SETNAME", "a b"])).is_err());
+        command_client(&server, &client, &cmd(&["SETNAME", ""])).unwrap();
+        assert_eq!(command_client(&server, &client, &getname), Ok(RESP::Null));
+    }
+
+    #[test]
+    fn test_list_filters_by_id() {
+        let server = Server::default();
+        let a = connect(&server, 5000);
+        let b = connect(&server, 5001);
+        a.begin_command("client", 30);
+        let list = |parts: &[&str]| match command_client(&server, &a, &cmd(parts)) {
+            Ok(RESP::BulkString(s)) => String::from_utf8(s).unwrap(),
+            other => panic!("unexpected reply {:?}", other),
+        };
+        let all = list(&["LIST"]);
+        assert_eq!(all.lines().count(), 2);
+        assert!(all.contains("addr=127.0.0.1:5000"));
+        assert!(all.contains("qbuf=30 qbuf-free=994"));
+        assert!(all.contains("cmd=client"));
+        let one = list(&["LIST", "ID", &b.id().to_string()]);
+        assert_eq!(one.lines().count(), 1);
+        assert!(one.starts_with(&format!("id={} ", b.id())));
+        assert!(list(&["LIST", "TYPE", "pubsub"]).is_empty());
+    }
+
+    #[test]
+    fn test_kill_filters() {
+        let server = Server::default();
+        let me = connect(&server, 5000);
+        let other = connect(&server, 5001);
+        let kill = |parts: &[&str]| command_client(&server, &me, &cmd(parts));
+
+        // SKIPME defaults to yes
+        assert_eq!(kill(&["KILL", "TYPE", "normal"]), Ok(RESP::Integer(1)));
+        assert!(other.is_killed());
+        assert!(!me.is_killed());
+
+        assert_eq!(kill(&["KILL", "USER", "nobody"]), Ok(RESP::Integer(0)));
+        assert!(kill(&["KILL", "127.0.0.1:9999"]).is_err());
+        assert_eq!(
+            kill(&["KILL", "127.0.0.1:5000"]),
+            Ok(RESP::SimpleString("OK".to_string()))
+        );
+        assert!(me.is_killed());
+        assert!(kill(&["KILL", "TYPE", "bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_reply_skip() {
+        let server = Server::default();
+        let client = connect(&server, 5000);
+        command_client(&server, &client, &cmd(&["REPLY", "SKIP"])).unwrap();
+        // The CLIENT REPLY SKIP reply and the one after it are dropped
+        assert!(!client.should_reply());
+        assert!(!client.should_reply());
+        assert!(client.should_reply());
+        command_client(&server, &client, &cmd(&["REPLY", "OFF"])).unwrap();
+        assert!(!client.should_reply());
+        command_client(&server, &client, &cmd(&["REPLY", "ON"])).unwrap();
+        assert!(client.should_reply());
+    }
+
+    #[test]
+    fn test_pause_modes() {
+        let table = ClientTable::new();
+        table.pause(Duration::from_secs(60), PauseMode::Write);
+        assert!(table.paused_until(true).is_some());
+        assert!(table.paused_until(false).is_none());
+        // Widening to ALL also holds back reads
+        table.pause(Duration::from_millis(1), PauseMode::All);
+        assert!(table.paused_until(false).is_some());
+        table.unpause();
+        assert!(table.paused_until(true).is_none());
+    }
+
+    #[test]
+    fn test_scripts_are_held_by_write_pause() {
+        let held =
+            |parts: &[&str]| held_by_write_pause(crate::command::resolve(&cmd(parts)).unwrap());
+        assert!(held(&["set", "k", "v"]));
+        assert!(held(&["eval", "return 1", "0"]));
+        assert!(held(&["evalsha", "abc", "0"]));
+        assert!(held(&["fcall", "f", "0"]));
+        assert!(!held(&["eval_ro", "return 1", "0"]));
+        assert!(!held(&["evalsha_ro", "abc", "0"]));
+        assert!(!held(&["fcall_ro", "f", "0"]));
+        assert!(!held(&["get", "k"]));
+    }
+}
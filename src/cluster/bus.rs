@@ -0,0 +1,274 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpListener, TcpStream};
+
+use super::{Cluster, Node, SLOTS, now_ms};
+use crate::peer::Peer;
+use crate::server::Server;
+
+/// How often a node pings each of the others.
+const PING_INTERVAL: Duration = Duration::from_millis(100);
+
+// Nodes talk in RESP arrays of bulk strings, each message made of:
+//
+//   PING|PONG|MEET id ip port bus-port config-epoch current-epoch slots
+//   followed by "id ip port bus-port" for every other node the sender knows
+//
+// where slots is a comma separated list of slots and slot ranges like
+// "0-100,205". Every PING and MEET is answered with a PONG.
+
+/// The fields before the gossip about other nodes.
+const HEADER_FIELDS: usize = 8;
+
+fn format_ranges(ranges: &[(u16, u16)]) -> String {
+    ranges
+        .iter()
+        .map(|(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn parse_ranges(ranges: &str) -> Option<Vec<bool>> {
+    let mut claimed = vec![false; SLOTS];
+    for range in ranges.split(',').filter(|range| !range.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let (first, last): (usize, usize) = (first.parse().ok()?, last.parse().ok()?);
+        if first > last || last >= SLOTS {
+            return None;
+        }
+        claimed[first..=last].fill(true);
+    }
+    Some(claimed)
+}
+
+impl Cluster {
+    /// This node's view of itself and the cluster, to send another node.
+    fn message(&self, kind: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let myself = state.myself();
+        let mut message = vec![
+            kind.to_string(),
+            state.myself.clone(),
+            myself.ip.clone(),
+            myself.port.to_string(),
+            myself.bus_port.to_string(),
+            myself.config_epoch.to_string(),
+            state.current_epoch.to_string(),
+            format_ranges(&state.slot_ranges(&state.myself)),
+        ];
+        for (id, node) in &state.nodes {
+            if *id != state.myself {
+                message.push(format!(
+                    "{} {} {} {}",
+                    id, node.ip, node.port, node.bus_port
+                ));
+            }
+        }
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        message
+    }
+
+    /// Takes in what another node says about itself and the nodes it
+    /// knows. Returns the IDs of nodes heard of for the first time, which
+    /// need a link of their own.
+    fn receive(&self, message: &[String]) -> Vec<String> {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        if message.len() < HEADER_FIELDS {
+            return Vec::new();
+        }
+        let (Ok(port), Ok(bus_port), Ok(epoch), Ok(current_epoch), Some(claimed)) = (
+            message[3].parse::<u16>(),
+            message[4].parse::<u16>(),
+            message[5].parse::<u64>(),
+            message[6].parse::<u64>(),
+            parse_ranges(&message[7]),
+        ) else {
+            return Vec::new();
+        };
+        let sender = &message[1];
+        let mut state = self.state.lock().unwrap();
+        if *sender == state.myself {
+            return Vec::new();
+        }
+        let mut discovered = Vec::new();
+        if !state.nodes.contains_key(sender) {
+            discovered.push(sender.clone());
+        }
+        let node = state
+            .nodes
+            .entry(sender.clone())
+            .or_insert_with(|| Node::new(message[2].clone(), port, bus_port));
+        node.ip = message[2].clone();
+        node.port = port;
+        node.bus_port = bus_port;
+        node.config_epoch = epoch;
+        node.last_seen = Instant::now();
+        if message[0] == "PONG" {
+            node.last_pong = now_ms();
+            node.connected = true;
+        }
+        state.current_epoch = state.current_epoch.max(current_epoch);
+
+        // A claim wins over the current owner's with a higher config
+        // epoch, and a node no longer claiming a slot gives it up
+        for (slot, claims) in claimed.into_iter().enumerate() {
+            let owner = &state.slots[slot];
+            if claims {
+                let wins = match owner {
+                    None => true,
+                    Some(owner) if owner == sender => false,
+                    Some(owner) => state.nodes[owner].config_epoch < epoch,
+                };
+                if wins {
+                    state.slots[slot] = Some(sender.clone());
+                    state.importing.remove(&(slot as u16));
+                    state.migrating.remove(&(slot as u16));
+                }
+            } else if owner.as_ref() == Some(sender) {
+                state.slots[slot] = None;
+            }
+        }
+
+        for gossip in &message[HEADER_FIELDS..] {
+            let fields: Vec<&str> = gossip.split(' ').collect();
+            if let [id, ip, port, bus_port] = fields[..]
+                && let (Ok(port), Ok(bus_port)) = (port.parse(), bus_port.parse())
+                && id != state.myself
+                && !state.nodes.contains_key(id)
+            {
+                state
+                    .nodes
+                    .insert(id.to_string(), Node::new(ip.to_string(), port, bus_port));
+                discovered.push(id.to_string());
+            }
+        }
+        discovered
+    }
+
+    fn set_connected(&self, id: &str, connected: bool) {
+        if let Some(node) = self.state.lock().unwrap().nodes.get_mut(id) {
+            node.connected = connected;
+        }
+    }
+
+    fn bus_addr(&self, id: &str) -> Option<(String, u16)> {
+        let state = self.state.lock().unwrap();
+        state
+            .nodes
+            .get(id)
+            .map(|node| (node.ip.clone(), node.bus_port))
+    }
+}
+
+/// Answers other nodes on the cluster bus.
+pub async fn serve_bus(listener: TcpListener, server: Arc<Server>) -> io::Result<()> {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(answer(stream, server.clone()));
+            }
+            Err(e) => {
+                println!("error: {}", e);
+                continue;
+            }
+        }
+    }
+}
+
+async fn answer(stream: TcpStream, server: Arc<Server>) {
+    let cluster = &server.cluster;
+    let mut peer = Peer::new(stream);
+    while let Ok(message) = peer.read_command().await {
+        if !matches!(message[0].as_str(), "PING" | "MEET") {
+            break;
+        }
+        for id in cluster.receive(&message) {
+            spawn_link(&server, id);
+        }
+        if peer.send(&cluster.message("PONG")).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Introduces this node to the one listening on `ip:bus_port`. Once it
+/// answers, both know each other and the rest follows by gossip.
+pub(super) fn meet(server: &Arc<Server>, ip: String, bus_port: u16) {
+    let server = server.clone();
+    tokio::spawn(async move {
+        let cluster = &server.cluster;
+        let result = async {
+            let mut peer = Peer::connect(&ip, bus_port).await?;
+            peer.send(&cluster.message("MEET")).await?;
+            peer.read_command().await
+        };
+        match result.await {
+            Ok(reply) => {
+                for id in cluster.receive(&reply) {
+                    spawn_link(&server, id);
+                }
+            }
+            Err(e) => eprintln!("cluster: meeting {}:{} failed: {}", ip, bus_port, e),
+        }
+    });
+}
+
+/// Pings a node for as long as it's part of the cluster, reconnecting
+/// whenever the connection drops.
+fn spawn_link(server: &Arc<Server>, id: String) {
+    let server = server.clone();
+    tokio::spawn(async move {
+        let cluster = &server.cluster;
+        while let Some((ip, bus_port)) = cluster.bus_addr(&id) {
+            if ping(&server, &id, &ip, bus_port).await.is_err() {
+                cluster.set_connected(&id, false);
+            }
+            tokio::time::sleep(PING_INTERVAL).await;
+        }
+    });
+}
+
+async fn ping(server: &Arc<Server>, id: &str, ip: &str, bus_port: u16) -> io::Result<()> {
+    let cluster = &server.cluster;
+    let mut peer = Peer::connect(ip, bus_port).await?;
+    let mut interval = tokio::time::interval(PING_INTERVAL);
+    loop {
+        interval.tick().await;
+        peer.send(&cluster.message("PING")).await?;
+        let reply = tokio::time::timeout(cluster.node_timeout, peer.read_command())
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+        for id in cluster.receive(&reply) {
+            spawn_link(server, id);
+        }
+        // The node may have moved to another address
+        if cluster.bus_addr(id) != Some((ip.to_string(), bus_port)) {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slot_ranges_round_trip() {
+        let claimed = parse_ranges("0-2,5,16383").unwrap();
+        assert_eq!(claimed.iter().filter(|c| **c).count(), 5);
+        assert!(claimed[5] && !claimed[4] && claimed[16383]);
+        assert_eq!(format_ranges(&[(0, 2), (5, 5)]), "0-2,5");
+        assert!(parse_ranges("").unwrap().iter().all(|c| !c));
+        assert!(parse_ranges("3-1").is_none());
+        assert!(parse_ranges("16384").is_none());
+    }
+}
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use crate::command::{self, CommandArg};
+use crate::peer::Peer;
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+
+/// How long MIGRATE waits on the target when given a timeout of 0.
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+struct Options {
+    copy: bool,
+    replace: bool,
+    /// Username, if any, and password to authenticate with
+    auth: Option<(Option<String>, String)>,
+    keys: Vec<String>,
+}
+
+fn parse_options(args: &[CommandArg]) -> ServerResult<Options> {
+    let syntax = || ServerError::InvalidArgument("syntax error".to_string());
+    let mut options = Options {
+        copy: false,
+        replace: false,
+        auth: None,
+        keys: Vec::new(),
+    };
+    let mut i = 5;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "COPY" => options.copy = true,
+            "REPLACE" => options.replace = true,
+            "AUTH" => {
+                let password = args.get(i + 1).ok_or_else(syntax)?;
+                options.auth = Some((None, password.to_string()));
+                i += 1;
+            }
+            "AUTH2" => {
+                let (Some(user), Some(password)) = (args.get(i + 1), args.get(i + 2)) else {
+                    return Err(syntax());
+                };
+                options.auth = Some((Some(user.to_string()), password.to_string()));
+                i += 2;
+            }
+            "KEYS" => {
+                if !args[2].is_empty() {
+                    return Err(ServerError::InvalidArgument(
+                        "When using MIGRATE KEYS option, the key argument must be set to the empty string".to_string(),
+                    ));
+                }
+                options.keys = args[i + 1..].iter().map(|arg| arg.to_string()).collect();
+                break;
+            }
+            _ => return Err(syntax()),
+        }
+        i += 1;
+    }
+    if options.keys.is_empty() {
+        options.keys.push(args[2].to_string());
+    }
+    Ok(options)
+}
+
+/// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE]
+/// [AUTH password | AUTH2 username password] [KEYS key ...]
+///
+/// Sends keys to another server with RESTORE, then deletes them here
+/// unless COPY is given. Replies NOKEY when none of them exist.
+pub async fn command_migrate(server: &Server, args: &[CommandArg]) -> ServerResult<RESP> {
+    let not_integer =
+        || ServerError::InvalidArgument("value is not an integer or out of range".to_string());
+    let port: u16 = args[1].parse().map_err(|_| not_integer())?;
+    let db: i64 = args[3].parse().map_err(|_| not_integer())?;
+    let timeout: i64 = args[4].parse().map_err(|_| not_integer())?;
+    let options = parse_options(args)?;
+    // There is only one database to migrate to
+    if db != 0 {
+        return Err(ServerError::InvalidArgument(
+            "Target instance replied with error: ERR DB index is out of range".to_string(),
+        ));
+    }
+    let timeout = match timeout {
+        ..=0 => DEFAULT_TIMEOUT,
+        ms => Duration::from_millis(ms as u64),
+    };
+
+    let mut dumps = Vec::new();
+    for key in &options.keys {
+        if let Some((payload, expires_at)) = server.storage.dump(key).await {
+            dumps.push((key.clone(), payload, expires_at));
+        }
+    }
+    if dumps.is_empty() {
+        return Ok(RESP::SimpleString("NOKEY".to_string()));
+    }
+
+    // The target is importing the slot, so only takes the keys when told
+    // they come from a migration
+    let asking = server.cluster.is_enabled();
+    let transfer = async {
+        let mut peer = Peer::connect(&args[0], port).await?;
+        if let Some((user, password)) = &options.auth {
+            let mut auth = vec!["AUTH".to_string()];
+            auth.extend(user.iter().cloned());
+            auth.push(password.clone());
+            peer.send(&auth).await?;
+            let reply = peer.read_line().await?;
+            if reply.starts_with('-') {
+                return Ok(Some(reply));
+            }
+        }
+        for (key, payload, expires_at) in &dumps {
+            if asking {
+                peer.command(&["ASKING"]).await?;
+            }
+            let mut restore = vec!["RESTORE".to_string(), key.clone()];
+            match expires_at {
+                Some(at) => restore.extend([at.to_string(), payload.clone(), "ABSTTL".to_string()]),
+                None => restore.extend(["0".to_string(), payload.clone()]),
+            }
+            if options.replace {
+                restore.push("REPLACE".to_string());
+            }
+            peer.send(&restore).await?;
+            let reply = peer.read_line().await?;
+            if reply.starts_with('-') {
+                return Ok(Some(reply));
+            }
+        }
+        Ok::<Option<String>, std::io::Error>(None)
+    };
+    match tokio::time::timeout(timeout, transfer).await {
+        Err(_) => {
+            return Err(ServerError::IoErr(
+                "error or timeout reading to target instance".to_string(),
+            ));
+        }
+        Ok(Err(e)) => {
+            return Err(ServerError::IoErr(format!(
+                "error or timeout connecting to target instance: {}",
+                e
+            )));
+        }
+        Ok(Ok(Some(error))) => {
+            return Err(ServerError::InvalidArgument(format!(
+                "Target instance replied with error: {}",
+                &error[1..]
+            )));
+        }
+        Ok(Ok(None)) => (),
+    }
+
+    if !options.copy {
+        let mut del = vec![CommandArg::from("DEL")];
+        del.extend(dumps.into_iter().map(|(key, _, _)| CommandArg::from(key)));
+        let spec = command::resolve(&del)?;
+        server.storage.execute(spec, &del).await?;
+    }
+    Ok(RESP::SimpleString("OK".to_string()))
+}
@@ -0,0 +1,891 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod bus;
+mod migrate;
+
+pub use self::bus::serve_bus;
+pub use self::migrate::command_migrate;
+use crate::client::Client;
+use crate::command::{Command, CommandArg, CommandSpec};
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+use crate::storage::result::StorageError;
+
+/// Hash slots the keyspace is divided into.
+pub const SLOTS: usize = 16384;
+
+/// How far above the client port the cluster bus listens, unless
+/// `cluster-port` says otherwise.
+const BUS_PORT_OFFSET: u16 = 10000;
+
+/// `cluster-node-timeout` when it isn't configured, in milliseconds.
+const DEFAULT_NODE_TIMEOUT: u64 = 15000;
+
+/// CRC16-CCITT (XMODEM), the checksum Redis Cluster hashes keys with.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The part of a key that decides where it lives: its hash tag, the part
+/// between the first `{` and the next `}`, when that isn't empty, so
+/// related keys can be kept together. Otherwise the whole key.
+pub fn hash_tag(key: &str) -> &[u8] {
+    let bytes = key.as_bytes();
+    bytes
+        .iter()
+        .position(|b| *b == b'{')
+        .and_then(|open| {
+            let tag = &bytes[open + 1..];
+            let close = tag.iter().position(|b| *b == b'}')?;
+            (close > 0).then(|| &tag[..close])
+        })
+        .unwrap_or(bytes)
+}
+
+/// The slot a key belongs to, from a hash of its hash tag.
+pub fn key_slot(key: &str) -> u16 {
+    crc16(hash_tag(key)) % SLOTS as u16
+}
+
+/// A 40 character node ID, like the ones Redis makes up.
+fn new_node_id() -> String {
+    format!(
+        "{:032x}{:08x}",
+        rand::random::<u128>(),
+        rand::random::<u32>()
+    )
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// A node of the cluster, this one included.
+struct Node {
+    ip: String,
+    port: u16,
+    bus_port: u16,
+    /// Settles which node owns a slot when two claim it: the higher wins
+    config_epoch: u64,
+    /// Unix milliseconds of the last PONG, 0 if none came yet
+    last_pong: u64,
+    /// When anything last arrived from it, for failure detection
+    last_seen: Instant,
+    /// Whether our bus connection to it is up
+    connected: bool,
+}
+
+impl Node {
+    fn new(ip: String, port: u16, bus_port: u16) -> Self {
+        Node {
+            ip,
+            port,
+            bus_port,
+            config_epoch: 0,
+            last_pong: 0,
+            last_seen: Instant::now(),
+            connected: false,
+        }
+    }
+
+    fn addr(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+struct State {
+    myself: String,
+    nodes: BTreeMap<String, Node>,
+    /// The ID of the node owning each slot
+    slots: Vec<Option<String>>,
+    /// Slots this node is handing over, to the node taking them
+    migrating: BTreeMap<u16, String>,
+    /// Slots this node is taking over, from the node handing them over
+    importing: BTreeMap<u16, String>,
+    current_epoch: u64,
+}
+
+impl State {
+    fn myself(&self) -> &Node {
+        &self.nodes[&self.myself]
+    }
+
+    fn owns(&self, slot: u16) -> bool {
+        self.slots[slot as usize].as_ref() == Some(&self.myself)
+    }
+
+    /// The slots owned by `id` as ranges of first and last slot.
+    fn slot_ranges(&self, id: &str) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for (slot, owner) in self.slots.iter().enumerate() {
+            if owner.as_deref() != Some(id) {
+                continue;
+            }
+            let slot = slot as u16;
+            match ranges.last_mut() {
+                Some((_, last)) if *last + 1 == slot => *last = slot,
+                _ => ranges.push((slot, slot)),
+            }
+        }
+        ranges
+    }
+
+    fn node(&self, id: &str) -> ServerResult<&Node> {
+        self.nodes
+            .get(id)
+            .ok_or_else(|| ServerError::InvalidArgument(format!("I don't know about node {}", id)))
+    }
+}
+
+/// This node's view of the cluster: who is in it and which node serves
+/// each slot. Nodes keep each other up to date over the cluster bus.
+pub struct Cluster {
+    enabled: bool,
+    node_timeout: Duration,
+    state: Mutex<State>,
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+}
+
+impl Cluster {
+    /// Set up from the `cluster-*` configs. Without `cluster-enabled yes`
+    /// the node is standalone and the rest goes unused.
+    pub fn new(config: &HashMap<String, String>) -> Self {
+        let get = |key: &str| config.get(key).map(String::as_str).unwrap_or_default();
+        let port: u16 = get("port").parse().unwrap_or(6379);
+        let bus_port = get("cluster-port")
+            .parse()
+            .ok()
+            .filter(|port| *port != 0)
+            .unwrap_or(port.wrapping_add(BUS_PORT_OFFSET));
+        let ip = match get("cluster-announce-ip") {
+            "" => "127.0.0.1",
+            ip => ip,
+        };
+        let myself = new_node_id();
+        let nodes = BTreeMap::from([(myself.clone(), Node::new(ip.to_string(), port, bus_port))]);
+        Cluster {
+            enabled: get("cluster-enabled") == "yes",
+            node_timeout: Duration::from_millis(
+                get("cluster-node-timeout")
+                    .parse()
+                    .unwrap_or(DEFAULT_NODE_TIMEOUT),
+            ),
+            state: Mutex::new(State {
+                myself,
+                nodes,
+                slots: vec![None; SLOTS],
+                migrating: BTreeMap::new(),
+                importing: BTreeMap::new(),
+                current_epoch: 0,
+            }),
+            messages_sent: AtomicU64::new(0),
+            messages_received: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn bus_port(&self) -> u16 {
+        self.state.lock().unwrap().myself().bus_port
+    }
+
+    fn is_failing(&self, node: &Node) -> bool {
+        node.last_seen.elapsed() > self.node_timeout
+    }
+
+    /// Sends a request elsewhere when its slot isn't served here: MOVED
+    /// to the slot's owner, or ASK to the node importing it for keys that
+    /// already left. Runs before every command in cluster mode.
+    pub async fn check_request(
+        &self,
+        server: &Server,
+        client: &Client,
+        spec: &CommandSpec,
+        args: &[CommandArg],
+    ) -> ServerResult<()> {
+        let asking = spec.command != Command::Asking && client.take_asking();
+        // MIGRATE moves keys this node may only partly have, so it names
+        // them however it likes
+        if spec.command == Command::Migrate {
+            return Ok(());
+        }
+        let keys = spec.keys(args);
+        let Some(first) = keys.first() else {
+            return Ok(());
+        };
+        let slot = key_slot(first);
+        if keys.iter().any(|key| key_slot(key) != slot) {
+            return Err(StorageError::CrossSlot.into());
+        }
+        let migrating_to = {
+            let state = self.state.lock().unwrap();
+            if !state.owns(slot) {
+                if asking && state.importing.contains_key(&slot) {
+                    return Ok(());
+                }
+                return match &state.slots[slot as usize] {
+                    Some(owner) => Err(ServerError::Moved(slot, state.nodes[owner].addr())),
+                    None => Err(ServerError::ClusterDown),
+                };
+            }
+            match state.migrating.get(&slot) {
+                Some(target) => state.nodes[target].addr(),
+                None => return Ok(()),
+            }
+        };
+        for key in keys {
+            if !server.storage.contains(key).await {
+                return Err(ServerError::Ask(slot, migrating_to));
+            }
+        }
+        Ok(())
+    }
+
+    fn info(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let assigned = state.slots.iter().filter(|owner| owner.is_some()).count();
+        let pfail = state
+            .slots
+            .iter()
+            .flatten()
+            .filter(|owner| **owner != state.myself && self.is_failing(&state.nodes[*owner]))
+            .count();
+        let size = state
+            .nodes
+            .keys()
+            .filter(|id| state.slots.contains(&Some(id.to_string())))
+            .count();
+        let ok = if assigned == SLOTS { "ok" } else { "fail" };
+        [
+            ("cluster_state", ok.to_string()),
+            ("cluster_slots_assigned", assigned.to_string()),
+            ("cluster_slots_ok", (assigned - pfail).to_string()),
+            ("cluster_slots_pfail", pfail.to_string()),
+            ("cluster_slots_fail", "0".to_string()),
+            ("cluster_known_nodes", state.nodes.len().to_string()),
+            ("cluster_size", size.to_string()),
+            ("cluster_current_epoch", state.current_epoch.to_string()),
+            ("cluster_my_epoch", state.myself().config_epoch.to_string()),
+            (
+                "cluster_stats_messages_sent",
+                self.messages_sent.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "cluster_stats_messages_received",
+                self.messages_received.load(Ordering::Relaxed).to_string(),
+            ),
+        ]
+        .iter()
+        .map(|(key, value)| format!("{}:{}\r\n", key, value))
+        .collect()
+    }
+
+    /// One line per node, in the format of `nodes.conf`.
+    fn nodes(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        for (id, node) in &state.nodes {
+            let myself = *id == state.myself;
+            let mut flags = if myself { "myself,master" } else { "master" }.to_string();
+            if !myself && self.is_failing(node) {
+                flags.push_str(",fail?");
+            }
+            let link = if myself || node.connected {
+                "connected"
+            } else {
+                "disconnected"
+            };
+            out.push_str(&format!(
+                "{} {}@{} {} - 0 {} {} {}",
+                id,
+                node.addr(),
+                node.bus_port,
+                flags,
+                node.last_pong,
+                node.config_epoch,
+                link
+            ));
+            for (first, last) in state.slot_ranges(id) {
+                if first == last {
+                    out.push_str(&format!(" {}", first));
+                } else {
+                    out.push_str(&format!(" {}-{}", first, last));
+                }
+            }
+            if myself {
+                for (slot, target) in &state.migrating {
+                    out.push_str(&format!(" [{}->-{}]", slot, target));
+                }
+                for (slot, source) in &state.importing {
+                    out.push_str(&format!(" [{}-<-{}]", slot, source));
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn slots(&self) -> RESP {
+        let state = self.state.lock().unwrap();
+        let mut ranges: Vec<(u16, u16, &String)> = state
+            .nodes
+            .keys()
+            .flat_map(|id| {
+                state
+                    .slot_ranges(id)
+                    .into_iter()
+                    .map(move |(first, last)| (first, last, id))
+            })
+            .collect();
+        ranges.sort();
+        RESP::Array(
+            ranges
+                .into_iter()
+                .map(|(first, last, id)| {
+                    let node = &state.nodes[id];
+                    RESP::Array(vec![
+                        RESP::Integer(first as i64),
+                        RESP::Integer(last as i64),
+                        RESP::Array(vec![
+                            RESP::BulkString(node.ip.clone().into()),
+                            RESP::Integer(node.port as i64),
+                            RESP::BulkString(id.clone().into()),
+                        ]),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn shards(&self) -> RESP {
+        let state = self.state.lock().unwrap();
+        let bulk = |s: &str| RESP::BulkString(s.into());
+        RESP::Array(
+            state
+                .nodes
+                .iter()
+                .map(|(id, node)| {
+                    let slots = state
+                        .slot_ranges(id)
+                        .into_iter()
+                        .flat_map(|(first, last)| {
+                            [RESP::Integer(first as i64), RESP::Integer(last as i64)]
+                        })
+                        .collect();
+                    let healthy = *id == state.myself || !self.is_failing(node);
+                    let description = vec![
+                        bulk("id"),
+                        bulk(id),
+                        bulk("port"),
+                        RESP::Integer(node.port as i64),
+                        bulk("ip"),
+                        bulk(&node.ip),
+                        bulk("endpoint"),
+                        bulk(&node.ip),
+                        bulk("role"),
+                        bulk("master"),
+                        bulk("replication-offset"),
+                        RESP::Integer(0),
+                        bulk("health"),
+                        bulk(if healthy { "online" } else { "fail" }),
+                    ];
+                    RESP::Array(vec![
+                        bulk("slots"),
+                        RESP::Array(slots),
+                        bulk("nodes"),
+                        RESP::Array(vec![RESP::Array(description)]),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    /// ADDSLOTS and DELSLOTS, and their RANGE forms, once the slots are
+    /// parsed. Nothing changes unless every slot can be.
+    fn assign(&self, slots: &[u16], add: bool) -> ServerResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let mut seen = vec![false; SLOTS];
+        for slot in slots {
+            let assigned = state.slots[*slot as usize].is_some();
+            if add && assigned {
+                return Err(invalid(&format!("Slot {} is already busy", slot)));
+            }
+            if !add && !assigned {
+                return Err(invalid(&format!("Slot {} is already unassigned", slot)));
+            }
+            if std::mem::replace(&mut seen[*slot as usize], true) {
+                return Err(invalid(&format!("Slot {} specified multiple times", slot)));
+            }
+        }
+        let owner = add.then(|| state.myself.clone());
+        for slot in slots {
+            state.slots[*slot as usize] = owner.clone();
+            state.importing.remove(slot);
+            state.migrating.remove(slot);
+        }
+        Ok(())
+    }
+
+    /// CLUSTER SETSLOT slot IMPORTING|MIGRATING|NODE id, or STABLE.
+    async fn set_slot(&self, server: &Server, slot: u16, args: &[CommandArg]) -> ServerResult<()> {
+        let action = args[0].to_uppercase();
+        let id = match (action.as_str(), args.len()) {
+            ("STABLE", 1) => None,
+            ("IMPORTING" | "MIGRATING" | "NODE", 2) => Some(args[1].clone()),
+            _ => {
+                return Err(invalid(
+                    "Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP",
+                ));
+            }
+        };
+        // A slot can only change hands once its keys are gone
+        let giving_away = {
+            let state = self.state.lock().unwrap();
+            id.as_ref().is_some_and(|id| *id != state.myself) && state.owns(slot)
+        };
+        if action == "NODE" && giving_away && !keys_in_slot(server, slot).await.is_empty() {
+            return Err(invalid(&format!(
+                "Can't assign hashslot {} to a different node while I still hold keys for this hash slot.",
+                slot
+            )));
+        }
+        let mut state = self.state.lock().unwrap();
+        let Some(id) = id else {
+            state.migrating.remove(&slot);
+            state.importing.remove(&slot);
+            return Ok(());
+        };
+        state.node(&id)?;
+        match action.as_str() {
+            "MIGRATING" => {
+                if !state.owns(slot) {
+                    return Err(invalid(&format!("I'm not the owner of hash slot {}", slot)));
+                }
+                state.migrating.insert(slot, id.to_string());
+            }
+            "IMPORTING" => {
+                if state.owns(slot) {
+                    return Err(invalid(&format!(
+                        "I'm already the owner of hash slot {}",
+                        slot
+                    )));
+                }
+                state.importing.insert(slot, id.to_string());
+            }
+            _ => {
+                state.migrating.remove(&slot);
+                // Taking over an imported slot needs a new epoch, so the
+                // rest of the cluster prefers our claim to the old owner's
+                if id == state.myself && state.importing.remove(&slot).is_some() {
+                    state.current_epoch += 1;
+                    let epoch = state.current_epoch;
+                    let myself = state.myself.clone();
+                    state.nodes.get_mut(&myself).unwrap().config_epoch = epoch;
+                }
+                state.slots[slot as usize] = Some(id.to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: &str) -> ServerError {
+    ServerError::InvalidArgument(message.to_string())
+}
+
+fn parse_slot(value: &str) -> ServerResult<u16> {
+    value
+        .parse::<u16>()
+        .ok()
+        .filter(|slot| (*slot as usize) < SLOTS)
+        .ok_or_else(|| invalid("Invalid or out of range slot"))
+}
+
+async fn keys_in_slot(server: &Server, slot: u16) -> Vec<String> {
+    server
+        .storage
+        .keys_where(move |key| key_slot(key) == slot)
+        .await
+}
+
+/// CLUSTER, with `args` starting at the subcommand.
+pub async fn command_cluster(
+    server: &Arc<Server>,
+    spec: &CommandSpec,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let cluster = &server.cluster;
+    let ok = || Ok(RESP::SimpleString("OK".to_string()));
+    if spec.name == "cluster|help" {
+        return Ok(RESP::Array(
+            [
+                "CLUSTER <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "ADDSLOTS <slot> [<slot> ...]",
+                "    Assign slots to current node.",
+                "ADDSLOTSRANGE <start slot> <end slot> [<start slot> <end slot> ...]",
+                "    Assign slots which are between <start-slot> and <end-slot> to current node.",
+                "COUNTKEYSINSLOT <slot>",
+                "    Return the number of keys in <slot>.",
+                "DELSLOTS <slot> [<slot> ...]",
+                "    Delete slots information from current node.",
+                "DELSLOTSRANGE <start slot> <end slot> [<start slot> <end slot> ...]",
+                "    Delete slots information which are between <start-slot> and <end-slot>.",
+                "GETKEYSINSLOT <slot> <count>",
+                "    Return key names stored by current node in a slot.",
+                "INFO",
+                "    Return information about the cluster.",
+                "KEYSLOT <key>",
+                "    Return the hash slot for <key>.",
+                "MEET <ip> <port> [<bus-port>]",
+                "    Connect nodes into a working cluster.",
+                "MYID",
+                "    Return the node id.",
+                "NODES",
+                "    Return cluster configuration seen by node.",
+                "SETSLOT <slot> (IMPORTING <node-id>|MIGRATING <node-id>|STABLE|NODE <node-id>)",
+                "    Set slot state.",
+                "SHARDS",
+                "    Return information about slot range mappings and the nodes associated with them.",
+                "SLOTS",
+                "    Return information about slots range mappings.",
+                "HELP",
+                "    Print this help.",
+            ]
+            .iter()
+            .map(|line| RESP::SimpleString(line.to_string()))
+            .collect(),
+        ));
+    }
+    if !cluster.is_enabled() {
+        return Err(invalid("This instance has cluster support disabled"));
+    }
+    match spec.name {
+        "cluster|info" => Ok(RESP::BulkString(cluster.info().into())),
+        "cluster|myid" => Ok(RESP::BulkString(
+            cluster.state.lock().unwrap().myself.clone().into(),
+        )),
+        "cluster|nodes" => Ok(RESP::BulkString(cluster.nodes().into())),
+        "cluster|slots" => Ok(cluster.slots()),
+        "cluster|shards" => Ok(cluster.shards()),
+        "cluster|keyslot" => Ok(RESP::Integer(key_slot(&args[1]) as i64)),
+        "cluster|countkeysinslot" => {
+            let slot = parse_slot(&args[1])?;
+            Ok(RESP::Integer(keys_in_slot(server, slot).await.len() as i64))
+        }
+        "cluster|getkeysinslot" => {
+            let slot = parse_slot(&args[1])?;
+            let count: usize = args[2]
+                .parse()
+                .map_err(|_| invalid("Invalid number of keys"))?;
+            let mut keys = keys_in_slot(server, slot).await;
+            keys.sort();
+            keys.truncate(count);
+            Ok(RESP::Array(
+                keys.into_iter()
+                    .map(|s| RESP::BulkString(s.into()))
+                    .collect(),
+            ))
+        }
+        "cluster|meet" => {
+            let port = |value: &str| {
+                value
+                    .parse::<u16>()
+                    .map_err(|_| invalid(&format!("Invalid base port specified: {}", value)))
+            };
+            let base = port(&args[2])?;
+            let bus = match args.get(3) {
+                Some(bus) => port(bus)?,
+                None => base.wrapping_add(BUS_PORT_OFFSET),
+            };
+            bus::meet(server, args[1].to_string(), bus);
+            ok()
+        }
+        "cluster|addslots" | "cluster|delslots" => {
+            let slots = args[1..]
+                .iter()
+                .map(|slot| parse_slot(slot))
+                .collect::<ServerResult<Vec<u16>>>()?;
+            cluster.assign(&slots, spec.name == "cluster|addslots")?;
+            ok()
+        }
+        "cluster|addslotsrange" | "cluster|delslotsrange" => {
+            if !(args.len() - 1).is_multiple_of(2) {
+                return Err(ServerError::WrongArity(spec.name.to_string()));
+            }
+            let mut slots = Vec::new();
+            for range in args[1..].chunks(2) {
+                let (first, last) = (parse_slot(&range[0])?, parse_slot(&range[1])?);
+                if first > last {
+                    return Err(invalid(&format!(
+                        "start slot number {} is greater than end slot number {}",
+                        first, last
+                    )));
+                }
+                slots.extend(first..=last);
+            }
+            cluster.assign(&slots, spec.name == "cluster|addslotsrange")?;
+            ok()
+        }
+        "cluster|setslot" => {
+            let slot = parse_slot(&args[1])?;
+            cluster.set_slot(server, slot, &args[2..]).await?;
+            ok()
+        }
+        _ => Err(ServerError::UnknownSubcommand(
+            args[0].to_string(),
+            "CLUSTER".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::server::{process_request, serve};
+    use crate::storage::Keyspace;
+
+    #[test]
+    fn test_key_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot("foo"), 12182);
+        assert_eq!(key_slot("bar"), 5061);
+        assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+        assert_eq!(key_slot("foo{}{bar}"), key_slot("foo{}{bar}"));
+        assert_ne!(key_slot("foo{}{bar}"), key_slot("bar"));
+        assert_eq!(key_slot("foo{{bar}}zap"), key_slot("{bar"));
+    }
+
+    struct TestNode {
+        server: Arc<Server>,
+        client: Arc<Client>,
+        port: u16,
+        bus_port: u16,
+    }
+
+    impl TestNode {
+        async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let bus = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let bus_port = bus.local_addr().unwrap().port();
+            let config = HashMap::from([
+                ("port".to_string(), port.to_string()),
+                ("cluster-enabled".to_string(), "yes".to_string()),
+                ("cluster-port".to_string(), bus_port.to_string()),
+            ]);
+            let server = Arc::new(Server::new(config, Keyspace::default()));
+            tokio::spawn(serve(listener, server.clone()));
+            tokio::spawn(serve_bus(bus, server.clone()));
+            let client = server.clients.register(String::new(), String::new());
+            client.login("default");
+            TestNode {
+                server,
+                client,
+                port,
+                bus_port,
+            }
+        }
+
+        async fn send(&self, parts: &[&str]) -> RESP {
+            let request = RESP::Array(
+                parts
+                    .iter()
+                    .map(|part| RESP::BulkString(part.as_bytes().to_vec()))
+                    .collect(),
+            );
+            process_request(request, self.server.clone(), &self.client)
+                .await
+                .unwrap_or_else(|e| RESP::Error(e.to_string()))
+        }
+
+        fn id(&self) -> String {
+            self.server.cluster.state.lock().unwrap().myself.clone()
+        }
+
+        fn addr(&self) -> String {
+            format!("127.0.0.1:{}", self.port)
+        }
+    }
+
+    /// Polls until `check` holds, failing after a few seconds.
+    async fn eventually(check: impl Fn() -> bool) {
+        for _ in 0..300 {
+            if check() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the cluster never converged");
+    }
+
+    fn ok() -> RESP {
+        RESP::SimpleString("OK".to_string())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_redirects_and_slot_migration() {
+        let a = TestNode::start().await;
+        let b = TestNode::start().await;
+        let b_port = b.port.to_string();
+        let b_bus = b.bus_port.to_string();
+        assert_eq!(
+            a.send(&["cluster", "meet", "127.0.0.1", &b_port, &b_bus])
+                .await,
+            ok()
+        );
+        assert_eq!(
+            a.send(&["cluster", "addslotsrange", "0", "8191"]).await,
+            ok()
+        );
+        assert_eq!(
+            b.send(&["cluster", "addslotsrange", "8192", "16383"]).await,
+            ok()
+        );
+        eventually(|| {
+            a.server.cluster.info().starts_with("cluster_state:ok")
+                && b.server.cluster.info().starts_with("cluster_state:ok")
+        })
+        .await;
+        assert_eq!(
+            b.send(&["cluster", "addslots", "100"]).await,
+            RESP::Error("ERR Slot 100 is already busy".to_string())
+        );
+        assert_eq!(
+            a.send(&["cluster", "keyslot", "foo"]).await,
+            RESP::Integer(12182)
+        );
+
+        // foo lives on b and bar on a
+        let moved_foo = RESP::Error(format!("MOVED 12182 {}", b.addr()));
+        assert_eq!(a.send(&["set", "foo", "1"]).await, moved_foo);
+        assert_eq!(b.send(&["set", "foo", "1"]).await, ok());
+        assert_eq!(b.send(&["set", "{foo}2", "2"]).await, ok());
+        assert_eq!(a.send(&["set", "bar", "1"]).await, ok());
+        assert_eq!(
+            a.send(&["mget", "bar", "foo"]).await,
+            RESP::Error("CROSSSLOT Keys in request don't hash to the same slot".to_string())
+        );
+        assert_eq!(
+            a.send(&["eval", "return 1", "2", "bar", "foo"]).await,
+            RESP::Error("CROSSSLOT Keys in request don't hash to the same slot".to_string())
+        );
+        assert_eq!(a.send(&["eval", "return 1", "1", "foo"]).await, moved_foo);
+        assert_eq!(
+            b.send(&["cluster", "countkeysinslot", "12182"]).await,
+            RESP::Integer(2)
+        );
+        assert_eq!(
+            a.send(&["ping"]).await,
+            RESP::SimpleString("PONG".to_string())
+        );
+
+        // Move slot 12182 from b to a, one key at a time
+        let (a_id, b_id) = (a.id(), b.id());
+        assert_eq!(
+            a.send(&["cluster", "setslot", "12182", "importing", &b_id])
+                .await,
+            ok()
+        );
+        assert_eq!(
+            b.send(&["cluster", "setslot", "12182", "migrating", &a_id])
+                .await,
+            ok()
+        );
+        let a_port = a.port.to_string();
+        assert_eq!(
+            b.send(&["migrate", "127.0.0.1", &a_port, "foo", "0", "5000"])
+                .await,
+            ok()
+        );
+        let ask = RESP::Error(format!("ASK 12182 {}", a.addr()));
+        assert_eq!(b.send(&["get", "foo"]).await, ask);
+        assert_eq!(
+            b.send(&["get", "{foo}2"]).await,
+            RESP::BulkString("2".into())
+        );
+        assert_eq!(a.send(&["get", "foo"]).await, moved_foo);
+        assert_eq!(a.send(&["asking"]).await, ok());
+        assert_eq!(a.send(&["get", "foo"]).await, RESP::BulkString("1".into()));
+        assert_eq!(a.send(&["get", "foo"]).await, moved_foo);
+        assert_eq!(
+            b.send(&["cluster", "setslot", "12182", "node", &a_id]).await,
+            RESP::Error("ERR Can't assign hashslot 12182 to a different node while I still hold keys for this hash slot.".to_string())
+        );
+        assert_eq!(
+            b.send(&[
+                "migrate",
+                "127.0.0.1",
+                &a_port,
+                "",
+                "0",
+                "5000",
+                "KEYS",
+                "{foo}2"
+            ])
+            .await,
+            ok()
+        );
+        assert_eq!(
+            b.send(&[
+                "migrate",
+                "127.0.0.1",
+                &a_port,
+                "",
+                "0",
+                "5000",
+                "KEYS",
+                "{foo}2"
+            ])
+            .await,
+            RESP::SimpleString("NOKEY".to_string())
+        );
+        assert_eq!(
+            a.send(&["cluster", "setslot", "12182", "node", &a_id])
+                .await,
+            ok()
+        );
+        assert_eq!(
+            b.send(&["cluster", "setslot", "12182", "node", &a_id])
+                .await,
+            ok()
+        );
+        assert_eq!(a.send(&["get", "foo"]).await, RESP::BulkString("1".into()));
+        assert_eq!(
+            b.send(&["get", "foo"]).await,
+            RESP::Error(format!("MOVED 12182 {}", a.addr()))
+        );
+        // The new owner's bumped epoch wins everywhere
+        eventually(|| {
+            b.server.cluster.nodes().lines().any(|line| {
+                let fields: Vec<&str> = line.split(' ').collect();
+                fields[0] == a_id && fields[6] == "1" && fields.contains(&"12182")
+            })
+        })
+        .await;
+        let RESP::Array(slots) = a.send(&["cluster", "slots"]).await else {
+            panic!("CLUSTER SLOTS replies with an array");
+        };
+        assert_eq!(slots.len(), 4);
+    }
+}
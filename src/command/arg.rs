@@ -0,0 +1,117 @@
+use std::fmt;
+use std::ops::Deref;
+
+/// An argument of a request. Commands read arguments as text, which is
+/// what this derefs to; values they store keep the bytes the client sent,
+/// which needn't be UTF-8.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommandArg {
+    text: String,
+    /// The bytes sent, when they aren't UTF-8 and `text` is a lossy copy
+    bytes: Option<Vec<u8>>,
+}
+
+impl CommandArg {
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.bytes {
+            Some(bytes) => bytes,
+            None => self.text.as_bytes(),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self.bytes {
+            Some(bytes) => bytes,
+            None => self.text.into_bytes(),
+        }
+    }
+
+    /// The argument as text, lossy when it isn't UTF-8.
+    pub fn into_string(self) -> String {
+        self.text
+    }
+
+    /// Whether the argument is text, as keys and options must be.
+    pub fn is_utf8(&self) -> bool {
+        self.bytes.is_none()
+    }
+}
+
+impl Deref for CommandArg {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.text
+    }
+}
+
+impl AsRef<str> for CommandArg {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for CommandArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.text.fmt(f)
+    }
+}
+
+impl From<String> for CommandArg {
+    fn from(text: String) -> Self {
+        CommandArg { text, bytes: None }
+    }
+}
+
+impl From<&str> for CommandArg {
+    fn from(text: &str) -> Self {
+        CommandArg::from(text.to_string())
+    }
+}
+
+impl From<Vec<u8>> for CommandArg {
+    fn from(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => CommandArg::from(text),
+            Err(e) => CommandArg {
+                text: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                bytes: Some(e.into_bytes()),
+            },
+        }
+    }
+}
+
+impl PartialEq<str> for CommandArg {
+    fn eq(&self, other: &str) -> bool {
+        self.is_utf8() && self.text == other
+    }
+}
+
+impl PartialEq<&str> for CommandArg {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl PartialEq<String> for CommandArg {
+    fn eq(&self, other: &String) -> bool {
+        self == other.as_str()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_binary_args_keep_their_bytes() {
+        let text = CommandArg::from(b"value".to_vec());
+        assert!(text.is_utf8());
+        assert_eq!(text, "value");
+        let binary = CommandArg::from(vec![b'a', 0x81]);
+        assert!(!binary.is_utf8());
+        assert_eq!(binary.as_str(), "a\u{fffd}");
+        assert_ne!(binary, "a\u{fffd}");
+        assert_eq!(binary.into_bytes(), vec![b'a', 0x81]);
+    }
+}
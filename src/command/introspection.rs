@@ -0,0 +1,405 @@
+use super::{Arg, ArgType, COMMAND_TABLE, CommandArg, CommandSpec, flags, lookup, resolve};
+use crate::glob::glob_match;
+use crate::resp::RESP;
+use crate::server::{ServerError, ServerResult};
+
+fn bulk(s: &str) -> RESP {
+    RESP::BulkString(s.into())
+}
+
+fn status(s: &str) -> RESP {
+    RESP::SimpleString(s.to_string())
+}
+
+/// Finds a command by name, accepting `container|sub` for subcommands.
+fn lookup_full(name: &str) -> Option<&'static CommandSpec> {
+    match name.split_once('|') {
+        Some((container, sub)) => lookup(container)?.subcommand(sub),
+        None => lookup(name),
+    }
+}
+
+/// Redis 7 style key specs, derived from the first/last/step positions
+/// and the key count.
+fn key_specs(spec: &CommandSpec) -> RESP {
+    let key_flags = || {
+        if spec.has_flag(flags::WRITE) {
+            RESP::Array(vec![status("RW"), status("update")])
+        } else {
+            RESP::Array(vec![status("RO"), status("access")])
+        }
+    };
+    let mut specs = Vec::new();
+    if spec.first_key != 0 {
+        specs.push(range_key_spec(spec, key_flags()));
+    }
+    if let Some(key_num) = spec.key_num {
+        specs.push(RESP::Array(vec![
+            bulk("flags"),
+            key_flags(),
+            bulk("begin_search"),
+            RESP::Array(vec![
+                bulk("type"),
+                bulk("index"),
+                bulk("spec"),
+                RESP::Array(vec![bulk("index"), RESP::Integer(key_num.index as i64)]),
+            ]),
+            bulk("find_keys"),
+            RESP::Array(vec![
+                bulk("type"),
+                bulk("keynum"),
+                bulk("spec"),
+                RESP::Array(vec![
+                    bulk("keynumidx"),
+                    RESP::Integer(0),
+                    bulk("firstkey"),
+                    RESP::Integer(key_num.first as i64),
+                    bulk("keystep"),
+                    RESP::Integer(key_num.step as i64),
+                ]),
+            ]),
+        ]));
+    }
+    RESP::Array(specs)
+}
+
+fn range_key_spec(spec: &CommandSpec, key_flags: RESP) -> RESP {
+    let last_key = if spec.last_key < 0 {
+        spec.last_key
+    } else {
+        spec.last_key - spec.first_key
+    };
+    RESP::Array(vec![
+        bulk("flags"),
+        key_flags,
+        bulk("begin_search"),
+        RESP::Array(vec![
+            bulk("type"),
+            bulk("index"),
+            bulk("spec"),
+            RESP::Array(vec![bulk("index"), RESP::Integer(spec.first_key as i64)]),
+        ]),
+        bulk("find_keys"),
+        RESP::Array(vec![
+            bulk("type"),
+            bulk("range"),
+            bulk("spec"),
+            RESP::Array(vec![
+                bulk("lastkey"),
+                RESP::Integer(last_key as i64),
+                bulk("keystep"),
+                RESP::Integer(spec.step as i64),
+                bulk("limit"),
+                RESP::Integer(0),
+            ]),
+        ]),
+    ])
+}
+
+/// One COMMAND INFO entry.
+fn info(spec: &'static CommandSpec) -> RESP {
+    let command_flags = flags::NAMES
+        .iter()
+        .filter(|(flag, _)| spec.has_flag(*flag))
+        .map(|(_, name)| status(name))
+        .collect();
+    let categories = spec
+        .categories
+        .iter()
+        .map(|category| status(&format!("@{}", category)))
+        .collect();
+    RESP::Array(vec![
+        bulk(spec.name),
+        RESP::Integer(spec.arity as i64),
+        RESP::Array(command_flags),
+        RESP::Integer(spec.first_key as i64),
+        RESP::Integer(spec.last_key as i64),
+        RESP::Integer(spec.step as i64),
+        RESP::Array(categories),
+        RESP::Array(spec.tips.iter().map(|tip| status(tip)).collect()),
+        key_specs(spec),
+        RESP::Array(spec.subcommands.iter().map(info).collect()),
+    ])
+}
+
+/// Key arguments name the key spec that finds them.
+fn arg_docs(arg: &Arg, key_spec_index: usize) -> RESP {
+    let mut fields = vec![
+        bulk("name"),
+        bulk(arg.name),
+        bulk("type"),
+        bulk(arg.kind.as_str()),
+    ];
+    if arg.kind == ArgType::Key {
+        fields.extend([bulk("key_spec_index"), RESP::Integer(key_spec_index as i64)]);
+    }
+    if let Some(token) = arg.token {
+        fields.extend([bulk("token"), bulk(token)]);
+    }
+    let mut arg_flags = Vec::new();
+    if arg.optional {
+        arg_flags.push(status("optional"));
+    }
+    if arg.multiple {
+        arg_flags.push(status("multiple"));
+    }
+    if !arg_flags.is_empty() {
+        fields.extend([bulk("flags"), RESP::Array(arg_flags)]);
+    }
+    if !arg.arguments.is_empty() {
+        fields.extend([
+            bulk("arguments"),
+            RESP::Array(
+                arg.arguments
+                    .iter()
+                    .map(|arg| arg_docs(arg, key_spec_index))
+                    .collect(),
+            ),
+        ]);
+    }
+    RESP::Array(fields)
+}
+
+/// One COMMAND DOCS value.
+fn docs(spec: &'static CommandSpec) -> RESP {
+    let mut fields = vec![
+        bulk("summary"),
+        bulk(spec.summary),
+        bulk("since"),
+        bulk(spec.since),
+        bulk("group"),
+        bulk(spec.group),
+        bulk("complexity"),
+        bulk(spec.complexity),
+    ];
+    if !spec.arguments.is_empty() {
+        // CMS.MERGE's destination is at a fixed position and its sources
+        // are counted, so each has a key spec of its own
+        let last_spec = usize::from(spec.first_key != 0 && spec.key_num.is_some());
+        let mut keys_seen = 0;
+        let arguments = spec
+            .arguments
+            .iter()
+            .map(|arg| {
+                let docs = arg_docs(arg, keys_seen.min(last_spec));
+                if arg.kind == ArgType::Key {
+                    keys_seen += 1;
+                }
+                docs
+            })
+            .collect();
+        fields.extend([bulk("arguments"), RESP::Array(arguments)]);
+    }
+    if !spec.subcommands.is_empty() {
+        let subcommands = spec
+            .subcommands
+            .iter()
+            .flat_map(|sub| [bulk(sub.name), docs(sub)])
+            .collect();
+        fields.extend([bulk("subcommands"), RESP::Array(subcommands)]);
+    }
+    RESP::Array(fields)
+}
+
+fn command_list(args: &[CommandArg]) -> ServerResult<RESP> {
+    let all = COMMAND_TABLE
+        .iter()
+        .flat_map(|spec| spec.with_subcommands());
+    let names: Vec<&str> = match args {
+        [] => all.map(|spec| spec.name).collect(),
+        [filterby, kind, value] if filterby.eq_ignore_ascii_case("FILTERBY") => {
+            match kind.to_lowercase().as_str() {
+                // There are no modules, so nothing belongs to one
+                "module" => Vec::new(),
+                "aclcat" => {
+                    let category = value.to_lowercase();
+                    all.filter(|spec| spec.categories.contains(&category.as_str()))
+                        .map(|spec| spec.name)
+                        .collect()
+                }
+                "pattern" => all
+                    .filter(|spec| glob_match(&value.to_lowercase(), spec.name))
+                    .map(|spec| spec.name)
+                    .collect(),
+                _ => return Err(ServerError::InvalidArgument("syntax error".to_string())),
+            }
+        }
+        _ => return Err(ServerError::InvalidArgument("syntax error".to_string())),
+    };
+    Ok(RESP::Array(names.into_iter().map(bulk).collect()))
+}
+
+fn command_getkeys(args: &[CommandArg], with_flags: bool) -> ServerResult<RESP> {
+    let invalid = |message: &str| ServerError::InvalidArgument(message.to_string());
+    let spec = match resolve(args) {
+        Ok(spec) => spec,
+        Err(ServerError::WrongArity(_)) => {
+            return Err(invalid("Invalid number of arguments specified for command"));
+        }
+        Err(_) => return Err(invalid("Invalid command specified")),
+    };
+    let keys = spec.keys(args);
+    if keys.is_empty() {
+        return Err(invalid("The command has no key arguments"));
+    }
+    let key_flags = || {
+        if spec.has_flag(flags::WRITE) {
+            RESP::Array(vec![status("RW"), status("update")])
+        } else {
+            RESP::Array(vec![status("RO"), status("access")])
+        }
+    };
+    Ok(RESP::Array(
+        keys.into_iter()
+            .map(|key| {
+                if with_flags {
+                    RESP::Array(vec![bulk(key), key_flags()])
+                } else {
+                    bulk(key)
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// COMMAND [subcommand [args]], with `args` starting at the subcommand.
+pub fn command_command(args: &[CommandArg]) -> ServerResult<RESP> {
+    let Some(subcommand) = args.first() else {
+        return Ok(RESP::Array(COMMAND_TABLE.iter().map(info).collect()));
+    };
+    let rest = &args[1..];
+    match subcommand.to_lowercase().as_str() {
+        "count" => Ok(RESP::Integer(COMMAND_TABLE.len() as i64)),
+        "info" if rest.is_empty() => Ok(RESP::Array(COMMAND_TABLE.iter().map(info).collect())),
+        "info" => Ok(RESP::Array(
+            rest.iter()
+                .map(|name| lookup_full(name).map(info).unwrap_or(RESP::Null))
+                .collect(),
+        )),
+        "docs" => {
+            let specs: Vec<&'static CommandSpec> = if rest.is_empty() {
+                COMMAND_TABLE.iter().collect()
+            } else {
+                rest.iter().filter_map(|name| lookup_full(name)).collect()
+            };
+            Ok(RESP::Array(
+                specs
+                    .into_iter()
+                    .flat_map(|spec| [bulk(spec.name), docs(spec)])
+                    .collect(),
+            ))
+        }
+        "list" => command_list(rest),
+        "getkeys" => command_getkeys(rest, false),
+        "getkeysandflags" => command_getkeys(rest, true),
+        "help" => Ok(RESP::Array(
+            [
+                "COMMAND <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "(no subcommand)",
+                "    Return details about all Redis commands.",
+                "COUNT",
+                "    Return the total number of commands in this Redis server.",
+                "LIST",
+                "    Return a list of all commands in this Redis server.",
+                "INFO [<command-name> ...]",
+                "    Return details about multiple Redis commands.",
+                "    If no command names are given, documentation details for all",
+                "    commands are returned.",
+                "DOCS [<command-name> ...]",
+                "    Return documentation details about multiple Redis commands.",
+                "    If no command names are given, documentation details for all",
+                "    commands are returned.",
+                "GETKEYS <full-command>",
+                "    Return the keys from a full Redis command.",
+                "GETKEYSANDFLAGS <full-command>",
+                "    Return the keys and the access flags from a full Redis command.",
+                "HELP",
+                "    Print this help.",
+            ]
+            .iter()
+            .map(|line| status(line))
+            .collect(),
+        )),
+        _ => Err(ServerError::UnknownSubcommand(
+            subcommand.to_string(),
+            "COMMAND".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::cmd;
+
+    #[test]
+    fn test_bare_command_lists_everything() {
+        match command_command(&[]) {
+            Ok(RESP::Array(entries)) => assert_eq!(entries.len(), COMMAND_TABLE.len()),
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_command_info() {
+        let reply = command_command(&cmd(&["INFO", "get", "bogus"])).unwrap();
+        let RESP::Array(entries) = reply else {
+            panic!("expected an array");
+        };
+        assert_eq!(entries[1], RESP::Null);
+        let RESP::Array(get) = &entries[0] else {
+            panic!("expected an array");
+        };
+        assert_eq!(get[0], bulk("get"));
+        assert_eq!(get[1], RESP::Integer(2));
+        assert_eq!(
+            get[2],
+            RESP::Array(vec![status("readonly"), status("fast")])
+        );
+        assert_eq!(get[3], RESP::Integer(1));
+    }
+
+    #[test]
+    fn test_command_list_filterby() {
+        let list = |parts: &[&str]| match command_command(&cmd(parts)) {
+            Ok(RESP::Array(names)) => names,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        let lists = list(&["LIST", "FILTERBY", "ACLCAT", "list"]);
+        assert!(lists.contains(&bulk("lpush")));
+        assert!(!lists.contains(&bulk("get")));
+        assert_eq!(
+            list(&["LIST", "FILTERBY", "PATTERN", "config|*"]).len(),
+            lookup("config").unwrap().subcommands.len()
+        );
+        assert!(list(&["LIST", "FILTERBY", "MODULE", "json"]).is_empty());
+    }
+
+    #[test]
+    fn test_command_getkeys() {
+        assert_eq!(
+            command_command(&cmd(&["GETKEYS", "MSET", "a", "1", "b", "2"])),
+            Ok(RESP::Array(vec![bulk("a"), bulk("b")]))
+        );
+        assert_eq!(
+            command_command(&cmd(&["GETKEYS", "EVAL", "return 1", "2", "a", "b", "c"])),
+            Ok(RESP::Array(vec![bulk("a"), bulk("b")]))
+        );
+        assert!(command_command(&cmd(&["GETKEYS", "EVAL", "return 1", "0"])).is_err());
+        assert!(command_command(&cmd(&["GETKEYS", "PING"])).is_err());
+        assert!(command_command(&cmd(&["GETKEYS", "GET"])).is_err());
+        assert!(command_command(&cmd(&["GETKEYS", "BOGUS", "x"])).is_err());
+    }
+
+    #[test]
+    fn test_command_docs() {
+        let RESP::Array(reply) = command_command(&cmd(&["DOCS", "set"])).unwrap() else {
+            panic!("expected an array");
+        };
+        assert_eq!(reply[0], bulk("set"));
+        let RESP::Array(fields) = &reply[1] else {
+            panic!("expected an array");
+        };
+        assert!(fields.contains(&bulk("arguments")));
+    }
+}
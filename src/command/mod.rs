@@ -0,0 +1,649 @@
+mod arg;
+mod introspection;
+mod table;
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+pub use self::arg::CommandArg;
+pub use self::introspection::command_command;
+pub use self::table::COMMAND_TABLE;
+use crate::server::{ServerError, ServerResult};
+
+/// Command flags, as reported by COMMAND INFO.
+pub mod flags {
+    pub const WRITE: u32 = 1 << 0;
+    pub const READONLY: u32 = 1 << 1;
+    pub const DENYOOM: u32 = 1 << 2;
+    pub const ADMIN: u32 = 1 << 3;
+    pub const NOSCRIPT: u32 = 1 << 4;
+    pub const LOADING: u32 = 1 << 5;
+    pub const STALE: u32 = 1 << 6;
+    pub const FAST: u32 = 1 << 7;
+    pub const NO_AUTH: u32 = 1 << 8;
+    pub const ALLOW_BUSY: u32 = 1 << 9;
+
+    pub const NAMES: [(u32, &str); 10] = [
+        (WRITE, "write"),
+        (READONLY, "readonly"),
+        (DENYOOM, "denyoom"),
+        (ADMIN, "admin"),
+        (NOSCRIPT, "noscript"),
+        (LOADING, "loading"),
+        (STALE, "stale"),
+        (FAST, "fast"),
+        (NO_AUTH, "no_auth"),
+        (ALLOW_BUSY, "allow_busy"),
+    ];
+}
+
+/// What a request dispatches to. Subcommands share their container's
+/// variant and are told apart by their first argument.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Ping,
+    Echo,
+    Command,
+    Config,
+    Quit,
+    Info,
+    Client,
+    Auth,
+    Acl,
+    Memory,
+    Object,
+    Debug,
+
+    // Scripting
+    Eval,
+    EvalSha,
+    EvalRo,
+    EvalShaRo,
+    Script,
+    Function,
+    FCall,
+    FCallRo,
+
+    // Replication
+    ReplicaOf,
+    SlaveOf,
+    ReplConf,
+    PSync,
+    Wait,
+
+    // Cluster
+    Cluster,
+    Asking,
+    Migrate,
+
+    // Pub/sub
+    Subscribe,
+    Unsubscribe,
+    PSubscribe,
+    PUnsubscribe,
+    Publish,
+
+    // Transactions
+    Multi,
+    Exec,
+    Discard,
+    Watch,
+    Unwatch,
+
+    // KV
+    Del,
+    Dump,
+    Restore,
+    Expire,
+    PExpire,
+    ExpireAt,
+    PExpireAt,
+    Ttl,
+    PTtl,
+    Persist,
+    Get,
+    Incr,
+    Set,
+    SetEx,
+    PSetEx,
+    MGet,
+    MSet,
+
+    // List
+    LLen,
+    LPush,
+    LPop,
+    RPush,
+    RPop,
+
+    // Bitmap
+    SetBit,
+    GetBit,
+    BitCount,
+    BitPos,
+    BitOp,
+    BitField,
+    BitFieldRo,
+
+    // HyperLogLog
+    PfAdd,
+    PfCount,
+    PfMerge,
+    PfDebug,
+    PfSelfTest,
+
+    // Geo
+    GeoAdd,
+    GeoDist,
+    GeoPos,
+    GeoHash,
+    GeoSearch,
+    GeoSearchStore,
+
+    // JSON
+    JsonSet,
+    JsonGet,
+    JsonDel,
+    JsonMGet,
+    JsonNumIncrBy,
+    JsonStrAppend,
+    JsonArrAppend,
+    JsonArrInsert,
+    JsonArrPop,
+    JsonArrLen,
+    JsonObjKeys,
+    JsonType,
+
+    // Probabilistic
+    BfReserve,
+    BfAdd,
+    BfMAdd,
+    BfExists,
+    BfMExists,
+    BfInfo,
+    CfReserve,
+    CfAdd,
+    CfAddNx,
+    CfInsert,
+    CfInsertNx,
+    CfExists,
+    CfMExists,
+    CfDel,
+    CfCount,
+    CfInfo,
+    CmsInitByDim,
+    CmsInitByProb,
+    CmsIncrBy,
+    CmsQuery,
+    CmsMerge,
+    CmsInfo,
+    TopKReserve,
+    TopKAdd,
+    TopKIncrBy,
+    TopKQuery,
+    TopKList,
+    TopKInfo,
+
+    // Time series
+    TsCreate,
+    TsAlter,
+    TsAdd,
+    TsMAdd,
+    TsGet,
+    TsRange,
+    TsRevRange,
+    TsMRange,
+    TsMRevRange,
+    TsQueryIndex,
+    TsCreateRule,
+    TsDeleteRule,
+    TsInfo,
+
+    // Vector set
+    VAdd,
+    VRem,
+    VSim,
+    VCard,
+    VDim,
+    VEmb,
+    VSetAttr,
+    VGetAttr,
+    VInfo,
+
+    // Search
+    FtCreate,
+    FtDropIndex,
+    FtList,
+    FtInfo,
+    FtSearch,
+    FtAggregate,
+
+    // Stream
+    XAdd,
+    XLen,
+    XRange,
+    XRevRange,
+    XDel,
+    XTrim,
+    XRead,
+    XReadGroup,
+    XGroup,
+    XAck,
+    XPending,
+    XClaim,
+    XAutoClaim,
+    XInfo,
+}
+
+impl Command {
+    pub fn from(input: &[CommandArg]) -> Option<Command> {
+        lookup(input.first()?).map(|spec| spec.command)
+    }
+
+    pub fn spec(&self) -> &'static CommandSpec {
+        BY_COMMAND[*self as usize].expect("every command has a table entry")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgType {
+    Key,
+    String,
+    Integer,
+    PureToken,
+    OneOf,
+    Block,
+}
+
+impl ArgType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArgType::Key => "key",
+            ArgType::String => "string",
+            ArgType::Integer => "integer",
+            ArgType::PureToken => "pure-token",
+            ArgType::OneOf => "oneof",
+            ArgType::Block => "block",
+        }
+    }
+}
+
+/// An argument as documented by COMMAND DOCS.
+#[derive(Debug)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgType,
+    pub token: Option<&'static str>,
+    pub optional: bool,
+    pub multiple: bool,
+    /// Alternatives for `OneOf`, members for `Block`
+    pub arguments: &'static [Arg],
+}
+
+impl Arg {
+    const fn new(name: &'static str, kind: ArgType) -> Self {
+        Arg {
+            name,
+            kind,
+            token: None,
+            optional: false,
+            multiple: false,
+            arguments: &[],
+        }
+    }
+
+    pub const fn key(name: &'static str) -> Self {
+        Self::new(name, ArgType::Key)
+    }
+
+    pub const fn string(name: &'static str) -> Self {
+        Self::new(name, ArgType::String)
+    }
+
+    pub const fn integer(name: &'static str) -> Self {
+        Self::new(name, ArgType::Integer)
+    }
+
+    pub const fn token(name: &'static str, token: &'static str) -> Self {
+        Self::new(name, ArgType::PureToken).with_token(token)
+    }
+
+    pub const fn one_of(name: &'static str, arguments: &'static [Arg]) -> Self {
+        Arg {
+            arguments,
+            ..Self::new(name, ArgType::OneOf)
+        }
+    }
+
+    pub const fn block(name: &'static str, arguments: &'static [Arg]) -> Self {
+        Arg {
+            arguments,
+            ..Self::new(name, ArgType::Block)
+        }
+    }
+
+    pub const fn with_token(self, token: &'static str) -> Self {
+        Arg {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    pub const fn optional(self) -> Self {
+        Arg {
+            optional: true,
+            ..self
+        }
+    }
+
+    pub const fn multiple(self) -> Self {
+        Arg {
+            multiple: true,
+            ..self
+        }
+    }
+}
+
+/// Keys counted by an argument rather than sitting at fixed positions, like
+/// EVAL's `numkeys key [key ...]`: the count is at `index`, and the keys
+/// start `first` arguments after it, `step` apart.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyNum {
+    pub index: i32,
+    pub first: i32,
+    pub step: i32,
+}
+
+/// One entry of the command table. Arity and key positions count the
+/// command name itself, like Redis: a negative arity means "at least", and
+/// a negative last key counts from the end.
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// Lowercase, `container|sub` for subcommands
+    pub name: &'static str,
+    pub command: Command,
+    pub arity: i32,
+    pub flags: u32,
+    /// ACL categories, without the leading `@`
+    pub categories: &'static [&'static str],
+    pub first_key: i32,
+    pub last_key: i32,
+    pub step: i32,
+    /// For keys that follow a token rather than sitting at fixed
+    /// positions, like XREAD's `STREAMS key [key ...] id [id ...]`: the
+    /// keys are the first half of what comes after the token, searched
+    /// for from `first_key` on
+    pub key_keyword: Option<&'static str>,
+    /// Keys following a count, after any at fixed positions
+    pub key_num: Option<KeyNum>,
+    pub summary: &'static str,
+    pub since: &'static str,
+    pub group: &'static str,
+    pub complexity: &'static str,
+    /// Redis command tips, like `request_policy:multi_shard` for commands
+    /// whose keys can be served separately
+    pub tips: &'static [&'static str],
+    pub arguments: &'static [Arg],
+    pub subcommands: &'static [CommandSpec],
+}
+
+impl CommandSpec {
+    /// Starting point for table entries, which override what they need.
+    pub const DEFAULT: CommandSpec = CommandSpec {
+        name: "",
+        command: Command::Ping,
+        arity: -1,
+        flags: 0,
+        categories: &[],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        key_keyword: None,
+        key_num: None,
+        summary: "",
+        since: "1.0.0",
+        group: "generic",
+        complexity: "O(1)",
+        tips: &[],
+        arguments: &[],
+        subcommands: &[],
+    };
+
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let arity = self.arity.unsigned_abs() as usize;
+        if self.arity < 0 {
+            argc >= arity
+        } else {
+            argc == arity
+        }
+    }
+
+    /// The container name for subcommands, the name itself otherwise.
+    pub fn container_name(&self) -> &'static str {
+        self.name.split('|').next().unwrap_or(self.name)
+    }
+
+    pub fn subcommand(&self, name: &str) -> Option<&'static CommandSpec> {
+        let full = format!("{}|{}", self.name, name.to_lowercase());
+        self.subcommands.iter().find(|sub| sub.name == full)
+    }
+
+    /// Where this entry is in `all_specs()`.
+    pub fn position(&self) -> usize {
+        POSITIONS[self.name]
+    }
+
+    /// This command and its subcommands, the way COMMAND LIST shows them.
+    pub fn with_subcommands(&'static self) -> impl Iterator<Item = &'static CommandSpec> {
+        std::iter::once(self).chain(self.subcommands.iter())
+    }
+
+    /// The keys of a request, found from the first/last/step positions,
+    /// then from the count of `key_num`.
+    pub fn keys<'a>(&self, args: &'a [CommandArg]) -> Vec<&'a String> {
+        let mut keys = self.positional_keys(args);
+        if let Some(key_num) = self.key_num {
+            keys.extend(key_num.keys(args));
+        }
+        keys
+    }
+
+    fn positional_keys<'a>(&self, args: &'a [CommandArg]) -> Vec<&'a String> {
+        if self.first_key <= 0 || self.first_key as usize >= args.len() {
+            return Vec::new();
+        }
+        if let Some(keyword) = self.key_keyword {
+            let Some(at) = args[self.first_key as usize..]
+                .iter()
+                .position(|arg| arg.eq_ignore_ascii_case(keyword))
+            else {
+                return Vec::new();
+            };
+            let rest = &args[self.first_key as usize + at + 1..];
+            return rest[..rest.len() / 2].iter().map(|arg| &**arg).collect();
+        }
+        let last = if self.last_key < 0 {
+            args.len() as i32 + self.last_key
+        } else {
+            self.last_key.min(args.len() as i32 - 1)
+        };
+        (self.first_key..=last)
+            .step_by(self.step.max(1) as usize)
+            .map(|i| &*args[i as usize])
+            .collect()
+    }
+}
+
+impl KeyNum {
+    /// Nothing when the count isn't a number or there are fewer keys than
+    /// it says, which the command itself reports.
+    fn keys<'a>(&self, args: &'a [CommandArg]) -> Vec<&'a String> {
+        let count = args
+            .get(self.index as usize)
+            .and_then(|count| count.parse::<usize>().ok());
+        let first = self.index as usize + self.first as usize;
+        let step = self.step.max(1) as usize;
+        match count {
+            Some(count) if count > 0 && first + (count - 1) * step < args.len() => args[first..]
+                .iter()
+                .step_by(step)
+                .take(count)
+                .map(|arg| &**arg)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Top-level table entries by name.
+static BY_NAME: LazyLock<HashMap<&'static str, &'static CommandSpec>> =
+    LazyLock::new(|| COMMAND_TABLE.iter().map(|spec| (spec.name, spec)).collect());
+
+/// Top-level table entries by `Command`, as an index.
+static BY_COMMAND: LazyLock<Vec<Option<&'static CommandSpec>>> = LazyLock::new(|| {
+    let mut specs = Vec::new();
+    for spec in COMMAND_TABLE.iter() {
+        let index = spec.command as usize;
+        if specs.len() <= index {
+            specs.resize(index + 1, None);
+        }
+        specs[index].get_or_insert(spec);
+    }
+    specs
+});
+
+/// Every table entry, each container followed by its subcommands.
+static ALL_SPECS: LazyLock<Vec<&'static CommandSpec>> = LazyLock::new(|| {
+    COMMAND_TABLE
+        .iter()
+        .flat_map(CommandSpec::with_subcommands)
+        .collect()
+});
+
+/// Positions in `ALL_SPECS` by full name.
+static POSITIONS: LazyLock<HashMap<&'static str, usize>> = LazyLock::new(|| {
+    ALL_SPECS
+        .iter()
+        .enumerate()
+        .map(|(position, spec)| (spec.name, position))
+        .collect()
+});
+
+/// Every table entry, subcommands included, in a fixed order that
+/// `CommandSpec::position` indexes.
+pub fn all_specs() -> &'static [&'static CommandSpec] {
+    &ALL_SPECS
+}
+
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    match BY_NAME.get(name) {
+        Some(spec) => Some(spec),
+        None => BY_NAME.get(name.to_ascii_lowercase().as_str()).copied(),
+    }
+}
+
+/// Finds the entry a request runs, descending into subcommands, and checks
+/// its arity.
+pub fn resolve(args: &[CommandArg]) -> ServerResult<&'static CommandSpec> {
+    let name = args
+        .first()
+        .ok_or_else(|| ServerError::UnknownCommand(Vec::new()))?;
+    let spec = lookup(name).ok_or_else(|| {
+        ServerError::UnknownCommand(args.iter().map(|arg| arg.to_string()).collect())
+    })?;
+    if !spec.check_arity(args.len()) {
+        return Err(ServerError::WrongArity(spec.name.to_string()));
+    }
+    // Bare COMMAND is the only container that runs without a subcommand
+    if spec.subcommands.is_empty() || args.len() == 1 {
+        return Ok(spec);
+    }
+    let sub = spec.subcommand(&args[1]).ok_or_else(|| {
+        ServerError::UnknownSubcommand(args[1].to_string(), spec.name.to_uppercase())
+    })?;
+    if !sub.check_arity(args.len()) {
+        return Err(ServerError::WrongArity(sub.name.to_string()));
+    }
+    Ok(sub)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::cmd;
+
+    #[test]
+    fn test_table_is_consistent() {
+        for spec in COMMAND_TABLE.iter() {
+            assert_eq!(spec.command.spec().name, spec.name);
+            assert!(!spec.summary.is_empty(), "{} has no summary", spec.name);
+            for sub in spec.subcommands {
+                assert_eq!(sub.command, spec.command);
+                assert_eq!(sub.container_name(), spec.name);
+                assert!(sub.arity.unsigned_abs() >= 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve(&cmd(&["get", "k"])).unwrap().name, "get");
+        assert_eq!(
+            resolve(&cmd(&["CONFIG", "get", "x"])).unwrap().name,
+            "config|get"
+        );
+        assert_eq!(
+            resolve(&cmd(&["get"])).unwrap_err(),
+            ServerError::WrongArity("get".to_string())
+        );
+        assert_eq!(
+            resolve(&cmd(&["config", "get"])).unwrap_err(),
+            ServerError::WrongArity("config|get".to_string())
+        );
+        assert!(matches!(
+            resolve(&cmd(&["config", "bogus"])),
+            Err(ServerError::UnknownSubcommand(..))
+        ));
+        assert!(matches!(
+            resolve(&cmd(&["bogus"])),
+            Err(ServerError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_keys() {
+        let keys = |parts: &[&str]| -> Vec<String> {
+            let args = cmd(parts);
+            resolve(&args)
+                .unwrap()
+                .keys(&args)
+                .into_iter()
+                .cloned()
+                .collect()
+        };
+        assert_eq!(keys(&["get", "a"]), vec!["a"]);
+        assert_eq!(keys(&["del", "a", "b", "c"]), vec!["a", "b", "c"]);
+        assert_eq!(keys(&["mset", "a", "1", "b", "2"]), vec!["a", "b"]);
+        assert_eq!(keys(&["memory", "usage", "a"]), vec!["a"]);
+        assert_eq!(
+            keys(&["xread", "count", "1", "streams", "a", "b", "0", "0"]),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            keys(&["xreadgroup", "group", "g", "c", "streams", "s", ">"]),
+            vec!["s"]
+        );
+        assert_eq!(keys(&["fcall", "f", "2", "a", "b", "c"]), vec!["a", "b"]);
+        assert_eq!(
+            keys(&["cms.merge", "d", "2", "a", "b", "weights", "1", "2"]),
+            vec!["d", "a", "b"]
+        );
+        assert!(keys(&["eval", "return 1", "3", "a"]).is_empty());
+        assert!(keys(&["eval", "return 1", "x", "a"]).is_empty());
+        assert!(keys(&["ping"]).is_empty());
+    }
+}
@@ -0,0 +1,4435 @@
+use super::flags::*;
+use super::{Arg, Command, CommandSpec, KeyNum};
+
+const ADMIN_CATEGORIES: &[&str] = &["admin", "slow", "dangerous"];
+const CONNECTION_ADMIN_CATEGORIES: &[&str] = &["admin", "slow", "dangerous", "connection"];
+const CONNECTION_CATEGORIES: &[&str] = &["slow", "connection"];
+const ADMIN_FLAGS: u32 = ADMIN | NOSCRIPT | LOADING | STALE;
+const SERVER_FLAGS: u32 = NOSCRIPT | LOADING | STALE;
+
+/// Shared by every container's HELP subcommand.
+const fn help(name: &'static str, categories: &'static [&'static str]) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity: 2,
+        flags: LOADING | STALE,
+        categories,
+        summary: "Returns helpful text about the different subcommands.",
+        since: "5.0.0",
+        complexity: "O(1)",
+        ..CommandSpec::DEFAULT
+    }
+}
+
+const CONFIG_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "config|get",
+        command: Command::Config,
+        arity: 3,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Returns the effective value of a configuration parameter.",
+        since: "2.0.0",
+        group: "server",
+        complexity: "O(N) when N is the number of configuration parameters provided",
+        arguments: &[Arg::string("parameter")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "config|set",
+        command: Command::Config,
+        arity: 4,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Sets a configuration parameter to the given value.",
+        since: "2.0.0",
+        group: "server",
+        complexity: "O(N) when N is the number of configuration parameters provided",
+        arguments: &[Arg::string("parameter"), Arg::string("value")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "config|resetstat",
+        command: Command::Config,
+        arity: 2,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Resets the server's statistics.",
+        since: "2.0.0",
+        group: "server",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Config,
+        group: "server",
+        ..help("config|help", &["slow"])
+    },
+];
+
+const CLUSTER_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "cluster|info",
+        command: Command::Cluster,
+        arity: 2,
+        flags: STALE,
+        categories: &["slow"],
+        summary: "Returns information about the state of a node.",
+        since: "3.0.0",
+        group: "cluster",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|myid",
+        command: Command::Cluster,
+        arity: 2,
+        flags: STALE,
+        categories: &["slow"],
+        summary: "Returns the ID of a node.",
+        since: "3.0.0",
+        group: "cluster",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|nodes",
+        command: Command::Cluster,
+        arity: 2,
+        flags: STALE,
+        categories: &["slow"],
+        summary: "Returns the cluster configuration for a node.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the total number of Cluster nodes",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|slots",
+        command: Command::Cluster,
+        arity: 2,
+        flags: LOADING | STALE,
+        categories: &["slow"],
+        summary: "Returns the mapping of cluster slots to nodes.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the total number of Cluster nodes",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|shards",
+        command: Command::Cluster,
+        arity: 2,
+        flags: LOADING | STALE,
+        categories: &["slow"],
+        summary: "Returns the mapping of cluster slots to shards.",
+        since: "7.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the total number of cluster nodes",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|keyslot",
+        command: Command::Cluster,
+        arity: 3,
+        flags: STALE,
+        categories: &["slow"],
+        summary: "Returns the hash slot for a key.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the number of bytes in the key",
+        arguments: &[Arg::string("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|countkeysinslot",
+        command: Command::Cluster,
+        arity: 3,
+        flags: STALE,
+        categories: &["slow"],
+        summary: "Returns the number of keys in a hash slot.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the number of keys",
+        arguments: &[Arg::integer("slot")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|getkeysinslot",
+        command: Command::Cluster,
+        arity: 4,
+        flags: STALE,
+        categories: &["slow"],
+        summary: "Returns the key names in a hash slot.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the number of keys",
+        arguments: &[Arg::integer("slot"), Arg::integer("count")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|meet",
+        command: Command::Cluster,
+        arity: -4,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Forces a node to handshake with another node.",
+        since: "3.0.0",
+        group: "cluster",
+        arguments: &[
+            Arg::string("ip"),
+            Arg::integer("port"),
+            Arg::integer("cluster-bus-port").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|addslots",
+        command: Command::Cluster,
+        arity: -3,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Assigns new hash slots to a node.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the total number of hash slot arguments",
+        arguments: &[Arg::integer("slot").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|addslotsrange",
+        command: Command::Cluster,
+        arity: -4,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Assigns new hash slot ranges to a node.",
+        since: "7.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the total number of the slots between the start slot and end slot arguments.",
+        arguments: SLOT_RANGES,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|delslots",
+        command: Command::Cluster,
+        arity: -3,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Sets hash slots as unbound for a node.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the total number of hash slot arguments",
+        arguments: &[Arg::integer("slot").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|delslotsrange",
+        command: Command::Cluster,
+        arity: -4,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Sets hash slot ranges as unbound for a node.",
+        since: "7.0.0",
+        group: "cluster",
+        complexity: "O(N) where N is the total number of the slots between the start slot and end slot arguments.",
+        arguments: SLOT_RANGES,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster|setslot",
+        command: Command::Cluster,
+        arity: -4,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Binds a hash slot to a node.",
+        since: "3.0.0",
+        group: "cluster",
+        arguments: &[
+            Arg::integer("slot"),
+            Arg::one_of(
+                "subcommand",
+                &[
+                    Arg::string("node-id").with_token("IMPORTING"),
+                    Arg::string("node-id").with_token("MIGRATING"),
+                    Arg::string("node-id").with_token("NODE"),
+                    Arg::token("stable", "STABLE"),
+                ],
+            ),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Cluster,
+        group: "cluster",
+        ..help("cluster|help", &["slow"])
+    },
+];
+
+const SLOT_RANGES: &[Arg] = &[Arg::block(
+    "range",
+    &[Arg::integer("start-slot"), Arg::integer("end-slot")],
+)
+.multiple()];
+
+const REPLICAOF_ARGUMENTS: &[Arg] = &[Arg::string("host"), Arg::integer("port")];
+
+const CLIENT_KILL_FILTERS: &[Arg] = &[
+    Arg::string("ip:port").optional(),
+    Arg::integer("client-id").with_token("ID").optional(),
+    Arg::string("ip:port").with_token("ADDR").optional(),
+    Arg::string("ip:port").with_token("LADDR").optional(),
+    Arg::string("username").with_token("USER").optional(),
+    Arg::one_of(
+        "client-type",
+        &[
+            Arg::token("normal", "NORMAL"),
+            Arg::token("master", "MASTER"),
+            Arg::token("replica", "REPLICA"),
+            Arg::token("pubsub", "PUBSUB"),
+        ],
+    )
+    .with_token("TYPE")
+    .optional(),
+    Arg::one_of(
+        "skipme",
+        &[Arg::token("yes", "YES"), Arg::token("no", "NO")],
+    )
+    .with_token("SKIPME")
+    .optional(),
+    Arg::integer("maxage").with_token("MAXAGE").optional(),
+];
+
+const CLIENT_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "client|id",
+        command: Command::Client,
+        arity: 2,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns the unique client ID of the connection.",
+        since: "5.0.0",
+        group: "connection",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|info",
+        command: Command::Client,
+        arity: 2,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns information about the connection.",
+        since: "6.2.0",
+        group: "connection",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|list",
+        command: Command::Client,
+        arity: -2,
+        flags: ADMIN_FLAGS,
+        categories: CONNECTION_ADMIN_CATEGORIES,
+        summary: "Lists open connections.",
+        since: "2.4.0",
+        group: "connection",
+        complexity: "O(N) where N is the number of client connections",
+        arguments: &[
+            Arg::one_of(
+                "client-type",
+                &[
+                    Arg::token("normal", "NORMAL"),
+                    Arg::token("master", "MASTER"),
+                    Arg::token("replica", "REPLICA"),
+                    Arg::token("pubsub", "PUBSUB"),
+                ],
+            )
+            .with_token("TYPE")
+            .optional(),
+            Arg::integer("client-id")
+                .with_token("ID")
+                .optional()
+                .multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|setname",
+        command: Command::Client,
+        arity: 3,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Sets the connection name.",
+        since: "2.6.9",
+        group: "connection",
+        arguments: &[Arg::string("connection-name")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|getname",
+        command: Command::Client,
+        arity: 2,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns the name of the connection.",
+        since: "2.6.9",
+        group: "connection",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|kill",
+        command: Command::Client,
+        arity: -3,
+        flags: ADMIN_FLAGS,
+        categories: CONNECTION_ADMIN_CATEGORIES,
+        summary: "Terminates open connections.",
+        since: "2.4.0",
+        group: "connection",
+        complexity: "O(N) where N is the number of client connections",
+        arguments: CLIENT_KILL_FILTERS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|pause",
+        command: Command::Client,
+        arity: -3,
+        flags: ADMIN_FLAGS,
+        categories: CONNECTION_ADMIN_CATEGORIES,
+        summary: "Suspends commands processing.",
+        since: "3.0.0",
+        group: "connection",
+        arguments: &[
+            Arg::integer("timeout"),
+            Arg::one_of(
+                "mode",
+                &[Arg::token("write", "WRITE"), Arg::token("all", "ALL")],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|unpause",
+        command: Command::Client,
+        arity: 2,
+        flags: ADMIN_FLAGS,
+        categories: CONNECTION_ADMIN_CATEGORIES,
+        summary: "Resumes processing commands from paused clients.",
+        since: "6.2.0",
+        group: "connection",
+        complexity: "O(N) Where N is the number of paused clients",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|no-evict",
+        command: Command::Client,
+        arity: 3,
+        flags: ADMIN_FLAGS,
+        categories: CONNECTION_ADMIN_CATEGORIES,
+        summary: "Sets the client eviction mode of the connection.",
+        since: "7.0.0",
+        group: "connection",
+        arguments: &[Arg::one_of(
+            "enabled",
+            &[Arg::token("on", "ON"), Arg::token("off", "OFF")],
+        )],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|tracking",
+        command: Command::Client,
+        arity: -3,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Controls server-assisted client-side caching for the connection.",
+        since: "6.0.0",
+        group: "connection",
+        complexity: "O(1). Some options may introduce additional complexity.",
+        arguments: &[
+            Arg::one_of(
+                "status",
+                &[Arg::token("on", "ON"), Arg::token("off", "OFF")],
+            ),
+            Arg::integer("client-id").with_token("REDIRECT").optional(),
+            Arg::string("prefix")
+                .with_token("PREFIX")
+                .optional()
+                .multiple(),
+            Arg::token("bcast", "BCAST").optional(),
+            Arg::token("optin", "OPTIN").optional(),
+            Arg::token("optout", "OPTOUT").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|caching",
+        command: Command::Client,
+        arity: 3,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Instructs the server whether to track the keys in the next request.",
+        since: "6.0.0",
+        group: "connection",
+        arguments: &[Arg::one_of(
+            "mode",
+            &[Arg::token("yes", "YES"), Arg::token("no", "NO")],
+        )],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|getredir",
+        command: Command::Client,
+        arity: 2,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns the client ID to which the connection's tracking notifications are redirected.",
+        since: "6.0.0",
+        group: "connection",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|trackinginfo",
+        command: Command::Client,
+        arity: 2,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns information about server-assisted client-side caching for the connection.",
+        since: "6.2.0",
+        group: "connection",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client|reply",
+        command: Command::Client,
+        arity: 3,
+        flags: SERVER_FLAGS,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Instructs the server whether to reply to commands.",
+        since: "3.2.0",
+        group: "connection",
+        arguments: &[Arg::one_of(
+            "action",
+            &[
+                Arg::token("on", "ON"),
+                Arg::token("off", "OFF"),
+                Arg::token("skip", "SKIP"),
+            ],
+        )],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Client,
+        group: "connection",
+        ..help("client|help", CONNECTION_CATEGORIES)
+    },
+];
+
+const ACL_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "acl|setuser",
+        command: Command::Acl,
+        arity: -3,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Creates and modifies an ACL user and its rules.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(N). Where N is the number of rules provided.",
+        arguments: &[
+            Arg::string("username"),
+            Arg::string("rule").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|getuser",
+        command: Command::Acl,
+        arity: 3,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Lists the ACL rules of a user.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(N). Where N is the number of password, command and pattern rules that the user has.",
+        arguments: &[Arg::string("username")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|deluser",
+        command: Command::Acl,
+        arity: -3,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Deletes ACL users, and terminates their connections.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(1) amortized time considering the typical user.",
+        arguments: &[Arg::string("username").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|list",
+        command: Command::Acl,
+        arity: 2,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Dumps the effective rules in ACL file format.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(N). Where N is the number of configured users.",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|users",
+        command: Command::Acl,
+        arity: 2,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Lists all ACL users.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(N). Where N is the number of configured users.",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|whoami",
+        command: Command::Acl,
+        arity: 2,
+        flags: SERVER_FLAGS,
+        categories: &["slow"],
+        summary: "Returns the authenticated username of the current connection.",
+        since: "6.0.0",
+        group: "server",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|cat",
+        command: Command::Acl,
+        arity: -2,
+        flags: SERVER_FLAGS,
+        categories: &["slow"],
+        summary: "Lists the ACL categories, or the commands inside a category.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(1) since the categories and commands are a fixed set.",
+        arguments: &[Arg::string("category").optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|log",
+        command: Command::Acl,
+        arity: -2,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Lists recent security events generated due to ACL rules.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(N) with N being the number of entries shown.",
+        arguments: &[Arg::one_of(
+            "operation",
+            &[Arg::integer("count"), Arg::token("reset", "RESET")],
+        )
+        .optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|save",
+        command: Command::Acl,
+        arity: 2,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Saves the effective ACL rules in the configured ACL file.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(N). Where N is the number of configured users.",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|load",
+        command: Command::Acl,
+        arity: 2,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Reloads the rules from the configured ACL file.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "O(N). Where N is the number of configured users.",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|genpass",
+        command: Command::Acl,
+        arity: -2,
+        flags: SERVER_FLAGS,
+        categories: &["slow"],
+        summary: "Generates a pseudorandom, secure password that can be used to identify ACL users.",
+        since: "6.0.0",
+        group: "server",
+        arguments: &[Arg::integer("bits").optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl|dryrun",
+        command: Command::Acl,
+        arity: -4,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "Simulates the execution of a command by a user, without executing the command.",
+        since: "7.0.0",
+        group: "server",
+        arguments: &[
+            Arg::string("username"),
+            Arg::string("command"),
+            Arg::string("arg").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Acl,
+        group: "server",
+        ..help("acl|help", &["slow"])
+    },
+];
+
+const MEMORY_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "memory|usage",
+        command: Command::Memory,
+        arity: -3,
+        flags: READONLY,
+        categories: &["read", "slow"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Estimates the memory usage of a key.",
+        since: "4.0.0",
+        group: "server",
+        complexity: "O(N) where N is the number of samples.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("count").with_token("SAMPLES").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "memory|stats",
+        command: Command::Memory,
+        arity: 2,
+        categories: &["slow"],
+        summary: "Returns details about memory usage.",
+        since: "4.0.0",
+        group: "server",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "memory|doctor",
+        command: Command::Memory,
+        arity: 2,
+        categories: &["slow"],
+        summary: "Outputs a memory problems report.",
+        since: "4.0.0",
+        group: "server",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Memory,
+        group: "server",
+        ..help("memory|help", &["slow"])
+    },
+];
+
+const OBJECT_KEY_ARGUMENT: &[Arg] = &[Arg::key("key")];
+
+const OBJECT_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "object|encoding",
+        command: Command::Object,
+        arity: 3,
+        flags: READONLY,
+        categories: &["keyspace", "read", "slow"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Returns the internal encoding of a Redis object.",
+        since: "2.2.3",
+        arguments: OBJECT_KEY_ARGUMENT,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "object|freq",
+        command: Command::Object,
+        arity: 3,
+        flags: READONLY,
+        categories: &["keyspace", "read", "slow"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Returns the logarithmic access frequency counter of a Redis object.",
+        since: "4.0.0",
+        arguments: OBJECT_KEY_ARGUMENT,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "object|idletime",
+        command: Command::Object,
+        arity: 3,
+        flags: READONLY,
+        categories: &["keyspace", "read", "slow"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Returns the time since the last access to a Redis object.",
+        since: "2.2.3",
+        arguments: OBJECT_KEY_ARGUMENT,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "object|refcount",
+        command: Command::Object,
+        arity: 3,
+        flags: READONLY,
+        categories: &["keyspace", "read", "slow"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Returns the reference count of a value of a key.",
+        since: "2.2.3",
+        arguments: OBJECT_KEY_ARGUMENT,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Object,
+        ..help("object|help", &["keyspace", "slow"])
+    },
+];
+
+const COMMAND_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "command|count",
+        command: Command::Command,
+        arity: 2,
+        flags: LOADING | STALE,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns a count of commands.",
+        since: "2.8.13",
+        group: "server",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "command|info",
+        command: Command::Command,
+        arity: -2,
+        flags: LOADING | STALE,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns information about one, multiple or all commands.",
+        since: "2.8.13",
+        group: "server",
+        complexity: "O(N) where N is the number of commands to look up",
+        arguments: &[Arg::string("command-name").optional().multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "command|docs",
+        command: Command::Command,
+        arity: -2,
+        flags: LOADING | STALE,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns documentary information about one, multiple or all commands.",
+        since: "7.0.0",
+        group: "server",
+        complexity: "O(N) where N is the number of commands to look up",
+        arguments: &[Arg::string("command-name").optional().multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "command|list",
+        command: Command::Command,
+        arity: -2,
+        flags: LOADING | STALE,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns a list of command names.",
+        since: "7.0.0",
+        group: "server",
+        complexity: "O(N) where N is the total number of Redis commands",
+        arguments: &[Arg::one_of(
+            "filterby",
+            &[
+                Arg::string("module-name").with_token("MODULE"),
+                Arg::string("category").with_token("ACLCAT"),
+                Arg::string("pattern").with_token("PATTERN"),
+            ],
+        )
+        .with_token("FILTERBY")
+        .optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "command|getkeys",
+        command: Command::Command,
+        arity: -3,
+        flags: LOADING | STALE,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Extracts the key names from an arbitrary command.",
+        since: "2.8.13",
+        group: "server",
+        complexity: "O(N) where N is the number of arguments to the command",
+        arguments: &[
+            Arg::string("command"),
+            Arg::string("arg").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "command|getkeysandflags",
+        command: Command::Command,
+        arity: -3,
+        flags: LOADING | STALE,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Extracts the key names and access flags for an arbitrary command.",
+        since: "7.0.0",
+        group: "server",
+        complexity: "O(N) where N is the number of arguments to the command",
+        arguments: &[
+            Arg::string("command"),
+            Arg::string("arg").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Command,
+        group: "server",
+        ..help("command|help", CONNECTION_CATEGORIES)
+    },
+];
+
+const SCRIPTING_CATEGORIES: &[&str] = &["slow", "scripting"];
+
+/// The `numkeys key [key ...]` of scripts, functions and CMS.MERGE.
+const NUMKEYS: Option<KeyNum> = Some(KeyNum {
+    index: 2,
+    first: 1,
+    step: 1,
+});
+
+const EVAL_ARGUMENTS: &[Arg] = &[
+    Arg::string("script"),
+    Arg::integer("numkeys"),
+    Arg::key("key").optional().multiple(),
+    Arg::string("arg").optional().multiple(),
+];
+
+const EVALSHA_ARGUMENTS: &[Arg] = &[
+    Arg::string("sha1"),
+    Arg::integer("numkeys"),
+    Arg::key("key").optional().multiple(),
+    Arg::string("arg").optional().multiple(),
+];
+
+const SCRIPT_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "script|load",
+        command: Command::Script,
+        arity: 3,
+        flags: NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Loads a server-side Lua script to the script cache.",
+        since: "2.6.0",
+        group: "scripting",
+        complexity: "O(N) with N being the length in bytes of the script body.",
+        arguments: &[Arg::string("script")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "script|exists",
+        command: Command::Script,
+        arity: -3,
+        flags: NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Determines whether server-side Lua scripts exist in the script cache.",
+        since: "2.6.0",
+        group: "scripting",
+        complexity: "O(N) with N being the number of scripts to check (so checking a single script is an O(1) operation).",
+        arguments: &[Arg::string("sha1").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "script|flush",
+        command: Command::Script,
+        arity: -2,
+        flags: NOSCRIPT,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Removes all server-side Lua scripts from the script cache.",
+        since: "2.6.0",
+        group: "scripting",
+        complexity: "O(N) with N being the number of scripts in cache",
+        arguments: &[Arg::one_of(
+            "flush-type",
+            &[Arg::token("async", "ASYNC"), Arg::token("sync", "SYNC")],
+        )
+        .optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "script|kill",
+        command: Command::Script,
+        arity: 2,
+        flags: NOSCRIPT | ALLOW_BUSY,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Terminates a server-side Lua script during execution.",
+        since: "2.6.0",
+        group: "scripting",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Script,
+        group: "scripting",
+        ..help("script|help", SCRIPTING_CATEGORIES)
+    },
+];
+
+const FUNCTION_WRITE_CATEGORIES: &[&str] = &["write", "slow", "scripting"];
+
+const FCALL_ARGUMENTS: &[Arg] = &[
+    Arg::string("function"),
+    Arg::integer("numkeys"),
+    Arg::key("key").optional().multiple(),
+    Arg::string("arg").optional().multiple(),
+];
+
+const FUNCTION_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "function|load",
+        command: Command::Function,
+        arity: -3,
+        flags: WRITE | DENYOOM | NOSCRIPT,
+        categories: FUNCTION_WRITE_CATEGORIES,
+        summary: "Creates a library.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "O(1) (considering compilation time is redundant)",
+        arguments: &[
+            Arg::token("replace", "REPLACE").optional(),
+            Arg::string("function-code"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function|delete",
+        command: Command::Function,
+        arity: 3,
+        flags: WRITE | NOSCRIPT,
+        categories: FUNCTION_WRITE_CATEGORIES,
+        summary: "Deletes a library and its functions.",
+        since: "7.0.0",
+        group: "scripting",
+        arguments: &[Arg::string("library-name")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function|list",
+        command: Command::Function,
+        arity: -2,
+        flags: NOSCRIPT,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Returns information about all libraries.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "O(N) where N is the number of functions",
+        arguments: &[
+            Arg::string("library-name-pattern")
+                .with_token("LIBRARYNAME")
+                .optional(),
+            Arg::token("withcode", "WITHCODE").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function|dump",
+        command: Command::Function,
+        arity: 2,
+        flags: NOSCRIPT,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Dumps all libraries into a serialized binary payload.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "O(N) where N is the number of functions",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function|restore",
+        command: Command::Function,
+        arity: -3,
+        flags: WRITE | DENYOOM | NOSCRIPT,
+        categories: FUNCTION_WRITE_CATEGORIES,
+        summary: "Restores all libraries from a payload.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "O(N) where N is the number of functions on the payload",
+        arguments: &[
+            Arg::string("serialized-value"),
+            Arg::one_of(
+                "policy",
+                &[
+                    Arg::token("flush", "FLUSH"),
+                    Arg::token("append", "APPEND"),
+                    Arg::token("replace", "REPLACE"),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function|flush",
+        command: Command::Function,
+        arity: -2,
+        flags: WRITE | NOSCRIPT,
+        categories: FUNCTION_WRITE_CATEGORIES,
+        summary: "Deletes all libraries and functions.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "O(N) where N is the number of functions deleted",
+        arguments: &[Arg::one_of(
+            "flush-type",
+            &[Arg::token("async", "ASYNC"), Arg::token("sync", "SYNC")],
+        )
+        .optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function|stats",
+        command: Command::Function,
+        arity: 2,
+        flags: NOSCRIPT | ALLOW_BUSY,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Returns information about a function during execution.",
+        since: "7.0.0",
+        group: "scripting",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function|kill",
+        command: Command::Function,
+        arity: 2,
+        flags: NOSCRIPT | ALLOW_BUSY,
+        categories: SCRIPTING_CATEGORIES,
+        summary: "Terminates a function during execution.",
+        since: "7.0.0",
+        group: "scripting",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::Function,
+        group: "scripting",
+        ..help("function|help", SCRIPTING_CATEGORIES)
+    },
+];
+
+const JSON_WRITE_CATEGORIES: &[&str] = &["write", "json", "slow"];
+const JSON_READ_CATEGORIES: &[&str] = &["read", "json", "slow"];
+
+const BLOOM_WRITE_CATEGORIES: &[&str] = &["write", "bloom", "fast"];
+const BLOOM_READ_CATEGORIES: &[&str] = &["read", "bloom", "fast"];
+const CUCKOO_WRITE_CATEGORIES: &[&str] = &["write", "cuckoo", "fast"];
+const CUCKOO_READ_CATEGORIES: &[&str] = &["read", "cuckoo", "fast"];
+const CMS_WRITE_CATEGORIES: &[&str] = &["write", "cms", "fast"];
+const CMS_READ_CATEGORIES: &[&str] = &["read", "cms", "fast"];
+const TOPK_WRITE_CATEGORIES: &[&str] = &["write", "topk", "slow"];
+const TOPK_READ_CATEGORIES: &[&str] = &["read", "topk", "fast"];
+
+const BF_INFO_FIELDS: &[Arg] = &[
+    Arg::token("capacity", "CAPACITY"),
+    Arg::token("size", "SIZE"),
+    Arg::token("filters", "FILTERS"),
+    Arg::token("items", "ITEMS"),
+    Arg::token("expansion", "EXPANSION"),
+];
+
+const CMS_INCRBY_ITEMS: &[Arg] = &[Arg::string("item"), Arg::integer("increment")];
+
+const TOPK_RESERVE_PARAMS: &[Arg] = &[
+    Arg::integer("width"),
+    Arg::integer("depth"),
+    Arg::string("decay"),
+];
+
+const TOPK_INCRBY_ITEMS: &[Arg] = &[Arg::string("item"), Arg::integer("increment")];
+
+const TS_WRITE_CATEGORIES: &[&str] = &["write", "timeseries", "fast"];
+const TS_READ_CATEGORIES: &[&str] = &["read", "timeseries", "slow"];
+
+const TS_DUPLICATE_POLICIES: &[Arg] = &[
+    Arg::token("block", "BLOCK"),
+    Arg::token("first", "FIRST"),
+    Arg::token("last", "LAST"),
+    Arg::token("min", "MIN"),
+    Arg::token("max", "MAX"),
+    Arg::token("sum", "SUM"),
+];
+
+const TS_AGGREGATORS: &[Arg] = &[
+    Arg::token("avg", "AVG"),
+    Arg::token("sum", "SUM"),
+    Arg::token("min", "MIN"),
+    Arg::token("max", "MAX"),
+    Arg::token("range", "RANGE"),
+    Arg::token("count", "COUNT"),
+    Arg::token("first", "FIRST"),
+    Arg::token("last", "LAST"),
+];
+
+/// The settings of TS.CREATE, TS.ALTER and TS.ADD.
+const TS_OPTIONS: &[Arg] = &[
+    Arg::integer("retentionPeriod")
+        .with_token("RETENTION")
+        .optional(),
+    Arg::integer("size").with_token("CHUNK_SIZE").optional(),
+    Arg::one_of("policy", TS_DUPLICATE_POLICIES)
+        .with_token("DUPLICATE_POLICY")
+        .optional(),
+    Arg::block("labels", &[Arg::string("label"), Arg::string("value")])
+        .with_token("LABELS")
+        .optional()
+        .multiple(),
+];
+
+/// The options TS.RANGE and TS.MRANGE share after the range.
+const TS_RANGE_OPTIONS: &[Arg] = &[
+    Arg::integer("Timestamp")
+        .with_token("FILTER_BY_TS")
+        .optional()
+        .multiple(),
+    Arg::block("filter_by_value", &[Arg::string("min"), Arg::string("max")])
+        .with_token("FILTER_BY_VALUE")
+        .optional(),
+    Arg::integer("count").with_token("COUNT").optional(),
+    Arg::string("value").with_token("ALIGN").optional(),
+    Arg::block(
+        "aggregation",
+        &[
+            Arg::one_of("aggregator", TS_AGGREGATORS),
+            Arg::integer("bucketDuration"),
+        ],
+    )
+    .with_token("AGGREGATION")
+    .optional(),
+];
+
+const TS_MRANGE_ARGUMENTS: &[Arg] = &[
+    Arg::string("fromTimestamp"),
+    Arg::string("toTimestamp"),
+    Arg::block("options", TS_RANGE_OPTIONS),
+    Arg::one_of(
+        "labels",
+        &[
+            Arg::token("withlabels", "WITHLABELS"),
+            Arg::string("label1")
+                .with_token("SELECTED_LABELS")
+                .multiple(),
+        ],
+    )
+    .optional(),
+    Arg::string("filterExpr").with_token("FILTER").multiple(),
+];
+
+const TS_RANGE_ARGUMENTS: &[Arg] = &[
+    Arg::key("key"),
+    Arg::string("fromTimestamp"),
+    Arg::string("toTimestamp"),
+    Arg::block("options", TS_RANGE_OPTIONS),
+];
+
+const VECTOR_WRITE_CATEGORIES: &[&str] = &["write", "vectorset", "slow"];
+const VECTOR_READ_CATEGORIES: &[&str] = &["read", "vectorset", "fast"];
+
+/// `VALUES num vector`, the only way vectors are given.
+const VECTOR_VALUES: &[Arg] = &[Arg::integer("num"), Arg::string("vector").multiple()];
+
+const SEARCH_WRITE_CATEGORIES: &[&str] = &["write", "search", "slow"];
+const SEARCH_READ_CATEGORIES: &[&str] = &["read", "search", "slow"];
+
+/// One field of an FT.CREATE schema.
+const SEARCH_FIELD: &[Arg] = &[
+    Arg::string("field_name"),
+    Arg::string("alias").with_token("AS").optional(),
+    Arg::one_of(
+        "field_type",
+        &[
+            Arg::token("text", "TEXT"),
+            Arg::token("tag", "TAG"),
+            Arg::token("numeric", "NUMERIC"),
+        ],
+    ),
+    Arg::string("weight").with_token("WEIGHT").optional(),
+    Arg::string("separator").with_token("SEPARATOR").optional(),
+    Arg::token("casesensitive", "CASESENSITIVE").optional(),
+    Arg::token("sortable", "SORTABLE").optional(),
+];
+
+const SEARCH_LIMIT: Arg = Arg::block("limit", &[Arg::integer("offset"), Arg::integer("num")])
+    .with_token("LIMIT")
+    .optional();
+
+const SEARCH_DIALECT: Arg = Arg::integer("dialect").with_token("DIALECT").optional();
+
+const GEO_UNITS: &[Arg] = &[
+    Arg::token("m", "M"),
+    Arg::token("km", "KM"),
+    Arg::token("ft", "FT"),
+    Arg::token("mi", "MI"),
+];
+
+const GEO_SEARCH_FROM: &[Arg] = &[
+    Arg::string("member").with_token("FROMMEMBER"),
+    Arg::block(
+        "fromlonlat",
+        &[Arg::string("longitude"), Arg::string("latitude")],
+    )
+    .with_token("FROMLONLAT"),
+];
+
+const GEO_SEARCH_BY: &[Arg] = &[
+    Arg::block(
+        "circle",
+        &[Arg::string("radius"), Arg::one_of("unit", GEO_UNITS)],
+    )
+    .with_token("BYRADIUS"),
+    Arg::block(
+        "box",
+        &[
+            Arg::string("width"),
+            Arg::string("height"),
+            Arg::one_of("unit", GEO_UNITS),
+        ],
+    )
+    .with_token("BYBOX"),
+];
+
+const GEO_SEARCH_ORDER: &[Arg] = &[Arg::token("asc", "ASC"), Arg::token("desc", "DESC")];
+
+const GEO_SEARCH_COUNT: &[Arg] = &[
+    Arg::integer("count").with_token("COUNT"),
+    Arg::token("any", "ANY").optional(),
+];
+
+const STREAM_WRITE_CATEGORIES: &[&str] = &["write", "stream", "slow"];
+const STREAM_READ_CATEGORIES: &[&str] = &["read", "stream", "slow"];
+
+/// The `MAXLEN|MINID [=|~] threshold [LIMIT count]` of XADD and XTRIM.
+const STREAM_TRIM_ARGUMENTS: &[Arg] = &[
+    Arg::one_of(
+        "strategy",
+        &[Arg::token("maxlen", "MAXLEN"), Arg::token("minid", "MINID")],
+    ),
+    Arg::one_of(
+        "operator",
+        &[Arg::token("equal", "="), Arg::token("approximately", "~")],
+    )
+    .optional(),
+    Arg::string("threshold"),
+    Arg::integer("count").with_token("LIMIT").optional(),
+];
+
+const STREAM_ID_SELECTOR: &[Arg] = &[Arg::string("id"), Arg::token("new-id", "$")];
+
+const STREAM_CONSUMER_ARGUMENTS: &[Arg] = &[
+    Arg::key("key"),
+    Arg::string("group"),
+    Arg::string("consumer"),
+];
+
+const XGROUP_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "xgroup|create",
+        command: Command::XGroup,
+        arity: -5,
+        flags: WRITE | DENYOOM,
+        categories: STREAM_WRITE_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Creates a consumer group.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("group"),
+            Arg::one_of("id-selector", STREAM_ID_SELECTOR),
+            Arg::token("mkstream", "MKSTREAM").optional(),
+            Arg::integer("entries-read")
+                .with_token("ENTRIESREAD")
+                .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xgroup|createconsumer",
+        command: Command::XGroup,
+        arity: 5,
+        flags: WRITE | DENYOOM,
+        categories: STREAM_WRITE_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Creates a consumer in a consumer group.",
+        since: "6.2.0",
+        group: "stream",
+        arguments: STREAM_CONSUMER_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xgroup|delconsumer",
+        command: Command::XGroup,
+        arity: 5,
+        flags: WRITE,
+        categories: STREAM_WRITE_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Deletes a consumer from a consumer group.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: STREAM_CONSUMER_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xgroup|destroy",
+        command: Command::XGroup,
+        arity: 4,
+        flags: WRITE,
+        categories: STREAM_WRITE_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Destroys a consumer group.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(N) where N is the number of entries in the group's pending entries list (PEL).",
+        arguments: &[Arg::key("key"), Arg::string("group")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xgroup|setid",
+        command: Command::XGroup,
+        arity: -5,
+        flags: WRITE,
+        categories: STREAM_WRITE_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Sets the last-delivered ID of a consumer group.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("group"),
+            Arg::one_of("id-selector", STREAM_ID_SELECTOR),
+            Arg::integer("entriesread")
+                .with_token("ENTRIESREAD")
+                .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::XGroup,
+        group: "stream",
+        ..help("xgroup|help", &["stream", "slow"])
+    },
+];
+
+const XINFO_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "xinfo|consumers",
+        command: Command::XInfo,
+        arity: 4,
+        flags: READONLY,
+        categories: STREAM_READ_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Returns a list of the consumers in a consumer group.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[Arg::key("key"), Arg::string("group")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xinfo|groups",
+        command: Command::XInfo,
+        arity: 3,
+        flags: READONLY,
+        categories: STREAM_READ_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Returns a list of the consumer groups of a stream.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xinfo|stream",
+        command: Command::XInfo,
+        arity: -3,
+        flags: READONLY,
+        categories: STREAM_READ_CATEGORIES,
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Returns information about a stream.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[
+            Arg::key("key"),
+            Arg::block(
+                "full-block",
+                &[
+                    Arg::token("full", "FULL"),
+                    Arg::integer("count").with_token("COUNT").optional(),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        command: Command::XInfo,
+        group: "stream",
+        ..help("xinfo|help", &["stream", "slow"])
+    },
+];
+
+/// Every command the server knows, in the order COMMAND lists them.
+pub static COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec {
+        name: "ping",
+        command: Command::Ping,
+        arity: -1,
+        flags: FAST,
+        categories: &["fast", "connection"],
+        summary: "Returns the server's liveliness response.",
+        group: "connection",
+        arguments: &[Arg::string("message").optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "echo",
+        command: Command::Echo,
+        arity: 2,
+        flags: FAST,
+        categories: &["fast", "connection"],
+        summary: "Returns the given string.",
+        group: "connection",
+        arguments: &[Arg::string("message")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "command",
+        command: Command::Command,
+        arity: -1,
+        flags: LOADING | STALE,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Returns detailed information about all commands.",
+        since: "2.8.13",
+        group: "server",
+        complexity: "O(N) where N is the total number of Redis commands",
+        subcommands: COMMAND_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "config",
+        command: Command::Config,
+        arity: -2,
+        summary: "A container for server configuration commands.",
+        since: "2.0.0",
+        group: "server",
+        complexity: "Depends on subcommand.",
+        subcommands: CONFIG_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "quit",
+        command: Command::Quit,
+        arity: -1,
+        flags: ALLOW_BUSY | NOSCRIPT | LOADING | STALE | FAST | NO_AUTH,
+        categories: &["fast", "connection"],
+        summary: "Closes the connection.",
+        group: "connection",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "info",
+        command: Command::Info,
+        arity: -1,
+        flags: LOADING | STALE,
+        categories: &["slow", "dangerous"],
+        summary: "Returns information and statistics about the server.",
+        group: "server",
+        arguments: &[Arg::string("section").optional().multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "client",
+        command: Command::Client,
+        arity: -2,
+        summary: "A container for client connection commands.",
+        since: "2.4.0",
+        group: "connection",
+        complexity: "Depends on subcommand.",
+        subcommands: CLIENT_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "auth",
+        command: Command::Auth,
+        arity: -2,
+        flags: NOSCRIPT | LOADING | STALE | FAST | NO_AUTH | ALLOW_BUSY,
+        categories: &["fast", "connection"],
+        summary: "Authenticates the connection.",
+        group: "connection",
+        complexity: "O(N) where N is the number of passwords defined for the user",
+        arguments: &[Arg::string("username").optional(), Arg::string("password")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "acl",
+        command: Command::Acl,
+        arity: -2,
+        summary: "A container for Access List Control commands.",
+        since: "6.0.0",
+        group: "server",
+        complexity: "Depends on subcommand.",
+        subcommands: ACL_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "memory",
+        command: Command::Memory,
+        arity: -2,
+        summary: "A container for memory diagnostics commands.",
+        since: "4.0.0",
+        group: "server",
+        complexity: "Depends on subcommand.",
+        subcommands: MEMORY_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "object",
+        command: Command::Object,
+        arity: -2,
+        summary: "A container for object introspection commands.",
+        since: "2.2.3",
+        complexity: "Depends on subcommand.",
+        subcommands: OBJECT_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "debug",
+        command: Command::Debug,
+        arity: -2,
+        flags: ADMIN_FLAGS,
+        categories: ADMIN_CATEGORIES,
+        summary: "A container for debugging commands.",
+        group: "server",
+        complexity: "Depends on subcommand.",
+        arguments: &[Arg::key("key").with_token("OBJECT")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "eval",
+        command: Command::Eval,
+        arity: -3,
+        flags: NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        key_num: NUMKEYS,
+        summary: "Executes a server-side Lua script.",
+        since: "2.6.0",
+        group: "scripting",
+        complexity: "Depends on the script that is executed.",
+        arguments: EVAL_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "evalsha",
+        command: Command::EvalSha,
+        arity: -3,
+        flags: NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        key_num: NUMKEYS,
+        summary: "Executes a server-side Lua script by SHA1 digest.",
+        since: "2.6.0",
+        group: "scripting",
+        complexity: "Depends on the script that is executed.",
+        arguments: EVALSHA_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "eval_ro",
+        command: Command::EvalRo,
+        arity: -3,
+        flags: READONLY | NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        key_num: NUMKEYS,
+        summary: "Executes a read-only server-side Lua script.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "Depends on the script that is executed.",
+        arguments: EVAL_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "evalsha_ro",
+        command: Command::EvalShaRo,
+        arity: -3,
+        flags: READONLY | NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        key_num: NUMKEYS,
+        summary: "Executes a read-only server-side Lua script by SHA1 digest.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "Depends on the script that is executed.",
+        arguments: EVALSHA_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "script",
+        command: Command::Script,
+        arity: -2,
+        summary: "A container for Lua scripts management commands.",
+        since: "2.6.0",
+        group: "scripting",
+        complexity: "Depends on subcommand.",
+        subcommands: SCRIPT_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "fcall",
+        command: Command::FCall,
+        arity: -3,
+        flags: NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        key_num: NUMKEYS,
+        summary: "Invokes a function.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "Depends on the function that is executed.",
+        arguments: FCALL_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "fcall_ro",
+        command: Command::FCallRo,
+        arity: -3,
+        flags: READONLY | NOSCRIPT | STALE,
+        categories: SCRIPTING_CATEGORIES,
+        key_num: NUMKEYS,
+        summary: "Invokes a read-only function.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "Depends on the function that is executed.",
+        arguments: FCALL_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "function",
+        command: Command::Function,
+        arity: -2,
+        summary: "A container for function commands.",
+        since: "7.0.0",
+        group: "scripting",
+        complexity: "Depends on subcommand.",
+        subcommands: FUNCTION_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "replicaof",
+        command: Command::ReplicaOf,
+        arity: 3,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Configures a server as replica of another, or promotes it to a master.",
+        since: "5.0.0",
+        group: "server",
+        arguments: REPLICAOF_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "slaveof",
+        command: Command::SlaveOf,
+        arity: 3,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "Sets a Redis server as a replica of another, or promotes it to being a master.",
+        group: "server",
+        arguments: REPLICAOF_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "replconf",
+        command: Command::ReplConf,
+        arity: -1,
+        flags: ADMIN_FLAGS | ALLOW_BUSY,
+        categories: ADMIN_CATEGORIES,
+        summary: "An internal command for configuring the replication stream.",
+        since: "3.0.0",
+        group: "server",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "psync",
+        command: Command::PSync,
+        arity: -3,
+        flags: ADMIN | NOSCRIPT | STALE,
+        categories: ADMIN_CATEGORIES,
+        summary: "An internal command used in replication.",
+        since: "2.8.0",
+        group: "server",
+        arguments: &[Arg::string("replicationid"), Arg::integer("offset")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "wait",
+        command: Command::Wait,
+        arity: 3,
+        flags: NOSCRIPT,
+        categories: CONNECTION_CATEGORIES,
+        summary: "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+        since: "3.0.0",
+        arguments: &[Arg::integer("numreplicas"), Arg::integer("timeout")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cluster",
+        command: Command::Cluster,
+        arity: -2,
+        summary: "A container for Redis Cluster commands.",
+        since: "3.0.0",
+        group: "cluster",
+        complexity: "Depends on subcommand.",
+        subcommands: CLUSTER_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "asking",
+        command: Command::Asking,
+        arity: 1,
+        flags: FAST,
+        categories: &["fast", "connection"],
+        summary: "Signals that a cluster client is following an -ASK redirect.",
+        since: "3.0.0",
+        group: "cluster",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "migrate",
+        command: Command::Migrate,
+        arity: -6,
+        flags: WRITE,
+        categories: &["keyspace", "write", "slow", "dangerous"],
+        first_key: 3,
+        last_key: 3,
+        step: 1,
+        summary: "Atomically transfers a key from one Redis instance to another.",
+        since: "2.6.0",
+        complexity: "This command actually executes a DUMP+DEL in the source instance, and a RESTORE in the target instance. See the pages of these commands for time complexity. Also an O(N) data transfer between the two instances is performed.",
+        arguments: &[
+            Arg::string("host"),
+            Arg::integer("port"),
+            Arg::key("key"),
+            Arg::integer("destination-db"),
+            Arg::integer("timeout"),
+            Arg::token("copy", "COPY").optional(),
+            Arg::token("replace", "REPLACE").optional(),
+            Arg::string("password").with_token("AUTH").optional(),
+            Arg::block("auth2", &[Arg::string("username"), Arg::string("password")])
+                .with_token("AUTH2")
+                .optional(),
+            Arg::key("key").with_token("KEYS").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "subscribe",
+        command: Command::Subscribe,
+        arity: -2,
+        flags: NOSCRIPT | LOADING | STALE,
+        categories: &["pubsub", "slow"],
+        summary: "Listens for messages published to channels.",
+        since: "2.0.0",
+        group: "pubsub",
+        complexity: "O(N) where N is the number of channels to subscribe to.",
+        arguments: &[Arg::string("channel").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "unsubscribe",
+        command: Command::Unsubscribe,
+        arity: -1,
+        flags: NOSCRIPT | LOADING | STALE,
+        categories: &["pubsub", "slow"],
+        summary: "Stops listening to messages posted to channels.",
+        since: "2.0.0",
+        group: "pubsub",
+        complexity: "O(N) where N is the number of channels to unsubscribe.",
+        arguments: &[Arg::string("channel").optional().multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "psubscribe",
+        command: Command::PSubscribe,
+        arity: -2,
+        flags: NOSCRIPT | LOADING | STALE,
+        categories: &["pubsub", "slow"],
+        summary: "Listens for messages published to channels that match one or more patterns.",
+        since: "2.0.0",
+        group: "pubsub",
+        complexity: "O(N) where N is the number of patterns to subscribe to.",
+        arguments: &[Arg::string("pattern").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "punsubscribe",
+        command: Command::PUnsubscribe,
+        arity: -1,
+        flags: NOSCRIPT | LOADING | STALE,
+        categories: &["pubsub", "slow"],
+        summary: "Stops listening to messages published to channels that match one or more patterns.",
+        since: "2.0.0",
+        group: "pubsub",
+        complexity: "O(N) where N is the number of patterns to unsubscribe.",
+        arguments: &[Arg::string("pattern").optional().multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "publish",
+        command: Command::Publish,
+        arity: 3,
+        flags: LOADING | STALE | FAST,
+        categories: &["pubsub", "fast"],
+        summary: "Posts a message to a channel.",
+        since: "2.0.0",
+        group: "pubsub",
+        complexity: "O(N+M) where N is the number of clients subscribed to the receiving channel and M is the total number of subscribed patterns (by any client).",
+        arguments: &[Arg::string("channel"), Arg::string("message")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "multi",
+        command: Command::Multi,
+        arity: 1,
+        flags: NOSCRIPT | LOADING | STALE | FAST | ALLOW_BUSY,
+        categories: &["fast", "transaction"],
+        summary: "Starts a transaction.",
+        since: "1.2.0",
+        group: "transactions",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "exec",
+        command: Command::Exec,
+        arity: 1,
+        flags: NOSCRIPT | LOADING | STALE,
+        categories: &["slow", "transaction"],
+        summary: "Executes all commands in a transaction.",
+        since: "1.2.0",
+        group: "transactions",
+        complexity: "Depends on commands in the transaction",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "discard",
+        command: Command::Discard,
+        arity: 1,
+        flags: NOSCRIPT | LOADING | STALE | FAST | ALLOW_BUSY,
+        categories: &["fast", "transaction"],
+        summary: "Discards a transaction.",
+        since: "2.0.0",
+        group: "transactions",
+        complexity: "O(N), when N is the number of queued commands",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "watch",
+        command: Command::Watch,
+        arity: -2,
+        flags: NOSCRIPT | LOADING | STALE | FAST | ALLOW_BUSY,
+        categories: &["fast", "transaction"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        summary: "Monitors changes to keys to determine the execution of a transaction.",
+        since: "2.2.0",
+        group: "transactions",
+        complexity: "O(1) for every key.",
+        arguments: &[Arg::key("key").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "unwatch",
+        command: Command::Unwatch,
+        arity: 1,
+        flags: NOSCRIPT | LOADING | STALE | FAST | ALLOW_BUSY,
+        categories: &["fast", "transaction"],
+        summary: "Forgets about watched keys of a transaction.",
+        since: "2.2.0",
+        group: "transactions",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "del",
+        command: Command::Del,
+        arity: -2,
+        flags: WRITE,
+        categories: &["keyspace", "write", "slow"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        summary: "Deletes one or more keys.",
+        complexity: "O(N) where N is the number of keys that will be removed.",
+        tips: &["request_policy:multi_shard", "response_policy:agg_sum"],
+        arguments: &[Arg::key("key").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "dump",
+        command: Command::Dump,
+        arity: 2,
+        flags: READONLY,
+        categories: &["keyspace", "read", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns a serialized representation of the value stored at a key.",
+        since: "2.6.0",
+        complexity: "O(1) to access the key and additional O(N*M) to serialize it, where N is the number of Redis objects composing the value and M their average size.",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "restore",
+        command: Command::Restore,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: &["keyspace", "write", "slow", "dangerous"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Creates a key from the serialized representation of a value.",
+        since: "2.6.0",
+        complexity: "O(1) to create the new key and additional O(N*M) to reconstruct the serialized value, where N is the number of Redis objects composing the value and M their average size.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("ttl"),
+            Arg::string("serialized-value"),
+            Arg::token("replace", "REPLACE").optional(),
+            Arg::token("absttl", "ABSTTL").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "expire",
+        command: Command::Expire,
+        arity: -3,
+        flags: WRITE | FAST,
+        categories: &["keyspace", "write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets the expiration time of a key in seconds.",
+        since: "1.0.0",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("seconds"),
+            Arg::one_of(
+                "condition",
+                &[
+                    Arg::token("nx", "NX"),
+                    Arg::token("xx", "XX"),
+                    Arg::token("gt", "GT"),
+                    Arg::token("lt", "LT"),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pexpire",
+        command: Command::PExpire,
+        arity: -3,
+        flags: WRITE | FAST,
+        categories: &["keyspace", "write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets the expiration time of a key in milliseconds.",
+        since: "2.6.0",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("milliseconds"),
+            Arg::one_of(
+                "condition",
+                &[
+                    Arg::token("nx", "NX"),
+                    Arg::token("xx", "XX"),
+                    Arg::token("gt", "GT"),
+                    Arg::token("lt", "LT"),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "expireat",
+        command: Command::ExpireAt,
+        arity: -3,
+        flags: WRITE | FAST,
+        categories: &["keyspace", "write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets the expiration time of a key to a Unix timestamp.",
+        since: "1.2.0",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("unix-time-seconds"),
+            Arg::one_of(
+                "condition",
+                &[
+                    Arg::token("nx", "NX"),
+                    Arg::token("xx", "XX"),
+                    Arg::token("gt", "GT"),
+                    Arg::token("lt", "LT"),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pexpireat",
+        command: Command::PExpireAt,
+        arity: -3,
+        flags: WRITE | FAST,
+        categories: &["keyspace", "write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+        since: "2.6.0",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("unix-time-milliseconds"),
+            Arg::one_of(
+                "condition",
+                &[
+                    Arg::token("nx", "NX"),
+                    Arg::token("xx", "XX"),
+                    Arg::token("gt", "GT"),
+                    Arg::token("lt", "LT"),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ttl",
+        command: Command::Ttl,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: &["keyspace", "read", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the expiration time in seconds of a key.",
+        since: "1.0.0",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pttl",
+        command: Command::PTtl,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: &["keyspace", "read", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the expiration time in milliseconds of a key.",
+        since: "2.6.0",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "persist",
+        command: Command::Persist,
+        arity: 2,
+        flags: WRITE | FAST,
+        categories: &["keyspace", "write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Removes the expiration time of a key.",
+        since: "2.2.0",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "get",
+        command: Command::Get,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: &["read", "string", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the string value of a key.",
+        group: "string",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "incr",
+        command: Command::Incr,
+        arity: 2,
+        flags: WRITE | DENYOOM | FAST,
+        categories: &["write", "string", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        group: "string",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "set",
+        command: Command::Set,
+        arity: 3,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "string", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+        group: "string",
+        arguments: &[Arg::key("key"), Arg::string("value")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "setex",
+        command: Command::SetEx,
+        arity: 4,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "string", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets the string value and expiration time of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        group: "string",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("seconds"),
+            Arg::string("value"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "psetex",
+        command: Command::PSetEx,
+        arity: 4,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "string", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist.",
+        since: "2.6.0",
+        group: "string",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("milliseconds"),
+            Arg::string("value"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "mget",
+        command: Command::MGet,
+        arity: -2,
+        flags: READONLY | FAST,
+        categories: &["read", "string", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        summary: "Atomically returns the string values of one or more keys.",
+        group: "string",
+        complexity: "O(N) where N is the number of keys to retrieve.",
+        tips: &["request_policy:multi_shard"],
+        arguments: &[Arg::key("key").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "mset",
+        command: Command::MSet,
+        arity: -3,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "string", "slow"],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+        summary: "Atomically creates or modifies the string values of one or more keys.",
+        since: "1.0.1",
+        group: "string",
+        complexity: "O(N) where N is the number of keys to set.",
+        tips: &[
+            "request_policy:multi_shard",
+            "response_policy:all_succeeded",
+        ],
+        arguments: &[Arg::block("data", &[Arg::key("key"), Arg::string("value")]).multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "llen",
+        command: Command::LLen,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: &["read", "list", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the length of a list.",
+        group: "list",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "lpush",
+        command: Command::LPush,
+        arity: 3,
+        flags: WRITE | DENYOOM | FAST,
+        categories: &["write", "list", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Prepends an element to a list. Creates the key if it doesn't exist.",
+        group: "list",
+        arguments: &[Arg::key("key"), Arg::string("element")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "lpop",
+        command: Command::LPop,
+        arity: 2,
+        flags: WRITE | FAST,
+        categories: &["write", "list", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the first element of a list after removing it. Deletes the list if the last element was popped.",
+        group: "list",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "rpush",
+        command: Command::RPush,
+        arity: 3,
+        flags: WRITE | DENYOOM | FAST,
+        categories: &["write", "list", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Appends an element to a list. Creates the key if it doesn't exist.",
+        group: "list",
+        arguments: &[Arg::key("key"), Arg::string("element")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "rpop",
+        command: Command::RPop,
+        arity: 2,
+        flags: WRITE | FAST,
+        categories: &["write", "list", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns and removes the last element of a list. Deletes the list if the last element was popped.",
+        group: "list",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "setbit",
+        command: Command::SetBit,
+        arity: 4,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "bitmap", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist.",
+        since: "2.2.0",
+        group: "bitmap",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("offset"),
+            Arg::integer("value"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "getbit",
+        command: Command::GetBit,
+        arity: 3,
+        flags: READONLY | FAST,
+        categories: &["read", "bitmap", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns a bit value by offset.",
+        since: "2.2.0",
+        group: "bitmap",
+        arguments: &[Arg::key("key"), Arg::integer("offset")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bitcount",
+        command: Command::BitCount,
+        arity: -2,
+        flags: READONLY,
+        categories: &["read", "bitmap", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Counts the number of set bits (population counting) in a string.",
+        since: "2.6.0",
+        group: "bitmap",
+        complexity: "O(N)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::block(
+                "range",
+                &[
+                    Arg::integer("start"),
+                    Arg::integer("end"),
+                    Arg::one_of(
+                        "unit",
+                        &[Arg::token("byte", "BYTE"), Arg::token("bit", "BIT")],
+                    )
+                    .optional(),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bitpos",
+        command: Command::BitPos,
+        arity: -3,
+        flags: READONLY,
+        categories: &["read", "bitmap", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Finds the first set (1) or clear (0) bit in a string.",
+        since: "2.8.7",
+        group: "bitmap",
+        complexity: "O(N)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("bit"),
+            Arg::block(
+                "range",
+                &[
+                    Arg::integer("start"),
+                    Arg::block(
+                        "end-unit-block",
+                        &[
+                            Arg::integer("end"),
+                            Arg::one_of(
+                                "unit",
+                                &[Arg::token("byte", "BYTE"), Arg::token("bit", "BIT")],
+                            )
+                            .optional(),
+                        ],
+                    )
+                    .optional(),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bitop",
+        command: Command::BitOp,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "bitmap", "slow"],
+        first_key: 2,
+        last_key: -1,
+        step: 1,
+        summary: "Performs bitwise operations on multiple strings, and stores the result.",
+        since: "2.6.0",
+        group: "bitmap",
+        complexity: "O(N)",
+        arguments: &[
+            Arg::one_of(
+                "operation",
+                &[
+                    Arg::token("and", "AND"),
+                    Arg::token("or", "OR"),
+                    Arg::token("xor", "XOR"),
+                    Arg::token("not", "NOT"),
+                    Arg::token("diff", "DIFF"),
+                ],
+            ),
+            Arg::key("destkey"),
+            Arg::key("key").multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bitfield",
+        command: Command::BitField,
+        arity: -2,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "bitmap", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Performs arbitrary bitfield integer operations on strings.",
+        since: "3.2.0",
+        group: "bitmap",
+        complexity: "O(1) for each subcommand specified",
+        arguments: &[
+            Arg::key("key"),
+            Arg::one_of(
+                "operation",
+                &[
+                    Arg::block(
+                        "get-block",
+                        &[Arg::string("encoding"), Arg::integer("offset")],
+                    )
+                    .with_token("GET"),
+                    Arg::block(
+                        "write",
+                        &[
+                            Arg::one_of(
+                                "overflow-block",
+                                &[
+                                    Arg::token("wrap", "WRAP"),
+                                    Arg::token("sat", "SAT"),
+                                    Arg::token("fail", "FAIL"),
+                                ],
+                            )
+                            .with_token("OVERFLOW")
+                            .optional(),
+                            Arg::one_of(
+                                "write-operation",
+                                &[
+                                    Arg::block(
+                                        "set-block",
+                                        &[
+                                            Arg::string("encoding"),
+                                            Arg::integer("offset"),
+                                            Arg::integer("value"),
+                                        ],
+                                    )
+                                    .with_token("SET"),
+                                    Arg::block(
+                                        "incrby-block",
+                                        &[
+                                            Arg::string("encoding"),
+                                            Arg::integer("offset"),
+                                            Arg::integer("increment"),
+                                        ],
+                                    )
+                                    .with_token("INCRBY"),
+                                ],
+                            ),
+                        ],
+                    ),
+                ],
+            )
+            .optional()
+            .multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bitfield_ro",
+        command: Command::BitFieldRo,
+        arity: -2,
+        flags: READONLY | FAST,
+        categories: &["read", "bitmap", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Performs arbitrary read-only bitfield integer operations on strings.",
+        since: "6.0.0",
+        group: "bitmap",
+        complexity: "O(1) for each subcommand specified",
+        arguments: &[
+            Arg::key("key"),
+            Arg::block(
+                "get-block",
+                &[Arg::string("encoding"), Arg::integer("offset")],
+            )
+            .with_token("GET")
+            .optional()
+            .multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pfadd",
+        command: Command::PfAdd,
+        arity: -2,
+        flags: WRITE | DENYOOM | FAST,
+        categories: &["write", "hyperloglog", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist.",
+        since: "2.8.9",
+        group: "hyperloglog",
+        complexity: "O(1) to add every element.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("element").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pfcount",
+        command: Command::PfCount,
+        arity: -2,
+        flags: READONLY,
+        categories: &["read", "hyperloglog", "slow"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        summary: "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s).",
+        since: "2.8.9",
+        group: "hyperloglog",
+        complexity: "O(1) with a very small average constant time when called with a single key. O(N) with N being the number of keys, and much bigger constant times, when called with multiple keys.",
+        arguments: &[Arg::key("key").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pfmerge",
+        command: Command::PfMerge,
+        arity: -2,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "hyperloglog", "slow"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        summary: "Merges one or more HyperLogLog values into a single key.",
+        since: "2.8.9",
+        group: "hyperloglog",
+        complexity: "O(N) to merge N HyperLogLogs, but with high constant times.",
+        arguments: &[
+            Arg::key("destkey"),
+            Arg::key("sourcekey").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pfdebug",
+        command: Command::PfDebug,
+        arity: 3,
+        flags: WRITE | DENYOOM | ADMIN,
+        categories: &["write", "hyperloglog", "admin", "slow", "dangerous"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        summary: "Internal commands for debugging HyperLogLog values.",
+        since: "2.8.9",
+        group: "hyperloglog",
+        complexity: "N/A",
+        arguments: &[Arg::string("subcommand"), Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "pfselftest",
+        command: Command::PfSelfTest,
+        arity: 1,
+        flags: ADMIN,
+        categories: &["hyperloglog", "admin", "slow", "dangerous"],
+        summary: "An internal command for testing HyperLogLog values.",
+        since: "2.8.9",
+        group: "hyperloglog",
+        complexity: "N/A",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "geoadd",
+        command: Command::GeoAdd,
+        arity: -5,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "geo", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds one or more members to a geospatial index. The key is created if it doesn't exist.",
+        since: "3.2.0",
+        group: "geo",
+        complexity: "O(log(N)) for each item added, where N is the number of elements in the sorted set.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::one_of(
+                "condition",
+                &[Arg::token("nx", "NX"), Arg::token("xx", "XX")],
+            )
+            .optional(),
+            Arg::token("change", "CH").optional(),
+            Arg::block(
+                "data",
+                &[
+                    Arg::string("longitude"),
+                    Arg::string("latitude"),
+                    Arg::string("member"),
+                ],
+            )
+            .multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "geodist",
+        command: Command::GeoDist,
+        arity: -4,
+        flags: READONLY,
+        categories: &["read", "geo", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the distance between two members of a geospatial index.",
+        since: "3.2.0",
+        group: "geo",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("member1"),
+            Arg::string("member2"),
+            Arg::one_of("unit", GEO_UNITS).optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "geohash",
+        command: Command::GeoHash,
+        arity: -2,
+        flags: READONLY,
+        categories: &["read", "geo", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns members from a geospatial index as geohash strings.",
+        since: "3.2.0",
+        group: "geo",
+        complexity: "O(1) for each member requested.",
+        arguments: &[Arg::key("key"), Arg::string("member").optional().multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "geopos",
+        command: Command::GeoPos,
+        arity: -2,
+        flags: READONLY,
+        categories: &["read", "geo", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the longitude and latitude of members from a geospatial index.",
+        since: "3.2.0",
+        group: "geo",
+        complexity: "O(1) for each member requested.",
+        arguments: &[Arg::key("key"), Arg::string("member").optional().multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "geosearch",
+        command: Command::GeoSearch,
+        arity: -7,
+        flags: READONLY,
+        categories: &["read", "geo", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Queries a geospatial index for members inside an area of a box or a circle.",
+        since: "6.2.0",
+        group: "geo",
+        complexity: "O(N+log(M)) where N is the number of elements in the grid-aligned bounding box area around the shape provided as the filter and M is the number of items inside the shape",
+        arguments: &[
+            Arg::key("key"),
+            Arg::one_of("from", GEO_SEARCH_FROM),
+            Arg::one_of("by", GEO_SEARCH_BY),
+            Arg::one_of("order", GEO_SEARCH_ORDER).optional(),
+            Arg::block("count-block", GEO_SEARCH_COUNT).optional(),
+            Arg::token("withcoord", "WITHCOORD").optional(),
+            Arg::token("withdist", "WITHDIST").optional(),
+            Arg::token("withhash", "WITHHASH").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "geosearchstore",
+        command: Command::GeoSearchStore,
+        arity: -8,
+        flags: WRITE | DENYOOM,
+        categories: &["write", "geo", "slow"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        summary: "Queries a geospatial index for members inside an area of a box or a circle, optionally stores the result.",
+        since: "6.2.0",
+        group: "geo",
+        complexity: "O(N+log(M)) where N is the number of elements in the grid-aligned bounding box area around the shape provided as the filter and M is the number of items inside the shape",
+        arguments: &[
+            Arg::key("destination"),
+            Arg::key("source"),
+            Arg::one_of("from", GEO_SEARCH_FROM),
+            Arg::one_of("by", GEO_SEARCH_BY),
+            Arg::one_of("order", GEO_SEARCH_ORDER).optional(),
+            Arg::block("count-block", GEO_SEARCH_COUNT).optional(),
+            Arg::token("storedist", "STOREDIST").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.set",
+        command: Command::JsonSet,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: JSON_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Sets or updates the JSON value at a path.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(M+N) when path is evaluated to a single value where M is the size of the original value (if it exists) and N is the size of the new value",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("path"),
+            Arg::string("value"),
+            Arg::one_of(
+                "condition",
+                &[Arg::token("nx", "NX"), Arg::token("xx", "XX")],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.get",
+        command: Command::JsonGet,
+        arity: -2,
+        flags: READONLY,
+        categories: JSON_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Gets the value at one or more paths in JSON serialized form.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(N) when path is evaluated to a single value where N is the size of the value",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("indent").with_token("INDENT").optional(),
+            Arg::string("newline").with_token("NEWLINE").optional(),
+            Arg::string("space").with_token("SPACE").optional(),
+            Arg::string("path").optional().multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.del",
+        command: Command::JsonDel,
+        arity: -2,
+        flags: WRITE | DENYOOM,
+        categories: JSON_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Deletes a value.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(N) when path is evaluated to a single value where N is the size of the deleted value",
+        arguments: &[Arg::key("key"), Arg::string("path").optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.mget",
+        command: Command::JsonMGet,
+        arity: -3,
+        flags: READONLY,
+        categories: JSON_READ_CATEGORIES,
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        summary: "Returns the values at a path from one or more keys.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(M*N) when path is evaluated to a single value where M is the number of keys and N is the size of the value",
+        arguments: &[Arg::key("key").multiple(), Arg::string("path")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.numincrby",
+        command: Command::JsonNumIncrBy,
+        arity: 4,
+        flags: WRITE | DENYOOM,
+        categories: JSON_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Increments the numeric value at path by a value.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(1) when path is evaluated to a single value",
+        arguments: &[Arg::key("key"), Arg::string("path"), Arg::string("value")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.strappend",
+        command: Command::JsonStrAppend,
+        arity: -3,
+        flags: WRITE | DENYOOM,
+        categories: JSON_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Appends a string to a JSON string value at path.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(1) when path is evaluated to a single value",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("path").optional(),
+            Arg::string("value"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.arrappend",
+        command: Command::JsonArrAppend,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: JSON_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Appends one or more JSON values into the array at path after the last element in it.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(1) when path is evaluated to a single value",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("path"),
+            Arg::string("value").multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.arrinsert",
+        command: Command::JsonArrInsert,
+        arity: -5,
+        flags: WRITE | DENYOOM,
+        categories: JSON_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Inserts the JSON scalar(s) value at the specified index in the array at path.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(N) when path is evaluated to a single value where N is the size of the array",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("path"),
+            Arg::integer("index"),
+            Arg::string("value").multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.arrpop",
+        command: Command::JsonArrPop,
+        arity: -2,
+        flags: WRITE | DENYOOM,
+        categories: JSON_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Removes and returns the element at the specified index in the array at path.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(N) when path is evaluated to a single value where N is the size of the array and the specified index is not the last element",
+        arguments: &[
+            Arg::key("key"),
+            Arg::block(
+                "path-block",
+                &[Arg::string("path"), Arg::integer("index").optional()],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.arrlen",
+        command: Command::JsonArrLen,
+        arity: -2,
+        flags: READONLY,
+        categories: JSON_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the length of the array at path.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(1) where path is evaluated to a single value",
+        arguments: &[Arg::key("key"), Arg::string("path").optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.objkeys",
+        command: Command::JsonObjKeys,
+        arity: -2,
+        flags: READONLY,
+        categories: JSON_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the JSON keys of the object at path.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(N) when path is evaluated to a single value, where N is the number of keys in the object",
+        arguments: &[Arg::key("key"), Arg::string("path").optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "json.type",
+        command: Command::JsonType,
+        arity: -2,
+        flags: READONLY,
+        categories: JSON_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the type of the JSON value at path.",
+        since: "1.0.0",
+        group: "json",
+        complexity: "O(1) when path is evaluated to a single value",
+        arguments: &[Arg::key("key"), Arg::string("path").optional()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bf.reserve",
+        command: Command::BfReserve,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: BLOOM_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Creates a new Bloom Filter.",
+        since: "1.0.0",
+        group: "bf",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("error_rate"),
+            Arg::integer("capacity"),
+            Arg::integer("expansion").with_token("EXPANSION").optional(),
+            Arg::token("nonscaling", "NONSCALING").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bf.add",
+        command: Command::BfAdd,
+        arity: 3,
+        flags: WRITE | DENYOOM | FAST,
+        categories: BLOOM_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds an item to a Bloom Filter.",
+        since: "1.0.0",
+        group: "bf",
+        complexity: "O(k), where k is the number of hash functions used by the last sub-filter",
+        arguments: &[Arg::key("key"), Arg::string("item")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bf.madd",
+        command: Command::BfMAdd,
+        arity: -3,
+        flags: WRITE | DENYOOM | FAST,
+        categories: BLOOM_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds one or more items to a Bloom Filter. A filter will be created if it does not exist.",
+        since: "1.0.0",
+        group: "bf",
+        complexity: "O(k * n), where k is the number of hash functions and n is the number of items",
+        arguments: &[Arg::key("key"), Arg::string("item").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bf.exists",
+        command: Command::BfExists,
+        arity: 3,
+        flags: READONLY | FAST,
+        categories: BLOOM_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Checks whether an item exists in a Bloom Filter.",
+        since: "1.0.0",
+        group: "bf",
+        complexity: "O(k), where k is the number of hash functions used by the last sub-filter",
+        arguments: &[Arg::key("key"), Arg::string("item")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bf.mexists",
+        command: Command::BfMExists,
+        arity: -3,
+        flags: READONLY | FAST,
+        categories: BLOOM_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Checks whether one or more items exist in a Bloom Filter.",
+        since: "1.0.0",
+        group: "bf",
+        complexity: "O(k * n), where k is the number of hash functions and n is the number of items",
+        arguments: &[Arg::key("key"), Arg::string("item").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "bf.info",
+        command: Command::BfInfo,
+        arity: -2,
+        flags: READONLY | FAST,
+        categories: BLOOM_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns information about a Bloom Filter.",
+        since: "1.0.0",
+        group: "bf",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::one_of("single_value", BF_INFO_FIELDS).optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.reserve",
+        command: Command::CfReserve,
+        arity: -3,
+        flags: WRITE | DENYOOM,
+        categories: CUCKOO_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Creates a new Cuckoo Filter.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("capacity"),
+            Arg::integer("bucketsize")
+                .with_token("BUCKETSIZE")
+                .optional(),
+            Arg::integer("maxiterations")
+                .with_token("MAXITERATIONS")
+                .optional(),
+            Arg::integer("expansion").with_token("EXPANSION").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.add",
+        command: Command::CfAdd,
+        arity: 3,
+        flags: WRITE | DENYOOM | FAST,
+        categories: CUCKOO_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds an item to a Cuckoo Filter.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(k + i), where k is the number of sub-filters and i is maxIterations",
+        arguments: &[Arg::key("key"), Arg::string("item")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.addnx",
+        command: Command::CfAddNx,
+        arity: 3,
+        flags: WRITE | DENYOOM | FAST,
+        categories: CUCKOO_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds an item to a Cuckoo Filter if the item did not exist previously.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(k + i), where k is the number of sub-filters and i is maxIterations",
+        arguments: &[Arg::key("key"), Arg::string("item")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.insert",
+        command: Command::CfInsert,
+        arity: -4,
+        flags: WRITE | DENYOOM | FAST,
+        categories: CUCKOO_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds one or more items to a Cuckoo Filter. A filter will be created if it does not exist.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(n * (k + i)), where n is the number of items, k is the number of sub-filters and i is maxIterations",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("capacity").with_token("CAPACITY").optional(),
+            Arg::token("nocreate", "NOCREATE").optional(),
+            Arg::token("items", "ITEMS"),
+            Arg::string("item").multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.insertnx",
+        command: Command::CfInsertNx,
+        arity: -4,
+        flags: WRITE | DENYOOM | FAST,
+        categories: CUCKOO_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Adds one or more items to a Cuckoo Filter if the items did not exist previously. A filter will be created if it does not exist.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(n * (k + i)), where n is the number of items, k is the number of sub-filters and i is maxIterations",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("capacity").with_token("CAPACITY").optional(),
+            Arg::token("nocreate", "NOCREATE").optional(),
+            Arg::token("items", "ITEMS"),
+            Arg::string("item").multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.exists",
+        command: Command::CfExists,
+        arity: 3,
+        flags: READONLY | FAST,
+        categories: CUCKOO_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Checks if one item exists in a Cuckoo Filter.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(k), where k is the number of sub-filters",
+        arguments: &[Arg::key("key"), Arg::string("item")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.mexists",
+        command: Command::CfMExists,
+        arity: -3,
+        flags: READONLY | FAST,
+        categories: CUCKOO_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Checks if one or more items exist in a Cuckoo Filter.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(k * n), where k is the number of sub-filters and n is the number of items",
+        arguments: &[Arg::key("key"), Arg::string("item").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.del",
+        command: Command::CfDel,
+        arity: 3,
+        flags: WRITE | FAST,
+        categories: CUCKOO_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Deletes an item from a Cuckoo Filter.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(k), where k is the number of sub-filters",
+        arguments: &[Arg::key("key"), Arg::string("item")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.count",
+        command: Command::CfCount,
+        arity: 3,
+        flags: READONLY | FAST,
+        categories: CUCKOO_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return the number of times an item might be in a Cuckoo Filter.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(k), where k is the number of sub-filters",
+        arguments: &[Arg::key("key"), Arg::string("item")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cf.info",
+        command: Command::CfInfo,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: CUCKOO_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns information about a Cuckoo Filter.",
+        since: "1.0.0",
+        group: "cf",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cms.initbydim",
+        command: Command::CmsInitByDim,
+        arity: 4,
+        flags: WRITE | DENYOOM | FAST,
+        categories: CMS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Initializes a Count-Min Sketch to dimensions specified by user.",
+        since: "2.0.0",
+        group: "cms",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("width"),
+            Arg::integer("depth"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cms.initbyprob",
+        command: Command::CmsInitByProb,
+        arity: 4,
+        flags: WRITE | DENYOOM | FAST,
+        categories: CMS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Initializes a Count-Min Sketch to accommodate requested tolerances.",
+        since: "2.0.0",
+        group: "cms",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("error"),
+            Arg::string("probability"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cms.incrby",
+        command: Command::CmsIncrBy,
+        arity: -4,
+        flags: WRITE | DENYOOM | FAST,
+        categories: CMS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Increases the count of one or more items by increment.",
+        since: "2.0.0",
+        group: "cms",
+        complexity: "O(n) where n is the number of items",
+        arguments: &[
+            Arg::key("key"),
+            Arg::block("items", CMS_INCRBY_ITEMS).multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cms.query",
+        command: Command::CmsQuery,
+        arity: -3,
+        flags: READONLY | FAST,
+        categories: CMS_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the count for one or more items in a sketch.",
+        since: "2.0.0",
+        group: "cms",
+        complexity: "O(n) where n is the number of items",
+        arguments: &[Arg::key("key"), Arg::string("item").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cms.merge",
+        command: Command::CmsMerge,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: CMS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        key_num: NUMKEYS,
+        summary: "Merges several sketches into one sketch.",
+        since: "2.0.0",
+        group: "cms",
+        complexity: "O(n) where n is the number of sketches",
+        arguments: &[
+            Arg::key("destination"),
+            Arg::integer("numKeys"),
+            Arg::key("source").multiple(),
+            Arg::integer("weight")
+                .with_token("WEIGHTS")
+                .optional()
+                .multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "cms.info",
+        command: Command::CmsInfo,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: CMS_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns information about a sketch.",
+        since: "2.0.0",
+        group: "cms",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "topk.reserve",
+        command: Command::TopKReserve,
+        arity: -3,
+        flags: WRITE | DENYOOM,
+        categories: TOPK_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Initializes a Top-K sketch with specified parameters.",
+        since: "2.0.0",
+        group: "topk",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("key"),
+            Arg::integer("topk"),
+            Arg::block("params", TOPK_RESERVE_PARAMS).optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "topk.add",
+        command: Command::TopKAdd,
+        arity: -3,
+        flags: WRITE | DENYOOM,
+        categories: TOPK_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Increases the count of one or more items by increment.",
+        since: "2.0.0",
+        group: "topk",
+        complexity: "O(n * k) where n is the number of items and k is the depth",
+        arguments: &[Arg::key("key"), Arg::string("items").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "topk.incrby",
+        command: Command::TopKIncrBy,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: TOPK_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Increases the count of one or more items by increment.",
+        since: "2.0.0",
+        group: "topk",
+        complexity: "O(n * k * incr) where n is the number of items, k is the depth and incr is the increment",
+        arguments: &[
+            Arg::key("key"),
+            Arg::block("items", TOPK_INCRBY_ITEMS).multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "topk.query",
+        command: Command::TopKQuery,
+        arity: -3,
+        flags: READONLY | FAST,
+        categories: TOPK_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Checks whether one or more items are in a sketch.",
+        since: "2.0.0",
+        group: "topk",
+        complexity: "O(n) where n is the number of items",
+        arguments: &[Arg::key("key"), Arg::string("item").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "topk.list",
+        command: Command::TopKList,
+        arity: -2,
+        flags: READONLY,
+        categories: TOPK_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return the full list of items in Top-K list.",
+        since: "2.0.0",
+        group: "topk",
+        complexity: "O(k*log(k)) where k is the value of top-k",
+        arguments: &[
+            Arg::key("key"),
+            Arg::token("withcount", "WITHCOUNT").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "topk.info",
+        command: Command::TopKInfo,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: TOPK_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns information about a sketch.",
+        since: "2.0.0",
+        group: "topk",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.create",
+        command: Command::TsCreate,
+        arity: -2,
+        flags: WRITE | DENYOOM,
+        categories: TS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Creates a new time series.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key"), Arg::block("options", TS_OPTIONS)],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.alter",
+        command: Command::TsAlter,
+        arity: -2,
+        flags: WRITE,
+        categories: TS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Updates the retention, chunk size, duplicate policy, and labels of an existing time series.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(N) where N is the number of labels requested to update",
+        arguments: &[Arg::key("key"), Arg::block("options", TS_OPTIONS)],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.add",
+        command: Command::TsAdd,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: TS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Appends a sample to a time series.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(M) when M is the amount of compaction rules or O(1) with no compaction",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("timestamp"),
+            Arg::string("value"),
+            Arg::block("options", TS_OPTIONS),
+            Arg::one_of("policy_ovr", TS_DUPLICATE_POLICIES)
+                .with_token("ON_DUPLICATE")
+                .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.madd",
+        command: Command::TsMAdd,
+        arity: -4,
+        flags: WRITE | DENYOOM,
+        categories: TS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: -3,
+        step: 3,
+        summary: "Appends new samples to one or more time series.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(N*M) when N is the amount of series updated and M is the amount of compaction rules or O(N) with no compaction",
+        tips: &["request_policy:multi_shard"],
+        arguments: &[Arg::block(
+            "ktv",
+            &[
+                Arg::key("key"),
+                Arg::string("timestamp"),
+                Arg::string("value"),
+            ],
+        )
+        .multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.get",
+        command: Command::TsGet,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: &["read", "timeseries", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Gets the sample with the highest timestamp from a given time series.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.range",
+        command: Command::TsRange,
+        arity: -4,
+        flags: READONLY,
+        categories: TS_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Queries a range in forward direction.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(n/m+k) where n = Number of data points, m = Chunk size (data points per chunk), k = Number of data points that are in the requested range",
+        arguments: TS_RANGE_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.revrange",
+        command: Command::TsRevRange,
+        arity: -4,
+        flags: READONLY,
+        categories: TS_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Queries a range in reverse direction.",
+        since: "1.4.0",
+        group: "timeseries",
+        complexity: "O(n/m+k) where n = Number of data points, m = Chunk size (data points per chunk), k = Number of data points that are in the requested range",
+        arguments: TS_RANGE_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.mrange",
+        command: Command::TsMRange,
+        arity: -5,
+        flags: READONLY,
+        categories: TS_READ_CATEGORIES,
+        summary: "Queries a range across multiple time series by filters in forward direction.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(n/m+k) where n = Number of data points, m = Chunk size (data points per chunk), k = Number of data points that are in the requested ranges",
+        tips: &["request_policy:all_shards"],
+        arguments: TS_MRANGE_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.mrevrange",
+        command: Command::TsMRevRange,
+        arity: -5,
+        flags: READONLY,
+        categories: TS_READ_CATEGORIES,
+        summary: "Query a range across multiple time-series by filters in reverse direction.",
+        since: "1.4.0",
+        group: "timeseries",
+        complexity: "O(n/m+k) where n = Number of data points, m = Chunk size (data points per chunk), k = Number of data points that are in the requested ranges",
+        tips: &["request_policy:all_shards"],
+        arguments: TS_MRANGE_ARGUMENTS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.queryindex",
+        command: Command::TsQueryIndex,
+        arity: -2,
+        flags: READONLY,
+        categories: TS_READ_CATEGORIES,
+        summary: "Get all time series keys matching a filter list.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(n) where n is the number of time-series that match the filters",
+        tips: &["request_policy:all_shards"],
+        arguments: &[Arg::string("filterExpr").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.createrule",
+        command: Command::TsCreateRule,
+        arity: -6,
+        flags: WRITE,
+        categories: TS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        summary: "Creates a compaction rule.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(1)",
+        arguments: &[
+            Arg::key("sourceKey"),
+            Arg::key("destKey"),
+            Arg::block(
+                "aggregation",
+                &[
+                    Arg::one_of("aggregator", TS_AGGREGATORS),
+                    Arg::integer("bucketDuration"),
+                ],
+            )
+            .with_token("AGGREGATION"),
+            Arg::integer("alignTimestamp").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.deleterule",
+        command: Command::TsDeleteRule,
+        arity: 3,
+        flags: WRITE,
+        categories: TS_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        summary: "Deletes a compaction rule.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(1)",
+        arguments: &[Arg::key("sourceKey"), Arg::key("destKey")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ts.info",
+        command: Command::TsInfo,
+        arity: 2,
+        flags: READONLY,
+        categories: TS_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns information and statistics for a time series.",
+        since: "1.0.0",
+        group: "timeseries",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vadd",
+        command: Command::VAdd,
+        arity: -6,
+        flags: WRITE | DENYOOM,
+        categories: VECTOR_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Add a new element to a vector set, or update its vector if it already exists.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(log(N)) for each element added, where N is the number of elements in the vector set.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::block("values", VECTOR_VALUES).with_token("VALUES"),
+            Arg::string("element"),
+            Arg::token("cas", "CAS").optional(),
+            Arg::one_of(
+                "quant_type",
+                &[Arg::token("noquant", "NOQUANT"), Arg::token("q8", "Q8")],
+            )
+            .optional(),
+            Arg::one_of(
+                "metric",
+                &[Arg::token("cosine", "COSINE"), Arg::token("l2", "L2")],
+            )
+            .with_token("METRIC")
+            .optional(),
+            Arg::integer("build-exploration-factor")
+                .with_token("EF")
+                .optional(),
+            Arg::string("attributes").with_token("SETATTR").optional(),
+            Arg::integer("numlinks").with_token("M").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vrem",
+        command: Command::VRem,
+        arity: 3,
+        flags: WRITE,
+        categories: VECTOR_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Remove an element from a vector set.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(log(N)) for each element removed, where N is the number of elements in the vector set.",
+        arguments: &[Arg::key("key"), Arg::string("element")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vsim",
+        command: Command::VSim,
+        arity: -4,
+        flags: READONLY,
+        categories: &["read", "vectorset", "slow"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return elements by vector similarity.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(log(N)) where N is the number of elements in the vector set.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::one_of(
+                "query",
+                &[
+                    Arg::string("element").with_token("ELE"),
+                    Arg::block("values", VECTOR_VALUES).with_token("VALUES"),
+                ],
+            ),
+            Arg::token("withscores", "WITHSCORES").optional(),
+            Arg::token("withattribs", "WITHATTRIBS").optional(),
+            Arg::integer("num").with_token("COUNT").optional(),
+            Arg::string("delta").with_token("EPSILON").optional(),
+            Arg::integer("search-exploration-factor")
+                .with_token("EF")
+                .optional(),
+            Arg::string("expression").with_token("FILTER").optional(),
+            Arg::integer("max-filtering-effort")
+                .with_token("FILTER-EF")
+                .optional(),
+            Arg::token("truth", "TRUTH").optional(),
+            Arg::token("nothread", "NOTHREAD").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vcard",
+        command: Command::VCard,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: VECTOR_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return the number of elements in a vector set.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vdim",
+        command: Command::VDim,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: VECTOR_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return the dimension of vectors in the vector set.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vemb",
+        command: Command::VEmb,
+        arity: 3,
+        flags: READONLY | FAST,
+        categories: VECTOR_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return the vector associated with an element.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key"), Arg::string("element")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vsetattr",
+        command: Command::VSetAttr,
+        arity: 4,
+        flags: WRITE | FAST,
+        categories: &["write", "vectorset", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Associate or remove the JSON attributes of elements.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key"), Arg::string("element"), Arg::string("json")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vgetattr",
+        command: Command::VGetAttr,
+        arity: 3,
+        flags: READONLY | FAST,
+        categories: VECTOR_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Retrieve the JSON attributes of elements.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key"), Arg::string("element")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "vinfo",
+        command: Command::VInfo,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: VECTOR_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return information about a vector set.",
+        since: "8.0.0",
+        group: "vectorset",
+        complexity: "O(1)",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ft.create",
+        command: Command::FtCreate,
+        arity: -5,
+        flags: WRITE | DENYOOM,
+        categories: SEARCH_WRITE_CATEGORIES,
+        summary: "Creates an index with the given spec",
+        since: "1.0.0",
+        group: "search",
+        complexity: "O(K) at creation where K is the number of fields, O(N) if scanning the keyspace is triggered, where N is the number of keys in the keyspace",
+        tips: &["request_policy:all_shards"],
+        arguments: &[
+            Arg::string("index"),
+            Arg::one_of(
+                "data_type",
+                &[Arg::token("hash", "HASH"), Arg::token("json", "JSON")],
+            )
+            .with_token("ON")
+            .optional(),
+            Arg::block(
+                "prefix",
+                &[Arg::integer("count"), Arg::string("prefix").multiple()],
+            )
+            .with_token("PREFIX")
+            .optional(),
+            Arg::block(
+                "stopwords",
+                &[
+                    Arg::integer("count"),
+                    Arg::string("stopword").optional().multiple(),
+                ],
+            )
+            .with_token("STOPWORDS")
+            .optional(),
+            Arg::token("schema", "SCHEMA"),
+            Arg::block("field", SEARCH_FIELD).multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ft.dropindex",
+        command: Command::FtDropIndex,
+        arity: -2,
+        flags: WRITE,
+        categories: SEARCH_WRITE_CATEGORIES,
+        summary: "Deletes the index",
+        since: "2.0.0",
+        group: "search",
+        complexity: "O(1) or O(N) if documents are deleted, where N is the number of keys in the keyspace",
+        tips: &["request_policy:all_shards"],
+        arguments: &[
+            Arg::string("index"),
+            Arg::token("delete docs", "DD").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ft._list",
+        command: Command::FtList,
+        arity: 1,
+        flags: READONLY,
+        categories: SEARCH_READ_CATEGORIES,
+        summary: "Returns a list of all existing indexes",
+        since: "2.0.0",
+        group: "search",
+        complexity: "O(1)",
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ft.info",
+        command: Command::FtInfo,
+        arity: 2,
+        flags: READONLY,
+        categories: SEARCH_READ_CATEGORIES,
+        summary: "Returns information and statistics on the index",
+        since: "1.0.0",
+        group: "search",
+        complexity: "O(1)",
+        tips: &["request_policy:all_shards"],
+        arguments: &[Arg::string("index")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ft.search",
+        command: Command::FtSearch,
+        arity: -3,
+        flags: READONLY,
+        categories: SEARCH_READ_CATEGORIES,
+        summary: "Searches the index with a textual query, returning either documents or just ids",
+        since: "1.0.0",
+        group: "search",
+        complexity: "O(N)",
+        tips: &["request_policy:all_shards"],
+        arguments: &[
+            Arg::string("index"),
+            Arg::string("query"),
+            Arg::token("nocontent", "NOCONTENT").optional(),
+            Arg::token("verbatim", "VERBATIM").optional(),
+            Arg::token("withscores", "WITHSCORES").optional(),
+            Arg::token("withsortkeys", "WITHSORTKEYS").optional(),
+            Arg::block(
+                "return",
+                &[Arg::integer("count"), Arg::string("identifier").multiple()],
+            )
+            .with_token("RETURN")
+            .optional(),
+            Arg::block(
+                "sortby",
+                &[
+                    Arg::string("sortby"),
+                    Arg::one_of(
+                        "order",
+                        &[Arg::token("asc", "ASC"), Arg::token("desc", "DESC")],
+                    )
+                    .optional(),
+                ],
+            )
+            .with_token("SORTBY")
+            .optional(),
+            SEARCH_LIMIT,
+            SEARCH_DIALECT,
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "ft.aggregate",
+        command: Command::FtAggregate,
+        arity: -3,
+        flags: READONLY,
+        categories: SEARCH_READ_CATEGORIES,
+        summary: "Run a search query on an index and perform aggregate transformations on the results",
+        since: "1.1.0",
+        group: "search",
+        complexity: "O(1)",
+        tips: &["request_policy:all_shards"],
+        arguments: &[
+            Arg::string("index"),
+            Arg::string("query"),
+            Arg::token("verbatim", "VERBATIM").optional(),
+            Arg::block(
+                "load",
+                &[Arg::integer("count"), Arg::string("field").multiple()],
+            )
+            .with_token("LOAD")
+            .optional(),
+            Arg::block(
+                "groupby",
+                &[
+                    Arg::integer("nargs"),
+                    Arg::string("property").multiple(),
+                    Arg::block(
+                        "reduce",
+                        &[
+                            Arg::string("function"),
+                            Arg::integer("nargs"),
+                            Arg::string("arg").multiple(),
+                            Arg::string("name").with_token("AS").optional(),
+                        ],
+                    )
+                    .with_token("REDUCE")
+                    .optional()
+                    .multiple(),
+                ],
+            )
+            .with_token("GROUPBY")
+            .optional()
+            .multiple(),
+            Arg::block(
+                "sortby",
+                &[
+                    Arg::integer("nargs"),
+                    Arg::string("property").multiple(),
+                    Arg::integer("num").with_token("MAX").optional(),
+                ],
+            )
+            .with_token("SORTBY")
+            .optional(),
+            SEARCH_LIMIT,
+            SEARCH_DIALECT,
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xadd",
+        command: Command::XAdd,
+        arity: -5,
+        flags: WRITE | DENYOOM | FAST,
+        categories: &["write", "stream", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Appends a new message to a stream. Creates the key if it doesn't exist.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(1) when adding a new entry, O(N) when trimming where N being the number of entries evicted.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::token("nomkstream", "NOMKSTREAM").optional(),
+            Arg::block("trim", STREAM_TRIM_ARGUMENTS).optional(),
+            Arg::one_of(
+                "id-selector",
+                &[Arg::token("auto-id", "*"), Arg::string("id")],
+            ),
+            Arg::block("data", &[Arg::string("field"), Arg::string("value")]).multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xlen",
+        command: Command::XLen,
+        arity: 2,
+        flags: READONLY | FAST,
+        categories: &["read", "stream", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Return the number of messages in a stream.",
+        since: "5.0.0",
+        group: "stream",
+        arguments: &[Arg::key("key")],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xrange",
+        command: Command::XRange,
+        arity: -4,
+        flags: READONLY,
+        categories: STREAM_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the messages from a stream within a range of IDs.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(N) with N being the number of elements being returned.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("start"),
+            Arg::string("end"),
+            Arg::integer("count").with_token("COUNT").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xrevrange",
+        command: Command::XRevRange,
+        arity: -4,
+        flags: READONLY,
+        categories: STREAM_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the messages from a stream within a range of IDs in reverse order.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(N) with N being the number of elements returned.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("end"),
+            Arg::string("start"),
+            Arg::integer("count").with_token("COUNT").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xdel",
+        command: Command::XDel,
+        arity: -3,
+        flags: WRITE | FAST,
+        categories: &["write", "stream", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the number of messages after removing them from a stream.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(1) for each single item to delete in the stream, regardless of the stream size.",
+        arguments: &[Arg::key("key"), Arg::string("id").multiple()],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xtrim",
+        command: Command::XTrim,
+        arity: -4,
+        flags: WRITE,
+        categories: STREAM_WRITE_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Deletes messages from the beginning of a stream.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(N), with N being the number of evicted entries.",
+        arguments: &[Arg::key("key"), Arg::block("trim", STREAM_TRIM_ARGUMENTS)],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xread",
+        command: Command::XRead,
+        arity: -4,
+        flags: READONLY,
+        categories: &["read", "stream", "slow", "blocking"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        key_keyword: Some("STREAMS"),
+        summary: "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(N) for each stream, with N being the number of entries returned.",
+        arguments: &[
+            Arg::integer("count").with_token("COUNT").optional(),
+            Arg::integer("milliseconds").with_token("BLOCK").optional(),
+            Arg::block(
+                "streams",
+                &[Arg::key("key").multiple(), Arg::string("id").multiple()],
+            )
+            .with_token("STREAMS"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xreadgroup",
+        command: Command::XReadGroup,
+        arity: -7,
+        flags: WRITE,
+        categories: &["write", "stream", "slow", "blocking"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        key_keyword: Some("STREAMS"),
+        summary: "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(M) for each stream, with M being the number of entries returned.",
+        arguments: &[
+            Arg::block(
+                "group-block",
+                &[Arg::string("group"), Arg::string("consumer")],
+            )
+            .with_token("GROUP"),
+            Arg::integer("count").with_token("COUNT").optional(),
+            Arg::integer("milliseconds").with_token("BLOCK").optional(),
+            Arg::token("noack", "NOACK").optional(),
+            Arg::block(
+                "streams",
+                &[Arg::key("key").multiple(), Arg::string("id").multiple()],
+            )
+            .with_token("STREAMS"),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xgroup",
+        command: Command::XGroup,
+        arity: -2,
+        summary: "A container for consumer groups commands.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "Depends on subcommand.",
+        subcommands: XGROUP_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xack",
+        command: Command::XAck,
+        arity: -4,
+        flags: WRITE | FAST,
+        categories: &["write", "stream", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(1) for each message ID processed.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("group"),
+            Arg::string("id").multiple(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xpending",
+        command: Command::XPending,
+        arity: -3,
+        flags: READONLY,
+        categories: STREAM_READ_CATEGORIES,
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Returns the information and entries from a stream consumer group's pending entries list.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(N) with N being the number of elements returned.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("group"),
+            Arg::block(
+                "filters",
+                &[
+                    Arg::integer("min-idle-time").with_token("IDLE").optional(),
+                    Arg::string("start"),
+                    Arg::string("end"),
+                    Arg::integer("count"),
+                    Arg::string("consumer").optional(),
+                ],
+            )
+            .optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xclaim",
+        command: Command::XClaim,
+        arity: -6,
+        flags: WRITE | FAST,
+        categories: &["write", "stream", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered a consumer group member.",
+        since: "5.0.0",
+        group: "stream",
+        complexity: "O(log N) with N being the number of messages in the PEL of the consumer group.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("group"),
+            Arg::string("consumer"),
+            Arg::string("min-idle-time"),
+            Arg::string("id").multiple(),
+            Arg::integer("ms").with_token("IDLE").optional(),
+            Arg::integer("unix-time-milliseconds")
+                .with_token("TIME")
+                .optional(),
+            Arg::integer("count").with_token("RETRYCOUNT").optional(),
+            Arg::token("force", "FORCE").optional(),
+            Arg::token("justid", "JUSTID").optional(),
+            Arg::string("lastid").with_token("LASTID").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xautoclaim",
+        command: Command::XAutoClaim,
+        arity: -6,
+        flags: WRITE | FAST,
+        categories: &["write", "stream", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        summary: "Changes, or acquires, ownership of messages in a consumer group, as if the messages were delivered to as consumer group member.",
+        since: "6.2.0",
+        group: "stream",
+        complexity: "O(1) if COUNT is small.",
+        arguments: &[
+            Arg::key("key"),
+            Arg::string("group"),
+            Arg::string("consumer"),
+            Arg::string("min-idle-time"),
+            Arg::string("start"),
+            Arg::integer("count").with_token("COUNT").optional(),
+            Arg::token("justid", "JUSTID").optional(),
+        ],
+        ..CommandSpec::DEFAULT
+    },
+    CommandSpec {
+        name: "xinfo",
+        command: Command::XInfo,
+        arity: -2,
+        summary: "A container for stream introspection commands.",
+        since: "4.0.0",
+        group: "stream",
+        complexity: "Depends on subcommand.",
+        subcommands: XINFO_SUBCOMMANDS,
+        ..CommandSpec::DEFAULT
+    },
+];
@@ -0,0 +1,354 @@
+//! bitmap -- bit-level operations over byte strings
+//!
+//! Bits are numbered from the most significant bit of the first byte, as
+//! SETBIT and friends see them. Whole 64-bit words are handled at once
+//! wherever a range covers them, so counting and scanning large bitmaps
+//! doesn't go bit by bit.
+
+const WORD: usize = size_of::<u64>();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+    /// Bits set in the first source but in none of the others
+    Diff,
+}
+
+fn word(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().expect("a word is 8 bytes"))
+}
+
+/// The bit at `offset`, 0 past the end.
+pub fn get(bytes: &[u8], offset: usize) -> bool {
+    bytes
+        .get(offset / 8)
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Sets the bit at `offset`, growing `bytes` with zeros to reach it, and
+/// returns its previous value.
+pub fn set(bytes: &mut Vec<u8>, offset: usize, bit: bool) -> bool {
+    let index = offset / 8;
+    if index >= bytes.len() {
+        bytes.resize(index + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    let previous = bytes[index] & mask != 0;
+    if bit {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+    previous
+}
+
+/// Set bits in `bytes`.
+pub fn count(bytes: &[u8]) -> u64 {
+    let words = bytes.chunks_exact(WORD);
+    let tail: u32 = words.remainder().iter().map(|byte| byte.count_ones()).sum();
+    words.map(|w| u64::from(word(w).count_ones())).sum::<u64>() + u64::from(tail)
+}
+
+/// Masks of the bits from `start` to `end` inclusive within their first
+/// and last bytes.
+fn edge_masks(start: usize, end: usize) -> (u8, u8) {
+    (0xff >> (start % 8), 0xff << (7 - end % 8))
+}
+
+/// Set bits from bit `start` to bit `end` inclusive, which must be within
+/// `bytes`.
+pub fn count_range(bytes: &[u8], start: usize, end: usize) -> u64 {
+    let (first, last) = (start / 8, end / 8);
+    let (first_mask, last_mask) = edge_masks(start, end);
+    if first == last {
+        return u64::from((bytes[first] & first_mask & last_mask).count_ones());
+    }
+    let edges = (bytes[first] & first_mask).count_ones() + (bytes[last] & last_mask).count_ones();
+    u64::from(edges) + count(&bytes[first + 1..last])
+}
+
+/// The first bit equal to `bit` from bit `start` to bit `end` inclusive,
+/// which must be within `bytes`.
+pub fn position(bytes: &[u8], bit: bool, start: usize, end: usize) -> Option<usize> {
+    let (first, last) = (start / 8, end / 8);
+    let (first_mask, last_mask) = edge_masks(start, end);
+    // Looking for a 0 is looking for a 1 in the complement
+    let flip = |value: u64| if bit { value } else { !value };
+    let mut i = first;
+    while i <= last {
+        // Words strictly between the edge bytes need no masking
+        if i > first && i + WORD <= last {
+            let found = flip(word(&bytes[i..i + WORD]));
+            if found != 0 {
+                return Some(i * 8 + found.leading_zeros() as usize);
+            }
+            i += WORD;
+            continue;
+        }
+        let mut mask = 0xff;
+        if i == first {
+            mask &= first_mask;
+        }
+        if i == last {
+            mask &= last_mask;
+        }
+        let found = flip(u64::from(bytes[i])) as u8 & mask;
+        if found != 0 {
+            return Some(i * 8 + found.leading_zeros() as usize);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Applies `f` to `dest` and `src` word by word, with `src` read as zeros
+/// past its end.
+fn combine(dest: &mut [u8], src: &[u8], f: impl Fn(u64, u64) -> u64) {
+    let whole = src.len().min(dest.len()) / WORD * WORD;
+    for (d, s) in dest[..whole]
+        .chunks_exact_mut(WORD)
+        .zip(src.chunks_exact(WORD))
+    {
+        d.copy_from_slice(&f(word(d), word(s)).to_be_bytes());
+    }
+    for (i, d) in dest.iter_mut().enumerate().skip(whole) {
+        let s = src.get(i).copied().unwrap_or(0);
+        *d = f(u64::from(*d), u64::from(s)) as u8;
+    }
+}
+
+/// BITOP over `sources`, as long as the longest of them with the shorter
+/// ones padded with zeros. NOT and DIFF take the sources they need to be
+/// given; callers check the counts.
+pub fn op(op: BitOp, sources: &[&[u8]]) -> Vec<u8> {
+    let len = sources.iter().map(|s| s.len()).max().unwrap_or(0);
+    let mut result = vec![0; len];
+    let Some((first, rest)) = sources.split_first() else {
+        return result;
+    };
+    result[..first.len()].copy_from_slice(first);
+    match op {
+        BitOp::And => rest
+            .iter()
+            .for_each(|s| combine(&mut result, s, |a, b| a & b)),
+        BitOp::Or => rest
+            .iter()
+            .for_each(|s| combine(&mut result, s, |a, b| a | b)),
+        BitOp::Xor => rest
+            .iter()
+            .for_each(|s| combine(&mut result, s, |a, b| a ^ b)),
+        BitOp::Not => combine(&mut result, &[], |a, _| !a),
+        BitOp::Diff => {
+            let mut others = vec![0; len];
+            for s in rest {
+                combine(&mut others, s, |a, b| a | b);
+            }
+            combine(&mut result, &others, |a, b| a & !b);
+        }
+    }
+    result
+}
+
+/// How BITFIELD handles a value that doesn't fit its field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// A BITFIELD integer type: `i1` to `i64`, or `u1` to `u63` so that
+/// every value fits an i64.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Field {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl Field {
+    /// Parses `i8`, `u16` and the like.
+    pub fn parse(s: &str) -> Option<Field> {
+        let signed = match s.as_bytes().first()? {
+            b'i' | b'I' => true,
+            b'u' | b'U' => false,
+            _ => return None,
+        };
+        let bits: u32 = s[1..].parse().ok()?;
+        let max = if signed { 64 } else { 63 };
+        (1..=max).contains(&bits).then_some(Field { signed, bits })
+    }
+
+    fn range(&self) -> (i128, i128) {
+        if self.signed {
+            (-(1 << (self.bits - 1)), (1 << (self.bits - 1)) - 1)
+        } else {
+            (0, (1 << self.bits) - 1)
+        }
+    }
+
+    /// The field at bit `offset`, with bits past the end read as 0.
+    pub fn get(&self, bytes: &[u8], offset: usize) -> i64 {
+        let mut value: u64 = 0;
+        for i in 0..self.bits as usize {
+            value = (value << 1) | u64::from(get(bytes, offset + i));
+        }
+        let unused = 64 - self.bits;
+        if self.signed {
+            // Shifting back down copies the field's sign bit
+            ((value << unused) as i64) >> unused
+        } else {
+            value as i64
+        }
+    }
+
+    /// Writes the low bits of `value` at bit `offset`, growing `bytes` to
+    /// hold them.
+    pub fn set(&self, bytes: &mut Vec<u8>, offset: usize, value: i64) {
+        let last = (offset + self.bits as usize - 1) / 8;
+        if last >= bytes.len() {
+            bytes.resize(last + 1, 0);
+        }
+        for i in 0..self.bits as usize {
+            let bit = (value as u64 >> (self.bits as usize - 1 - i)) & 1 == 1;
+            set(bytes, offset + i, bit);
+        }
+    }
+
+    /// `value + incr` as the field stores it, or None if it doesn't fit
+    /// and `overflow` is FAIL.
+    pub fn add(&self, value: i64, incr: i64, overflow: Overflow) -> Option<i64> {
+        let (min, max) = self.range();
+        let sum = i128::from(value) + i128::from(incr);
+        if (min..=max).contains(&sum) {
+            return Some(sum as i64);
+        }
+        match overflow {
+            Overflow::Wrap => Some(((sum - min).rem_euclid(max - min + 1) + min) as i64),
+            Overflow::Sat => Some(sum.clamp(min, max) as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set_grows_and_get() {
+        let mut bytes = Vec::new();
+        assert!(!set(&mut bytes, 7, true));
+        assert_eq!(bytes, vec![0x01]);
+        assert!(!set(&mut bytes, 17, true));
+        assert_eq!(bytes, vec![0x01, 0x00, 0x40]);
+        assert!(set(&mut bytes, 7, false));
+        assert!(get(&bytes, 17));
+        assert!(!get(&bytes, 7));
+        assert!(!get(&bytes, 1000));
+    }
+
+    #[test]
+    fn test_count() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(count(&bytes), 1024);
+        assert_eq!(count(b"foobar"), 26);
+        assert_eq!(count_range(b"foobar", 8, 15), 6);
+        assert_eq!(count_range(b"foobar", 5, 30), 17);
+        assert_eq!(count_range(b"foobar", 3, 3), 0);
+        assert_eq!(count_range(&bytes, 0, bytes.len() * 8 - 1), 1024);
+    }
+
+    #[test]
+    fn test_position() {
+        assert_eq!(position(&[0xff, 0xf0, 0x00], false, 0, 23), Some(12));
+        assert_eq!(position(&[0x00, 0xff, 0xf0], true, 0, 23), Some(8));
+        assert_eq!(position(&[0x00, 0x00, 0x00], true, 0, 23), None);
+        assert_eq!(position(&[0xff, 0xff], false, 0, 15), None);
+        assert_eq!(position(&[0x80, 0x80], true, 1, 15), Some(8));
+        assert_eq!(position(&[0x80, 0x80], true, 1, 7), None);
+
+        // A set bit deep in a long run of zeros is found through the words
+        let mut long = vec![0u8; 100];
+        long[77] = 0x04;
+        assert_eq!(position(&long, true, 3, 799), Some(77 * 8 + 5));
+        let mut ones = vec![0xffu8; 100];
+        ones[50] = 0xfe;
+        assert_eq!(position(&ones, false, 0, 799), Some(50 * 8 + 7));
+    }
+
+    #[test]
+    fn test_op() {
+        let a: &[u8] = b"foobar-long-enough-for-words";
+        let b: &[u8] = b"abcdef";
+        let and = op(BitOp::And, &[a, b]);
+        assert_eq!(and.len(), a.len());
+        assert!((0..6).all(|i| and[i] == a[i] & b[i]));
+        assert!(and[6..].iter().all(|byte| *byte == 0));
+        assert_eq!(&op(BitOp::Or, &[b, a])[6..], &a[6..]);
+        assert_eq!(op(BitOp::Xor, &[a, a]), vec![0; a.len()]);
+        assert_eq!(op(BitOp::Not, &[&[0x0f, 0xff]]), vec![0xf0, 0x00]);
+        assert_eq!(
+            op(BitOp::Diff, &[&[0xff, 0xff], &[0xf0], &[0x00, 0x0f]]),
+            vec![0x0f, 0xf0]
+        );
+        assert_eq!(op(BitOp::And, &[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(
+            Field::parse("u63"),
+            Some(Field {
+                signed: false,
+                bits: 63
+            })
+        );
+        assert_eq!(
+            Field::parse("i64"),
+            Some(Field {
+                signed: true,
+                bits: 64
+            })
+        );
+        assert_eq!(Field::parse("u64"), None);
+        assert_eq!(Field::parse("i0"), None);
+        assert_eq!(Field::parse("x8"), None);
+
+        let (u8f, i4f) = (Field::parse("u8").unwrap(), Field::parse("i4").unwrap());
+        let mut bytes = Vec::new();
+        i4f.set(&mut bytes, 3, -2);
+        assert_eq!(bytes, vec![0b0001_1100]);
+        assert_eq!(i4f.get(&bytes, 3), -2);
+        assert_eq!(u8f.get(&bytes, 0), 0b0001_1100);
+        assert_eq!(u8f.get(&bytes, 100), 0);
+
+        let i64f = Field::parse("i64").unwrap();
+        i64f.set(&mut bytes, 5, i64::MIN + 1);
+        assert_eq!(i64f.get(&bytes, 5), i64::MIN + 1);
+        assert_eq!(bytes.len(), 9);
+    }
+
+    #[test]
+    fn test_field_overflow() {
+        let u8f = Field::parse("u8").unwrap();
+        assert_eq!(u8f.add(250, 10, Overflow::Wrap), Some(4));
+        assert_eq!(u8f.add(250, 10, Overflow::Sat), Some(255));
+        assert_eq!(u8f.add(250, 10, Overflow::Fail), None);
+        assert_eq!(u8f.add(5, -10, Overflow::Wrap), Some(251));
+        assert_eq!(u8f.add(5, -10, Overflow::Sat), Some(0));
+
+        let i8f = Field::parse("i8").unwrap();
+        assert_eq!(i8f.add(120, 10, Overflow::Wrap), Some(-126));
+        assert_eq!(i8f.add(-120, -10, Overflow::Sat), Some(-128));
+        assert_eq!(i8f.add(0, 127, Overflow::Fail), Some(127));
+
+        let i64f = Field::parse("i64").unwrap();
+        assert_eq!(i64f.add(i64::MAX, 1, Overflow::Wrap), Some(i64::MIN));
+        assert_eq!(i64f.add(i64::MAX, 1, Overflow::Sat), Some(i64::MAX));
+        let u63f = Field::parse("u63").unwrap();
+        assert_eq!(u63f.add(0, -1, Overflow::Wrap), Some(i64::MAX));
+    }
+}
@@ -0,0 +1,248 @@
+//! bloom -- scalable Bloom filters, the way RedisBloom builds them
+//!
+//! A filter is a stack of fixed size Bloom filters. Items go into the
+//! newest one, and once it holds its capacity another is stacked on top,
+//! `expansion` times larger and with half the error rate, so the rate of
+//! the whole stays close to the one asked for. An item is hashed twice
+//! with MurmurHash64A and its bits derived from the two hashes, so the
+//! same items always set the same bits.
+
+use crate::ds::codec::{Reader, Writer};
+use crate::ds::hyperloglog::murmurhash64a;
+use std::f64::consts::LN_2;
+
+pub const DEFAULT_ERROR_RATE: f64 = 0.01;
+pub const DEFAULT_CAPACITY: u64 = 100;
+pub const DEFAULT_EXPANSION: u32 = 2;
+const HASH_SEED: u64 = 0xc6a4_a793_5bd1_e995;
+/// Each new layer's error rate, as a fraction of the one below.
+const TIGHTENING_RATIO: f64 = 0.5;
+const LAYER_OVERHEAD: usize = 48;
+
+/// The two hashes an item's bits are derived from.
+fn hashes(item: &[u8]) -> (u64, u64) {
+    let a = murmurhash64a(item, HASH_SEED);
+    (a, murmurhash64a(item, a))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Layer {
+    capacity: u64,
+    error_rate: f64,
+    hashes: u32,
+    bit_count: u64,
+    bits: Vec<u8>,
+    items: u64,
+}
+
+impl Layer {
+    /// The hashes and bits for `capacity` items at `error_rate`.
+    fn size(capacity: u64, error_rate: f64) -> (u32, u64) {
+        let bits_per_item = -error_rate.ln() / (LN_2 * LN_2);
+        (
+            ((LN_2 * bits_per_item).ceil() as u32).max(1),
+            ((capacity as f64 * bits_per_item).ceil() as u64).max(1),
+        )
+    }
+
+    fn new(capacity: u64, error_rate: f64) -> Layer {
+        let (hashes, bit_count) = Layer::size(capacity, error_rate);
+        Layer {
+            capacity,
+            error_rate,
+            hashes,
+            bit_count,
+            bits: vec![0; bit_count.div_ceil(8) as usize],
+            items: 0,
+        }
+    }
+
+    fn positions(&self, (a, b): (u64, u64)) -> impl Iterator<Item = usize> + '_ {
+        (0..u64::from(self.hashes))
+            .map(move |i| (a.wrapping_add(i.wrapping_mul(b)) % self.bit_count) as usize)
+    }
+
+    fn contains(&self, hashes: (u64, u64)) -> bool {
+        self.positions(hashes)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn add(&mut self, hashes: (u64, u64)) {
+        let positions: Vec<usize> = self.positions(hashes).collect();
+        for bit in positions {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+        self.items += 1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalableBloom {
+    /// The error rate asked for, which the first layer gets
+    error_rate: f64,
+    /// How much larger each new layer is, 0 for a filter that doesn't grow
+    expansion: u32,
+    layers: Vec<Layer>,
+}
+
+impl ScalableBloom {
+    /// A filter for `capacity` items at `error_rate`, which must be
+    /// between 0 and 1. An `expansion` of 0 makes it non scaling.
+    pub fn new(error_rate: f64, capacity: u64, expansion: u32) -> ScalableBloom {
+        ScalableBloom {
+            error_rate,
+            expansion,
+            layers: vec![Layer::new(capacity, error_rate)],
+        }
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let hashes = hashes(item);
+        self.layers.iter().any(|layer| layer.contains(hashes))
+    }
+
+    /// Adds `item`, true if it wasn't in the filter yet. None if the
+    /// filter is full and doesn't scale.
+    pub fn add(&mut self, item: &[u8]) -> Option<bool> {
+        let hashes = hashes(item);
+        if self.layers.iter().any(|layer| layer.contains(hashes)) {
+            return Some(false);
+        }
+        let top = self.layers.last().expect("a filter has a layer");
+        if top.items >= top.capacity {
+            if self.expansion == 0 {
+                return None;
+            }
+            let layer = Layer::new(
+                top.capacity.saturating_mul(u64::from(self.expansion)),
+                top.error_rate * TIGHTENING_RATIO,
+            );
+            self.layers.push(layer);
+        }
+        self.layers.last_mut()?.add(hashes);
+        Some(true)
+    }
+
+    /// The items the filter holds before it needs another layer.
+    pub fn capacity(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.capacity).sum()
+    }
+
+    pub fn items(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.items).sum()
+    }
+
+    pub fn layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn expansion(&self) -> Option<u32> {
+        (self.expansion > 0).then_some(self.expansion)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| LAYER_OVERHEAD + layer.bits.len())
+            .sum()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer
+            .f64(self.error_rate)
+            .u32(self.expansion)
+            .u64(self.layers.len() as u64);
+        for layer in &self.layers {
+            writer
+                .u64(layer.capacity)
+                .f64(layer.error_rate)
+                .u64(layer.items)
+                .bytes(&layer.bits);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<ScalableBloom> {
+        let mut reader = Reader::new(bytes);
+        let error_rate = reader.f64()?;
+        let expansion = reader.u32()?;
+        let mut layers = Vec::new();
+        for _ in 0..reader.u64()? {
+            let (capacity, error_rate) = (reader.u64()?, reader.f64()?);
+            let items = reader.u64()?;
+            let bits = reader.bytes()?;
+            // Check the size before allocating a layer of it
+            let (_, bit_count) = Layer::size(capacity, error_rate);
+            if bits.len() as u64 != bit_count.div_ceil(8) {
+                return None;
+            }
+            let mut layer = Layer::new(capacity, error_rate);
+            layer.items = items;
+            layer.bits.copy_from_slice(bits);
+            layers.push(layer);
+        }
+        if layers.is_empty() || !reader.is_empty() {
+            return None;
+        }
+        Some(ScalableBloom {
+            error_rate,
+            expansion,
+            layers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_layer_size() {
+        // 1% needs 9.59 bits and 7 hashes per item
+        let layer = Layer::new(1000, 0.01);
+        assert_eq!((layer.bit_count, layer.hashes), (9586, 7));
+    }
+
+    #[test]
+    fn test_add_and_scale() {
+        let mut filter = ScalableBloom::new(0.01, 10, 2);
+        // A new item can still be a false positive, and then isn't added,
+        // more so while the layers are small
+        let added = (0..100)
+            .filter(|i| filter.add(format!("item{}", i).as_bytes()) == Some(true))
+            .count() as u64;
+        assert!(added > 90, "{}", added);
+        assert_eq!(filter.add(b"item7"), Some(false));
+        assert!((0..100).all(|i| filter.contains(format!("item{}", i).as_bytes())));
+        let false_positives = (100..10_100)
+            .filter(|i| filter.contains(format!("item{}", i).as_bytes()))
+            .count();
+        // Every layer adds its own rate, and layers this small miss theirs
+        assert!(false_positives < 500, "{}", false_positives);
+        // 10 + 20 + 40 + 80
+        assert_eq!((filter.layers(), filter.capacity()), (4, 150));
+        assert_eq!(filter.items(), added);
+    }
+
+    #[test]
+    fn test_non_scaling() {
+        let mut filter = ScalableBloom::new(0.01, 2, 0);
+        assert_eq!(filter.add(b"a"), Some(true));
+        assert_eq!(filter.add(b"b"), Some(true));
+        assert_eq!(filter.add(b"a"), Some(false));
+        assert_eq!(filter.add(b"c"), None);
+        assert_eq!(filter.expansion(), None);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut filter = ScalableBloom::new(0.001, 5, 3);
+        for i in 0..20 {
+            filter.add(&[i]);
+        }
+        let bytes = filter.to_bytes();
+        assert_eq!(ScalableBloom::from_bytes(&bytes), Some(filter));
+        assert_eq!(ScalableBloom::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+}
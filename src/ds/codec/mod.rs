@@ -0,0 +1,131 @@
+//! codec -- little endian fields for values that snapshot as bytes
+//!
+//! Filters and sketches are mostly arrays of bits and counters, so they
+//! save as one byte string rather than a field per element. Lengths are
+//! written before the bytes they count.
+
+#[derive(Debug, Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Writer {
+        self.bytes.push(value);
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Writer {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Writer {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn f32(&mut self, value: f32) -> &mut Writer {
+        self.u32(value.to_bits())
+    }
+
+    pub fn f64(&mut self, value: f64) -> &mut Writer {
+        self.u64(value.to_bits())
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Writer {
+        self.u64(value.len() as u64);
+        self.bytes.extend_from_slice(value);
+        self
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes)
+    }
+}
+
+/// Reads back what a `Writer` wrote. Every read is None once the bytes
+/// run out.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.take(4)?.try_into().ok().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.take(8)?.try_into().ok().map(u64::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Option<f32> {
+        self.u32().map(f32::from_bits)
+    }
+
+    pub fn f64(&mut self) -> Option<f64> {
+        self.u64().map(f64::from_bits)
+    }
+
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u64()?;
+        self.take(usize::try_from(len).ok()?)
+    }
+
+    /// Bytes not read yet.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = Writer::new()
+            .u8(7)
+            .u32(1 << 20)
+            .u64(u64::MAX)
+            .f32(-1.5)
+            .f64(0.01)
+            .bytes(b"abc")
+            .finish();
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(reader.u8(), Some(7));
+        assert_eq!(reader.u32(), Some(1 << 20));
+        assert_eq!(reader.u64(), Some(u64::MAX));
+        assert_eq!(reader.f32(), Some(-1.5));
+        assert_eq!(reader.f64(), Some(0.01));
+        assert_eq!(reader.bytes(), Some(&b"abc"[..]));
+        assert!(reader.is_empty());
+        assert_eq!(reader.u8(), None);
+    }
+}
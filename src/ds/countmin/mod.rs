@@ -0,0 +1,182 @@
+//! countmin -- Count-Min sketches
+//!
+//! A sketch is `depth` rows of `width` counters. Adding to an item adds
+//! to one counter per row, picked by hashing the item with the row number
+//! as seed, and an item's count is the smallest of its counters: never
+//! less than the true count, and more only by what other items sharing
+//! all its counters added.
+
+use crate::ds::codec::{Reader, Writer};
+use crate::ds::hyperloglog::murmurhash64a;
+
+const OVERHEAD: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountMinSketch {
+    width: u32,
+    depth: u32,
+    counters: Vec<u32>,
+    /// The sum of all increments
+    count: u64,
+}
+
+/// The width and depth for counts that are off by at most `error` times
+/// the total count, with `probability` that they are off by more.
+pub fn dimensions(error: f64, probability: f64) -> (u32, u32) {
+    let width = (2.0 / error).ceil() as u32;
+    let depth = (probability.ln() / 0.5f64.ln()).ceil() as u32;
+    (width, depth.max(1))
+}
+
+impl CountMinSketch {
+    pub fn new(width: u32, depth: u32) -> CountMinSketch {
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width as usize * depth as usize],
+            count: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The counter of `item` in each row.
+    fn cells<'a>(&'a self, item: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        (0..self.depth).map(move |row| {
+            let column = murmurhash64a(item, u64::from(row)) % u64::from(self.width);
+            row as usize * self.width as usize + column as usize
+        })
+    }
+
+    /// Adds `by` to `item` and returns its new count. None, changing
+    /// nothing, if a counter would overflow.
+    pub fn increment(&mut self, item: &[u8], by: u32) -> Option<u32> {
+        let cells: Vec<usize> = self.cells(item).collect();
+        if cells
+            .iter()
+            .any(|cell| self.counters[*cell].checked_add(by).is_none())
+        {
+            return None;
+        }
+        for cell in &cells {
+            self.counters[*cell] += by;
+        }
+        self.count += u64::from(by);
+        cells.iter().map(|cell| self.counters[*cell]).min()
+    }
+
+    pub fn query(&self, item: &[u8]) -> u32 {
+        self.cells(item)
+            .map(|cell| self.counters[cell])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Replaces the counters with the weighted sum of `sources`, which
+    /// must have the same dimensions. None, changing nothing, if a
+    /// counter would end up negative or overflow.
+    pub fn merge(&mut self, sources: &[(&CountMinSketch, i64)]) -> Option<()> {
+        let mut counters = Vec::with_capacity(self.counters.len());
+        for cell in 0..self.counters.len() {
+            let sum = sources.iter().try_fold(0i64, |sum, (source, weight)| {
+                sum.checked_add(i64::from(source.counters[cell]).checked_mul(*weight)?)
+            })?;
+            counters.push(u32::try_from(sum).ok()?);
+        }
+        let count = sources.iter().try_fold(0i64, |sum, (source, weight)| {
+            sum.checked_add(i64::try_from(source.count).ok()?.checked_mul(*weight)?)
+        })?;
+        self.count = u64::try_from(count).ok()?;
+        self.counters = counters;
+        Some(())
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        OVERHEAD + self.counters.len() * 4
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.u32(self.width).u32(self.depth).u64(self.count);
+        for counter in &self.counters {
+            writer.u32(*counter);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<CountMinSketch> {
+        let mut reader = Reader::new(bytes);
+        let (width, depth) = (reader.u32()?, reader.u32()?);
+        let cells = u64::from(width) * u64::from(depth);
+        if cells * 4 + 8 != reader.remaining() as u64 {
+            return None;
+        }
+        let mut sketch = CountMinSketch::new(width, depth);
+        sketch.count = reader.u64()?;
+        for counter in sketch.counters.iter_mut() {
+            *counter = reader.u32()?;
+        }
+        reader.is_empty().then_some(sketch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dimensions() {
+        assert_eq!(dimensions(0.001, 0.01), (2000, 7));
+        assert_eq!(dimensions(0.5, 0.9), (4, 1));
+    }
+
+    #[test]
+    fn test_counts_never_undercount() {
+        let mut sketch = CountMinSketch::new(20, 4);
+        for i in 0..100u32 {
+            sketch.increment(&i.to_le_bytes(), i % 7 + 1);
+        }
+        for i in 0..100u32 {
+            assert!(sketch.query(&i.to_le_bytes()) > i % 7);
+        }
+        assert_eq!(
+            sketch.count(),
+            (0..100u32).map(|i| u64::from(i % 7 + 1)).sum()
+        );
+        assert_eq!(sketch.increment(b"x", u32::MAX), None);
+        assert!(sketch.query(b"x") < u32::MAX);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = CountMinSketch::new(10, 3);
+        let mut b = CountMinSketch::new(10, 3);
+        a.increment(b"x", 2);
+        b.increment(b"x", 5);
+        let mut merged = CountMinSketch::new(10, 3);
+        merged.merge(&[(&a, 1), (&b, 3)]).unwrap();
+        assert_eq!(merged.query(b"x"), 17);
+        assert_eq!(merged.count(), 17);
+        assert_eq!(merged.merge(&[(&a, -1)]), None);
+        assert_eq!(merged.query(b"x"), 17);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut sketch = CountMinSketch::new(5, 2);
+        sketch.increment(b"a", 3);
+        let bytes = sketch.to_bytes();
+        assert_eq!(CountMinSketch::from_bytes(&bytes), Some(sketch));
+        assert_eq!(CountMinSketch::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+}
@@ -0,0 +1,361 @@
+//! cuckoo -- Cuckoo filters, the way RedisBloom builds them
+//!
+//! Items are kept as one byte fingerprints in buckets of a few slots.
+//! Each fingerprint has two candidate buckets, the second found from the
+//! first and the fingerprint alone, so a fingerprint can be moved without
+//! knowing its item. An insert with both buckets full kicks a resident
+//! over to its other bucket, and so on up to `max_iterations` times. When
+//! that fails the kicks are undone and another table is added. Unlike a
+//! Bloom filter, items can be deleted and counted.
+
+use crate::ds::codec::{Reader, Writer};
+use crate::ds::hyperloglog::murmurhash64a;
+
+pub const DEFAULT_CAPACITY: u64 = 1024;
+pub const DEFAULT_BUCKET_SIZE: u8 = 2;
+pub const DEFAULT_MAX_ITERATIONS: u16 = 20;
+pub const DEFAULT_EXPANSION: u16 = 1;
+/// Mixes a fingerprint into the bucket index of its other bucket.
+const ALT_MULTIPLIER: u64 = 0x5bd1_e995;
+const TABLE_OVERHEAD: usize = 32;
+
+/// An item's fingerprint, never 0 which marks a free slot, and its hash.
+fn fingerprint(item: &[u8]) -> (u8, u64) {
+    let hash = murmurhash64a(item, 0);
+    ((hash % 255 + 1) as u8, hash)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Table {
+    /// A power of two, so that `alt` maps the two buckets onto each other
+    buckets: u64,
+    slots: Vec<u8>,
+}
+
+impl Table {
+    fn new(buckets: u64, bucket_size: u8) -> Table {
+        Table {
+            buckets,
+            slots: vec![0; buckets as usize * usize::from(bucket_size)],
+        }
+    }
+
+    fn bucket_size(&self) -> usize {
+        self.slots.len() / self.buckets as usize
+    }
+
+    fn bucket(&self, index: u64) -> std::ops::Range<usize> {
+        let size = self.bucket_size();
+        index as usize * size..(index as usize + 1) * size
+    }
+
+    /// The other bucket a fingerprint in bucket `index` can go to.
+    fn alt(&self, index: u64, fp: u8) -> u64 {
+        (index ^ u64::from(fp).wrapping_mul(ALT_MULTIPLIER)) & (self.buckets - 1)
+    }
+
+    /// The one or two buckets an item can be in.
+    fn candidates(&self, fp: u8, hash: u64) -> Vec<u64> {
+        let first = hash & (self.buckets - 1);
+        let second = self.alt(first, fp);
+        if first == second {
+            vec![first]
+        } else {
+            vec![first, second]
+        }
+    }
+
+    fn find(&self, index: u64, fp: u8) -> Option<usize> {
+        self.bucket(index).find(|slot| self.slots[*slot] == fp)
+    }
+
+    fn count(&self, fp: u8, hash: u64) -> u64 {
+        self.candidates(fp, hash)
+            .into_iter()
+            .map(|index| {
+                self.slots[self.bucket(index)]
+                    .iter()
+                    .filter(|s| **s == fp)
+                    .count() as u64
+            })
+            .sum()
+    }
+
+    fn insert_free(&mut self, fp: u8, hash: u64) -> bool {
+        for index in self.candidates(fp, hash) {
+            if let Some(slot) = self.find(index, 0) {
+                self.slots[slot] = fp;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Makes room by moving fingerprints to their other buckets, taking
+    /// the slots to evict in turn. Leaves the table as it was on failure.
+    fn insert_kicking(&mut self, mut fp: u8, hash: u64, max_iterations: u16) -> bool {
+        let size = self.bucket_size();
+        let mut index = hash & (self.buckets - 1);
+        let mut kicked = Vec::new();
+        for i in 0..usize::from(max_iterations) {
+            let slot = self.bucket(index).start + i % size;
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+            kicked.push(slot);
+            index = self.alt(index, fp);
+            if let Some(free) = self.find(index, 0) {
+                self.slots[free] = fp;
+                return true;
+            }
+        }
+        for slot in kicked.into_iter().rev() {
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+        }
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CuckooFilter {
+    bucket_size: u8,
+    max_iterations: u16,
+    /// How much larger each new table is, 0 for a filter that doesn't grow
+    expansion: u16,
+    tables: Vec<Table>,
+    items: u64,
+    deleted: u64,
+}
+
+impl CuckooFilter {
+    /// A filter for about `capacity` items. Bucket counts are rounded up
+    /// to a power of two.
+    pub fn new(
+        capacity: u64,
+        bucket_size: u8,
+        max_iterations: u16,
+        expansion: u16,
+    ) -> CuckooFilter {
+        let buckets = capacity
+            .div_ceil(u64::from(bucket_size))
+            .next_power_of_two();
+        CuckooFilter {
+            bucket_size,
+            max_iterations,
+            expansion,
+            tables: vec![Table::new(buckets, bucket_size)],
+            items: 0,
+            deleted: 0,
+        }
+    }
+
+    /// Adds `item`, even if it is already in. False if there is no room
+    /// and the filter doesn't grow.
+    pub fn add(&mut self, item: &[u8]) -> bool {
+        let (fp, hash) = fingerprint(item);
+        let added = self
+            .tables
+            .iter_mut()
+            .rev()
+            .any(|table| table.insert_free(fp, hash))
+            || self
+                .tables
+                .last_mut()
+                .is_some_and(|table| table.insert_kicking(fp, hash, self.max_iterations));
+        if !added {
+            if self.expansion == 0 {
+                return false;
+            }
+            let buckets = self.tables.last().map_or(1, |table| table.buckets);
+            let buckets = buckets
+                .saturating_mul(u64::from(self.expansion))
+                .next_power_of_two();
+            let mut table = Table::new(buckets, self.bucket_size);
+            table.insert_free(fp, hash);
+            self.tables.push(table);
+        }
+        self.items += 1;
+        true
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let (fp, hash) = fingerprint(item);
+        self.tables.iter().any(|table| {
+            table
+                .candidates(fp, hash)
+                .into_iter()
+                .any(|index| table.find(index, fp).is_some())
+        })
+    }
+
+    /// How many times `item` seems to have been added, counting every
+    /// item with the same fingerprint and buckets.
+    pub fn count(&self, item: &[u8]) -> u64 {
+        let (fp, hash) = fingerprint(item);
+        self.tables.iter().map(|table| table.count(fp, hash)).sum()
+    }
+
+    /// Removes one copy of `item`, false if it wasn't found.
+    pub fn delete(&mut self, item: &[u8]) -> bool {
+        let (fp, hash) = fingerprint(item);
+        for table in self.tables.iter_mut().rev() {
+            for index in table.candidates(fp, hash) {
+                if let Some(slot) = table.find(index, fp) {
+                    table.slots[slot] = 0;
+                    self.items -= 1;
+                    self.deleted += 1;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// The buckets of the first table.
+    pub fn buckets(&self) -> u64 {
+        self.tables[0].buckets
+    }
+
+    pub fn tables(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    pub fn deleted(&self) -> u64 {
+        self.deleted
+    }
+
+    pub fn bucket_size(&self) -> u8 {
+        self.bucket_size
+    }
+
+    pub fn max_iterations(&self) -> u16 {
+        self.max_iterations
+    }
+
+    pub fn expansion(&self) -> u16 {
+        self.expansion
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.tables
+            .iter()
+            .map(|table| TABLE_OVERHEAD + table.slots.len())
+            .sum()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer
+            .u8(self.bucket_size)
+            .u32(u32::from(self.max_iterations))
+            .u32(u32::from(self.expansion))
+            .u64(self.items)
+            .u64(self.deleted)
+            .u64(self.tables.len() as u64);
+        for table in &self.tables {
+            writer.u64(table.buckets).bytes(&table.slots);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<CuckooFilter> {
+        let mut reader = Reader::new(bytes);
+        let bucket_size = reader.u8().filter(|size| *size > 0)?;
+        let max_iterations = u16::try_from(reader.u32()?).ok()?;
+        let expansion = u16::try_from(reader.u32()?).ok()?;
+        let (items, deleted) = (reader.u64()?, reader.u64()?);
+        let mut tables = Vec::new();
+        for _ in 0..reader.u64()? {
+            let buckets = reader.u64().filter(|n| n.is_power_of_two())?;
+            let slots = reader.bytes()?;
+            if slots.len() as u64 != buckets.checked_mul(u64::from(bucket_size))? {
+                return None;
+            }
+            tables.push(Table {
+                buckets,
+                slots: slots.to_vec(),
+            });
+        }
+        if tables.is_empty() || !reader.is_empty() {
+            return None;
+        }
+        Some(CuckooFilter {
+            bucket_size,
+            max_iterations,
+            expansion,
+            tables,
+            items,
+            deleted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alt_is_symmetric() {
+        let table = Table::new(64, 2);
+        for fp in 1..=255 {
+            for index in [0, 17, 63] {
+                assert_eq!(table.alt(table.alt(index, fp), fp), index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_count_delete() {
+        let mut filter = CuckooFilter::new(100, 2, 20, 1);
+        assert!(filter.add(b"a"));
+        assert!(filter.add(b"a"));
+        assert!(filter.add(b"b"));
+        assert!(filter.contains(b"a"));
+        assert!(!filter.contains(b"c"));
+        assert_eq!(filter.count(b"a"), 2);
+        assert!(filter.delete(b"a"));
+        assert_eq!(filter.count(b"a"), 1);
+        assert!(filter.delete(b"a"));
+        assert!(!filter.delete(b"a"));
+        assert!(!filter.contains(b"a"));
+        assert_eq!((filter.items(), filter.deleted()), (1, 2));
+    }
+
+    #[test]
+    fn test_grows_when_full() {
+        let mut filter = CuckooFilter::new(8, 2, 20, 1);
+        assert_eq!(filter.buckets(), 4);
+        for i in 0..100u32 {
+            assert!(filter.add(&i.to_le_bytes()));
+        }
+        assert!(filter.tables() > 1);
+        assert!((0..100u32).all(|i| filter.contains(&i.to_le_bytes())));
+        assert_eq!(filter.items(), 100);
+    }
+
+    #[test]
+    fn test_full_without_expansion() {
+        let mut filter = CuckooFilter::new(4, 1, 10, 0);
+        let added: Vec<u32> = (0..100u32)
+            .filter(|i| filter.add(&i.to_le_bytes()))
+            .collect();
+        assert!(added.len() <= 4);
+        assert_eq!(filter.items(), added.len() as u64);
+        // Failed kicks are undone, so everything added is still there
+        assert!(added.iter().all(|i| filter.contains(&i.to_le_bytes())));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut filter = CuckooFilter::new(8, 4, 50, 2);
+        for i in 0..40u32 {
+            filter.add(&i.to_le_bytes());
+        }
+        filter.delete(&3u32.to_le_bytes());
+        let bytes = filter.to_bytes();
+        assert_eq!(CuckooFilter::from_bytes(&bytes), Some(filter));
+        assert_eq!(CuckooFilter::from_bytes(&bytes[1..]), None);
+    }
+}
@@ -0,0 +1,158 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rand::{Rng, RngExt};
+
+/// dict -- hash map with O(1) random key sampling
+///
+/// `HashMap` has no way to pick a random entry without walking it, which
+/// approximated eviction and random-key commands need to do constantly.
+/// Every key is also stored in a dense vector, and each entry remembers
+/// its slot so removal is a swap_remove instead of a scan.
+pub struct Dict<K, V> {
+    map: HashMap<K, (V, usize)>,
+    keys: Vec<K>,
+}
+
+impl<K, V> Default for Dict<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Dict<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get(key).map(|(v, _)| v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get_mut(key).map(|(v, _)| v)
+    }
+
+    /// Inserts a value, returning the previous value stored under `key`.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.map.get_mut(&key) {
+            Some((old, _)) => Some(std::mem::replace(old, value)),
+            None => {
+                self.keys.push(key.clone());
+                self.map.insert(key, (value, self.keys.len() - 1));
+                None
+            }
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (value, slot) = self.map.remove(key)?;
+        self.keys.swap_remove(slot);
+        if let Some(moved) = self.keys.get(slot) {
+            self.map.get_mut::<K>(moved).unwrap().1 = slot;
+        }
+        Some(value)
+    }
+
+    pub fn random_key<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<&K> {
+        if self.keys.is_empty() {
+            None
+        } else {
+            self.keys.get(rng.random_range(0..self.keys.len()))
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.keys.iter()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter().map(|(k, (v, _))| (k, v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn test_insert_get() {
+        let mut dict: Dict<String, i32> = Dict::new();
+        assert!(dict.is_empty());
+        assert_eq!(dict.insert("a".to_string(), 1), None);
+        assert_eq!(dict.insert("a".to_string(), 2), Some(1));
+        assert_eq!(dict.get("a"), Some(&2));
+        assert_eq!(dict.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_keeps_slots_consistent() {
+        let mut dict: Dict<i32, i32> = Dict::new();
+        for n in 0..100 {
+            dict.insert(n, n * 10);
+        }
+        for n in (0..100).step_by(3) {
+            assert_eq!(dict.remove(&n), Some(n * 10));
+        }
+        assert_eq!(dict.remove(&0), None);
+        for n in 0..100 {
+            assert_eq!(dict.contains_key(&n), n % 3 != 0);
+        }
+        let mut keys: Vec<i32> = dict.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, (0..100).filter(|n| n % 3 != 0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_random_key() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut dict: Dict<i32, ()> = Dict::new();
+        assert!(dict.random_key(&mut rng).is_none());
+        for n in 0..10 {
+            dict.insert(n, ());
+        }
+        for _ in 0..100 {
+            let key = dict.random_key(&mut rng).unwrap();
+            assert!(dict.contains_key(key));
+        }
+    }
+}
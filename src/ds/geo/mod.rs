@@ -0,0 +1,299 @@
+//! geo -- coordinates as 52 bit geohashes, the way Redis scores them
+//!
+//! Longitude and latitude are each cut into 26 bits and interleaved,
+//! longitude first, so the hash is a sorted set score and points that
+//! are near each other mostly have near scores. Latitude only runs to the
+//! limits of Web Mercator. A search covers its area with the 3x3 block of
+//! cells around the centre at the finest step that still contains it, and
+//! then checks each candidate's exact distance.
+
+pub const LON_MIN: f64 = -180.0;
+pub const LON_MAX: f64 = 180.0;
+pub const LAT_MIN: f64 = -85.051_128_78;
+pub const LAT_MAX: f64 = 85.051_128_78;
+/// Bits per coordinate in a full precision hash.
+const STEP_MAX: u32 = 26;
+/// Earth's radius as Redis measures it.
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub lon: f64,
+    pub lat: f64,
+}
+
+impl Point {
+    /// A point, None outside the longitudes and latitudes Redis can index.
+    pub fn new(lon: f64, lat: f64) -> Option<Point> {
+        ((LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat))
+            .then_some(Point { lon, lat })
+    }
+}
+
+/// A distance unit and the meters in one of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Meters,
+    Kilometers,
+    Feet,
+    Miles,
+}
+
+impl Unit {
+    pub fn parse(s: &str) -> Option<Unit> {
+        match s.to_lowercase().as_str() {
+            "m" => Some(Unit::Meters),
+            "km" => Some(Unit::Kilometers),
+            "ft" => Some(Unit::Feet),
+            "mi" => Some(Unit::Miles),
+            _ => None,
+        }
+    }
+
+    pub fn meters(self) -> f64 {
+        match self {
+            Unit::Meters => 1.0,
+            Unit::Kilometers => 1000.0,
+            Unit::Feet => 0.3048,
+            Unit::Miles => 1609.34,
+        }
+    }
+}
+
+/// The area a search covers around its centre.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// Within this many meters
+    Radius(f64),
+    /// Within a box this many meters wide and high
+    Box(f64, f64),
+}
+
+impl Shape {
+    /// The distance from `center` to `point` if the point is inside.
+    pub fn distance_to(self, center: Point, point: Point) -> Option<f64> {
+        let distance = distance(center, point);
+        match self {
+            Shape::Radius(radius) => (distance <= radius).then_some(distance),
+            Shape::Box(width, height) => {
+                let lat_distance = EARTH_RADIUS * (point.lat - center.lat).to_radians().abs();
+                let along = Point {
+                    lon: center.lon,
+                    lat: point.lat,
+                };
+                let lon_distance = self::distance(point, along);
+                (lat_distance <= height / 2.0 && lon_distance <= width / 2.0).then_some(distance)
+            }
+        }
+    }
+
+    /// Half the extent in meters north-south and east-west.
+    fn half_extent(self) -> (f64, f64) {
+        match self {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box(width, height) => (height / 2.0, width / 2.0),
+        }
+    }
+}
+
+/// Great circle distance in meters.
+pub fn distance(a: Point, b: Point) -> f64 {
+    let (lat1, lat2) = (a.lat.to_radians(), b.lat.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((b.lon.to_radians() - a.lon.to_radians()) / 2.0).sin();
+    2.0 * EARTH_RADIUS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+fn interleave(lat: u32, lon: u32) -> u64 {
+    (0..32).fold(0, |bits, i| {
+        bits | (u64::from(lat >> i & 1) << (2 * i)) | (u64::from(lon >> i & 1) << (2 * i + 1))
+    })
+}
+
+fn deinterleave(bits: u64) -> (u32, u32) {
+    (0..32).fold((0, 0), |(lat, lon), i| {
+        (
+            lat | ((bits >> (2 * i) & 1) as u32) << i,
+            lon | ((bits >> (2 * i + 1) & 1) as u32) << i,
+        )
+    })
+}
+
+/// The cell of `step` bits per coordinate holding `point`, within the
+/// given latitude range.
+fn encode_in(point: Point, step: u32, lat_min: f64, lat_max: f64) -> u64 {
+    let cells = f64::from(1u32 << step);
+    let offset = |value: f64, min: f64, max: f64| {
+        (((value - min) / (max - min)) * cells).min(cells - 1.0) as u32
+    };
+    interleave(
+        offset(point.lat, lat_min, lat_max),
+        offset(point.lon, LON_MIN, LON_MAX),
+    )
+}
+
+/// The full precision hash of a point, which is its sorted set score.
+pub fn encode(point: Point) -> u64 {
+    encode_in(point, STEP_MAX, LAT_MIN, LAT_MAX)
+}
+
+/// The longitude and latitude bounds of a cell.
+fn cell_bounds(bits: u64, step: u32) -> ((f64, f64), (f64, f64)) {
+    let (lat, lon) = deinterleave(bits);
+    let cells = f64::from(1u32 << step);
+    let lon_width = (LON_MAX - LON_MIN) / cells;
+    let lat_height = (LAT_MAX - LAT_MIN) / cells;
+    let lon_min = LON_MIN + f64::from(lon) * lon_width;
+    let lat_min = LAT_MIN + f64::from(lat) * lat_height;
+    (
+        (lon_min, lon_min + lon_width),
+        (lat_min, lat_min + lat_height),
+    )
+}
+
+/// The centre of a full precision cell, which is what a score reads back as.
+pub fn decode(hash: u64) -> Point {
+    let ((lon_min, lon_max), (lat_min, lat_max)) = cell_bounds(hash, STEP_MAX);
+    Point {
+        lon: ((lon_min + lon_max) / 2.0).clamp(LON_MIN, LON_MAX),
+        lat: ((lat_min + lat_max) / 2.0).clamp(LAT_MIN, LAT_MAX),
+    }
+}
+
+/// The standard 11 character geohash of a score, as GEOHASH reports it.
+/// Standard geohashes span latitudes -90 to 90, so the point is encoded
+/// again.
+pub fn geohash_string(hash: u64) -> String {
+    let bits = encode_in(decode(hash), STEP_MAX, -90.0, 90.0);
+    (0..11)
+        .map(|i| {
+            // 52 bits fill ten characters and two bits of the eleventh,
+            // which Redis leaves at 0
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            BASE32[index as usize] as char
+        })
+        .collect()
+}
+
+/// Score ranges, each `[min, max)`, whose cells cover `shape` around
+/// `center`. Points in them still need checking against the shape.
+pub fn search_ranges(center: Point, shape: Shape) -> Vec<(u64, u64)> {
+    let (north_south, east_west) = shape.half_extent();
+    let lat_delta = (north_south / EARTH_RADIUS).to_degrees();
+    let lon_delta = (east_west / (EARTH_RADIUS * center.lat.to_radians().cos())).to_degrees();
+    let step = (1..=STEP_MAX)
+        .rev()
+        .find(|step| {
+            let bits = encode_in(center, *step, LAT_MIN, LAT_MAX);
+            let ((lon_min, lon_max), (lat_min, lat_max)) = cell_bounds(bits, *step);
+            let (width, height) = (lon_max - lon_min, lat_max - lat_min);
+            center.lon - lon_delta >= lon_min - width
+                && center.lon + lon_delta <= lon_max + width
+                && (center.lat - lat_delta).max(LAT_MIN) >= lat_min - height
+                && (center.lat + lat_delta).min(LAT_MAX) <= lat_max + height
+        })
+        .unwrap_or(1);
+
+    let bits = encode_in(center, step, LAT_MIN, LAT_MAX);
+    let ((lon_min, lon_max), (lat_min, lat_max)) = cell_bounds(bits, step);
+    let (width, height) = (lon_max - lon_min, lat_max - lat_min);
+    let shift = 2 * (STEP_MAX - step);
+    let mut ranges = Vec::with_capacity(9);
+    for dlat in [-1.0, 0.0, 1.0] {
+        // Step from the middle of the centre cell, so that a neighbour
+        // always lands in the middle of its own
+        let lat = (lat_min + lat_max) / 2.0 + dlat * height;
+        if !(LAT_MIN..=LAT_MAX).contains(&lat) {
+            continue;
+        }
+        for dlon in [-1.0, 0.0, 1.0] {
+            let mut lon = (lon_min + lon_max) / 2.0 + dlon * width;
+            if lon < LON_MIN {
+                lon += LON_MAX - LON_MIN;
+            } else if lon > LON_MAX {
+                lon -= LON_MAX - LON_MIN;
+            }
+            let cell = encode_in(Point { lon, lat }, step, LAT_MIN, LAT_MAX);
+            let range = (cell << shift, (cell + 1) << shift);
+            if !ranges.contains(&range) {
+                ranges.push(range);
+            }
+        }
+    }
+    ranges.sort_unstable();
+    ranges
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn palermo() -> Point {
+        Point::new(13.361389, 38.115556).unwrap()
+    }
+
+    fn catania() -> Point {
+        Point::new(15.087269, 37.502669).unwrap()
+    }
+
+    #[test]
+    fn test_encode_matches_redis() {
+        // Scores from the GEOADD example in Redis' documentation
+        assert_eq!(encode(palermo()), 3479099956230698);
+        assert_eq!(encode(catania()), 3479447370796909);
+        let point = decode(encode(palermo()));
+        assert!((point.lon - 13.361389).abs() < 1e-5);
+        assert!((point.lat - 38.115556).abs() < 1e-5);
+        assert_eq!(geohash_string(encode(palermo())), "sqc8b49rny0");
+        assert_eq!(geohash_string(encode(catania())), "sqdtr74hyu0");
+        assert_eq!(Point::new(10.0, 86.0), None);
+    }
+
+    #[test]
+    fn test_distance() {
+        // Redis measures between the cell centres the scores decode to
+        let meters = distance(decode(encode(palermo())), decode(encode(catania())));
+        assert!((meters - 166274.1516).abs() < 0.01, "{}", meters);
+        assert!(
+            Shape::Radius(200_000.0)
+                .distance_to(palermo(), catania())
+                .is_some()
+        );
+        assert!(
+            Shape::Radius(100_000.0)
+                .distance_to(palermo(), catania())
+                .is_none()
+        );
+        // Catania is about 150km east and 68km south of Palermo
+        assert!(
+            Shape::Box(400_000.0, 200_000.0)
+                .distance_to(palermo(), catania())
+                .is_some()
+        );
+        assert!(
+            Shape::Box(400_000.0, 100_000.0)
+                .distance_to(palermo(), catania())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_search_ranges_cover_the_shape() {
+        let center = Point::new(15.0, 37.0).unwrap();
+        for (point, radius) in [(palermo(), 200_000.0), (catania(), 60_000.0)] {
+            let hash = encode(point);
+            let ranges = search_ranges(center, Shape::Radius(radius));
+            assert!(ranges.iter().any(|(min, max)| (*min..*max).contains(&hash)));
+        }
+        // Across the antimeridian
+        let east = Point::new(179.9, 0.0).unwrap();
+        let west = encode(Point::new(-179.9, 0.0).unwrap());
+        let ranges = search_ranges(east, Shape::Radius(50_000.0));
+        assert!(ranges.iter().any(|(min, max)| (*min..*max).contains(&west)));
+    }
+}
@@ -0,0 +1,510 @@
+//! hyperloglog -- Redis's HyperLogLog string format
+//!
+//! An HLL is a string starting with a 16 byte header: the magic `HYLL`,
+//! the encoding, three unused bytes and the cached cardinality as a little
+//! endian u64 whose top bit marks it stale. 16384 six-bit registers follow,
+//! either densely packed or run-length encoded (sparse) while most of them
+//! are still zero. The bytes are the same as real Redis's, so values move
+//! between the two with DUMP, RESTORE or plain GET and SET.
+//!
+//! Sparse updates decode the registers and encode them again rather than
+//! patching opcodes in place, which can pick different runs than Redis
+//! would, but both read each other's encodings.
+
+/// Bits of the hash that pick a register.
+const P: u32 = 14;
+/// Bits of the hash left for the run of zeros.
+const Q: u32 = 64 - P;
+pub const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+const HEADER_SIZE: usize = 16;
+pub const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * REGISTER_BITS).div_ceil(8);
+const MAGIC: &[u8] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+const HASH_SEED: u64 = 0xadc8_3b19;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+// Sparse opcodes: ZERO is 00xxxxxx, XZERO is 01xxxxxx yyyyyyyy and VAL is
+// 1vvvvvxx, each with the run length minus one
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+const SPARSE_VAL_MAX_VALUE: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+
+/// Largest sparse HLL before it converts to dense, Redis's default
+/// `hll-sparse-max-bytes`.
+pub const SPARSE_MAX_BYTES: usize = 3000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Dense,
+    Sparse,
+}
+
+/// MurmurHash64A, the hash Redis feeds HLL elements through.
+pub fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let mut k = u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= u64::from(*byte) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// The register an element goes to and the value it offers it: one more
+/// than the number of trailing zeros in the rest of its hash.
+pub fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmurhash64a(element, HASH_SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // The guard bit caps the count at Q + 1
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+/// An empty HLL: sparse, all registers zero, with a cached count of 0.
+pub fn new_sparse() -> Vec<u8> {
+    let mut bytes = header(SPARSE);
+    bytes.extend(encode_runs(&[0; REGISTERS]).expect("zeros fit sparse"));
+    bytes
+}
+
+fn header(encoding: u8) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend([encoding, 0, 0, 0]);
+    bytes.extend([0; 8]);
+    bytes
+}
+
+/// The encoding of a string that looks like an HLL, None if it doesn't.
+/// Sparse payloads are only checked when decoded.
+pub fn encoding(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < HEADER_SIZE || &bytes[..4] != MAGIC {
+        return None;
+    }
+    match bytes[4] {
+        DENSE if bytes.len() == DENSE_SIZE => Some(Encoding::Dense),
+        SPARSE => Some(Encoding::Sparse),
+        _ => None,
+    }
+}
+
+/// The cached cardinality, unless a write made it stale.
+pub fn cached_count(bytes: &[u8]) -> Option<u64> {
+    let card = u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes"));
+    (card >> 63 == 0).then_some(card)
+}
+
+pub fn set_cached_count(bytes: &mut [u8], count: u64) {
+    bytes[8..16].copy_from_slice(&count.to_le_bytes());
+}
+
+pub fn invalidate_count(bytes: &mut [u8]) {
+    bytes[15] |= 0x80;
+}
+
+fn dense_register(registers: &[u8], index: usize) -> u8 {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let low = u16::from(registers[byte]);
+    let high = u16::from(registers.get(byte + 1).copied().unwrap_or(0));
+    (((high << 8 | low) >> shift) as u8) & REGISTER_MAX
+}
+
+fn set_dense_register(registers: &mut [u8], index: usize, value: u8) {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let mask = u16::from(REGISTER_MAX) << shift;
+    let value = u16::from(value) << shift;
+    registers[byte] = (registers[byte] & !(mask as u8)) | value as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next = (*next & !((mask >> 8) as u8)) | (value >> 8) as u8;
+    }
+}
+
+/// One sparse opcode: a run of `len` registers holding `value`, and
+/// whether it was spelled as XZERO.
+struct Run {
+    value: u8,
+    len: usize,
+    xzero: bool,
+}
+
+/// The opcodes of a sparse payload, None once one is malformed.
+fn sparse_runs(payload: &[u8]) -> impl Iterator<Item = Option<Run>> + '_ {
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        let op = *payload.get(i)?;
+        i += 1;
+        Some(match op >> 6 {
+            0 => Some(Run {
+                value: 0,
+                len: usize::from(op & 0x3f) + 1,
+                xzero: false,
+            }),
+            1 => payload.get(i).map(|next| {
+                i += 1;
+                Run {
+                    value: 0,
+                    len: (usize::from(op & 0x3f) << 8 | usize::from(*next)) + 1,
+                    xzero: true,
+                }
+            }),
+            _ => Some(Run {
+                value: ((op >> 2) & 0x1f) + 1,
+                len: usize::from(op & 0x3) + 1,
+                xzero: false,
+            }),
+        })
+    })
+}
+
+/// Registers as one byte each, None for a corrupted HLL.
+pub fn registers(bytes: &[u8]) -> Option<Vec<u8>> {
+    let payload = &bytes[HEADER_SIZE..];
+    match encoding(bytes)? {
+        Encoding::Dense => Some(
+            (0..REGISTERS)
+                .map(|index| dense_register(payload, index))
+                .collect(),
+        ),
+        Encoding::Sparse => {
+            let mut registers = Vec::with_capacity(REGISTERS);
+            for run in sparse_runs(payload) {
+                let run = run?;
+                if registers.len() + run.len > REGISTERS {
+                    return None;
+                }
+                registers.resize(registers.len() + run.len, run.value);
+            }
+            (registers.len() == REGISTERS).then_some(registers)
+        }
+    }
+}
+
+/// Sparse opcodes for `registers`, None if a value is too big for them.
+fn encode_runs(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        let mut len = registers[i..].iter().take_while(|v| **v == value).count();
+        i += len;
+        if value > SPARSE_VAL_MAX_VALUE {
+            return None;
+        }
+        while len > 0 {
+            let chunk = if value > 0 {
+                let chunk = len.min(SPARSE_VAL_MAX_LEN);
+                out.push(0x80 | (value - 1) << 2 | (chunk - 1) as u8);
+                chunk
+            } else if len > SPARSE_ZERO_MAX_LEN {
+                let chunk = len.min(SPARSE_XZERO_MAX_LEN);
+                out.push(0x40 | ((chunk - 1) >> 8) as u8);
+                out.push((chunk - 1) as u8);
+                chunk
+            } else {
+                out.push((len - 1) as u8);
+                len
+            };
+            len -= chunk;
+        }
+    }
+    Some(out)
+}
+
+/// An HLL holding `registers`, sparse when allowed and it fits in
+/// `sparse_max_bytes`, with its cached count marked stale.
+pub fn encode(registers: &[u8], allow_sparse: bool, sparse_max_bytes: usize) -> Vec<u8> {
+    if allow_sparse
+        && let Some(runs) = encode_runs(registers)
+        && HEADER_SIZE + runs.len() <= sparse_max_bytes
+    {
+        let mut bytes = header(SPARSE);
+        bytes.extend(runs);
+        invalidate_count(&mut bytes);
+        return bytes;
+    }
+    let mut bytes = header(DENSE);
+    bytes.resize(DENSE_SIZE, 0);
+    for (index, value) in registers.iter().enumerate() {
+        set_dense_register(&mut bytes[HEADER_SIZE..], index, *value);
+    }
+    invalidate_count(&mut bytes);
+    bytes
+}
+
+/// A sparse HLL rewritten dense, keeping its cached count. None if it is
+/// corrupted.
+pub fn to_dense(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut dense = encode(&registers(bytes)?, false, 0);
+    dense[8..HEADER_SIZE].copy_from_slice(&bytes[8..HEADER_SIZE]);
+    Some(dense)
+}
+
+/// Adds elements to an HLL, returning whether any register grew, or None
+/// if it is corrupted. Dense HLLs change in place; sparse ones are
+/// rebuilt, and turn dense once they no longer fit.
+pub fn add(bytes: &mut Vec<u8>, elements: &[&[u8]], sparse_max_bytes: usize) -> Option<bool> {
+    let mut changed = false;
+    if encoding(bytes)? == Encoding::Dense {
+        let payload = &mut bytes[HEADER_SIZE..];
+        for element in elements {
+            let (index, count) = pattern(element);
+            if count > dense_register(payload, index) {
+                set_dense_register(payload, index, count);
+                changed = true;
+            }
+        }
+    } else {
+        let mut regs = registers(bytes)?;
+        for element in elements {
+            let (index, count) = pattern(element);
+            if count > regs[index] {
+                regs[index] = count;
+                changed = true;
+            }
+        }
+        if changed {
+            *bytes = encode(&regs, true, sparse_max_bytes);
+        }
+    }
+    if changed {
+        invalidate_count(bytes);
+    }
+    Some(changed)
+}
+
+/// Raises each of `into`'s registers to `from`'s where that is larger.
+pub fn merge(into: &mut [u8], from: &[u8]) {
+    for (a, b) in into.iter_mut().zip(from) {
+        *a = (*a).max(*b);
+    }
+}
+
+/// The cardinality estimate for `registers`, with Otmar Ertl's improved
+/// estimator as Redis uses it.
+pub fn count(registers: &[u8]) -> u64 {
+    let mut histogram = [0u32; 64];
+    for value in registers {
+        histogram[usize::from(*value & REGISTER_MAX)] += 1;
+    }
+    let m = REGISTERS as f64;
+    let q = Q as usize;
+    let mut z = m * tau((m - f64::from(histogram[q + 1])) / m);
+    for j in (1..=q).rev() {
+        z += f64::from(histogram[j]);
+        z *= 0.5;
+    }
+    z += m * sigma(f64::from(histogram[0]) / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+/// PFDEBUG DECODE's rendering of a sparse HLL's opcodes, None if it is
+/// corrupted.
+pub fn describe_sparse(bytes: &[u8]) -> Option<String> {
+    let mut parts = Vec::new();
+    for run in sparse_runs(&bytes[HEADER_SIZE..]) {
+        let run = run?;
+        parts.push(match (run.value, run.xzero) {
+            (0, true) => format!("Z:{}", run.len),
+            (0, false) => format!("z:{}", run.len),
+            (value, _) => format!("v:{},{}", value, run.len),
+        });
+    }
+    Some(parts.join(" "))
+}
+
+/// PFSELFTEST: dense registers must read back what was written, and
+/// estimates must stay within the expected error, the same from either
+/// encoding.
+pub fn self_test() -> Result<(), String> {
+    let mut dense = vec![0u8; DENSE_SIZE - HEADER_SIZE];
+    let expected: Vec<u8> = (0..REGISTERS)
+        .map(|i| (murmurhash64a(&(i as u64).to_le_bytes(), 0) % 64) as u8)
+        .collect();
+    for (index, value) in expected.iter().enumerate() {
+        set_dense_register(&mut dense, index, *value);
+    }
+    for (index, value) in expected.iter().enumerate() {
+        if dense_register(&dense, index) != *value {
+            return Err(format!("Register error at index {}", index));
+        }
+    }
+
+    let mut hll = new_sparse();
+    let relative_error = 1.04 / (REGISTERS as f64).sqrt();
+    let mut checkpoint = 10;
+    for i in 1..=100_000u64 {
+        add(&mut hll, &[i.to_string().as_bytes()], SPARSE_MAX_BYTES)
+            .ok_or("Corrupted HLL while adding")?;
+        if i != checkpoint {
+            continue;
+        }
+        checkpoint *= 10;
+        let regs = registers(&hll).ok_or("Corrupted HLL while counting")?;
+        let estimate = count(&regs);
+        let dense_estimate = count(&registers(&encode(&regs, false, 0)).ok_or("Bad dense HLL")?);
+        if estimate != dense_estimate {
+            return Err(format!(
+                "Sparse and dense estimates differ at {}: {} vs {}",
+                i, estimate, dense_estimate
+            ));
+        }
+        // Five standard errors, and a little slack for tiny counts
+        let tolerance = (i as f64 * relative_error * 5.0).max(1.0);
+        if (estimate as f64 - i as f64).abs() > tolerance {
+            return Err(format!(
+                "Approximation error for {} elements is {}, expected at most {}",
+                i,
+                estimate as f64 - i as f64,
+                tolerance
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pattern() {
+        // About half the hashes have no trailing zero past the index bits
+        let mut ones = 0;
+        for i in 0..10_000 {
+            let (index, count) = pattern(format!("{}", i).as_bytes());
+            assert!(index < REGISTERS);
+            assert!((1..=Q as u8 + 1).contains(&count));
+            ones += usize::from(count == 1);
+        }
+        assert!((4_500..5_500).contains(&ones), "{}", ones);
+        assert_eq!(pattern(b"same"), pattern(b"same"));
+    }
+
+    #[test]
+    fn test_new_sparse_layout() {
+        let hll = new_sparse();
+        assert_eq!(
+            hll,
+            b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"
+        );
+        assert_eq!(encoding(&hll), Some(Encoding::Sparse));
+        assert_eq!(cached_count(&hll), Some(0));
+        assert_eq!(describe_sparse(&hll).unwrap(), "Z:16384");
+        assert_eq!(count(&registers(&hll).unwrap()), 0);
+    }
+
+    #[test]
+    fn test_sparse_runs_round_trip() {
+        let mut regs = vec![0u8; REGISTERS];
+        regs[0] = 3;
+        regs[1] = 3;
+        regs[70] = 32;
+        regs[REGISTERS - 1] = 1;
+        let hll = encode(&regs, true, SPARSE_MAX_BYTES);
+        assert_eq!(encoding(&hll), Some(Encoding::Sparse));
+        assert_eq!(cached_count(&hll), None);
+        assert_eq!(registers(&hll).unwrap(), regs);
+        assert_eq!(
+            describe_sparse(&hll).unwrap(),
+            "v:3,2 Z:68 v:32,1 Z:16312 v:1,1"
+        );
+
+        // Too big a value for sparse, or too many bytes, goes dense
+        regs[5] = 33;
+        let hll = encode(&regs, true, SPARSE_MAX_BYTES);
+        assert_eq!(encoding(&hll), Some(Encoding::Dense));
+        assert_eq!(hll.len(), DENSE_SIZE);
+        assert_eq!(registers(&hll).unwrap(), regs);
+        assert_eq!(
+            encoding(&encode(&[0; REGISTERS], true, 17)),
+            Some(Encoding::Dense)
+        );
+    }
+
+    #[test]
+    fn test_add_and_count() {
+        let mut hll = new_sparse();
+        assert_eq!(
+            add(&mut hll, &[b"a", b"b", b"c"], SPARSE_MAX_BYTES),
+            Some(true)
+        );
+        assert_eq!(add(&mut hll, &[b"a"], SPARSE_MAX_BYTES), Some(false));
+        assert_eq!(count(&registers(&hll).unwrap()), 3);
+
+        let elements: Vec<String> = (0..5000).map(|i| format!("element:{}", i)).collect();
+        let elements: Vec<&[u8]> = elements.iter().map(|e| e.as_bytes()).collect();
+        add(&mut hll, &elements, SPARSE_MAX_BYTES).unwrap();
+        assert_eq!(encoding(&hll), Some(Encoding::Dense));
+        let estimate = count(&registers(&hll).unwrap()) as f64;
+        assert!((estimate - 5003.0).abs() < 5003.0 * 0.05, "{}", estimate);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        assert_eq!(encoding(b"not an hll"), None);
+        let mut hll = new_sparse();
+        hll.pop();
+        assert_eq!(registers(&hll), None);
+        let mut dense = encode(&[1; REGISTERS], false, 0);
+        dense.push(0);
+        assert_eq!(encoding(&dense), None);
+    }
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(self_test(), Ok(()));
+    }
+}
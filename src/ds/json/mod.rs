@@ -0,0 +1,516 @@
+//! json -- JSON documents addressed by JSONPath
+//!
+//! Documents are kept parsed, so a command that changes one field walks to
+//! it rather than reading and writing the whole document. Object members
+//! keep their insertion order, like RedisJSON's.
+//!
+//! Paths are a subset of JSONPath: `$`, `.name` and `['name']`, `[index]`
+//! counting back from the end when negative, `[start:end]` slices, `*`
+//! wildcards and `..` recursive descent. Paths without the leading `$`
+//! are RedisJSON's legacy paths, which read the first match rather than
+//! all of them.
+
+mod path;
+
+use std::fmt::Write;
+use std::mem::size_of;
+
+pub use self::path::{Path, Step};
+
+/// Documents nest at most this deep, like RedisJSON's default.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Parses a JSON text.
+    pub fn parse(input: &str) -> Result<Value, String> {
+        let mut parser = Parser {
+            input: input.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        match parser.pos == parser.input.len() {
+            true => Ok(value),
+            false => Err(parser.error("trailing characters")),
+        }
+    }
+
+    /// The name JSON.TYPE reports.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    pub fn member(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn member_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match self {
+            Value::Object(members) => members.iter_mut().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn child(&self, step: &Step) -> Option<&Value> {
+        match (self, step) {
+            (Value::Array(items), Step::Index(i)) => items.get(*i),
+            (_, Step::Key(key)) => self.member(key),
+            _ => None,
+        }
+    }
+
+    fn child_mut(&mut self, step: &Step) -> Option<&mut Value> {
+        match (self, step) {
+            (Value::Array(items), Step::Index(i)) => items.get_mut(*i),
+            (value, Step::Key(key)) => value.member_mut(key),
+            _ => None,
+        }
+    }
+
+    /// Serializes compactly, as JSON.GET does without formatting options.
+    pub fn to_json(&self) -> String {
+        self.format(&Format::default())
+    }
+
+    pub fn format(&self, format: &Format) -> String {
+        let mut out = String::new();
+        self.write(&mut out, format, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, format: &Format, depth: usize) {
+        let newline = |out: &mut String, depth: usize| {
+            out.push_str(&format.newline);
+            for _ in 0..depth {
+                out.push_str(&format.indent);
+            }
+        };
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Integer(n) => {
+                let _ = write!(out, "{}", n);
+            }
+            // Floats keep a fraction or an exponent, so they read back as
+            // floats
+            Value::Float(f) if f.fract() == 0.0 && f.abs() < 1e17 => {
+                let _ = write!(out, "{:.1}", f);
+            }
+            Value::Float(f) if f.abs() >= 1e17 => {
+                let _ = write!(out, "{:e}", f);
+            }
+            Value::Float(f) => {
+                let _ = write!(out, "{}", f);
+            }
+            Value::String(s) => write_string(out, s),
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    item.write(out, format, depth + 1);
+                }
+                newline(out, depth);
+                out.push(']');
+            }
+            Value::Object(members) if members.is_empty() => out.push_str("{}"),
+            Value::Object(members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    write_string(out, key);
+                    out.push(':');
+                    out.push_str(&format.space);
+                    value.write(out, format, depth + 1);
+                }
+                newline(out, depth);
+                out.push('}');
+            }
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Value>()
+            + match self {
+                Value::String(s) => s.len(),
+                Value::Array(items) => items.iter().map(Value::memory_usage).sum(),
+                Value::Object(members) => members
+                    .iter()
+                    .map(|(key, value)| size_of::<String>() + key.len() + value.memory_usage())
+                    .sum(),
+                _ => 0,
+            }
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// JSON.GET's INDENT, NEWLINE and SPACE, all empty for compact output.
+#[derive(Debug, Default, Clone)]
+pub struct Format {
+    pub indent: String,
+    pub newline: String,
+    pub space: String,
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at offset {}", message, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.input.get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        match self.input[self.pos..].starts_with(word.as_bytes()) {
+            true => {
+                self.pos += word.len();
+                Ok(value)
+            }
+            false => Err(self.error("expected value")),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        self.skip_whitespace();
+        match self.input.get(self.pos) {
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat(b']') {
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    if self.eat(b']') {
+                        return Ok(Value::Array(items));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.error("expected ',' or ']'"));
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members: Vec<(String, Value)> = Vec::new();
+                if self.eat(b'}') {
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.input.get(self.pos) != Some(&b'"') {
+                        return Err(self.error("expected a member name"));
+                    }
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return Err(self.error("expected ':'"));
+                    }
+                    let value = self.value(depth + 1)?;
+                    // A repeated name replaces the earlier value
+                    match members.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, existing)) => *existing = value,
+                        None => members.push((key, value)),
+                    }
+                    if self.eat(b'}') {
+                        return Ok(Value::Object(members));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.error("expected ',' or '}'"));
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(self.error("expected value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let mut float = false;
+        while let Some(b) = self.input.get(self.pos) {
+            match b {
+                b'0'..=b'9' | b'-' | b'+' => (),
+                b'.' | b'e' | b'E' => float = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.input[start..self.pos]).expect("ASCII digits");
+        if !float && let Ok(n) = text.parse::<i64>() {
+            return Ok(Value::Integer(n));
+        }
+        match text.parse::<f64>() {
+            Ok(f) if f.is_finite() && !text.ends_with('.') => Ok(Value::Float(f)),
+            _ => Err(self.error("invalid number")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(&b) = self.input.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.input.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b if b < 0x20 => return Err(self.error("control character in string")),
+                b => out.push(b),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.input[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+}
+
+/// A stored document, which keeps count of the memory it uses as parts of
+/// it change.
+#[derive(Debug)]
+pub struct Document {
+    root: Value,
+    memory: usize,
+}
+
+impl Document {
+    pub fn new(root: Value) -> Self {
+        let memory = root.memory_usage();
+        Document { root, memory }
+    }
+
+    pub fn root(&self) -> &Value {
+        &self.root
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.memory
+    }
+
+    /// The locations `path` matches, in document order.
+    pub fn select(&self, path: &Path) -> Vec<Vec<Step>> {
+        path.select(&self.root)
+    }
+
+    pub fn get(&self, steps: &[Step]) -> Option<&Value> {
+        steps
+            .iter()
+            .try_fold(&self.root, |value, step| value.child(step))
+    }
+
+    /// Runs `f` on the value at `steps`. None if there is nothing there.
+    pub fn update<T>(&mut self, steps: &[Step], f: impl FnOnce(&mut Value) -> T) -> Option<T> {
+        let value = steps
+            .iter()
+            .try_fold(&mut self.root, |value, step| value.child_mut(step))?;
+        let before = value.memory_usage();
+        let result = f(value);
+        self.memory = (self.memory + value.memory_usage()).saturating_sub(before);
+        Some(result)
+    }
+
+    /// Adds `key` to the object at `steps`, if that is an object without it.
+    pub fn insert_member(&mut self, steps: &[Step], key: &str, value: Value) -> bool {
+        self.update(steps, |parent| match parent {
+            Value::Object(members) if !members.iter().any(|(k, _)| k == key) => {
+                members.push((key.to_string(), value));
+                true
+            }
+            _ => false,
+        })
+        .unwrap_or(false)
+    }
+
+    /// Removes the value at `steps` from its parent. The root can't be
+    /// removed this way.
+    pub fn remove(&mut self, steps: &[Step]) -> Option<Value> {
+        let (last, parent) = steps.split_last()?;
+        self.update(parent, |parent| match (parent, last) {
+            (Value::Array(items), Step::Index(i)) if *i < items.len() => Some(items.remove(*i)),
+            (Value::Object(members), Step::Key(key)) => {
+                let i = members.iter().position(|(k, _)| k == key)?;
+                Some(members.remove(i).1)
+            }
+            _ => None,
+        })
+        .flatten()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_serialize() {
+        let text = r#"{"a":[1,2.5,-3e2,true,null],"b":{"c":"x\"\n\u00e9\ud83d\ude00"},"d":{}}"#;
+        let value = Value::parse(text).unwrap();
+        assert_eq!(
+            value.to_json(),
+            "{\"a\":[1,2.5,-300.0,true,null],\"b\":{\"c\":\"x\\\"\\né😀\"},\"d\":{}}"
+        );
+        assert_eq!(Value::parse(&value.to_json()).unwrap(), value);
+        assert_eq!(value.member("a").unwrap().type_name(), "array");
+
+        for bad in ["", "{", "[1,]", "{\"a\" 1}", "01x", "\"\\x\"", "1 2", "tru"] {
+            assert!(Value::parse(bad).is_err(), "{}", bad);
+        }
+        let deep = "[".repeat(MAX_DEPTH + 2) + &"]".repeat(MAX_DEPTH + 2);
+        assert!(Value::parse(&deep).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let value = Value::parse(r#"{"a":[1],"b":{}}"#).unwrap();
+        let format = Format {
+            indent: "  ".to_string(),
+            newline: "\n".to_string(),
+            space: " ".to_string(),
+        };
+        assert_eq!(
+            value.format(&format),
+            "{\n  \"a\": [\n    1\n  ],\n  \"b\": {}\n}"
+        );
+    }
+
+    #[test]
+    fn test_document_updates_track_memory() {
+        let mut doc = Document::new(Value::parse(r#"{"a":{"b":"x"},"c":[1,2]}"#).unwrap());
+        let steps = [Step::Key("a".to_string()), Step::Key("b".to_string())];
+        doc.update(&steps, |v| {
+            *v = Value::String("a longer string".to_string())
+        });
+        assert_eq!(doc.memory_usage(), doc.root().memory_usage());
+        assert!(doc.insert_member(&[Step::Key("a".to_string())], "n", Value::Integer(1)));
+        assert!(!doc.insert_member(&[Step::Key("a".to_string())], "n", Value::Integer(2)));
+        assert_eq!(
+            doc.remove(&[Step::Key("c".to_string()), Step::Index(0)]),
+            Some(Value::Integer(1))
+        );
+        assert_eq!(doc.memory_usage(), doc.root().memory_usage());
+        assert_eq!(
+            doc.root().to_json(),
+            r#"{"a":{"b":"a longer string","n":1},"c":[2]}"#
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use super::Value;
+
+/// One step from a value to a child, as found by a path.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Key(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>),
+    Wildcard,
+    /// The value itself and everything below it, for `..`
+    Descendants,
+}
+
+/// A parsed JSONPath, or a legacy path rewritten as one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    selectors: Vec<Selector>,
+    legacy: bool,
+}
+
+impl Path {
+    pub fn parse(input: &str) -> Result<Path, String> {
+        let legacy = !input.starts_with('$');
+        let rest = match input {
+            "." | "" => "",
+            _ if !legacy => &input[1..],
+            _ if input.starts_with(['.', '[']) => input,
+            _ => return Path::parse_selectors(&format!(".{}", input), true),
+        };
+        Path::parse_selectors(rest, legacy)
+    }
+
+    fn parse_selectors(rest: &str, legacy: bool) -> Result<Path, String> {
+        let invalid = || format!("invalid JSONPath '{}'", rest);
+        let mut selectors = Vec::new();
+        let mut chars = rest;
+        while !chars.is_empty() {
+            if let Some(after) = chars.strip_prefix("..") {
+                selectors.push(Selector::Descendants);
+                chars = after;
+                if chars.is_empty() {
+                    return Err(invalid());
+                }
+                if chars.starts_with('[') {
+                    continue;
+                }
+            } else if let Some(after) = chars.strip_prefix('.') {
+                chars = after;
+            } else if !chars.starts_with('[') {
+                return Err(invalid());
+            }
+
+            if let Some(after) = chars.strip_prefix('[') {
+                let end = bracket_end(after).ok_or_else(invalid)?;
+                selectors.push(parse_bracket(&after[..end]).ok_or_else(invalid)?);
+                chars = &after[end + 1..];
+            } else if let Some(after) = chars.strip_prefix('*') {
+                selectors.push(Selector::Wildcard);
+                chars = after;
+            } else {
+                let end = chars.find(['.', '[']).unwrap_or(chars.len());
+                if end == 0 {
+                    return Err(invalid());
+                }
+                selectors.push(Selector::Key(chars[..end].to_string()));
+                chars = &chars[end..];
+            }
+        }
+        Ok(Path { selectors, legacy })
+    }
+
+    /// Whether this was written as a legacy path, without the `$`.
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.selectors.is_empty()
+    }
+
+    /// The path without its last selector, and that selector's member name
+    /// if it names one: where JSON.SET adds a member that isn't there yet.
+    pub fn split_new_member(&self) -> Option<(Path, &str)> {
+        let (last, parent) = self.selectors.split_last()?;
+        let Selector::Key(key) = last else {
+            return None;
+        };
+        let parent = Path {
+            selectors: parent.to_vec(),
+            legacy: self.legacy,
+        };
+        Some((parent, key))
+    }
+
+    /// The locations this path matches in `root`, in document order.
+    pub fn select(&self, root: &Value) -> Vec<Vec<Step>> {
+        let mut matches = vec![Vec::new()];
+        for selector in &self.selectors {
+            let mut next = Vec::new();
+            for steps in matches {
+                let value = steps
+                    .iter()
+                    .try_fold(root, |value, step| value.child(step))
+                    .expect("matched steps resolve");
+                select(selector, value, steps, &mut next);
+            }
+            matches = next;
+        }
+        matches
+    }
+}
+
+fn select(selector: &Selector, value: &Value, steps: Vec<Step>, out: &mut Vec<Vec<Step>>) {
+    let child = |step: Step| {
+        let mut steps = steps.clone();
+        steps.push(step);
+        steps
+    };
+    match (selector, value) {
+        (Selector::Key(key), Value::Object(_)) if value.member(key).is_some() => {
+            out.push(child(Step::Key(key.clone())));
+        }
+        (Selector::Index(i), Value::Array(items)) => {
+            let len = items.len() as i64;
+            let i = if *i < 0 { len + i } else { *i };
+            if (0..len).contains(&i) {
+                out.push(child(Step::Index(i as usize)));
+            }
+        }
+        (Selector::Slice(start, end), Value::Array(items)) => {
+            let len = items.len() as i64;
+            let bound = |i: i64| if i < 0 { (len + i).max(0) } else { i.min(len) };
+            let start = start.map_or(0, bound);
+            let end = end.map_or(len, bound);
+            for i in start..end {
+                out.push(child(Step::Index(i as usize)));
+            }
+        }
+        (Selector::Wildcard, Value::Array(items)) => {
+            out.extend((0..items.len()).map(|i| child(Step::Index(i))));
+        }
+        (Selector::Wildcard, Value::Object(members)) => {
+            out.extend(members.iter().map(|(key, _)| child(Step::Key(key.clone()))));
+        }
+        (Selector::Descendants, _) => {
+            let mut stack = vec![(steps.clone(), value)];
+            while let Some((steps, value)) = stack.pop() {
+                let children: Vec<(Step, &Value)> = match value {
+                    Value::Array(items) => items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| (Step::Index(i), v))
+                        .collect(),
+                    Value::Object(members) => members
+                        .iter()
+                        .map(|(k, v)| (Step::Key(k.clone()), v))
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                for (step, child) in children.into_iter().rev() {
+                    let mut child_steps = steps.clone();
+                    child_steps.push(step);
+                    stack.push((child_steps, child));
+                }
+                out.push(steps);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Where the `]` closing a bracket is, skipping any inside quotes.
+fn bracket_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(_), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ']') => return Some(i),
+            _ => (),
+        }
+        escaped = false;
+    }
+    None
+}
+
+fn parse_bracket(inside: &str) -> Option<Selector> {
+    let inside = inside.trim();
+    if inside == "*" {
+        return Some(Selector::Wildcard);
+    }
+    if let Some(quote @ ('\'' | '"')) = inside.chars().next() {
+        let name = inside.strip_prefix(quote)?.strip_suffix(quote)?;
+        let mut key = String::new();
+        let mut chars = name.chars();
+        while let Some(c) = chars.next() {
+            key.push(if c == '\\' { chars.next()? } else { c });
+        }
+        return Some(Selector::Key(key));
+    }
+    if let Some((start, end)) = inside.split_once(':') {
+        let bound = |s: &str| match s.trim() {
+            "" => Some(None),
+            s => s.parse().ok().map(Some),
+        };
+        return Some(Selector::Slice(bound(start)?, bound(end)?));
+    }
+    inside.parse().ok().map(Selector::Index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(path: &str, json: &str) -> Vec<String> {
+        let root = Value::parse(json).unwrap();
+        let path = Path::parse(path).unwrap();
+        path.select(&root)
+            .iter()
+            .map(|steps| {
+                steps
+                    .iter()
+                    .try_fold(&root, |value, step| value.child(step))
+                    .unwrap()
+                    .to_json()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_selectors() {
+        let doc = r#"{"a":{"b":[1,2,3],"c":{"b":4}},"d e":5}"#;
+        assert_eq!(matches("$", doc), [doc]);
+        assert_eq!(matches(".", doc), [doc]);
+        assert_eq!(matches("$.a.b[0]", doc), ["1"]);
+        assert_eq!(matches("a.b[-1]", doc), ["3"]);
+        assert_eq!(matches("$.a.b[1:]", doc), ["2", "3"]);
+        assert_eq!(matches("$.a.b[:-2]", doc), ["1"]);
+        assert_eq!(matches("$['d e']", doc), ["5"]);
+        assert_eq!(matches("$.a.*", doc), [r#"[1,2,3]"#, r#"{"b":4}"#]);
+        assert_eq!(matches("$..b", doc), [r#"[1,2,3]"#, "4"]);
+        assert_eq!(matches("$..b[0]", doc), ["1"]);
+        assert!(matches("$.missing", doc).is_empty());
+        assert!(matches("$.a.b.c", doc).is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(Path::parse("$.a").is_ok_and(|p| !p.is_legacy()));
+        assert!(Path::parse(".a").is_ok_and(|p| p.is_legacy()));
+        assert!(Path::parse("$").is_ok_and(|p| p.is_root()));
+        for bad in ["$a", "$[", "$.a[x]", "$['a]", "$.a..", "$."] {
+            assert!(Path::parse(bad).is_err(), "{}", bad);
+        }
+        let path = Path::parse("$.a.b").unwrap();
+        let (parent, key) = path.split_new_member().unwrap();
+        assert_eq!((parent, key), (Path::parse("$.a").unwrap(), "b"));
+    }
+}
@@ -1,9 +1,11 @@
 use crate::ds::list::Deque;
 
-/// An array-based deque implementation with fixed capacity.
-/// Meant to be used as part of hybrid data structures.
+// An array-based deque implementation with fixed capacity.
+// Meant to be used as part of hybrid data structures.
 
 const PAGE_SIZE: usize = 4096;
+/// Maximum number of elements a single deque can hold.
+pub const CAPACITY: usize = PAGE_SIZE - 1;
 
 /// Classic array deque implementation
 ///
@@ -39,7 +41,11 @@ impl<T> ArrayDeque<T> {
     }
 
     pub fn is_full(&self) -> bool {
-        self.len() == PAGE_SIZE - 1
+        self.len() == CAPACITY
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len()).map(move |i| self.values[wrapping_add!(self.l, i)].as_ref().unwrap())
     }
 }
 
@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use std::fmt::Debug;
+use std::marker::PhantomData;
 use std::ptr;
 
 use crate::ds::list::Deque;
@@ -23,7 +24,7 @@ pub struct DoublyLinkedList<T> {
 //   there can't be cross-thread aliasing of those Rcs.
 unsafe impl<T> Send for DoublyLinkedList<T> where T: Send {}
 
-/**
+/*
  * These macros take advantage of the symmetry of doubly linked lists.
  * These functions are implemented from the perspective of left operations.
  */
@@ -77,6 +78,33 @@ impl<T> DoublyLinkedList<T> {
             len: 0,
         }
     }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Left-to-right iterator. Borrows the list, so no node can be freed
+/// while the iterator is alive.
+pub struct Iter<'a, T> {
+    next: Link<T>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        unsafe {
+            let node = self.next.as_ref()?;
+            // Walking away from the head follows `left`, see push_impl
+            self.next = node.left;
+            Some(&node.value)
+        }
+    }
 }
 
 impl<T> Deque<T> for DoublyLinkedList<T> {
@@ -125,6 +153,6 @@ impl<T> Debug for DoublyLinkedList<T> {
 
 impl<T> Drop for DoublyLinkedList<T> {
     fn drop(&mut self) {
-        while let Some(_) = self.rpop() {}
+        while self.rpop().is_some() {}
     }
 }
@@ -23,6 +23,7 @@ pub trait Deque<T> {
 pub use self::quicklist::Quicklist as List;
 
 #[cfg(test)]
+#[allow(clippy::needless_late_init)]
 mod test {
     use super::*;
 
@@ -136,6 +137,27 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_iter() {
+        let mut list: List<i32> = List::new();
+        assert_eq!(list.iter().count(), 0);
+        for n in 1..=10_000 {
+            if n % 2 == 0 {
+                list.rpush(n);
+            } else {
+                list.lpush(n);
+            }
+        }
+        let values: Vec<i32> = list.iter().copied().collect();
+        let expected: Vec<i32> = (1..=10_000)
+            .rev()
+            .filter(|n| n % 2 == 1)
+            .chain((1..=10_000).filter(|n| n % 2 == 0))
+            .collect();
+        assert_eq!(values, expected);
+        assert!(list.pages() > 1);
+    }
+
     /// Tests a large amount of ops that will exceed
     /// page size of hybrid implementations
     #[test]
@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 
 use crate::ds::list::Deque;
-use crate::ds::list::array::ArrayDeque;
+use crate::ds::list::array::{self, ArrayDeque};
 use crate::ds::list::dll::DoublyLinkedList;
 
 /// quicklist -- fast dequeue data structure implementation
@@ -10,19 +10,38 @@ use crate::ds::list::dll::DoublyLinkedList;
 /// the same cache-locality optimization technique as a B-tree.
 /// I thought of this approach all on my own but then I googled
 /// it, it turns out Redis already uses it. Such is life.
-
 pub struct Quicklist<T> {
     inner: DoublyLinkedList<ArrayDeque<T>>,
     len: usize,
 }
 
+impl<T> Default for Quicklist<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Quicklist<T> {
+    /// Elements that fit in a single page.
+    pub const PAGE_CAPACITY: usize = array::CAPACITY;
+    /// Bytes allocated for every page, regardless of how full it is.
+    pub const PAGE_BYTES: usize = size_of::<ArrayDeque<T>>() + 2 * size_of::<usize>();
+
     pub fn new() -> Self {
         Self {
             inner: DoublyLinkedList::new(),
             len: 0,
         }
     }
+
+    /// Number of pages currently allocated.
+    pub fn pages(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.inner.iter().flat_map(|page| page.iter())
+    }
 }
 
 impl<T> Deque<T> for Quicklist<T> {
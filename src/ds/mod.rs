@@ -1 +1,16 @@
+pub mod bitmap;
+pub mod bloom;
+pub mod codec;
+pub mod countmin;
+pub mod cuckoo;
+pub mod dict;
+pub mod geo;
+pub mod hyperloglog;
+pub mod json;
 pub mod list;
+pub mod search;
+pub mod sortedset;
+pub mod stream;
+pub mod timeseries;
+pub mod topk;
+pub mod vectorset;
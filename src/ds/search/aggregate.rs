@@ -0,0 +1,517 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// A value in an aggregation row. Values are strings, compared and added
+/// up as numbers when they are numbers; TOLIST makes lists.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Cell {
+    Value(String),
+    List(Vec<String>),
+}
+
+impl Cell {
+    fn number(&self) -> Option<f64> {
+        match self {
+            Cell::Value(s) => s.parse::<f64>().ok().filter(|n| !n.is_nan()),
+            Cell::List(_) => None,
+        }
+    }
+}
+
+/// Properties by name, without the `@`, in the order they were made.
+pub type Row = Vec<(String, Cell)>;
+
+fn get<'a>(row: &'a Row, property: &str) -> Option<&'a Cell> {
+    row.iter()
+        .find(|(name, _)| name == property)
+        .map(|(_, cell)| cell)
+}
+
+/// Which document fields rows start with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Load {
+    All,
+    Fields(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reducer {
+    Count,
+    CountDistinct,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    ToList,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Reduce {
+    reducer: Reducer,
+    /// None for COUNT
+    property: Option<String>,
+    alias: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    GroupBy {
+        properties: Vec<String>,
+        reducers: Vec<Reduce>,
+    },
+    SortBy {
+        /// Properties with whether they sort descending
+        keys: Vec<(String, bool)>,
+        max: Option<usize>,
+    },
+    Limit {
+        offset: usize,
+        count: usize,
+    },
+}
+
+/// What FT.AGGREGATE does with the matching documents, step by step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    load: Option<Load>,
+    steps: Vec<Step>,
+}
+
+/// `@name` as `name`.
+fn property(arg: &str, step: &str) -> Result<String, String> {
+    match arg.strip_prefix('@') {
+        Some(name) if !name.is_empty() => Ok(name.to_string()),
+        _ => Err(format!(
+            "Bad arguments for {}: Unknown property `{}`",
+            step, arg
+        )),
+    }
+}
+
+/// `nargs arg...` starting at `args[i]`.
+fn counted<'a>(args: &'a [String], i: usize, step: &str) -> Result<&'a [String], String> {
+    let bad = || format!("Bad arguments for {}", step);
+    let count: usize = args.get(i).and_then(|n| n.parse().ok()).ok_or_else(bad)?;
+    args.get(i + 1..i + 1 + count).ok_or_else(bad)
+}
+
+fn number(args: &[String], i: usize, step: &str) -> Result<usize, String> {
+    args.get(i)
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| format!("Bad arguments for {}", step))
+}
+
+impl Pipeline {
+    /// Parses FT.AGGREGATE's arguments after the query: LOAD, then any
+    /// number of GROUPBY with REDUCE, SORTBY and LIMIT in the order they
+    /// run. DIALECT, TIMEOUT and VERBATIM are accepted and ignored.
+    pub fn parse(args: &[String]) -> Result<Pipeline, String> {
+        let mut pipeline = Pipeline {
+            load: None,
+            steps: Vec::new(),
+        };
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].to_uppercase().as_str() {
+                "LOAD" if args.get(i + 1).is_some_and(|arg| arg == "*") => {
+                    pipeline.load = Some(Load::All);
+                    i += 1;
+                }
+                "LOAD" => {
+                    let fields = counted(args, i + 1, "LOAD")?;
+                    let fields = fields
+                        .iter()
+                        .map(|field| field.strip_prefix('@').unwrap_or(field).to_string())
+                        .collect::<Vec<String>>();
+                    i += 1 + fields.len();
+                    pipeline.load = Some(Load::Fields(fields));
+                }
+                "GROUPBY" => {
+                    let properties = counted(args, i + 1, "GROUPBY")?
+                        .iter()
+                        .map(|arg| property(arg, "GROUPBY"))
+                        .collect::<Result<Vec<String>, String>>()?;
+                    i += 1 + properties.len();
+                    let mut reducers = Vec::new();
+                    while args
+                        .get(i + 1)
+                        .is_some_and(|arg| arg.eq_ignore_ascii_case("REDUCE"))
+                    {
+                        let (reduce, next) = Pipeline::parse_reduce(args, i + 2)?;
+                        reducers.push(reduce);
+                        i = next - 1;
+                    }
+                    pipeline.steps.push(Step::GroupBy {
+                        properties,
+                        reducers,
+                    });
+                }
+                "SORTBY" => {
+                    let sort = counted(args, i + 1, "SORTBY")?;
+                    i += 1 + sort.len();
+                    let mut keys: Vec<(String, bool)> = Vec::new();
+                    for arg in sort {
+                        match arg.to_uppercase().as_str() {
+                            "ASC" | "DESC" if !keys.is_empty() => {
+                                keys.last_mut().unwrap().1 = arg.eq_ignore_ascii_case("DESC");
+                            }
+                            _ => keys.push((property(arg, "SORTBY")?, false)),
+                        }
+                    }
+                    let mut max = None;
+                    if args
+                        .get(i + 1)
+                        .is_some_and(|arg| arg.eq_ignore_ascii_case("MAX"))
+                    {
+                        max = Some(number(args, i + 2, "SORTBY")?);
+                        i += 2;
+                    }
+                    pipeline.steps.push(Step::SortBy { keys, max });
+                }
+                "LIMIT" => {
+                    let offset = number(args, i + 1, "LIMIT")?;
+                    let count = number(args, i + 2, "LIMIT")?;
+                    pipeline.steps.push(Step::Limit { offset, count });
+                    i += 2;
+                }
+                "DIALECT" | "TIMEOUT" => {
+                    number(args, i + 1, &args[i].to_uppercase())?;
+                    i += 1;
+                }
+                "VERBATIM" => {}
+                _ => return Err(format!("Unknown argument `{}`", args[i])),
+            }
+            i += 1;
+        }
+        Ok(pipeline)
+    }
+
+    /// `function nargs arg... [AS name]` starting at `args[i]`, and where
+    /// the arguments after it begin.
+    fn parse_reduce(args: &[String], i: usize) -> Result<(Reduce, usize), String> {
+        let bad = || "Bad arguments for REDUCE".to_string();
+        let function = args.get(i).ok_or_else(bad)?.to_uppercase();
+        let reducer = match function.as_str() {
+            "COUNT" => Reducer::Count,
+            "COUNT_DISTINCT" => Reducer::CountDistinct,
+            "SUM" => Reducer::Sum,
+            "MIN" => Reducer::Min,
+            "MAX" => Reducer::Max,
+            "AVG" => Reducer::Avg,
+            "TOLIST" => Reducer::ToList,
+            _ => return Err(format!("Unknown reducer `{}`", args[i])),
+        };
+        let reducer_args = counted(args, i + 1, "REDUCE")?;
+        let property = match (reducer, reducer_args) {
+            (Reducer::Count, []) => None,
+            (Reducer::Count, _) => return Err(bad()),
+            (_, [arg]) => Some(property(arg, "REDUCE")?),
+            _ => return Err(bad()),
+        };
+        let mut next = i + 2 + reducer_args.len();
+        let alias = match args.get(next) {
+            Some(arg) if arg.eq_ignore_ascii_case("AS") => {
+                next += 2;
+                args.get(next - 1).ok_or_else(bad)?.clone()
+            }
+            // Like RediSearch's names for reductions without one
+            _ => format!(
+                "__generated_alias{}{}",
+                function.to_lowercase(),
+                property.as_deref().unwrap_or_default()
+            ),
+        };
+        let reduce = Reduce {
+            reducer,
+            property,
+            alias,
+        };
+        Ok((reduce, next))
+    }
+
+    /// The fields rows start with: those LOADed, and those the steps use
+    /// up to the first GROUPBY, after which rows are groups.
+    pub fn load(&self) -> Load {
+        let mut fields = match &self.load {
+            Some(Load::All) => return Load::All,
+            Some(Load::Fields(fields)) => fields.clone(),
+            None => Vec::new(),
+        };
+        let mut add = |name: &String| {
+            if !fields.contains(name) {
+                fields.push(name.clone());
+            }
+        };
+        for step in &self.steps {
+            match step {
+                Step::GroupBy {
+                    properties,
+                    reducers,
+                } => {
+                    properties.iter().for_each(&mut add);
+                    reducers.iter().flat_map(|r| &r.property).for_each(&mut add);
+                    break;
+                }
+                Step::SortBy { keys, .. } => keys.iter().for_each(|(name, _)| add(name)),
+                Step::Limit { .. } => {}
+            }
+        }
+        Load::Fields(fields)
+    }
+
+    /// Runs the steps over `rows`, returning how many rows there were
+    /// before the last LIMIT along with the rows left.
+    pub fn run(&self, mut rows: Vec<Row>) -> (usize, Vec<Row>) {
+        let mut total = rows.len();
+        for step in &self.steps {
+            match step {
+                Step::GroupBy {
+                    properties,
+                    reducers,
+                } => rows = group(rows, properties, reducers),
+                Step::SortBy { keys, max } => {
+                    rows.sort_by(|a, b| {
+                        keys.iter()
+                            .map(|(name, descending)| {
+                                compare(get(a, name), get(b, name), *descending)
+                            })
+                            .find(|ordering| ordering.is_ne())
+                            .unwrap_or(Ordering::Equal)
+                    });
+                    if let Some(max) = max {
+                        rows.truncate(*max);
+                    }
+                }
+                Step::Limit { offset, count } => {
+                    total = rows.len();
+                    rows = rows.into_iter().skip(*offset).take(*count).collect();
+                    continue;
+                }
+            }
+            total = rows.len();
+        }
+        (total, rows)
+    }
+}
+
+/// Numbers by value, anything else as strings, with rows missing the
+/// property last either way.
+fn compare(a: Option<&Cell>, b: Option<&Cell>, descending: bool) -> Ordering {
+    let ordering = match (a, b) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) => return Ordering::Greater,
+        (Some(_), None) => return Ordering::Less,
+        (Some(a), Some(b)) => match (a.number(), b.number()) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.cmp_text(b),
+        },
+    };
+    match descending {
+        true => ordering.reverse(),
+        false => ordering,
+    }
+}
+
+impl Cell {
+    fn cmp_text(&self, other: &Cell) -> Ordering {
+        match (self, other) {
+            (Cell::Value(a), Cell::Value(b)) => a.cmp(b),
+            (Cell::List(a), Cell::List(b)) => a.cmp(b),
+            (Cell::Value(_), Cell::List(_)) => Ordering::Less,
+            (Cell::List(_), Cell::Value(_)) => Ordering::Greater,
+        }
+    }
+}
+
+/// One row per distinct combination of `properties`, in the order they
+/// are first seen, with the reductions of the rows in each.
+fn group(rows: Vec<Row>, properties: &[String], reducers: &[Reduce]) -> Vec<Row> {
+    let mut groups: Vec<(Vec<Option<Cell>>, Vec<Row>)> = Vec::new();
+    let mut positions: HashMap<Vec<Option<Cell>>, usize> = HashMap::new();
+    for row in rows {
+        let key: Vec<Option<Cell>> = properties
+            .iter()
+            .map(|name| get(&row, name).cloned())
+            .collect();
+        let position = *positions.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(row);
+    }
+    groups
+        .into_iter()
+        .map(|(key, rows)| {
+            let mut row: Row = properties
+                .iter()
+                .zip(key)
+                .filter_map(|(name, cell)| Some((name.clone(), cell?)))
+                .collect();
+            for reduce in reducers {
+                if let Some(cell) = reduce.run(&rows) {
+                    row.push((reduce.alias.clone(), cell));
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+impl Reduce {
+    /// The reduction of a group's rows. None for numeric reductions of
+    /// groups with no numbers.
+    fn run(&self, rows: &[Row]) -> Option<Cell> {
+        let cells = || {
+            rows.iter()
+                .filter_map(|row| get(row, self.property.as_deref()?))
+        };
+        let numbers = || cells().filter_map(Cell::number);
+        let value = |n: f64| Some(Cell::Value(n.to_string()));
+        match self.reducer {
+            Reducer::Count => value(rows.len() as f64),
+            Reducer::CountDistinct => value(cells().collect::<HashSet<_>>().len() as f64),
+            Reducer::Sum => value(numbers().sum()),
+            Reducer::Min => numbers().reduce(f64::min).and_then(value),
+            Reducer::Max => numbers().reduce(f64::max).and_then(value),
+            Reducer::Avg => {
+                let (sum, count) = numbers().fold((0.0, 0), |(sum, count), n| (sum + n, count + 1));
+                (count > 0).then(|| sum / count as f64).and_then(value)
+            }
+            Reducer::ToList => {
+                let mut list: Vec<String> = Vec::new();
+                for cell in cells() {
+                    let values = match cell {
+                        Cell::Value(value) => std::slice::from_ref(value),
+                        Cell::List(values) => values.as_slice(),
+                    };
+                    for value in values {
+                        if !list.contains(value) {
+                            list.push(value.clone());
+                        }
+                    }
+                }
+                Some(Cell::List(list))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn row(cells: &[(&str, &str)]) -> Row {
+        cells
+            .iter()
+            .map(|(name, value)| (name.to_string(), Cell::Value(value.to_string())))
+            .collect()
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![
+            row(&[("kind", "fruit"), ("price", "3")]),
+            row(&[("kind", "bread"), ("price", "2.5")]),
+            row(&[("kind", "fruit"), ("price", "10")]),
+            row(&[("price", "1")]),
+        ]
+    }
+
+    #[test]
+    fn test_group_and_sort() {
+        let pipeline = Pipeline::parse(&args(
+            "GROUPBY 1 @kind REDUCE COUNT 0 AS n REDUCE SUM 1 @price \
+             REDUCE TOLIST 1 @price SORTBY 2 @n DESC LIMIT 0 2",
+        ))
+        .unwrap();
+        assert_eq!(
+            pipeline.load(),
+            Load::Fields(vec!["kind".to_string(), "price".to_string()])
+        );
+        let (total, rows) = pipeline.run(rows());
+        assert_eq!(total, 3);
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    ("kind".to_string(), Cell::Value("fruit".to_string())),
+                    ("n".to_string(), Cell::Value("2".to_string())),
+                    (
+                        "__generated_aliassumprice".to_string(),
+                        Cell::Value("13".to_string())
+                    ),
+                    (
+                        "__generated_aliastolistprice".to_string(),
+                        Cell::List(vec!["3".to_string(), "10".to_string()])
+                    ),
+                ],
+                vec![
+                    ("kind".to_string(), Cell::Value("bread".to_string())),
+                    ("n".to_string(), Cell::Value("1".to_string())),
+                    (
+                        "__generated_aliassumprice".to_string(),
+                        Cell::Value("2.5".to_string())
+                    ),
+                    (
+                        "__generated_aliastolistprice".to_string(),
+                        Cell::List(vec!["2.5".to_string()])
+                    ),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_numbers_and_missing() {
+        let pipeline = Pipeline::parse(&args(
+            "LOAD 1 @name SORTBY 2 @kind ASC SORTBY 1 @price MAX 3",
+        ))
+        .unwrap();
+        assert_eq!(
+            pipeline.load(),
+            Load::Fields(vec![
+                "name".to_string(),
+                "kind".to_string(),
+                "price".to_string()
+            ])
+        );
+        let (total, sorted) = pipeline.run(rows());
+        assert_eq!(total, 3);
+        let prices: Vec<&Cell> = sorted
+            .iter()
+            .map(|row| get(row, "price").unwrap())
+            .collect();
+        assert_eq!(
+            prices,
+            vec![
+                &Cell::Value("1".to_string()),
+                &Cell::Value("2.5".to_string()),
+                &Cell::Value("3".to_string()),
+            ]
+        );
+        let pipeline = Pipeline::parse(&args("GROUPBY 0 REDUCE AVG 1 @price AS avg")).unwrap();
+        assert_eq!(pipeline.run(rows()).1, vec![row(&[("avg", "4.125")])]);
+        assert_eq!(Pipeline::parse(&args("LOAD *")).unwrap().load(), Load::All);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "GROUPBY 1 kind",
+            "GROUPBY 2 @kind",
+            "GROUPBY 1 @kind REDUCE MEDIAN 1 @price",
+            "GROUPBY 1 @kind REDUCE COUNT 1 @price",
+            "GROUPBY 1 @kind REDUCE SUM 1 @price AS",
+            "SORTBY 1 @a MAX x",
+            "LIMIT 0",
+            "APPLY x",
+        ] {
+            assert!(Pipeline::parse(&args(bad)).is_err(), "{}", bad);
+        }
+    }
+}
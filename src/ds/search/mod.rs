@@ -0,0 +1,656 @@
+//! search -- secondary indexes over JSON documents, like RediSearch
+//!
+//! An index covers the keys starting with any of its prefixes and reads
+//! the fields of its schema out of each document by JSONPath. TEXT fields
+//! are split into lowercased terms, without stopwords, for full-text
+//! queries scored by TF-IDF; TAG fields into exact values; NUMERIC fields
+//! into numbers for range queries. The storage layer updates indexes as
+//! it writes, so queries never read the documents themselves.
+//!
+//! There are no positions in the postings, so there are no phrase or
+//! proximity queries, and any field can be sorted on, SORTABLE or not.
+
+mod aggregate;
+mod query;
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub use self::aggregate::{Cell, Load, Pipeline, Row};
+pub use self::query::{Bound, Query};
+use crate::ds::json::{Document, Path, Value};
+
+/// RediSearch's stopwords, left out of the text unless the index has its
+/// own list.
+pub const DEFAULT_STOPWORDS: [&str; 33] = [
+    "a", "is", "the", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into",
+    "it", "no", "not", "of", "on", "or", "such", "that", "their", "then", "there", "these", "they",
+    "this", "to", "was", "will", "with",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    /// Full text, with terms found here scoring `weight` times as much
+    Text {
+        weight: f64,
+    },
+    /// Exact values, split on `separator` when given as one string
+    Tag {
+        separator: char,
+        case_sensitive: bool,
+    },
+    Numeric,
+}
+
+impl FieldType {
+    /// The name FT.INFO reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::Text { .. } => "TEXT",
+            FieldType::Tag { .. } => "TAG",
+            FieldType::Numeric => "NUMERIC",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    /// The JSONPath as given
+    pub path: String,
+    selector: Path,
+    pub name: String,
+    pub kind: FieldType,
+    pub sortable: bool,
+}
+
+impl Field {
+    pub fn new(path: &str, name: &str, kind: FieldType, sortable: bool) -> Result<Field, String> {
+        Ok(Field {
+            path: path.to_string(),
+            selector: Path::parse(path)?,
+            name: name.to_string(),
+            kind,
+            sortable,
+        })
+    }
+
+    /// What the path matches in `doc`, with arrays opened up one level.
+    fn values<'a>(&self, doc: &'a Document) -> Vec<&'a Value> {
+        let mut values = Vec::new();
+        for steps in doc.select(&self.selector) {
+            match doc.get(&steps) {
+                Some(Value::Array(items)) => values.extend(items),
+                Some(value) => values.push(value),
+                None => {}
+            }
+        }
+        values
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Schema {
+    /// Key prefixes the index covers, every key when empty
+    pub prefixes: Vec<String>,
+    pub fields: Vec<Field>,
+    /// None for `DEFAULT_STOPWORDS`
+    pub stopwords: Option<Vec<String>>,
+}
+
+impl Schema {
+    /// A field by name, with its position.
+    pub fn field(&self, name: &str) -> Option<(usize, &Field)> {
+        self.fields
+            .iter()
+            .enumerate()
+            .find(|(_, field)| field.name == name)
+    }
+
+    pub fn covers(&self, key: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| key.starts_with(p.as_str()))
+    }
+
+    pub fn is_stopword(&self, term: &str) -> bool {
+        match &self.stopwords {
+            Some(stopwords) => stopwords.iter().any(|s| s == term),
+            None => DEFAULT_STOPWORDS.contains(&term),
+        }
+    }
+
+    /// The terms of `text`, lowercased, in order.
+    fn tokenize<'a>(&'a self, text: &'a str) -> impl Iterator<Item = String> + 'a {
+        text.split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .filter(|term| !self.is_stopword(term))
+    }
+}
+
+/// What SORTBY compares for one field of a document: numbers before
+/// strings, and strings lowercased.
+#[derive(Debug, Clone)]
+pub enum SortKey {
+    Number(f64),
+    String(String),
+}
+
+impl SortKey {
+    /// Parses what `Display` writes, `#` and a number or `$` and a string,
+    /// like RediSearch's WITHSORTKEYS.
+    pub fn parse(s: &str) -> Option<SortKey> {
+        match s.split_at_checked(1)? {
+            ("#", number) => number.parse().ok().map(SortKey::Number),
+            ("$", string) => Some(SortKey::String(string.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortKey::Number(n) => write!(f, "#{}", n),
+            SortKey::String(s) => write!(f, "${}", s),
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Number(a), SortKey::Number(b)) => a.total_cmp(b),
+            (SortKey::String(a), SortKey::String(b)) => a.cmp(b),
+            (SortKey::Number(_), SortKey::String(_)) => Ordering::Less,
+            (SortKey::String(_), SortKey::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+/// A number ordered with `total_cmp`, so it can key a set.
+#[derive(Debug, Clone, Copy)]
+struct Number(f64);
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Number {}
+
+/// What was indexed for a document, to take it out again.
+#[derive(Debug)]
+struct Doc {
+    key: String,
+    /// Terms over all TEXT fields, for normalizing term frequencies
+    length: u32,
+    terms: Vec<String>,
+    tags: Vec<(usize, String)>,
+    numbers: Vec<(usize, f64)>,
+    /// By field position
+    sort_keys: Vec<Option<SortKey>>,
+}
+
+/// A document matching a search.
+pub struct Hit<'a> {
+    pub key: &'a str,
+    pub score: f64,
+    doc: &'a Doc,
+}
+
+impl Hit<'_> {
+    /// What SORTBY compares for the field at `position`, if the document
+    /// has it.
+    pub fn sort_key(&self, position: usize) -> Option<&SortKey> {
+        self.doc.sort_keys.get(position)?.as_ref()
+    }
+}
+
+#[derive(Debug)]
+pub struct Index {
+    schema: Schema,
+    ids: HashMap<String, u64>,
+    /// By id, which grows with every document added so results come in
+    /// the order documents were indexed
+    docs: BTreeMap<u64, Doc>,
+    next_id: u64,
+    /// Postings of each term: the documents with it and, for each, the
+    /// fields it is in and how often
+    terms: BTreeMap<String, BTreeMap<u64, Vec<(usize, u32)>>>,
+    tags: BTreeSet<(usize, String, u64)>,
+    numbers: BTreeSet<(usize, Number, u64)>,
+    /// Entries over all postings, tags and numbers
+    records: usize,
+}
+
+impl Index {
+    pub fn new(schema: Schema) -> Index {
+        Index {
+            schema,
+            ids: HashMap::new(),
+            docs: BTreeMap::new(),
+            next_id: 0,
+            terms: BTreeMap::new(),
+            tags: BTreeSet::new(),
+            numbers: BTreeSet::new(),
+            records: 0,
+        }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Documents indexed.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Distinct terms over all TEXT fields.
+    pub fn terms_count(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn records_count(&self) -> usize {
+        self.records
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.docs.values().map(|doc| doc.key.as_str())
+    }
+
+    /// Indexes `doc` under `key`, replacing what was indexed for it
+    /// before. Documents with none of the fields are indexed all the
+    /// same, so `*` finds them.
+    pub fn add(&mut self, key: &str, doc: &Document) {
+        self.remove(key);
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut terms: BTreeMap<String, Vec<(usize, u32)>> = BTreeMap::new();
+        let mut entry = Doc {
+            key: key.to_string(),
+            length: 0,
+            terms: Vec::new(),
+            tags: Vec::new(),
+            numbers: Vec::new(),
+            sort_keys: Vec::with_capacity(self.schema.fields.len()),
+        };
+        for (position, field) in self.schema.fields.iter().enumerate() {
+            let values = field.values(doc);
+            let mut sort_key = None;
+            match &field.kind {
+                FieldType::Text { .. } => {
+                    for text in values.iter().filter_map(|value| match value {
+                        Value::String(s) => Some(s),
+                        _ => None,
+                    }) {
+                        sort_key.get_or_insert_with(|| SortKey::String(text.to_lowercase()));
+                        for term in self.schema.tokenize(text) {
+                            entry.length += 1;
+                            let fields = terms.entry(term).or_default();
+                            match fields.last_mut() {
+                                Some((f, count)) if *f == position => *count += 1,
+                                _ => fields.push((position, 1)),
+                            }
+                        }
+                    }
+                }
+                FieldType::Tag {
+                    separator,
+                    case_sensitive,
+                } => {
+                    for value in &values {
+                        let text = match value {
+                            Value::String(s) => s.clone(),
+                            Value::Bool(b) => b.to_string(),
+                            _ => continue,
+                        };
+                        for tag in text.split(*separator).map(str::trim) {
+                            let tag = match case_sensitive {
+                                true => tag.to_string(),
+                                false => tag.to_lowercase(),
+                            };
+                            if tag.is_empty() || entry.tags.contains(&(position, tag.clone())) {
+                                continue;
+                            }
+                            sort_key.get_or_insert_with(|| SortKey::String(tag.to_lowercase()));
+                            entry.tags.push((position, tag));
+                        }
+                    }
+                }
+                FieldType::Numeric => {
+                    for value in &values {
+                        let number = match value {
+                            Value::Integer(n) => *n as f64,
+                            Value::Float(n) => *n,
+                            _ => continue,
+                        };
+                        sort_key.get_or_insert(SortKey::Number(number));
+                        entry.numbers.push((position, number));
+                    }
+                }
+            }
+            entry.sort_keys.push(sort_key);
+        }
+        for (term, fields) in terms {
+            self.terms
+                .entry(term.clone())
+                .or_default()
+                .insert(id, fields);
+            entry.terms.push(term);
+        }
+        for (position, tag) in &entry.tags {
+            self.tags.insert((*position, tag.clone(), id));
+        }
+        for &(position, number) in &entry.numbers {
+            self.numbers.insert((position, Number(number), id));
+        }
+        self.records += entry.terms.len() + entry.tags.len() + entry.numbers.len();
+        self.ids.insert(key.to_string(), id);
+        self.docs.insert(id, entry);
+    }
+
+    /// Takes `key` out of the index. False if it wasn't in.
+    pub fn remove(&mut self, key: &str) -> bool {
+        let Some(id) = self.ids.remove(key) else {
+            return false;
+        };
+        let doc = self.docs.remove(&id).expect("ids have documents");
+        for term in doc.terms.iter() {
+            let postings = self.terms.get_mut(term).expect("terms have postings");
+            postings.remove(&id);
+            if postings.is_empty() {
+                self.terms.remove(term);
+            }
+        }
+        for (position, tag) in doc.tags.iter() {
+            self.tags.remove(&(*position, tag.clone(), id));
+        }
+        for &(position, number) in doc.numbers.iter() {
+            self.numbers.remove(&(position, Number(number), id));
+        }
+        self.records -= doc.terms.len() + doc.tags.len() + doc.numbers.len();
+        true
+    }
+
+    /// The documents matching `query`, in the order they were indexed.
+    pub fn search(&self, query: &Query) -> Vec<Hit<'_>> {
+        self.evaluate(query)
+            .into_iter()
+            .map(|(id, score)| {
+                let doc = &self.docs[&id];
+                Hit {
+                    key: &doc.key,
+                    score,
+                    doc,
+                }
+            })
+            .collect()
+    }
+
+    fn all(&self) -> BTreeMap<u64, f64> {
+        self.docs.keys().map(|&id| (id, 0.0)).collect()
+    }
+
+    /// Matching document ids with their scores. Only text adds to the
+    /// score; everything else is a filter.
+    fn evaluate(&self, query: &Query) -> BTreeMap<u64, f64> {
+        let mut found = BTreeMap::new();
+        match query {
+            Query::All => return self.all(),
+            Query::Term { fields, term } => {
+                if let Some(postings) = self.terms.get(term) {
+                    self.score(postings, fields, &mut found);
+                }
+            }
+            Query::Prefix { fields, prefix } => {
+                let terms = self
+                    .terms
+                    .range(prefix.clone()..)
+                    .take_while(|(term, _)| term.starts_with(prefix.as_str()));
+                for (_, postings) in terms {
+                    self.score(postings, fields, &mut found);
+                }
+            }
+            Query::Tag { field, tags } => {
+                for (tag, prefix) in tags {
+                    let matching =
+                        self.tags
+                            .range((*field, tag.clone(), 0)..)
+                            .take_while(|(f, t, _)| {
+                                *f == *field
+                                    && match prefix {
+                                        true => t.starts_with(tag.as_str()),
+                                        false => t == tag,
+                                    }
+                            });
+                    found.extend(matching.map(|&(_, _, id)| (id, 0.0)));
+                }
+            }
+            Query::Numeric { field, min, max } => {
+                if min.value > max.value {
+                    return found;
+                }
+                let range = (*field, Number(min.value), 0)..=(*field, Number(max.value), u64::MAX);
+                let matching = self.numbers.range(range).filter(|(_, number, _)| {
+                    (min.inclusive || number.0 > min.value)
+                        && (max.inclusive || number.0 < max.value)
+                });
+                found.extend(matching.map(|&(_, _, id)| (id, 0.0)));
+            }
+            Query::And(queries) => {
+                let (negated, queries): (Vec<&Query>, Vec<&Query>) = queries
+                    .iter()
+                    .partition(|query| matches!(query, Query::Not(_)));
+                if queries.is_empty() && negated.is_empty() {
+                    return found;
+                }
+                // Negations filter the rest rather than being worked out
+                // as everything else
+                found = match queries.split_first() {
+                    None => self.all(),
+                    Some((first, rest)) => {
+                        let mut found = self.evaluate(first);
+                        for query in rest {
+                            let other = self.evaluate(query);
+                            found.retain(|id, score| match other.get(id) {
+                                Some(more) => {
+                                    *score += more;
+                                    true
+                                }
+                                None => false,
+                            });
+                        }
+                        found
+                    }
+                };
+                for query in negated {
+                    let Query::Not(inner) = query else {
+                        unreachable!("partitioned on Not");
+                    };
+                    let excluded = self.evaluate(inner);
+                    found.retain(|id, _| !excluded.contains_key(id));
+                }
+            }
+            Query::Or(queries) => {
+                for query in queries {
+                    for (id, score) in self.evaluate(query) {
+                        *found.entry(id).or_default() += score;
+                    }
+                }
+            }
+            Query::Not(query) => {
+                let excluded = self.evaluate(query);
+                found = self.all();
+                found.retain(|id, _| !excluded.contains_key(id));
+            }
+        }
+        found
+    }
+
+    /// Adds each document's TF-IDF for one term: how often the term is in
+    /// `fields` (every TEXT field when empty), weighted and over the
+    /// document's length, times how rare the term is.
+    fn score(
+        &self,
+        postings: &BTreeMap<u64, Vec<(usize, u32)>>,
+        fields: &[usize],
+        found: &mut BTreeMap<u64, f64>,
+    ) {
+        let idf = (1.0 + self.docs.len() as f64 / postings.len() as f64).ln();
+        for (id, occurrences) in postings {
+            let frequency: f64 = occurrences
+                .iter()
+                .filter(|(field, _)| fields.is_empty() || fields.contains(field))
+                .map(|&(field, count)| match self.schema.fields[field].kind {
+                    FieldType::Text { weight } => weight * f64::from(count),
+                    _ => 0.0,
+                })
+                .sum();
+            if frequency == 0.0 {
+                continue;
+            }
+            let length = f64::from(self.docs[id].length.max(1));
+            *found.entry(*id).or_default() += frequency * idf / length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index() -> Index {
+        let fields = vec![
+            Field::new("$.title", "title", FieldType::Text { weight: 2.0 }, false).unwrap(),
+            Field::new("$.body", "body", FieldType::Text { weight: 1.0 }, false).unwrap(),
+            Field::new(
+                "$.tags",
+                "tags",
+                FieldType::Tag {
+                    separator: ',',
+                    case_sensitive: false,
+                },
+                false,
+            )
+            .unwrap(),
+            Field::new("$.price", "price", FieldType::Numeric, true).unwrap(),
+        ];
+        Index::new(Schema {
+            prefixes: vec!["doc:".to_string()],
+            fields,
+            stopwords: None,
+        })
+    }
+
+    fn doc(json: &str) -> Document {
+        Document::new(Value::parse(json).unwrap())
+    }
+
+    fn keys(index: &Index, query: &str) -> Vec<String> {
+        let query = Query::parse(query, index.schema()).unwrap();
+        index
+            .search(&query)
+            .iter()
+            .map(|hit| hit.key.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_index_and_query() {
+        let mut index = index();
+        assert!(index.schema().covers("doc:1"));
+        assert!(!index.schema().covers("other"));
+        index.add(
+            "doc:1",
+            &doc(r#"{"title": "Red apples", "body": "Sweet and crisp", "tags": "fruit, Red", "price": 3}"#),
+        );
+        index.add(
+            "doc:2",
+            &doc(r#"{"title": "Green pears", "body": "The apple's cousin", "tags": ["fruit", "green"], "price": 4.5}"#),
+        );
+        index.add("doc:3", &doc(r#"{"title": "Bread", "price": "n/a"}"#));
+        assert_eq!(index.len(), 3);
+        assert_eq!(keys(&index, "*"), vec!["doc:1", "doc:2", "doc:3"]);
+        assert_eq!(keys(&index, "apple"), vec!["doc:2"]);
+        assert_eq!(keys(&index, "app*"), vec!["doc:1", "doc:2"]);
+        assert_eq!(keys(&index, "@title:app*"), vec!["doc:1"]);
+        assert_eq!(keys(&index, "apples | bread"), vec!["doc:1", "doc:3"]);
+        assert_eq!(keys(&index, "-bread"), vec!["doc:1", "doc:2"]);
+        assert_eq!(keys(&index, "@tags:{RED}"), vec!["doc:1"]);
+        assert_eq!(keys(&index, "@tags:{fruit} -@tags:{red}"), vec!["doc:2"]);
+        assert_eq!(keys(&index, "@tags:{gr*}"), vec!["doc:2"]);
+        assert_eq!(keys(&index, "@price:[3 4.5]"), vec!["doc:1", "doc:2"]);
+        assert_eq!(keys(&index, "@price:[(3 +inf]"), vec!["doc:2"]);
+        assert_eq!(keys(&index, "@price:[-inf (3]"), Vec::<String>::new());
+        assert_eq!(keys(&index, "the"), Vec::<String>::new());
+
+        // Terms in the title weigh more than the body's
+        let query = Query::parse("apples | apple", index.schema()).unwrap();
+        let hits = index.search(&query);
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(hits[0].sort_key(3), Some(&SortKey::Number(3.0)));
+        assert_eq!(
+            hits[1].sort_key(0),
+            Some(&SortKey::String("green pears".into()))
+        );
+
+        // Replacing a document takes out what it had before
+        index.add("doc:1", &doc(r#"{"title": "Plums"}"#));
+        assert_eq!(keys(&index, "apples"), Vec::<String>::new());
+        assert_eq!(keys(&index, "*"), vec!["doc:2", "doc:3", "doc:1"]);
+        assert!(index.remove("doc:2"));
+        assert!(!index.remove("doc:2"));
+        assert_eq!(keys(&index, "@tags:{fruit}"), Vec::<String>::new());
+        assert_eq!(index.terms_count(), 2);
+        assert_eq!(index.records_count(), 2);
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let keys = [
+            SortKey::String("b".to_string()),
+            SortKey::Number(10.0),
+            SortKey::String("a".to_string()),
+            SortKey::Number(-1.5),
+        ];
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        let written: Vec<String> = sorted.iter().map(SortKey::to_string).collect();
+        assert_eq!(written, vec!["#-1.5", "#10", "$a", "$b"]);
+        for key in &keys {
+            assert_eq!(SortKey::parse(&key.to_string()).as_ref(), Some(key));
+        }
+        assert_eq!(SortKey::parse("x"), None);
+    }
+}
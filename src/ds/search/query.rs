@@ -0,0 +1,457 @@
+use super::{FieldType, Schema};
+
+/// Groups and negations nest at most this deep.
+const MAX_DEPTH: usize = 64;
+
+/// One end of a numeric range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bound {
+    pub value: f64,
+    pub inclusive: bool,
+}
+
+/// A parsed query, with fields resolved to their place in the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// `*`, every document
+    All,
+    /// A term in the TEXT fields given, or in all of them when empty
+    Term {
+        fields: Vec<usize>,
+        term: String,
+    },
+    /// `prefix*`, any term starting with the prefix
+    Prefix {
+        fields: Vec<usize>,
+        prefix: String,
+    },
+    /// `@field:{a | b*}`, any of the tags, or tags starting with those
+    /// marked as prefixes
+    Tag {
+        field: usize,
+        tags: Vec<(String, bool)>,
+    },
+    /// `@field:[min max]`
+    Numeric {
+        field: usize,
+        min: Bound,
+        max: Bound,
+    },
+    /// Every query matches, which none do when empty
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+impl Query {
+    /// Parses RediSearch's query syntax: terms and `prefix*`s, `@field:`
+    /// to look in one field (or `@a|b:` for several), `{tags}` and
+    /// `[min max]` ranges for TAG and NUMERIC fields, juxtaposition for
+    /// AND, `|` for OR, `-` for NOT and parentheses to group.
+    pub fn parse(input: &str, schema: &Schema) -> Result<Query, String> {
+        let mut parser = Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+            depth: 0,
+            schema,
+        };
+        let query = parser.union(&[])?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(query),
+            Some(_) => Err(parser.error()),
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+    schema: &'a Schema,
+}
+
+fn is_term_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.error()),
+        }
+    }
+
+    fn error(&self) -> String {
+        let near: String = self.chars[self.pos.min(self.chars.len())..]
+            .iter()
+            .take(16)
+            .collect();
+        match near.is_empty() {
+            true => format!("Syntax error at offset {}", self.pos),
+            false => format!("Syntax error at offset {} near {}", self.pos, near),
+        }
+    }
+
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        match self.depth > MAX_DEPTH {
+            true => Err("Query nests too deep".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    /// Alternatives separated by `|`, looking in `fields`.
+    fn union(&mut self, fields: &[usize]) -> Result<Query, String> {
+        let mut alternatives = vec![self.intersection(fields)?];
+        while self.eat('|') {
+            alternatives.push(self.intersection(fields)?);
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Query::Or(alternatives),
+        })
+    }
+
+    /// Queries one after the other, which must all match. Stopwords are
+    /// left out, so one made of nothing else matches nothing.
+    fn intersection(&mut self, fields: &[usize]) -> Result<Query, String> {
+        let mut queries = Vec::new();
+        let mut empty = true;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some(')' | '|') => break,
+                Some(_) => {
+                    empty = false;
+                    queries.extend(self.unary(fields)?);
+                }
+            }
+        }
+        if empty {
+            return Err(self.error());
+        }
+        Ok(match queries.len() {
+            1 => queries.pop().unwrap(),
+            _ => Query::And(queries),
+        })
+    }
+
+    /// One query, or None for a stopword.
+    fn unary(&mut self, fields: &[usize]) -> Result<Option<Query>, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                self.nest()?;
+                let query = self.unary(fields)?;
+                self.depth -= 1;
+                Ok(query.map(|query| Query::Not(Box::new(query))))
+            }
+            Some('(') => {
+                self.pos += 1;
+                self.nest()?;
+                let query = self.union(fields)?;
+                self.depth -= 1;
+                self.expect(')')?;
+                Ok(Some(query))
+            }
+            Some('@') => {
+                self.pos += 1;
+                self.field_query()
+            }
+            Some('*') => {
+                self.pos += 1;
+                Ok(Some(Query::All))
+            }
+            _ => self.term(fields),
+        }
+    }
+
+    /// After the `@`: the field names, then what to look for in them.
+    fn field_query(&mut self) -> Result<Option<Query>, String> {
+        let mut names = vec![self.name()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            names.push(self.name()?);
+        }
+        self.expect(':')?;
+        let mut fields = Vec::with_capacity(names.len());
+        for name in &names {
+            let (position, _) = self
+                .schema
+                .field(name)
+                .ok_or_else(|| format!("Unknown field `{}`", name))?;
+            fields.push(position);
+        }
+        let kind = &self.schema.fields[fields[0]].kind;
+        match kind {
+            FieldType::Text { .. } => {
+                let all_text = fields
+                    .iter()
+                    .all(|&f| matches!(self.schema.fields[f].kind, FieldType::Text { .. }));
+                if !all_text {
+                    return Err(self.error());
+                }
+                self.nest()?;
+                let query = self.unary(&fields);
+                self.depth -= 1;
+                query
+            }
+            _ if fields.len() > 1 => Err(self.error()),
+            FieldType::Tag { case_sensitive, .. } => {
+                let case_sensitive = *case_sensitive;
+                self.tags(fields[0], case_sensitive).map(Some)
+            }
+            FieldType::Numeric => self.range(fields[0]).map(Some),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_term_char) {
+            self.pos += 1;
+        }
+        match self.pos > start {
+            true => Ok(self.chars[start..self.pos].iter().collect()),
+            false => Err(self.error()),
+        }
+    }
+
+    /// A term, with backslashes escaping any character, and a trailing
+    /// `*` making it a prefix.
+    fn term(&mut self, fields: &[usize]) -> Result<Option<Query>, String> {
+        let mut term = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' && self.pos + 1 < self.chars.len() {
+                term.push(self.chars[self.pos + 1]);
+                self.pos += 2;
+            } else if is_term_char(c) {
+                term.push(c);
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        if term.is_empty() {
+            return Err(self.error());
+        }
+        let term = term.to_lowercase();
+        let fields = fields.to_vec();
+        if self.peek() == Some('*') {
+            self.pos += 1;
+            return Ok(Some(Query::Prefix {
+                fields,
+                prefix: term,
+            }));
+        }
+        if self.schema.is_stopword(&term) {
+            return Ok(None);
+        }
+        Ok(Some(Query::Term { fields, term }))
+    }
+
+    /// `{a | b | c*}` for a TAG field.
+    fn tags(&mut self, field: usize, case_sensitive: bool) -> Result<Query, String> {
+        self.expect('{')?;
+        let mut tags = Vec::new();
+        loop {
+            // Characters with whether they were escaped, so an escaped
+            // `*` or space is part of the tag
+            let mut chars: Vec<(char, bool)> = Vec::new();
+            loop {
+                match self.peek() {
+                    None => return Err(self.error()),
+                    Some('|' | '}') => break,
+                    Some('\\') if self.pos + 1 < self.chars.len() => {
+                        chars.push((self.chars[self.pos + 1], true));
+                        self.pos += 2;
+                    }
+                    Some(c) => {
+                        chars.push((c, false));
+                        self.pos += 1;
+                    }
+                }
+            }
+            let unescaped_space = |(c, escaped): &(char, bool)| !escaped && c.is_whitespace();
+            while chars.last().is_some_and(unescaped_space) {
+                chars.pop();
+            }
+            let start = chars.iter().take_while(|c| unescaped_space(c)).count();
+            let prefix = chars.last() == Some(&('*', false));
+            if prefix {
+                chars.pop();
+            }
+            let tag: String = chars[start.min(chars.len())..]
+                .iter()
+                .map(|(c, _)| c)
+                .collect();
+            if tag.is_empty() {
+                return Err(self.error());
+            }
+            let tag = match case_sensitive {
+                true => tag.to_string(),
+                false => tag.to_lowercase(),
+            };
+            tags.push((tag, prefix));
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                break;
+            }
+            self.pos += 1;
+        }
+        Ok(Query::Tag { field, tags })
+    }
+
+    /// `[min max]` for a NUMERIC field.
+    fn range(&mut self, field: usize) -> Result<Query, String> {
+        self.expect('[')?;
+        let min = self.bound()?;
+        self.eat(',');
+        let max = self.bound()?;
+        self.expect(']')?;
+        Ok(Query::Numeric { field, min, max })
+    }
+
+    /// A number, or `inf`, `+inf` or `-inf`, after `(` when exclusive.
+    fn bound(&mut self) -> Result<Bound, String> {
+        let inclusive = !self.eat('(');
+        self.skip_whitespace();
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !matches!(c, ',' | ']'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let value = match text.to_lowercase().as_str() {
+            "inf" | "+inf" => f64::INFINITY,
+            "-inf" => f64::NEG_INFINITY,
+            _ => text
+                .parse::<f64>()
+                .ok()
+                .filter(|value| !value.is_nan())
+                .ok_or_else(|| format!("Bad lower or upper range: {}", text))?,
+        };
+        Ok(Bound { value, inclusive })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ds::search::{Field, Schema};
+
+    fn schema() -> Schema {
+        Schema {
+            prefixes: Vec::new(),
+            fields: vec![
+                Field::new("$.title", "title", FieldType::Text { weight: 1.0 }, false).unwrap(),
+                Field::new("$.body", "body", FieldType::Text { weight: 1.0 }, false).unwrap(),
+                Field::new(
+                    "$.tags",
+                    "tags",
+                    FieldType::Tag {
+                        separator: ',',
+                        case_sensitive: false,
+                    },
+                    false,
+                )
+                .unwrap(),
+                Field::new("$.price", "price", FieldType::Numeric, true).unwrap(),
+            ],
+            stopwords: None,
+        }
+    }
+
+    fn term(fields: &[usize], term: &str) -> Query {
+        Query::Term {
+            fields: fields.to_vec(),
+            term: term.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let schema = schema();
+        let parse = |input: &str| Query::parse(input, &schema);
+        assert_eq!(parse("*"), Ok(Query::All));
+        assert_eq!(
+            parse("Hello the world"),
+            Ok(Query::And(vec![term(&[], "hello"), term(&[], "world")]))
+        );
+        assert_eq!(parse("the"), Ok(Query::And(Vec::new())));
+        assert_eq!(
+            parse("@title:(a|hel*) -@body:x"),
+            Ok(Query::And(vec![
+                Query::Or(vec![
+                    Query::And(Vec::new()),
+                    Query::Prefix {
+                        fields: vec![0],
+                        prefix: "hel".to_string(),
+                    },
+                ]),
+                Query::Not(Box::new(term(&[1], "x"))),
+            ]))
+        );
+        assert_eq!(
+            parse("@title|body:foo | bar"),
+            Ok(Query::Or(vec![term(&[0, 1], "foo"), term(&[], "bar")]))
+        );
+        assert_eq!(
+            parse(r"@tags:{ Red | light\ blue | gr* }"),
+            Ok(Query::Tag {
+                field: 2,
+                tags: vec![
+                    ("red".to_string(), false),
+                    ("light blue".to_string(), false),
+                    ("gr".to_string(), true),
+                ],
+            })
+        );
+        assert_eq!(
+            parse("@price:[(10 +inf]"),
+            Ok(Query::Numeric {
+                field: 3,
+                min: Bound {
+                    value: 10.0,
+                    inclusive: false,
+                },
+                max: Bound {
+                    value: f64::INFINITY,
+                    inclusive: true,
+                },
+            })
+        );
+        assert_eq!(parse("@nope:x"), Err("Unknown field `nope`".to_string()));
+        assert!(parse("@price:x").is_err());
+        assert!(parse("@tags:{a").is_err());
+        assert!(parse("(a b").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("a ||").is_err());
+        assert!(parse(&"(".repeat(100)).is_err());
+        assert!(parse(&"-".repeat(100_000)).is_err());
+        assert!(parse(&"@title:".repeat(100_000)).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::mem::size_of;
+use std::ops::Bound;
+
+/// Bytes charged for every member on top of its name: its score in both
+/// indexes and the tree and table slots.
+const MEMBER_OVERHEAD: usize = 2 * size_of::<f64>() + 2 * size_of::<String>();
+
+/// A score with a total order, so it can key the ordered index.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Members ordered by score, then by name, as Redis' sorted sets are.
+#[derive(Debug, Default)]
+pub struct SortedSet {
+    scores: HashMap<String, f64>,
+    order: BTreeSet<(Score, String)>,
+    member_bytes: usize,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets a member's score, returning the one it replaced.
+    pub fn insert(&mut self, member: &str, score: f64) -> Option<f64> {
+        let previous = self.scores.insert(member.to_string(), score);
+        match previous {
+            Some(old) => {
+                self.order.remove(&(Score(old), member.to_string()));
+            }
+            None => self.member_bytes += 2 * member.len(),
+        }
+        self.order.insert((Score(score), member.to_string()));
+        previous
+    }
+
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.order.remove(&(Score(score), member.to_string()));
+        self.member_bytes -= 2 * member.len();
+        Some(score)
+    }
+
+    /// Members with `min <= score < max`, in order.
+    pub fn range(&self, min: f64, max: f64) -> impl Iterator<Item = (&str, f64)> {
+        let start = Bound::Included((Score(min), String::new()));
+        self.order
+            .range((start, Bound::Unbounded))
+            .take_while(move |(score, _)| score.0 < max)
+            .map(|(score, member)| (member.as_str(), score.0))
+    }
+
+    /// Every member, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.order
+            .iter()
+            .map(|(score, member)| (member.as_str(), score.0))
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.len() * MEMBER_OVERHEAD + self.member_bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_order_and_updates() {
+        let mut set = SortedSet::new();
+        assert_eq!(set.insert("b", 2.0), None);
+        assert_eq!(set.insert("a", 2.0), None);
+        assert_eq!(set.insert("c", 1.0), None);
+        assert_eq!(set.insert("c", 3.0), Some(1.0));
+        let members: Vec<_> = set.iter().map(|(m, _)| m).collect();
+        assert_eq!(members, ["a", "b", "c"]);
+        let in_range: Vec<_> = set.range(2.0, 3.0).collect();
+        assert_eq!(in_range, [("a", 2.0), ("b", 2.0)]);
+
+        let usage = set.memory_usage();
+        assert_eq!(set.remove("a"), Some(2.0));
+        assert_eq!(set.remove("a"), None);
+        assert!(set.memory_usage() < usage);
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.score("c"), Some(3.0));
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem::size_of;
+
+use super::StreamId;
+
+const GROUP_OVERHEAD: usize = size_of::<ConsumerGroup>();
+const CONSUMER_OVERHEAD: usize = size_of::<Consumer>() + size_of::<String>();
+/// A PEL slot plus the ID's place in its consumer's own set.
+const PENDING_OVERHEAD: usize = 2 * size_of::<StreamId>() + size_of::<PendingEntry>();
+
+/// A delivered entry waiting to be acknowledged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: String,
+    /// Unix milliseconds of the last delivery
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Consumer {
+    /// Unix milliseconds of the last time it read or claimed
+    pub seen_time: u64,
+    /// Unix milliseconds of the last time it was given an entry
+    pub active_time: Option<u64>,
+    /// Its share of the group's pending entries
+    pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Its pending IDs after `after`, in order.
+    pub fn pending_after(&self, after: StreamId) -> impl Iterator<Item = StreamId> + '_ {
+        self.pending
+            .range((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded))
+            .copied()
+    }
+}
+
+/// A consumer group: how far it has read the stream, and what its
+/// consumers were given but haven't acknowledged (the PEL).
+#[derive(Debug, Clone)]
+pub struct ConsumerGroup {
+    /// The last ID delivered to any of its consumers
+    pub last_id: StreamId,
+    /// Entries read so far, when it can be known
+    pub entries_read: Option<u64>,
+    pel: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<String, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_id,
+            entries_read,
+            pel: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    pub fn consumer(&self, name: &str) -> Option<&Consumer> {
+        self.consumers.get(name)
+    }
+
+    pub fn consumer_mut(&mut self, name: &str) -> Option<&mut Consumer> {
+        self.consumers.get_mut(name)
+    }
+
+    /// Consumers in name order.
+    pub fn consumers(&self) -> impl Iterator<Item = (&str, &Consumer)> {
+        self.consumers
+            .iter()
+            .map(|(name, consumer)| (name.as_str(), consumer))
+    }
+
+    /// Creates a consumer if it doesn't exist yet, returning whether it
+    /// did not.
+    pub fn create_consumer(&mut self, name: &str, now_ms: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        let consumer = Consumer {
+            seen_time: now_ms,
+            ..Consumer::default()
+        };
+        self.consumers.insert(name.to_string(), consumer);
+        true
+    }
+
+    /// Records that a consumer read or claimed, creating it if needed.
+    /// Returns whether it was created.
+    pub fn seen(&mut self, name: &str, now_ms: u64) -> bool {
+        let created = self.create_consumer(name, now_ms);
+        if let Some(consumer) = self.consumers.get_mut(name) {
+            consumer.seen_time = now_ms;
+        }
+        created
+    }
+
+    /// Deletes a consumer and its pending entries, returning how many it
+    /// had, or None if there was no such consumer.
+    pub fn delete_consumer(&mut self, name: &str) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pel.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pel.len()
+    }
+
+    pub fn pending(&self, id: StreamId) -> Option<&PendingEntry> {
+        self.pel.get(&id)
+    }
+
+    /// Pending entries with IDs in `start..=end`, in order.
+    pub fn pending_range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl Iterator<Item = (StreamId, &PendingEntry)> {
+        let range = if start <= end {
+            Some(self.pel.range(start..=end))
+        } else {
+            None
+        };
+        range.into_iter().flatten().map(|(id, entry)| (*id, entry))
+    }
+
+    /// Hands `id` to `consumer`, which must exist, as a first delivery. An
+    /// entry already pending elsewhere moves over.
+    pub fn deliver(&mut self, id: StreamId, consumer: &str, now_ms: u64) {
+        self.assign(id, consumer, now_ms, 1);
+    }
+
+    /// Moves a pending entry, or creates one, for XCLAIM.
+    pub fn claim(&mut self, id: StreamId, consumer: &str, delivery_time: u64, count: u64) {
+        self.assign(id, consumer, delivery_time, count);
+    }
+
+    fn assign(&mut self, id: StreamId, consumer: &str, delivery_time: u64, count: u64) {
+        if let Some(previous) = self.pel.remove(&id)
+            && let Some(owner) = self.consumers.get_mut(&previous.consumer)
+        {
+            owner.pending.remove(&id);
+        }
+        let owner = self
+            .consumers
+            .get_mut(consumer)
+            .expect("entries are assigned to existing consumers");
+        owner.pending.insert(id);
+        owner.active_time = Some(delivery_time.max(owner.active_time.unwrap_or(0)));
+        self.pel.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_string(),
+                delivery_time,
+                delivery_count: count,
+            },
+        );
+    }
+
+    /// Acknowledges an entry, returning whether it was pending.
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        let Some(entry) = self.pel.remove(&id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(&id);
+        }
+        true
+    }
+
+    /// Approximate bytes used.
+    pub fn memory_usage(&self) -> usize {
+        let consumers: usize = self
+            .consumers
+            .keys()
+            .map(|name| CONSUMER_OVERHEAD + name.len())
+            .sum();
+        GROUP_OVERHEAD + consumers + self.pel.len() * PENDING_OVERHEAD
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(ms: u64) -> StreamId {
+        StreamId::new(ms, 0)
+    }
+
+    #[test]
+    fn test_deliver_claim_and_ack() {
+        let mut group = ConsumerGroup::new(StreamId::MIN, Some(0));
+        assert!(group.seen("alice", 100));
+        assert!(!group.seen("alice", 150));
+        group.create_consumer("bob", 100);
+        group.deliver(id(1), "alice", 200);
+        group.deliver(id(2), "alice", 200);
+        assert_eq!(group.pending_len(), 2);
+        assert_eq!(group.consumer("alice").unwrap().pending(), 2);
+        assert_eq!(group.consumer("alice").unwrap().seen_time, 150);
+
+        group.claim(id(2), "bob", 300, 2);
+        assert_eq!(group.consumer("alice").unwrap().pending(), 1);
+        let entry = group.pending(id(2)).unwrap();
+        assert_eq!((entry.consumer.as_str(), entry.delivery_count), ("bob", 2));
+        let after: Vec<StreamId> = group
+            .consumer("alice")
+            .unwrap()
+            .pending_after(StreamId::MIN)
+            .collect();
+        assert_eq!(after, vec![id(1)]);
+
+        assert!(group.ack(id(1)));
+        assert!(!group.ack(id(1)));
+        assert_eq!(group.pending_range(StreamId::MIN, StreamId::MAX).count(), 1);
+        assert_eq!(group.delete_consumer("bob"), Some(1));
+        assert_eq!(group.pending_len(), 0);
+        assert_eq!(group.delete_consumer("bob"), None);
+    }
+}
@@ -0,0 +1,535 @@
+mod group;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem::size_of;
+
+pub use self::group::{Consumer, ConsumerGroup, PendingEntry};
+
+/// Entries a node holds before a new one is started, like Redis'
+/// `stream-node-max-entries`.
+pub const NODE_MAX_ENTRIES: usize = 100;
+
+/// Bytes charged for every node on top of its entries.
+const NODE_OVERHEAD: usize = 2 * size_of::<StreamId>() + size_of::<Node>();
+/// Bytes charged for every entry on top of its fields and values.
+const ENTRY_OVERHEAD: usize = size_of::<Entry>();
+
+/// A stream entry ID: milliseconds and a sequence number within them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// Reads `ms-seq`, or a bare `ms` with `missing_seq` as its sequence.
+    pub fn parse(s: &str, missing_seq: u64) -> Option<StreamId> {
+        match s.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamId::new(s.parse().ok()?, missing_seq)),
+        }
+    }
+
+    /// The smallest ID after this one, None past the last possible ID.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The largest ID before this one, None before 0-0.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+struct Entry {
+    id: StreamId,
+    /// Field names and values, interleaved
+    fields: Vec<String>,
+    /// Deleted entries stay in their node until the whole node goes, the
+    /// way Redis flags them in a listpack
+    deleted: bool,
+}
+
+impl Entry {
+    fn memory_usage(&self) -> usize {
+        ENTRY_OVERHEAD + self.fields.iter().map(String::len).sum::<usize>()
+    }
+}
+
+/// A run of consecutive entries, keyed in the stream by its first ID.
+#[derive(Default)]
+struct Node {
+    entries: Vec<Entry>,
+    /// Entries not flagged as deleted
+    live: usize,
+}
+
+/// How XADD and XTRIM cut a stream down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trim {
+    /// Keep at most this many entries
+    MaxLen(usize),
+    /// Drop entries with lower IDs
+    MinId(StreamId),
+}
+
+/// An append-only log of field-value entries with increasing IDs.
+///
+/// Like Redis' radix tree of listpacks, entries are packed into nodes of
+/// up to `NODE_MAX_ENTRIES`, ordered by their first ID, so appends touch
+/// only the last node and ranges seek straight to the node they start in.
+/// Deleting flags an entry in place; its node is freed once all of its
+/// entries are gone.
+#[derive(Default)]
+pub struct Stream {
+    nodes: BTreeMap<StreamId, Node>,
+    len: usize,
+    last_id: StreamId,
+    max_deleted_id: StreamId,
+    entries_added: u64,
+    /// Bytes held by the entries and nodes, kept up to date on every change
+    entry_bytes: usize,
+    groups: BTreeMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The highest ID ever added, even if it was deleted since.
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    /// Entries added over the stream's lifetime, deleted ones included.
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    /// Nodes currently allocated, as XINFO reports radix tree nodes.
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Restores the counters a stream carries beyond its entries, for
+    /// loading snapshots.
+    pub fn set_counters(&mut self, last_id: StreamId, max_deleted_id: StreamId, added: u64) {
+        self.last_id = last_id;
+        self.max_deleted_id = max_deleted_id;
+        self.entries_added = added;
+    }
+
+    /// The ID `*` stands for: the current time, or the last ID's time with
+    /// the next sequence if the clock went backwards. None once the last
+    /// possible ID was used.
+    pub fn auto_id(&self, now_ms: u64) -> Option<StreamId> {
+        if now_ms > self.last_id.ms {
+            Some(StreamId::new(now_ms, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// The ID `ms-*` stands for.
+    pub fn auto_seq_id(&self, ms: u64) -> Option<StreamId> {
+        if ms == self.last_id.ms {
+            self.last_id.next().filter(|id| id.ms == ms)
+        } else {
+            Some(StreamId::new(ms, 0))
+        }
+    }
+
+    /// Appends an entry. `id` must be greater than `last_id`.
+    pub fn add(&mut self, id: StreamId, fields: Vec<String>) {
+        debug_assert!(id > self.last_id || self.entries_added == 0);
+        let entry = Entry {
+            id,
+            fields,
+            deleted: false,
+        };
+        self.entry_bytes += entry.memory_usage();
+        let node = match self.nodes.last_entry() {
+            Some(node) if node.get().entries.len() < NODE_MAX_ENTRIES => node.into_mut(),
+            _ => {
+                self.entry_bytes += NODE_OVERHEAD;
+                self.nodes.entry(id).or_default()
+            }
+        };
+        node.entries.push(entry);
+        node.live += 1;
+        self.len += 1;
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    /// Live entries with IDs in `start..=end`, in order. Reverse it for
+    /// XREVRANGE.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (StreamId, &[String])> {
+        // The node holding `start` is keyed at or before it
+        let from = self
+            .nodes
+            .range(..=start)
+            .next_back()
+            .map_or(start, |(id, _)| *id);
+        let nodes = if start <= end {
+            Some(self.nodes.range(from..=end))
+        } else {
+            None
+        };
+        nodes
+            .into_iter()
+            .flatten()
+            .flat_map(|(_, node)| node.entries.iter())
+            .filter(move |entry| !entry.deleted && entry.id >= start && entry.id <= end)
+            .map(|entry| (entry.id, entry.fields.as_slice()))
+    }
+
+    /// The fields of a live entry.
+    pub fn get(&self, id: StreamId) -> Option<&[String]> {
+        let (_, node) = self.nodes.range(..=id).next_back()?;
+        let index = node.entries.binary_search_by_key(&id, |e| e.id).ok()?;
+        let entry = &node.entries[index];
+        (!entry.deleted).then_some(entry.fields.as_slice())
+    }
+
+    pub fn first(&self) -> Option<(StreamId, &[String])> {
+        self.range(StreamId::MIN, StreamId::MAX).next()
+    }
+
+    pub fn last(&self) -> Option<(StreamId, &[String])> {
+        self.range(StreamId::MIN, StreamId::MAX).next_back()
+    }
+
+    /// Deletes an entry, returning whether it was there.
+    pub fn delete(&mut self, id: StreamId) -> bool {
+        let Some((&master, node)) = self.nodes.range_mut(..=id).next_back() else {
+            return false;
+        };
+        let Ok(index) = node.entries.binary_search_by_key(&id, |e| e.id) else {
+            return false;
+        };
+        let entry = &mut node.entries[index];
+        if entry.deleted {
+            return false;
+        }
+        entry.deleted = true;
+        node.live -= 1;
+        if node.live == 0 {
+            self.remove_node(master);
+        }
+        self.len -= 1;
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    fn remove_node(&mut self, master: StreamId) {
+        if let Some(node) = self.nodes.remove(&master) {
+            let bytes: usize = node.entries.iter().map(Entry::memory_usage).sum();
+            self.entry_bytes -= bytes + NODE_OVERHEAD;
+        }
+    }
+
+    /// Drops entries from the front until `trim` holds, returning how many
+    /// went. Approximate trimming only frees whole nodes, and `limit`
+    /// caps how many entries go in one call.
+    pub fn trim(&mut self, trim: Trim, approximate: bool, limit: Option<usize>) -> usize {
+        let mut trimmed = 0;
+        while let Some((&master, node)) = self.nodes.first_key_value() {
+            let last = node.entries.last().map(|e| e.id).unwrap_or_default();
+            let live = node.live;
+            let whole = match trim {
+                Trim::MaxLen(maxlen) => self.len - live >= maxlen,
+                Trim::MinId(minid) => last < minid,
+            };
+            if whole {
+                if limit.is_some_and(|limit| trimmed + live > limit) {
+                    break;
+                }
+                self.remove_node(master);
+                self.len -= live;
+                trimmed += live;
+                continue;
+            }
+            if approximate {
+                break;
+            }
+            // The cut falls inside this node: flag entries one by one
+            let node = self
+                .nodes
+                .get_mut(&master)
+                .expect("the node was just found");
+            for entry in node.entries.iter_mut().filter(|e| !e.deleted) {
+                let keep = match trim {
+                    Trim::MaxLen(maxlen) => self.len <= maxlen,
+                    Trim::MinId(minid) => entry.id >= minid,
+                };
+                if keep || limit.is_some_and(|limit| trimmed >= limit) {
+                    break;
+                }
+                entry.deleted = true;
+                node.live -= 1;
+                self.len -= 1;
+                trimmed += 1;
+            }
+            if node.live == 0 {
+                self.remove_node(master);
+            }
+            break;
+        }
+        trimmed
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Adds a group, returning false if the name is taken.
+    pub fn create_group(&mut self, name: &str, group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        self.groups.insert(name.to_string(), group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Groups in name order.
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &ConsumerGroup)> {
+        self.groups
+            .iter()
+            .map(|(name, group)| (name.as_str(), group))
+    }
+
+    /// How many entries a group delivering up to `id` has read, if that
+    /// can be known: it can't once entries after the group's position
+    /// were deleted.
+    pub fn entries_read_at(
+        &self,
+        id: StreamId,
+        previous: Option<u64>,
+        from: StreamId,
+    ) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if id >= self.last_id {
+            return Some(self.entries_added);
+        }
+        if let Some(first) = self.first().map(|(first, _)| first)
+            && id < first
+            && self.max_deleted_id < first
+        {
+            return Some(self.entries_added - self.len as u64);
+        }
+        match previous {
+            Some(read) if self.max_deleted_id <= from => {
+                Some(read + self.range(from.next()?, id).count() as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Entries a group hasn't been delivered yet, if that can be known.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 || group.last_id >= self.last_id {
+            return Some(0);
+        }
+        match self.first() {
+            None => return Some(0),
+            Some((first, _)) if group.last_id < first && self.max_deleted_id < first => {
+                return Some(self.len as u64);
+            }
+            _ => (),
+        }
+        match group.entries_read {
+            Some(read) if self.max_deleted_id <= group.last_id => {
+                Some(self.entries_added.saturating_sub(read))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Stream {
+    /// Approximate bytes used, groups included.
+    pub fn memory_usage(&self) -> usize {
+        self.entry_bytes
+            + self
+                .groups
+                .iter()
+                .map(|(name, group)| name.len() + group.memory_usage())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId::new(ms, seq)
+    }
+
+    fn fields(value: &str) -> Vec<String> {
+        vec!["f".to_string(), value.to_string()]
+    }
+
+    fn ids(stream: &Stream) -> Vec<StreamId> {
+        stream
+            .range(StreamId::MIN, StreamId::MAX)
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_and_order() {
+        assert_eq!(StreamId::parse("5-3", 0), Some(id(5, 3)));
+        assert_eq!(StreamId::parse("5", u64::MAX), Some(id(5, u64::MAX)));
+        assert_eq!(StreamId::parse("5-x", 0), None);
+        assert_eq!(StreamId::parse("-1", 0), None);
+        assert!(id(1, 9) < id(2, 0));
+        assert_eq!(id(1, u64::MAX).next(), Some(id(2, 0)));
+        assert_eq!(id(2, 0).prev(), Some(id(1, u64::MAX)));
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(id(7, 1).to_string(), "7-1");
+    }
+
+    #[test]
+    fn test_auto_ids() {
+        let mut stream = Stream::new();
+        assert_eq!(stream.auto_id(10), Some(id(10, 0)));
+        stream.add(id(10, 0), fields("a"));
+        // The clock went backwards
+        assert_eq!(stream.auto_id(5), Some(id(10, 1)));
+        assert_eq!(stream.auto_seq_id(10), Some(id(10, 1)));
+        assert_eq!(stream.auto_seq_id(11), Some(id(11, 0)));
+    }
+
+    #[test]
+    fn test_add_spans_nodes_and_ranges() {
+        let mut stream = Stream::new();
+        for n in 1..=250 {
+            stream.add(id(n, 0), fields(&n.to_string()));
+        }
+        assert_eq!(stream.len(), 250);
+        assert_eq!(stream.nodes(), 3);
+        let forward: Vec<StreamId> = stream
+            .range(id(99, 0), id(102, 0))
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(forward, vec![id(99, 0), id(100, 0), id(101, 0), id(102, 0)]);
+        let back: Vec<StreamId> = stream
+            .range(id(99, 0), id(102, 0))
+            .rev()
+            .take(2)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(back, vec![id(102, 0), id(101, 0)]);
+        assert_eq!(stream.range(id(5, 0), id(4, 0)).count(), 0);
+        assert_eq!(stream.get(id(150, 0)), Some(&fields("150")[..]));
+    }
+
+    #[test]
+    fn test_delete_frees_empty_nodes() {
+        let mut stream = Stream::new();
+        for n in 1..=150 {
+            stream.add(id(n, 0), fields("v"));
+        }
+        let full = stream.memory_usage();
+        assert!(stream.delete(id(120, 0)));
+        assert!(!stream.delete(id(120, 0)));
+        assert!(!stream.delete(id(999, 0)));
+        assert_eq!(stream.len(), 149);
+        assert_eq!(stream.max_deleted_id(), id(120, 0));
+        assert_eq!(stream.get(id(120, 0)), None);
+        for n in 101..=150 {
+            stream.delete(id(n, 0));
+        }
+        assert_eq!(stream.nodes(), 1);
+        assert!(stream.memory_usage() < full);
+        assert_eq!(stream.last_id(), id(150, 0));
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut stream = Stream::new();
+        for n in 1..=250 {
+            stream.add(id(n, 0), fields("v"));
+        }
+        // Approximate trimming stops at node boundaries
+        assert_eq!(stream.trim(Trim::MaxLen(120), true, None), 100);
+        assert_eq!(stream.len(), 150);
+        assert_eq!(stream.trim(Trim::MaxLen(120), false, None), 30);
+        assert_eq!(stream.first().map(|(id, _)| id), Some(id(131, 0)));
+        assert_eq!(stream.trim(Trim::MinId(id(140, 0)), false, Some(5)), 5);
+        assert_eq!(stream.trim(Trim::MinId(id(140, 0)), false, None), 4);
+        assert_eq!(ids(&stream).len(), 111);
+        assert_eq!(stream.trim(Trim::MaxLen(0), false, None), 111);
+        assert!(stream.is_empty());
+        assert_eq!(stream.nodes(), 0);
+        assert_eq!(stream.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_lag() {
+        let mut stream = Stream::new();
+        for n in 1..=5 {
+            stream.add(id(n, 0), fields("v"));
+        }
+        let mut group = ConsumerGroup::new(StreamId::MIN, None);
+        assert_eq!(stream.lag(&group), Some(5));
+        group.last_id = id(2, 0);
+        group.entries_read = stream.entries_read_at(id(2, 0), Some(0), id(0, 0));
+        assert_eq!(group.entries_read, Some(2));
+        assert_eq!(stream.lag(&group), Some(3));
+        stream.delete(id(4, 0));
+        assert_eq!(stream.lag(&group), None);
+    }
+}
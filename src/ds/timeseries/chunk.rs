@@ -0,0 +1,276 @@
+use super::Sample;
+
+/// Appends bits to a byte vector, most significant first.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    len: u64,
+}
+
+impl BitWriter {
+    /// Writes the low `bits` bits of `value`.
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: u64,
+}
+
+impl BitReader<'_> {
+    /// The next `bits` bits, None past the end.
+    fn read(&mut self, bits: u32) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.bytes.get((self.position / 8) as usize)?;
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = value << 1 | u64::from(bit);
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    /// The number of leading 1 bits, up to `max`, and the 0 ending them.
+    fn prefix(&mut self, max: u32) -> Option<u32> {
+        let mut ones = 0;
+        while ones < max && self.read(1)? == 1 {
+            ones += 1;
+        }
+        Some(ones)
+    }
+}
+
+/// The widths a delta of deltas can be written in, after a prefix of as
+/// many 1 bits as the width's place, and a 0 unless it is the last.
+const DELTA_WIDTHS: [u32; 4] = [7, 9, 12, 64];
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+/// Samples in ascending timestamp order, compressed like Gorilla does it:
+/// each timestamp as the change from the previous gap, which for regular
+/// samples is 0 and takes a single bit, and each value XORed with the
+/// previous one, keeping only the bits that differ.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct Chunk {
+    bits: BitWriter,
+    count: u32,
+    first: u64,
+    last: u64,
+    last_delta: u64,
+    last_value: u64,
+    /// Leading and trailing zeros of the last written XOR, which a value
+    /// whose meaningful bits fit inside reuses
+    window: Option<(u32, u32)>,
+}
+
+impl Chunk {
+    /// Chunks of at most about `size` bytes holding `samples`.
+    pub(super) fn from_samples(samples: &[Sample], size: usize) -> Vec<Chunk> {
+        let mut chunks = vec![Chunk::default()];
+        for &(timestamp, value) in samples {
+            let chunk = chunks.last_mut().expect("there is a chunk");
+            if chunk.bytes() >= size {
+                chunks.push(Chunk::default());
+            }
+            chunks
+                .last_mut()
+                .expect("there is a chunk")
+                .push(timestamp, value);
+        }
+        chunks
+    }
+
+    /// Appends a sample, which must be later than the last one.
+    pub(super) fn push(&mut self, timestamp: u64, value: f64) {
+        let value = value.to_bits();
+        if self.count == 0 {
+            self.bits.write(timestamp, 64);
+            self.bits.write(value, 64);
+            self.first = timestamp;
+        } else {
+            let delta = timestamp - self.last;
+            let zigzagged = zigzag(delta.wrapping_sub(self.last_delta) as i64);
+            self.write_delta(zigzagged);
+            self.write_value(value ^ self.last_value);
+            self.last_delta = delta;
+        }
+        self.last = timestamp;
+        self.last_value = value;
+        self.count += 1;
+    }
+
+    fn write_delta(&mut self, zigzagged: u64) {
+        if zigzagged == 0 {
+            self.bits.write(0, 1);
+            return;
+        }
+        for (place, width) in DELTA_WIDTHS.into_iter().enumerate() {
+            let place = place as u32 + 1;
+            if width == 64 || zigzagged < 1 << width {
+                let prefix = if width == 64 {
+                    (1 << place) - 1
+                } else {
+                    ((1 << place) - 1) << 1
+                };
+                let prefix_bits = if width == 64 { place } else { place + 1 };
+                self.bits.write(prefix, prefix_bits);
+                self.bits.write(zigzagged, width);
+                return;
+            }
+        }
+    }
+
+    fn write_value(&mut self, xor: u64) {
+        if xor == 0 {
+            self.bits.write(0, 1);
+            return;
+        }
+        let leading = xor.leading_zeros().min(31);
+        let trailing = xor.trailing_zeros();
+        match self.window {
+            Some((l, t)) if leading >= l && trailing >= t => {
+                self.bits.write(0b10, 2);
+                self.bits.write(xor >> t, 64 - l - t);
+            }
+            _ => {
+                let meaningful = 64 - leading - trailing;
+                self.bits.write(0b11, 2);
+                self.bits.write(u64::from(leading), 5);
+                self.bits.write(u64::from(meaningful - 1), 6);
+                self.bits.write(xor >> trailing, meaningful);
+                self.window = Some((leading, trailing));
+            }
+        }
+    }
+
+    /// Every sample, or None if the bits don't decode.
+    pub(super) fn samples(&self) -> Option<Vec<Sample>> {
+        decode(&self.bits.bytes, self.count)
+    }
+
+    pub(super) fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub(super) fn first(&self) -> u64 {
+        self.first
+    }
+
+    pub(super) fn last(&self) -> u64 {
+        self.last
+    }
+
+    pub(super) fn last_value(&self) -> f64 {
+        f64::from_bits(self.last_value)
+    }
+
+    pub(super) fn bytes(&self) -> usize {
+        self.bits.bytes.len()
+    }
+
+    pub(super) fn data(&self) -> &[u8] {
+        &self.bits.bytes
+    }
+}
+
+/// Reads `count` samples back from a chunk's bytes.
+pub(super) fn decode(bytes: &[u8], count: u32) -> Option<Vec<Sample>> {
+    let mut reader = BitReader { bytes, position: 0 };
+    let mut samples = Vec::with_capacity(count as usize);
+    if count == 0 {
+        return Some(samples);
+    }
+    let mut timestamp = reader.read(64)?;
+    let mut value = reader.read(64)?;
+    let (mut delta, mut window) = (0u64, (0, 0));
+    samples.push((timestamp, f64::from_bits(value)));
+    for _ in 1..count {
+        let place = reader.prefix(DELTA_WIDTHS.len() as u32)?;
+        if place > 0 {
+            let zigzagged = reader.read(DELTA_WIDTHS[place as usize - 1])?;
+            delta = delta.wrapping_add(unzigzag(zigzagged) as u64);
+        }
+        timestamp = timestamp.checked_add(delta)?;
+        match reader.prefix(2)? {
+            0 => {}
+            1 => {
+                let (l, t) = window;
+                value ^= reader.read(64 - l - t)? << t;
+            }
+            _ => {
+                let leading = reader.read(5)? as u32;
+                let meaningful = reader.read(6)? as u32 + 1;
+                let trailing = 64u32.checked_sub(leading + meaningful)?;
+                window = (leading, trailing);
+                value ^= reader.read(meaningful)? << trailing;
+            }
+        }
+        samples.push((timestamp, f64::from_bits(value)));
+    }
+    Some(samples)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let samples: Vec<Sample> = vec![
+            (1000, 1.5),
+            (2000, 1.5),
+            (3000, 2.0),
+            (3001, -7.25),
+            (9000, 1e300),
+            (9000 + (1 << 40), f64::MIN_POSITIVE),
+            (u64::MAX, 0.0),
+        ];
+        let chunks = Chunk::from_samples(&samples, 4096);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].samples(), Some(samples));
+    }
+
+    #[test]
+    fn test_regular_samples_compress() {
+        let samples: Vec<Sample> = (0..1000).map(|i| (i * 1000, 42.0)).collect();
+        let chunk = &Chunk::from_samples(&samples, 4096)[0];
+        // 16 bytes for the first sample, then two bits for each of the rest
+        assert!(chunk.bytes() < 16 + 1000 * 2 / 8 + 4, "{}", chunk.bytes());
+        assert_eq!(chunk.samples(), Some(samples));
+    }
+
+    #[test]
+    fn test_splits_into_chunks() {
+        let samples: Vec<Sample> = (0..1000)
+            .map(|i| (i * 37 + i * i, i as f64 / 3.0))
+            .collect();
+        let chunks = Chunk::from_samples(&samples, 256);
+        assert!(chunks.len() > 1);
+        let decoded: Vec<Sample> = chunks.iter().flat_map(|c| c.samples().unwrap()).collect();
+        assert_eq!(decoded, samples);
+    }
+
+    #[test]
+    fn test_truncated_bytes() {
+        let samples: Vec<Sample> = (0..10).map(|i| (i * 10, i as f64)).collect();
+        let chunk = &Chunk::from_samples(&samples, 4096)[0];
+        assert_eq!(decode(&chunk.data()[..chunk.bytes() - 2], 10), None);
+    }
+}
@@ -0,0 +1,718 @@
+//! timeseries -- samples over time, kept in compressed chunks
+//!
+//! A series is a run of chunks in timestamp order, each a few kilobytes of
+//! Gorilla-compressed samples. Samples arriving in order are appended to
+//! the last chunk; a late one, or one for a timestamp already there,
+//! decodes the chunk it falls in and encodes it again. Samples older than
+//! the retention, counted back from the newest, are dropped.
+//!
+//! Compaction rules downsample a series into others, like
+//! RedisTimeSeries: once a sample opens a new bucket, the bucket before
+//! it is aggregated and added to the destination, and a late sample for
+//! an earlier bucket recomputes that one.
+
+mod chunk;
+
+use self::chunk::Chunk;
+use crate::ds::codec::{Reader, Writer};
+
+/// A timestamp in milliseconds and its value.
+pub type Sample = (u64, f64);
+
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+const SERIES_OVERHEAD: usize = 96;
+const CHUNK_OVERHEAD: usize = 48;
+const RULE_OVERHEAD: usize = 48;
+
+/// What happens to a sample for a timestamp the series already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Refuse it
+    Block,
+    First,
+    Last,
+    Min,
+    Max,
+    Sum,
+}
+
+impl DuplicatePolicy {
+    pub fn parse(name: &str) -> Option<DuplicatePolicy> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "block" => DuplicatePolicy::Block,
+            "first" => DuplicatePolicy::First,
+            "last" => DuplicatePolicy::Last,
+            "min" => DuplicatePolicy::Min,
+            "max" => DuplicatePolicy::Max,
+            "sum" => DuplicatePolicy::Sum,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DuplicatePolicy::Block => "block",
+            DuplicatePolicy::First => "first",
+            DuplicatePolicy::Last => "last",
+            DuplicatePolicy::Min => "min",
+            DuplicatePolicy::Max => "max",
+            DuplicatePolicy::Sum => "sum",
+        }
+    }
+
+    /// The value kept when `new` arrives for a timestamp holding `old`.
+    fn resolve(self, old: f64, new: f64) -> Option<f64> {
+        match self {
+            DuplicatePolicy::Block => None,
+            DuplicatePolicy::First => Some(old),
+            DuplicatePolicy::Last => Some(new),
+            DuplicatePolicy::Min => Some(old.min(new)),
+            DuplicatePolicy::Max => Some(old.max(new)),
+            DuplicatePolicy::Sum => Some(old + new),
+        }
+    }
+}
+
+/// How the samples of a bucket are summed up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Avg,
+    Sum,
+    Min,
+    Max,
+    /// The maximum less the minimum
+    Range,
+    Count,
+    First,
+    Last,
+}
+
+impl Aggregation {
+    pub fn parse(name: &str) -> Option<Aggregation> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "avg" => Aggregation::Avg,
+            "sum" => Aggregation::Sum,
+            "min" => Aggregation::Min,
+            "max" => Aggregation::Max,
+            "range" => Aggregation::Range,
+            "count" => Aggregation::Count,
+            "first" => Aggregation::First,
+            "last" => Aggregation::Last,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aggregation::Avg => "avg",
+            Aggregation::Sum => "sum",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::Range => "range",
+            Aggregation::Count => "count",
+            Aggregation::First => "first",
+            Aggregation::Last => "last",
+        }
+    }
+
+    /// The aggregate of `samples`, None if there are none.
+    pub fn apply(self, samples: &[Sample]) -> Option<f64> {
+        let (&(_, first), &(_, last)) = (samples.first()?, samples.last()?);
+        let values = samples.iter().map(|(_, value)| *value);
+        let sum = || values.clone().sum::<f64>();
+        let min = || values.clone().fold(f64::INFINITY, f64::min);
+        let max = || values.clone().fold(f64::NEG_INFINITY, f64::max);
+        Some(match self {
+            Aggregation::Avg => sum() / samples.len() as f64,
+            Aggregation::Sum => sum(),
+            Aggregation::Min => min(),
+            Aggregation::Max => max(),
+            Aggregation::Range => max() - min(),
+            Aggregation::Count => samples.len() as f64,
+            Aggregation::First => first,
+            Aggregation::Last => last,
+        })
+    }
+}
+
+/// The start of the `bucket` milliseconds long bucket holding `timestamp`,
+/// with buckets starting at `align` and every `bucket` before or after.
+/// The first bucket starts no earlier than 0.
+pub fn bucket_start(timestamp: u64, bucket: u64, align: u64) -> u64 {
+    let offset = (i128::from(timestamp) - i128::from(align)).rem_euclid(i128::from(bucket));
+    timestamp.saturating_sub(offset as u64)
+}
+
+/// One sample per bucket holding any of `samples`, stamped with the
+/// bucket's start.
+pub fn aggregate(
+    samples: &[Sample],
+    aggregation: Aggregation,
+    bucket: u64,
+    align: u64,
+) -> Vec<Sample> {
+    samples
+        .chunk_by(|a, b| bucket_start(a.0, bucket, align) == bucket_start(b.0, bucket, align))
+        .filter_map(|samples| {
+            let start = bucket_start(samples[0].0, bucket, align);
+            Some((start, aggregation.apply(samples)?))
+        })
+        .collect()
+}
+
+/// A `label=value` style matcher, as TS.MRANGE and TS.QUERYINDEX take
+/// them. `label=` matches series without the label and `label!=` those
+/// with it; `label=(a,b)` matches either value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelFilter {
+    label: String,
+    equal: bool,
+    values: Vec<String>,
+}
+
+impl LabelFilter {
+    pub fn parse(expression: &str) -> Option<LabelFilter> {
+        let (label, equal, value) = match expression.split_once("!=") {
+            Some((label, value)) => (label, false, value),
+            None => {
+                let (label, value) = expression.split_once('=')?;
+                (label, true, value)
+            }
+        };
+        if label.is_empty() {
+            return None;
+        }
+        let values = match value.strip_prefix('(') {
+            Some(list) => list
+                .strip_suffix(')')?
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+            None if value.is_empty() => Vec::new(),
+            None => vec![value.to_string()],
+        };
+        Some(LabelFilter {
+            label: label.to_string(),
+            equal,
+            values,
+        })
+    }
+
+    /// Whether this filter alone picks out series, rather than only
+    /// ruling some out. A query needs at least one.
+    pub fn is_matcher(&self) -> bool {
+        self.equal && !self.values.is_empty()
+    }
+
+    pub fn matches(&self, labels: &[(String, String)]) -> bool {
+        let value = labels
+            .iter()
+            .find(|(label, _)| *label == self.label)
+            .map(|(_, value)| value);
+        let found = match value {
+            None => false,
+            Some(_) if self.values.is_empty() => true,
+            Some(value) => self.values.contains(value),
+        };
+        // `label=` asks for the label to be missing, which `found` is the
+        // opposite of
+        if self.values.is_empty() {
+            found != self.equal
+        } else {
+            found == self.equal
+        }
+    }
+}
+
+/// Downsamples every sample of a series into `destination`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub destination: String,
+    pub aggregation: Aggregation,
+    pub bucket: u64,
+    pub align: u64,
+    /// The start of the bucket still taking samples
+    current: Option<u64>,
+}
+
+impl Rule {
+    pub fn new(destination: String, aggregation: Aggregation, bucket: u64, align: u64) -> Rule {
+        Rule {
+            destination,
+            aggregation,
+            bucket,
+            align,
+            current: None,
+        }
+    }
+}
+
+/// Why a sample wasn't added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddError {
+    /// It is older than the retention allows
+    TooOld,
+    /// Its timestamp is taken and the policy is `Block`
+    Duplicate,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSeries {
+    /// Milliseconds of samples kept back from the newest, 0 for all
+    retention: u64,
+    chunk_size: usize,
+    duplicate_policy: DuplicatePolicy,
+    labels: Vec<(String, String)>,
+    /// Never empty, the first chunk of an empty series holds nothing
+    chunks: Vec<Chunk>,
+    rules: Vec<Rule>,
+    /// The series this one is compacted from
+    source: Option<String>,
+}
+
+impl TimeSeries {
+    pub fn new(
+        retention: u64,
+        chunk_size: usize,
+        duplicate_policy: DuplicatePolicy,
+        labels: Vec<(String, String)>,
+    ) -> TimeSeries {
+        TimeSeries {
+            retention,
+            chunk_size,
+            duplicate_policy,
+            labels,
+            chunks: vec![Chunk::default()],
+            rules: Vec::new(),
+            source: None,
+        }
+    }
+
+    pub fn retention(&self) -> u64 {
+        self.retention
+    }
+
+    pub fn set_retention(&mut self, retention: u64) {
+        self.retention = retention;
+        self.trim();
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Takes effect for chunks started from now on.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size;
+    }
+
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
+
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+
+    pub fn set_labels(&mut self, labels: Vec<(String, String)>) {
+        self.labels = labels;
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn set_source(&mut self, source: Option<String>) {
+        self.source = source;
+    }
+
+    /// Adds a rule, replacing any with the same destination.
+    pub fn add_rule(&mut self, rule: Rule) {
+        self.remove_rule(&rule.destination);
+        self.rules.push(rule);
+    }
+
+    /// Removes the rule into `destination`, false if there is none.
+    pub fn remove_rule(&mut self, destination: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|rule| rule.destination != destination);
+        self.rules.len() < before
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.chunks
+            .iter()
+            .map(|chunk| u64::from(chunk.count()))
+            .sum()
+    }
+
+    pub fn chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn first_timestamp(&self) -> Option<u64> {
+        let chunk = &self.chunks[0];
+        (chunk.count() > 0).then(|| chunk.first())
+    }
+
+    /// The newest sample.
+    pub fn last(&self) -> Option<Sample> {
+        let chunk = self.chunks.last().expect("a series has a chunk");
+        (chunk.count() > 0).then(|| (chunk.last(), chunk.last_value()))
+    }
+
+    /// Adds a sample, settling a duplicate timestamp with `on_duplicate`
+    /// or else the series' own policy. Returns what the compaction rules
+    /// have for their destinations: each a destination and a sample for
+    /// it.
+    pub fn add(
+        &mut self,
+        timestamp: u64,
+        value: f64,
+        on_duplicate: Option<DuplicatePolicy>,
+    ) -> Result<Vec<(String, Sample)>, AddError> {
+        if let Some((last, _)) = self.last()
+            && self.retention > 0
+            && timestamp < last.saturating_sub(self.retention)
+        {
+            return Err(AddError::TooOld);
+        }
+        let chunk = self.chunks.last_mut().expect("a series has a chunk");
+        if chunk.count() == 0 || timestamp > chunk.last() {
+            if chunk.bytes() >= self.chunk_size {
+                self.chunks.push(Chunk::default());
+            }
+            let chunk = self.chunks.last_mut().expect("a series has a chunk");
+            chunk.push(timestamp, value);
+        } else {
+            let policy = on_duplicate.unwrap_or(self.duplicate_policy);
+            self.upsert(timestamp, value, policy)?;
+        }
+        self.trim();
+        Ok(self.compact(timestamp))
+    }
+
+    /// Adds a sample at or before the newest one, encoding the chunk it
+    /// falls in again.
+    fn upsert(
+        &mut self,
+        timestamp: u64,
+        value: f64,
+        policy: DuplicatePolicy,
+    ) -> Result<(), AddError> {
+        let index = self
+            .chunks
+            .iter()
+            .rposition(|chunk| chunk.first() <= timestamp)
+            .unwrap_or(0);
+        let mut samples = self.chunks[index].samples().expect("chunks decode");
+        match samples.binary_search_by_key(&timestamp, |(timestamp, _)| *timestamp) {
+            Ok(i) => {
+                samples[i].1 = policy
+                    .resolve(samples[i].1, value)
+                    .ok_or(AddError::Duplicate)?;
+            }
+            Err(i) => samples.insert(i, (timestamp, value)),
+        }
+        let chunks = Chunk::from_samples(&samples, self.chunk_size);
+        self.chunks.splice(index..=index, chunks);
+        Ok(())
+    }
+
+    /// Drops what has fallen out of the retention.
+    fn trim(&mut self) {
+        let Some((last, _)) = self.last() else {
+            return;
+        };
+        if self.retention == 0 {
+            return;
+        }
+        let cutoff = last.saturating_sub(self.retention);
+        while self.chunks[0].last() < cutoff {
+            self.chunks.remove(0);
+        }
+        if self.chunks[0].first() < cutoff {
+            let mut samples = self.chunks[0].samples().expect("chunks decode");
+            samples.retain(|(timestamp, _)| *timestamp >= cutoff);
+            let chunks = Chunk::from_samples(&samples, self.chunk_size);
+            self.chunks.splice(0..=0, chunks);
+        }
+    }
+
+    /// What the rules make of a sample just added at `timestamp`.
+    fn compact(&mut self, timestamp: u64) -> Vec<(String, Sample)> {
+        let mut compacted = Vec::new();
+        for i in 0..self.rules.len() {
+            let rule = &self.rules[i];
+            let bucket = bucket_start(timestamp, rule.bucket, rule.align);
+            let finished = match rule.current {
+                // The sample opens a new bucket, closing the current one
+                Some(current) if bucket > current => Some(current),
+                // A late sample changes a bucket already closed
+                Some(current) if bucket < current => Some(bucket),
+                _ => None,
+            };
+            if let Some(start) = finished {
+                let end = start.saturating_add(rule.bucket - 1);
+                if let Some(value) = rule.aggregation.apply(&self.range(start, end)) {
+                    compacted.push((rule.destination.clone(), (start, value)));
+                }
+            }
+            let rule = &mut self.rules[i];
+            rule.current = rule.current.max(Some(bucket));
+        }
+        compacted
+    }
+
+    /// The samples from `from` to `to`, both included.
+    pub fn range(&self, from: u64, to: u64) -> Vec<Sample> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.count() > 0 && chunk.last() >= from && chunk.first() <= to)
+            .flat_map(|chunk| chunk.samples().expect("chunks decode"))
+            .filter(|(timestamp, _)| (from..=to).contains(timestamp))
+            .collect()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let chunks: usize = self
+            .chunks
+            .iter()
+            .map(|chunk| CHUNK_OVERHEAD + chunk.bytes())
+            .sum();
+        let labels: usize = self
+            .labels
+            .iter()
+            .map(|(label, value)| label.len() + value.len())
+            .sum();
+        let rules: usize = self
+            .rules
+            .iter()
+            .map(|rule| RULE_OVERHEAD + rule.destination.len())
+            .sum();
+        SERIES_OVERHEAD + chunks + labels + rules + self.source.as_ref().map_or(0, String::len)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer
+            .u64(self.retention)
+            .u64(self.chunk_size as u64)
+            .bytes(self.duplicate_policy.name().as_bytes())
+            .u64(self.labels.len() as u64);
+        for (label, value) in &self.labels {
+            writer.bytes(label.as_bytes()).bytes(value.as_bytes());
+        }
+        match &self.source {
+            Some(source) => writer.u8(1).bytes(source.as_bytes()),
+            None => writer.u8(0),
+        };
+        writer.u64(self.rules.len() as u64);
+        for rule in &self.rules {
+            writer
+                .bytes(rule.destination.as_bytes())
+                .bytes(rule.aggregation.name().as_bytes())
+                .u64(rule.bucket)
+                .u64(rule.align);
+            match rule.current {
+                Some(current) => writer.u8(1).u64(current),
+                None => writer.u8(0),
+            };
+        }
+        writer.u64(self.chunks.len() as u64);
+        for chunk in &self.chunks {
+            writer.u32(chunk.count()).bytes(chunk.data());
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<TimeSeries> {
+        fn string(reader: &mut Reader<'_>) -> Option<String> {
+            String::from_utf8(reader.bytes()?.to_vec()).ok()
+        }
+        let mut reader = Reader::new(bytes);
+        let retention = reader.u64()?;
+        let chunk_size = usize::try_from(reader.u64()?).ok()?;
+        let duplicate_policy = DuplicatePolicy::parse(&string(&mut reader)?)?;
+        let mut labels = Vec::new();
+        for _ in 0..reader.u64()? {
+            labels.push((string(&mut reader)?, string(&mut reader)?));
+        }
+        let source = match reader.u8()? {
+            0 => None,
+            _ => Some(string(&mut reader)?),
+        };
+        let mut rules = Vec::new();
+        for _ in 0..reader.u64()? {
+            let destination = string(&mut reader)?;
+            let aggregation = Aggregation::parse(&string(&mut reader)?)?;
+            let (bucket, align) = (reader.u64().filter(|b| *b > 0)?, reader.u64()?);
+            let current = match reader.u8()? {
+                0 => None,
+                _ => Some(reader.u64()?),
+            };
+            rules.push(Rule {
+                destination,
+                aggregation,
+                bucket,
+                align,
+                current,
+            });
+        }
+        let mut chunks = Vec::new();
+        for _ in 0..reader.u64()? {
+            let count = reader.u32()?;
+            // Encoding the samples again checks they decode and brings
+            // back what appending to the chunk needs
+            let samples = chunk::decode(reader.bytes()?, count)?;
+            chunks.extend(Chunk::from_samples(&samples, usize::MAX));
+        }
+        if chunks.is_empty() || !reader.is_empty() {
+            return None;
+        }
+        Some(TimeSeries {
+            retention,
+            chunk_size,
+            duplicate_policy,
+            labels,
+            chunks,
+            rules,
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn series() -> TimeSeries {
+        TimeSeries::new(0, DEFAULT_CHUNK_SIZE, DuplicatePolicy::Block, Vec::new())
+    }
+
+    #[test]
+    fn test_add_and_range() {
+        let mut series = series();
+        for (timestamp, value) in [(10, 1.0), (30, 3.0), (20, 2.0), (5, 0.5)] {
+            assert_eq!(series.add(timestamp, value, None), Ok(Vec::new()));
+        }
+        assert_eq!(series.range(0, 25), [(5, 0.5), (10, 1.0), (20, 2.0)]);
+        assert_eq!(series.last(), Some((30, 3.0)));
+        assert_eq!(series.first_timestamp(), Some(5));
+        assert_eq!(series.samples(), 4);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut series = series();
+        series.add(10, 1.0, None).unwrap();
+        series.add(20, 1.0, None).unwrap();
+        assert_eq!(series.add(10, 5.0, None), Err(AddError::Duplicate));
+        series.add(10, 5.0, Some(DuplicatePolicy::Sum)).unwrap();
+        series.add(20, 0.5, Some(DuplicatePolicy::Min)).unwrap();
+        series.set_duplicate_policy(DuplicatePolicy::Last);
+        series.add(20, 7.0, None).unwrap();
+        assert_eq!(series.range(0, u64::MAX), [(10, 6.0), (20, 7.0)]);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut series = TimeSeries::new(100, 64, DuplicatePolicy::Block, Vec::new());
+        for timestamp in 0..200 {
+            series.add(timestamp, timestamp as f64, None).unwrap();
+        }
+        assert_eq!(series.first_timestamp(), Some(99));
+        assert_eq!(series.samples(), 101);
+        assert!(series.chunks() > 1);
+        assert_eq!(series.add(50, 1.0, None), Err(AddError::TooOld));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let samples = [(0, 1.0), (5, 3.0), (10, 2.0), (25, 4.0), (29, -4.0)];
+        assert_eq!(
+            aggregate(&samples, Aggregation::Avg, 10, 0),
+            [(0, 2.0), (10, 2.0), (20, 0.0)]
+        );
+        assert_eq!(
+            aggregate(&samples, Aggregation::Range, 10, 0),
+            [(0, 2.0), (10, 0.0), (20, 8.0)]
+        );
+        assert_eq!(
+            aggregate(&samples, Aggregation::Count, 10, 5),
+            [(0, 1.0), (5, 2.0), (25, 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_compaction() {
+        let mut series = series();
+        series.add_rule(Rule::new("dest".to_string(), Aggregation::Sum, 10, 0));
+        assert_eq!(series.add(1, 1.0, None), Ok(Vec::new()));
+        assert_eq!(series.add(5, 2.0, None), Ok(Vec::new()));
+        assert_eq!(
+            series.add(12, 4.0, None),
+            Ok(vec![("dest".to_string(), (0, 3.0))])
+        );
+        assert_eq!(
+            series.add(35, 1.0, None),
+            Ok(vec![("dest".to_string(), (10, 4.0))])
+        );
+        // A late sample recomputes its closed bucket
+        assert_eq!(
+            series.add(7, 1.0, None),
+            Ok(vec![("dest".to_string(), (0, 4.0))])
+        );
+        assert!(series.remove_rule("dest"));
+        assert!(!series.remove_rule("dest"));
+    }
+
+    #[test]
+    fn test_label_filters() {
+        let labels = vec![
+            ("region".to_string(), "eu".to_string()),
+            ("host".to_string(), "a".to_string()),
+        ];
+        let matches = |expression: &str| LabelFilter::parse(expression).unwrap().matches(&labels);
+        assert!(matches("region=eu"));
+        assert!(!matches("region=us"));
+        assert!(matches("region=(us,eu)"));
+        assert!(matches("region!=us"));
+        assert!(!matches("region!=(us,eu)"));
+        assert!(matches("kind="));
+        assert!(!matches("host="));
+        assert!(matches("host!="));
+        assert!(!matches("kind!="));
+        assert!(LabelFilter::parse("region").is_none());
+        assert!(LabelFilter::parse("=eu").is_none());
+        assert!(!LabelFilter::parse("host!=a").unwrap().is_matcher());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut series = TimeSeries::new(
+            1000,
+            128,
+            DuplicatePolicy::Max,
+            vec![("a".to_string(), "b".to_string())],
+        );
+        series.set_source(Some("raw".to_string()));
+        series.add_rule(Rule::new("avg".to_string(), Aggregation::Avg, 60, 7));
+        for timestamp in 0..300 {
+            series
+                .add(timestamp * 3, (timestamp % 7) as f64, None)
+                .unwrap();
+        }
+        let bytes = series.to_bytes();
+        assert_eq!(TimeSeries::from_bytes(&bytes), Some(series));
+        assert_eq!(TimeSeries::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! topk -- the heaviest hitters of a stream, with HeavyKeeper
+//!
+//! Like RedisBloom, counts are kept in `depth` rows of `width` buckets,
+//! each holding one item's fingerprint and a count. An item landing on a
+//! bucket of another item decays that count with probability
+//! `decay ^ count`, and takes the bucket over once it reaches 0, so small
+//! counts are pushed out while large ones stay. The `k` items with the
+//! highest counts are kept by name alongside.
+//!
+//! RedisBloom decides each decay with a random number; here the odds are
+//! drawn from a hash of the bucket and the item instead, so the same adds
+//! always give the same list.
+
+use crate::ds::codec::{Reader, Writer};
+use crate::ds::hyperloglog::murmurhash64a;
+
+pub const DEFAULT_WIDTH: u32 = 8;
+pub const DEFAULT_DEPTH: u32 = 7;
+pub const DEFAULT_DECAY: f64 = 0.9;
+const FINGERPRINT_SEED: u64 = 1919;
+const DECAY_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+const OVERHEAD: usize = 48;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Bucket {
+    fingerprint: u32,
+    count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    item: String,
+    count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopK {
+    k: u32,
+    width: u32,
+    depth: u32,
+    decay: f64,
+    buckets: Vec<Bucket>,
+    /// At most `k` items, in no particular order
+    top: Vec<Entry>,
+}
+
+impl TopK {
+    pub fn new(k: u32, width: u32, depth: u32, decay: f64) -> TopK {
+        TopK {
+            k,
+            width,
+            depth,
+            decay,
+            buckets: vec![Bucket::default(); width as usize * depth as usize],
+            top: Vec::new(),
+        }
+    }
+
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn decay(&self) -> f64 {
+        self.decay
+    }
+
+    /// Whether a bucket holding `count` of another item decays, as a
+    /// deterministic draw for this bucket, count and item.
+    fn decays(&self, cell: usize, count: u32, fingerprint: u32) -> bool {
+        let mut seed = [0; 16];
+        seed[..8].copy_from_slice(&(cell as u64).to_le_bytes());
+        seed[8..12].copy_from_slice(&count.to_le_bytes());
+        seed[12..].copy_from_slice(&fingerprint.to_le_bytes());
+        let draw = murmurhash64a(&seed, DECAY_SEED) as f64 / u64::MAX as f64;
+        draw < self.decay.powf(f64::from(count))
+    }
+
+    /// Adds `by` to `item`. Returns the item it pushed out of the top `k`,
+    /// if any.
+    pub fn add(&mut self, item: &str, by: u32) -> Option<String> {
+        let fingerprint = murmurhash64a(item.as_bytes(), FINGERPRINT_SEED) as u32;
+        let mut max_count = 0;
+        for row in 0..self.depth {
+            let column = murmurhash64a(item.as_bytes(), u64::from(row)) % u64::from(self.width);
+            let cell = row as usize * self.width as usize + column as usize;
+            let bucket = self.buckets[cell];
+            if bucket.count == 0 || bucket.fingerprint == fingerprint {
+                let count = bucket.count.saturating_add(by);
+                self.buckets[cell] = Bucket { fingerprint, count };
+                max_count = max_count.max(count);
+                continue;
+            }
+            let mut count = bucket.count;
+            for left in (1..=by).rev() {
+                if self.decays(cell, count, fingerprint) {
+                    count -= 1;
+                    if count == 0 {
+                        count = left;
+                        self.buckets[cell].fingerprint = fingerprint;
+                        max_count = max_count.max(left);
+                        break;
+                    }
+                }
+            }
+            self.buckets[cell].count = count;
+        }
+
+        if let Some(entry) = self.top.iter_mut().find(|entry| entry.item == item) {
+            entry.count = entry.count.max(max_count);
+            return None;
+        }
+        if max_count == 0 {
+            return None;
+        }
+        let entry = Entry {
+            item: item.to_string(),
+            count: max_count,
+        };
+        if self.top.len() < self.k as usize {
+            self.top.push(entry);
+            return None;
+        }
+        let (min, _) = self
+            .top
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| entry.count)?;
+        if max_count <= self.top[min].count {
+            return None;
+        }
+        Some(std::mem::replace(&mut self.top[min], entry).item)
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.top.iter().any(|entry| entry.item == item)
+    }
+
+    /// The top items and their counts, largest first.
+    pub fn list(&self) -> Vec<(&str, u32)> {
+        let mut list: Vec<(&str, u32)> = self
+            .top
+            .iter()
+            .map(|entry| (entry.item.as_str(), entry.count))
+            .collect();
+        list.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        list
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let top: usize = self.top.iter().map(|entry| 8 + entry.item.len()).sum();
+        OVERHEAD + self.buckets.len() * 8 + top
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer
+            .u32(self.k)
+            .u32(self.width)
+            .u32(self.depth)
+            .f64(self.decay);
+        for bucket in &self.buckets {
+            writer.u32(bucket.fingerprint).u32(bucket.count);
+        }
+        writer.u64(self.top.len() as u64);
+        for entry in &self.top {
+            writer.bytes(entry.item.as_bytes()).u32(entry.count);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<TopK> {
+        let mut reader = Reader::new(bytes);
+        let (k, width, depth) = (reader.u32()?, reader.u32()?, reader.u32()?);
+        let decay = reader.f64()?;
+        let cells = u64::from(width) * u64::from(depth);
+        if cells * 8 > reader.remaining() as u64 {
+            return None;
+        }
+        let mut topk = TopK::new(k, width, depth, decay);
+        for bucket in topk.buckets.iter_mut() {
+            *bucket = Bucket {
+                fingerprint: reader.u32()?,
+                count: reader.u32()?,
+            };
+        }
+        for _ in 0..reader.u64()? {
+            let item = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+            topk.top.push(Entry {
+                item,
+                count: reader.u32()?,
+            });
+        }
+        reader.is_empty().then_some(topk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_heavy_hitters() {
+        let mut topk = TopK::new(3, 50, 4, 0.9);
+        for round in 0..100 {
+            for item in ["a", "b", "c"] {
+                topk.add(item, 1);
+            }
+            topk.add(&format!("noise{}", round), 1);
+        }
+        topk.add("a", 50);
+        let list = topk.list();
+        assert_eq!(list[0], ("a", 150));
+        let mut items: Vec<&str> = list.iter().map(|(item, _)| *item).collect();
+        items.sort_unstable();
+        assert_eq!(items, ["a", "b", "c"]);
+        assert!(topk.contains("b") && !topk.contains("noise3"));
+    }
+
+    #[test]
+    fn test_add_reports_expelled() {
+        let mut topk = TopK::new(1, 8, 7, 0.9);
+        assert_eq!(topk.add("a", 1), None);
+        assert_eq!(topk.add("a", 1), None);
+        assert_eq!(topk.add("b", 5), Some("a".to_string()));
+        assert_eq!(topk.list(), [("b", 5)]);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let run = || {
+            let mut topk = TopK::new(5, 4, 3, 0.9);
+            for i in 0..500 {
+                topk.add(&format!("item{}", i % 37 * i % 11), 1);
+            }
+            topk
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut topk = TopK::new(2, 4, 3, 0.9);
+        topk.add("a", 3);
+        topk.add("b", 1);
+        let bytes = topk.to_bytes();
+        assert_eq!(TopK::from_bytes(&bytes), Some(topk));
+        assert_eq!(TopK::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+}
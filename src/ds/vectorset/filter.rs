@@ -0,0 +1,492 @@
+use crate::ds::json::Value;
+
+/// Parentheses and brackets nest at most this deep.
+const MAX_DEPTH: usize = 64;
+/// Expressions have at most this many operators, which bounds how deep
+/// evaluating them goes.
+const MAX_OPERATORS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    String(String),
+    Array(Vec<Expr>),
+    /// `.name`, a member of the attributes
+    Field(String),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+/// What an expression comes to.
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Number(f64),
+    String(String),
+    Array(Vec<Scalar>),
+}
+
+impl Scalar {
+    /// JSON numbers, strings and booleans, and arrays of those. Nulls and
+    /// objects have no value.
+    fn from_json(value: &Value) -> Option<Scalar> {
+        match value {
+            Value::Bool(b) => Some(Scalar::Number(f64::from(u8::from(*b)))),
+            Value::Integer(n) => Some(Scalar::Number(*n as f64)),
+            Value::Float(n) => Some(Scalar::Number(*n)),
+            Value::String(s) => Some(Scalar::String(s.clone())),
+            Value::Array(items) => items
+                .iter()
+                .map(Scalar::from_json)
+                .collect::<Option<_>>()
+                .map(Scalar::Array),
+            Value::Null | Value::Object(_) => None,
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        match self {
+            Scalar::Number(n) => *n != 0.0,
+            Scalar::String(s) => !s.is_empty(),
+            Scalar::Array(items) => !items.is_empty(),
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Scalar::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+fn boolean(b: bool) -> Scalar {
+    Scalar::Number(f64::from(u8::from(b)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Field(String),
+    Word(String),
+    Symbol(&'static str),
+}
+
+/// Operators, longest first so `**` isn't read as two `*`.
+const SYMBOLS: [&str; 19] = [
+    "||", "&&", "==", "!=", "<=", ">=", "**", "!", "<", ">", "+", "-", "*", "/", "%", "(", ")",
+    "[", "]",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Symbol(","));
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && next.is_some_and(|n| n.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric()
+                    || chars[i] == '.'
+                    || (matches!(chars[i], '+' | '-') && matches!(chars[i - 1], 'e' | 'E')))
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number '{}'", text))?;
+            tokens.push(Token::Number(number));
+        } else if c == '.' || c.is_alphabetic() || c == '_' {
+            let start = if c == '.' { i + 1 } else { i };
+            i = start;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            if c == '.' {
+                if name.is_empty() {
+                    return Err("expected a field name after '.'".to_string());
+                }
+                tokens.push(Token::Field(name));
+            } else {
+                tokens.push(Token::Word(name.to_lowercase()));
+            }
+        } else if c == '"' || c == '\'' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some(&q) if q == c => break,
+                    Some('\\') => {
+                        text.push(*chars.get(i + 1).ok_or("unterminated string")?);
+                        i += 2;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::String(text));
+            i += 1;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or_else(|| format!("unexpected character '{}'", c))?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+    operators: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Takes the next token if it is one of the `symbols`, or one of them
+    /// spelled as a word.
+    fn take(&mut self, symbols: &[(&str, Op)]) -> Option<Op> {
+        let op = symbols.iter().find_map(|&(name, op)| match self.peek()? {
+            Token::Symbol(symbol) if *symbol == name => Some(op),
+            Token::Word(word) if word == name => Some(op),
+            _ => None,
+        })?;
+        self.pos += 1;
+        Some(op)
+    }
+
+    fn take_symbol(&mut self, name: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(symbol)) if *symbol == name)
+            || matches!(self.peek(), Some(Token::Word(word)) if word == name);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, name: &str) -> Result<(), String> {
+        match self.take_symbol(name) {
+            true => Ok(()),
+            false => Err(format!("expected '{}'", name)),
+        }
+    }
+
+    /// Counts an operator towards `MAX_OPERATORS`.
+    fn operator(&mut self) -> Result<(), String> {
+        self.operators += 1;
+        match self.operators > MAX_OPERATORS {
+            true => Err("expression has too many operators".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    /// Binary operators of one precedence, left to right.
+    fn binary(
+        &mut self,
+        ops: &[(&str, Op)],
+        operand: fn(&mut Parser) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut left = operand(self)?;
+        while let Some(op) = self.take(ops) {
+            let right = operand(self)?;
+            self.operator()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("expression nests too deep".to_string());
+        }
+        let expr = self.binary(&[("||", Op::Or), ("or", Op::Or)], Parser::and);
+        self.depth -= 1;
+        expr
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&[("&&", Op::And), ("and", Op::And)], Parser::not)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.take_symbol("!") || self.take_symbol("not") {
+            self.operator()?;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        self.binary(&ops, Parser::membership)
+    }
+
+    fn membership(&mut self) -> Result<Expr, String> {
+        self.binary(&[("in", Op::In)], Parser::additive)
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        self.binary(&[("+", Op::Add), ("-", Op::Sub)], Parser::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let ops = [("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)];
+        self.binary(&ops, Parser::power)
+    }
+
+    /// `**`, which groups to the right.
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.unary()?;
+        if self.take_symbol("**") {
+            self.operator()?;
+            let exponent = self.power()?;
+            return Ok(Expr::Binary(Op::Pow, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.take_symbol("-") {
+            self.operator()?;
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned().ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::String(s) => Ok(Expr::String(s)),
+            Token::Field(name) => Ok(Expr::Field(name)),
+            Token::Word(word) if word == "true" => Ok(Expr::Number(1.0)),
+            Token::Word(word) if word == "false" => Ok(Expr::Number(0.0)),
+            Token::Symbol("(") => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Symbol("[") => {
+                let mut items = Vec::new();
+                if !self.take_symbol("]") {
+                    loop {
+                        items.push(self.or()?);
+                        if self.take_symbol("]") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Array(items))
+            }
+            Token::Word(word) => Err(format!("unexpected '{}'", word)),
+            Token::Symbol(symbol) => Err(format!("unexpected '{}'", symbol)),
+        }
+    }
+}
+
+/// A VSIM FILTER expression over the top level fields of an element's
+/// JSON attributes, like `.year >= 1980 and .genre in ["drama", "crime"]`.
+/// It has `and`, `or` and `not` (or `&&`, `||` and `!`), comparisons,
+/// `in` for array membership or substrings, and arithmetic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Filter, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            depth: 0,
+            operators: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Filter { expr }),
+            Some(_) => Err("unexpected tokens after the expression".to_string()),
+        }
+    }
+
+    /// Whether `attributes` pass. Elements without attributes, or missing
+    /// a field the expression uses, never do.
+    pub fn matches(&self, attributes: Option<&Value>) -> bool {
+        attributes
+            .and_then(|attributes| eval(&self.expr, attributes))
+            .is_some_and(|result| result.is_true())
+    }
+}
+
+/// None where a field is missing or the operands don't suit the operator.
+fn eval(expr: &Expr, attributes: &Value) -> Option<Scalar> {
+    match expr {
+        Expr::Number(n) => Some(Scalar::Number(*n)),
+        Expr::String(s) => Some(Scalar::String(s.clone())),
+        Expr::Array(items) => items
+            .iter()
+            .map(|item| eval(item, attributes))
+            .collect::<Option<_>>()
+            .map(Scalar::Array),
+        Expr::Field(name) => Scalar::from_json(attributes.member(name)?),
+        Expr::Not(expr) => Some(boolean(!eval(expr, attributes)?.is_true())),
+        Expr::Negate(expr) => Some(Scalar::Number(-eval(expr, attributes)?.number()?)),
+        Expr::Binary(Op::Or, left, right) => {
+            let left = eval(left, attributes)?.is_true();
+            Some(boolean(left || eval(right, attributes)?.is_true()))
+        }
+        Expr::Binary(Op::And, left, right) => {
+            let left = eval(left, attributes)?.is_true();
+            Some(boolean(left && eval(right, attributes)?.is_true()))
+        }
+        Expr::Binary(op, left, right) => {
+            let (left, right) = (eval(left, attributes)?, eval(right, attributes)?);
+            binary(*op, &left, &right)
+        }
+    }
+}
+
+fn binary(op: Op, left: &Scalar, right: &Scalar) -> Option<Scalar> {
+    let ordering = || match (left, right) {
+        (Scalar::Number(a), Scalar::Number(b)) => a.partial_cmp(b),
+        (Scalar::String(a), Scalar::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    match op {
+        Op::Eq => Some(boolean(left == right)),
+        Op::Ne => Some(boolean(left != right)),
+        Op::Lt => Some(boolean(ordering()?.is_lt())),
+        Op::Le => Some(boolean(ordering()?.is_le())),
+        Op::Gt => Some(boolean(ordering()?.is_gt())),
+        Op::Ge => Some(boolean(ordering()?.is_ge())),
+        Op::In => match (left, right) {
+            (_, Scalar::Array(items)) => Some(boolean(items.contains(left))),
+            (Scalar::String(needle), Scalar::String(haystack)) => {
+                Some(boolean(haystack.contains(needle.as_str())))
+            }
+            _ => None,
+        },
+        _ => {
+            let (a, b) = (left.number()?, right.number()?);
+            let n = match op {
+                Op::Add => a + b,
+                Op::Sub => a - b,
+                Op::Mul => a * b,
+                Op::Div => a / b,
+                Op::Rem => a % b,
+                Op::Pow => a.powf(b),
+                _ => unreachable!("logical and comparison operators are handled above"),
+            };
+            n.is_finite().then_some(Scalar::Number(n))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(filter: &str, attributes: &str) -> bool {
+        let attributes = Value::parse(attributes).unwrap();
+        Filter::parse(filter).unwrap().matches(Some(&attributes))
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let movie = r#"{"year": 1994, "rating": 8.9, "genre": "crime", "tags": ["cult", "classic"], "seen": true}"#;
+        assert!(matches(".year > 1990", movie));
+        assert!(matches(".year >= 1994 and .rating < 9", movie));
+        assert!(!matches(".year > 2000 || .genre == 'drama'", movie));
+        assert!(matches(".year > 2000 or .genre == \"crime\"", movie));
+        assert!(matches("not (.year > 2000) && !(.rating == 1)", movie));
+        assert!(matches(".genre in ['crime', 'drama']", movie));
+        assert!(matches("\"cult\" in .tags", movie));
+        assert!(matches("'rim' in .genre", movie));
+        assert!(matches(".seen == true", movie));
+        assert!(matches(
+            "(.year - 1900) * 2 == 188 and 2 ** 3 ** 2 == 512",
+            movie
+        ));
+        assert!(matches(".year % 100 == 94 and -.rating < 0", movie));
+        assert!(matches(".rating > 8.5e0", movie));
+    }
+
+    #[test]
+    fn test_missing_fields_never_match() {
+        assert!(!matches(".missing > 1", r#"{"year": 1}"#));
+        assert!(!matches("not .missing", r#"{"year": 1}"#));
+        assert!(!matches(".year > 'a'", r#"{"year": 1}"#));
+        let filter = Filter::parse(".year > 1").unwrap();
+        assert!(!filter.matches(None));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "",
+            ".year >",
+            "(.year > 1",
+            ".year > 1 1",
+            "'open",
+            ".year @ 1",
+            ". > 1",
+            "[1, 2",
+            "unknown",
+        ] {
+            assert!(Filter::parse(bad).is_err(), "{}", bad);
+        }
+        let deep = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert!(Filter::parse(&deep).is_err());
+        assert!(Filter::parse(&"!".repeat(2000)).is_err());
+        assert!(Filter::parse(&"1+".repeat(2000)).is_err());
+    }
+}
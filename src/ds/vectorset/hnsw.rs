@@ -0,0 +1,773 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+use crate::ds::codec::{Reader, Writer};
+
+/// Layers a node can be promoted to, far more than any set will use.
+const MAX_LEVEL: usize = 16;
+const NODE_OVERHEAD: usize = 48;
+const NO_ENTRY: u32 = u32::MAX;
+
+/// How far apart two vectors are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// One minus the cosine of the angle between them, from 0 to 2
+    Cosine,
+    /// The Euclidean distance
+    L2,
+}
+
+impl Metric {
+    pub fn parse(name: &str) -> Option<Metric> {
+        match name.to_uppercase().as_str() {
+            "COSINE" => Some(Metric::Cosine),
+            "L2" => Some(Metric::L2),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Cosine => "cosine",
+            Metric::L2 => "l2",
+        }
+    }
+}
+
+/// How the components of stored vectors are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantization {
+    /// As they are
+    None,
+    /// As bytes scaled by the largest component, a quarter of the size
+    Int8,
+}
+
+impl Quantization {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quantization::None => "f32",
+            Quantization::Int8 => "int8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Stored {
+    F32(Vec<f32>),
+    Int8 { values: Vec<i8>, scale: f32 },
+}
+
+impl Stored {
+    fn new(vector: &[f32], quantization: Quantization) -> Stored {
+        match quantization {
+            Quantization::None => Stored::F32(vector.to_vec()),
+            Quantization::Int8 => {
+                let max = vector.iter().fold(0f32, |max, x| max.max(x.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                let values = vector
+                    .iter()
+                    .map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+                    .collect();
+                Stored::Int8 { values, scale }
+            }
+        }
+    }
+
+    fn to_f32(&self) -> Vec<f32> {
+        match self {
+            Stored::F32(vector) => vector.clone(),
+            Stored::Int8 { values, scale } => {
+                values.iter().map(|&x| f32::from(x) * scale).collect()
+            }
+        }
+    }
+
+    fn dot(&self, query: &[f32]) -> f32 {
+        match self {
+            Stored::F32(vector) => vector.iter().zip(query).map(|(a, b)| a * b).sum(),
+            Stored::Int8 { values, scale } => {
+                let dot: f32 = values
+                    .iter()
+                    .zip(query)
+                    .map(|(&a, b)| f32::from(a) * b)
+                    .sum();
+                dot * scale
+            }
+        }
+    }
+
+    fn squared_distance(&self, query: &[f32]) -> f32 {
+        match self {
+            Stored::F32(vector) => vector.iter().zip(query).map(|(a, b)| (a - b).powi(2)).sum(),
+            Stored::Int8 { values, scale } => values
+                .iter()
+                .zip(query)
+                .map(|(&a, b)| (f32::from(a) * scale - b).powi(2))
+                .sum(),
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        match self {
+            Stored::F32(vector) => vector.len() * 4,
+            Stored::Int8 { values, .. } => values.len() + 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    vector: Stored,
+    /// What a cosine vector was divided by to store it at unit length
+    norm: f32,
+    /// The nodes this one links to on each layer it is on
+    links: Vec<Vec<u32>>,
+}
+
+/// A distance and the node at it, ordered by distance.
+#[derive(Debug, Clone, Copy)]
+struct Near(f32, u32);
+
+impl PartialEq for Near {
+    fn eq(&self, other: &Near) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Near {}
+
+impl PartialOrd for Near {
+    fn partial_cmp(&self, other: &Near) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Near {
+    fn cmp(&self, other: &Near) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// A Hierarchical Navigable Small World graph: every vector is a node on
+/// layer 0, and on each layer above with odds of 1 in `m` per layer.
+/// Nodes link to up to `m` near neighbours on each layer, `2 * m` on
+/// layer 0. A search walks greedily down from the single entry node on the
+/// top layer, then widens to the `ef` best candidates on layer 0.
+///
+/// Links are kept symmetric, so removing a node knows every node that
+/// linked to it, and reconnects them among themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hnsw {
+    dim: usize,
+    metric: Metric,
+    quantization: Quantization,
+    m: usize,
+    ef_construction: usize,
+    /// Node slots by id, None for removed ones waiting to be reused
+    nodes: Vec<Option<Node>>,
+    free: Vec<u32>,
+    entry: Option<u32>,
+    len: usize,
+}
+
+impl Hnsw {
+    pub fn new(
+        dim: usize,
+        metric: Metric,
+        quantization: Quantization,
+        m: usize,
+        ef_construction: usize,
+    ) -> Hnsw {
+        Hnsw {
+            dim,
+            metric,
+            quantization,
+            m,
+            ef_construction,
+            nodes: Vec::new(),
+            free: Vec::new(),
+            entry: None,
+            len: 0,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        self.quantization
+    }
+
+    pub fn m(&self) -> usize {
+        self.m
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The highest layer, 0 for an empty graph.
+    pub fn max_level(&self) -> usize {
+        self.entry.map_or(0, |entry| self.level_of(entry))
+    }
+
+    /// One more than the highest id in use.
+    pub fn capacity(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn node(&self, id: u32) -> &Node {
+        self.nodes[id as usize]
+            .as_ref()
+            .expect("linked nodes exist")
+    }
+
+    fn links(&mut self, id: u32, layer: usize) -> &mut Vec<u32> {
+        let node = self.nodes[id as usize]
+            .as_mut()
+            .expect("linked nodes exist");
+        &mut node.links[layer]
+    }
+
+    fn level_of(&self, id: u32) -> usize {
+        self.node(id).links.len() - 1
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { self.m * 2 } else { self.m }
+    }
+
+    /// The vector as it is compared: at unit length for cosine, with the
+    /// length it had.
+    fn prepare(&self, vector: &[f32]) -> (Vec<f32>, f32) {
+        match self.metric {
+            Metric::Cosine => {
+                let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    (vector.iter().map(|x| x / norm).collect(), norm)
+                } else {
+                    (vector.to_vec(), 0.0)
+                }
+            }
+            Metric::L2 => (vector.to_vec(), 1.0),
+        }
+    }
+
+    fn distance(&self, query: &[f32], id: u32) -> f32 {
+        let stored = &self.node(id).vector;
+        match self.metric {
+            Metric::Cosine => (1.0 - stored.dot(query)).clamp(0.0, 2.0),
+            Metric::L2 => stored.squared_distance(query).sqrt(),
+        }
+    }
+
+    fn distance_between(&self, a: u32, b: u32) -> f32 {
+        self.distance(&self.node(a).vector.to_f32(), b)
+    }
+
+    /// The vector stored as `id`, back at the length it was added with.
+    pub fn vector(&self, id: u32) -> Option<Vec<f32>> {
+        let node = self.nodes.get(id as usize)?.as_ref()?;
+        let vector = node.vector.to_f32();
+        Some(match self.metric {
+            Metric::Cosine => vector.into_iter().map(|x| x * node.norm).collect(),
+            Metric::L2 => vector,
+        })
+    }
+
+    /// Adds a vector of `dim` components, which goes up as many layers as
+    /// `seed` picks, and returns its id.
+    pub fn insert(&mut self, vector: &[f32], seed: u64) -> u32 {
+        let (vector, norm) = self.prepare(vector);
+        let level = random_level(seed, self.m);
+        let node = Node {
+            vector: Stored::new(&vector, self.quantization),
+            norm,
+            links: vec![Vec::new(); level + 1],
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id as usize] = Some(node);
+                id
+            }
+            None => {
+                self.nodes.push(Some(node));
+                (self.nodes.len() - 1) as u32
+            }
+        };
+        self.len += 1;
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return id;
+        };
+        let top = self.level_of(entry);
+        let mut nearest = vec![Near(self.distance(&vector, entry), entry)];
+        for layer in (level + 1..=top).rev() {
+            nearest = self.search_layer(&vector, &nearest, 1, layer, &|_| true, usize::MAX);
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(
+                &vector,
+                &nearest,
+                self.ef_construction,
+                layer,
+                &|_| true,
+                usize::MAX,
+            );
+            for Near(_, neighbour) in self.select(&found, self.max_links(layer)) {
+                self.connect(id, neighbour, layer);
+            }
+            // A node every neighbour turned away would be out of reach, so
+            // it links to the nearest one over that one's limit instead
+            if let Some(&Near(_, nearest)) = found.first()
+                && self.node(id).links[layer].is_empty()
+            {
+                self.links(id, layer).push(nearest);
+                self.links(nearest, layer).push(id);
+            }
+            nearest = found;
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+        id
+    }
+
+    /// Picks up to `max` of `candidates`, nearest first, skipping those
+    /// nearer to one already picked than to the query so links spread out
+    /// in different directions, then filling up with the skipped ones.
+    fn select(&self, candidates: &[Near], max: usize) -> Vec<Near> {
+        let mut picked: Vec<Near> = Vec::with_capacity(max);
+        let mut skipped = Vec::new();
+        for &candidate in candidates {
+            if picked.len() == max {
+                break;
+            }
+            let Near(distance, id) = candidate;
+            let vector = self.node(id).vector.to_f32();
+            if picked
+                .iter()
+                .all(|&Near(_, other)| self.distance(&vector, other) > distance)
+            {
+                picked.push(candidate);
+            } else {
+                skipped.push(candidate);
+            }
+        }
+        let room = max - picked.len();
+        picked.extend(skipped.into_iter().take(room));
+        picked
+    }
+
+    /// Links `a` and `b` both ways on `layer` if `b` has room for `a`,
+    /// making room by dropping its farthest neighbour if `a` is nearer and
+    /// that neighbour has other links to fall back on.
+    fn connect(&mut self, a: u32, b: u32, layer: usize) {
+        let max = self.max_links(layer);
+        if self.node(b).links[layer].len() >= max {
+            let distance = self.distance_between(b, a);
+            let vector = self.node(b).vector.to_f32();
+            let mut neighbours: Vec<Near> = self.node(b).links[layer]
+                .iter()
+                .map(|&n| Near(self.distance(&vector, n), n))
+                .collect();
+            neighbours.sort_by(|x, y| y.cmp(x));
+            let dropped = neighbours
+                .into_iter()
+                .find(|&Near(d, n)| d > distance && self.node(n).links[layer].len() > 1);
+            let Some(Near(_, dropped)) = dropped else {
+                return;
+            };
+            self.links(b, layer).retain(|&n| n != dropped);
+            self.links(dropped, layer).retain(|&n| n != b);
+        }
+        self.links(a, layer).push(b);
+        self.links(b, layer).push(a);
+    }
+
+    /// The `ef` nodes nearest to `query` that pass `accept`, nearest first,
+    /// found by walking `layer` from `entries`. Nodes that don't pass are
+    /// still walked through, up to `max_visits` nodes in all.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entries: &[Near],
+        ef: usize,
+        layer: usize,
+        accept: &dyn Fn(u32) -> bool,
+        max_visits: usize,
+    ) -> Vec<Near> {
+        let mut visited: HashSet<u32> = entries.iter().map(|&Near(_, id)| id).collect();
+        let mut candidates: BinaryHeap<Reverse<Near>> =
+            entries.iter().copied().map(Reverse).collect();
+        let mut results: BinaryHeap<Near> = entries
+            .iter()
+            .copied()
+            .filter(|&Near(_, id)| accept(id))
+            .collect();
+        while results.len() > ef {
+            results.pop();
+        }
+        'walk: while let Some(Reverse(Near(distance, id))) = candidates.pop() {
+            let worst = results.peek().map_or(f32::INFINITY, |near| near.0);
+            if results.len() >= ef && distance > worst {
+                break;
+            }
+            for &neighbour in &self.node(id).links[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                if visited.len() > max_visits {
+                    break 'walk;
+                }
+                let distance = self.distance(query, neighbour);
+                let worst = results.peek().map_or(f32::INFINITY, |near| near.0);
+                if results.len() < ef || distance < worst {
+                    candidates.push(Reverse(Near(distance, neighbour)));
+                    if accept(neighbour) {
+                        results.push(Near(distance, neighbour));
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+        results.into_sorted_vec()
+    }
+
+    /// Up to `count` ids nearest to `query` that pass `accept`, nearest
+    /// first with their distances, looking at `ef` candidates and at most
+    /// `max_visits` nodes.
+    pub fn search(
+        &self,
+        query: &[f32],
+        count: usize,
+        ef: usize,
+        accept: &dyn Fn(u32) -> bool,
+        max_visits: usize,
+    ) -> Vec<(u32, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let (query, _) = self.prepare(query);
+        let mut nearest = vec![Near(self.distance(&query, entry), entry)];
+        for layer in (1..=self.level_of(entry)).rev() {
+            nearest = self.search_layer(&query, &nearest, 1, layer, &|_| true, usize::MAX);
+        }
+        let mut found = self.search_layer(&query, &nearest, ef.max(count), 0, accept, max_visits);
+        found.truncate(count);
+        found.into_iter().map(|Near(d, id)| (id, d)).collect()
+    }
+
+    /// Like `search`, but compares `query` with every node.
+    pub fn search_exhaustive(
+        &self,
+        query: &[f32],
+        count: usize,
+        accept: &dyn Fn(u32) -> bool,
+    ) -> Vec<(u32, f32)> {
+        let (query, _) = self.prepare(query);
+        let mut found: Vec<Near> = (0..self.nodes.len() as u32)
+            .filter(|&id| self.nodes[id as usize].is_some() && accept(id))
+            .map(|id| Near(self.distance(&query, id), id))
+            .collect();
+        found.sort();
+        found.truncate(count);
+        found.into_iter().map(|Near(d, id)| (id, d)).collect()
+    }
+
+    /// Removes the node `id`, linking the nodes it linked to with each
+    /// other where they have room.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let Some(node) = self.nodes.get_mut(id as usize).and_then(Option::take) else {
+            return false;
+        };
+        self.free.push(id);
+        self.len -= 1;
+        for (layer, orphans) in node.links.iter().enumerate() {
+            for &orphan in orphans {
+                self.links(orphan, layer).retain(|&n| n != id);
+            }
+            let max = self.max_links(layer);
+            for &orphan in orphans {
+                let vector = self.node(orphan).vector.to_f32();
+                let mut others: Vec<Near> = orphans
+                    .iter()
+                    .filter(|&&other| {
+                        other != orphan && !self.node(orphan).links[layer].contains(&other)
+                    })
+                    .map(|&other| Near(self.distance(&vector, other), other))
+                    .collect();
+                others.sort();
+                for Near(_, other) in others {
+                    if self.node(orphan).links[layer].len() >= max {
+                        break;
+                    }
+                    if self.node(other).links[layer].len() < max {
+                        self.links(orphan, layer).push(other);
+                        self.links(other, layer).push(orphan);
+                    }
+                }
+            }
+        }
+        if self.entry == Some(id) {
+            self.entry = self.highest_node();
+        }
+        true
+    }
+
+    fn highest_node(&self) -> Option<u32> {
+        let mut highest: Option<(usize, u32)> = None;
+        for (id, node) in self.nodes.iter().enumerate() {
+            if let Some(node) = node
+                && highest.is_none_or(|(level, _)| node.links.len() - 1 > level)
+            {
+                highest = Some((node.links.len() - 1, id as u32));
+            }
+        }
+        highest.map(|(_, id)| id)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.nodes
+            .iter()
+            .flatten()
+            .map(|node| {
+                let links: usize = node.links.iter().map(|l| l.len() * 4 + 24).sum();
+                NODE_OVERHEAD + node.vector.bytes() + links
+            })
+            .sum::<usize>()
+            + self.nodes.len() * 8
+            + self.free.len() * 4
+    }
+
+    pub fn to_bytes(&self, writer: &mut Writer) {
+        writer
+            .u32(self.dim as u32)
+            .u8(self.metric as u8)
+            .u8(self.quantization as u8)
+            .u32(self.m as u32)
+            .u32(self.ef_construction as u32)
+            .u32(self.entry.unwrap_or(NO_ENTRY))
+            .u64(self.nodes.len() as u64);
+        for node in &self.nodes {
+            let Some(node) = node else {
+                writer.u8(0);
+                continue;
+            };
+            writer.u8(1).f32(node.norm);
+            match &node.vector {
+                Stored::F32(vector) => {
+                    for &x in vector {
+                        writer.f32(x);
+                    }
+                }
+                Stored::Int8 { values, scale } => {
+                    writer.f32(*scale);
+                    for &x in values {
+                        writer.u8(x as u8);
+                    }
+                }
+            }
+            writer.u32(node.links.len() as u32);
+            for links in &node.links {
+                writer.u32(links.len() as u32);
+                for &id in links {
+                    writer.u32(id);
+                }
+            }
+        }
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> Option<Hnsw> {
+        let dim = reader.u32()? as usize;
+        let metric = match reader.u8()? {
+            0 => Metric::Cosine,
+            1 => Metric::L2,
+            _ => return None,
+        };
+        let quantization = match reader.u8()? {
+            0 => Quantization::None,
+            1 => Quantization::Int8,
+            _ => return None,
+        };
+        let (m, ef_construction) = (reader.u32()? as usize, reader.u32()? as usize);
+        let entry = reader.u32()?;
+        let count = reader.u64()?;
+        if count > reader.remaining() as u64 {
+            return None;
+        }
+        let mut hnsw = Hnsw::new(dim, metric, quantization, m, ef_construction);
+        for id in 0..count as u32 {
+            if reader.u8()? == 0 {
+                hnsw.nodes.push(None);
+                hnsw.free.push(id);
+                continue;
+            }
+            let norm = reader.f32()?;
+            let vector = match quantization {
+                Quantization::None => {
+                    Stored::F32((0..dim).map(|_| reader.f32()).collect::<Option<_>>()?)
+                }
+                Quantization::Int8 => {
+                    let scale = reader.f32()?;
+                    let values = (0..dim)
+                        .map(|_| reader.u8().map(|x| x as i8))
+                        .collect::<Option<_>>()?;
+                    Stored::Int8 { values, scale }
+                }
+            };
+            let levels = reader.u32()? as usize;
+            if levels == 0 || levels > MAX_LEVEL + 1 {
+                return None;
+            }
+            let mut links = Vec::with_capacity(levels);
+            for _ in 0..levels {
+                let len = reader.u32()?;
+                let layer = (0..len)
+                    .map(|_| reader.u32().filter(|&n| u64::from(n) < count))
+                    .collect::<Option<Vec<_>>>()?;
+                links.push(layer);
+            }
+            hnsw.nodes.push(Some(Node {
+                vector,
+                norm,
+                links,
+            }));
+            hnsw.len += 1;
+        }
+        hnsw.entry = match entry {
+            NO_ENTRY => None,
+            entry => Some(entry),
+        };
+        let linked = |id: &u32| hnsw.nodes.get(*id as usize).is_some_and(Option::is_some);
+        let consistent = hnsw.entry.is_none_or(|entry| linked(&entry))
+            && hnsw.entry.is_some() == (hnsw.len > 0)
+            && hnsw
+                .nodes
+                .iter()
+                .flatten()
+                .all(|node| node.links.iter().flatten().all(linked));
+        consistent.then_some(hnsw)
+    }
+}
+
+/// The highest layer a node goes on: each one up with odds of 1 in `m`,
+/// drawn from `seed` so the same vectors always build the same graph.
+fn random_level(seed: u64, m: usize) -> usize {
+    // 53 bits for a uniform draw in (0, 1]
+    let uniform = ((seed >> 11) + 1) as f64 / (1u64 << 53) as f64;
+    let level = -uniform.ln() / (m.max(2) as f64).ln();
+    (level as usize).min(MAX_LEVEL)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Points on a grid, so the nearest neighbours are known.
+    fn grid(hnsw: &mut Hnsw, side: u32) {
+        for x in 0..side {
+            for y in 0..side {
+                let seed = u64::from(x * side + y).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                hnsw.insert(&[x as f32, y as f32], seed);
+            }
+        }
+    }
+
+    fn ids(found: &[(u32, f32)]) -> Vec<u32> {
+        found.iter().map(|&(id, _)| id).collect()
+    }
+
+    #[test]
+    fn test_search_finds_nearest() {
+        let mut hnsw = Hnsw::new(2, Metric::L2, Quantization::None, 4, 50);
+        grid(&mut hnsw, 20);
+        assert_eq!(hnsw.len(), 400);
+        for (x, y) in [(0.0, 0.0), (7.2, 13.9), (19.0, 3.1), (10.4, 10.4)] {
+            let found = hnsw.search(&[x, y], 5, 50, &|_| true, usize::MAX);
+            let truth = hnsw.search_exhaustive(&[x, y], 5, &|_| true);
+            assert_eq!(ids(&found), ids(&truth), "near {} {}", x, y);
+        }
+        let found = hnsw.search(&[3.0, 4.0], 1, 10, &|_| true, usize::MAX);
+        assert_eq!(found, vec![(3 * 20 + 4, 0.0)]);
+    }
+
+    #[test]
+    fn test_filtered_search() {
+        let mut hnsw = Hnsw::new(2, Metric::L2, Quantization::None, 4, 50);
+        grid(&mut hnsw, 20);
+        let odd = |id: u32| id % 2 == 1;
+        let found = hnsw.search(&[5.0, 5.0], 4, 50, &odd, usize::MAX);
+        assert_eq!(
+            ids(&found),
+            ids(&hnsw.search_exhaustive(&[5.0, 5.0], 4, &odd))
+        );
+        // Running out of visits ends the search with what it has
+        let found = hnsw.search(&[5.0, 5.0], 4, 50, &|id| id == 399, 10);
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn test_cosine_and_quantization() {
+        let mut hnsw = Hnsw::new(3, Metric::Cosine, Quantization::Int8, 8, 100);
+        hnsw.insert(&[1.0, 0.0, 0.0], 1);
+        hnsw.insert(&[0.0, 2.0, 0.0], 2);
+        hnsw.insert(&[-3.0, 0.0, 0.0], 3);
+        let found = hnsw.search(&[10.0, 1.0, 0.0], 3, 10, &|_| true, usize::MAX);
+        assert_eq!(ids(&found), vec![0, 1, 2]);
+        assert!((found[2].1 - 1.995).abs() < 0.01, "{}", found[2].1);
+        let vector = hnsw.vector(1).unwrap();
+        assert!((vector[1] - 2.0).abs() < 0.02, "{:?}", vector);
+    }
+
+    #[test]
+    fn test_remove_keeps_graph_searchable() {
+        let mut hnsw = Hnsw::new(2, Metric::L2, Quantization::None, 4, 50);
+        grid(&mut hnsw, 20);
+        for id in (0..400).step_by(3) {
+            assert!(hnsw.remove(id));
+        }
+        assert!(!hnsw.remove(0));
+        assert_eq!(hnsw.len(), 266);
+        let found = hnsw.search(&[9.0, 9.0], 5, 50, &|_| true, usize::MAX);
+        let truth = hnsw.search_exhaustive(&[9.0, 9.0], 5, &|_| true);
+        assert_eq!(ids(&found), ids(&truth));
+        // Removed slots are reused
+        assert_eq!(hnsw.insert(&[0.5, 0.5], 7), 399);
+        for id in 0..hnsw.capacity() as u32 {
+            hnsw.remove(id);
+        }
+        assert!(hnsw.is_empty());
+        assert_eq!(
+            hnsw.search(&[0.0, 0.0], 1, 10, &|_| true, usize::MAX),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut hnsw = Hnsw::new(2, Metric::L2, Quantization::Int8, 4, 50);
+        grid(&mut hnsw, 5);
+        hnsw.remove(3);
+        let mut writer = Writer::new();
+        hnsw.to_bytes(&mut writer);
+        let bytes = writer.finish();
+        assert_eq!(Hnsw::from_bytes(&mut Reader::new(&bytes)), Some(hnsw));
+        assert_eq!(
+            Hnsw::from_bytes(&mut Reader::new(&bytes[..bytes.len() - 1])),
+            None
+        );
+    }
+}
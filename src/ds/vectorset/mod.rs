@@ -0,0 +1,352 @@
+//! vectorset -- named vectors searched by similarity, with an HNSW graph
+//!
+//! Like the vector sets of Redis 8, each element is a string with a
+//! vector of a fixed number of components and, optionally, JSON
+//! attributes that searches can filter on. Vectors are compared by cosine
+//! or Euclidean distance and are kept as 8 bit integers unless asked
+//! otherwise, which costs a little precision for a quarter of the memory.
+//!
+//! Redis places each new node on the graph's layers at random; here the
+//! draw is a hash of the element's name, so replicas and reloaded
+//! snapshots build the same graph from the same commands.
+
+mod filter;
+mod hnsw;
+
+use std::collections::HashMap;
+
+pub use self::filter::Filter;
+use self::hnsw::Hnsw;
+pub use self::hnsw::{Metric, Quantization};
+use crate::ds::codec::{Reader, Writer};
+use crate::ds::hyperloglog::murmurhash64a;
+use crate::ds::json::Value;
+
+pub const DEFAULT_M: usize = 16;
+pub const DEFAULT_EF_CONSTRUCTION: usize = 200;
+pub const DEFAULT_EF: usize = 100;
+const LEVEL_SEED: u64 = 0x5bd1_e995;
+const ELEMENT_OVERHEAD: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    attributes: Option<Value>,
+}
+
+/// A search's settings past the vector.
+#[derive(Debug, Clone)]
+pub struct Search<'a> {
+    pub count: usize,
+    /// Candidates kept while searching, more for better recall
+    pub ef: usize,
+    pub filter: Option<&'a Filter>,
+    /// Nodes looked at before giving up on finding `count` that pass the
+    /// filter
+    pub filter_ef: usize,
+    /// Compare with every element instead of searching the graph
+    pub exhaustive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorSet {
+    index: Hnsw,
+    /// Elements by node id, None where the node was removed
+    elements: Vec<Option<Element>>,
+    ids: HashMap<String, u32>,
+}
+
+impl VectorSet {
+    pub fn new(
+        dim: usize,
+        metric: Metric,
+        quantization: Quantization,
+        m: usize,
+        ef_construction: usize,
+    ) -> VectorSet {
+        VectorSet {
+            index: Hnsw::new(dim, metric, quantization, m, ef_construction),
+            elements: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.index.dim()
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.index.metric()
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        self.index.quantization()
+    }
+
+    pub fn m(&self) -> usize {
+        self.index.m()
+    }
+
+    pub fn max_level(&self) -> usize {
+        self.index.max_level()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.ids.contains_key(name)
+    }
+
+    fn element(&self, name: &str) -> Option<&Element> {
+        let id = *self.ids.get(name)?;
+        self.elements[id as usize].as_ref()
+    }
+
+    /// How many elements have attributes.
+    pub fn attributes_count(&self) -> usize {
+        self.elements
+            .iter()
+            .flatten()
+            .filter(|element| element.attributes.is_some())
+            .count()
+    }
+
+    /// Adds `name` with `vector`, which must have `dim` components, or
+    /// replaces its vector if it is in already, keeping its attributes.
+    /// Whether it is new.
+    pub fn add(&mut self, name: &str, vector: &[f32]) -> bool {
+        let old = self.ids.remove(name);
+        let attributes = old.and_then(|id| {
+            self.index.remove(id);
+            self.elements[id as usize].take()?.attributes
+        });
+        let id = self
+            .index
+            .insert(vector, murmurhash64a(name.as_bytes(), LEVEL_SEED));
+        if self.elements.len() <= id as usize {
+            self.elements.resize(id as usize + 1, None);
+        }
+        self.elements[id as usize] = Some(Element {
+            name: name.to_string(),
+            attributes,
+        });
+        self.ids.insert(name.to_string(), id);
+        old.is_none()
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let Some(id) = self.ids.remove(name) else {
+            return false;
+        };
+        self.index.remove(id);
+        self.elements[id as usize] = None;
+        true
+    }
+
+    /// The vector of `name`, as far as quantization kept it.
+    pub fn vector(&self, name: &str) -> Option<Vec<f32>> {
+        self.index.vector(*self.ids.get(name)?)
+    }
+
+    /// None if there is no `name`, Some(None) if it has no attributes.
+    pub fn attributes(&self, name: &str) -> Option<Option<&Value>> {
+        self.element(name)
+            .map(|element| element.attributes.as_ref())
+    }
+
+    /// Sets or, with None, clears the attributes of `name`. False if it
+    /// isn't in the set.
+    pub fn set_attributes(&mut self, name: &str, attributes: Option<Value>) -> bool {
+        let Some(&id) = self.ids.get(name) else {
+            return false;
+        };
+        let element = self.elements[id as usize].as_mut().expect("ids are live");
+        element.attributes = attributes;
+        true
+    }
+
+    /// The elements nearest to `vector`, nearest first, with their
+    /// similarity: 1 for the same direction or point, falling to 0 for
+    /// the opposite direction, or as the distance grows.
+    pub fn search(&self, vector: &[f32], search: &Search) -> Vec<(&str, f64)> {
+        let accept = |id: u32| {
+            search.filter.is_none_or(|filter| {
+                let element = self.elements[id as usize].as_ref();
+                filter.matches(element.and_then(|element| element.attributes.as_ref()))
+            })
+        };
+        let found = match search.exhaustive {
+            true => self.index.search_exhaustive(vector, search.count, &accept),
+            false => {
+                let max_visits = match search.filter {
+                    Some(_) => search.filter_ef,
+                    None => usize::MAX,
+                };
+                self.index
+                    .search(vector, search.count, search.ef, &accept, max_visits)
+            }
+        };
+        found
+            .into_iter()
+            .map(|(id, distance)| {
+                let element = self.elements[id as usize]
+                    .as_ref()
+                    .expect("nodes have elements");
+                let similarity = match self.metric() {
+                    Metric::Cosine => 1.0 - f64::from(distance) / 2.0,
+                    Metric::L2 => 1.0 / (1.0 + f64::from(distance)),
+                };
+                (element.name.as_str(), similarity)
+            })
+            .collect()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let elements: usize = self
+            .elements
+            .iter()
+            .flatten()
+            .map(|element| {
+                let attributes = element.attributes.as_ref().map_or(0, Value::memory_usage);
+                ELEMENT_OVERHEAD + element.name.len() * 2 + attributes
+            })
+            .sum();
+        self.index.memory_usage() + elements + self.elements.len() * 8
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.index.to_bytes(&mut writer);
+        for element in &self.elements {
+            let Some(element) = element else {
+                writer.u8(0);
+                continue;
+            };
+            writer.u8(1).bytes(element.name.as_bytes());
+            match &element.attributes {
+                Some(attributes) => writer.u8(1).bytes(attributes.to_json().as_bytes()),
+                None => writer.u8(0),
+            };
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<VectorSet> {
+        let mut reader = Reader::new(bytes);
+        let index = Hnsw::from_bytes(&mut reader)?;
+        let mut set = VectorSet {
+            elements: Vec::with_capacity(index.capacity()),
+            index,
+            ids: HashMap::new(),
+        };
+        for id in 0..set.index.capacity() as u32 {
+            if reader.u8()? == 0 {
+                set.elements.push(None);
+                continue;
+            }
+            let name = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+            let attributes = match reader.u8()? {
+                0 => None,
+                _ => Some(Value::parse(std::str::from_utf8(reader.bytes()?).ok()?).ok()?),
+            };
+            if set.ids.insert(name.clone(), id).is_some() {
+                return None;
+            }
+            set.elements.push(Some(Element { name, attributes }));
+        }
+        let consistent = set.ids.len() == set.index.len()
+            && set.ids.values().all(|&id| set.index.vector(id).is_some());
+        (reader.is_empty() && consistent).then_some(set)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn search(count: usize) -> Search<'static> {
+        Search {
+            count,
+            ef: DEFAULT_EF,
+            filter: None,
+            filter_ef: usize::MAX,
+            exhaustive: false,
+        }
+    }
+
+    fn names<'a>(found: &[(&'a str, f64)]) -> Vec<&'a str> {
+        found.iter().map(|(name, _)| *name).collect()
+    }
+
+    #[test]
+    fn test_add_search_remove() {
+        let mut set = VectorSet::new(2, Metric::Cosine, Quantization::None, 4, 50);
+        assert!(set.add("east", &[1.0, 0.0]));
+        assert!(set.add("north", &[0.0, 3.0]));
+        assert!(set.add("west", &[-2.0, 0.0]));
+        assert!(!set.add("west", &[-2.0, 0.1]));
+        assert_eq!(set.len(), 3);
+        let found = set.search(&[1.0, 0.0], &search(3));
+        assert_eq!(names(&found), vec!["east", "north", "west"]);
+        assert!((found[0].1 - 1.0).abs() < 1e-6);
+        assert!((found[1].1 - 0.5).abs() < 1e-6);
+        assert_eq!(set.vector("north"), Some(vec![0.0, 3.0]));
+        assert!(set.remove("east"));
+        assert!(!set.remove("east"));
+        assert_eq!(names(&set.search(&[1.0, 0.0], &search(1))), vec!["north"]);
+    }
+
+    #[test]
+    fn test_filtered_search() {
+        let mut set = VectorSet::new(1, Metric::L2, Quantization::Int8, 4, 50);
+        for i in 0..100 {
+            let name = format!("n{}", i);
+            set.add(&name, &[i as f32]);
+            let attributes = Value::parse(&format!("{{\"even\": {}}}", i % 2 == 0)).unwrap();
+            set.set_attributes(&name, Some(attributes));
+        }
+        // Replacing the vector keeps the attributes
+        set.add("n7", &[7.0]);
+        assert_eq!(set.attributes_count(), 100);
+        let filter = Filter::parse(".even").unwrap();
+        let filtered = Search {
+            filter: Some(&filter),
+            ..search(3)
+        };
+        assert_eq!(
+            names(&set.search(&[7.0], &filtered)),
+            vec!["n6", "n8", "n4"]
+        );
+        let exhaustive = Search {
+            exhaustive: true,
+            ..filtered
+        };
+        assert_eq!(
+            names(&set.search(&[7.0], &exhaustive)),
+            vec!["n6", "n8", "n4"]
+        );
+        assert!(set.set_attributes("n6", None));
+        assert_eq!(set.attributes("n6"), Some(None));
+        assert_eq!(set.attributes("none"), None);
+        assert!(!set.set_attributes("none", None));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut set = VectorSet::new(3, Metric::Cosine, Quantization::Int8, 8, 100);
+        set.add("a", &[1.0, 2.0, 3.0]);
+        set.add("b", &[-1.0, 0.5, 0.0]);
+        set.add("c", &[0.0, 0.0, 1.0]);
+        set.remove("b");
+        set.set_attributes("a", Some(Value::parse(r#"{"x": [1, "y"]}"#).unwrap()));
+        let bytes = set.to_bytes();
+        assert_eq!(VectorSet::from_bytes(&bytes), Some(set));
+        assert_eq!(VectorSet::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+}
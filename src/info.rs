@@ -0,0 +1,280 @@
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::command::CommandArg;
+use crate::server::Server;
+
+/// Sections in the order INFO prints them, with their header titles.
+const SECTIONS: [(&str, &str); 11] = [
+    ("server", "Server"),
+    ("clients", "Clients"),
+    ("memory", "Memory"),
+    ("persistence", "Persistence"),
+    ("stats", "Stats"),
+    ("replication", "Replication"),
+    ("cpu", "CPU"),
+    ("commandstats", "Commandstats"),
+    ("errorstats", "Errorstats"),
+    ("cluster", "Cluster"),
+    ("keyspace", "Keyspace"),
+];
+
+/// Sections left out of a plain INFO because they can get long.
+const NON_DEFAULT_SECTIONS: [&str; 1] = ["commandstats"];
+
+/// Expands the INFO arguments into the list of sections to print.
+/// No arguments means "default"; unknown names are ignored like Redis does.
+fn select_sections(args: &[CommandArg]) -> Vec<(&'static str, &'static str)> {
+    let args: Vec<String> = if args.is_empty() {
+        vec!["default".to_string()]
+    } else {
+        args.iter().map(|s| s.to_lowercase()).collect()
+    };
+    SECTIONS
+        .iter()
+        .filter(|(section, _)| {
+            args.iter().any(|arg| match arg.as_str() {
+                "all" | "everything" => true,
+                "default" => !NON_DEFAULT_SECTIONS.contains(section),
+                name => name == *section,
+            })
+        })
+        .copied()
+        .collect()
+}
+
+/// Formats bytes the way Redis does for `*_human` fields.
+fn bytes_to_human(bytes: usize) -> String {
+    let bytes = bytes as f64;
+    let units = [(1u64 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")];
+    for (size, unit) in units {
+        if bytes >= size as f64 {
+            return format!("{:.2}{}", bytes / size as f64, unit);
+        }
+    }
+    format!("{}B", bytes)
+}
+
+/// User and system CPU seconds consumed by this process, read from procfs.
+/// Reports zero on platforms without it.
+fn cpu_times() -> (f64, f64) {
+    // Clock ticks per second on every Linux platform we care about
+    const TICKS: f64 = 100.0;
+    let stat = std::fs::read_to_string("/proc/self/stat").unwrap_or_default();
+    // The command name may contain spaces, so start after its closing paren
+    let fields: Vec<&str> = match stat.rfind(')') {
+        Some(end) => stat[end + 1..].split_whitespace().collect(),
+        None => return (0.0, 0.0),
+    };
+    let field = |i: usize| {
+        fields
+            .get(i)
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(0.0)
+    };
+    // utime and stime are fields 14 and 15, 12 and 13 after the paren
+    (field(11) / TICKS, field(12) / TICKS)
+}
+
+fn write_section(output: &mut String, server: &Server, section: &str, title: &str) {
+    let stats = &server.stats;
+    let mut lines: Vec<(String, String)> = Vec::new();
+    let mut line = |key: &str, value: String| lines.push((key.to_string(), value));
+    match section {
+        "server" => {
+            let uptime = stats.uptime().as_secs();
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_micros())
+                .unwrap_or(0);
+            line("redis_version", env!("CARGO_PKG_VERSION").to_string());
+            let mode = if server.cluster.is_enabled() {
+                "cluster"
+            } else {
+                "standalone"
+            };
+            line("redis_mode", mode.to_string());
+            line(
+                "os",
+                format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+            );
+            line("arch_bits", (usize::BITS).to_string());
+            line("multiplexing_api", "tokio".to_string());
+            line("process_id", std::process::id().to_string());
+            line("tcp_port", server.port());
+            line("server_time_usec", now.to_string());
+            line("uptime_in_seconds", uptime.to_string());
+            line("uptime_in_days", (uptime / 86400).to_string());
+        }
+        "clients" => {
+            line("connected_clients", stats.connected_clients().to_string());
+            line("blocked_clients", server.blocking.blocked().to_string());
+        }
+        "memory" => {
+            let storage = &server.storage;
+            let used = storage.used_memory();
+            let peak = storage.peak_memory().max(used);
+            let peak_perc = if peak == 0 {
+                100.0
+            } else {
+                used as f64 * 100.0 / peak as f64
+            };
+            line("used_memory", used.to_string());
+            line("used_memory_human", bytes_to_human(used));
+            line("used_memory_peak", peak.to_string());
+            line("used_memory_peak_human", bytes_to_human(peak));
+            line("used_memory_peak_perc", format!("{:.2}%", peak_perc));
+            line("maxmemory", storage.maxmemory().to_string());
+            line("maxmemory_human", bytes_to_human(storage.maxmemory()));
+            line(
+                "maxmemory_policy",
+                storage.maxmemory_policy().as_str().to_string(),
+            );
+            line("mem_allocator", "libc".to_string());
+        }
+        "persistence" => {
+            // Nothing is persisted yet, so there is never a save in progress
+            line("loading", "0".to_string());
+            line("rdb_changes_since_last_save", "0".to_string());
+            line("rdb_bgsave_in_progress", "0".to_string());
+            line("rdb_last_save_time", stats.started_at().to_string());
+            line("rdb_last_bgsave_status", "ok".to_string());
+            line("aof_enabled", "0".to_string());
+            line("aof_rewrite_in_progress", "0".to_string());
+        }
+        "stats" => {
+            let storage_stats = server.storage.stats();
+            let errors: u64 = stats.errors().values().sum();
+            line(
+                "total_connections_received",
+                stats.connections_received().to_string(),
+            );
+            line(
+                "total_commands_processed",
+                stats.commands_processed().to_string(),
+            );
+            line("total_net_input_bytes", stats.net_input_bytes().to_string());
+            line(
+                "total_net_output_bytes",
+                stats.net_output_bytes().to_string(),
+            );
+            line("rejected_connections", "0".to_string());
+            line("expired_keys", storage_stats.expired_keys.to_string());
+            line("evicted_keys", storage_stats.evicted_keys.to_string());
+            line("keyspace_hits", storage_stats.keyspace_hits.to_string());
+            line("keyspace_misses", storage_stats.keyspace_misses.to_string());
+            let (channels, patterns) = server.pubsub.counts();
+            line("pubsub_channels", channels.to_string());
+            line("pubsub_patterns", patterns.to_string());
+            for (key, count) in server.replication.sync_stats() {
+                line(key, count.to_string());
+            }
+            line("total_error_replies", errors.to_string());
+        }
+        "replication" => {
+            let read_only = server.get_config_value("replica-read-only") != "no";
+            for (key, value) in server.replication.info(read_only) {
+                line(&key, value);
+            }
+        }
+        "cpu" => {
+            let (user, sys) = cpu_times();
+            line("used_cpu_sys", format!("{:.6}", sys));
+            line("used_cpu_user", format!("{:.6}", user));
+        }
+        "commandstats" => {
+            for (name, command) in stats.commands() {
+                let per_call = command.usec as f64 / command.calls.max(1) as f64;
+                line(
+                    &format!("cmdstat_{}", name),
+                    format!(
+                        "calls={},usec={},usec_per_call={:.2},rejected_calls=0,failed_calls={}",
+                        command.calls, command.usec, per_call, command.failed_calls
+                    ),
+                );
+            }
+        }
+        "errorstats" => {
+            for (prefix, count) in stats.errors() {
+                line(&format!("errorstat_{}", prefix), format!("count={}", count));
+            }
+        }
+        "cluster" => {
+            let enabled = if server.cluster.is_enabled() {
+                "1"
+            } else {
+                "0"
+            };
+            line("cluster_enabled", enabled.to_string());
+        }
+        "keyspace" => {
+            let storage = &server.storage;
+            if storage.keys_count() > 0 {
+                line(
+                    "db0",
+                    format!(
+                        "keys={},expires={},avg_ttl=0",
+                        storage.keys_count(),
+                        storage.expires_count()
+                    ),
+                );
+            }
+        }
+        _ => unreachable!("unknown INFO section {}", section),
+    }
+
+    let _ = write!(output, "# {}\r\n", title);
+    for (key, value) in lines {
+        let _ = write!(output, "{}:{}\r\n", key, value);
+    }
+}
+
+/// Renders the INFO reply for the requested sections.
+pub fn info(server: &Server, args: &[CommandArg]) -> String {
+    let mut output = String::new();
+    for (i, (section, title)) in select_sections(args).into_iter().enumerate() {
+        if i > 0 {
+            output.push_str("\r\n");
+        }
+        write_section(&mut output, server, section, title);
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::cmd;
+
+    #[test]
+    fn test_select_sections() {
+        let default = select_sections(&[]);
+        let names: Vec<&str> = default.iter().map(|(name, _)| *name).collect();
+        assert!(names.contains(&"server"));
+        assert!(names.contains(&"keyspace"));
+        assert!(!names.contains(&"commandstats"));
+        assert_eq!(select_sections(&cmd(&["all"])).len(), SECTIONS.len());
+        assert_eq!(
+            select_sections(&cmd(&["KEYSPACE", "memory"])),
+            vec![("memory", "Memory"), ("keyspace", "Keyspace")]
+        );
+        assert!(select_sections(&cmd(&["bogus"])).is_empty());
+    }
+
+    #[test]
+    fn test_bytes_to_human() {
+        assert_eq!(bytes_to_human(0), "0B");
+        assert_eq!(bytes_to_human(1024), "1.00K");
+        assert_eq!(bytes_to_human(3 * 1024 * 1024 / 2), "1.50M");
+    }
+
+    #[test]
+    fn test_info_sections() {
+        let server = Server::default();
+        let output = info(&server, &cmd(&["server", "commandstats"]));
+        assert!(output.starts_with("# Server\r\n"));
+        assert!(output.contains(&format!("redis_version:{}\r\n", env!("CARGO_PKG_VERSION"))));
+        assert!(output.contains("\r\n\r\n# Commandstats\r\n"));
+        assert!(!output.contains("# Memory"));
+    }
+}
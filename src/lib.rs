@@ -1,3 +1,22 @@
+pub mod acl;
+pub mod blocking;
+pub mod client;
+pub mod cluster;
+pub mod command;
+pub mod config;
 pub mod ds;
+pub mod glob;
+pub mod info;
+pub mod peer;
+pub mod pubsub;
+pub mod replication;
 pub mod resp;
+pub mod scripting;
+pub mod server;
+pub mod stats;
 pub mod storage;
+pub mod tracking;
+pub mod transaction;
+
+#[cfg(test)]
+mod test_support;
@@ -2,14 +2,8 @@ use std::collections::HashMap;
 
 use std::env;
 
-mod command;
-mod config;
-mod ds;
-mod resp;
-mod server;
-mod storage;
-
-use config::{get_config_from_cli_args, load_config_from_file, load_config_from_stdin};
+use kv::config::{get_config_from_cli_args, load_config_from_file, load_config_from_stdin};
+use kv::server;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
@@ -24,7 +18,7 @@ async fn main() -> std::io::Result<()> {
     let mut args: Vec<String> = env::args().collect();
     args.remove(0);
 
-    let config_path: String = if args.len() > 0 && !args[0].starts_with("-") {
+    let config_path: String = if !args.is_empty() && !args[0].starts_with("-") {
         args.remove(0)
     } else {
         println!(
@@ -33,7 +27,7 @@ async fn main() -> std::io::Result<()> {
         "redis.conf".to_string()
     };
 
-    let use_stdin: bool = if args.len() > 0 && args.last().unwrap().eq(&"-".to_string()) {
+    let use_stdin: bool = if !args.is_empty() && args.last().unwrap().eq(&"-".to_string()) {
         args.pop();
         true
     } else {
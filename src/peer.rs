@@ -0,0 +1,145 @@
+use std::io;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::client::QUERY_BUFFER_SIZE;
+use crate::command::CommandArg;
+use crate::resp::{RESP, RESPError, bytes_to_resp};
+use crate::server::{ServerError, ServerResult};
+
+/// A connection this server opened to another one, like a replica's link
+/// to its master, with whatever arrived that hasn't been used yet.
+pub struct Peer {
+    pub stream: TcpStream,
+    pub buffer: Vec<u8>,
+}
+
+impl Peer {
+    pub async fn connect(host: &str, port: u16) -> io::Result<Self> {
+        Ok(Self::new(TcpStream::connect((host, port)).await?))
+    }
+
+    pub fn new(stream: TcpStream) -> Self {
+        Peer {
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Reads more from the other end, failing once it hangs up.
+    pub async fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = [0; QUERY_BUFFER_SIZE];
+        let size = self.stream.read(&mut chunk).await?;
+        if size == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.buffer.extend_from_slice(&chunk[..size]);
+        Ok(())
+    }
+
+    pub async fn read_line(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(end) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                let line: Vec<u8> = self.buffer.drain(..end + 2).take(end).collect();
+                return String::from_utf8(line).map_err(io::Error::other);
+            }
+            self.fill().await?;
+        }
+    }
+
+    pub async fn read_exact(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        while self.buffer.len() < len {
+            self.fill().await?;
+        }
+        Ok(self.buffer.drain(..len).collect())
+    }
+
+    /// Waits for the next whole command the other end sends.
+    pub async fn read_command(&mut self) -> io::Result<Vec<String>> {
+        loop {
+            if let Some((args, _)) =
+                take_command(&mut self.buffer).map_err(|e| io::Error::other(e.to_string()))?
+            {
+                return Ok(args.into_iter().map(CommandArg::into_string).collect());
+            }
+            self.fill().await?;
+        }
+    }
+
+    pub async fn send(&mut self, args: &[impl AsRef<str>]) -> io::Result<()> {
+        let command = RESP::Array(
+            args.iter()
+                .map(|arg| RESP::BulkString(arg.as_ref().into()))
+                .collect(),
+        );
+        self.stream.write_all(&command.to_bytes()).await
+    }
+
+    /// Sends a command and reads its one line reply, failing on an error
+    /// reply.
+    pub async fn command(&mut self, args: &[impl AsRef<str>]) -> io::Result<String> {
+        self.send(args).await?;
+        let reply = self.read_line().await?;
+        match reply.strip_prefix('-') {
+            Some(error) => Err(io::Error::other(format!(
+                "{} replied: {}",
+                args[0].as_ref(),
+                error
+            ))),
+            None => Ok(reply.trim_start_matches(['+', ':']).to_string()),
+        }
+    }
+}
+
+/// Takes the next complete command off the front of `buffer`, returning
+/// its arguments and the bytes it took up. None means more bytes are
+/// needed.
+pub fn take_command(buffer: &mut Vec<u8>) -> ServerResult<Option<(Vec<CommandArg>, Vec<u8>)>> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+    let protocol_error =
+        || ServerError::IncorrectFormat("expected an array of bulk strings".to_string());
+    if buffer[0] != b'*' {
+        return Err(protocol_error());
+    }
+    let mut index = 0;
+    let request = match bytes_to_resp(buffer, &mut index) {
+        Ok(request) if index <= buffer.len() => request,
+        Ok(_) | Err(RESPError::OutOfBounds(_)) => return Ok(None),
+        Err(e) => return Err(ServerError::IncorrectFormat(e.to_string())),
+    };
+    let RESP::Array(elements) = request else {
+        return Err(protocol_error());
+    };
+    let mut args = Vec::with_capacity(elements.len());
+    for element in elements {
+        let RESP::BulkString(arg) = element else {
+            return Err(protocol_error());
+        };
+        args.push(CommandArg::from(arg));
+    }
+    let raw = buffer.drain(..index).collect();
+    Ok(Some((args, raw)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_command_waits_for_whole_commands() {
+        let mut buffer = b"*2\r\n$3\r\nget\r\n$1\r\nk\r\n*1\r\n$4\r\nPI".to_vec();
+        let (args, raw) = take_command(&mut buffer).unwrap().unwrap();
+        assert_eq!(args, vec!["get", "k"]);
+        assert_eq!(raw.len(), 20);
+        assert_eq!(take_command(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(b"NG\r");
+        assert_eq!(take_command(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(b"\n");
+        assert_eq!(take_command(&mut buffer).unwrap().unwrap().0, vec!["PING"]);
+        assert!(buffer.is_empty());
+        assert!(take_command(&mut b"+OK\r\n".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub mod notify;
+
+use crate::client::Client;
+use crate::command::{Command, CommandArg, CommandSpec};
+use crate::glob::glob_match;
+use crate::resp::RESP;
+use crate::server::{Server, ServerResult};
+
+/// What a client is subscribed to.
+#[derive(Default)]
+struct Subscriptions {
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+}
+
+impl Subscriptions {
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
+
+#[derive(Default)]
+struct PubSubState {
+    channels: HashMap<String, BTreeMap<u64, Arc<Client>>>,
+    patterns: HashMap<String, BTreeMap<u64, Arc<Client>>>,
+    clients: HashMap<u64, Subscriptions>,
+}
+
+/// Channel and pattern subscriptions, and the keyspace events published
+/// to them.
+#[derive(Default)]
+pub struct PubSub {
+    state: Mutex<PubSubState>,
+    /// `notify-keyspace-events`, parsed
+    keyspace_events: AtomicU32,
+}
+
+fn bulk(s: &str) -> RESP {
+    RESP::BulkString(s.into())
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_keyspace_events(&self, flags: u32) {
+        self.keyspace_events.store(flags, Ordering::Relaxed);
+    }
+
+    /// How many channels and patterns have subscribers, for INFO.
+    pub fn counts(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.channels.len(), state.patterns.len())
+    }
+
+    /// Sends `message` to the subscribers of `channel` and of the patterns
+    /// matching it, returning how many clients got it.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let state = self.state.lock().unwrap();
+        let mut receivers = 0;
+        if let Some(subscribers) = state.channels.get(channel) {
+            for client in subscribers.values() {
+                client.push(RESP::Array(vec![
+                    bulk("message"),
+                    bulk(channel),
+                    bulk(message),
+                ]));
+            }
+            receivers += subscribers.len();
+        }
+        for (pattern, subscribers) in &state.patterns {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            for client in subscribers.values() {
+                client.push(RESP::Array(vec![
+                    bulk("pmessage"),
+                    bulk(pattern),
+                    bulk(channel),
+                    bulk(message),
+                ]));
+            }
+            receivers += subscribers.len();
+        }
+        receivers
+    }
+
+    /// Publishes that `event` happened to `key`, if `notify-keyspace-events`
+    /// asks for its class.
+    pub fn notify_keyspace_event(&self, class: u32, event: &str, key: &str) {
+        let flags = self.keyspace_events.load(Ordering::Relaxed);
+        if flags & class == 0 {
+            return;
+        }
+        if flags & notify::KEYSPACE != 0 {
+            self.publish(&format!("__keyspace@0__:{}", key), event);
+        }
+        if flags & notify::KEYEVENT != 0 {
+            self.publish(&format!("__keyevent@0__:{}", event), key);
+        }
+    }
+
+    /// Subscribes to channels, or patterns, with one confirmation each.
+    fn subscribe(&self, client: &Arc<Client>, names: &[CommandArg], pattern: bool) -> Vec<RESP> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let (kind, table) = if pattern {
+            ("psubscribe", &mut state.patterns)
+        } else {
+            ("subscribe", &mut state.channels)
+        };
+        let subscriptions = state.clients.entry(client.id()).or_default();
+        let mut replies = Vec::new();
+        for name in names {
+            let added = if pattern {
+                subscriptions.patterns.insert(name.to_string())
+            } else {
+                subscriptions.channels.insert(name.to_string())
+            };
+            if added {
+                table
+                    .entry(name.to_string())
+                    .or_default()
+                    .insert(client.id(), client.clone());
+            }
+            replies.push(RESP::Array(vec![
+                bulk(kind),
+                bulk(name),
+                RESP::Integer(subscriptions.count() as i64),
+            ]));
+        }
+        client.set_subscriptions(subscriptions.channels.len(), subscriptions.patterns.len());
+        replies
+    }
+
+    /// Unsubscribes from channels, or patterns, or from all of them when
+    /// `names` is empty.
+    fn unsubscribe(&self, client: &Client, names: &[CommandArg], pattern: bool) -> Vec<RESP> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let (kind, table) = if pattern {
+            ("punsubscribe", &mut state.patterns)
+        } else {
+            ("unsubscribe", &mut state.channels)
+        };
+        let subscriptions = state.clients.entry(client.id()).or_default();
+        let names: Vec<String> = match (names.is_empty(), pattern) {
+            (false, _) => names.iter().map(|name| name.to_string()).collect(),
+            (true, false) => subscriptions.channels.iter().cloned().collect(),
+            (true, true) => subscriptions.patterns.iter().cloned().collect(),
+        };
+        let mut replies = Vec::new();
+        for name in &names {
+            let removed = if pattern {
+                subscriptions.patterns.remove(name)
+            } else {
+                subscriptions.channels.remove(name)
+            };
+            if removed && let Some(subscribers) = table.get_mut(name) {
+                subscribers.remove(&client.id());
+                if subscribers.is_empty() {
+                    table.remove(name);
+                }
+            }
+            replies.push(RESP::Array(vec![
+                bulk(kind),
+                bulk(name),
+                RESP::Integer(subscriptions.count() as i64),
+            ]));
+        }
+        // Nothing to unsubscribe from still gets a reply
+        if replies.is_empty() {
+            replies.push(RESP::Array(vec![
+                bulk(kind),
+                RESP::Null,
+                RESP::Integer(subscriptions.count() as i64),
+            ]));
+        }
+        client.set_subscriptions(subscriptions.channels.len(), subscriptions.patterns.len());
+        if subscriptions.count() == 0 {
+            state.clients.remove(&client.id());
+        }
+        replies
+    }
+
+    /// Drops the subscriptions of a client that disconnected.
+    pub fn forget(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let Some(subscriptions) = state.clients.remove(&id) else {
+            return;
+        };
+        for (table, names) in [
+            (&mut state.channels, subscriptions.channels),
+            (&mut state.patterns, subscriptions.patterns),
+        ] {
+            for name in names {
+                if let Some(subscribers) = table.get_mut(&name) {
+                    subscribers.remove(&id);
+                    if subscribers.is_empty() {
+                        table.remove(&name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// SUBSCRIBE, UNSUBSCRIBE, PSUBSCRIBE and PUNSUBSCRIBE. They confirm each
+/// channel with a reply of its own, so all but the last are pushed, which
+/// the connection writes ahead of the reply.
+pub fn command_subscribe(
+    server: &Server,
+    client: &Arc<Client>,
+    spec: &CommandSpec,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let pubsub = &server.pubsub;
+    let names = &args[1..];
+    let mut replies = match spec.command {
+        Command::Subscribe => pubsub.subscribe(client, names, false),
+        Command::PSubscribe => pubsub.subscribe(client, names, true),
+        Command::Unsubscribe => pubsub.unsubscribe(client, names, false),
+        _ => pubsub.unsubscribe(client, names, true),
+    };
+    let last = replies.pop().unwrap_or(RESP::Null);
+    for reply in replies {
+        client.push(reply);
+    }
+    Ok(last)
+}
+
+/// PUBLISH channel message
+pub fn command_publish(server: &Server, args: &[CommandArg]) -> ServerResult<RESP> {
+    Ok(RESP::Integer(
+        server.pubsub.publish(&args[1], &args[2]) as i64
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{connect, send};
+
+    fn message(parts: &[&str]) -> RESP {
+        RESP::Array(parts.iter().map(|part| bulk(part)).collect())
+    }
+
+    #[tokio::test]
+    async fn test_publish_to_channels_and_patterns() {
+        let server = Arc::new(Server::default());
+        let subscriber = connect(&server);
+        let publisher = connect(&server);
+        assert_eq!(
+            send(&server, &subscriber, &["subscribe", "news", "sport"]).await,
+            RESP::Array(vec![bulk("subscribe"), bulk("sport"), RESP::Integer(2)])
+        );
+        assert_eq!(
+            subscriber.take_pushes(),
+            vec![RESP::Array(vec![
+                bulk("subscribe"),
+                bulk("news"),
+                RESP::Integer(1)
+            ])]
+        );
+        send(&server, &subscriber, &["psubscribe", "n*"]).await;
+        assert!(subscriber.describe().contains(" sub=2 psub=1 "));
+        assert_eq!(
+            send(&server, &subscriber, &["get", "k"]).await,
+            RESP::Error("ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context".to_string())
+        );
+
+        assert_eq!(
+            send(&server, &publisher, &["publish", "news", "hi"]).await,
+            RESP::Integer(2)
+        );
+        assert_eq!(
+            subscriber.take_pushes(),
+            vec![
+                message(&["message", "news", "hi"]),
+                message(&["pmessage", "n*", "news", "hi"]),
+            ]
+        );
+
+        send(&server, &subscriber, &["unsubscribe"]).await;
+        send(&server, &subscriber, &["punsubscribe"]).await;
+        subscriber.take_pushes();
+        assert_eq!(
+            send(&server, &publisher, &["publish", "news", "hi"]).await,
+            RESP::Integer(0)
+        );
+        assert_eq!(
+            send(&server, &subscriber, &["unsubscribe"]).await,
+            RESP::Array(vec![bulk("unsubscribe"), RESP::Null, RESP::Integer(0)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keyspace_notifications() {
+        let server = Arc::new(Server::default());
+        let subscriber = connect(&server);
+        let client = connect(&server);
+        send(&server, &subscriber, &["psubscribe", "__key*__:*"]).await;
+        // Off until configured
+        send(&server, &client, &["set", "k", "v"]).await;
+        assert!(subscriber.take_pushes().is_empty());
+        assert!(matches!(
+            send(
+                &server,
+                &client,
+                &["config", "set", "notify-keyspace-events", "Kw"]
+            )
+            .await,
+            RESP::Error(_)
+        ));
+
+        send(
+            &server,
+            &client,
+            &["config", "set", "notify-keyspace-events", "KEl"],
+        )
+        .await;
+        send(&server, &client, &["set", "k", "v"]).await;
+        send(&server, &client, &["rpush", "list", "a"]).await;
+        send(&server, &client, &["rpop", "list"]).await;
+        let event =
+            |channel: &str, payload: &str| message(&["pmessage", "__key*__:*", channel, payload]);
+        assert_eq!(
+            subscriber.take_pushes(),
+            vec![
+                event("__keyspace@0__:list", "rpush"),
+                event("__keyevent@0__:rpush", "list"),
+                event("__keyspace@0__:list", "rpop"),
+                event("__keyevent@0__:rpop", "list"),
+            ]
+        );
+
+        send(
+            &server,
+            &client,
+            &["config", "set", "notify-keyspace-events", "Eg$n"],
+        )
+        .await;
+        send(&server, &client, &["set", "fresh", "v"]).await;
+        send(&server, &client, &["del", "fresh", "missing"]).await;
+        assert_eq!(
+            subscriber.take_pushes(),
+            vec![
+                event("__keyevent@0__:new", "fresh"),
+                event("__keyevent@0__:set", "fresh"),
+                event("__keyevent@0__:del", "fresh"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_evicted_events() {
+        let server = Arc::new(Server::default());
+        let subscriber = connect(&server);
+        let client = connect(&server);
+        send(
+            &server,
+            &subscriber,
+            &[
+                "subscribe",
+                "__keyevent@0__:expired",
+                "__keyevent@0__:evicted",
+            ],
+        )
+        .await;
+        subscriber.take_pushes();
+        send(
+            &server,
+            &client,
+            &["config", "set", "notify-keyspace-events", "Exe"],
+        )
+        .await;
+
+        send(&server, &client, &["set", "k", "v"]).await;
+        let RESP::BulkString(payload) = send(&server, &client, &["dump", "k"]).await else {
+            panic!("DUMP replies with a bulk string");
+        };
+        let payload = String::from_utf8(payload).unwrap();
+        send(&server, &client, &["restore", "short", "1", &payload]).await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        send(&server, &client, &["get", "short"]).await;
+        assert_eq!(
+            subscriber.take_pushes(),
+            vec![message(&["message", "__keyevent@0__:expired", "short"])]
+        );
+
+        send(
+            &server,
+            &client,
+            &["config", "set", "maxmemory-policy", "allkeys-random"],
+        )
+        .await;
+        send(&server, &client, &["config", "set", "maxmemory", "1"]).await;
+        send(&server, &client, &["set", "k", "v2"]).await;
+        assert!(subscriber.take_pushes().contains(&message(&[
+            "message",
+            "__keyevent@0__:evicted",
+            "k"
+        ])));
+    }
+}
@@ -0,0 +1,68 @@
+//! Keyspace event classes, as the letters of `notify-keyspace-events`.
+
+/// `K`: publish on `__keyspace@0__:<key>`
+pub const KEYSPACE: u32 = 1 << 0;
+/// `E`: publish on `__keyevent@0__:<event>`
+pub const KEYEVENT: u32 = 1 << 1;
+/// `g`: type-agnostic commands like DEL and RESTORE
+pub const GENERIC: u32 = 1 << 2;
+pub const STRING: u32 = 1 << 3;
+pub const LIST: u32 = 1 << 4;
+pub const SET: u32 = 1 << 5;
+pub const HASH: u32 = 1 << 6;
+pub const ZSET: u32 = 1 << 7;
+pub const EXPIRED: u32 = 1 << 8;
+pub const EVICTED: u32 = 1 << 9;
+pub const STREAM: u32 = 1 << 10;
+pub const KEY_MISS: u32 = 1 << 11;
+pub const MODULE: u32 = 1 << 12;
+/// `n`: a key was created
+pub const NEW: u32 = 1 << 13;
+
+/// What `A` stands for. Key misses and new keys have to be asked for by
+/// name, like in Redis.
+pub const ALL: u32 =
+    GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM | MODULE;
+
+const LETTERS: [(char, u32); 15] = [
+    ('K', KEYSPACE),
+    ('E', KEYEVENT),
+    ('g', GENERIC),
+    ('$', STRING),
+    ('l', LIST),
+    ('s', SET),
+    ('h', HASH),
+    ('z', ZSET),
+    ('x', EXPIRED),
+    ('e', EVICTED),
+    ('t', STREAM),
+    ('m', KEY_MISS),
+    ('d', MODULE),
+    ('n', NEW),
+    ('A', ALL),
+];
+
+/// Parses a `notify-keyspace-events` value, None if it has an unknown
+/// letter.
+pub fn parse(value: &str) -> Option<u32> {
+    value.chars().try_fold(0, |flags, letter| {
+        LETTERS
+            .iter()
+            .find(|(l, _)| *l == letter)
+            .map(|(_, flag)| flags | flag)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(""), Some(0));
+        assert_eq!(parse("Ex"), Some(KEYEVENT | EXPIRED));
+        assert_eq!(parse("KA"), Some(KEYSPACE | ALL));
+        assert_eq!(parse("KA").map(|f| f & KEY_MISS), Some(0));
+        assert_eq!(parse("Kq"), None);
+    }
+}
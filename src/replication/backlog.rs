@@ -0,0 +1,251 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use tokio::sync::watch;
+
+use crate::command::CommandArg;
+use crate::resp::RESP;
+
+/// `repl-backlog-size` when it isn't configured, like Redis.
+pub const DEFAULT_BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// The tail of the replication stream in a ring buffer, kept so a replica
+/// that lost its link can pick up where it left off instead of syncing
+/// from scratch. Offsets count bytes since the stream started.
+pub struct Backlog {
+    buffer: Vec<u8>,
+    /// Offset just past the last byte written
+    end: u64,
+    /// Bytes held, at most the buffer's length
+    len: usize,
+}
+
+impl Backlog {
+    /// An empty backlog continuing the stream from `offset`.
+    pub fn new(capacity: usize, offset: u64) -> Self {
+        Backlog {
+            buffer: vec![0; capacity.max(1)],
+            end: offset,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Offset of the oldest byte still held.
+    pub fn first_offset(&self) -> u64 {
+        self.end - self.len as u64
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn append(&mut self, mut bytes: &[u8]) {
+        let capacity = self.capacity();
+        self.end += bytes.len() as u64;
+        // Only the newest `capacity` bytes can survive anyway
+        if bytes.len() > capacity {
+            bytes = &bytes[bytes.len() - capacity..];
+        }
+        let start = ((self.end - bytes.len() as u64) % capacity as u64) as usize;
+        let first = bytes.len().min(capacity - start);
+        self.buffer[start..start + first].copy_from_slice(&bytes[..first]);
+        self.buffer[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+        self.len = (self.len + bytes.len()).min(capacity);
+    }
+
+    /// Everything from `offset` to the end, or None once it's been
+    /// overwritten or if it hasn't been written yet.
+    pub fn read_from(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset < self.first_offset() || offset > self.end {
+            return None;
+        }
+        let capacity = self.capacity();
+        let count = (self.end - offset) as usize;
+        let start = (offset % capacity as u64) as usize;
+        let first = count.min(capacity - start);
+        let mut bytes = Vec::with_capacity(count);
+        bytes.extend_from_slice(&self.buffer[start..start + first]);
+        bytes.extend_from_slice(&self.buffer[..count - first]);
+        Some(bytes)
+    }
+
+    /// A copy holding as much of the newest data as fits in `capacity`.
+    fn resized(&self, capacity: usize) -> Self {
+        let mut resized = Backlog::new(capacity, self.first_offset());
+        if let Some(bytes) = self.read_from(self.first_offset()) {
+            resized.append(&bytes);
+        }
+        resized
+    }
+}
+
+/// Where writes go on their way to replicas: the backlog, plus the stream
+/// offset that connections serving replicas watch for new data.
+///
+/// There is no backlog until the first replica syncs, and only a master
+/// propagates its own writes. A replica's backlog holds what its master
+/// sent instead, so its own replicas see the same stream and offsets.
+pub struct Feed {
+    propagating: AtomicBool,
+    backlog: Mutex<Option<Backlog>>,
+    backlog_size: AtomicUsize,
+    offset: watch::Sender<u64>,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Feed {
+    pub fn new() -> Self {
+        Feed {
+            propagating: AtomicBool::new(false),
+            backlog: Mutex::new(None),
+            backlog_size: AtomicUsize::new(DEFAULT_BACKLOG_SIZE),
+            offset: watch::Sender::new(0),
+        }
+    }
+
+    /// Adds a write to the stream, if this server propagates its writes.
+    /// Callers hold whatever lock orders the write against others on the
+    /// same keys, so replicas apply them in the same order.
+    pub fn propagate(&self, args: &[CommandArg]) {
+        if !self.propagating.load(Ordering::Relaxed) {
+            return;
+        }
+        let command = RESP::Array(
+            args.iter()
+                .map(|s| RESP::BulkString(s.as_bytes().to_vec()))
+                .collect(),
+        );
+        self.append(&command.to_bytes());
+    }
+
+    /// Adds raw bytes to the stream. Does nothing without a backlog.
+    pub fn append(&self, bytes: &[u8]) {
+        let mut backlog = self.backlog.lock().unwrap();
+        if let Some(backlog) = backlog.as_mut() {
+            backlog.append(bytes);
+            self.offset.send_replace(backlog.end());
+        }
+    }
+
+    /// The offset just past the last byte of the stream.
+    pub fn offset(&self) -> u64 {
+        *self.offset.borrow()
+    }
+
+    /// Notifies of every offset change from now on.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.offset.subscribe()
+    }
+
+    pub fn read_from(&self, offset: u64) -> Option<Vec<u8>> {
+        self.backlog.lock().unwrap().as_ref()?.read_from(offset)
+    }
+
+    pub fn is_propagating(&self) -> bool {
+        self.propagating.load(Ordering::Relaxed)
+    }
+
+    /// Starts propagating this server's writes, creating the backlog at
+    /// the current offset if there is none yet.
+    pub fn start_propagating(&self) {
+        let mut backlog = self.backlog.lock().unwrap();
+        if backlog.is_none() {
+            *backlog = Some(Backlog::new(
+                self.backlog_size.load(Ordering::Relaxed),
+                self.offset(),
+            ));
+        }
+        self.propagating.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops propagating this server's writes, keeping the backlog so the
+    /// stream can continue from it under a new master.
+    pub fn stop_propagating(&self) {
+        self.propagating.store(false, Ordering::Relaxed);
+    }
+
+    /// Starts over with an empty backlog at `offset`, after a full sync.
+    pub fn reset(&self, offset: u64) {
+        let mut backlog = self.backlog.lock().unwrap();
+        *backlog = Some(Backlog::new(
+            self.backlog_size.load(Ordering::Relaxed),
+            offset,
+        ));
+        self.offset.send_replace(offset);
+    }
+
+    pub fn backlog_size(&self) -> usize {
+        self.backlog_size.load(Ordering::Relaxed)
+    }
+
+    pub fn set_backlog_size(&self, size: usize) {
+        self.backlog_size.store(size.max(1), Ordering::Relaxed);
+        let mut backlog = self.backlog.lock().unwrap();
+        if let Some(current) = backlog.as_ref()
+            && current.capacity() != size.max(1)
+        {
+            *backlog = Some(current.resized(size.max(1)));
+        }
+    }
+
+    /// The first offset held and how many bytes follow it, or None
+    /// without a backlog.
+    pub fn backlog_range(&self) -> Option<(u64, usize)> {
+        let backlog = self.backlog.lock().unwrap();
+        backlog.as_ref().map(|b| (b.first_offset(), b.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::cmd;
+
+    #[test]
+    fn test_backlog_wraps_around() {
+        let mut backlog = Backlog::new(8, 100);
+        assert_eq!(backlog.read_from(100), Some(vec![]));
+        backlog.append(b"abcdef");
+        assert_eq!(backlog.read_from(102), Some(b"cdef".to_vec()));
+        backlog.append(b"ghij");
+        assert_eq!(backlog.end(), 110);
+        assert_eq!(backlog.first_offset(), 102);
+        assert_eq!(backlog.read_from(102), Some(b"cdefghij".to_vec()));
+        assert_eq!(backlog.read_from(101), None);
+        assert_eq!(backlog.read_from(111), None);
+        backlog.append(b"0123456789");
+        assert_eq!(backlog.read_from(112), Some(b"23456789".to_vec()));
+        assert_eq!(backlog.resized(4).read_from(116), Some(b"6789".to_vec()));
+    }
+
+    #[test]
+    fn test_feed_propagates_only_with_a_backlog() {
+        let feed = Feed::new();
+        feed.propagate(&cmd(&["set", "k", "v"]));
+        assert_eq!(feed.offset(), 0);
+        feed.start_propagating();
+        let offsets = feed.subscribe();
+        feed.propagate(&cmd(&["set", "k", "v"]));
+        assert!(offsets.has_changed().unwrap());
+        assert_eq!(
+            feed.read_from(0).unwrap(),
+            b"*3\r\n$3\r\nset\r\n$1\r\nk\r\n$1\r\nv\r\n"
+        );
+        feed.stop_propagating();
+        feed.propagate(&cmd(&["del", "k"]));
+        assert_eq!(feed.offset(), 27);
+    }
+}
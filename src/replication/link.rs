@@ -0,0 +1,182 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+use super::LinkStatus;
+use crate::client::{Client, ClientKind};
+use crate::peer::{Peer, take_command};
+use crate::resp::RESP;
+use crate::server::{Server, process_request};
+use crate::storage::parse_snapshot;
+
+/// How long a replica waits before reconnecting to its master.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How often a replica acknowledges what it has processed.
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps a replica linked to its master: connects, syncs and applies the
+/// stream, starting over whenever the link drops. Runs until REPLICAOF
+/// points elsewhere and aborts it.
+pub(super) async fn run(server: Arc<Server>, id: u64, host: String, port: u16) {
+    loop {
+        if let Err(e) = follow(&server, id, &host, port).await {
+            eprintln!("replication: link to {}:{} failed: {}", host, port, e);
+        }
+        server
+            .replication
+            .set_link_status(id, LinkStatus::Connecting);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// The client that the master's commands run as, unregistered when the
+/// link task ends or is aborted.
+struct MasterClient<'a> {
+    server: &'a Server,
+    client: Arc<Client>,
+}
+
+impl Drop for MasterClient<'_> {
+    fn drop(&mut self) {
+        self.server.clients.unregister(self.client.id());
+    }
+}
+
+/// One connection to the master, from the handshake until it drops.
+async fn follow(server: &Arc<Server>, id: u64, host: &str, port: u16) -> io::Result<()> {
+    let replication = &server.replication;
+    let stream = TcpStream::connect((host, port)).await?;
+    let addr = |a: io::Result<std::net::SocketAddr>| a.map(|a| a.to_string()).unwrap_or_default();
+    let master = MasterClient {
+        server,
+        client: server
+            .clients
+            .register(addr(stream.peer_addr()), addr(stream.local_addr())),
+    };
+    master.client.set_kind(ClientKind::Master);
+    master.client.login("default");
+    let mut connection = Peer::new(stream);
+
+    // A master that wants a password says so here, which is fine
+    if let Err(e) = connection.command(&["PING"]).await
+        && !e.to_string().contains("NOAUTH")
+    {
+        return Err(e);
+    }
+    let masterauth = server.get_config_value("masterauth");
+    if !masterauth.is_empty() {
+        let masteruser = server.get_config_value("masteruser");
+        if masteruser.is_empty() {
+            connection.command(&["AUTH", &masterauth]).await?;
+        } else {
+            connection
+                .command(&["AUTH", &masteruser, &masterauth])
+                .await?;
+        }
+    }
+    connection
+        .command(&["REPLCONF", "listening-port", &server.port()])
+        .await?;
+    connection.command(&["REPLCONF", "capa", "psync2"]).await?;
+
+    replication.set_link_status(id, LinkStatus::Syncing);
+    let (replid, offset) = match replication.psync_position() {
+        Some((replid, offset)) => (replid, (offset + 1).to_string()),
+        None => ("?".to_string(), "-1".to_string()),
+    };
+    let reply = connection.command(&["PSYNC", &replid, &offset]).await?;
+    let mut words = reply.split(' ');
+    match words.next() {
+        Some("FULLRESYNC") => {
+            let (Some(replid), Some(Ok(offset))) = (words.next(), words.next().map(str::parse))
+            else {
+                return Err(io::Error::other(format!("bad PSYNC reply: {}", reply)));
+            };
+            let snapshot = receive_snapshot(&mut connection).await?;
+            let records = parse_snapshot(&snapshot)
+                .ok_or_else(|| io::Error::other("master sent a malformed snapshot"))?;
+            let loader = server.clone();
+            tokio::task::spawn_blocking(move || loader.storage.load_snapshot(records))
+                .await
+                .map_err(io::Error::other)?;
+            replication.full_synced(id, replid.to_string(), offset);
+        }
+        Some("CONTINUE") => replication.continued(id, words.next().map(str::to_string)),
+        _ => return Err(io::Error::other(format!("bad PSYNC reply: {}", reply))),
+    }
+    replication.set_link_status(id, LinkStatus::Connected);
+    stream_commands(server, id, &master.client, &mut connection).await
+}
+
+/// Reads the `$<len>\r\n<payload>` snapshot of a full sync.
+async fn receive_snapshot(connection: &mut Peer) -> io::Result<String> {
+    // Masters may send empty lines to keep the link alive while preparing
+    let header = loop {
+        let line = connection.read_line().await?;
+        if !line.is_empty() {
+            break line;
+        }
+    };
+    let len = header
+        .strip_prefix('$')
+        .and_then(|len| len.parse().ok())
+        .ok_or_else(|| io::Error::other(format!("bad snapshot header: {}", header)))?;
+    let payload = connection.read_exact(len).await?;
+    String::from_utf8(payload).map_err(io::Error::other)
+}
+
+/// Applies the master's commands as they come, adding each to our own
+/// backlog for replicas of ours, and acknowledges progress.
+async fn stream_commands(
+    server: &Arc<Server>,
+    id: u64,
+    client: &Arc<Client>,
+    connection: &mut Peer,
+) -> io::Result<()> {
+    let replication = &server.replication;
+    let mut ack = tokio::time::interval(ACK_INTERVAL);
+    loop {
+        while let Some((args, raw)) =
+            take_command(&mut connection.buffer).map_err(|e| io::Error::other(e.to_string()))?
+        {
+            let getack = args.len() >= 2
+                && args[0].eq_ignore_ascii_case("replconf")
+                && args[1].eq_ignore_ascii_case("getack");
+            {
+                let _applying = replication.applying.lock().await;
+                if !getack && !args[0].eq_ignore_ascii_case("ping") {
+                    let request = RESP::Array(
+                        args.into_iter()
+                            .map(|s| RESP::BulkString(s.into_bytes()))
+                            .collect(),
+                    );
+                    if let Err(e) = process_request(request, server.clone(), client).await {
+                        eprintln!("replication: command from master failed: {}", e);
+                    }
+                }
+                replication.feed.append(&raw);
+            }
+            if getack {
+                acknowledge(replication, connection).await?;
+            }
+        }
+        tokio::select! {
+            read = connection.fill() => {
+                read?;
+                replication.link_io(id);
+            }
+            _ = ack.tick() => acknowledge(replication, connection).await?,
+            _ = client.killed() => {
+                return Err(io::Error::other("master client killed"));
+            }
+        }
+    }
+}
+
+async fn acknowledge(replication: &super::Replication, connection: &mut Peer) -> io::Result<()> {
+    let offset = replication.feed.offset().to_string();
+    connection.send(&["REPLCONF", "ACK", &offset]).await
+}
@@ -0,0 +1,271 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::{PendingSync, ReplicaInfo, Replication};
+use crate::client::{Client, ClientKind, QUERY_BUFFER_SIZE};
+use crate::command::CommandArg;
+use crate::peer::take_command;
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+
+impl Replication {
+    /// Whether the backlog can bring a replica that followed `replid` up
+    /// to `offset` into line.
+    fn can_continue(&self, replid: &str, offset: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        let known = replid == state.replid
+            || (replid == state.replid2
+                && state
+                    .second_replid_offset
+                    .is_some_and(|second| offset <= second));
+        known && self.feed.read_from(offset).is_some()
+    }
+
+    /// The sync PSYNC agreed with `client`, once its reply is sent.
+    pub fn take_sync(&self, client: u64) -> Option<PendingSync> {
+        self.state.lock().unwrap().pending.remove(&client)
+    }
+
+    fn add_replica(&self, client: &Client, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        let port = state
+            .listening_ports
+            .get(&client.id())
+            .copied()
+            .unwrap_or(0);
+        let ip = client
+            .addr()
+            .rsplit_once(':')
+            .map_or(client.addr(), |(ip, _)| ip)
+            .to_string();
+        state.replicas.insert(
+            client.id(),
+            ReplicaInfo {
+                ip,
+                port,
+                ack_offset: offset,
+                last_ack: Instant::now(),
+            },
+        );
+    }
+
+    fn remove_replica(&self, client: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.replicas.remove(&client);
+        state.listening_ports.remove(&client);
+    }
+
+    fn ack(&self, client: u64, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(replica) = state.replicas.get_mut(&client) {
+            replica.ack_offset = replica.ack_offset.max(offset);
+            replica.last_ack = Instant::now();
+        }
+        drop(state);
+        self.acked.notify_waiters();
+    }
+
+    fn replicas_acked(&self, offset: u64) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .replicas
+            .values()
+            .filter(|replica| replica.ack_offset >= offset)
+            .count()
+    }
+}
+
+/// PSYNC replid offset. Agrees to continue from the backlog when it can,
+/// and otherwise takes a snapshot for a full sync. Either way the
+/// connection hands over to `serve_replica` after the reply.
+pub async fn command_psync(
+    server: &Arc<Server>,
+    client: &Client,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let replication = &server.replication;
+    {
+        let state = replication.state.lock().unwrap();
+        if let Some(master) = &state.master
+            && master.status != super::LinkStatus::Connected
+        {
+            return Err(ServerError::NoMasterLink);
+        }
+    }
+    // Redis counts offsets from 1, so the replica asks for the byte after
+    // the last one it has
+    let requested = args[1].parse::<i64>().ok().filter(|offset| *offset > 0);
+    if let Some(offset) = requested.map(|offset| offset as u64 - 1)
+        && replication.can_continue(&args[0], offset)
+    {
+        replication.sync_partial_ok.fetch_add(1, Ordering::Relaxed);
+        let mut state = replication.state.lock().unwrap();
+        state
+            .pending
+            .insert(client.id(), PendingSync::Partial { offset });
+        return Ok(RESP::SimpleString(format!("CONTINUE {}", state.replid)));
+    }
+    if args[0] != "?" {
+        replication.sync_partial_err.fetch_add(1, Ordering::Relaxed);
+    }
+    replication.sync_full.fetch_add(1, Ordering::Relaxed);
+
+    let master = !replication.is_replica();
+    let snapshot_server = server.clone();
+    let (snapshot, offset) = tokio::task::spawn_blocking(move || {
+        let replication = &snapshot_server.replication;
+        let _applying = replication.applying.blocking_lock();
+        snapshot_server.storage.snapshot(|| {
+            if master {
+                replication.feed.start_propagating();
+            }
+            replication.feed.offset()
+        })
+    })
+    .await
+    .expect("snapshot task panicked");
+    let mut state = replication.state.lock().unwrap();
+    state
+        .pending
+        .insert(client.id(), PendingSync::Full { snapshot, offset });
+    Ok(RESP::SimpleString(format!(
+        "FULLRESYNC {} {}",
+        state.replid, offset
+    )))
+}
+
+/// Feeds a replica the stream over its connection until it goes away:
+/// the snapshot of a full sync first, then everything that reaches the
+/// backlog. Reads the acknowledgements it sends back meanwhile.
+pub async fn serve_replica(
+    stream: &mut TcpStream,
+    server: &Arc<Server>,
+    client: &Arc<Client>,
+    sync: PendingSync,
+) {
+    let replication = &server.replication;
+    let feed = &replication.feed;
+    client.set_kind(ClientKind::Replica);
+    let (mut reader, mut writer) = stream.split();
+    let mut sent = match sync {
+        PendingSync::Full { snapshot, offset } => {
+            let payload = format!("${}\r\n{}", snapshot.len(), snapshot);
+            if writer.write_all(payload.as_bytes()).await.is_err() {
+                return;
+            }
+            server.stats.record_output(payload.len());
+            offset
+        }
+        PendingSync::Partial { offset } => offset,
+    };
+    replication.add_replica(client, sent);
+    let mut offsets = feed.subscribe();
+    let mut buffer = [0; QUERY_BUFFER_SIZE];
+    let mut pending = Vec::new();
+    loop {
+        let end = *offsets.borrow_and_update();
+        if end > sent {
+            // A replica too slow to keep up with the backlog has to resync
+            let Some(bytes) = feed.read_from(sent) else {
+                break;
+            };
+            if writer.write_all(&bytes).await.is_err() {
+                break;
+            }
+            server.stats.record_output(bytes.len());
+            sent += bytes.len() as u64;
+            continue;
+        }
+        tokio::select! {
+            changed = offsets.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            }
+            read = reader.read(&mut buffer) => {
+                let size = match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(size) => size,
+                };
+                server.stats.record_input(size);
+                pending.extend_from_slice(&buffer[..size]);
+                let mut valid = true;
+                loop {
+                    match take_command(&mut pending) {
+                        Ok(Some((args, _))) => {
+                            if args.len() == 3
+                                && args[0].eq_ignore_ascii_case("replconf")
+                                && args[1].eq_ignore_ascii_case("ack")
+                                && let Ok(offset) = args[2].parse()
+                            {
+                                replication.ack(client.id(), offset);
+                            }
+                        }
+                        Ok(None) => break,
+                        Err(_) => {
+                            valid = false;
+                            break;
+                        }
+                    }
+                }
+                if !valid {
+                    break;
+                }
+            }
+            _ = client.killed() => break,
+        }
+    }
+    replication.remove_replica(client.id());
+}
+
+/// WAIT numreplicas timeout. Blocks until that many replicas acknowledged
+/// every write made so far, or the timeout in milliseconds passes (0
+/// waits forever), and replies with how many did.
+pub async fn command_wait(server: &Server, args: &[CommandArg]) -> ServerResult<RESP> {
+    let not_integer =
+        || ServerError::InvalidArgument("value is not an integer or out of range".to_string());
+    let wanted: i64 = args[0].parse().map_err(|_| not_integer())?;
+    let timeout: i64 = args[1].parse().map_err(|_| not_integer())?;
+    if timeout < 0 {
+        return Err(ServerError::InvalidArgument(
+            "timeout is negative".to_string(),
+        ));
+    }
+    let replication = &server.replication;
+    if replication.is_replica() {
+        return Err(ServerError::InvalidArgument(
+            "WAIT cannot be used with replica instances.".to_string(),
+        ));
+    }
+    let target = replication.feed.offset();
+    let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+    let mut asked = false;
+    loop {
+        let acked = replication.acked.notified();
+        tokio::pin!(acked);
+        acked.as_mut().enable();
+        let count = replication.replicas_acked(target);
+        if count as i64 >= wanted || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Ok(RESP::Integer(count as i64));
+        }
+        if !asked {
+            replication
+                .feed
+                .propagate(&["REPLCONF".into(), "GETACK".into(), "*".into()]);
+            asked = true;
+        }
+        match deadline {
+            Some(deadline) => {
+                tokio::select! {
+                    _ = acked => {}
+                    _ = tokio::time::sleep_until(deadline.into()) => {}
+                }
+            }
+            None => acked.await,
+        }
+    }
+}
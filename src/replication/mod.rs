@@ -0,0 +1,541 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+mod backlog;
+mod link;
+mod master;
+
+pub use self::backlog::{DEFAULT_BACKLOG_SIZE, Feed};
+pub use self::master::{command_psync, command_wait, serve_replica};
+use crate::client::Client;
+use crate::command::CommandArg;
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+
+/// What `master_replid2` shows when there is no previous replication ID.
+const NO_REPLID: &str = "0000000000000000000000000000000000000000";
+
+/// A fresh 40 character replication ID, like the ones Redis makes up.
+fn new_replid() -> String {
+    format!(
+        "{:032x}{:08x}",
+        rand::random::<u128>(),
+        rand::random::<u32>()
+    )
+}
+
+/// How far along a replica's link to its master is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LinkStatus {
+    Connecting,
+    /// Receiving the snapshot of a full sync
+    Syncing,
+    Connected,
+}
+
+struct MasterLink {
+    host: String,
+    port: u16,
+    /// Tells this REPLICAOF's link task apart from ones it replaced
+    id: u64,
+    status: LinkStatus,
+    last_io: Instant,
+    task: JoinHandle<()>,
+}
+
+/// A replica being fed the stream, as INFO shows it.
+struct ReplicaInfo {
+    ip: String,
+    /// The port it announced with REPLCONF listening-port, 0 if none
+    port: u16,
+    /// The offset it last acknowledged
+    ack_offset: u64,
+    last_ack: Instant,
+}
+
+/// How a PSYNC was answered, for the connection to carry out once the
+/// reply has gone out.
+pub enum PendingSync {
+    /// A snapshot of the keyspace taken at `offset`
+    Full { snapshot: String, offset: u64 },
+    /// The backlog from `offset` on is all the replica needs
+    Partial { offset: u64 },
+}
+
+struct State {
+    replid: String,
+    /// The ID this server replicated under before, still good for partial
+    /// syncs up to `second_replid_offset`
+    replid2: String,
+    second_replid_offset: Option<u64>,
+    /// Set while this server is a replica
+    master: Option<MasterLink>,
+    replicas: BTreeMap<u64, ReplicaInfo>,
+    /// Ports given with REPLCONF listening-port, by client ID
+    listening_ports: HashMap<u64, u16>,
+    /// Syncs agreed with PSYNC but not yet started, by client ID
+    pending: HashMap<u64, PendingSync>,
+}
+
+/// This server's part in replication: its role, replication ID and the
+/// replicas it feeds. Writes reach replicas through the `Feed`, which the
+/// keyspace shares.
+pub struct Replication {
+    feed: Arc<Feed>,
+    state: Mutex<State>,
+    next_link_id: AtomicU64,
+    /// Held by the master link while it applies a command and adds it to
+    /// the backlog, so snapshots taken on a replica match its offset
+    applying: tokio::sync::Mutex<()>,
+    /// Woken whenever a replica acknowledges an offset, for WAIT
+    acked: Notify,
+    sync_full: AtomicU64,
+    sync_partial_ok: AtomicU64,
+    sync_partial_err: AtomicU64,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Replication {
+    pub fn new() -> Self {
+        Replication {
+            feed: Arc::new(Feed::new()),
+            state: Mutex::new(State {
+                replid: new_replid(),
+                replid2: NO_REPLID.to_string(),
+                second_replid_offset: None,
+                master: None,
+                replicas: BTreeMap::new(),
+                listening_ports: HashMap::new(),
+                pending: HashMap::new(),
+            }),
+            next_link_id: AtomicU64::new(1),
+            applying: tokio::sync::Mutex::new(()),
+            acked: Notify::new(),
+            sync_full: AtomicU64::new(0),
+            sync_partial_ok: AtomicU64::new(0),
+            sync_partial_err: AtomicU64::new(0),
+        }
+    }
+
+    pub fn feed(&self) -> &Arc<Feed> {
+        &self.feed
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.state.lock().unwrap().master.is_some()
+    }
+
+    /// Clears the sync counters for CONFIG RESETSTAT.
+    pub fn reset_stats(&self) {
+        self.sync_full.store(0, Ordering::Relaxed);
+        self.sync_partial_ok.store(0, Ordering::Relaxed);
+        self.sync_partial_err.store(0, Ordering::Relaxed);
+    }
+
+    /// The sync counters of INFO stats.
+    pub fn sync_stats(&self) -> [(&'static str, u64); 3] {
+        [
+            ("sync_full", self.sync_full.load(Ordering::Relaxed)),
+            (
+                "sync_partial_ok",
+                self.sync_partial_ok.load(Ordering::Relaxed),
+            ),
+            (
+                "sync_partial_err",
+                self.sync_partial_err.load(Ordering::Relaxed),
+            ),
+        ]
+    }
+
+    /// Starts replicating from a master, replacing any previous one. Our
+    /// own replicas are dropped so they resync with the new data.
+    pub fn replicate(&self, server: &Arc<Server>, host: String, port: u16) {
+        let id = self.next_link_id.fetch_add(1, Ordering::Relaxed);
+        let task = tokio::spawn(link::run(server.clone(), id, host.clone(), port));
+        let mut state = self.state.lock().unwrap();
+        if let Some(previous) = state.master.take() {
+            previous.task.abort();
+        }
+        state.master = Some(MasterLink {
+            host,
+            port,
+            id,
+            status: LinkStatus::Connecting,
+            last_io: Instant::now(),
+            task,
+        });
+        self.feed.stop_propagating();
+        drop(state);
+        self.disconnect_replicas(server);
+    }
+
+    /// Turns a replica into a master. The stream carries on under a new
+    /// replication ID, and replicas that followed the old one can still
+    /// continue from the backlog.
+    pub fn promote(&self) {
+        let mut state = self.state.lock().unwrap();
+        let Some(master) = state.master.take() else {
+            return;
+        };
+        master.task.abort();
+        state.replid2 = std::mem::replace(&mut state.replid, new_replid());
+        state.second_replid_offset = Some(self.feed.offset());
+        self.feed.start_propagating();
+    }
+
+    fn disconnect_replicas(&self, server: &Server) {
+        let ids: Vec<u64> = self
+            .state
+            .lock()
+            .unwrap()
+            .replicas
+            .keys()
+            .copied()
+            .collect();
+        for client in server.clients.list() {
+            if ids.contains(&client.id()) {
+                client.kill();
+            }
+        }
+    }
+
+    /// The replication ID and offset a replica asks its master to
+    /// continue from, or None for a first full sync.
+    fn psync_position(&self) -> Option<(String, u64)> {
+        self.feed.backlog_range()?;
+        let state = self.state.lock().unwrap();
+        Some((state.replid.clone(), self.feed.offset()))
+    }
+
+    /// Whether `id` is still the current master link.
+    fn is_current_link(state: &State, id: u64) -> bool {
+        state.master.as_ref().is_some_and(|master| master.id == id)
+    }
+
+    fn set_link_status(&self, id: u64, status: LinkStatus) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(master) = state.master.as_mut().filter(|master| master.id == id) {
+            master.status = status;
+            master.last_io = Instant::now();
+        }
+    }
+
+    fn link_io(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(master) = state.master.as_mut().filter(|master| master.id == id) {
+            master.last_io = Instant::now();
+        }
+    }
+
+    /// Takes on the master's replication ID after loading its snapshot.
+    fn full_synced(&self, id: u64, replid: String, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        if !Self::is_current_link(&state, id) {
+            return;
+        }
+        state.replid = replid;
+        state.replid2 = NO_REPLID.to_string();
+        state.second_replid_offset = None;
+        self.feed.reset(offset);
+    }
+
+    /// Follows the master to a new replication ID on a partial sync,
+    /// keeping the old one for our own replicas.
+    fn continued(&self, id: u64, replid: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        if !Self::is_current_link(&state, id) {
+            return;
+        }
+        if let Some(replid) = replid.filter(|replid| *replid != state.replid) {
+            state.replid2 = std::mem::replace(&mut state.replid, replid);
+            state.second_replid_offset = Some(self.feed.offset());
+        }
+    }
+
+    /// The fields of INFO replication.
+    pub fn info(&self, read_only: bool) -> Vec<(String, String)> {
+        let state = self.state.lock().unwrap();
+        let offset = self.feed.offset();
+        let mut lines: Vec<(String, String)> = Vec::new();
+        let mut line = |key: &str, value: String| lines.push((key.to_string(), value));
+        match &state.master {
+            None => line("role", "master".to_string()),
+            Some(master) => {
+                line("role", "slave".to_string());
+                line("master_host", master.host.clone());
+                line("master_port", master.port.to_string());
+                let up = master.status == LinkStatus::Connected;
+                line(
+                    "master_link_status",
+                    if up { "up" } else { "down" }.to_string(),
+                );
+                line(
+                    "master_last_io_seconds_ago",
+                    master.last_io.elapsed().as_secs().to_string(),
+                );
+                line(
+                    "master_sync_in_progress",
+                    ((master.status == LinkStatus::Syncing) as u8).to_string(),
+                );
+                line("slave_read_repl_offset", offset.to_string());
+                line("slave_repl_offset", offset.to_string());
+                line("slave_priority", "100".to_string());
+                line("slave_read_only", (read_only as u8).to_string());
+                line("replica_announced", "1".to_string());
+            }
+        }
+        line("connected_slaves", state.replicas.len().to_string());
+        for (i, replica) in state.replicas.values().enumerate() {
+            line(
+                &format!("slave{}", i),
+                format!(
+                    "ip={},port={},state=online,offset={},lag={}",
+                    replica.ip,
+                    replica.port,
+                    replica.ack_offset,
+                    replica.last_ack.elapsed().as_secs()
+                ),
+            );
+        }
+        line("master_failover_state", "no-failover".to_string());
+        line("master_replid", state.replid.clone());
+        line("master_replid2", state.replid2.clone());
+        line("master_repl_offset", offset.to_string());
+        line(
+            "second_repl_offset",
+            state
+                .second_replid_offset
+                .map_or("-1".to_string(), |offset| (offset + 1).to_string()),
+        );
+        let backlog = self.feed.backlog_range();
+        let (first, histlen) = backlog.unwrap_or((0, 0));
+        line("repl_backlog_active", (backlog.is_some() as u8).to_string());
+        line("repl_backlog_size", self.feed.backlog_size().to_string());
+        // Redis counts backlog offsets from 1
+        line(
+            "repl_backlog_first_byte_offset",
+            (first + backlog.is_some() as u64).to_string(),
+        );
+        line("repl_backlog_histlen", histlen.to_string());
+        lines
+    }
+}
+
+fn ok() -> ServerResult<RESP> {
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+fn parse_port(value: &str) -> ServerResult<u16> {
+    value.parse().map_err(|_| {
+        ServerError::InvalidArgument("value is not an integer or out of range".to_string())
+    })
+}
+
+/// REPLICAOF and SLAVEOF
+pub fn command_replicaof(server: &Arc<Server>, args: &[CommandArg]) -> ServerResult<RESP> {
+    let replication = &server.replication;
+    if args[0].eq_ignore_ascii_case("no") && args[1].eq_ignore_ascii_case("one") {
+        replication.promote();
+        return ok();
+    }
+    let port = parse_port(&args[1])?;
+    {
+        let state = replication.state.lock().unwrap();
+        if let Some(master) = &state.master
+            && master.host == args[0].as_str()
+            && master.port == port
+        {
+            return Ok(RESP::SimpleString(
+                "OK Already connected to specified master".to_string(),
+            ));
+        }
+    }
+    replication.replicate(server, args[0].to_string(), port);
+    ok()
+}
+
+/// REPLCONF, which replicas use to describe themselves during the
+/// handshake and to acknowledge what they processed.
+pub fn command_replconf(
+    server: &Server,
+    client: &Client,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    if !args.len().is_multiple_of(2) {
+        return Err(ServerError::InvalidArgument("syntax error".to_string()));
+    }
+    for pair in args.chunks(2) {
+        match pair[0].to_lowercase().as_str() {
+            "listening-port" => {
+                let port = parse_port(&pair[1])?;
+                let mut state = server.replication.state.lock().unwrap();
+                state.listening_ports.insert(client.id(), port);
+            }
+            // Acknowledgements are read by the connection feeding the
+            // replica, so anywhere else they mean nothing
+            "ip-address" | "capa" | "ack" | "getack" => (),
+            option => {
+                return Err(ServerError::InvalidArgument(format!(
+                    "Unrecognized REPLCONF option: {}",
+                    option
+                )));
+            }
+        }
+    }
+    ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::client::ClientKind;
+    use crate::server::serve;
+    use crate::storage::{Keyspace, ShardPool};
+    use crate::test_support::send;
+
+    /// A server listening on a free port, with a client connected to it.
+    async fn start(storage: Keyspace) -> (Arc<Server>, Arc<Client>, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = HashMap::from([("port".to_string(), port.to_string())]);
+        let server = Arc::new(Server::new(config, storage));
+        tokio::spawn(serve(listener, server.clone()));
+        let client = server.clients.register(String::new(), String::new());
+        client.login("default");
+        (server, client, port)
+    }
+
+    /// Polls until `parts` gets `expected`, failing after a few seconds.
+    async fn eventually(
+        server: &Arc<Server>,
+        client: &Arc<Client>,
+        parts: &[&str],
+        expected: RESP,
+    ) {
+        for _ in 0..200 {
+            if send(server, client, parts).await == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        assert_eq!(send(server, client, parts).await, expected);
+    }
+
+    fn info_field(server: &Server, field: &str) -> String {
+        server
+            .replication
+            .info(true)
+            .into_iter()
+            .find(|(key, _)| key == field)
+            .map(|(_, value)| value)
+            .unwrap_or_default()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_replica_follows_master() {
+        let (master, master_client, master_port) = start(Keyspace::default()).await;
+        let (replica, replica_client, _) = start(Keyspace::SharedNothing(ShardPool::new(2))).await;
+        send(&master, &master_client, &["set", "before", "1"]).await;
+        send(&master, &master_client, &["rpush", "list", "a"]).await;
+
+        let port = master_port.to_string();
+        assert_eq!(
+            send(
+                &replica,
+                &replica_client,
+                &["replicaof", "127.0.0.1", &port]
+            )
+            .await,
+            RESP::SimpleString("OK".to_string())
+        );
+        eventually(
+            &replica,
+            &replica_client,
+            &["get", "before"],
+            RESP::BulkString("1".into()),
+        )
+        .await;
+        assert_eq!(
+            send(&replica, &replica_client, &["llen", "list"]).await,
+            RESP::Integer(1)
+        );
+
+        // Writes after the sync are streamed, and WAIT sees them acknowledged
+        send(&master, &master_client, &["incr", "counter"]).await;
+        send(&master, &master_client, &["mset", "a", "1", "b", "2"]).await;
+        assert_eq!(
+            send(&master, &master_client, &["wait", "1", "5000"]).await,
+            RESP::Integer(1)
+        );
+        eventually(
+            &replica,
+            &replica_client,
+            &["mget", "counter", "a", "b"],
+            RESP::Array(vec![
+                RESP::BulkString("1".into()),
+                RESP::BulkString("1".into()),
+                RESP::BulkString("2".into()),
+            ]),
+        )
+        .await;
+        assert_eq!(
+            send(&replica, &replica_client, &["set", "x", "1"]).await,
+            RESP::Error("READONLY You can't write against a read only replica.".to_string())
+        );
+        assert_eq!(
+            send(&replica, &replica_client, &["wait", "0", "0"]).await,
+            RESP::Error("ERR WAIT cannot be used with replica instances.".to_string())
+        );
+        assert_eq!(info_field(&replica, "master_link_status"), "up");
+        assert_eq!(info_field(&master, "connected_slaves"), "1");
+        assert_eq!(
+            info_field(&replica, "master_replid"),
+            info_field(&master, "master_replid")
+        );
+
+        // A dropped link picks up from the backlog
+        for client in master.clients.list() {
+            if client.kind() == ClientKind::Replica {
+                client.kill();
+            }
+        }
+        while info_field(&master, "connected_slaves") != "0" {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        send(&master, &master_client, &["set", "after", "2"]).await;
+        eventually(
+            &replica,
+            &replica_client,
+            &["get", "after"],
+            RESP::BulkString("2".into()),
+        )
+        .await;
+        assert_eq!(master.replication.sync_stats()[0].1, 1);
+        assert_eq!(master.replication.sync_stats()[1].1, 1);
+
+        // Promoted, the replica takes writes of its own
+        send(&replica, &replica_client, &["replicaof", "no", "one"]).await;
+        assert_eq!(
+            send(&replica, &replica_client, &["set", "x", "1"]).await,
+            RESP::SimpleString("OK".to_string())
+        );
+        assert_eq!(
+            info_field(&replica, "master_replid2"),
+            info_field(&master, "master_replid")
+        );
+    }
+}
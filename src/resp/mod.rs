@@ -1,39 +1,57 @@
 mod result;
 mod util;
 
-use std::fmt::{self, Display};
-
-use crate::resp::result::{RESPError, RESPResult};
+pub use crate::resp::result::RESPError;
+use crate::resp::result::RESPResult;
 use crate::resp::util::*;
 
 #[derive(Debug, PartialEq)]
 pub enum RESP {
     Array(Vec<RESP>),
-    BulkString(String),
+    /// Any bytes, so values that aren't UTF-8 go out as they were written
+    BulkString(Vec<u8>),
     Null,
+    /// The null some commands send where an array would go, like XREAD
+    /// when nothing came
+    NullArray,
     SimpleString(String),
     Integer(i64),
     Error(String),
+    /// A RESP3 out-of-band push, sent between replies
+    Push(Vec<RESP>),
 }
 
-impl Display for RESP {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl RESP {
+    /// The reply as it goes over the wire.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.write_to(&mut output);
+        output
+    }
+
+    fn write_to(&self, output: &mut Vec<u8>) {
         match self {
-            Self::Array(array) => {
-                write!(f, "*{}\r\n", array.len())?;
+            Self::Array(array) | Self::Push(array) => {
+                let kind = if matches!(self, Self::Push(_)) {
+                    '>'
+                } else {
+                    '*'
+                };
+                output.extend_from_slice(format!("{}{}\r\n", kind, array.len()).as_bytes());
                 for item in array {
-                    write!(f, "{}", item)?;
+                    item.write_to(output);
                 }
-                Ok(())
             }
-            Self::BulkString(s) => write!(f, "${}\r\n{}\r\n", s.len(), s),
-            Self::Null => write!(f, "$-1\r\n"),
-            Self::SimpleString(s) => write!(f, "+{}\r\n", s),
-            Self::Integer(i) => {
-                let sign = if i.is_negative() { "-" } else { "" };
-                write!(f, ":{}{}\r\n", sign, i.abs())
+            Self::BulkString(s) => {
+                output.extend_from_slice(format!("${}\r\n", s.len()).as_bytes());
+                output.extend_from_slice(s);
+                output.extend_from_slice(b"\r\n");
             }
-            Self::Error(s) => write!(f, "-{}\r\n", s),
+            Self::Null => output.extend_from_slice(b"$-1\r\n"),
+            Self::NullArray => output.extend_from_slice(b"*-1\r\n"),
+            Self::SimpleString(s) => output.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            Self::Integer(i) => output.extend_from_slice(format!(":{}\r\n", i).as_bytes()),
+            Self::Error(s) => output.extend_from_slice(format!("-{}\r\n", s).as_bytes()),
         }
     }
 }
@@ -63,12 +81,12 @@ where
 }
 
 pub fn bytes_to_resp(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
-    if buffer.len() == 0 {
+    if buffer.is_empty() {
         return Err(RESPError::Unknown);
     }
     if buffer[0] == b'*' {
         // bytes must be a RESP protocol array
-        return parse_array(buffer, index);
+        parse_array(buffer, index)
     } else {
         // If the command doesn't start with a RESP type, then the command
         // isn't using the Redis serialization protocol. It should be interpreted
@@ -76,14 +94,13 @@ pub fn bytes_to_resp(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
         // *2\r\n$4\r\nECHO\r\n$4\r\nHEY!\r\n <-- RESP
         // ECHO HEY!\r\n <-- plain text
         // This is necessary for compatibility with some Redis tools
-        return try_parse_preresp(buffer, index);
+        try_parse_preresp(buffer, index)
     }
 }
 
-fn parser_router(
-    buffer: &[u8],
-    index: &mut usize,
-) -> Option<fn(&[u8], &mut usize) -> RESPResult<RESP>> {
+type Parser = fn(&[u8], &mut usize) -> RESPResult<RESP>;
+
+fn parser_router(buffer: &[u8], index: &mut usize) -> Option<Parser> {
     match buffer[*index] {
         b'*' => Some(parse_array),
         b'$' => Some(parse_bulk_string),
@@ -123,8 +140,7 @@ fn parse_bulk_string(buffer: &[u8], index: &mut usize) -> RESPResult<RESP> {
     if length < -1 {
         return Err(RESPError::IncorrectLength(length));
     }
-    let bytes = binary_extract_bytes(buffer, index, length as usize)?;
-    let data: String = String::from_utf8(bytes)?;
+    let data = binary_extract_bytes(buffer, index, length as usize)?;
     // Increment the index to skip the \r\n
     *index += 2;
     Ok(RESP::BulkString(data))
@@ -144,11 +160,11 @@ fn try_parse_preresp(buffer: &[u8], _index: &mut usize) -> RESPResult<RESP> {
     {
         return Err(RESPError::Unknown);
     }
-    let result = String::from_utf8_lossy(&buffer[..buffer.len() - 2])
-        .split(" ")
-        .map(|s| RESP::BulkString(s.to_string()))
+    let result = buffer[..buffer.len() - 2]
+        .split(|b| *b == b' ')
+        .map(|s| RESP::BulkString(s.to_vec()))
         .collect();
-    return Ok(RESP::Array(result));
+    Ok(RESP::Array(result))
 }
 
 #[cfg(test)]
@@ -164,7 +180,7 @@ mod test {
                 let result = bytes_to_resp(buffer, &mut index).unwrap();
                 assert_eq!(result, $expected);
                 assert_eq!(index, $expected_index);
-                assert_eq!(result.to_string(), $buffer);
+                assert_eq!(result.to_bytes(), $buffer.as_bytes());
             }
         };
     }
@@ -192,7 +208,7 @@ mod test {
     parse_test!(
         test_parse_array_bulk_string,
         "*1\r\n$5\r\nhello\r\n",
-        RESP::Array(vec![RESP::BulkString(String::from("hello"))]),
+        RESP::Array(vec![RESP::BulkString("hello".into())]),
         15
     );
 
@@ -211,7 +227,7 @@ mod test {
         "*2\r\n+hello\r\n$5\r\nworld\r\n",
         RESP::Array(vec![
             RESP::SimpleString(String::from("hello")),
-            RESP::BulkString(String::from("world"))
+            RESP::BulkString("world".into())
         ]),
         23
     );
@@ -221,7 +237,7 @@ mod test {
         "*2\r\n+hello\r\n$5\r\nworld\r\n",
         RESP::Array(vec![
             RESP::SimpleString(String::from("hello")),
-            RESP::BulkString(String::from("world"))
+            RESP::BulkString("world".into())
         ]),
         23
     );
@@ -236,4 +252,20 @@ mod test {
     );
 
     parse_test_expect_error!(test_bytes_to_resp_unknown, "?OK\r\n", RESPError::Unknown, 0);
+
+    #[test]
+    fn test_write_integers() {
+        assert_eq!(RESP::Integer(42).to_bytes(), b":42\r\n");
+        assert_eq!(RESP::Integer(-7).to_bytes(), b":-7\r\n");
+        assert_eq!(
+            RESP::Integer(i64::MIN).to_bytes(),
+            b":-9223372036854775808\r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_nulls() {
+        assert_eq!(RESP::Null.to_bytes(), b"$-1\r\n");
+        assert_eq!(RESP::NullArray.to_bytes(), b"*-1\r\n");
+    }
 }
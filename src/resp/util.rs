@@ -53,6 +53,7 @@ pub fn binary_extract_line(buffer: &[u8], index: &mut usize) -> RESPResult<Vec<u
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_return)]
 mod test {
     use super::*;
 
@@ -0,0 +1,124 @@
+use mlua::{Lua, Table, Value};
+
+use crate::command::CommandArg;
+use crate::resp::RESP;
+
+/// A script's return value as a reply, the way Redis converts it: numbers
+/// are truncated to integers, `true` is 1, `false` and nil are nil, and
+/// tables are arrays up to their first nil unless they carry an `ok` or
+/// `err` field.
+pub fn to_resp(value: &Value) -> RESP {
+    match value {
+        Value::Nil | Value::Boolean(false) => RESP::Null,
+        Value::Boolean(true) => RESP::Integer(1),
+        Value::Integer(n) => RESP::Integer(*n),
+        Value::Number(n) => RESP::Integer(*n as i64),
+        Value::String(s) => RESP::BulkString(s.as_bytes().to_vec()),
+        Value::Table(table) => table_to_resp(table),
+        _ => RESP::Null,
+    }
+}
+
+fn table_to_resp(table: &Table) -> RESP {
+    if let Ok(Value::String(message)) = table.raw_get::<_, Value>("err") {
+        return RESP::Error(message.to_string_lossy().into_owned());
+    }
+    if let Ok(Value::String(status)) = table.raw_get::<_, Value>("ok") {
+        return RESP::SimpleString(status.to_string_lossy().into_owned());
+    }
+    let mut elements = Vec::new();
+    for i in 1.. {
+        match table.raw_get::<_, Value>(i) {
+            Ok(Value::Nil) | Err(_) => break,
+            Ok(value) => elements.push(to_resp(&value)),
+        }
+    }
+    RESP::Array(elements)
+}
+
+/// A reply as a script sees it: nil becomes `false`, status and error
+/// replies become tables with an `ok` or `err` field.
+pub fn to_lua<'lua>(lua: &'lua Lua, reply: RESP) -> mlua::Result<Value<'lua>> {
+    Ok(match reply {
+        RESP::Null | RESP::NullArray => Value::Boolean(false),
+        RESP::Integer(n) => Value::Number(n as f64),
+        RESP::BulkString(s) => Value::String(lua.create_string(&s)?),
+        RESP::SimpleString(status) => Value::Table(single_field(lua, "ok", &status)?),
+        RESP::Error(message) => Value::Table(single_field(lua, "err", &message)?),
+        RESP::Array(elements) | RESP::Push(elements) => {
+            let table = lua.create_table_with_capacity(elements.len(), 0)?;
+            for (i, element) in elements.into_iter().enumerate() {
+                table.raw_set(i + 1, to_lua(lua, element)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+/// `{ok = ...}` or `{err = ...}`, as returned by `redis.status_reply` and
+/// `redis.error_reply`.
+pub fn single_field<'lua>(lua: &'lua Lua, field: &str, value: &str) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.raw_set(field, value)?;
+    Ok(table)
+}
+
+/// Turns a `redis.call` argument into a string. Only strings and numbers
+/// are allowed, numbers written the way Lua would.
+pub fn to_argument(value: &Value) -> Option<CommandArg> {
+    match value {
+        Value::String(s) => Some(s.as_bytes().to_vec().into()),
+        Value::Integer(n) => Some(n.to_string().into()),
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e17 => {
+            Some((*n as i64).to_string().into())
+        }
+        Value::Number(n) => Some(n.to_string().into()),
+        _ => None,
+    }
+}
+
+/// `KEYS` or `ARGV`: the request's arguments as Lua strings, with the
+/// bytes the client sent.
+pub fn to_lua_args<'lua>(lua: &'lua Lua, args: &[CommandArg]) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table_with_capacity(args.len(), 0)?;
+    for (i, arg) in args.iter().enumerate() {
+        table.raw_set(i + 1, lua.create_string(arg.as_bytes())?)?;
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let lua = Lua::new();
+        let reply = || {
+            RESP::Array(vec![
+                RESP::Integer(3),
+                RESP::BulkString("x".into()),
+                RESP::SimpleString("OK".to_string()),
+                RESP::Error("ERR nope".to_string()),
+                RESP::Array(vec![]),
+            ])
+        };
+        let value = to_lua(&lua, reply()).unwrap();
+        assert_eq!(to_resp(&value), reply());
+    }
+
+    #[test]
+    fn test_lua_values() {
+        let lua = Lua::new();
+        let eval = |code: &str| to_resp(&lua.load(code).eval::<Value>().unwrap());
+        assert_eq!(eval("return 3.99"), RESP::Integer(3));
+        assert_eq!(eval("return true"), RESP::Integer(1));
+        assert_eq!(eval("return false"), RESP::Null);
+        assert_eq!(eval("return nil"), RESP::Null);
+        // An array stops at its first nil
+        assert_eq!(
+            eval("return {1, 'two', nil, 4}"),
+            RESP::Array(vec![RESP::Integer(1), RESP::BulkString("two".into())])
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use mlua::{Function, HookTriggers, Lua, LuaOptions, RegistryKey, StdLib, Table, Value, Variadic};
+
+use super::convert::{single_field, to_argument, to_lua, to_lua_args, to_resp};
+use super::sha1hex;
+use crate::client::Client;
+use crate::command::{self, Command, CommandArg, command_command, flags};
+use crate::info::info;
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+use crate::storage::Executor;
+
+/// How many VM instructions run between checks for SCRIPT KILL.
+const KILL_CHECK_INSTRUCTIONS: u32 = 1_000;
+
+/// An error reply raised inside a script, passed to the caller untouched.
+#[derive(Debug)]
+pub struct Reply(pub String);
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Reply {}
+
+/// What `redis.call` reaches during one run of a script.
+pub struct Context<'a, 'e> {
+    pub server: &'a Server,
+    pub client: &'a Client,
+    pub execute: &'a mut Executor<'e>,
+    /// EVAL_RO, or a script declaring `no-writes`
+    pub read_only: bool,
+    /// Set once the script sends a write, after which it can't be killed
+    pub wrote: &'a AtomicBool,
+}
+
+impl Context<'_, '_> {
+    /// Runs one command for `redis.call` or `redis.pcall`.
+    fn call(&mut self, args: &[CommandArg]) -> ServerResult<RESP> {
+        let spec = command::resolve(args)?;
+        let not_allowed = || {
+            ServerError::InvalidArgument(
+                "This Redis command is not allowed from script".to_string(),
+            )
+        };
+        if spec.has_flag(flags::NOSCRIPT) {
+            return Err(not_allowed());
+        }
+        let write = spec.has_flag(flags::WRITE);
+        if write && self.read_only {
+            return Err(ServerError::InvalidArgument(
+                "Write commands are not allowed from read-only scripts.".to_string(),
+            ));
+        }
+        if write && self.server.rejects_writes(self.client) {
+            return Err(ServerError::ReadOnly);
+        }
+        self.server
+            .acl
+            .lock()
+            .unwrap()
+            .check(self.client, spec, args)?;
+        if write {
+            self.wrote.store(true, Ordering::Relaxed);
+        }
+        let start = Instant::now();
+        let result = match spec.command {
+            Command::Ping if args.len() == 2 => Ok(RESP::SimpleString(args[1].to_string())),
+            Command::Ping => Ok(RESP::SimpleString("PONG".to_string())),
+            Command::Echo => Ok(RESP::BulkString(args[1].as_bytes().to_vec())),
+            Command::Info => Ok(RESP::BulkString(info(self.server, &args[1..]).into())),
+            Command::Command => command_command(&args[1..]),
+            Command::Config | Command::Client | Command::Auth | Command::Acl | Command::Quit => {
+                Err(not_allowed())
+            }
+            _ => (self.execute)(spec, args).map_err(ServerError::from),
+        };
+        self.server
+            .stats
+            .record_command(spec, start.elapsed(), result.is_err());
+        result
+    }
+}
+
+/// A Lua interpreter with the `redis` API loaded.
+pub struct Engine {
+    lua: Lua,
+}
+
+impl Engine {
+    /// Sets up an interpreter whose scripts stop at their next check once
+    /// `killed` is set, telling the caller they were stopped by
+    /// `kill_command`.
+    pub fn new(killed: Arc<AtomicBool>, kill_command: &'static str) -> Self {
+        let lua = Lua::new_with(
+            StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+            LuaOptions::default(),
+        )
+        .expect("the Lua standard library loads");
+        install_api(&lua).expect("the redis API loads");
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(KILL_CHECK_INSTRUCTIONS),
+            move |_, _| {
+                if killed.load(Ordering::Relaxed) {
+                    Err(mlua::Error::external(Reply(format!(
+                        "ERR Script killed by user with {}...",
+                        kill_command
+                    ))))
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        Engine { lua }
+    }
+
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
+    /// Calls `function` with the keys and arguments as its two parameters,
+    /// and `redis.call` and `redis.pcall` reaching `context`.
+    pub fn call(
+        &self,
+        function: &RegistryKey,
+        keys: &[CommandArg],
+        argv: &[CommandArg],
+        context: &mut Context,
+    ) -> ServerResult<RESP> {
+        let function: Function = self.lua.registry_value(function).map_err(script_error)?;
+        let context = RefCell::new(context);
+        self.lua
+            .scope(|scope| {
+                let redis: Table = self.lua.globals().get("redis")?;
+                redis.set(
+                    "call",
+                    scope.create_function(|lua, args: Variadic<Value>| {
+                        match context.borrow_mut().call(&arguments(&args)?) {
+                            Ok(reply) => to_lua(lua, reply),
+                            Err(e) => Err(mlua::Error::external(Reply(e.to_string()))),
+                        }
+                    })?,
+                )?;
+                redis.set(
+                    "pcall",
+                    scope.create_function(|lua, args: Variadic<Value>| {
+                        let reply = arguments(&args)
+                            .map_err(|e| ServerError::Script(describe(&e)))
+                            .and_then(|args| context.borrow_mut().call(&args))
+                            .unwrap_or_else(|e| RESP::Error(e.to_string()));
+                        to_lua(lua, reply)
+                    })?,
+                )?;
+                let keys = to_lua_args(&self.lua, keys)?;
+                let argv = to_lua_args(&self.lua, argv)?;
+                let result = function.call::<_, Value>((keys, argv));
+                // The callbacks die with the scope, so don't leave them
+                // around for code that runs outside a call
+                redis.set("call", Value::Nil)?;
+                redis.set("pcall", Value::Nil)?;
+                Ok(to_resp(&result?))
+            })
+            .map_err(script_error)
+    }
+}
+
+/// The `redis` table, minus `call` and `pcall` which only exist while a
+/// script runs.
+fn install_api(lua: &Lua) -> mlua::Result<()> {
+    let redis = lua.create_table()?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, message: String| single_field(lua, "err", &message))?,
+    )?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, status: String| single_field(lua, "ok", &status))?,
+    )?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, body: mlua::String| Ok(sha1hex(body.as_bytes())))?,
+    )?;
+    // There is no log file to write to
+    redis.set("log", lua.create_function(|_, _: Variadic<Value>| Ok(()))?)?;
+    for (i, level) in ["LOG_DEBUG", "LOG_VERBOSE", "LOG_NOTICE", "LOG_WARNING"]
+        .iter()
+        .enumerate()
+    {
+        redis.set(*level, i)?;
+    }
+    let globals = lua.globals();
+    globals.set("redis", redis)?;
+    // Scripts have no business reading files
+    globals.set("loadfile", Value::Nil)?;
+    globals.set("dofile", Value::Nil)?;
+    Ok(())
+}
+
+/// The arguments of `redis.call`, which must be strings or numbers.
+fn arguments(args: &[Value]) -> mlua::Result<Vec<CommandArg>> {
+    if args.is_empty() {
+        return Err(mlua::Error::external(Reply(
+            "ERR Please specify at least one argument for this redis lib call".to_string(),
+        )));
+    }
+    args.iter()
+        .map(|arg| {
+            to_argument(arg).ok_or_else(|| {
+                mlua::Error::external(Reply(
+                    "ERR Lua redis lib command arguments must be strings or integers".to_string(),
+                ))
+            })
+        })
+        .collect()
+}
+
+/// The error reply a script raised, if that's what `e` comes from.
+fn reply(e: &mlua::Error) -> Option<&str> {
+    match e {
+        mlua::Error::CallbackError { cause, .. } => reply(cause),
+        mlua::Error::ExternalError(inner) => inner.downcast_ref::<Reply>().map(|r| r.0.as_str()),
+        _ => None,
+    }
+}
+
+/// A Lua error message without mlua's own decoration or the traceback,
+/// on one line so it fits in an error reply.
+pub fn describe(e: &mlua::Error) -> String {
+    match e {
+        mlua::Error::SyntaxError { message, .. } | mlua::Error::RuntimeError(message) => message
+            .split("\nstack traceback:")
+            .next()
+            .unwrap_or_default()
+            .replace('\n', " "),
+        mlua::Error::CallbackError { cause, .. } => describe(cause),
+        _ => e.to_string().replace('\n', " "),
+    }
+}
+
+/// The error reply for a script that failed with `e`.
+pub fn script_error(e: mlua::Error) -> ServerError {
+    match reply(&e) {
+        Some(message) => ServerError::Script(message.to_string()),
+        None => ServerError::Script(format!("ERR Error running script: {}", describe(&e))),
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use mlua::RegistryKey;
+
+use super::convert::to_lua_args;
+use super::engine::{Context, Engine, describe, script_error};
+use super::{SCRIPT_FLAGS, is_flush_mode, run, sha1hex};
+use crate::client::Client;
+use crate::command::{Command, CommandArg, CommandSpec};
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+
+struct Compiled {
+    function: RegistryKey,
+    /// Declared with the `no-writes` flag
+    no_writes: bool,
+}
+
+/// EVAL's interpreter and the scripts compiled into it, by SHA1 of their
+/// body.
+pub struct ScriptCache {
+    engine: Engine,
+    scripts: HashMap<String, Compiled>,
+}
+
+impl ScriptCache {
+    pub fn new(killed: Arc<AtomicBool>) -> Self {
+        ScriptCache {
+            engine: Engine::new(killed, "SCRIPT KILL"),
+            scripts: HashMap::new(),
+        }
+    }
+
+    pub fn contains(&self, sha: &str) -> bool {
+        self.scripts.contains_key(sha)
+    }
+
+    /// Compiles a script unless one with the same SHA1 already was.
+    pub fn compile(&mut self, sha: &str, script: &str) -> ServerResult<()> {
+        if self.contains(sha) {
+            return Ok(());
+        }
+        let (body, no_writes) = parse_shebang(script)?;
+        let lua = self.engine.lua();
+        let function = lua
+            .load(&body)
+            .set_name("@user_script")
+            .into_function()
+            .map_err(|e| {
+                ServerError::Script(format!(
+                    "ERR Error compiling script (new function): {}",
+                    describe(&e)
+                ))
+            })?;
+        let function = lua.create_registry_value(function).map_err(script_error)?;
+        self.scripts.insert(
+            sha.to_string(),
+            Compiled {
+                function,
+                no_writes,
+            },
+        );
+        Ok(())
+    }
+
+    /// Drops every compiled script, for SCRIPT FLUSH.
+    pub fn flush(&mut self) {
+        self.scripts.clear();
+        self.engine.lua().expire_registry_values();
+    }
+
+    /// Runs a compiled script with `KEYS` and `ARGV` set.
+    fn run(
+        &self,
+        sha: &str,
+        keys: &[CommandArg],
+        argv: &[CommandArg],
+        context: &mut Context,
+    ) -> ServerResult<RESP> {
+        let script = self.scripts.get(sha).ok_or(ServerError::NoScript)?;
+        context.read_only |= script.no_writes;
+        let lua = self.engine.lua();
+        let globals = lua.globals();
+        globals
+            .set("KEYS", to_lua_args(lua, keys).map_err(script_error)?)
+            .map_err(script_error)?;
+        globals
+            .set("ARGV", to_lua_args(lua, argv).map_err(script_error)?)
+            .map_err(script_error)?;
+        self.engine.call(&script.function, keys, argv, context)
+    }
+}
+
+/// Reads a script's optional shebang line. Returns the body with that line
+/// blanked, so line numbers in errors stay right, and whether it declared
+/// `no-writes`.
+fn parse_shebang(body: &str) -> ServerResult<(String, bool)> {
+    let Some(shebang) = body.strip_prefix("#!") else {
+        return Ok((body.to_string(), false));
+    };
+    let line = shebang.split('\n').next().unwrap_or_default();
+    let mut parts = line.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if engine != "lua" {
+        return Err(ServerError::InvalidArgument(format!(
+            "Could not find scripting engine '{}'",
+            engine
+        )));
+    }
+    let mut no_writes = false;
+    for option in parts {
+        let Some(flags) = option.strip_prefix("flags=") else {
+            return Err(ServerError::InvalidArgument(format!(
+                "Unknown lua shebang option: {}",
+                option
+            )));
+        };
+        for flag in flags.split(',').filter(|flag| !flag.is_empty()) {
+            if !SCRIPT_FLAGS.contains(&flag) {
+                return Err(ServerError::InvalidArgument(format!(
+                    "Unexpected flag in script shebang: {}",
+                    flag
+                )));
+            }
+            no_writes |= flag == "no-writes";
+        }
+    }
+    // From the shebang's newline on, so it's an empty first line
+    Ok((shebang[line.len()..].to_string(), no_writes))
+}
+
+/// Splits `numkeys key [key ...] arg [arg ...]` into keys and arguments.
+pub(super) fn keys_and_args(
+    args: &[CommandArg],
+) -> ServerResult<(Vec<CommandArg>, Vec<CommandArg>)> {
+    let numkeys: i64 = args[0].parse().map_err(|_| {
+        ServerError::InvalidArgument("value is not an integer or out of range".to_string())
+    })?;
+    if numkeys < 0 {
+        return Err(ServerError::InvalidArgument(
+            "Number of keys can't be negative".to_string(),
+        ));
+    }
+    let rest = &args[1..];
+    if numkeys as usize > rest.len() {
+        return Err(ServerError::InvalidArgument(
+            "Number of keys can't be greater than number of args".to_string(),
+        ));
+    }
+    let (keys, argv) = rest.split_at(numkeys as usize);
+    Ok((keys.to_vec(), argv.to_vec()))
+}
+
+/// EVAL, EVALSHA, EVAL_RO and EVALSHA_RO
+pub async fn command_eval(
+    server: &Arc<Server>,
+    client: &Arc<Client>,
+    spec: &CommandSpec,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let (keys, argv) = keys_and_args(&args[2..])?;
+    let (sha, body) = match spec.command {
+        Command::Eval | Command::EvalRo => (sha1hex(args[1].as_bytes()), Some(args[1].clone())),
+        _ => {
+            let sha = args[1].to_lowercase();
+            if !server.scripts.cache.lock().unwrap().contains(&sha) {
+                return Err(ServerError::NoScript);
+            }
+            (sha, None)
+        }
+    };
+    let read_only = matches!(spec.command, Command::EvalRo | Command::EvalShaRo);
+    let shared = server.clone();
+    run(server, client, None, read_only, move |context| {
+        let mut cache = shared.scripts.cache.lock().unwrap();
+        if let Some(body) = body {
+            cache.compile(&sha, &body)?;
+        }
+        cache.run(&sha, &keys, &argv, context)
+    })
+    .await
+}
+
+/// SCRIPT
+pub fn command_script(
+    server: &Server,
+    spec: &CommandSpec,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let scripts = &server.scripts;
+    match spec.name {
+        "script|load" => {
+            let sha = sha1hex(args[1].as_bytes());
+            scripts.cache.lock().unwrap().compile(&sha, &args[1])?;
+            Ok(RESP::BulkString(sha.into()))
+        }
+        "script|exists" => {
+            let cache = scripts.cache.lock().unwrap();
+            Ok(RESP::Array(
+                args[1..]
+                    .iter()
+                    .map(|sha| RESP::Integer(cache.contains(&sha.to_lowercase()) as i64))
+                    .collect(),
+            ))
+        }
+        "script|flush" => {
+            // Flushing is quick enough that ASYNC is done synchronously too
+            if !is_flush_mode(&args[1..]) {
+                return Err(ServerError::InvalidArgument(
+                    "SCRIPT FLUSH only support SYNC|ASYNC option".to_string(),
+                ));
+            }
+            scripts.cache.lock().unwrap().flush();
+            Ok(RESP::SimpleString("OK".to_string()))
+        }
+        "script|kill" => {
+            scripts.kill(false)?;
+            Ok(RESP::SimpleString("OK".to_string()))
+        }
+        _ => Ok(RESP::Array(
+            [
+                "SCRIPT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "EXISTS <sha1> [<sha1> ...]",
+                "    Return information about the existence of the scripts in the script cache.",
+                "FLUSH [ASYNC|SYNC]",
+                "    Flush the Lua scripts cache.",
+                "KILL",
+                "    Kill the currently executing Lua script.",
+                "LOAD <script>",
+                "    Load a script into the scripts cache without executing it.",
+                "HELP",
+                "    Print this help.",
+            ]
+            .iter()
+            .map(|line| RESP::SimpleString(line.to_string()))
+            .collect(),
+        )),
+    }
+}
@@ -0,0 +1,885 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+use mlua::{Lua, RegistryKey, Table, Value};
+
+use super::engine::{Context, Engine, Reply, describe, script_error};
+use super::eval::keys_and_args;
+use super::{SCRIPT_FLAGS, is_flush_mode, run};
+use crate::client::Client;
+use crate::command::{Command, CommandArg, CommandSpec};
+use crate::glob::glob_match;
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+use crate::storage::result::StorageError;
+
+/// First line of FUNCTION DUMP payloads and of the functions file.
+const DUMP_HEADER: &str = "KVFUNCTIONS 1";
+
+/// A function as registered by its library.
+struct Registered {
+    library: String,
+    callback: Arc<RegistryKey>,
+    flags: Vec<String>,
+    description: Option<String>,
+}
+
+impl Registered {
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+}
+
+struct Library {
+    code: String,
+    functions: Vec<String>,
+}
+
+/// A function taken out of the libraries, so calling it doesn't keep
+/// them locked while it runs.
+struct Callable {
+    engine: Arc<Mutex<Engine>>,
+    callback: Arc<RegistryKey>,
+}
+
+impl Callable {
+    fn call(
+        &self,
+        keys: &[CommandArg],
+        argv: &[CommandArg],
+        context: &mut Context,
+    ) -> ServerResult<RESP> {
+        let engine = self.engine.lock().unwrap();
+        engine.call(&self.callback, keys, argv, context)
+    }
+}
+
+/// What `redis.register_function` was given, before it is accepted.
+struct Registration {
+    name: String,
+    callback: RegistryKey,
+    flags: Vec<String>,
+    description: Option<String>,
+}
+
+/// How FUNCTION RESTORE treats libraries that already exist.
+#[derive(Clone, Copy, PartialEq)]
+enum RestorePolicy {
+    Flush,
+    Append,
+    Replace,
+}
+
+/// Function libraries, loaded into an interpreter of their own.
+pub struct Libraries {
+    engine: Arc<Mutex<Engine>>,
+    killed: Arc<AtomicBool>,
+    libraries: BTreeMap<String, Library>,
+    functions: BTreeMap<String, Registered>,
+}
+
+fn invalid(message: &str) -> ServerError {
+    ServerError::InvalidArgument(message.to_string())
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Reads the `#!lua name=<library>` line every library starts with.
+/// Returns the library name and the code with that line blanked.
+fn parse_metadata(code: &str) -> ServerResult<(String, String)> {
+    let Some(shebang) = code.strip_prefix("#!") else {
+        return Err(invalid("Missing library metadata"));
+    };
+    let line = shebang.split('\n').next().unwrap_or_default();
+    let mut parts = line.split_whitespace();
+    let engine = parts.next().unwrap_or_default();
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(ServerError::InvalidArgument(format!(
+            "Engine '{}' not found",
+            engine
+        )));
+    }
+    let mut name = None;
+    for part in parts {
+        match part.strip_prefix("name=") {
+            Some(value) => name = Some(value.to_string()),
+            None => {
+                return Err(ServerError::InvalidArgument(format!(
+                    "Invalid metadata value given: {}",
+                    part
+                )));
+            }
+        }
+    }
+    let name = name.ok_or_else(|| invalid("Library name was not given"))?;
+    if !is_valid_name(&name) {
+        return Err(invalid(
+            "Library names can only contain letters, numbers, or underscores(_) and must be at least one character long",
+        ));
+    }
+    // From the metadata's newline on, so it's an empty first line
+    Ok((name, shebang[line.len()..].to_string()))
+}
+
+/// The arguments of `redis.register_function`: either a name and a
+/// callback, or a table of named arguments.
+fn registration(lua: &Lua, args: mlua::Variadic<Value>) -> mlua::Result<Registration> {
+    let fail = |message: &str| mlua::Error::external(Reply(format!("ERR {}", message)));
+    let (name, callback, flags, description) = match &args[..] {
+        [Value::String(name), Value::Function(callback)] => (
+            name.to_str()?.to_string(),
+            callback.clone(),
+            Vec::new(),
+            None,
+        ),
+        [Value::Table(named)] => {
+            let (mut name, mut callback, mut flags, mut description) =
+                (None, None, Vec::new(), None);
+            for pair in named.clone().pairs::<String, Value>() {
+                let (key, value) = pair?;
+                match (key.as_str(), value) {
+                    ("function_name", Value::String(s)) => name = Some(s.to_str()?.to_string()),
+                    ("callback", Value::Function(f)) => callback = Some(f),
+                    ("description", Value::String(s)) => {
+                        description = Some(s.to_str()?.to_string())
+                    }
+                    ("flags", Value::Table(list)) => {
+                        for flag in list.sequence_values::<String>() {
+                            flags.push(flag?);
+                        }
+                    }
+                    _ => return Err(fail("unknown argument given to redis.register_function")),
+                }
+            }
+            let name = name
+                .ok_or_else(|| fail("redis.register_function must get a function name argument"))?;
+            let callback = callback
+                .ok_or_else(|| fail("redis.register_function must get a callback argument"))?;
+            (name, callback, flags, description)
+        }
+        _ => return Err(fail("wrong number of arguments to redis.register_function")),
+    };
+    if !is_valid_name(&name) {
+        return Err(fail(
+            "Function names can only contain letters, numbers, or underscores(_) and must be at least one character long",
+        ));
+    }
+    if let Some(flag) = flags
+        .iter()
+        .find(|flag| !SCRIPT_FLAGS.contains(&flag.as_str()))
+    {
+        return Err(fail(&format!("unknown flag given: {}", flag)));
+    }
+    Ok(Registration {
+        name,
+        callback: lua.create_registry_value(callback)?,
+        flags,
+        description,
+    })
+}
+
+impl Libraries {
+    pub fn new(killed: Arc<AtomicBool>) -> Self {
+        Libraries {
+            engine: Arc::new(Mutex::new(Engine::new(killed.clone(), "FUNCTION KILL"))),
+            killed,
+            libraries: BTreeMap::new(),
+            functions: BTreeMap::new(),
+        }
+    }
+
+    /// FUNCTION LOAD: runs a library's code so it can register its
+    /// functions, and returns the library's name.
+    fn load(&mut self, code: &str, replace: bool) -> ServerResult<String> {
+        let (name, body) = parse_metadata(code)?;
+        if self.libraries.contains_key(&name) && !replace {
+            return Err(ServerError::InvalidArgument(format!(
+                "Library '{}' already exists",
+                name
+            )));
+        }
+        let registered = self.run_library(&body)?;
+        if registered.is_empty() {
+            return Err(invalid("No functions registered"));
+        }
+        for registration in &registered {
+            if let Some(existing) = self.functions.get(&registration.name)
+                && existing.library != name
+            {
+                return Err(ServerError::InvalidArgument(format!(
+                    "Function {} already exists",
+                    registration.name
+                )));
+            }
+        }
+        self.remove(&name);
+        let mut functions = Vec::new();
+        for registration in registered {
+            functions.push(registration.name.clone());
+            self.functions.insert(
+                registration.name,
+                Registered {
+                    library: name.clone(),
+                    callback: Arc::new(registration.callback),
+                    flags: registration.flags,
+                    description: registration.description,
+                },
+            );
+        }
+        functions.sort();
+        self.libraries.insert(
+            name.clone(),
+            Library {
+                code: code.to_string(),
+                functions,
+            },
+        );
+        Ok(name)
+    }
+
+    /// Runs a library's code with `redis.register_function` available, and
+    /// returns what it registered.
+    fn run_library(&self, body: &str) -> ServerResult<Vec<Registration>> {
+        let engine = self.engine.lock().unwrap();
+        let lua = engine.lua();
+        // Each library keeps its globals to itself
+        let environment = lua.create_table().map_err(script_error)?;
+        let lookup = lua.create_table().map_err(script_error)?;
+        lookup.set("__index", lua.globals()).map_err(script_error)?;
+        environment.set_metatable(Some(lookup));
+        let chunk = lua
+            .load(body)
+            .set_name("@user_function")
+            .set_environment(environment)
+            .into_function()
+            .map_err(|e| {
+                ServerError::Script(format!("ERR Error compiling function: {}", describe(&e)))
+            })?;
+
+        let registered = RefCell::new(Vec::new());
+        let redis: Table = lua.globals().get("redis").map_err(script_error)?;
+        lua.scope(|scope| {
+            let register = scope.create_function(|lua, args: mlua::Variadic<Value>| {
+                let registration = registration(lua, args)?;
+                let mut registered = registered.borrow_mut();
+                if registered
+                    .iter()
+                    .any(|r: &Registration| r.name == registration.name)
+                {
+                    return Err(mlua::Error::external(Reply(
+                        "ERR Function already exists in the library".to_string(),
+                    )));
+                }
+                registered.push(registration);
+                Ok(())
+            })?;
+            redis.set("register_function", register)?;
+            let result = chunk.call::<_, ()>(());
+            redis.set("register_function", Value::Nil)?;
+            result
+        })
+        .map_err(|e| match script_error(e) {
+            ServerError::Script(message) if message.starts_with("ERR Error running script") => {
+                ServerError::Script(message.replacen(
+                    "Error running script",
+                    "Error registering functions",
+                    1,
+                ))
+            }
+            e => e,
+        })?;
+        Ok(registered.into_inner())
+    }
+
+    /// Drops a library and its functions, if it exists.
+    fn remove(&mut self, name: &str) -> bool {
+        let Some(library) = self.libraries.remove(name) else {
+            return false;
+        };
+        for function in library.functions {
+            self.functions.remove(&function);
+        }
+        self.engine.lock().unwrap().lua().expire_registry_values();
+        true
+    }
+
+    fn flush(&mut self) {
+        *self = Libraries::new(self.killed.clone());
+    }
+
+    /// Every library's code, in a form FUNCTION RESTORE reads back.
+    fn dump(&self) -> String {
+        let mut payload = format!("{}\n", DUMP_HEADER);
+        for library in self.libraries.values() {
+            payload.push_str(&format!("{}\n{}\n", library.code.len(), library.code));
+        }
+        payload
+    }
+
+    /// FUNCTION RESTORE. Libraries are loaded into a fresh interpreter
+    /// which replaces this one only if all of them load, so a bad payload
+    /// changes nothing.
+    fn restore(&mut self, payload: &str, policy: RestorePolicy) -> ServerResult<()> {
+        let codes =
+            parse_dump(payload).ok_or_else(|| invalid("payload version or checksum are wrong"))?;
+        let mut restored = Libraries::new(self.killed.clone());
+        if policy != RestorePolicy::Flush {
+            for library in self.libraries.values() {
+                restored.load(&library.code, false)?;
+            }
+        }
+        for code in codes {
+            restored.load(code, policy == RestorePolicy::Replace)?;
+        }
+        *self = restored;
+        Ok(())
+    }
+
+    /// Loads the libraries saved in `path`, if it exists.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let payload = match fs::read_to_string(path) {
+            Ok(payload) => payload,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        self.restore(&payload, RestorePolicy::Flush)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        // Write then rename so a crash never leaves a truncated file behind
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.dump())
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn list(&self, pattern: Option<&str>, with_code: bool) -> RESP {
+        let field = |name: &str| RESP::SimpleString(name.to_string());
+        let libraries = self
+            .libraries
+            .iter()
+            .filter(|(name, _)| pattern.is_none_or(|pattern| glob_match(pattern, name)))
+            .map(|(name, library)| {
+                let functions = library
+                    .functions
+                    .iter()
+                    .map(|function| {
+                        let registered = &self.functions[function];
+                        RESP::Array(vec![
+                            field("name"),
+                            RESP::BulkString(function.clone().into()),
+                            field("description"),
+                            registered
+                                .description
+                                .clone()
+                                .map_or(RESP::Null, |s| RESP::BulkString(s.into())),
+                            field("flags"),
+                            RESP::Array(
+                                registered
+                                    .flags
+                                    .iter()
+                                    .map(|flag| RESP::SimpleString(flag.clone()))
+                                    .collect(),
+                            ),
+                        ])
+                    })
+                    .collect();
+                let mut entry = vec![
+                    field("library_name"),
+                    RESP::BulkString(name.clone().into()),
+                    field("engine"),
+                    RESP::BulkString("LUA".into()),
+                    field("functions"),
+                    RESP::Array(functions),
+                ];
+                if with_code {
+                    entry.push(field("library_code"));
+                    entry.push(RESP::BulkString(library.code.clone().into()));
+                }
+                RESP::Array(entry)
+            })
+            .collect();
+        RESP::Array(libraries)
+    }
+
+    fn callable(&self, function: &Registered) -> Callable {
+        Callable {
+            engine: self.engine.clone(),
+            callback: function.callback.clone(),
+        }
+    }
+}
+
+/// The library codes in a FUNCTION DUMP payload: a header line, then each
+/// library as its length in bytes on a line of its own, the code, and a
+/// newline.
+fn parse_dump(payload: &str) -> Option<Vec<&str>> {
+    let mut rest = payload.strip_prefix(DUMP_HEADER)?.strip_prefix('\n')?;
+    let mut codes = Vec::new();
+    while !rest.is_empty() {
+        let (length, after) = rest.split_once('\n')?;
+        let length: usize = length.parse().ok()?;
+        codes.push(after.get(..length)?);
+        rest = after.get(length..)?.strip_prefix('\n')?;
+    }
+    Some(codes)
+}
+
+/// Writes the libraries to the `functions-file`, when there is one, so
+/// they survive a restart.
+fn persist(server: &Server, libraries: &Libraries) -> ServerResult<()> {
+    let Some(path) = server.functions_path() else {
+        return Ok(());
+    };
+    libraries.save(&path).map_err(|e| {
+        ServerError::InvalidArgument(format!("Functions changed but could not be saved: {}", e))
+    })
+}
+
+/// FCALL and FCALL_RO
+pub async fn command_fcall(
+    server: &Arc<Server>,
+    client: &Arc<Client>,
+    spec: &CommandSpec,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let name = args[1].to_string();
+    let (keys, argv) = keys_and_args(&args[2..])?;
+    let (callable, read_only) = {
+        let libraries = server.scripts.libraries.lock().unwrap();
+        let function = libraries
+            .functions
+            .get(&name)
+            .ok_or_else(|| invalid("Function not found"))?;
+        let no_writes = function.has_flag("no-writes");
+        if spec.command == Command::FCallRo && !no_writes {
+            return Err(invalid(
+                "Can not execute a script with write flag using *_ro command.",
+            ));
+        }
+        // Functions that may write are refused up front when memory is
+        // full, unless they say they cope
+        let maxmemory = server.storage.maxmemory();
+        if !no_writes
+            && !function.has_flag("allow-oom")
+            && maxmemory > 0
+            && server.storage.used_memory() > maxmemory
+        {
+            return Err(StorageError::OutOfMemory.into());
+        }
+        (libraries.callable(function), no_writes)
+    };
+    // The libraries stay unlocked while the function runs, so FUNCTION
+    // STATS can report it from the running state
+    let running = Some((name, args.iter().map(|arg| arg.to_string()).collect()));
+    run(server, client, running, read_only, move |context| {
+        callable.call(&keys, &argv, context)
+    })
+    .await
+}
+
+/// FUNCTION
+pub fn command_function(
+    server: &Server,
+    spec: &CommandSpec,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let ok = || Ok(RESP::SimpleString("OK".to_string()));
+    let scripts = &server.scripts;
+    match spec.name {
+        "function|load" => {
+            let (replace, code) = match &args[1..] {
+                [code] => (false, code),
+                [flag, code] if flag.eq_ignore_ascii_case("replace") => (true, code),
+                [flag, _] => {
+                    return Err(ServerError::InvalidArgument(format!(
+                        "Unknown option given: {}",
+                        flag
+                    )));
+                }
+                _ => return Err(ServerError::WrongArity("function|load".to_string())),
+            };
+            let mut libraries = scripts.libraries.lock().unwrap();
+            let name = libraries.load(code, replace)?;
+            persist(server, &libraries)?;
+            Ok(RESP::BulkString(name.into()))
+        }
+        "function|delete" => {
+            let mut libraries = scripts.libraries.lock().unwrap();
+            if !libraries.remove(&args[1]) {
+                return Err(invalid("Library not found"));
+            }
+            persist(server, &libraries)?;
+            ok()
+        }
+        "function|list" => {
+            let (mut pattern, mut with_code) = (None, false);
+            let mut options = args[1..].iter();
+            while let Some(option) = options.next() {
+                if option.eq_ignore_ascii_case("withcode") && !with_code {
+                    with_code = true;
+                } else if option.eq_ignore_ascii_case("libraryname") && pattern.is_none() {
+                    let value = options
+                        .next()
+                        .ok_or_else(|| invalid("library name argument was not given"))?;
+                    pattern = Some(value.as_str());
+                } else {
+                    return Err(ServerError::InvalidArgument(format!(
+                        "Unknown argument {}",
+                        option
+                    )));
+                }
+            }
+            Ok(scripts.libraries.lock().unwrap().list(pattern, with_code))
+        }
+        "function|dump" => Ok(RESP::BulkString(
+            scripts.libraries.lock().unwrap().dump().into(),
+        )),
+        "function|restore" => {
+            let policy = match args.get(2).map(|policy| policy.to_lowercase()).as_deref() {
+                None | Some("append") => RestorePolicy::Append,
+                Some("flush") => RestorePolicy::Flush,
+                Some("replace") => RestorePolicy::Replace,
+                Some(_) => {
+                    return Err(invalid(
+                        "Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE.",
+                    ));
+                }
+            };
+            if args.len() > 3 {
+                return Err(ServerError::WrongArity("function|restore".to_string()));
+            }
+            let mut libraries = scripts.libraries.lock().unwrap();
+            libraries.restore(&args[1], policy)?;
+            persist(server, &libraries)?;
+            ok()
+        }
+        "function|flush" => {
+            // Flushing is quick enough that ASYNC is done synchronously too
+            if !is_flush_mode(&args[1..]) {
+                return Err(invalid("FUNCTION FLUSH only supports SYNC|ASYNC option"));
+            }
+            let mut libraries = scripts.libraries.lock().unwrap();
+            libraries.flush();
+            persist(server, &libraries)?;
+            ok()
+        }
+        "function|stats" => {
+            let field = |name: &str| RESP::SimpleString(name.to_string());
+            let running = match &*scripts.running.lock().unwrap() {
+                Some(running) => match &running.function {
+                    Some((name, command)) => RESP::Array(vec![
+                        field("name"),
+                        RESP::BulkString(name.clone().into()),
+                        field("command"),
+                        RESP::Array(
+                            command
+                                .iter()
+                                .cloned()
+                                .map(|s| RESP::BulkString(s.into()))
+                                .collect(),
+                        ),
+                        field("duration_ms"),
+                        RESP::Integer(running.started.elapsed().as_millis() as i64),
+                    ]),
+                    None => RESP::Null,
+                },
+                None => RESP::Null,
+            };
+            let libraries = scripts.libraries.lock().unwrap();
+            Ok(RESP::Array(vec![
+                field("running_script"),
+                running,
+                field("engines"),
+                RESP::Array(vec![
+                    RESP::BulkString("LUA".into()),
+                    RESP::Array(vec![
+                        field("libraries_count"),
+                        RESP::Integer(libraries.libraries.len() as i64),
+                        field("functions_count"),
+                        RESP::Integer(libraries.functions.len() as i64),
+                    ]),
+                ]),
+            ]))
+        }
+        "function|kill" => {
+            scripts.kill(true)?;
+            ok()
+        }
+        _ => Ok(RESP::Array(
+            [
+                "FUNCTION <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "LOAD [REPLACE] <FUNCTION CODE>",
+                "    Create a new library with the given library name and code.",
+                "DELETE <LIBRARY NAME>",
+                "    Delete the given library.",
+                "LIST [LIBRARYNAME PATTERN] [WITHCODE]",
+                "    Return general information on all the libraries.",
+                "DUMP",
+                "    Return a serialized payload representing the current libraries.",
+                "RESTORE <PAYLOAD> [FLUSH|APPEND|REPLACE]",
+                "    Restore the libraries represented by the given payload.",
+                "FLUSH [ASYNC|SYNC]",
+                "    Delete all the libraries.",
+                "STATS",
+                "    Return information about the current function running.",
+                "KILL",
+                "    Kill the current running function.",
+                "HELP",
+                "    Print this help.",
+            ]
+            .iter()
+            .map(|line| RESP::SimpleString(line.to_string()))
+            .collect(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{connect, send};
+
+    const LIBRARY: &str = "#!lua name=counters
+local prefix = 'counter:'
+redis.register_function('bump', function(keys, args)
+    return redis.call('incr', prefix .. keys[1])
+end)
+redis.register_function{
+    function_name = 'peek',
+    callback = function(keys) return redis.call('get', prefix .. keys[1]) end,
+    flags = {'no-writes'},
+    description = 'Reads a counter',
+}";
+
+    fn ok() -> RESP {
+        RESP::SimpleString("OK".to_string())
+    }
+
+    fn error(message: &str) -> RESP {
+        RESP::Error(message.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_load_and_call() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        assert_eq!(
+            send(&server, &client, &["function", "load", LIBRARY]).await,
+            RESP::BulkString("counters".into())
+        );
+        assert_eq!(
+            send(&server, &client, &["fcall", "bump", "1", "a"]).await,
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            send(&server, &client, &["fcall_ro", "peek", "1", "a"]).await,
+            RESP::BulkString("1".into())
+        );
+        assert_eq!(
+            send(&server, &client, &["fcall", "nope", "0"]).await,
+            error("ERR Function not found")
+        );
+        assert_eq!(
+            send(&server, &client, &["function", "load", LIBRARY]).await,
+            error("ERR Library 'counters' already exists")
+        );
+        assert_eq!(
+            send(&server, &client, &["function", "load", "REPLACE", LIBRARY]).await,
+            RESP::BulkString("counters".into())
+        );
+        // Library globals stay out of other libraries
+        let other =
+            "#!lua name=other\nredis.register_function('leak', function() return prefix end)";
+        send(&server, &client, &["function", "load", other]).await;
+        assert_eq!(
+            send(&server, &client, &["fcall", "leak", "0"]).await,
+            RESP::Null
+        );
+        assert_eq!(
+            send(&server, &client, &["function", "delete", "counters"]).await,
+            ok()
+        );
+        assert_eq!(
+            send(&server, &client, &["fcall", "bump", "1", "a"]).await,
+            error("ERR Function not found")
+        );
+        // The metadata is still line 1
+        let failing =
+            "#!lua name=failing\n\nredis.register_function('fail', function() error('boom') end)";
+        send(&server, &client, &["function", "load", failing]).await;
+        assert_eq!(
+            send(&server, &client, &["fcall", "fail", "0"]).await,
+            error("ERR Error running script: user_function:3: boom")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flags_are_enforced() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        let library = "#!lua name=lib
+redis.register_function{function_name='sneaky', callback=function() return redis.call('set', 'k', 'v') end, flags={'no-writes'}}
+redis.register_function('writer', function() return redis.call('set', 'k', 'v') end)";
+        send(&server, &client, &["function", "load", library]).await;
+        assert_eq!(
+            send(&server, &client, &["fcall", "sneaky", "0"]).await,
+            error("ERR Write commands are not allowed from read-only scripts.")
+        );
+        assert_eq!(
+            send(&server, &client, &["fcall_ro", "writer", "0"]).await,
+            error("ERR Can not execute a script with write flag using *_ro command.")
+        );
+        assert_eq!(
+            send(&server, &client, &["fcall", "writer", "0"]).await,
+            ok()
+        );
+        let bad_flag = "#!lua name=bad\nredis.register_function{function_name='f', callback=function() end, flags={'fast'}}";
+        assert_eq!(
+            send(&server, &client, &["function", "load", bad_flag]).await,
+            error("ERR unknown flag given: fast")
+        );
+        assert_eq!(
+            send(&server, &client, &["function", "load", "return 1"]).await,
+            error("ERR Missing library metadata")
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["function", "load", "#!lua name=empty\nlocal x = 1"]
+            )
+            .await,
+            error("ERR No functions registered")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dump_and_restore() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        send(&server, &client, &["function", "load", LIBRARY]).await;
+        let RESP::BulkString(payload) = send(&server, &client, &["function", "dump"]).await else {
+            panic!("FUNCTION DUMP returns a bulk string");
+        };
+        let payload = String::from_utf8(payload).unwrap();
+        assert_eq!(
+            send(&server, &client, &["function", "restore", &payload]).await,
+            error("ERR Library 'counters' already exists")
+        );
+        assert_eq!(send(&server, &client, &["function", "flush"]).await, ok());
+        assert_eq!(
+            send(&server, &client, &["function", "restore", &payload]).await,
+            ok()
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["function", "restore", &payload, "replace"]
+            )
+            .await,
+            ok()
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["function", "restore", "garbage", "flush"]
+            )
+            .await,
+            error("ERR payload version or checksum are wrong")
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["function", "list", "libraryname", "count*"]
+            )
+            .await,
+            RESP::Array(vec![RESP::Array(vec![
+                RESP::SimpleString("library_name".to_string()),
+                RESP::BulkString("counters".into()),
+                RESP::SimpleString("engine".to_string()),
+                RESP::BulkString("LUA".into()),
+                RESP::SimpleString("functions".to_string()),
+                RESP::Array(vec![
+                    RESP::Array(vec![
+                        RESP::SimpleString("name".to_string()),
+                        RESP::BulkString("bump".into()),
+                        RESP::SimpleString("description".to_string()),
+                        RESP::Null,
+                        RESP::SimpleString("flags".to_string()),
+                        RESP::Array(vec![]),
+                    ]),
+                    RESP::Array(vec![
+                        RESP::SimpleString("name".to_string()),
+                        RESP::BulkString("peek".into()),
+                        RESP::SimpleString("description".to_string()),
+                        RESP::BulkString("Reads a counter".into()),
+                        RESP::SimpleString("flags".to_string()),
+                        RESP::Array(vec![RESP::SimpleString("no-writes".to_string())]),
+                    ]),
+                ]),
+            ])])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_stats_while_function_runs() {
+        let server = Arc::new(Server::default());
+        server.set_config_value("busy-reply-threshold".to_string(), "10".to_string());
+        let client = connect(&server);
+        let other = connect(&server);
+        let library =
+            "#!lua name=spin\nredis.register_function('spin', function() while true do end end)";
+        send(&server, &client, &["function", "load", library]).await;
+
+        let (runner, looping) = (server.clone(), client.clone());
+        let function =
+            tokio::spawn(async move { send(&runner, &looping, &["fcall", "spin", "0"]).await });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let RESP::Array(stats) = send(&server, &other, &["function", "stats"]).await else {
+            panic!("FUNCTION STATS returns an array");
+        };
+        let RESP::Array(running) = &stats[1] else {
+            panic!("FUNCTION STATS reports the running function");
+        };
+        assert_eq!(running[1], RESP::BulkString("spin".into()));
+        assert_eq!(send(&server, &other, &["function", "kill"]).await, ok());
+        assert_eq!(
+            function.await.unwrap(),
+            error("ERR Script killed by user with FUNCTION KILL...")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_libraries_survive_restart() {
+        let dir = std::env::temp_dir().to_str().unwrap().to_string();
+        let file = format!("kv-functions-{}", std::process::id());
+        let server = Arc::new(Server::default());
+        server.set_config_value("dir".to_string(), dir);
+        server.set_config_value("functions-file".to_string(), file.clone());
+        let path = server.functions_path().unwrap();
+        assert_eq!(path, std::env::temp_dir().join(file));
+        let client = connect(&server);
+        send(&server, &client, &["function", "load", LIBRARY]).await;
+
+        let restarted = Arc::new(Server::default());
+        restarted.scripts.load_libraries(&path).unwrap();
+        let client = connect(&restarted);
+        assert_eq!(
+            send(&restarted, &client, &["fcall", "bump", "1", "a"]).await,
+            RESP::Integer(1)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
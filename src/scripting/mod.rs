@@ -0,0 +1,419 @@
+mod convert;
+mod engine;
+mod eval;
+mod functions;
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sha1::{Digest, Sha1};
+use tokio::sync::Notify;
+
+use self::engine::Context;
+use self::eval::ScriptCache;
+pub use self::eval::{command_eval, command_script};
+use self::functions::Libraries;
+pub use self::functions::{command_fcall, command_function};
+use crate::client::Client;
+use crate::command::CommandArg;
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+
+/// How long a script may run before other clients get BUSY, unless the
+/// `busy-reply-threshold` config says otherwise.
+pub const DEFAULT_BUSY_REPLY_THRESHOLD: Duration = Duration::from_millis(5000);
+
+/// Where function libraries are saved, under `dir`, unless the
+/// `functions-file` config says otherwise.
+pub const DEFAULT_FUNCTIONS_FILE: &str = "functions.dump";
+
+/// Flags scripts may declare in their shebang, and functions when they
+/// register.
+const SCRIPT_FLAGS: [&str; 5] = [
+    "no-writes",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "allow-cross-slot-keys",
+];
+
+/// The optional `ASYNC` or `SYNC` argument of SCRIPT FLUSH and FUNCTION
+/// FLUSH.
+fn is_flush_mode(args: &[CommandArg]) -> bool {
+    match args {
+        [] => true,
+        [mode] => mode.eq_ignore_ascii_case("async") || mode.eq_ignore_ascii_case("sync"),
+        _ => false,
+    }
+}
+
+/// Lowercase hex SHA1, the name scripts are cached under.
+pub fn sha1hex(body: &[u8]) -> String {
+    Sha1::digest(body)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The script running right now.
+struct Running {
+    started: Instant,
+    /// For FCALL, the function and the request that called it
+    function: Option<(String, Vec<String>)>,
+}
+
+impl Running {
+    /// The command that stops this script.
+    fn kill_command(&self) -> &'static str {
+        match self.function {
+            Some(_) => "FUNCTION KILL",
+            None => "SCRIPT KILL",
+        }
+    }
+}
+
+/// EVAL's script cache, the function libraries, and the script or
+/// function running right now.
+///
+/// Only one script runs at a time, with the whole keyspace to itself.
+/// Everyone else waits for it, and once it has run longer than the busy
+/// threshold they get a BUSY error instead, except for commands allowed
+/// while busy such as SCRIPT KILL.
+pub struct Scripts {
+    cache: Mutex<ScriptCache>,
+    libraries: Mutex<Libraries>,
+    running: Mutex<Option<Running>>,
+    finished: Notify,
+    killed: Arc<AtomicBool>,
+    wrote: AtomicBool,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        let killed = Arc::new(AtomicBool::new(false));
+        Scripts {
+            cache: Mutex::new(ScriptCache::new(killed.clone())),
+            libraries: Mutex::new(Libraries::new(killed.clone())),
+            running: Mutex::new(None),
+            finished: Notify::new(),
+            killed,
+            wrote: AtomicBool::new(false),
+        }
+    }
+
+    /// Loads the function libraries saved in `path` at startup.
+    pub fn load_libraries(&self, path: &Path) -> Result<(), String> {
+        self.libraries.lock().unwrap().load_file(path)
+    }
+
+    /// Waits for the running script, if any, to finish, giving up with
+    /// BUSY once it has run for `threshold`.
+    pub async fn wait_idle(&self, threshold: Duration) -> ServerResult<()> {
+        loop {
+            let finished = self.finished.notified();
+            let deadline = match &*self.running.lock().unwrap() {
+                None => return Ok(()),
+                Some(running) if running.started.elapsed() >= threshold => {
+                    return Err(ServerError::Busy(running.kill_command()));
+                }
+                Some(running) => running.started + threshold,
+            };
+            tokio::select! {
+                _ = finished => {}
+                _ = tokio::time::sleep_until(deadline.into()) => {}
+            }
+        }
+    }
+
+    /// Waits for its turn and marks a script as running.
+    async fn begin(&self, function: Option<(String, Vec<String>)>) {
+        let mut function = Some(function);
+        loop {
+            let finished = self.finished.notified();
+            {
+                let mut running = self.running.lock().unwrap();
+                if running.is_none() {
+                    *running = Some(Running {
+                        started: Instant::now(),
+                        function: function.take().flatten(),
+                    });
+                    self.killed.store(false, Ordering::Relaxed);
+                    self.wrote.store(false, Ordering::Relaxed);
+                    return;
+                }
+            }
+            finished.await;
+        }
+    }
+
+    fn end(&self) {
+        *self.running.lock().unwrap() = None;
+        self.finished.notify_waiters();
+    }
+
+    /// SCRIPT KILL and FUNCTION KILL: stops the running script at its next
+    /// check, unless it has already written, since that would leave its
+    /// work half done. Each only stops its own kind of script.
+    pub fn kill(&self, function: bool) -> ServerResult<()> {
+        match &*self.running.lock().unwrap() {
+            Some(running) if running.function.is_some() == function => {}
+            _ => return Err(ServerError::NotBusy),
+        }
+        if self.wrote.load(Ordering::Relaxed) {
+            return Err(ServerError::Unkillable);
+        }
+        self.killed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Runs `job` on a blocking thread with the keyspace to itself, once no
+/// other script is running. `function` describes an FCALL for FUNCTION
+/// STATS.
+async fn run<F>(
+    server: &Arc<Server>,
+    client: &Arc<Client>,
+    function: Option<(String, Vec<String>)>,
+    read_only: bool,
+    job: F,
+) -> ServerResult<RESP>
+where
+    F: FnOnce(&mut Context) -> ServerResult<RESP> + Send + 'static,
+{
+    server.scripts.begin(function).await;
+    let (shared, client) = (server.clone(), client.clone());
+    let result = tokio::task::spawn_blocking(move || {
+        let server = &*shared;
+        server.storage.atomically(|execute| {
+            job(&mut Context {
+                server,
+                client: &client,
+                execute,
+                read_only,
+                wrote: &server.scripts.wrote,
+            })
+        })
+    })
+    .await;
+    server.scripts.end();
+    result.unwrap_or_else(|e| {
+        Err(ServerError::Script(format!(
+            "ERR Error running script: {}",
+            e
+        )))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::result::StorageError;
+    use crate::test_support::{bulk, connect, send};
+
+    #[tokio::test]
+    async fn test_eval_reaches_storage() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        let script = "redis.call('set', KEYS[1], ARGV[1]); return {redis.call('get', KEYS[1]), redis.call('incr', KEYS[2]), 1.5, redis.call('get', 'missing')}";
+        assert_eq!(
+            send(&server, &client, &["eval", script, "2", "a", "b", "x"]).await,
+            RESP::Array(vec![
+                bulk("x"),
+                RESP::Integer(1),
+                RESP::Integer(1),
+                RESP::Null
+            ])
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["eval", "return redis.status_reply('FINE')", "0"]
+            )
+            .await,
+            RESP::SimpleString("FINE".to_string())
+        );
+        assert_eq!(
+            send(&server, &client, &["eval", "return 1", "3", "a"]).await,
+            RESP::Error("ERR Number of keys can't be greater than number of args".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_script_cache() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        let sha = sha1hex(b"return ARGV[1]");
+        assert_eq!(
+            send(&server, &client, &["script", "load", "return ARGV[1]"]).await,
+            bulk(&sha)
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["evalsha", &sha.to_uppercase(), "0", "hi"]
+            )
+            .await,
+            bulk("hi")
+        );
+        assert_eq!(
+            send(&server, &client, &["script", "exists", &sha, "ffff"]).await,
+            RESP::Array(vec![RESP::Integer(1), RESP::Integer(0)])
+        );
+        assert_eq!(
+            send(&server, &client, &["script", "flush"]).await,
+            RESP::SimpleString("OK".to_string())
+        );
+        assert_eq!(
+            send(&server, &client, &["evalsha", &sha, "0"]).await,
+            RESP::Error(ServerError::NoScript.to_string())
+        );
+        assert!(matches!(
+            send(&server, &client, &["script", "load", "return +"]).await,
+            RESP::Error(e) if e.starts_with("ERR Error compiling script")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_script_errors() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        send(&server, &client, &["lpush", "list", "x"]).await;
+        // An error reply from redis.call reaches the caller untouched,
+        // while redis.pcall hands it to the script
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["eval", "return redis.call('get', 'list')", "0"]
+            )
+            .await,
+            RESP::Error(StorageError::WrongType.to_string())
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &[
+                    "eval",
+                    "local r = redis.pcall('get', 'list'); return r.err ~= nil",
+                    "0"
+                ]
+            )
+            .await,
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["eval_ro", "return redis.call('set', 'a', 1)", "0"]
+            )
+            .await,
+            RESP::Error("ERR Write commands are not allowed from read-only scripts.".to_string())
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &[
+                    "eval",
+                    "#!lua flags=no-writes\nreturn redis.call('del', 'a')",
+                    "0"
+                ]
+            )
+            .await,
+            RESP::Error("ERR Write commands are not allowed from read-only scripts.".to_string())
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["eval", "#!lua flags=bogus\nreturn 1", "0"]
+            )
+            .await,
+            RESP::Error("ERR Unexpected flag in script shebang: bogus".to_string())
+        );
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["eval", "return redis.call('eval', 'return 1', 0)", "0"]
+            )
+            .await,
+            RESP::Error("ERR This Redis command is not allowed from script".to_string())
+        );
+        assert_eq!(
+            send(&server, &client, &["eval", "error('boom')", "0"]).await,
+            RESP::Error("ERR Error running script: user_script:1: boom".to_string())
+        );
+        // The shebang is still line 1
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["eval", "#!lua\nlocal x = 1\nerror('boom')", "0"]
+            )
+            .await,
+            RESP::Error("ERR Error running script: user_script:3: boom".to_string())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_busy_script_can_be_killed() {
+        let server = Arc::new(Server::default());
+        server.set_config_value("busy-reply-threshold".to_string(), "10".to_string());
+        let client = connect(&server);
+        let other = connect(&server);
+        assert_eq!(
+            send(&server, &other, &["script", "kill"]).await,
+            RESP::Error(ServerError::NotBusy.to_string())
+        );
+
+        let (runner, looping) = (server.clone(), client.clone());
+        let script = tokio::spawn(async move {
+            send(&runner, &looping, &["eval", "while true do end", "0"]).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            send(&server, &other, &["ping"]).await,
+            RESP::Error(ServerError::Busy("SCRIPT KILL").to_string())
+        );
+        assert_eq!(
+            send(&server, &other, &["script", "kill"]).await,
+            RESP::SimpleString("OK".to_string())
+        );
+        assert_eq!(
+            script.await.unwrap(),
+            RESP::Error("ERR Script killed by user with SCRIPT KILL...".to_string())
+        );
+        assert_eq!(
+            send(&server, &other, &["ping"]).await,
+            RESP::SimpleString("PONG".to_string())
+        );
+
+        // Once a script has written, killing it would leave a half-done job
+        let (runner, looping) = (server.clone(), client.clone());
+        let script = tokio::spawn(async move {
+            let body = "redis.call('set', 'k', 'v'); while true do end";
+            send(&runner, &looping, &["eval", body, "0"]).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            send(&server, &other, &["script", "kill"]).await,
+            RESP::Error(ServerError::Unkillable.to_string())
+        );
+        // Nothing but restarting would stop it, so let the test finish
+        server.scripts.killed.store(true, Ordering::Relaxed);
+        script.await.unwrap();
+    }
+}
@@ -1,47 +1,227 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
 };
 
+use crate::acl::{Acl, command_acl, command_auth};
+use crate::blocking::{Blocking, command_xread};
+use crate::client::{
+    Client, ClientKind, ClientTable, QUERY_BUFFER_LIMIT, QUERY_BUFFER_SIZE, command_client,
+    held_by_write_pause,
+};
+use crate::cluster::{Cluster, command_cluster, command_migrate, serve_bus};
+use crate::config::parse_memory;
+use crate::info::info;
+use crate::peer::take_command;
+use crate::pubsub::{PubSub, command_publish, command_subscribe, notify};
+use crate::replication::{
+    Replication, command_psync, command_replconf, command_replicaof, command_wait, serve_replica,
+};
 use crate::resp::{RESP, bytes_to_resp};
-use crate::storage::Storage;
+use crate::scripting::{
+    DEFAULT_BUSY_REPLY_THRESHOLD, DEFAULT_FUNCTIONS_FILE, Scripts, command_eval, command_fcall,
+    command_function, command_script,
+};
+use crate::stats::ServerStats;
+use crate::storage::result::StorageError;
+use crate::storage::{DEFAULT_SHARDS, EvictionPolicy, Keyspace, ShardPool, ShardedStorage};
+use crate::tracking::Tracking;
+use crate::transaction::{
+    Watches, command_discard, command_exec, command_multi, command_unwatch, command_watch, queue,
+};
 
-use super::command::Command;
+use crate::command::{self, Command, CommandArg, CommandSpec, command_command, flags};
 
+/// Errors from handling a request. They display as Redis error replies,
+/// prefix included, so clients can tell them apart.
 #[derive(Debug, PartialEq)]
 pub enum ServerError {
-    UnknownCommand(String),
-    CommandError,
+    /// The whole request, name first
+    UnknownCommand(Vec<String>),
     IncorrectFormat(String),
+    InvalidConfig(String, String),
+    /// A bad argument, with the message to send after `ERR`
+    InvalidArgument(String),
+    WrongArity(String),
+    /// The subcommand and the container it was given to
+    UnknownSubcommand(String, String),
+    NoAuth,
+    WrongPass,
+    NoPermission(String),
+    NoScript,
+    /// A script has run past `busy-reply-threshold`, with the command
+    /// that would stop it
+    Busy(&'static str),
+    NotBusy,
+    Unkillable,
+    /// An error reply coming out of a script, prefix included
+    Script(String),
+    ExecAbort,
+    /// A write sent to a read-only replica
+    ReadOnly,
+    NoMasterLink,
+    /// The slot a key hashes to, and the address of the node serving it
+    Moved(u16, String),
+    /// Like `Moved`, for a single request while the slot is migrating
+    Ask(u16, String),
+    ClusterDown,
+    /// Talking to another server failed
+    IoErr(String),
+    Storage(StorageError),
+}
+
+impl From<StorageError> for ServerError {
+    fn from(e: StorageError) -> Self {
+        ServerError::Storage(e)
+    }
 }
 
 impl fmt::Display for ServerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ServerError::UnknownCommand(s) => write!(f, "Unknown command: {}", s),
-            ServerError::CommandError => write!(f, "Command error"),
-            ServerError::IncorrectFormat(format) => {
-                write!(f, "Incorrect serialization format for command: {}", format)
+            ServerError::UnknownCommand(args) => {
+                let name = args.first().map(String::as_str).unwrap_or_default();
+                write!(
+                    f,
+                    "ERR unknown command '{}', with args beginning with: ",
+                    name
+                )?;
+                for arg in args.iter().skip(1) {
+                    write!(f, "'{}' ", arg)?;
+                }
+                Ok(())
             }
+            ServerError::IncorrectFormat(message) => write!(f, "ERR Protocol error: {}", message),
+            ServerError::InvalidConfig(key, value) => write!(
+                f,
+                "ERR CONFIG SET failed (possibly related to argument '{}') - invalid value '{}'",
+                key, value
+            ),
+            ServerError::InvalidArgument(message) => write!(f, "ERR {}", message),
+            ServerError::WrongArity(name) => {
+                write!(f, "ERR wrong number of arguments for '{}' command", name)
+            }
+            ServerError::UnknownSubcommand(sub, container) => {
+                write!(
+                    f,
+                    "ERR unknown subcommand '{}'. Try {} HELP.",
+                    sub, container
+                )
+            }
+            ServerError::NoAuth => write!(f, "NOAUTH Authentication required."),
+            ServerError::WrongPass => write!(
+                f,
+                "WRONGPASS invalid username-password pair or user is disabled."
+            ),
+            ServerError::NoPermission(message) => write!(f, "NOPERM {}", message),
+            ServerError::NoScript => write!(f, "NOSCRIPT No matching script. Please use EVAL."),
+            ServerError::Busy(kill) => write!(
+                f,
+                "BUSY Redis is busy running a script. You can only call {} or SHUTDOWN NOSAVE.",
+                kill
+            ),
+            ServerError::NotBusy => write!(f, "NOTBUSY No scripts in execution right now."),
+            ServerError::Unkillable => write!(
+                f,
+                "UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command."
+            ),
+            ServerError::Script(message) => write!(f, "{}", message),
+            ServerError::ExecAbort => write!(
+                f,
+                "EXECABORT Transaction discarded because of previous errors."
+            ),
+            ServerError::ReadOnly => {
+                write!(f, "READONLY You can't write against a read only replica.")
+            }
+            ServerError::NoMasterLink => {
+                write!(
+                    f,
+                    "NOMASTERLINK Can't SYNC while not connected with my master"
+                )
+            }
+            ServerError::Moved(slot, addr) => write!(f, "MOVED {} {}", slot, addr),
+            ServerError::Ask(slot, addr) => write!(f, "ASK {} {}", slot, addr),
+            ServerError::ClusterDown => write!(f, "CLUSTERDOWN Hash slot not served"),
+            ServerError::IoErr(message) => write!(f, "IOERR {}", message),
+            ServerError::Storage(e) => write!(f, "{}", e),
         }
     }
 }
 
 pub type ServerResult<T> = Result<T, ServerError>;
 
+/// Config keys that `Server::apply_config` acts on, applied at startup.
+const APPLIED_CONFIG_KEYS: [&str; 7] = [
+    "maxmemory",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "requirepass",
+    "repl-backlog-size",
+    "replica-read-only",
+    "notify-keyspace-events",
+];
+
 pub struct Server {
     config: Mutex<HashMap<String, String>>,
-    storage: Mutex<Storage>,
+    pub(crate) storage: Keyspace,
+    pub(crate) stats: ServerStats,
+    pub(crate) clients: ClientTable,
+    pub(crate) acl: Mutex<Acl>,
+    pub(crate) scripts: Scripts,
+    pub(crate) replication: Replication,
+    pub(crate) cluster: Cluster,
+    pub(crate) tracking: Arc<Tracking>,
+    pub(crate) watches: Arc<Watches>,
+    pub(crate) pubsub: Arc<PubSub>,
+    pub(crate) blocking: Arc<Blocking>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new(HashMap::new(), Keyspace::default())
+    }
 }
 
 impl Server {
-    pub fn new(config: HashMap<String, String>, storage: Mutex<Storage>) -> Self {
+    pub fn new(config: HashMap<String, String>, storage: Keyspace) -> Self {
+        let replication = Replication::new();
+        storage.set_feed(replication.feed());
+        let tracking = Arc::new(Tracking::new());
+        storage.set_tracking(&tracking);
+        let watches = Arc::new(Watches::new());
+        storage.set_watches(&watches);
+        let pubsub = Arc::new(PubSub::new());
+        storage.set_pubsub(&pubsub);
+        let blocking = Arc::new(Blocking::new());
+        storage.set_blocking(&blocking);
+        let cluster = Cluster::new(&config);
         Server {
             config: Mutex::new(config),
             storage,
+            stats: ServerStats::new(),
+            clients: ClientTable::new(),
+            acl: Mutex::new(Acl::new()),
+            scripts: Scripts::new(),
+            replication,
+            cluster,
+            tracking,
+            watches,
+            pubsub,
+            blocking,
+        }
+    }
+
+    pub fn port(&self) -> String {
+        let port = self.get_config_value("port");
+        if port.is_empty() {
+            "6379".to_string()
+        } else {
+            port
         }
     }
 
@@ -58,19 +238,186 @@ impl Server {
     pub fn set_config_value(&self, key: String, value: String) {
         self.config.lock().unwrap().insert(key.to_string(), value);
     }
+
+    /// How long a script runs before other clients get BUSY.
+    /// `lua-time-limit` is the old name of `busy-reply-threshold`.
+    pub fn busy_reply_threshold(&self) -> Duration {
+        ["busy-reply-threshold", "lua-time-limit"]
+            .iter()
+            .find_map(|key| self.get_config_value(key).parse().ok())
+            .map_or(DEFAULT_BUSY_REPLY_THRESHOLD, Duration::from_millis)
+    }
+
+    /// Where function libraries are saved: `functions-file`, relative to
+    /// `dir` unless it's absolute. None when `functions-file` is empty.
+    pub fn functions_path(&self) -> Option<PathBuf> {
+        let file = self.get_config_value("functions-file");
+        if file.is_empty() {
+            return None;
+        }
+        Some(Path::new(&self.get_config_value("dir")).join(file))
+    }
+
+    /// Whether `client` is turned away from writing, as it is on a
+    /// read-only replica unless it's the link to the master.
+    pub fn rejects_writes(&self, client: &Client) -> bool {
+        self.replication.is_replica()
+            && client.kind() != ClientKind::Master
+            && ["replica-read-only", "slave-read-only"]
+                .iter()
+                .all(|key| self.get_config_value(key) != "no")
+    }
+
+    /// CONFIG RESETSTAT
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+        self.storage.reset_stats();
+        self.replication.reset_stats();
+    }
+
+    /// Pushes config values that the storage layer and ACLs care about
+    /// down to them. Other keys are accepted as-is.
+    pub fn apply_config(&self, key: &str, value: &str) -> ServerResult<()> {
+        let invalid = || ServerError::InvalidConfig(key.to_string(), value.to_string());
+        if key == "requirepass" {
+            self.acl.lock().unwrap().set_requirepass(value);
+            return Ok(());
+        }
+        let storage = &self.storage;
+        match key {
+            "maxmemory" => storage.set_maxmemory(parse_memory(value).ok_or_else(invalid)?),
+            "maxmemory-policy" => {
+                storage.set_maxmemory_policy(EvictionPolicy::parse(value).ok_or_else(invalid)?)
+            }
+            "maxmemory-samples" => {
+                storage.set_maxmemory_samples(value.parse().map_err(|_| invalid())?)
+            }
+            "busy-reply-threshold" | "lua-time-limit" => {
+                value.parse::<u64>().map_err(|_| invalid())?;
+            }
+            "repl-backlog-size" => self
+                .replication
+                .feed()
+                .set_backlog_size(parse_memory(value).ok_or_else(invalid)?),
+            "replica-read-only" | "slave-read-only" if value != "yes" && value != "no" => {
+                return Err(invalid());
+            }
+            "notify-keyspace-events" => self
+                .pubsub
+                .set_keyspace_events(notify::parse(value).ok_or_else(invalid)?),
+            _ => (),
+        }
+        Ok(())
+    }
 }
 
-pub async fn start(config: HashMap<String, String>) -> std::io::Result<()> {
+/// Builds the keyspace for the `execution-model` and `shards` configs.
+fn keyspace_from_config(config: &HashMap<String, String>) -> ServerResult<Keyspace> {
+    let shards = match config.get("shards") {
+        Some(value) => Some(
+            value
+                .parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| ServerError::InvalidConfig("shards".to_string(), value.clone()))?,
+        ),
+        None => None,
+    };
+    match config.get("execution-model").map(String::as_str) {
+        None | Some("locking") => Ok(Keyspace::Locking(Arc::new(ShardedStorage::new(
+            shards.unwrap_or(DEFAULT_SHARDS),
+        )))),
+        Some("shared-nothing") => Ok(Keyspace::SharedNothing(ShardPool::new(
+            shards.unwrap_or_else(ShardPool::default_shards),
+        ))),
+        Some(other) => Err(ServerError::InvalidConfig(
+            "execution-model".to_string(),
+            other.to_string(),
+        )),
+    }
+}
+
+pub async fn start(mut config: HashMap<String, String>) -> std::io::Result<()> {
     let default_port = "6379".to_string();
     let port = config.get("port").unwrap_or(&default_port);
 
     let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
     println!("Server initialized");
-    let storage = Mutex::new(Storage::new());
+    config
+        .entry("functions-file".to_string())
+        .or_insert_with(|| DEFAULT_FUNCTIONS_FILE.to_string());
+    let storage = keyspace_from_config(&config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
 
     let server: Arc<Server> = Arc::new(Server::new(config, storage));
+    for key in APPLIED_CONFIG_KEYS {
+        let value = server.get_config_value(key);
+        if value.is_empty() {
+            continue;
+        }
+        if let Err(e) = server.apply_config(key, &value) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                e.to_string(),
+            ));
+        }
+    }
+
+    // Like Redis, an ACL file overrides requirepass for the default user
+    let aclfile = server.get_config_value("aclfile");
+    if !aclfile.is_empty()
+        && let Err(e) = server.acl.lock().unwrap().load(&aclfile)
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+    }
+
+    if let Some(path) = server.functions_path()
+        && let Err(e) = server.scripts.load_libraries(&path)
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+    }
+
+    // "host port", as REPLICAOF takes it
+    for key in ["replicaof", "slaveof"] {
+        let value = server.get_config_value(key);
+        if value.is_empty() {
+            continue;
+        }
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                ServerError::InvalidConfig(key.to_string(), value.clone()).to_string(),
+            )
+        };
+        let (host, port) = value.split_once(' ').ok_or_else(invalid)?;
+        let port = port.trim().parse().map_err(|_| invalid())?;
+        server
+            .replication
+            .replicate(&server, host.to_string(), port);
+    }
+
+    match server.get_config_value("cluster-enabled").as_str() {
+        "" | "no" => (),
+        "yes" => {
+            let bus_port = server.cluster.bus_port();
+            let bus = TcpListener::bind(format!("127.0.0.1:{}", bus_port)).await?;
+            tokio::spawn(serve_bus(bus, server.clone()));
+        }
+        value => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                ServerError::InvalidConfig("cluster-enabled".to_string(), value.to_string())
+                    .to_string(),
+            ));
+        }
+    }
 
     println!("Ready to accept connections");
+    serve(listener, server).await
+}
+
+/// Accepts connections until the listener fails for good.
+pub async fn serve(listener: TcpListener, server: Arc<Server>) -> std::io::Result<()> {
     loop {
         match listener.accept().await {
             Ok((stream, _)) => {
@@ -85,30 +432,48 @@ pub async fn start(config: HashMap<String, String>) -> std::io::Result<()> {
 }
 
 async fn handle_connection(mut stream: TcpStream, server: Arc<Server>) {
-    let mut buffer = [0; 1024];
-    loop {
-        match stream.read(&mut buffer).await {
+    let mut chunk = [0; QUERY_BUFFER_SIZE];
+    // What arrived that isn't a whole request yet
+    let mut query = Vec::new();
+    let addr =
+        |a: std::io::Result<std::net::SocketAddr>| a.map(|a| a.to_string()).unwrap_or_default();
+    let client = server
+        .clients
+        .register(addr(stream.peer_addr()), addr(stream.local_addr()));
+    if server.acl.lock().unwrap().default_user_nopass() {
+        client.login("default");
+    }
+    server.stats.connection_opened();
+    'connection: loop {
+        let read = tokio::select! {
+            read = stream.read(&mut chunk) => read,
+            _ = client.pushed() => {
+                if let Err(e) = write_pushes(&mut stream, &server, &client).await {
+                    eprintln!("error writing push: {}", e);
+                    break;
+                }
+                continue;
+            }
+            _ = client.killed() => break,
+        };
+        match read {
             Ok(0) => {
                 break;
             }
             Ok(size) => {
-                let mut index: usize = 0;
-                let request: RESP = match bytes_to_resp(&buffer[..size].to_vec(), &mut index) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        let request_str = buffer_to_debug_string(&buffer[..size]);
-                        RESP::Error(format!("error parsing request {}: {}", request_str, e))
-                    }
-                };
-                let response: RESP = match process_request(request, server.clone()) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        let request_str = buffer_to_debug_string(&buffer[..size]);
-                        RESP::Error(format!("error processing request {}: {}", request_str, e))
+                server.stats.record_input(size);
+                query.extend_from_slice(&chunk[..size]);
+                while let Some((request, size)) = take_request(&mut query) {
+                    if !serve_request(&mut stream, &server, &client, request, size).await {
+                        break 'connection;
                     }
-                };
-                if let Err(e) = stream.write_all(&response.to_string().as_bytes()).await {
-                    eprintln!("error writing response: {}", e)
+                }
+                if query.len() > QUERY_BUFFER_LIMIT {
+                    eprintln!(
+                        "closing client {}: query buffer over the limit",
+                        client.id()
+                    );
+                    break;
                 }
             }
             Err(e) => {
@@ -117,96 +482,393 @@ async fn handle_connection(mut stream: TcpStream, server: Arc<Server>) {
             }
         }
     }
+    server.clients.unregister(client.id());
+    server.tracking.forget(client.id());
+    crate::transaction::forget(&server, &client);
+    server.pubsub.forget(client.id());
+    server.stats.connection_closed();
 }
 
-fn buffer_to_debug_string(buffer: &[u8]) -> String {
-    String::from_utf8_lossy(buffer)
-        .replace("\n", "\\n")
-        .replace("\r", "\\r")
+/// Takes the next whole request off the front of a connection's query
+/// buffer, with the bytes it took up. None means more bytes are needed.
+/// Requests are RESP arrays, or inline commands on one line like the ones
+/// typed into telnet.
+fn take_request(query: &mut Vec<u8>) -> Option<(ServerResult<Vec<CommandArg>>, usize)> {
+    if query.first() != Some(&b'*') {
+        let end = query.windows(2).position(|w| w == b"\r\n")? + 2;
+        let line: Vec<u8> = query.drain(..end).collect();
+        let request = bytes_to_resp(&line, &mut 0)
+            .map_err(|e| ServerError::IncorrectFormat(e.to_string()))
+            .and_then(request_args);
+        return Some((request, end));
+    }
+    match take_command(query) {
+        Ok(Some((args, raw))) => Some((Ok(args), raw.len())),
+        Ok(None) => None,
+        // There's no telling where the next request starts after this
+        Err(e) => {
+            let size = query.len();
+            query.clear();
+            Some((Err(e), size))
+        }
+    }
 }
 
-pub fn process_request(request: RESP, server: Arc<Server>) -> ServerResult<RESP> {
-    let elements = match request {
-        RESP::Array(v) => v,
-        _ => {
-            return Err(ServerError::IncorrectFormat(
-                "Expected first element to be an Array".to_string(),
-            ));
+/// Runs one request of `size` bytes and writes its reply. False once the
+/// connection is done with, like after QUIT or PSYNC.
+async fn serve_request(
+    stream: &mut TcpStream,
+    server: &Arc<Server>,
+    client: &Arc<Client>,
+    request: ServerResult<Vec<CommandArg>>,
+    size: usize,
+) -> bool {
+    let request = request.and_then(|command| Ok((command::resolve(&command)?, command)));
+    let name = request.as_ref().map_or("", |(spec, _)| spec.name);
+    client.begin_command(name, size);
+    // EXEC is held when anything it would run writes
+    let write = request.as_ref().is_ok_and(|(spec, _)| {
+        held_by_write_pause(spec)
+            || (spec.command == Command::Exec && client.transaction().writes())
+    });
+    tokio::select! {
+        _ = server.clients.wait_unpaused(write) => {}
+        _ = client.killed() => return false,
+    }
+    let result = match request {
+        Ok((spec, command)) => execute_request(spec, command, server.clone(), client).await,
+        Err(e) => {
+            client.transaction().fail();
+            Err(e)
         }
     };
-    let mut command = Vec::new();
-    for elem in elements.iter() {
-        match elem {
-            RESP::BulkString(s) => command.push(s.clone()),
-            _ => {
-                return Err(ServerError::IncorrectFormat(
-                    "Expected first element to be an Array of BulkString".to_string(),
-                ));
-            }
+    let response = result.unwrap_or_else(|e| RESP::Error(e.to_string()));
+    if let RESP::Error(message) = &response {
+        let prefix = message.split(' ').next().unwrap_or_default();
+        server.stats.record_error(prefix);
+    }
+    // Pushed while the command ran, so ahead of its reply
+    if let Err(e) = write_pushes(stream, server, client).await {
+        eprintln!("error writing push: {}", e);
+        return false;
+    }
+    if client.should_reply() {
+        let response = response.to_bytes();
+        server.stats.record_output(response.len());
+        client.begin_reply(response.len());
+        if let Err(e) = stream.write_all(&response).await {
+            eprintln!("error writing response: {}", e)
         }
     }
+    client.end_reply();
+    if client.is_killed() {
+        return false;
+    }
+    // After PSYNC the connection carries the replication stream
+    if let Some(sync) = server.replication.take_sync(client.id()) {
+        serve_replica(stream, server, client, sync).await;
+        return false;
+    }
+    true
+}
+
+/// Writes the messages pushed to a client since it last wrote any.
+async fn write_pushes(
+    stream: &mut TcpStream,
+    server: &Server,
+    client: &Client,
+) -> std::io::Result<()> {
+    for message in client.take_pushes() {
+        let message = message.to_bytes();
+        server.stats.record_output(message.len());
+        stream.write_all(&message).await?;
+    }
+    Ok(())
+}
 
-    let command_type = Command::from(&command);
+/// The arguments of a request, which must be an array of bulk strings.
+fn request_args(request: RESP) -> ServerResult<Vec<CommandArg>> {
+    let format_error =
+        || ServerError::IncorrectFormat("expected an array of bulk strings".to_string());
+    let RESP::Array(elements) = request else {
+        return Err(format_error());
+    };
+    elements
+        .into_iter()
+        .map(|element| match element {
+            RESP::BulkString(s) => Ok(CommandArg::from(s)),
+            _ => Err(format_error()),
+        })
+        .collect()
+}
 
-    if command_type.is_none() {
-        let command = command.join(" ");
-        let err = ServerError::UnknownCommand(command);
-        return Err(err);
+pub async fn process_request(
+    request: RESP,
+    server: Arc<Server>,
+    client: &Arc<Client>,
+) -> ServerResult<RESP> {
+    let command = request_args(request)?;
+    let spec = command::resolve(&command).inspect_err(|_| client.transaction().fail())?;
+    execute_request(spec, command, server, client).await
+}
+
+/// PING outside the subscribed context.
+pub(crate) fn command_ping(command: &[CommandArg]) -> RESP {
+    if command.len() == 2 {
+        RESP::SimpleString(command[1].to_string())
+    } else {
+        RESP::SimpleString("PONG".to_string())
     }
-    let command_type = command_type.unwrap();
+}
 
-    return match command_type {
-        Command::Ping => {
-            if command.len() == 2 {
-                Ok(RESP::SimpleString(command[1].to_string()))
-            } else {
-                Ok(RESP::SimpleString("PONG".to_string()))
-            }
+/// Refuses a request the client may not run right now.
+async fn check_request(
+    spec: &'static CommandSpec,
+    command: &[CommandArg],
+    server: &Arc<Server>,
+    client: &Arc<Client>,
+) -> ServerResult<()> {
+    // The master's commands were checked when the master ran them
+    let from_master = client.kind() == ClientKind::Master;
+    if !spec.has_flag(flags::NO_AUTH) && !from_master {
+        if !client.is_authenticated() {
+            return Err(ServerError::NoAuth);
         }
-        Command::Echo => {
-            if command.len() == 2 {
-                Ok(RESP::BulkString(command[1].to_string()))
-            } else {
-                Err(ServerError::CommandError)
+        server.acl.lock().unwrap().check(client, spec, command)?;
+    }
+    if client.is_subscribed()
+        && !matches!(
+            spec.command,
+            Command::Subscribe
+                | Command::Unsubscribe
+                | Command::PSubscribe
+                | Command::PUnsubscribe
+                | Command::Ping
+                | Command::Quit
+        )
+    {
+        return Err(ServerError::InvalidArgument(format!(
+            "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+            spec.name
+        )));
+    }
+    if spec.has_flag(flags::WRITE) && server.rejects_writes(client) {
+        return Err(ServerError::ReadOnly);
+    }
+    if server.cluster.is_enabled() && !from_master {
+        server
+            .cluster
+            .check_request(server, client, spec, command)
+            .await?;
+    }
+    server.tracking.before_command(client, spec, command);
+    if !spec.has_flag(flags::ALLOW_BUSY) {
+        server
+            .scripts
+            .wait_idle(server.busy_reply_threshold())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Runs a request already resolved to `spec`.
+async fn execute_request(
+    spec: &'static CommandSpec,
+    command: Vec<CommandArg>,
+    server: Arc<Server>,
+    client: &Arc<Client>,
+) -> ServerResult<RESP> {
+    // A command refused inside MULTI makes EXEC abort
+    check_request(spec, &command, &server, client)
+        .await
+        .inspect_err(|_| client.transaction().fail())?;
+    if client.transaction().is_open()
+        && !matches!(
+            spec.command,
+            Command::Exec | Command::Discard | Command::Multi | Command::Watch | Command::Quit
+        )
+    {
+        return queue(client, spec, command);
+    }
+
+    let subscribed = client.is_subscribed();
+    let start = Instant::now();
+    let result = match spec.command {
+        // Subscribed RESP2 connections can only tell replies from messages
+        // by their shape
+        Command::Ping if subscribed => Ok(RESP::Array(vec![
+            RESP::BulkString("pong".into()),
+            RESP::BulkString(
+                command
+                    .get(1)
+                    .map_or_else(Vec::new, |arg| arg.as_bytes().to_vec()),
+            ),
+        ])),
+        Command::Ping => Ok(command_ping(&command)),
+        Command::Echo => Ok(RESP::BulkString(command[1].as_bytes().to_vec())),
+        Command::Command => command_command(&command[1..]),
+        Command::Config => match spec.name {
+            "config|get" => {
+                let value = server.get_config_value(&command[2]);
+                Ok(RESP::SimpleString(value))
+            }
+            "config|resetstat" => {
+                server.reset_stats();
+                Ok(RESP::SimpleString("OK".to_string()))
             }
+            "config|set" => {
+                let key = command[2].to_string();
+                let value = command[3].to_string();
+                server.apply_config(&key, &value)?;
+                server.set_config_value(key, value);
+                Ok(RESP::SimpleString(command[3].to_string()))
+            }
+            _ => Ok(RESP::Array(
+                [
+                    "CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                    "GET <pattern>",
+                    "    Return parameters matching the glob-like <pattern> and their values.",
+                    "SET <directive> <value>",
+                    "    Set the configuration <directive> to <value>.",
+                    "RESETSTAT",
+                    "    Reset statistics reported by the INFO command.",
+                    "HELP",
+                    "    Print this help.",
+                ]
+                .iter()
+                .map(|line| RESP::SimpleString(line.to_string()))
+                .collect(),
+            )),
+        },
+        Command::Quit => Ok(RESP::SimpleString("OK".to_string())),
+        Command::Info => Ok(RESP::BulkString(info(&server, &command[1..]).into())),
+        Command::Client => command_client(&server, client, &command[1..]),
+        Command::Auth => command_auth(&server, client, &command[1..]),
+        Command::Acl => command_acl(&server, client, &command[1..]),
+        Command::Eval | Command::EvalSha | Command::EvalRo | Command::EvalShaRo => {
+            command_eval(&server, client, spec, &command).await
         }
-        Command::Command => {
-            if command[1].eq_ignore_ascii_case("DOCS") {
-                Ok(RESP::Array(Vec::new()))
-            } else {
-                Err(ServerError::CommandError)
+        Command::Script => command_script(&server, spec, &command[1..]),
+        Command::FCall | Command::FCallRo => command_fcall(&server, client, spec, &command).await,
+        Command::Function => {
+            let result = command_function(&server, spec, &command[1..]);
+            if result.is_ok() && spec.has_flag(flags::WRITE) {
+                server.replication.feed().propagate(&command);
             }
+            result
         }
-        Command::Config => {
-            if command[1].eq_ignore_ascii_case("GET") {
-                if command.len() == 3 {
-                    let key = command[2].to_string();
-                    let value = server.get_config_value(&key);
-                    Ok(RESP::SimpleString(value))
-                } else {
-                    Err(ServerError::CommandError)
-                }
-            } else if command[1].eq_ignore_ascii_case("SET") {
-                if command.len() == 4 {
-                    let key = command[2].to_string();
-                    let value = command[3].to_string();
-                    server.set_config_value(key, value);
-                    Ok(RESP::SimpleString(command[3].to_string()))
-                } else {
-                    Err(ServerError::CommandError)
-                }
-            } else {
-                Err(ServerError::CommandError)
+        Command::ReplicaOf | Command::SlaveOf => command_replicaof(&server, &command[1..]),
+        Command::ReplConf => command_replconf(&server, client, &command[1..]),
+        Command::PSync => command_psync(&server, client, &command[1..]).await,
+        Command::Wait => command_wait(&server, &command[1..]).await,
+        Command::Cluster => command_cluster(&server, spec, &command[1..]).await,
+        Command::Asking => {
+            if !server.cluster.is_enabled() {
+                return Err(ServerError::InvalidArgument(
+                    "This instance has cluster support disabled".to_string(),
+                ));
             }
+            client.set_asking();
+            Ok(RESP::SimpleString("OK".to_string()))
         }
-        Command::Quit => Ok(RESP::SimpleString("OK".to_string())),
+        Command::Migrate => command_migrate(&server, &command[1..]).await,
+        Command::Subscribe | Command::Unsubscribe | Command::PSubscribe | Command::PUnsubscribe => {
+            command_subscribe(&server, client, spec, &command)
+        }
+        Command::Publish => command_publish(&server, &command),
+        Command::XRead | Command::XReadGroup => {
+            command_xread(&server, client, spec, &command).await
+        }
+        Command::Multi => command_multi(client),
+        Command::Exec => command_exec(&server, client).await,
+        Command::Discard => command_discard(&server, client),
+        Command::Watch => command_watch(&server, client, &command[1..]),
+        Command::Unwatch => command_unwatch(&server, client),
         _ => {
             // Execute command on server
-            let result = server.storage.lock().unwrap().process_command(&command);
-            return match result {
-                Ok(resp) => Ok(resp),
-                Err(_) => Err(ServerError::CommandError),
-            };
+            server
+                .storage
+                .execute(spec, &command)
+                .await
+                .map_err(ServerError::from)
         }
     };
+    server
+        .stats
+        .record_command(spec, start.elapsed(), result.is_err());
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::test_support::{exchange, listen};
+
+    #[tokio::test]
+    async fn test_requests_larger_than_a_read() {
+        let (_server, mut stream) = listen().await;
+        let value = vec![b'v'; 5000];
+        assert_eq!(
+            exchange(&mut stream, &[b"set", b"big", &value]).await,
+            b"+OK\r\n"
+        );
+        let reply = exchange(&mut stream, &[b"get", b"big"]).await;
+        assert_eq!(reply, [&b"$5000\r\n"[..], &value, b"\r\n"].concat());
+    }
+
+    #[tokio::test]
+    async fn test_requests_split_and_pipelined() {
+        let (_server, mut stream) = listen().await;
+        stream.write_all(b"*1\r\n$4\r\nPI").await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        stream
+            .write_all(b"NG\r\n*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\nPING\r\n")
+            .await
+            .unwrap();
+        let expected = b"+PONG\r\n$2\r\nhi\r\n+PONG\r\n";
+        let mut reply = vec![0; expected.len()];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    #[tokio::test]
+    async fn test_bit_operations_reply_their_bytes() {
+        let (_server, mut stream) = listen().await;
+        exchange(&mut stream, &[b"setbit", b"bm", b"7", b"1"]).await;
+        exchange(&mut stream, &[b"setbit", b"bm", b"0", b"1"]).await;
+        assert_eq!(
+            exchange(&mut stream, &[b"get", b"bm"]).await,
+            b"$1\r\n\x81\r\n"
+        );
+        exchange(
+            &mut stream,
+            &[b"bitfield", b"bf", b"set", b"u16", b"0", b"65280"],
+        )
+        .await;
+        assert_eq!(
+            exchange(&mut stream, &[b"get", b"bf"]).await,
+            b"$2\r\n\xff\x00\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hyperloglog_survives_get_and_set() {
+        let (_server, mut stream) = listen().await;
+        exchange(&mut stream, &[b"pfadd", b"hll", b"a", b"b", b"c"]).await;
+        let reply = exchange(&mut stream, &[b"get", b"hll"]).await;
+        let start = reply.iter().position(|&b| b == b'\n').unwrap() + 1;
+        let payload = &reply[start..reply.len() - 2];
+        assert!(std::str::from_utf8(payload).is_err());
+        assert_eq!(
+            exchange(&mut stream, &[b"set", b"copy", payload]).await,
+            b"+OK\r\n"
+        );
+        assert_eq!(exchange(&mut stream, &[b"get", b"copy"]).await, reply);
+        assert_eq!(
+            exchange(&mut stream, &[b"pfcount", b"copy"]).await,
+            b":3\r\n"
+        );
+    }
 }
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::command::{self, CommandSpec};
+
+#[derive(Default, Clone)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    pub failed_calls: u64,
+}
+
+/// The counters behind one command's `CommandStats`, updated without a
+/// lock so connections don't queue up behind each other to count.
+#[derive(Default)]
+struct CommandCounters {
+    calls: AtomicU64,
+    usec: AtomicU64,
+    failed_calls: AtomicU64,
+}
+
+/// Server-wide counters reported by INFO. Everything except gauges like
+/// `connected_clients` is cleared by CONFIG RESETSTAT.
+pub struct ServerStats {
+    started: Instant,
+    /// Unix seconds at startup
+    started_at: u64,
+    connected_clients: AtomicU64,
+    connections_received: AtomicU64,
+    commands_processed: AtomicU64,
+    net_input_bytes: AtomicU64,
+    net_output_bytes: AtomicU64,
+    /// By `CommandSpec::position`
+    commands: Vec<CommandCounters>,
+    /// Keyed by error prefix, e.g. ERR or WRONGTYPE
+    errors: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Default for ServerStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerStats {
+    pub fn new() -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        ServerStats {
+            started: Instant::now(),
+            started_at,
+            connected_clients: AtomicU64::new(0),
+            connections_received: AtomicU64::new(0),
+            commands_processed: AtomicU64::new(0),
+            net_input_bytes: AtomicU64::new(0),
+            net_output_bytes: AtomicU64::new(0),
+            commands: command::all_specs()
+                .iter()
+                .map(|_| CommandCounters::default())
+                .collect(),
+            errors: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn started_at(&self) -> u64 {
+        self.started_at
+    }
+
+    pub fn connection_opened(&self) {
+        self.connections_received.fetch_add(1, Ordering::Relaxed);
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn record_input(&self, bytes: usize) {
+        self.net_input_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_output(&self, bytes: usize) {
+        self.net_output_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_command(&self, spec: &CommandSpec, elapsed: Duration, failed: bool) {
+        self.commands_processed.fetch_add(1, Ordering::Relaxed);
+        let counters = &self.commands[spec.position()];
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        counters
+            .usec
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        if failed {
+            counters.failed_calls.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_error(&self, prefix: &str) {
+        *self
+            .errors
+            .lock()
+            .unwrap()
+            .entry(prefix.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn connected_clients(&self) -> u64 {
+        self.connected_clients.load(Ordering::Relaxed)
+    }
+
+    pub fn connections_received(&self) -> u64 {
+        self.connections_received.load(Ordering::Relaxed)
+    }
+
+    pub fn commands_processed(&self) -> u64 {
+        self.commands_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn net_input_bytes(&self) -> u64 {
+        self.net_input_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn net_output_bytes(&self) -> u64 {
+        self.net_output_bytes.load(Ordering::Relaxed)
+    }
+
+    /// The commands called so far by name, like `get` or `config|get`,
+    /// sorted for stable INFO output.
+    pub fn commands(&self) -> BTreeMap<&'static str, CommandStats> {
+        command::all_specs()
+            .iter()
+            .zip(&self.commands)
+            .filter_map(|(spec, counters)| {
+                let calls = counters.calls.load(Ordering::Relaxed);
+                let stats = CommandStats {
+                    calls,
+                    usec: counters.usec.load(Ordering::Relaxed),
+                    failed_calls: counters.failed_calls.load(Ordering::Relaxed),
+                };
+                (calls > 0).then_some((spec.name, stats))
+            })
+            .collect()
+    }
+
+    pub fn errors(&self) -> BTreeMap<String, u64> {
+        self.errors.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        self.connections_received.store(0, Ordering::Relaxed);
+        self.commands_processed.store(0, Ordering::Relaxed);
+        self.net_input_bytes.store(0, Ordering::Relaxed);
+        self.net_output_bytes.store(0, Ordering::Relaxed);
+        for counters in &self.commands {
+            counters.calls.store(0, Ordering::Relaxed);
+            counters.usec.store(0, Ordering::Relaxed);
+            counters.failed_calls.store(0, Ordering::Relaxed);
+        }
+        self.errors.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spec(name: &str) -> &'static CommandSpec {
+        command::all_specs()
+            .iter()
+            .find(|spec| spec.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_record_command() {
+        let stats = ServerStats::new();
+        stats.record_command(spec("get"), Duration::from_micros(10), false);
+        stats.record_command(spec("get"), Duration::from_micros(20), true);
+        stats.record_command(spec("config|get"), Duration::ZERO, false);
+        let commands = stats.commands();
+        let get = commands.get("get").unwrap();
+        assert_eq!(get.calls, 2);
+        assert_eq!(get.usec, 30);
+        assert_eq!(get.failed_calls, 1);
+        assert_eq!(commands.get("config|get").unwrap().calls, 1);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(stats.commands_processed(), 3);
+    }
+
+    #[test]
+    fn test_reset_keeps_gauges() {
+        let stats = ServerStats::new();
+        stats.connection_opened();
+        stats.record_command(spec("ping"), Duration::ZERO, false);
+        stats.record_error("ERR");
+        stats.reset();
+        assert_eq!(stats.connected_clients(), 1);
+        assert_eq!(stats.connections_received(), 0);
+        assert_eq!(stats.commands_processed(), 0);
+        assert!(stats.commands().is_empty());
+        assert!(stats.errors().is_empty());
+    }
+}
@@ -0,0 +1,574 @@
+use std::borrow::Cow;
+
+use super::memory::MemoryUsage;
+use super::result::{StorageError, StorageResult, parse_integer};
+use super::{PrimitiveStorageValue, Storage, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::bitmap::{self, BitOp, Field, Overflow};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+/// SETBIT offsets stay below this, keeping strings under 512MB like Redis.
+const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8;
+
+const INVALID_OFFSET: &str = "bit offset is not an integer or out of range";
+
+fn parse_offset(arg: &str) -> StorageResult<usize> {
+    arg.parse::<u64>()
+        .ok()
+        .filter(|offset| *offset < MAX_BIT_OFFSET)
+        .map(|offset| offset as usize)
+        .ok_or_else(|| StorageError::syntax(INVALID_OFFSET))
+}
+
+/// Whether a range counts bytes or bits, from the optional BYTE|BIT
+/// argument.
+fn parse_unit(arg: Option<&CommandArg>) -> StorageResult<bool> {
+    match arg.map(|arg| arg.to_uppercase()).as_deref() {
+        None | Some("BYTE") => Ok(false),
+        Some("BIT") => Ok(true),
+        Some(_) => Err(StorageError::syntax_error()),
+    }
+}
+
+/// Resolves `start` and `end`, which count back from the end when
+/// negative, to an inclusive range over `len` units. None if it's empty.
+fn resolve_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let resolve = |index: i64| {
+        if index < 0 {
+            (len + index).max(0)
+        } else {
+            index
+        }
+    };
+    let (start, end) = (resolve(start), resolve(end).min(len - 1));
+    (start <= end).then_some((start as usize, end as usize))
+}
+
+enum FieldOp {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+/// One BITFIELD operation, with the OVERFLOW in effect where it appeared.
+struct FieldRequest {
+    op: FieldOp,
+    field: Field,
+    offset: usize,
+    overflow: Overflow,
+}
+
+impl FieldRequest {
+    fn is_write(&self) -> bool {
+        !matches!(self.op, FieldOp::Get)
+    }
+
+    /// Runs the operation on `bytes`. SET replies with the old value and
+    /// INCRBY with the new one, or Null when OVERFLOW FAIL left it alone.
+    fn run(&self, bytes: &mut Vec<u8>) -> RESP {
+        let old = self.field.get(bytes, self.offset);
+        let (new, reply) = match self.op {
+            FieldOp::Get => return RESP::Integer(old),
+            FieldOp::Set(value) => (self.field.add(0, value, self.overflow), old),
+            FieldOp::IncrBy(incr) => {
+                let new = self.field.add(old, incr, self.overflow);
+                (new, new.unwrap_or(0))
+            }
+        };
+        match new {
+            Some(new) => {
+                self.field.set(bytes, self.offset, new);
+                RESP::Integer(reply)
+            }
+            None => RESP::Null,
+        }
+    }
+}
+
+/// Parses every BITFIELD operation before any runs, so a bad one fails
+/// the command without writing anything.
+fn parse_bitfield(command: &[CommandArg], read_only: bool) -> StorageResult<Vec<FieldRequest>> {
+    let mut requests = Vec::new();
+    let mut overflow = Overflow::Wrap;
+    let mut i = 2;
+    while i < command.len() {
+        let name = command[i].to_uppercase();
+        let arity = match name.as_str() {
+            "GET" => 3,
+            "SET" | "INCRBY" => 4,
+            "OVERFLOW" => 2,
+            _ => return Err(StorageError::syntax_error()),
+        };
+        let args = command
+            .get(i + 1..i + arity)
+            .ok_or_else(StorageError::syntax_error)?;
+        i += arity;
+        if name == "OVERFLOW" {
+            overflow = match args[0].to_uppercase().as_str() {
+                "WRAP" => Overflow::Wrap,
+                "SAT" => Overflow::Sat,
+                "FAIL" => Overflow::Fail,
+                _ => return Err(StorageError::syntax("Invalid OVERFLOW type specified")),
+            };
+            continue;
+        }
+        let field = Field::parse(&args[0]).ok_or_else(|| {
+            StorageError::syntax("Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.",
+            )
+        })?;
+        // `#N` is the Nth field of this width
+        let offset = match args[1].strip_prefix('#') {
+            Some(index) => index
+                .parse::<u64>()
+                .ok()
+                .and_then(|index| index.checked_mul(u64::from(field.bits)))
+                .ok_or_else(|| StorageError::syntax(INVALID_OFFSET))?,
+            None => parse_offset(&args[1])? as u64,
+        };
+        if offset + u64::from(field.bits) > MAX_BIT_OFFSET {
+            return Err(StorageError::syntax(INVALID_OFFSET));
+        }
+        let op = match name.as_str() {
+            "GET" => FieldOp::Get,
+            "SET" => FieldOp::Set(parse_integer(&args[2])?),
+            _ => FieldOp::IncrBy(parse_integer(&args[2])?),
+        };
+        if read_only && !matches!(op, FieldOp::Get) {
+            return Err(StorageError::syntax(
+                "BITFIELD_RO only supports the GET subcommand",
+            ));
+        }
+        requests.push(FieldRequest {
+            op,
+            field,
+            offset: offset as usize,
+            overflow,
+        });
+    }
+    Ok(requests)
+}
+
+impl Storage {
+    /// The bytes of the string at `key`, counted as a read.
+    pub(super) fn read_string(&mut self, key: &str) -> StorageResult<Option<Cow<'_, [u8]>>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::Primitive(value)) => Ok(Some(value.as_bytes())),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the bytes of the string at `key`, creating an empty one
+    /// first if needed. The string is kept as bytes from then on, so that
+    /// later bit writes don't have to check it is still UTF-8.
+    pub(super) fn write_bytes<T>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&mut Vec<u8>) -> T,
+    ) -> StorageResult<T> {
+        if self.lookup(key).is_none() {
+            self.insert(
+                key.to_string(),
+                StorageValue::Primitive(PrimitiveStorageValue::Bytes(Vec::new())),
+            );
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::Primitive(value) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = value.memory_usage();
+        let mut bytes = match std::mem::replace(value, PrimitiveStorageValue::Integer(0)) {
+            PrimitiveStorageValue::String(s) => s.into_bytes(),
+            PrimitiveStorageValue::Integer(n) => n.to_string().into_bytes(),
+            PrimitiveStorageValue::Bytes(b) => b,
+        };
+        let result = f(&mut bytes);
+        *value = PrimitiveStorageValue::Bytes(bytes);
+        self.used_memory = (self.used_memory + value.memory_usage()).saturating_sub(before);
+        Ok(result)
+    }
+
+    /// SETBIT key offset value
+    pub(super) fn command_setbit(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let offset = parse_offset(&command[2])?;
+        let bit = match command[3].as_str() {
+            "0" => false,
+            "1" => true,
+            _ => {
+                return Err(StorageError::syntax(
+                    "bit is not an integer or out of range",
+                ));
+            }
+        };
+        let key = &command[1];
+        let previous = self.write_bytes(key, |bytes| bitmap::set(bytes, offset, bit))?;
+        self.notify(notify::STRING, "setbit", key);
+        Ok(RESP::Integer(i64::from(previous)))
+    }
+
+    /// GETBIT key offset
+    pub(super) fn command_getbit(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let offset = parse_offset(&command[2])?;
+        let bit = self
+            .read_string(&command[1])?
+            .is_some_and(|bytes| bitmap::get(&bytes, offset));
+        Ok(RESP::Integer(i64::from(bit)))
+    }
+
+    /// BITCOUNT key [start end [BYTE|BIT]]
+    pub(super) fn command_bitcount(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let range = match &command[2..] {
+            [] => None,
+            [start, end, unit @ ..] if unit.len() <= 1 => Some((
+                parse_integer(start)?,
+                parse_integer(end)?,
+                parse_unit(unit.first())?,
+            )),
+            _ => return Err(StorageError::syntax_error()),
+        };
+        let Some(bytes) = self.read_string(&command[1])? else {
+            return Ok(RESP::Integer(0));
+        };
+        let count = match range {
+            None => bitmap::count(&bytes),
+            Some((start, end, bits)) => {
+                let len = if bits { bytes.len() * 8 } else { bytes.len() };
+                match resolve_range(start, end, len) {
+                    None => 0,
+                    Some((start, end)) if bits => bitmap::count_range(&bytes, start, end),
+                    Some((start, end)) => bitmap::count(&bytes[start..=end]),
+                }
+            }
+        };
+        Ok(RESP::Integer(count as i64))
+    }
+
+    /// BITPOS key bit [start [end [BYTE|BIT]]]
+    pub(super) fn command_bitpos(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let bit = match parse_integer(&command[2])? {
+            0 => false,
+            1 => true,
+            _ => return Err(StorageError::syntax("The bit argument must be 1 or 0.")),
+        };
+        if command.len() > 6 {
+            return Err(StorageError::syntax_error());
+        }
+        let start = command.get(3).map_or(Ok(0), |arg| parse_integer(arg))?;
+        let end = command.get(4).map_or(Ok(-1), |arg| parse_integer(arg))?;
+        let bits = parse_unit(command.get(5))?;
+        let Some(bytes) = self.read_string(&command[1])? else {
+            // A missing key is an empty string, which is all clear bits
+            return Ok(RESP::Integer(if bit { -1 } else { 0 }));
+        };
+        let len = if bits { bytes.len() * 8 } else { bytes.len() };
+        let Some((start, end)) = resolve_range(start, end, len) else {
+            return Ok(RESP::Integer(-1));
+        };
+        let (start, end) = if bits {
+            (start, end)
+        } else {
+            (start * 8, end * 8 + 7)
+        };
+        let position = match bitmap::position(&bytes, bit, start, end) {
+            Some(position) => position as i64,
+            // Without an explicit end the string reads as padded with
+            // clear bits, so the first one is just past it
+            None if !bit && command.len() <= 4 => (end + 1) as i64,
+            None => -1,
+        };
+        Ok(RESP::Integer(position))
+    }
+
+    /// BITOP AND|OR|XOR|NOT|DIFF destkey key [key ...]
+    pub(super) fn command_bitop(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let op = match command[1].to_uppercase().as_str() {
+            "AND" => BitOp::And,
+            "OR" => BitOp::Or,
+            "XOR" => BitOp::Xor,
+            "NOT" => BitOp::Not,
+            "DIFF" => BitOp::Diff,
+            _ => return Err(StorageError::syntax_error()),
+        };
+        let (dest, keys) = (&command[2], &command[3..]);
+        if op == BitOp::Not && keys.len() != 1 {
+            return Err(StorageError::syntax(
+                "BITOP NOT must be called with a single source key.",
+            ));
+        }
+        if op == BitOp::Diff && keys.len() < 2 {
+            return Err(StorageError::syntax(
+                "BITOP DIFF must be called with at least two source keys.",
+            ));
+        }
+        let mut sources = Vec::with_capacity(keys.len());
+        for key in keys {
+            let bytes = self.read_string(key)?.map(Cow::into_owned);
+            sources.push(bytes.unwrap_or_default());
+        }
+        let sources: Vec<&[u8]> = sources.iter().map(Vec::as_slice).collect();
+        let result = bitmap::op(op, &sources);
+        let len = result.len();
+        if result.is_empty() {
+            self.expire_if_needed(dest);
+            if self.remove(dest).is_some() {
+                self.notify(notify::GENERIC, "del", dest);
+            }
+        } else {
+            let value = StorageValue::Primitive(PrimitiveStorageValue::Bytes(result));
+            self.insert(dest.to_string(), value);
+            self.notify(notify::STRING, "set", dest);
+        }
+        Ok(RESP::Integer(len as i64))
+    }
+
+    /// BITFIELD key [GET encoding offset | SET encoding offset value |
+    /// INCRBY encoding offset increment | OVERFLOW WRAP|SAT|FAIL ...],
+    /// and BITFIELD_RO with only GETs.
+    pub(super) fn command_bitfield(
+        &mut self,
+        command: &[CommandArg],
+        read_only: bool,
+    ) -> StorageResult<RESP> {
+        let requests = parse_bitfield(command, read_only)?;
+        let key = &command[1];
+        if !requests.iter().any(FieldRequest::is_write) {
+            let bytes = self.read_string(key)?.unwrap_or_default();
+            let replies = requests
+                .iter()
+                .map(|request| RESP::Integer(request.field.get(&bytes, request.offset)))
+                .collect();
+            return Ok(RESP::Array(replies));
+        }
+        let replies = self.write_bytes(key, |bytes| {
+            requests.iter().map(|request| request.run(bytes)).collect()
+        })?;
+        self.notify(notify::STRING, "setbit", key);
+        Ok(RESP::Array(replies))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{cmd, run};
+
+    #[test]
+    fn test_setbit_and_getbit() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["setbit", "b", "7", "1"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["setbit", "b", "7", "0"]),
+            Ok(RESP::Integer(1))
+        );
+        run(&mut storage, &["setbit", "b", "100", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["getbit", "b", "100"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["getbit", "b", "99999"]),
+            Ok(RESP::Integer(0))
+        );
+        assert!(run(&mut storage, &["setbit", "b", "-1", "1"]).is_err());
+        assert!(run(&mut storage, &["setbit", "b", "0", "2"]).is_err());
+
+        // Setting bits on a string works on its bytes: "`" is 0x60
+        run(&mut storage, &["set", "s", "`"]).unwrap();
+        run(&mut storage, &["setbit", "s", "7", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["get", "s"]),
+            Ok(RESP::BulkString("a".into()))
+        );
+        run(&mut storage, &["del", "b", "s"]).unwrap();
+        assert_eq!(storage.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_bitcount_and_bitpos() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "s", "foobar"]).unwrap();
+        for (args, expected) in [
+            (vec![], 26),
+            (vec!["0", "0"], 4),
+            (vec!["1", "1"], 6),
+            (vec!["1", "1", "BYTE"], 6),
+            (vec!["5", "30", "BIT"], 17),
+            (vec!["-2", "-1"], 7),
+            (vec!["3", "1"], 0),
+        ] {
+            let mut command = cmd(&["bitcount", "s"]);
+            command.extend(args.iter().map(|s| CommandArg::from(*s)));
+            assert_eq!(
+                storage.process_command(&command),
+                Ok(RESP::Integer(expected))
+            );
+        }
+        assert!(run(&mut storage, &["bitcount", "s", "0"]).is_err());
+
+        run(&mut storage, &["setbit", "p", "0", "1"]).unwrap();
+        run(&mut storage, &["setbit", "p", "1", "1"]).unwrap();
+        run(&mut storage, &["setbit", "p", "20", "1"]).unwrap();
+        for (args, expected) in [
+            (vec!["0"], 2),
+            (vec!["1"], 0),
+            (vec!["1", "1"], 20),
+            (vec!["1", "2", "-1", "BIT"], 20),
+            (vec!["0", "0", "0"], 2),
+            (vec!["1", "0", "1", "BIT"], 0),
+            (vec!["1", "3"], -1),
+        ] {
+            let mut command = cmd(&["bitpos", "p"]);
+            command.extend(args.iter().map(|s| CommandArg::from(*s)));
+            assert_eq!(
+                storage.process_command(&command),
+                Ok(RESP::Integer(expected)),
+                "{:?}",
+                args
+            );
+        }
+        // Clear bits past the end are only found without an explicit end
+        run(&mut storage, &["set", "ones", "\u{7f}"]).unwrap();
+        run(&mut storage, &["setbit", "ones", "0", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bitpos", "ones", "0"]),
+            Ok(RESP::Integer(8))
+        );
+        assert_eq!(
+            run(&mut storage, &["bitpos", "ones", "0", "0", "-1"]),
+            Ok(RESP::Integer(-1))
+        );
+        assert_eq!(
+            run(&mut storage, &["bitpos", "missing", "0"]),
+            Ok(RESP::Integer(0))
+        );
+        assert!(run(&mut storage, &["bitpos", "p", "2"]).is_err());
+    }
+
+    #[test]
+    fn test_bitop() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "a", "abc"]).unwrap();
+        run(&mut storage, &["set", "b", "a"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bitop", "and", "dest", "a", "b"]),
+            Ok(RESP::Integer(3))
+        );
+        assert_eq!(
+            run(&mut storage, &["get", "dest"]),
+            Ok(RESP::BulkString("a\0\0".into()))
+        );
+        run(
+            &mut storage,
+            &["bitop", "diff", "dest", "a", "b", "missing"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["get", "dest"]),
+            Ok(RESP::BulkString("\0bc".into()))
+        );
+        assert!(run(&mut storage, &["bitop", "not", "dest", "a", "b"]).is_err());
+        assert!(run(&mut storage, &["bitop", "diff", "dest", "a"]).is_err());
+
+        // NOT leaves bytes that aren't UTF-8, which bit commands still read
+        run(&mut storage, &["bitop", "not", "dest", "b"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bitcount", "dest"]),
+            Ok(RESP::Integer(5))
+        );
+
+        // Only missing keys make an empty result, which deletes the target
+        assert_eq!(
+            run(&mut storage, &["bitop", "or", "dest", "missing"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["get", "dest"]), Ok(RESP::Null));
+        run(&mut storage, &["rpush", "l", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bitop", "or", "dest", "a", "l"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_bitfield() {
+        let mut storage = Storage::new();
+        let output = run(
+            &mut storage,
+            &[
+                "bitfield", "b", "set", "u8", "#1", "200", "incrby", "u8", "8", "100", "get", "i8",
+                "8",
+            ],
+        );
+        assert_eq!(
+            output,
+            Ok(RESP::Array(vec![
+                RESP::Integer(0),
+                RESP::Integer(44),
+                RESP::Integer(44)
+            ]))
+        );
+        let output = run(
+            &mut storage,
+            &[
+                "bitfield", "b", "overflow", "sat", "incrby", "u8", "8", "250", "overflow", "fail",
+                "incrby", "u8", "8", "1", "incrby", "i64", "16", "-1",
+            ],
+        );
+        assert_eq!(
+            output,
+            Ok(RESP::Array(vec![
+                RESP::Integer(255),
+                RESP::Null,
+                RESP::Integer(-1)
+            ]))
+        );
+        assert_eq!(run(&mut storage, &["bitcount", "b"]), Ok(RESP::Integer(72)));
+
+        // The same bytes GET and SET see
+        run(&mut storage, &["set", "s", "A"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bitfield_ro", "s", "get", "u8", "0"]),
+            Ok(RESP::Array(vec![RESP::Integer(65)]))
+        );
+        run(&mut storage, &["bitfield", "s", "set", "u8", "0", "66"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["get", "s"]),
+            Ok(RESP::BulkString("B".into()))
+        );
+
+        // Reads don't create the key
+        assert_eq!(
+            run(&mut storage, &["bitfield", "none", "get", "u4", "0"]),
+            Ok(RESP::Array(vec![RESP::Integer(0)]))
+        );
+        assert_eq!(run(&mut storage, &["get", "none"]), Ok(RESP::Null));
+
+        for bad in [
+            cmd(&["bitfield", "b", "get", "u64", "0"]),
+            cmd(&["bitfield", "b", "get", "u8"]),
+            cmd(&["bitfield", "b", "get", "u8", "-1"]),
+            cmd(&["bitfield", "b", "overflow", "maybe"]),
+            cmd(&["bitfield", "b", "set", "u8", "0", "x"]),
+            cmd(&["bitfield_ro", "b", "set", "u8", "0", "1"]),
+        ] {
+            assert!(storage.process_command(&bad).is_err(), "{:?}", bad);
+        }
+        // Nothing ran from the command that failed to parse
+        assert!(
+            run(
+                &mut storage,
+                &["bitfield", "s", "set", "u8", "0", "0", "get", "u99", "0"]
+            )
+            .is_err()
+        );
+        assert_eq!(
+            run(&mut storage, &["get", "s"]),
+            Ok(RESP::BulkString("B".into()))
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use std::time::Instant;
+
+use rand::{Rng, RngExt};
+
+use super::result::{StorageError, StorageResult};
+use super::{Storage, StorageEntry};
+use crate::pubsub::notify;
+
+/// Starting counter for new keys, so they aren't evicted before they
+/// get a chance to be accessed.
+pub const LFU_INIT_VAL: u8 = 5;
+/// Higher values make the counter saturate more slowly.
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// Minutes of inactivity per decrement of the counter.
+const LFU_DECAY_TIME: u64 = 1;
+/// Best candidates kept between eviction rounds.
+const EVICTION_POOL_SIZE: usize = 16;
+
+pub const DEFAULT_MAXMEMORY_SAMPLES: usize = 5;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EvictionPolicy {
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn parse(name: &str) -> Option<EvictionPolicy> {
+        match name.to_lowercase().as_str() {
+            "noeviction" => Some(EvictionPolicy::NoEviction),
+            "allkeys-lru" => Some(EvictionPolicy::AllKeysLru),
+            "volatile-lru" => Some(EvictionPolicy::VolatileLru),
+            "allkeys-lfu" => Some(EvictionPolicy::AllKeysLfu),
+            "volatile-lfu" => Some(EvictionPolicy::VolatileLfu),
+            "allkeys-random" => Some(EvictionPolicy::AllKeysRandom),
+            "volatile-random" => Some(EvictionPolicy::VolatileRandom),
+            "volatile-ttl" => Some(EvictionPolicy::VolatileTtl),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::VolatileLfu => "volatile-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileRandom => "volatile-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// Whether only keys with a TTL are candidates for eviction.
+    fn is_volatile(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        )
+    }
+
+    pub fn is_lfu(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu
+        )
+    }
+}
+
+/// Candidates sorted by ascending score, so the best one is at the end.
+/// Like Redis, the pool survives between calls, which makes the sampled
+/// approximation converge towards true LRU/LFU over time.
+#[derive(Default)]
+pub struct EvictionPool {
+    candidates: Vec<(u64, String)>,
+}
+
+impl EvictionPool {
+    fn insert(&mut self, score: u64, key: &str) {
+        if self.candidates.iter().any(|(_, k)| k == key) {
+            return;
+        }
+        let full = self.candidates.len() >= EVICTION_POOL_SIZE;
+        if full && score <= self.candidates[0].0 {
+            return;
+        }
+        let position = self.candidates.partition_point(|(s, _)| *s < score);
+        self.candidates.insert(position, (score, key.to_string()));
+        if self.candidates.len() > EVICTION_POOL_SIZE {
+            self.candidates.remove(0);
+        }
+    }
+
+    fn pop(&mut self) -> Option<String> {
+        self.candidates.pop().map(|(_, k)| k)
+    }
+
+    pub fn clear(&mut self) {
+        self.candidates.clear();
+    }
+}
+
+impl StorageEntry {
+    /// Records an access for both the LRU clock and the LFU counter.
+    pub(super) fn touch<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let counter = self.lfu_counter();
+        self.lfu = lfu_log_incr(counter, rng);
+        self.lru = Instant::now();
+    }
+
+    /// The LFU counter after applying decay for the time since last access.
+    pub fn lfu_counter(&self) -> u8 {
+        let minutes = self.lru.elapsed().as_secs() / 60;
+        let periods = minutes / LFU_DECAY_TIME;
+        self.lfu.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+}
+
+/// Logarithmic counter increment: the higher the counter, the less
+/// likely an access is to bump it.
+fn lfu_log_incr<R: Rng + ?Sized>(counter: u8, rng: &mut R) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+    if rng.random::<f64>() < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+impl Storage {
+    /// Evicts keys according to the policy until used memory drops under
+    /// `maxmemory`. Fails with `OutOfMemory` when nothing can be evicted.
+    pub(super) fn free_memory_if_needed(&mut self) -> StorageResult<()> {
+        if self.maxmemory == 0 {
+            return Ok(());
+        }
+        while self.used_memory > self.maxmemory {
+            if !self.evict_one() {
+                return Err(StorageError::OutOfMemory);
+            }
+        }
+        Ok(())
+    }
+
+    /// Evicts the policy's best candidate, if it has one.
+    pub(super) fn evict_one(&mut self) -> bool {
+        let key = match self.policy {
+            EvictionPolicy::NoEviction => None,
+            EvictionPolicy::AllKeysRandom => self.store.random_key(&mut self.rng).cloned(),
+            EvictionPolicy::VolatileRandom => self.expires.random_key(&mut self.rng).cloned(),
+            _ => self.pool_candidate(),
+        };
+        match key {
+            Some(key) => {
+                self.remove(&key);
+                self.stats.evicted_keys += 1;
+                self.propagate_del(&key);
+                self.notify(notify::EVICTED, "evicted", &key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pool_candidate(&mut self) -> Option<String> {
+        let volatile = self.policy.is_volatile();
+        for _ in 0..self.maxmemory_samples {
+            let key = if volatile {
+                self.expires.random_key(&mut self.rng)
+            } else {
+                self.store.random_key(&mut self.rng)
+            };
+            let Some(key) = key else { break };
+            let Some(score) = self.eviction_score(key) else {
+                continue;
+            };
+            self.eviction_pool.insert(score, key);
+        }
+        // Pool entries may be stale: the key could have been deleted or
+        // lost its TTL since it was sampled.
+        while let Some(key) = self.eviction_pool.pop() {
+            let exists = if volatile {
+                self.expires.contains_key(&key)
+            } else {
+                self.store.contains_key(&key)
+            };
+            if exists {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Higher scores are better eviction candidates.
+    fn eviction_score(&self, key: &str) -> Option<u64> {
+        match self.policy {
+            EvictionPolicy::VolatileTtl => self.expires.get(key).map(|at| u64::MAX - at),
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => self
+                .store
+                .get(key)
+                .map(|entry| (u8::MAX - entry.lfu_counter()) as u64),
+            _ => self
+                .store
+                .get(key)
+                .map(|entry| entry.lru.elapsed().as_millis() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn test_parse_policy() {
+        for name in [
+            "noeviction",
+            "allkeys-lru",
+            "volatile-lru",
+            "allkeys-lfu",
+            "volatile-lfu",
+            "allkeys-random",
+            "volatile-random",
+            "volatile-ttl",
+        ] {
+            assert_eq!(EvictionPolicy::parse(name).unwrap().as_str(), name);
+        }
+        assert_eq!(
+            EvictionPolicy::parse("ALLKEYS-LRU"),
+            Some(EvictionPolicy::AllKeysLru)
+        );
+        assert_eq!(EvictionPolicy::parse("lru"), None);
+    }
+
+    #[test]
+    fn test_pool_keeps_best_candidates() {
+        let mut pool = EvictionPool::default();
+        for n in 0..100 {
+            pool.insert(n, &format!("key{}", n));
+        }
+        pool.insert(99, "key99");
+        assert_eq!(pool.candidates.len(), EVICTION_POOL_SIZE);
+        assert_eq!(pool.pop(), Some("key99".to_string()));
+        assert_eq!(pool.pop(), Some("key98".to_string()));
+    }
+
+    #[test]
+    fn test_lfu_log_incr_saturates() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut counter = LFU_INIT_VAL;
+        for _ in 0..1000 {
+            counter = lfu_log_incr(counter, &mut rng);
+        }
+        assert!(counter > LFU_INIT_VAL);
+        // With a log factor of 10, a thousand hits is nowhere near saturation
+        assert!(counter < 50);
+    }
+}
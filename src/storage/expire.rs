@@ -0,0 +1,386 @@
+use super::memory::EXPIRE_OVERHEAD;
+use super::result::{StorageError, StorageResult, parse_integer};
+use super::{PrimitiveStorageValue, Storage, StorageValue, now_ms};
+use crate::command::CommandArg;
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+/// What a command takes its times in.
+#[derive(Clone, Copy)]
+pub(super) enum Unit {
+    Seconds,
+    Milliseconds,
+}
+
+impl Unit {
+    fn to_ms(self, time: i64) -> Option<i64> {
+        match self {
+            Unit::Seconds => time.checked_mul(1000),
+            Unit::Milliseconds => Some(time),
+        }
+    }
+}
+
+/// The NX, XX, GT and LT options of EXPIRE and friends. A key without a
+/// TTL counts as expiring never, so GT never applies to it and LT always
+/// does.
+#[derive(Default)]
+struct Conditions {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+}
+
+impl Conditions {
+    fn parse(options: &[CommandArg]) -> StorageResult<Self> {
+        let mut conditions = Conditions::default();
+        for option in options {
+            match option.to_uppercase().as_str() {
+                "NX" => conditions.nx = true,
+                "XX" => conditions.xx = true,
+                "GT" => conditions.gt = true,
+                "LT" => conditions.lt = true,
+                _ => {
+                    return Err(StorageError::syntax(&format!(
+                        "Unsupported option {}",
+                        option
+                    )));
+                }
+            }
+        }
+        if conditions.nx && (conditions.xx || conditions.gt || conditions.lt) {
+            return Err(StorageError::syntax(
+                "NX and XX, GT or LT options at the same time are not compatible",
+            ));
+        }
+        if conditions.gt && conditions.lt {
+            return Err(StorageError::syntax(
+                "GT and LT options at the same time are not compatible",
+            ));
+        }
+        Ok(conditions)
+    }
+
+    /// Whether a key expiring at `current` may be given `when` instead.
+    fn allow(&self, current: Option<u64>, when: i64) -> bool {
+        match current {
+            None => !self.xx && !self.gt,
+            Some(current) => {
+                let current = current as i64;
+                let refused =
+                    self.nx || (self.gt && when <= current) || (self.lt && when >= current);
+                !refused
+            }
+        }
+    }
+}
+
+fn invalid_expire_time(command: &CommandArg) -> StorageError {
+    StorageError::syntax(&format!(
+        "invalid expire time in '{}' command",
+        command.to_lowercase()
+    ))
+}
+
+impl Storage {
+    /// EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT key time [NX | XX | GT | LT]
+    pub(super) fn command_expire(
+        &mut self,
+        command: &[CommandArg],
+        unit: Unit,
+        absolute: bool,
+    ) -> StorageResult<RESP> {
+        let time: i64 = parse_integer(&command[2])?;
+        let conditions = Conditions::parse(&command[3..])?;
+        let when = unit
+            .to_ms(time)
+            .and_then(|ms| {
+                if absolute {
+                    Some(ms)
+                } else {
+                    ms.checked_add(now_ms() as i64)
+                }
+            })
+            .ok_or_else(|| invalid_expire_time(&command[0]))?;
+        let key = command[1].as_str();
+        if !self.contains(key) || !conditions.allow(self.expires.get(key).copied(), when) {
+            self.unchanged = true;
+            return Ok(RESP::Integer(0));
+        }
+        if when <= now_ms() as i64 {
+            self.remove(key);
+            self.notify(notify::GENERIC, "del", key);
+            self.propagate_as = Some(vec!["DEL".into(), command[1].clone()]);
+        } else {
+            self.expire_at(key, when as u64);
+            self.notify(notify::GENERIC, "expire", key);
+            // Replicas expire the key at the same moment however late
+            // they apply this
+            self.propagate_as = Some(vec![
+                "PEXPIREAT".into(),
+                command[1].clone(),
+                when.to_string().into(),
+            ]);
+        }
+        Ok(RESP::Integer(1))
+    }
+
+    /// TTL and PTTL key: -2 when the key doesn't exist and -1 when it has
+    /// no TTL.
+    pub(super) fn command_ttl(&mut self, command: &[CommandArg], unit: Unit) -> RESP {
+        let key = command[1].as_str();
+        if !self.contains(key) {
+            return RESP::Integer(-2);
+        }
+        let Some(at) = self.expires.get(key) else {
+            return RESP::Integer(-1);
+        };
+        let left = at.saturating_sub(now_ms()) as i64;
+        RESP::Integer(match unit {
+            Unit::Seconds => (left + 500) / 1000,
+            Unit::Milliseconds => left,
+        })
+    }
+
+    /// SETEX and PSETEX key time value
+    pub(super) fn command_setex(
+        &mut self,
+        command: &[CommandArg],
+        unit: Unit,
+    ) -> StorageResult<RESP> {
+        let time: i64 = parse_integer(&command[2])?;
+        let when = unit
+            .to_ms(time)
+            .filter(|ms| *ms > 0)
+            .and_then(|ms| ms.checked_add(now_ms() as i64))
+            .ok_or_else(|| invalid_expire_time(&command[0]))?;
+        let key = command[1].to_string();
+        let value = PrimitiveStorageValue::from(command[3].clone());
+        self.insert(key.clone(), StorageValue::Primitive(value));
+        self.expire_at(&key, when as u64);
+        self.notify(notify::STRING, "set", &key);
+        self.notify(notify::GENERIC, "expire", &key);
+        // SET takes no TTL here, so replicas get the value and then the
+        // absolute expire time EXPIRE would send them
+        self.propagate(&["SET".into(), command[1].clone(), command[3].clone()]);
+        self.propagate_as = Some(vec![
+            "PEXPIREAT".into(),
+            command[1].clone(),
+            when.to_string().into(),
+        ]);
+        Ok(RESP::SimpleString("OK".to_string()))
+    }
+
+    /// PERSIST key
+    pub(super) fn command_persist(&mut self, command: &[CommandArg]) -> RESP {
+        let key = command[1].as_str();
+        self.expire_if_needed(key);
+        if self.expires.remove(key).is_none() {
+            self.unchanged = true;
+            return RESP::Integer(0);
+        }
+        self.used_memory = self.used_memory.saturating_sub(EXPIRE_OVERHEAD + key.len());
+        self.notify(notify::GENERIC, "persist", key);
+        RESP::Integer(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::replication::Feed;
+    use crate::test_support::{bulk, cmd, run};
+
+    #[test]
+    fn test_expire_and_ttl() {
+        let mut storage = Storage::new();
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(-2)));
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "10"]),
+            Ok(RESP::Integer(0))
+        );
+        run(&mut storage, &["set", "k", "v"]).unwrap();
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(-1)));
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "10"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(10)));
+        assert_eq!(
+            run(&mut storage, &["pexpire", "k", "5000"]),
+            Ok(RESP::Integer(1))
+        );
+        let Ok(RESP::Integer(left)) = run(&mut storage, &["pttl", "k"]) else {
+            panic!("PTTL replies with an integer");
+        };
+        assert!(left > 4000 && left <= 5000);
+        let at = (now_ms() / 1000 + 100).to_string();
+        assert_eq!(
+            run(&mut storage, &["expireat", "k", &at]),
+            Ok(RESP::Integer(1))
+        );
+        assert!(matches!(
+            run(&mut storage, &["ttl", "k"]),
+            Ok(RESP::Integer(99..=100))
+        ));
+        // SET drops the TTL
+        run(&mut storage, &["set", "k", "v"]).unwrap();
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(-1)));
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "soon"]),
+            Err(StorageError::ValueNotInteger)
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", &i64::MAX.to_string()]),
+            Err(StorageError::CommandSyntaxError(
+                "invalid expire time in 'expire' command".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_expire_in_the_past_deletes() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "k", "v"]).unwrap();
+        let at = (now_ms() - 1).to_string();
+        assert_eq!(
+            run(&mut storage, &["pexpireat", "k", &at]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["get", "k"]), Ok(RESP::Null));
+        assert_eq!(storage.used_memory(), 0);
+        run(&mut storage, &["set", "k", "v"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "-1"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(-2)));
+    }
+
+    #[test]
+    fn test_expire_conditions() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "k", "v"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "100", "xx"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "100", "gt"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "100", "nx"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "200", "nx"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "50", "gt"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "200", "gt"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "300", "lt"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "150", "xx", "lt"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(150)));
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "10", "nx", "gt"]),
+            Err(StorageError::CommandSyntaxError(
+                "NX and XX, GT or LT options at the same time are not compatible".to_string()
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "10", "gt", "lt"]),
+            Err(StorageError::CommandSyntaxError(
+                "GT and LT options at the same time are not compatible".to_string()
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "10", "later"]),
+            Err(StorageError::CommandSyntaxError(
+                "Unsupported option later".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_setex_and_persist() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["setex", "k", "10", "v"]),
+            Ok(RESP::SimpleString("OK".to_string()))
+        );
+        assert_eq!(run(&mut storage, &["get", "k"]), Ok(bulk("v")));
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(10)));
+        let with_ttl = storage.used_memory();
+        assert_eq!(run(&mut storage, &["persist", "k"]), Ok(RESP::Integer(1)));
+        assert_eq!(run(&mut storage, &["persist", "k"]), Ok(RESP::Integer(0)));
+        assert_eq!(run(&mut storage, &["ttl", "k"]), Ok(RESP::Integer(-1)));
+        assert!(storage.used_memory() < with_ttl);
+        run(&mut storage, &["psetex", "k", "5000", "w"]).unwrap();
+        assert_eq!(run(&mut storage, &["get", "k"]), Ok(bulk("w")));
+        assert!(matches!(
+            run(&mut storage, &["pttl", "k"]),
+            Ok(RESP::Integer(4001..=5000))
+        ));
+        assert_eq!(
+            run(&mut storage, &["setex", "k", "0", "v"]),
+            Err(StorageError::CommandSyntaxError(
+                "invalid expire time in 'setex' command".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_relative_times_propagate_as_absolute() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "k", "v"]).unwrap();
+        let before = now_ms();
+        storage
+            .command_expire(&cmd(&["expire", "k", "10", "nx"]), Unit::Seconds, false)
+            .unwrap();
+        let rewritten = storage.propagate_as.take().unwrap();
+        assert_eq!(rewritten[0].as_str(), "PEXPIREAT");
+        let at: u64 = rewritten[2].parse().unwrap();
+        assert!(at >= before + 10_000 && at <= now_ms() + 10_000);
+        assert_eq!(rewritten.len(), 3);
+    }
+
+    #[test]
+    fn test_unchanged_keys_are_not_propagated() {
+        let mut storage = Storage::new();
+        let feed = Arc::new(Feed::new());
+        feed.start_propagating();
+        storage.set_feed(feed.clone());
+        run(&mut storage, &["set", "k", "v"]).unwrap();
+        let offset = feed.offset();
+        for refused in [
+            &["expire", "missing", "10"][..],
+            &["expire", "k", "10", "xx"],
+            &["persist", "k"],
+        ] {
+            assert_eq!(run(&mut storage, refused), Ok(RESP::Integer(0)));
+        }
+        assert_eq!(feed.offset(), offset);
+        assert_eq!(
+            run(&mut storage, &["expire", "k", "10"]),
+            Ok(RESP::Integer(1))
+        );
+        let propagated = String::from_utf8(feed.read_from(offset).unwrap()).unwrap();
+        assert!(propagated.starts_with("*3\r\n$9\r\nPEXPIREAT\r\n$1\r\nk\r\n"));
+    }
+}
@@ -0,0 +1,665 @@
+use super::result::{StorageError, StorageResult, parse_in};
+use super::{Storage, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::bloom::{self, ScalableBloom};
+use crate::ds::cuckoo::{self, CuckooFilter};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+const ITEM_EXISTS: &str = "item exists";
+const NOT_FOUND: &str = "not found";
+const BLOOM_FULL: &str = "non scaling filter is full";
+const CUCKOO_FULL: &str = "Filter is full";
+const BAD_CAPACITY: &str = "(capacity should be larger than 0)";
+
+fn integer(value: bool) -> RESP {
+    RESP::Integer(i64::from(value))
+}
+
+fn info(fields: Vec<(&str, RESP)>) -> RESP {
+    RESP::Array(
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| [RESP::SimpleString(name.to_string()), value])
+            .collect(),
+    )
+}
+
+/// CF.INSERT's options, ahead of its items.
+struct Insert<'a> {
+    capacity: u64,
+    no_create: bool,
+    items: &'a [CommandArg],
+}
+
+impl<'a> Insert<'a> {
+    fn parse(command: &'a [CommandArg]) -> StorageResult<Insert<'a>> {
+        let mut insert = Insert {
+            capacity: cuckoo::DEFAULT_CAPACITY,
+            no_create: false,
+            items: &[],
+        };
+        let mut i = 2;
+        while let Some(arg) = command.get(i) {
+            match arg.to_uppercase().as_str() {
+                "CAPACITY" => {
+                    insert.capacity = parse_in(command.get(i + 1), 1..=u64::MAX, BAD_CAPACITY)?;
+                    i += 1;
+                }
+                "NOCREATE" => insert.no_create = true,
+                "ITEMS" => {
+                    insert.items = &command[i + 1..];
+                    break;
+                }
+                _ => return Err(StorageError::syntax_error()),
+            }
+            i += 1;
+        }
+        if insert.items.is_empty() {
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
+        }
+        Ok(insert)
+    }
+}
+
+impl Storage {
+    fn read_bloom(&mut self, key: &str) -> StorageResult<Option<&ScalableBloom>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::Bloom(filter)) => Ok(Some(filter)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the Bloom filter at `key`, creating one with the
+    /// default error rate and capacity first if `create` is set, and keeps
+    /// `used_memory` in step with what it changes. None if there is no
+    /// filter to run on.
+    fn write_bloom<T>(
+        &mut self,
+        key: &str,
+        create: bool,
+        f: impl FnOnce(&mut ScalableBloom) -> T,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            if !create {
+                return Ok(None);
+            }
+            let filter = ScalableBloom::new(
+                bloom::DEFAULT_ERROR_RATE,
+                bloom::DEFAULT_CAPACITY,
+                bloom::DEFAULT_EXPANSION,
+            );
+            self.insert(key.to_string(), StorageValue::Bloom(filter));
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::Bloom(filter) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = filter.memory_usage();
+        let result = f(filter);
+        self.used_memory = (self.used_memory + filter.memory_usage()).saturating_sub(before);
+        Ok(Some(result))
+    }
+
+    fn read_cuckoo(&mut self, key: &str) -> StorageResult<Option<&CuckooFilter>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::Cuckoo(filter)) => Ok(Some(filter)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `write_bloom`, creating a filter for `create` items with the
+    /// default options if there is none.
+    fn write_cuckoo<T>(
+        &mut self,
+        key: &str,
+        create: Option<u64>,
+        f: impl FnOnce(&mut CuckooFilter) -> T,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            let Some(capacity) = create else {
+                return Ok(None);
+            };
+            let filter = CuckooFilter::new(
+                capacity,
+                cuckoo::DEFAULT_BUCKET_SIZE,
+                cuckoo::DEFAULT_MAX_ITERATIONS,
+                cuckoo::DEFAULT_EXPANSION,
+            );
+            self.insert(key.to_string(), StorageValue::Cuckoo(filter));
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::Cuckoo(filter) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = filter.memory_usage();
+        let result = f(filter);
+        self.used_memory = (self.used_memory + filter.memory_usage()).saturating_sub(before);
+        Ok(Some(result))
+    }
+
+    /// BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]
+    pub(super) fn command_bf_reserve(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let error_rate = command[2]
+            .parse::<f64>()
+            .map_err(|_| StorageError::syntax("bad error rate"))?;
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err(StorageError::syntax("(0 < error rate range < 1)"));
+        }
+        let capacity = parse_in(command.get(3), 1..=u64::MAX, BAD_CAPACITY)?;
+        let (mut expansion, mut non_scaling) = (None, false);
+        let mut i = 4;
+        while let Some(arg) = command.get(i) {
+            match arg.to_uppercase().as_str() {
+                "EXPANSION" => {
+                    let message = "expansion should be greater or equal to 1";
+                    expansion = Some(parse_in(command.get(i + 1), 1..=u32::MAX, message)?);
+                    i += 1;
+                }
+                "NONSCALING" => non_scaling = true,
+                _ => return Err(StorageError::syntax_error()),
+            }
+            i += 1;
+        }
+        if non_scaling && expansion.is_some() {
+            return Err(StorageError::syntax("Non scaling filters cannot expand"));
+        }
+        let key = &command[1];
+        if self.lookup(key).is_some() {
+            return Err(StorageError::syntax(ITEM_EXISTS));
+        }
+        let expansion = match non_scaling {
+            true => 0,
+            false => expansion.unwrap_or(bloom::DEFAULT_EXPANSION),
+        };
+        let filter = ScalableBloom::new(error_rate, capacity, expansion);
+        self.insert(key.to_string(), StorageValue::Bloom(filter));
+        self.notify(notify::MODULE, "bf.reserve", key);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// Adds `items` to the filter at `key`, creating it if needed: for
+    /// each, whether it was new, or None if the filter was full.
+    fn bf_add(&mut self, key: &str, items: &[CommandArg]) -> StorageResult<Vec<Option<bool>>> {
+        let added = self
+            .write_bloom(key, true, |filter| {
+                items
+                    .iter()
+                    .map(|item| filter.add(item.as_bytes()))
+                    .collect::<Vec<_>>()
+            })?
+            .expect("the filter is created");
+        if added.contains(&Some(true)) {
+            self.notify(notify::MODULE, "bf.add", key);
+        }
+        Ok(added)
+    }
+
+    /// BF.ADD key item
+    pub(super) fn command_bf_add(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        match self.bf_add(&command[1], &command[2..])?[..] {
+            [Some(added)] => Ok(integer(added)),
+            _ => Err(StorageError::syntax(BLOOM_FULL)),
+        }
+    }
+
+    /// BF.MADD key item [item ...]
+    pub(super) fn command_bf_madd(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let added = self.bf_add(&command[1], &command[2..])?;
+        Ok(RESP::Array(
+            added
+                .into_iter()
+                .map(|added| added.map_or(RESP::Error(format!("ERR {}", BLOOM_FULL)), integer))
+                .collect(),
+        ))
+    }
+
+    /// BF.EXISTS key item, and BF.MEXISTS key item [item ...] with
+    /// `multiple` set
+    pub(super) fn command_bf_exists(
+        &mut self,
+        command: &[CommandArg],
+        multiple: bool,
+    ) -> StorageResult<RESP> {
+        let filter = self.read_bloom(&command[1])?;
+        let mut found = command[2..]
+            .iter()
+            .map(|item| integer(filter.is_some_and(|filter| filter.contains(item.as_bytes()))));
+        match multiple {
+            true => Ok(RESP::Array(found.collect())),
+            false => Ok(found.next().expect("arity checked")),
+        }
+    }
+
+    /// BF.INFO key [CAPACITY | SIZE | FILTERS | ITEMS | EXPANSION]
+    pub(super) fn command_bf_info(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if command.len() > 3 {
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
+        }
+        let filter = self
+            .read_bloom(&command[1])?
+            .ok_or_else(|| StorageError::syntax(NOT_FOUND))?;
+        let expansion = filter
+            .expansion()
+            .map_or(RESP::Null, |expansion| RESP::Integer(i64::from(expansion)));
+        let fields = vec![
+            ("Capacity", RESP::Integer(filter.capacity() as i64)),
+            ("Size", RESP::Integer(filter.memory_usage() as i64)),
+            ("Number of filters", RESP::Integer(filter.layers() as i64)),
+            (
+                "Number of items inserted",
+                RESP::Integer(filter.items() as i64),
+            ),
+            ("Expansion rate", expansion),
+        ];
+        let Some(wanted) = command.get(2) else {
+            return Ok(info(fields));
+        };
+        let index = ["CAPACITY", "SIZE", "FILTERS", "ITEMS", "EXPANSION"]
+            .iter()
+            .position(|name| wanted.eq_ignore_ascii_case(name))
+            .ok_or_else(|| StorageError::syntax("Invalid information value"))?;
+        let (_, value) = fields.into_iter().nth(index).expect("a field per name");
+        Ok(RESP::Array(vec![value]))
+    }
+
+    /// CF.RESERVE key capacity [BUCKETSIZE size] [MAXITERATIONS n]
+    /// [EXPANSION expansion]
+    pub(super) fn command_cf_reserve(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let capacity = parse_in(command.get(2), 1..=u64::MAX, BAD_CAPACITY)?;
+        let mut bucket_size = cuckoo::DEFAULT_BUCKET_SIZE;
+        let mut max_iterations = cuckoo::DEFAULT_MAX_ITERATIONS;
+        let mut expansion = cuckoo::DEFAULT_EXPANSION;
+        let mut i = 3;
+        while let Some(arg) = command.get(i) {
+            let value = command.get(i + 1);
+            match arg.to_uppercase().as_str() {
+                "BUCKETSIZE" => {
+                    let message = "Bucket size must be between 1 and 255";
+                    bucket_size = parse_in(value, 1..=u8::MAX, message)?;
+                }
+                "MAXITERATIONS" => {
+                    let message = "Max iterations must be between 1 and 65535";
+                    max_iterations = parse_in(value, 1..=u16::MAX, message)?;
+                }
+                "EXPANSION" => {
+                    let message = "Expansion must be between 0 and 32768";
+                    expansion = parse_in(value, 0..=32768, message)?;
+                }
+                _ => return Err(StorageError::syntax_error()),
+            }
+            i += 2;
+        }
+        let key = &command[1];
+        if self.lookup(key).is_some() {
+            return Err(StorageError::syntax(ITEM_EXISTS));
+        }
+        let filter = CuckooFilter::new(capacity, bucket_size, max_iterations, expansion);
+        self.insert(key.to_string(), StorageValue::Cuckoo(filter));
+        self.notify(notify::MODULE, "cf.reserve", key);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// Adds `items` to the filter at `key`, creating it for `create` items
+    /// if needed, and skipping those already in if `nx` is set: for each,
+    /// whether it was added, or None if the filter was full. None overall
+    /// if there is no filter and none was created.
+    fn cf_add(
+        &mut self,
+        key: &str,
+        create: Option<u64>,
+        items: &[CommandArg],
+        nx: bool,
+    ) -> StorageResult<Option<Vec<Option<bool>>>> {
+        let added = self.write_cuckoo(key, create, |filter| {
+            items
+                .iter()
+                .map(|item| {
+                    let item = item.as_bytes();
+                    if nx && filter.contains(item) {
+                        Some(false)
+                    } else {
+                        filter.add(item).then_some(true)
+                    }
+                })
+                .collect::<Vec<_>>()
+        })?;
+        if added
+            .as_ref()
+            .is_some_and(|added| added.contains(&Some(true)))
+        {
+            self.notify(notify::MODULE, "cf.add", key);
+        }
+        Ok(added)
+    }
+
+    /// CF.ADD key item, and CF.ADDNX key item with `nx` set
+    pub(super) fn command_cf_add(
+        &mut self,
+        command: &[CommandArg],
+        nx: bool,
+    ) -> StorageResult<RESP> {
+        let create = Some(cuckoo::DEFAULT_CAPACITY);
+        match self
+            .cf_add(&command[1], create, &command[2..], nx)?
+            .as_deref()
+        {
+            Some([Some(added)]) => Ok(integer(*added)),
+            _ => Err(StorageError::syntax(CUCKOO_FULL)),
+        }
+    }
+
+    /// CF.INSERT key [CAPACITY capacity] [NOCREATE] ITEMS item [item ...],
+    /// and CF.INSERTNX with `nx` set. Items that didn't fit are -1.
+    pub(super) fn command_cf_insert(
+        &mut self,
+        command: &[CommandArg],
+        nx: bool,
+    ) -> StorageResult<RESP> {
+        let insert = Insert::parse(command)?;
+        let create = (!insert.no_create).then_some(insert.capacity);
+        let added = self
+            .cf_add(&command[1], create, insert.items, nx)?
+            .ok_or_else(|| StorageError::syntax(NOT_FOUND))?;
+        Ok(RESP::Array(
+            added
+                .into_iter()
+                .map(|added| added.map_or(RESP::Integer(-1), integer))
+                .collect(),
+        ))
+    }
+
+    /// CF.EXISTS key item, and CF.MEXISTS key item [item ...] with
+    /// `multiple` set
+    pub(super) fn command_cf_exists(
+        &mut self,
+        command: &[CommandArg],
+        multiple: bool,
+    ) -> StorageResult<RESP> {
+        let filter = self.read_cuckoo(&command[1])?;
+        let mut found = command[2..]
+            .iter()
+            .map(|item| integer(filter.is_some_and(|filter| filter.contains(item.as_bytes()))));
+        match multiple {
+            true => Ok(RESP::Array(found.collect())),
+            false => Ok(found.next().expect("arity checked")),
+        }
+    }
+
+    /// CF.DEL key item
+    pub(super) fn command_cf_del(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let key = &command[1];
+        let deleted = self
+            .write_cuckoo(key, None, |filter| filter.delete(command[2].as_bytes()))?
+            .ok_or_else(|| StorageError::syntax("Not found"))?;
+        if deleted {
+            self.notify(notify::MODULE, "cf.del", key);
+        }
+        Ok(integer(deleted))
+    }
+
+    /// CF.COUNT key item
+    pub(super) fn command_cf_count(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let count = self
+            .read_cuckoo(&command[1])?
+            .map_or(0, |filter| filter.count(command[2].as_bytes()));
+        Ok(RESP::Integer(count as i64))
+    }
+
+    /// CF.INFO key
+    pub(super) fn command_cf_info(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let filter = self
+            .read_cuckoo(&command[1])?
+            .ok_or_else(|| StorageError::syntax(NOT_FOUND))?;
+        Ok(info(vec![
+            ("Size", RESP::Integer(filter.memory_usage() as i64)),
+            ("Number of buckets", RESP::Integer(filter.buckets() as i64)),
+            ("Number of filters", RESP::Integer(filter.tables() as i64)),
+            (
+                "Number of items inserted",
+                RESP::Integer(filter.items() as i64),
+            ),
+            (
+                "Number of items deleted",
+                RESP::Integer(filter.deleted() as i64),
+            ),
+            (
+                "Bucket size",
+                RESP::Integer(i64::from(filter.bucket_size())),
+            ),
+            (
+                "Expansion rate",
+                RESP::Integer(i64::from(filter.expansion())),
+            ),
+            (
+                "Max iterations",
+                RESP::Integer(i64::from(filter.max_iterations())),
+            ),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{cmd, run};
+
+    fn ints(values: &[i64]) -> RESP {
+        RESP::Array(values.iter().map(|n| RESP::Integer(*n)).collect())
+    }
+
+    #[test]
+    fn test_bf_add_and_exists() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["bf.add", "bf", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.add", "bf", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.madd", "bf", "a", "b", "c"]),
+            Ok(ints(&[0, 1, 1]))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.exists", "bf", "b"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.mexists", "bf", "a", "z"]),
+            Ok(ints(&[1, 0]))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.exists", "missing", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.info", "bf", "items"]),
+            Ok(ints(&[3]))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.info", "bf", "capacity"]),
+            Ok(ints(&[100]))
+        );
+        run(&mut storage, &["set", "s", "v"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["bf.add", "s", "a"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_bf_reserve() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["bf.reserve", "bf", "0.01", "2", "NONSCALING"]
+            ),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.reserve", "bf", "0.01", "2"]),
+            Err(StorageError::syntax(ITEM_EXISTS))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.madd", "bf", "a", "b", "c"]),
+            Ok(RESP::Array(vec![
+                RESP::Integer(1),
+                RESP::Integer(1),
+                RESP::Error(format!("ERR {}", BLOOM_FULL)),
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.add", "bf", "c"]),
+            Err(StorageError::syntax(BLOOM_FULL))
+        );
+        assert_eq!(
+            run(&mut storage, &["bf.info", "bf", "expansion"]),
+            Ok(RESP::Array(vec![RESP::Null]))
+        );
+        for (args, message) in [
+            (&["2", "10"][..], "(0 < error rate range < 1)"),
+            (&["x", "10"][..], "bad error rate"),
+            (&["0.1", "0"][..], BAD_CAPACITY),
+            (
+                &["0.1", "10", "EXPANSION", "0"][..],
+                "expansion should be greater or equal to 1",
+            ),
+            (
+                &["0.1", "10", "EXPANSION", "2", "NONSCALING"][..],
+                "Non scaling filters cannot expand",
+            ),
+        ] {
+            let mut command = cmd(&["bf.reserve", "other"]);
+            command.extend(cmd(args));
+            assert_eq!(
+                storage.process_command(&command),
+                Err(StorageError::syntax(message)),
+                "{:?}",
+                args
+            );
+        }
+        assert_eq!(
+            run(&mut storage, &["bf.info", "missing"]),
+            Err(StorageError::syntax(NOT_FOUND))
+        );
+    }
+
+    #[test]
+    fn test_bf_info() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["bf.reserve", "bf", "0.01", "10", "EXPANSION", "4"],
+        )
+        .unwrap();
+        for i in 0..15 {
+            run(&mut storage, &["bf.add", "bf", &i.to_string()]).unwrap();
+        }
+        let RESP::Array(fields) = run(&mut storage, &["bf.info", "bf"]).unwrap() else {
+            panic!("BF.INFO replies with an array");
+        };
+        assert_eq!(fields[0], RESP::SimpleString(String::from("Capacity")));
+        assert_eq!(fields[1], RESP::Integer(50));
+        assert_eq!(fields[5], RESP::Integer(2));
+        assert_eq!(fields[9], RESP::Integer(4));
+    }
+
+    #[test]
+    fn test_cf_commands() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["cf.reserve", "cf", "100", "BUCKETSIZE", "4"]
+            ),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.add", "cf", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.add", "cf", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.addnx", "cf", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.count", "cf", "a"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.del", "cf", "a"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.mexists", "cf", "a", "b"]),
+            Ok(ints(&[1, 0]))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.insertnx", "cf", "ITEMS", "a", "b"]),
+            Ok(ints(&[0, 1]))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.del", "missing", "a"]),
+            Err(StorageError::syntax("Not found"))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.exists", "missing", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        let RESP::Array(fields) = run(&mut storage, &["cf.info", "cf"]).unwrap() else {
+            panic!("CF.INFO replies with an array");
+        };
+        // 100 items in buckets of 4 round up to 32 buckets
+        assert_eq!(fields[3], RESP::Integer(32));
+        assert_eq!(fields[7], RESP::Integer(2));
+        assert_eq!(fields[9], RESP::Integer(1));
+        assert_eq!(fields[11], RESP::Integer(4));
+    }
+
+    #[test]
+    fn test_cf_insert() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["cf.insert", "cf", "NOCREATE", "ITEMS", "a"]),
+            Err(StorageError::syntax(NOT_FOUND))
+        );
+        assert_eq!(
+            run(&mut storage, &["cf.insert", "cf", "CAPACITY", "1", "ITEMS"]),
+            Err(StorageError::WrongArity(String::from("cf.insert")))
+        );
+        run(
+            &mut storage,
+            &["cf.reserve", "cf", "2", "BUCKETSIZE", "1", "EXPANSION", "0"],
+        )
+        .unwrap();
+        let RESP::Array(added) = run(
+            &mut storage,
+            &["cf.insert", "cf", "ITEMS", "a", "b", "c", "d", "e"],
+        )
+        .unwrap() else {
+            panic!("CF.INSERT replies with an array");
+        };
+        // Two slots fit two items at most
+        let full = added.iter().filter(|r| **r == RESP::Integer(-1)).count();
+        assert!(full >= 3, "{:?}", added);
+        assert_eq!(
+            run(
+                &mut storage,
+                &["cf.reserve", "x", "10", "MAXITERATIONS", "0"]
+            ),
+            Err(StorageError::syntax(
+                "Max iterations must be between 1 and 65535"
+            ))
+        );
+    }
+}
@@ -0,0 +1,721 @@
+use super::result::{StorageError, StorageResult, parse_integer};
+use super::{Storage, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::geo::{self, Point, Shape, Unit};
+use crate::ds::sortedset::SortedSet;
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+const INVALID_FLOAT: &str = "value is not a valid float";
+const UNSUPPORTED_UNIT: &str = "unsupported unit provided. please use M, KM, FT, MI";
+
+fn parse_float(arg: &str) -> StorageResult<f64> {
+    arg.parse::<f64>()
+        .ok()
+        .filter(|value| !value.is_nan())
+        .ok_or_else(|| StorageError::syntax(INVALID_FLOAT))
+}
+
+fn parse_point(lon: &str, lat: &str) -> StorageResult<Point> {
+    let (lon, lat) = (parse_float(lon)?, parse_float(lat)?);
+    Point::new(lon, lat).ok_or_else(|| {
+        let message = format!("invalid longitude,latitude pair {:.6},{:.6}", lon, lat);
+        StorageError::syntax(&message)
+    })
+}
+
+fn parse_unit(arg: &str) -> StorageResult<Unit> {
+    Unit::parse(arg).ok_or_else(|| StorageError::syntax(UNSUPPORTED_UNIT))
+}
+
+/// Distances are replied with four decimals, in the unit asked for.
+fn format_distance(meters: f64, unit: Unit) -> RESP {
+    RESP::BulkString(format!("{:.4}", meters / unit.meters()).into())
+}
+
+fn format_point(point: Point) -> RESP {
+    RESP::Array(vec![
+        RESP::BulkString(point.lon.to_string().into()),
+        RESP::BulkString(point.lat.to_string().into()),
+    ])
+}
+
+/// Where a GEOSEARCH starts from.
+enum Origin {
+    Member(String),
+    Point(Point),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sort {
+    Asc,
+    Desc,
+}
+
+/// The options of GEOSEARCH and GEOSEARCHSTORE.
+struct Search {
+    origin: Origin,
+    shape: Shape,
+    unit: Unit,
+    sort: Option<Sort>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+    store_dist: bool,
+}
+
+impl Search {
+    /// Parses the options after the source key.
+    fn parse(command: &[CommandArg], args: &[CommandArg], store: bool) -> StorageResult<Search> {
+        let mut origin = None;
+        let mut area = None;
+        let mut search = Search {
+            origin: Origin::Point(Point { lon: 0.0, lat: 0.0 }),
+            shape: Shape::Radius(0.0),
+            unit: Unit::Meters,
+            sort: None,
+            count: None,
+            any: false,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+            store_dist: false,
+        };
+        let mut i = 0;
+        while i < args.len() {
+            let rest = &args[i + 1..];
+            match args[i].to_uppercase().as_str() {
+                "FROMMEMBER" if origin.is_none() && !rest.is_empty() => {
+                    origin = Some(Origin::Member(rest[0].to_string()));
+                    i += 1;
+                }
+                "FROMLONLAT" if origin.is_none() && rest.len() >= 2 => {
+                    origin = Some(Origin::Point(parse_point(&rest[0], &rest[1])?));
+                    i += 2;
+                }
+                "BYRADIUS" if area.is_none() && rest.len() >= 2 => {
+                    let radius = parse_float(&rest[0])?;
+                    if radius < 0.0 {
+                        return Err(StorageError::syntax("radius cannot be negative"));
+                    }
+                    area = Some((radius, None, parse_unit(&rest[1])?));
+                    i += 2;
+                }
+                "BYBOX" if area.is_none() && rest.len() >= 3 => {
+                    let width = parse_float(&rest[0])?;
+                    let height = parse_float(&rest[1])?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err(StorageError::syntax("height or width cannot be negative"));
+                    }
+                    area = Some((width, Some(height), parse_unit(&rest[2])?));
+                    i += 3;
+                }
+                "ASC" => search.sort = Some(Sort::Asc),
+                "DESC" => search.sort = Some(Sort::Desc),
+                "COUNT" if !rest.is_empty() => {
+                    let count: i64 = parse_integer(&rest[0])?;
+                    if count <= 0 {
+                        return Err(StorageError::syntax("COUNT must be > 0"));
+                    }
+                    search.count = Some(count as usize);
+                    i += 1;
+                    if rest
+                        .get(1)
+                        .is_some_and(|arg| arg.eq_ignore_ascii_case("ANY"))
+                    {
+                        search.any = true;
+                        i += 1;
+                    }
+                }
+                "WITHCOORD" if !store => search.with_coord = true,
+                "WITHDIST" if !store => search.with_dist = true,
+                "WITHHASH" if !store => search.with_hash = true,
+                "STOREDIST" if store => search.store_dist = true,
+                _ => return Err(StorageError::syntax_error()),
+            }
+            i += 1;
+        }
+        let name = command[0].to_uppercase();
+        let Some(origin) = origin else {
+            let message = format!(
+                "exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+                name
+            );
+            return Err(StorageError::syntax(&message));
+        };
+        let Some((width, height, unit)) = area else {
+            let message = format!(
+                "exactly one of BYRADIUS and BYBOX can be specified for {}",
+                name
+            );
+            return Err(StorageError::syntax(&message));
+        };
+        search.origin = origin;
+        search.unit = unit;
+        search.shape = match height {
+            None => Shape::Radius(width * unit.meters()),
+            Some(height) => Shape::Box(width * unit.meters(), height * unit.meters()),
+        };
+        Ok(search)
+    }
+}
+
+/// A member a search found, with its distance in meters.
+struct Found {
+    member: String,
+    score: f64,
+    point: Point,
+    distance: f64,
+}
+
+impl Storage {
+    fn read_sorted_set(&mut self, key: &str) -> StorageResult<Option<&SortedSet>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::SortedSet(set)) => Ok(Some(set)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the sorted set at `key`, creating an empty one first if
+    /// `create` is set, and keeps `used_memory` in step with what it
+    /// changes. None if there is no set to run on.
+    fn write_sorted_set<T>(
+        &mut self,
+        key: &str,
+        create: bool,
+        f: impl FnOnce(&mut SortedSet) -> T,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            if !create {
+                return Ok(None);
+            }
+            self.insert(key.to_string(), StorageValue::SortedSet(SortedSet::new()));
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::SortedSet(set) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = set.memory_usage();
+        let result = f(set);
+        self.used_memory = (self.used_memory + set.memory_usage()).saturating_sub(before);
+        Ok(Some(result))
+    }
+
+    /// The points of `members` in the set at `key`, None for the missing.
+    fn geo_points(&mut self, key: &str, members: &[CommandArg]) -> StorageResult<Vec<Option<u64>>> {
+        let set = self.read_sorted_set(key)?;
+        Ok(members
+            .iter()
+            .map(|member| {
+                set.and_then(|set| set.score(member))
+                    .map(|score| score as u64)
+            })
+            .collect())
+    }
+
+    /// GEOADD key [NX|XX] [CH] longitude latitude member [...]
+    pub(super) fn command_geoadd(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let (mut nx, mut xx, mut ch) = (false, false, false);
+        let mut i = 2;
+        while let Some(arg) = command.get(i) {
+            match arg.to_uppercase().as_str() {
+                "NX" => nx = true,
+                "XX" => xx = true,
+                "CH" => ch = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        let items = &command[i..];
+        if items.is_empty() || !items.len().is_multiple_of(3) {
+            return Err(StorageError::syntax_error());
+        }
+        if nx && xx {
+            return Err(StorageError::syntax(
+                "XX and NX options at the same time are not compatible",
+            ));
+        }
+        let mut points = Vec::with_capacity(items.len() / 3);
+        for item in items.chunks(3) {
+            let point = parse_point(&item[0], &item[1])?;
+            points.push((&item[2], geo::encode(point) as f64));
+        }
+        let key = &command[1];
+        let (added, changed) = self
+            .write_sorted_set(key, !xx, |set| {
+                let (mut added, mut changed) = (0, 0);
+                for (member, score) in points {
+                    match set.score(member) {
+                        Some(_) if nx => (),
+                        Some(previous) => {
+                            if previous != score {
+                                set.insert(member, score);
+                                changed += 1;
+                            }
+                        }
+                        None if xx => (),
+                        None => {
+                            set.insert(member, score);
+                            added += 1;
+                        }
+                    }
+                }
+                (added, changed)
+            })?
+            .unwrap_or((0, 0));
+        if added + changed > 0 {
+            self.notify(notify::ZSET, "zadd", key);
+        }
+        self.remove_if_empty_set(key);
+        Ok(RESP::Integer(if ch { added + changed } else { added }))
+    }
+
+    /// Removes the set at `key` if it has no members, like one GEOADD NX
+    /// created without adding anything to.
+    fn remove_if_empty_set(&mut self, key: &str) {
+        if let Some(StorageValue::SortedSet(set)) = self.store.get(key).map(|entry| &entry.value)
+            && set.is_empty()
+        {
+            self.remove(key);
+        }
+    }
+
+    /// GEODIST key member1 member2 [M|KM|FT|MI]
+    pub(super) fn command_geodist(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let unit = match &command[4..] {
+            [] => Unit::Meters,
+            [unit] => parse_unit(unit)?,
+            _ => return Err(StorageError::syntax_error()),
+        };
+        match self.geo_points(&command[1], &command[2..4])?[..] {
+            [Some(a), Some(b)] => Ok(format_distance(
+                geo::distance(geo::decode(a), geo::decode(b)),
+                unit,
+            )),
+            _ => Ok(RESP::Null),
+        }
+    }
+
+    /// GEOPOS key [member ...]
+    pub(super) fn command_geopos(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let points = self.geo_points(&command[1], &command[2..])?;
+        Ok(RESP::Array(
+            points
+                .into_iter()
+                .map(|hash| hash.map_or(RESP::Null, |hash| format_point(geo::decode(hash))))
+                .collect(),
+        ))
+    }
+
+    /// GEOHASH key [member ...]
+    pub(super) fn command_geohash(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let points = self.geo_points(&command[1], &command[2..])?;
+        Ok(RESP::Array(
+            points
+                .into_iter()
+                .map(|hash| {
+                    hash.map_or(RESP::Null, |hash| {
+                        RESP::BulkString(geo::geohash_string(hash).into())
+                    })
+                })
+                .collect(),
+        ))
+    }
+
+    /// GEOSEARCH key FROMMEMBER member|FROMLONLAT longitude latitude
+    /// BYRADIUS radius unit|BYBOX width height unit [ASC|DESC]
+    /// [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH], and
+    /// GEOSEARCHSTORE destination source ... [STOREDIST]
+    pub(super) fn command_geosearch(
+        &mut self,
+        command: &[CommandArg],
+        store: bool,
+    ) -> StorageResult<RESP> {
+        let (source, args) = if store {
+            (&command[2], &command[3..])
+        } else {
+            (&command[1], &command[2..])
+        };
+        let search = Search::parse(command, args, store)?;
+        let found = match self.read_sorted_set(source)? {
+            Some(set) => search_set(set, &search)?,
+            None => Vec::new(),
+        };
+
+        if store {
+            let dest = &command[1];
+            let count = found.len();
+            if found.is_empty() {
+                self.expire_if_needed(dest);
+                if self.remove(dest).is_some() {
+                    self.notify(notify::GENERIC, "del", dest);
+                }
+                return Ok(RESP::Integer(0));
+            }
+            let mut set = SortedSet::new();
+            for found in found {
+                let score = if search.store_dist {
+                    found.distance / search.unit.meters()
+                } else {
+                    found.score
+                };
+                set.insert(&found.member, score);
+            }
+            self.insert(dest.to_string(), StorageValue::SortedSet(set));
+            self.notify(notify::ZSET, "geosearchstore", dest);
+            return Ok(RESP::Integer(count as i64));
+        }
+
+        let plain = !(search.with_dist || search.with_hash || search.with_coord);
+        let replies = found
+            .into_iter()
+            .map(|found| {
+                if plain {
+                    return RESP::BulkString(found.member.into());
+                }
+                let mut reply = vec![RESP::BulkString(found.member.into())];
+                if search.with_dist {
+                    reply.push(format_distance(found.distance, search.unit));
+                }
+                if search.with_hash {
+                    reply.push(RESP::Integer(found.score as i64));
+                }
+                if search.with_coord {
+                    reply.push(format_point(found.point));
+                }
+                RESP::Array(reply)
+            })
+            .collect();
+        Ok(RESP::Array(replies))
+    }
+}
+
+/// The members of `set` inside the search's shape, sorted and cut down as
+/// it asks. COUNT without ANY sorts nearest first, like Redis.
+fn search_set(set: &SortedSet, search: &Search) -> StorageResult<Vec<Found>> {
+    let center = match &search.origin {
+        Origin::Point(point) => *point,
+        Origin::Member(member) => match set.score(member) {
+            Some(score) => geo::decode(score as u64),
+            None => {
+                return Err(StorageError::syntax(
+                    "could not decode requested zset member",
+                ));
+            }
+        },
+    };
+    let mut found = Vec::new();
+    'ranges: for (min, max) in geo::search_ranges(center, search.shape) {
+        for (member, score) in set.range(min as f64, max as f64) {
+            let point = geo::decode(score as u64);
+            if let Some(distance) = search.shape.distance_to(center, point) {
+                found.push(Found {
+                    member: member.to_string(),
+                    score,
+                    point,
+                    distance,
+                });
+                if search.any && Some(found.len()) == search.count {
+                    break 'ranges;
+                }
+            }
+        }
+    }
+    let sort = match search.sort {
+        None if search.count.is_some() && !search.any => Some(Sort::Asc),
+        sort => sort,
+    };
+    match sort {
+        Some(Sort::Asc) => found.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        Some(Sort::Desc) => found.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        None => (),
+    }
+    if let Some(count) = search.count {
+        found.truncate(count);
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{bulk, run};
+
+    fn sicily() -> Storage {
+        let mut storage = Storage::new();
+        let added = run(
+            &mut storage,
+            &[
+                "geoadd",
+                "Sicily",
+                "13.361389",
+                "38.115556",
+                "Palermo",
+                "15.087269",
+                "37.502669",
+                "Catania",
+            ],
+        );
+        assert_eq!(added, Ok(RESP::Integer(2)));
+        storage
+    }
+
+    #[test]
+    fn test_geoadd_options() {
+        let mut storage = sicily();
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geoadd",
+                    "Sicily",
+                    "NX",
+                    "CH",
+                    "13.5",
+                    "38.0",
+                    "Palermo",
+                    "14.0",
+                    "37.0",
+                    "Agrigento"
+                ]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geoadd", "Sicily", "XX", "CH", "13.5", "38.0", "Palermo", "14.0", "37.0",
+                    "Enna"
+                ]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["geoadd", "Other", "XX", "1", "2", "m"]),
+            Ok(RESP::Integer(0))
+        );
+        assert!(!storage.contains("Other"));
+        assert_eq!(
+            run(&mut storage, &["geoadd", "Sicily", "1", "86", "m"]),
+            Err(StorageError::syntax(
+                "invalid longitude,latitude pair 1.000000,86.000000"
+            ))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["geoadd", "Sicily", "NX", "XX", "1", "2", "m"]
+            ),
+            Err(StorageError::syntax(
+                "XX and NX options at the same time are not compatible"
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["geoadd", "Sicily", "1", "2"]),
+            Err(StorageError::syntax_error())
+        );
+    }
+
+    #[test]
+    fn test_geodist_geopos_geohash() {
+        let mut storage = sicily();
+        assert_eq!(
+            run(&mut storage, &["geodist", "Sicily", "Palermo", "Catania"]),
+            Ok(bulk("166274.1516"))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["geodist", "Sicily", "Palermo", "Catania", "km"]
+            ),
+            Ok(bulk("166.2742"))
+        );
+        assert_eq!(
+            run(&mut storage, &["geodist", "Sicily", "Palermo", "Nowhere"]),
+            Ok(RESP::Null)
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["geodist", "Sicily", "Palermo", "Catania", "yd"]
+            ),
+            Err(StorageError::syntax(UNSUPPORTED_UNIT))
+        );
+        assert_eq!(
+            run(&mut storage, &["geohash", "Sicily", "Palermo", "Nowhere"]),
+            Ok(RESP::Array(vec![bulk("sqc8b49rny0"), RESP::Null]))
+        );
+        let Ok(RESP::Array(positions)) =
+            run(&mut storage, &["geopos", "Sicily", "Palermo", "Nowhere"])
+        else {
+            panic!("expected positions");
+        };
+        let RESP::Array(palermo) = &positions[0] else {
+            panic!("expected a position");
+        };
+        let RESP::BulkString(lon) = &palermo[0] else {
+            panic!("expected a longitude");
+        };
+        let lon: f64 = std::str::from_utf8(lon).unwrap().parse().unwrap();
+        assert!((lon - 13.361389).abs() < 1e-5);
+        assert_eq!(positions[1], RESP::Null);
+    }
+
+    #[test]
+    fn test_geosearch() {
+        let mut storage = sicily();
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geosearch",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "200",
+                    "km",
+                    "ASC"
+                ]
+            ),
+            Ok(RESP::Array(vec![bulk("Catania"), bulk("Palermo")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geosearch",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "100",
+                    "km",
+                    "WITHDIST",
+                    "WITHHASH"
+                ]
+            ),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("Catania"),
+                bulk("56.4413"),
+                RESP::Integer(3479447370796909)
+            ])]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geosearch",
+                    "Sicily",
+                    "FROMMEMBER",
+                    "Palermo",
+                    "BYBOX",
+                    "400",
+                    "400",
+                    "km",
+                    "DESC",
+                    "COUNT",
+                    "1"
+                ]
+            ),
+            Ok(RESP::Array(vec![bulk("Catania")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geosearch",
+                    "Sicily",
+                    "FROMMEMBER",
+                    "Nowhere",
+                    "BYRADIUS",
+                    "1",
+                    "km"
+                ]
+            ),
+            Err(StorageError::syntax(
+                "could not decode requested zset member"
+            ))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["geosearch", "Sicily", "BYRADIUS", "1", "km"]
+            ),
+            Err(StorageError::syntax(
+                "exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_geosearchstore() {
+        let mut storage = sicily();
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geosearchstore",
+                    "near",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "100",
+                    "km",
+                    "STOREDIST"
+                ]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        let Some(StorageValue::SortedSet(near)) =
+            storage.store.get("near").map(|entry| &entry.value)
+        else {
+            panic!("expected a sorted set");
+        };
+        assert!((near.score("Catania").unwrap() - 56.4413).abs() < 1e-3);
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geosearchstore",
+                    "near",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "0",
+                    "0",
+                    "BYRADIUS",
+                    "1",
+                    "m"
+                ]
+            ),
+            Ok(RESP::Integer(0))
+        );
+        assert!(!storage.contains("near"));
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "geosearchstore",
+                    "near",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "100",
+                    "km",
+                    "WITHDIST"
+                ]
+            ),
+            Err(StorageError::syntax_error())
+        );
+    }
+}
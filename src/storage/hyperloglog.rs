@@ -0,0 +1,281 @@
+use super::result::{StorageError, StorageResult};
+use super::{Storage, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::hyperloglog::{self, Encoding, REGISTERS, SPARSE_MAX_BYTES};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+fn registers(bytes: &[u8]) -> StorageResult<Vec<u8>> {
+    hyperloglog::registers(bytes).ok_or(StorageError::CorruptedHll)
+}
+
+impl Storage {
+    /// Whether `key` holds an HLL, erroring if it holds anything else.
+    /// Doesn't count as a hit or a miss.
+    fn hll_exists(&mut self, key: &str) -> StorageResult<bool> {
+        match self.lookup(key).map(|entry| &entry.value) {
+            Some(StorageValue::Primitive(value)) => {
+                match hyperloglog::encoding(&value.as_bytes()) {
+                    Some(_) => Ok(true),
+                    None => Err(StorageError::InvalidHll),
+                }
+            }
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(false),
+        }
+    }
+
+    /// The HLL at `key`, if there is one.
+    fn read_hll(&mut self, key: &str) -> StorageResult<Option<Vec<u8>>> {
+        let Some(bytes) = self.read_string(key)? else {
+            return Ok(None);
+        };
+        match hyperloglog::encoding(&bytes) {
+            Some(_) => Ok(Some(bytes.into_owned())),
+            None => Err(StorageError::InvalidHll),
+        }
+    }
+
+    /// PFADD key [element ...]
+    pub(super) fn command_pfadd(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let key = &command[1];
+        let exists = self.hll_exists(key)?;
+        let elements: Vec<&[u8]> = command[2..].iter().map(|e| e.as_bytes()).collect();
+        let changed = self
+            .write_bytes(key, |bytes| {
+                if !exists {
+                    *bytes = hyperloglog::new_sparse();
+                }
+                hyperloglog::add(bytes, &elements, SPARSE_MAX_BYTES)
+            })?
+            .ok_or(StorageError::CorruptedHll)?;
+        if !exists || changed {
+            self.notify(notify::STRING, "pfadd", key);
+        }
+        Ok(RESP::Integer(i64::from(!exists || changed)))
+    }
+
+    /// PFCOUNT key [key ...]
+    ///
+    /// A single key's count is cached in its header until the next write;
+    /// several keys are counted as their union without touching them.
+    pub(super) fn command_pfcount(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if let [_, key] = command {
+            let Some(bytes) = self.read_hll(key)? else {
+                return Ok(RESP::Integer(0));
+            };
+            if let Some(count) = hyperloglog::cached_count(&bytes) {
+                return Ok(RESP::Integer(count as i64));
+            }
+            let count = hyperloglog::count(&registers(&bytes)?);
+            self.write_bytes(key, |bytes| hyperloglog::set_cached_count(bytes, count))?;
+            return Ok(RESP::Integer(count as i64));
+        }
+        let mut union = vec![0; REGISTERS];
+        for key in &command[1..] {
+            if let Some(bytes) = self.read_hll(key)? {
+                hyperloglog::merge(&mut union, &registers(&bytes)?);
+            }
+        }
+        Ok(RESP::Integer(hyperloglog::count(&union) as i64))
+    }
+
+    /// PFMERGE destkey [sourcekey ...]
+    ///
+    /// The destination is one of the inputs, and stays sparse only if none
+    /// of them are dense.
+    pub(super) fn command_pfmerge(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let mut union = vec![0; REGISTERS];
+        let mut dense = false;
+        for key in &command[1..] {
+            if let Some(bytes) = self.read_hll(key)? {
+                dense |= hyperloglog::encoding(&bytes) == Some(Encoding::Dense);
+                hyperloglog::merge(&mut union, &registers(&bytes)?);
+            }
+        }
+        let merged = hyperloglog::encode(&union, !dense, SPARSE_MAX_BYTES);
+        let dest = &command[1];
+        self.write_bytes(dest, |bytes| *bytes = merged)?;
+        self.notify(notify::STRING, "pfadd", dest);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// PFDEBUG GETREG|DECODE|ENCODING|TODENSE key
+    pub(super) fn command_pfdebug(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let key = &command[2];
+        let Some(bytes) = self.read_hll(key)? else {
+            return Err(StorageError::syntax("The specified key does not exist"));
+        };
+        let sparse = hyperloglog::encoding(&bytes) == Some(Encoding::Sparse);
+        match command[1].to_uppercase().as_str() {
+            "GETREG" => {
+                let registers = registers(&bytes)?;
+                if sparse {
+                    self.convert_to_dense(key, &bytes)?;
+                }
+                let registers = registers
+                    .into_iter()
+                    .map(|value| RESP::Integer(i64::from(value)))
+                    .collect();
+                Ok(RESP::Array(registers))
+            }
+            "DECODE" if !sparse => Err(StorageError::syntax("HLL encoding is not sparse")),
+            "DECODE" => hyperloglog::describe_sparse(&bytes)
+                .map(RESP::SimpleString)
+                .ok_or(StorageError::CorruptedHll),
+            "ENCODING" => Ok(RESP::SimpleString(String::from(if sparse {
+                "sparse"
+            } else {
+                "dense"
+            }))),
+            "TODENSE" => {
+                if sparse {
+                    self.convert_to_dense(key, &bytes)?;
+                }
+                Ok(RESP::Integer(i64::from(sparse)))
+            }
+            _ => Err(StorageError::syntax(&format!(
+                "Unknown PFDEBUG subcommand '{}'",
+                command[1]
+            ))),
+        }
+    }
+
+    fn convert_to_dense(&mut self, key: &str, bytes: &[u8]) -> StorageResult<()> {
+        let dense = hyperloglog::to_dense(bytes).ok_or(StorageError::CorruptedHll)?;
+        self.write_bytes(key, |bytes| *bytes = dense)
+    }
+
+    /// PFSELFTEST
+    pub(super) fn command_pfselftest(&mut self) -> StorageResult<RESP> {
+        match hyperloglog::self_test() {
+            Ok(()) => Ok(RESP::SimpleString(String::from("OK"))),
+            Err(message) => Ok(RESP::Error(format!("TESTFAILED {}", message))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::PrimitiveStorageValue;
+    use crate::test_support::run;
+
+    #[test]
+    fn test_pfadd_and_pfcount() {
+        let mut storage = Storage::new();
+        assert_eq!(run(&mut storage, &["pfadd", "h"]), Ok(RESP::Integer(1)));
+        assert_eq!(run(&mut storage, &["pfadd", "h"]), Ok(RESP::Integer(0)));
+        assert_eq!(
+            run(&mut storage, &["pfadd", "h", "a", "b", "c"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["pfadd", "h", "a"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(run(&mut storage, &["pfcount", "h"]), Ok(RESP::Integer(3)));
+        // The count is now cached in the header
+        assert_eq!(run(&mut storage, &["pfcount", "h"]), Ok(RESP::Integer(3)));
+        assert_eq!(
+            run(&mut storage, &["pfcount", "missing"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["pfdebug", "encoding", "h"]),
+            Ok(RESP::SimpleString(String::from("sparse")))
+        );
+
+        run(&mut storage, &["set", "s", "plain"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["pfadd", "s", "a"]),
+            Err(StorageError::InvalidHll)
+        );
+        assert_eq!(
+            run(&mut storage, &["pfcount", "s"]),
+            Err(StorageError::InvalidHll)
+        );
+    }
+
+    #[test]
+    fn test_pfcount_multiple_keys_and_pfmerge() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["pfadd", "a", "1", "2", "3"]).unwrap();
+        run(&mut storage, &["pfadd", "b", "3", "4"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["pfcount", "a", "b", "missing"]),
+            Ok(RESP::Integer(4))
+        );
+        assert_eq!(
+            run(&mut storage, &["pfmerge", "c", "a", "b"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(run(&mut storage, &["pfcount", "c"]), Ok(RESP::Integer(4)));
+        // The destination counts as a source
+        assert_eq!(
+            run(&mut storage, &["pfmerge", "c"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(run(&mut storage, &["pfcount", "c"]), Ok(RESP::Integer(4)));
+    }
+
+    #[test]
+    fn test_pfdebug() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["pfadd", "h", "a"]).unwrap();
+        let Ok(RESP::SimpleString(decoded)) = run(&mut storage, &["pfdebug", "decode", "h"]) else {
+            panic!("expected a decoded HLL");
+        };
+        assert!(decoded.contains("v:"), "{}", decoded);
+        assert_eq!(
+            run(&mut storage, &["pfdebug", "todense", "h"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["pfdebug", "todense", "h"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["pfdebug", "encoding", "h"]),
+            Ok(RESP::SimpleString(String::from("dense")))
+        );
+        let Ok(RESP::Array(registers)) = run(&mut storage, &["pfdebug", "getreg", "h"]) else {
+            panic!("expected registers");
+        };
+        assert_eq!(registers.len(), REGISTERS);
+        assert_eq!(
+            registers.iter().filter(|r| **r != RESP::Integer(0)).count(),
+            1
+        );
+        assert_eq!(run(&mut storage, &["pfcount", "h"]), Ok(RESP::Integer(1)));
+        assert_eq!(
+            run(&mut storage, &["pfdebug", "decode", "h"]),
+            Err(StorageError::syntax("HLL encoding is not sparse"))
+        );
+        assert_eq!(
+            run(&mut storage, &["pfdebug", "encoding", "missing"]),
+            Err(StorageError::syntax("The specified key does not exist"))
+        );
+    }
+
+    #[test]
+    fn test_corrupted_hll() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["pfadd", "h", "a"]).unwrap();
+        // Chop the final opcode so the registers no longer add up
+        storage
+            .write_bytes("h", |bytes| {
+                bytes.pop();
+            })
+            .unwrap();
+        assert_eq!(
+            run(&mut storage, &["pfcount", "h", "h"]),
+            Err(StorageError::CorruptedHll)
+        );
+        let entry = storage.store.get("h").unwrap();
+        assert!(matches!(
+            entry.value,
+            StorageValue::Primitive(PrimitiveStorageValue::Bytes(_))
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+use super::memory::{ENTRY_OVERHEAD, EXPIRE_OVERHEAD, entry_memory_usage, sampled_memory_usage};
+use super::result::{StorageError, StorageResult, parse_integer};
+use super::{EvictionPolicy, PrimitiveStorageValue, Storage, StorageEntry, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::list::{Deque, List};
+use crate::resp::RESP;
+
+/// Elements sampled by MEMORY USAGE when SAMPLES isn't given.
+const DEFAULT_MEMORY_USAGE_SAMPLES: usize = 5;
+const LIST_PAGE_CAPACITY: usize = List::<PrimitiveStorageValue>::PAGE_CAPACITY;
+
+impl StorageValue {
+    /// The name of the internal representation, as reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            StorageValue::Primitive(PrimitiveStorageValue::Integer(_)) => "int",
+            StorageValue::Primitive(
+                PrimitiveStorageValue::String(_) | PrimitiveStorageValue::Bytes(_),
+            ) => "raw",
+            StorageValue::List(_) => "quicklist",
+            StorageValue::Stream(_) => "stream",
+            StorageValue::SortedSet(_) => "skiplist",
+            StorageValue::Json(_) => "json",
+            StorageValue::Bloom(_) => "bloom",
+            StorageValue::Cuckoo(_) => "cuckoo",
+            StorageValue::CountMin(_) => "cms",
+            StorageValue::TopK(_) => "topk",
+            StorageValue::TimeSeries(_) => "compressed",
+            StorageValue::VectorSet(_) => "hnsw",
+        }
+    }
+}
+
+fn unknown_subcommand(command: &[CommandArg]) -> StorageError {
+    let message = format!(
+        "unknown subcommand or wrong number of arguments for '{}'. Try {} HELP.",
+        command.get(1).map(|arg| arg.as_str()).unwrap_or_default(),
+        command[0].to_uppercase()
+    );
+    StorageError::syntax(&message)
+}
+
+fn help(lines: &[&str]) -> RESP {
+    RESP::Array(
+        lines
+            .iter()
+            .map(|line| RESP::SimpleString(line.to_string()))
+            .collect(),
+    )
+}
+
+/// Memory figures of one keyspace, or of several merged together.
+pub(super) struct MemoryReport {
+    pub used: usize,
+    pub peak: usize,
+    pub maxmemory: usize,
+    pub policy: EvictionPolicy,
+    pub keys: usize,
+    pub expires: usize,
+    /// Lists filling less than 10% of their pages, and the bytes they use
+    pub sparse_lists: usize,
+    pub sparse_bytes: usize,
+}
+
+impl MemoryReport {
+    /// Adds another shard's figures. The limit stays as it is, since
+    /// every shard has the keyspace's whole `maxmemory`.
+    pub fn merge(&mut self, other: &MemoryReport) {
+        self.used += other.used;
+        self.peak += other.peak;
+        self.keys += other.keys;
+        self.expires += other.expires;
+        self.sparse_lists += other.sparse_lists;
+        self.sparse_bytes += other.sparse_bytes;
+    }
+
+    /// Bytes spent on bookkeeping rather than on keys and values.
+    fn overhead(&self) -> (usize, usize) {
+        (self.keys * ENTRY_OVERHEAD, self.expires * EXPIRE_OVERHEAD)
+    }
+
+    /// The MEMORY STATS reply.
+    pub fn stats(&self) -> RESP {
+        let (main, expires) = self.overhead();
+        let overhead = main + expires;
+        let keys = self.keys;
+        let dataset = self.used.saturating_sub(overhead);
+        let percentage = |part: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                part as f64 * 100.0 / total as f64
+            }
+        };
+        let mut stats = vec![
+            ("peak.allocated", RESP::Integer(self.peak as i64)),
+            ("total.allocated", RESP::Integer(self.used as i64)),
+            ("startup.allocated", RESP::Integer(0)),
+            ("overhead.total", RESP::Integer(overhead as i64)),
+            ("keys.count", RESP::Integer(keys as i64)),
+            (
+                "keys.bytes-per-key",
+                RESP::Integer(self.used.checked_div(keys).unwrap_or(0) as i64),
+            ),
+            ("dataset.bytes", RESP::Integer(dataset as i64)),
+            (
+                "dataset.percentage",
+                RESP::BulkString(percentage(dataset, self.used).to_string().into()),
+            ),
+            (
+                "peak.percentage",
+                RESP::BulkString(percentage(self.used, self.peak).to_string().into()),
+            ),
+        ];
+        if keys > 0 {
+            stats.push((
+                "db.0",
+                RESP::Array(vec![
+                    RESP::SimpleString("overhead.hashtable.main".to_string()),
+                    RESP::Integer(main as i64),
+                    RESP::SimpleString("overhead.hashtable.expires".to_string()),
+                    RESP::Integer(expires as i64),
+                ]),
+            ));
+        }
+        RESP::Array(
+            stats
+                .into_iter()
+                .flat_map(|(name, value)| [RESP::SimpleString(name.to_string()), value])
+                .collect(),
+        )
+    }
+
+    /// The MEMORY DOCTOR report.
+    pub fn doctor(&self) -> String {
+        if self.keys == 0 {
+            return "This instance is empty, there is no memory usage to diagnose.".to_string();
+        }
+        let mut issues = Vec::new();
+        if self.peak > self.used * 3 / 2 {
+            issues.push(format!(
+                " * Peak memory: at some point used memory reached {} bytes, more than \
+                 150% of the current {} bytes. Large deletions or evictions happened since.",
+                self.peak, self.used
+            ));
+        }
+        if self.sparse_lists > 0 {
+            issues.push(format!(
+                " * Sparse lists: {} lists fill less than 10% of their pages, using {} bytes. \
+                 Every page costs {} bytes regardless of how many elements it holds.",
+                self.sparse_lists,
+                self.sparse_bytes,
+                List::<PrimitiveStorageValue>::PAGE_BYTES
+            ));
+        }
+        if self.maxmemory > 0 && self.used * 10 > self.maxmemory * 9 {
+            issues.push(format!(
+                " * Memory limit: used memory is over 90% of maxmemory ({} of {} bytes) \
+                 with the {} policy.",
+                self.used,
+                self.maxmemory,
+                self.policy.as_str()
+            ));
+        }
+        if issues.is_empty() {
+            "No memory issues found in this instance.".to_string()
+        } else {
+            format!(
+                "The following memory issues were found:\n\n{}\n",
+                issues.join("\n\n")
+            )
+        }
+    }
+}
+
+impl Storage {
+    /// Looks up a key without counting it as an access, so introspection
+    /// doesn't disturb LRU/LFU.
+    fn peek(&mut self, key: &str) -> Option<&StorageEntry> {
+        self.expire_if_needed(key);
+        self.store.get(key)
+    }
+
+    pub(super) fn command_memory(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if command.len() < 2 {
+            return Err(StorageError::WrongArity("memory".to_string()));
+        }
+        match command[1].to_lowercase().as_str() {
+            "usage" => self.memory_usage(command),
+            "stats" if command.len() == 2 => Ok(self.memory_report().stats()),
+            "doctor" if command.len() == 2 => {
+                Ok(RESP::BulkString(self.memory_report().doctor().into()))
+            }
+            "help" if command.len() == 2 => Ok(help(&[
+                "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "DOCTOR",
+                "    Return memory problems reports.",
+                "STATS",
+                "    Return information about the memory usage of the server.",
+                "USAGE <key> [SAMPLES <count>]",
+                "    Return memory in bytes used by <key> and its value. Nested values are",
+                "    sampled up to <count> times (default: 5, 0 means sample all).",
+                "HELP",
+                "    Print this help.",
+            ])),
+            _ => Err(unknown_subcommand(command)),
+        }
+    }
+
+    fn memory_usage(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let samples = match command.len() {
+            3 => DEFAULT_MEMORY_USAGE_SAMPLES,
+            5 if command[3].eq_ignore_ascii_case("SAMPLES") => parse_integer(&command[4])?,
+            _ => {
+                return Err(StorageError::syntax("syntax error"));
+            }
+        };
+        let key = command[2].as_str();
+        let expire_overhead = if self.expires.contains_key(key) {
+            EXPIRE_OVERHEAD + key.len()
+        } else {
+            0
+        };
+        match self.peek(key) {
+            Some(entry) => {
+                let bytes = ENTRY_OVERHEAD
+                    + key.len()
+                    + sampled_memory_usage(&entry.value, samples)
+                    + expire_overhead;
+                Ok(RESP::Integer(bytes as i64))
+            }
+            None => Ok(RESP::Null),
+        }
+    }
+
+    /// The numbers behind MEMORY STATS and MEMORY DOCTOR for this keyspace.
+    pub(super) fn memory_report(&self) -> MemoryReport {
+        let (sparse_lists, sparse_bytes) = self
+            .store
+            .iter()
+            .filter_map(|(_, entry)| match &entry.value {
+                StorageValue::List(l) if l.len() * 10 < l.pages() * LIST_PAGE_CAPACITY => {
+                    Some(l.pages() * List::<PrimitiveStorageValue>::PAGE_BYTES)
+                }
+                _ => None,
+            })
+            .fold((0, 0), |(count, bytes), page_bytes| {
+                (count + 1, bytes + page_bytes)
+            });
+        MemoryReport {
+            used: self.used_memory,
+            peak: self.peak_memory,
+            maxmemory: self.maxmemory,
+            policy: self.policy,
+            keys: self.store.len(),
+            expires: self.expires.len(),
+            sparse_lists,
+            sparse_bytes,
+        }
+    }
+
+    pub(super) fn command_object(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if command.len() == 2 && command[1].eq_ignore_ascii_case("HELP") {
+            return Ok(help(&[
+                "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "ENCODING <key>",
+                "    Return the kind of internal representation used in order to store the value",
+                "    associated with a <key>.",
+                "FREQ <key>",
+                "    Return the access frequency index of the <key>. The returned integer is",
+                "    proportional to the logarithm of the recent access frequency of the key.",
+                "IDLETIME <key>",
+                "    Return the idle time of the <key>, that is the approximated number of",
+                "    seconds elapsed since the last access to the key.",
+                "REFCOUNT <key>",
+                "    Return the number of references of the value associated with the specified",
+                "    <key>.",
+                "HELP",
+                "    Print this help.",
+            ]));
+        }
+        if command.len() != 3 {
+            return Err(unknown_subcommand(command));
+        }
+        let subcommand = command[1].to_lowercase();
+        let lfu = self.policy.is_lfu();
+        let entry = match self.peek(&command[2]) {
+            Some(entry) => entry,
+            None => return Ok(RESP::Null),
+        };
+        match subcommand.as_str() {
+            "encoding" => Ok(RESP::BulkString(entry.value.encoding().into())),
+            // Values are never shared between keys
+            "refcount" => Ok(RESP::Integer(1)),
+            "idletime" if lfu => Err(StorageError::syntax(
+                "An LFU maxmemory policy is selected, idle time not tracked",
+            )),
+            "idletime" => Ok(RESP::Integer(entry.lru.elapsed().as_secs() as i64)),
+            "freq" if !lfu => Err(StorageError::syntax(
+                "An LFU maxmemory policy is not selected, access frequency not tracked",
+            )),
+            "freq" => Ok(RESP::Integer(entry.lfu_counter() as i64)),
+            _ => Err(unknown_subcommand(command)),
+        }
+    }
+
+    /// Only DEBUG OBJECT is supported, which is where Redis reports
+    /// quicklist internals.
+    pub(super) fn command_debug(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if command.len() != 3 || !command[1].eq_ignore_ascii_case("OBJECT") {
+            return Err(StorageError::syntax("Only DEBUG OBJECT key is supported"));
+        }
+        let key = &command[2];
+        let entry = match self.peek(key) {
+            Some(entry) => entry,
+            None => return Err(StorageError::KeyNotFound),
+        };
+        let mut output = format!(
+            "Value at:{:p} refcount:1 encoding:{} memory:{} lru_seconds_idle:{}",
+            &entry.value,
+            entry.value.encoding(),
+            entry_memory_usage(key, &entry.value),
+            entry.lru.elapsed().as_secs()
+        );
+        if let StorageValue::List(l) = &entry.value {
+            output.push_str(&format!(
+                " ql_nodes:{} ql_avg_node:{:.2} ql_page_size:{} ql_page_bytes:{}",
+                l.pages(),
+                l.len() as f64 / l.pages().max(1) as f64,
+                LIST_PAGE_CAPACITY,
+                List::<PrimitiveStorageValue>::PAGE_BYTES
+            ));
+        }
+        Ok(RESP::SimpleString(output))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::EvictionPolicy;
+    use super::*;
+    use crate::test_support::run;
+
+    #[test]
+    fn test_object_encoding() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "s", "value"]).unwrap();
+        run(&mut storage, &["incr", "i"]).unwrap();
+        run(&mut storage, &["rpush", "l", "a"]).unwrap();
+        for (key, encoding) in [("s", "raw"), ("i", "int"), ("l", "quicklist")] {
+            let output = run(&mut storage, &["object", "encoding", key]).unwrap();
+            assert_eq!(output, RESP::BulkString(encoding.into()));
+        }
+        let output = run(&mut storage, &["object", "encoding", "missing"]).unwrap();
+        assert_eq!(output, RESP::Null);
+    }
+
+    #[test]
+    fn test_object_freq_requires_lfu() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "s", "value"]).unwrap();
+        assert!(run(&mut storage, &["object", "freq", "s"]).is_err());
+        let output = run(&mut storage, &["object", "idletime", "s"]).unwrap();
+        assert_eq!(output, RESP::Integer(0));
+
+        storage.set_maxmemory_policy(EvictionPolicy::AllKeysLfu);
+        assert!(run(&mut storage, &["object", "idletime", "s"]).is_err());
+        let output = run(&mut storage, &["object", "freq", "s"]).unwrap();
+        assert!(matches!(output, RESP::Integer(n) if n >= 5));
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "s", "value"]).unwrap();
+        let output = run(&mut storage, &["memory", "usage", "s"]).unwrap();
+        assert_eq!(output, RESP::Integer(storage.used_memory() as i64));
+
+        for _ in 0..100 {
+            run(&mut storage, &["rpush", "l", "abcd"]).unwrap();
+        }
+        let sampled = run(&mut storage, &["memory", "usage", "l", "samples", "3"]).unwrap();
+        let exact = run(&mut storage, &["memory", "usage", "l", "SAMPLES", "0"]).unwrap();
+        assert_eq!(sampled, exact);
+        let output = run(&mut storage, &["memory", "usage", "missing"]).unwrap();
+        assert_eq!(output, RESP::Null);
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "s", "value"]).unwrap();
+        let RESP::Array(stats) = run(&mut storage, &["memory", "stats"]).unwrap() else {
+            panic!("Expected an array");
+        };
+        let keys_count = stats
+            .chunks(2)
+            .find(|pair| pair[0] == RESP::SimpleString("keys.count".to_string()))
+            .map(|pair| &pair[1]);
+        assert_eq!(keys_count, Some(&RESP::Integer(1)));
+    }
+
+    #[test]
+    fn test_memory_doctor_reports_sparse_lists() {
+        let mut storage = Storage::new();
+        let output = run(&mut storage, &["memory", "doctor"]).unwrap();
+        assert!(
+            matches!(output, RESP::BulkString(s) if String::from_utf8_lossy(&s).contains("empty"))
+        );
+        run(&mut storage, &["rpush", "l", "a"]).unwrap();
+        let output = run(&mut storage, &["memory", "doctor"]).unwrap();
+        assert!(
+            matches!(output, RESP::BulkString(s) if String::from_utf8_lossy(&s).contains("Sparse lists"))
+        );
+    }
+
+    #[test]
+    fn test_debug_object_reports_pages() {
+        let mut storage = Storage::new();
+        for _ in 0..5000 {
+            run(&mut storage, &["lpush", "l", "a"]).unwrap();
+        }
+        let output = run(&mut storage, &["debug", "object", "l"]).unwrap();
+        assert!(matches!(output, RESP::SimpleString(s) if s.contains("ql_nodes:2")));
+    }
+}
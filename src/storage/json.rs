@@ -0,0 +1,737 @@
+use super::result::{StorageError, StorageResult, parse_integer};
+use super::{Storage, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::json::{Document, Format, Path, Value};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+const NO_SUCH_KEY: &str = "could not perform this operation on a key that doesn't exist";
+
+fn parse_path(arg: &str) -> StorageResult<Path> {
+    Path::parse(arg).map_err(|message| StorageError::syntax(&message))
+}
+
+fn parse_json(arg: &str) -> StorageResult<Value> {
+    Value::parse(arg).map_err(|message| StorageError::syntax(&format!("invalid JSON: {}", message)))
+}
+
+/// What a command did at each location its path matched: a result, or
+/// the type of the value it couldn't work on.
+type Matches<T> = Vec<Result<T, &'static str>>;
+
+/// Legacy paths reply with the first match alone, and fail if there is
+/// none or it has the wrong type.
+fn first_match<T>(path: &str, expected: &str, matches: Matches<T>) -> StorageResult<T> {
+    match matches.into_iter().next() {
+        Some(Ok(result)) => Ok(result),
+        Some(Err(found)) => Err(StorageError::syntax(&format!(
+            "wrong type of path value - expected {} but found {}",
+            expected, found
+        ))),
+        None => Err(StorageError::syntax(&format!(
+            "Path '{}' does not exist",
+            path
+        ))),
+    }
+}
+
+/// Replies with one result per match for JSONPath, or the first for a
+/// legacy path.
+fn reply_each<T>(
+    path_arg: &str,
+    path: &Path,
+    expected: &str,
+    matches: Matches<T>,
+    reply: impl Fn(T) -> RESP,
+) -> StorageResult<RESP> {
+    if path.is_legacy() {
+        return first_match(path_arg, expected, matches).map(reply);
+    }
+    Ok(RESP::Array(
+        matches
+            .into_iter()
+            .map(|result| result.map_or(RESP::Null, &reply))
+            .collect(),
+    ))
+}
+
+fn integer(n: usize) -> RESP {
+    RESP::Integer(n as i64)
+}
+
+impl Storage {
+    fn read_json(&mut self, key: &str) -> StorageResult<Option<&Document>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::Json(doc)) => Ok(Some(doc)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the document at `key`, keeping `used_memory` in step
+    /// with what it changes. None if there is no document.
+    fn write_json<T>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&mut Document) -> StorageResult<T>,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            return Ok(None);
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::Json(doc) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = doc.memory_usage();
+        let result = f(doc);
+        self.used_memory = (self.used_memory + doc.memory_usage()).saturating_sub(before);
+        result.map(Some)
+    }
+
+    /// Runs `f` on every value `path` matches in the document at `key`. `f`
+    /// returns None for values it doesn't apply to. Notifies `event` if it
+    /// applied anywhere.
+    fn update_json<T>(
+        &mut self,
+        command: &[CommandArg],
+        path: &Path,
+        event: &str,
+        mut f: impl FnMut(&mut Value) -> StorageResult<Option<T>>,
+    ) -> StorageResult<Matches<T>> {
+        let key = &command[1];
+        let matches = self
+            .write_json(key, |doc| {
+                let mut matches = Vec::new();
+                for steps in doc.select(path) {
+                    let result = doc.update(&steps, |value| {
+                        let found = value.type_name();
+                        f(value).map(|result| result.ok_or(found))
+                    });
+                    if let Some(result) = result {
+                        matches.push(result?);
+                    }
+                }
+                Ok(matches)
+            })?
+            .ok_or_else(|| StorageError::syntax(NO_SUCH_KEY))?;
+        if matches.iter().any(Result::is_ok) {
+            self.notify(notify::MODULE, event, key);
+        }
+        Ok(matches)
+    }
+
+    /// Runs `f` on every value `path` matches in the document at `key`.
+    /// None if there is no document.
+    fn read_json_matches<T>(
+        &mut self,
+        key: &str,
+        path: &Path,
+        f: impl Fn(&Value) -> Option<T>,
+    ) -> StorageResult<Option<Matches<T>>> {
+        let Some(doc) = self.read_json(key)? else {
+            return Ok(None);
+        };
+        let matches = doc
+            .select(path)
+            .iter()
+            .filter_map(|steps| doc.get(steps))
+            .map(|value| f(value).ok_or(value.type_name()))
+            .collect();
+        Ok(Some(matches))
+    }
+
+    /// JSON.SET key path value [NX|XX]
+    pub(super) fn command_json_set(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let key = &command[1];
+        let path = parse_path(&command[2])?;
+        let value = parse_json(&command[3])?;
+        let (nx, xx) = match command.get(4).map(|arg| arg.to_uppercase()).as_deref() {
+            None => (false, false),
+            Some("NX") => (true, false),
+            Some("XX") => (false, true),
+            Some(_) => return Err(StorageError::syntax_error()),
+        };
+        if command.len() > 5 {
+            return Err(StorageError::syntax_error());
+        }
+
+        let set = self.write_json(key, |doc| {
+            let matches = doc.select(&path);
+            if !matches.is_empty() {
+                if nx {
+                    return Ok(false);
+                }
+                for steps in matches {
+                    doc.update(&steps, |target| *target = value.clone());
+                }
+                return Ok(true);
+            }
+            let Some((parent, member)) = path.split_new_member() else {
+                return Ok(false);
+            };
+            if xx {
+                return Ok(false);
+            }
+            let mut set = false;
+            for steps in doc.select(&parent) {
+                set |= doc.insert_member(&steps, member, value.clone());
+            }
+            Ok(set)
+        })?;
+        let set = match set {
+            Some(set) => set,
+            None if !path.is_root() => {
+                return Err(StorageError::syntax(
+                    "new objects must be created at the root",
+                ));
+            }
+            None if xx => false,
+            None => {
+                self.insert(key.to_string(), StorageValue::Json(Document::new(value)));
+                true
+            }
+        };
+        if !set {
+            return Ok(RESP::Null);
+        }
+        self.notify(notify::MODULE, "json.set", key);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// JSON.GET key [INDENT indent] [NEWLINE newline] [SPACE space]
+    /// [path ...]
+    pub(super) fn command_json_get(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let mut format = Format::default();
+        let mut i = 2;
+        while i + 1 < command.len() {
+            match command[i].to_uppercase().as_str() {
+                "INDENT" => format.indent = command[i + 1].to_string(),
+                "NEWLINE" => format.newline = command[i + 1].to_string(),
+                "SPACE" => format.space = command[i + 1].to_string(),
+                _ => break,
+            }
+            i += 2;
+        }
+        let root = [CommandArg::from(".")];
+        let path_args = match &command[i..] {
+            [] => &root[..],
+            paths => paths,
+        };
+        let mut paths = Vec::with_capacity(path_args.len());
+        for arg in path_args {
+            paths.push(parse_path(arg)?);
+        }
+        let Some(doc) = self.read_json(&command[1])? else {
+            return Ok(RESP::Null);
+        };
+
+        // Legacy paths read their first match, unless mixed with JSONPath
+        let legacy = paths.iter().all(Path::is_legacy);
+        let mut results = Vec::with_capacity(paths.len());
+        for (arg, path) in path_args.iter().zip(&paths) {
+            let matches = doc.select(path);
+            let mut values = matches.iter().filter_map(|steps| doc.get(steps));
+            let result = if legacy {
+                let missing = || StorageError::syntax(&format!("Path '{}' does not exist", arg));
+                values.next().ok_or_else(missing)?.clone()
+            } else {
+                Value::Array(values.cloned().collect())
+            };
+            results.push((arg.to_string(), result));
+        }
+        let reply = match results.len() {
+            1 => results.pop().expect("one result").1,
+            _ => Value::Object(results),
+        };
+        Ok(RESP::BulkString(reply.format(&format).into()))
+    }
+
+    /// JSON.DEL key [path]
+    pub(super) fn command_json_del(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let key = &command[1];
+        let path = parse_path(command.get(2).map_or("$", |arg| arg.as_str()))?;
+        if command.len() > 3 {
+            return Err(StorageError::syntax_error());
+        }
+        let deleted = if path.is_root() {
+            match self.write_json(key, |_| Ok(()))? {
+                Some(()) => {
+                    self.remove(key);
+                    1
+                }
+                None => 0,
+            }
+        } else {
+            self.write_json(key, |doc| {
+                // Later locations first, so removing one doesn't move the
+                // ones still to go
+                let mut matches = doc.select(&path);
+                matches.sort_unstable_by(|a, b| b.cmp(a));
+                Ok(matches
+                    .iter()
+                    .filter(|steps| doc.remove(steps).is_some())
+                    .count())
+            })?
+            .unwrap_or(0)
+        };
+        if deleted > 0 {
+            self.notify(notify::MODULE, "json.del", key);
+        }
+        Ok(integer(deleted))
+    }
+
+    /// JSON.MGET key [key ...] path
+    pub(super) fn command_json_mget(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let (path_arg, keys) = command[1..].split_last().expect("arity checked");
+        let path = parse_path(path_arg)?;
+        let mut replies = Vec::with_capacity(keys.len());
+        for key in keys {
+            let doc = match self.read_json(key) {
+                Ok(Some(doc)) => doc,
+                Ok(None) | Err(StorageError::WrongType) => {
+                    replies.push(RESP::Null);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let matches = doc.select(&path);
+            let mut values = matches.iter().filter_map(|steps| doc.get(steps));
+            replies.push(if path.is_legacy() {
+                values
+                    .next()
+                    .map_or(RESP::Null, |value| RESP::BulkString(value.to_json().into()))
+            } else {
+                RESP::BulkString(Value::Array(values.cloned().collect()).to_json().into())
+            });
+        }
+        Ok(RESP::Array(replies))
+    }
+
+    /// JSON.NUMINCRBY key path value
+    pub(super) fn command_json_numincrby(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let path = parse_path(&command[2])?;
+        let increment = match parse_json(&command[3])? {
+            number @ (Value::Integer(_) | Value::Float(_)) => number,
+            _ => return Err(StorageError::syntax("expected a number")),
+        };
+        let matches = self.update_json(command, &path, "json.numincrby", |value| {
+            let result = match (&*value, &increment) {
+                (Value::Integer(a), Value::Integer(b)) => match a.checked_add(*b) {
+                    Some(sum) => Value::Integer(sum),
+                    None => Value::Float(*a as f64 + *b as f64),
+                },
+                (Value::Integer(a), Value::Float(b)) => Value::Float(*a as f64 + b),
+                (Value::Float(a), Value::Integer(b)) => Value::Float(a + *b as f64),
+                (Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+                _ => return Ok(None),
+            };
+            if matches!(result, Value::Float(f) if !f.is_finite()) {
+                return Err(StorageError::syntax("result is not a finite number"));
+            }
+            *value = result.clone();
+            Ok(Some(result))
+        })?;
+        let reply = if path.is_legacy() {
+            first_match(&command[2], "number", matches)?
+        } else {
+            Value::Array(
+                matches
+                    .into_iter()
+                    .map(|result| result.unwrap_or(Value::Null))
+                    .collect(),
+            )
+        };
+        Ok(RESP::BulkString(reply.to_json().into()))
+    }
+
+    /// JSON.STRAPPEND key [path] value
+    pub(super) fn command_json_strappend(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let (path_arg, value) = match &command[2..] {
+            [value] => (".", value),
+            [path, value] => (path.as_str(), value),
+            _ => return Err(StorageError::syntax_error()),
+        };
+        let path = parse_path(path_arg)?;
+        let Value::String(suffix) = parse_json(value)? else {
+            return Err(StorageError::syntax("expected a JSON string"));
+        };
+        let matches = self.update_json(command, &path, "json.strappend", |value| {
+            let Value::String(s) = value else {
+                return Ok(None);
+            };
+            s.push_str(&suffix);
+            Ok(Some(s.len()))
+        })?;
+        reply_each(path_arg, &path, "string", matches, integer)
+    }
+
+    /// JSON.ARRAPPEND key path value [value ...]
+    pub(super) fn command_json_arrappend(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let path = parse_path(&command[2])?;
+        let mut values = Vec::with_capacity(command.len() - 3);
+        for arg in &command[3..] {
+            values.push(parse_json(arg)?);
+        }
+        let matches = self.update_json(command, &path, "json.arrappend", |value| {
+            let Value::Array(items) = value else {
+                return Ok(None);
+            };
+            items.extend(values.iter().cloned());
+            Ok(Some(items.len()))
+        })?;
+        reply_each(&command[2], &path, "array", matches, integer)
+    }
+
+    /// JSON.ARRINSERT key path index value [value ...]
+    pub(super) fn command_json_arrinsert(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let path = parse_path(&command[2])?;
+        let index: i64 = parse_integer(&command[3])?;
+        let mut values = Vec::with_capacity(command.len() - 4);
+        for arg in &command[4..] {
+            values.push(parse_json(arg)?);
+        }
+        let matches = self.update_json(command, &path, "json.arrinsert", |value| {
+            let Value::Array(items) = value else {
+                return Ok(None);
+            };
+            let len = items.len() as i64;
+            let at = if index < 0 { len + index } else { index };
+            if !(0..=len).contains(&at) {
+                return Err(StorageError::syntax("index out of bounds"));
+            }
+            let at = at as usize;
+            items.splice(at..at, values.iter().cloned());
+            Ok(Some(items.len()))
+        })?;
+        reply_each(&command[2], &path, "array", matches, integer)
+    }
+
+    /// JSON.ARRPOP key [path [index]]
+    ///
+    /// The index defaults to the last element, and is clamped to the array.
+    pub(super) fn command_json_arrpop(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let path_arg = command.get(2).map_or(".", |arg| arg.as_str());
+        let path = parse_path(path_arg)?;
+        let index: i64 = match command.get(3) {
+            None => -1,
+            Some(arg) => parse_integer(arg)?,
+        };
+        if command.len() > 4 {
+            return Err(StorageError::syntax_error());
+        }
+        let matches = self.update_json(command, &path, "json.arrpop", |value| {
+            let Value::Array(items) = value else {
+                return Ok(None);
+            };
+            if items.is_empty() {
+                return Ok(Some(None));
+            }
+            let len = items.len() as i64;
+            let at = if index < 0 { len + index } else { index };
+            let popped = items.remove(at.clamp(0, len - 1) as usize);
+            Ok(Some(Some(popped.to_json())))
+        })?;
+        reply_each(path_arg, &path, "array", matches, |popped| {
+            popped.map_or(RESP::Null, |s| RESP::BulkString(s.into()))
+        })
+    }
+
+    /// JSON.ARRLEN key [path]
+    pub(super) fn command_json_arrlen(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        self.json_inspect(command, "array", |value| match value {
+            Value::Array(items) => Some(integer(items.len())),
+            _ => None,
+        })
+    }
+
+    /// JSON.OBJKEYS key [path]
+    pub(super) fn command_json_objkeys(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        self.json_inspect(command, "object", |value| match value {
+            Value::Object(members) => Some(RESP::Array(
+                members
+                    .iter()
+                    .map(|(key, _)| RESP::BulkString(key.clone().into()))
+                    .collect(),
+            )),
+            _ => None,
+        })
+    }
+
+    /// JSON.TYPE key [path]
+    pub(super) fn command_json_type(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let path_arg = command.get(2).map_or(".", |arg| arg.as_str());
+        let path = parse_path(path_arg)?;
+        let Some(matches) =
+            self.read_json_matches(&command[1], &path, |value| Some(value.type_name()))?
+        else {
+            return Ok(RESP::Null);
+        };
+        let mut types = matches.into_iter().map(|found| found.unwrap_or_else(|t| t));
+        if path.is_legacy() {
+            return Ok(types
+                .next()
+                .map_or(RESP::Null, |t| RESP::SimpleString(t.to_string())));
+        }
+        Ok(RESP::Array(
+            types.map(|t| RESP::BulkString(t.into())).collect(),
+        ))
+    }
+
+    /// The shared shape of JSON.ARRLEN and JSON.OBJKEYS: `key [path]`, a
+    /// null reply for a missing key, and one result per match.
+    fn json_inspect(
+        &mut self,
+        command: &[CommandArg],
+        expected: &str,
+        f: impl Fn(&Value) -> Option<RESP>,
+    ) -> StorageResult<RESP> {
+        if command.len() > 3 {
+            return Err(StorageError::syntax_error());
+        }
+        let path_arg = command.get(2).map_or(".", |arg| arg.as_str());
+        let path = parse_path(path_arg)?;
+        match self.read_json_matches(&command[1], &path, f)? {
+            Some(matches) => reply_each(path_arg, &path, expected, matches, |r| r),
+            None => Ok(RESP::Null),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{bulk, run};
+
+    fn ok() -> RESP {
+        RESP::SimpleString(String::from("OK"))
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let mut storage = Storage::new();
+        let doc = r#"{"name":"kv","tags":["a","b"],"stats":{"stars":1}}"#;
+        assert_eq!(run(&mut storage, &["json.set", "doc", "$", doc]), Ok(ok()));
+        assert_eq!(run(&mut storage, &["json.get", "doc"]), Ok(bulk(doc)));
+        assert_eq!(
+            run(&mut storage, &["json.get", "doc", "$.tags[0]"]),
+            Ok(bulk(r#"["a"]"#))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.get", "doc", ".stats.stars"]),
+            Ok(bulk("1"))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.get", "doc", ".name", ".stats"]),
+            Ok(bulk(r#"{".name":"kv",".stats":{"stars":1}}"#))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "json.get", "doc", "INDENT", "\t", "NEWLINE", "\n", "SPACE", " ", "$.stats"
+                ]
+            ),
+            Ok(bulk("[\n\t{\n\t\t\"stars\": 1\n\t}\n]"))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.get", "doc", ".missing"]),
+            Err(StorageError::syntax("Path '.missing' does not exist"))
+        );
+        assert_eq!(run(&mut storage, &["json.get", "nothing"]), Ok(RESP::Null));
+
+        // Setting a field changes it in place, or adds it to its parent
+        assert_eq!(
+            run(&mut storage, &["json.set", "doc", "$.stats.stars", "2"]),
+            Ok(ok())
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["json.set", "doc", "$.stats.forks", "0", "NX"]
+            ),
+            Ok(ok())
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["json.set", "doc", "$.stats.forks", "1", "NX"]
+            ),
+            Ok(RESP::Null)
+        );
+        assert_eq!(
+            run(&mut storage, &["json.set", "doc", "$.a.b", "1"]),
+            Ok(RESP::Null)
+        );
+        assert_eq!(
+            run(&mut storage, &["json.get", "doc", "$.stats"]),
+            Ok(bulk(r#"[{"stars":2,"forks":0}]"#))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.set", "new", "$.a", "1"]),
+            Err(StorageError::syntax(
+                "new objects must be created at the root"
+            ))
+        );
+        assert!(run(&mut storage, &["json.set", "new", "$", "{bad"]).is_err());
+        run(&mut storage, &["set", "s", "v"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["json.get", "s"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_del_and_mget() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["json.set", "a", "$", r#"{"x":[1,2,3],"y":{"x":4}}"#],
+        )
+        .unwrap();
+        run(&mut storage, &["json.set", "b", "$", r#"{"x":5}"#]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["json.mget", "a", "b", "c", "$..x"]),
+            Ok(RESP::Array(vec![
+                bulk("[[1,2,3],4]"),
+                bulk("[5]"),
+                RESP::Null
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.del", "a", "$.x[0:2]"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.get", "a"]),
+            Ok(bulk(r#"{"x":[3],"y":{"x":4}}"#))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.del", "a", "$..x"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(run(&mut storage, &["json.del", "b"]), Ok(RESP::Integer(1)));
+        assert!(!storage.contains("b"));
+        assert_eq!(run(&mut storage, &["json.del", "b"]), Ok(RESP::Integer(0)));
+    }
+
+    #[test]
+    fn test_numbers_and_strings() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &[
+                "json.set",
+                "doc",
+                "$",
+                r#"{"a":1,"b":{"a":"x"},"c":{"a":1.5}}"#,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["json.numincrby", "doc", "$..a", "2"]),
+            Ok(bulk("[3,null,3.5]"))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.numincrby", "doc", ".a", "0.5"]),
+            Ok(bulk("3.5"))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.numincrby", "doc", ".b", "1"]),
+            Err(StorageError::syntax(
+                "wrong type of path value - expected number but found object"
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.strappend", "doc", "$..a", "\"yz\""]),
+            Ok(RESP::Array(vec![RESP::Null, RESP::Integer(3), RESP::Null]))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.strappend", "doc", ".b.a", "1"]),
+            Err(StorageError::syntax("expected a JSON string"))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.numincrby", "missing", "$", "1"]),
+            Err(StorageError::syntax(NO_SUCH_KEY))
+        );
+    }
+
+    #[test]
+    fn test_arrays_and_inspection() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["json.set", "doc", "$", r#"{"list":[1],"obj":{"k":[]}}"#],
+        )
+        .unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["json.arrappend", "doc", "$.list", "2", "\"3\""]
+            ),
+            Ok(RESP::Array(vec![RESP::Integer(3)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.arrinsert", "doc", ".list", "-1", "0"]),
+            Ok(RESP::Integer(4))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.arrinsert", "doc", ".list", "9", "0"]),
+            Err(StorageError::syntax("index out of bounds"))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.get", "doc", "$.list"]),
+            Ok(bulk(r#"[[1,2,0,"3"]]"#))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.arrpop", "doc", ".list"]),
+            Ok(bulk("\"3\""))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.arrpop", "doc", "$.*", "0"]),
+            Ok(RESP::Array(vec![bulk("1"), RESP::Null]))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.arrlen", "doc", "$..*"]),
+            Ok(RESP::Array(vec![
+                RESP::Integer(2),
+                RESP::Null,
+                RESP::Null,
+                RESP::Null,
+                RESP::Integer(0)
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.objkeys", "doc"]),
+            Ok(RESP::Array(vec![bulk("list"), bulk("obj")]))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.type", "doc", "$.list[*]"]),
+            Ok(RESP::Array(vec![bulk("integer"), bulk("integer")]))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.type", "doc", ".obj"]),
+            Ok(RESP::SimpleString(String::from("object")))
+        );
+        assert_eq!(
+            run(&mut storage, &["json.arrlen", "missing"]),
+            Ok(RESP::Null)
+        );
+    }
+
+    #[test]
+    fn test_memory_follows_updates() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["json.set", "doc", "$", r#"{"a":[]}"#]).unwrap();
+        let before = storage.used_memory();
+        run(
+            &mut storage,
+            &["json.arrappend", "doc", "$.a", "\"a long string\""],
+        )
+        .unwrap();
+        assert!(storage.used_memory() > before);
+        run(&mut storage, &["json.arrpop", "doc", "$.a"]).unwrap();
+        assert_eq!(storage.used_memory(), before);
+        run(&mut storage, &["json.del", "doc"]).unwrap();
+        assert_eq!(storage.used_memory(), 0);
+    }
+}
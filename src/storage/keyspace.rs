@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use super::result::StorageResult;
+use super::{EvictionPolicy, Executor, Record, ShardPool, ShardedStorage, Storage, StorageStats};
+use crate::blocking::Blocking;
+use crate::command::{CommandArg, CommandSpec};
+use crate::ds::stream::StreamId;
+use crate::pubsub::PubSub;
+use crate::replication::Feed;
+use crate::resp::RESP;
+use crate::tracking::Tracking;
+use crate::transaction::Watches;
+
+/// How commands reach the data, picked by the `execution-model` config.
+pub enum Keyspace {
+    /// Connections lock the shards they need themselves, from threads
+    /// that may block so the runtime's aren't held up waiting
+    Locking(Arc<ShardedStorage>),
+    /// Shards are threads owning their data, and connections message them
+    SharedNothing(ShardPool),
+}
+
+impl Default for Keyspace {
+    fn default() -> Self {
+        Keyspace::Locking(Arc::new(ShardedStorage::default()))
+    }
+}
+
+impl Keyspace {
+    pub async fn execute(
+        &self,
+        spec: &'static CommandSpec,
+        args: &[CommandArg],
+    ) -> StorageResult<RESP> {
+        match self {
+            Keyspace::Locking(storage) => {
+                let (storage, args) = (storage.clone(), args.to_vec());
+                unblocked(move || storage.execute(spec, &args)).await
+            }
+            Keyspace::SharedNothing(pool) => pool.execute(spec, args).await,
+        }
+    }
+
+    /// Runs requests one after the other with every shard they touch
+    /// held throughout, for EXEC. `proceed` is asked once they're all
+    /// held, and when it says no nothing runs and None comes back.
+    pub async fn execute_batch(
+        &self,
+        requests: &[(&'static CommandSpec, Vec<CommandArg>)],
+        proceed: impl FnOnce() -> bool + Send + 'static,
+    ) -> Option<Vec<StorageResult<RESP>>> {
+        match self {
+            Keyspace::Locking(storage) => {
+                let (storage, requests) = (storage.clone(), requests.to_vec());
+                unblocked(move || storage.execute_batch(&requests, proceed)).await
+            }
+            Keyspace::SharedNothing(pool) => pool.execute_batch(requests, proceed).await,
+        }
+    }
+
+    /// Runs `f` with exclusive access to the whole keyspace, handing it an
+    /// executor for as many commands as it likes. Blocks the calling
+    /// thread, so async code should reach it through `spawn_blocking`.
+    pub fn atomically<T>(&self, f: impl FnOnce(&mut Executor<'_>) -> T) -> T {
+        match self {
+            Keyspace::Locking(storage) => storage.atomically(f),
+            Keyspace::SharedNothing(pool) => pool.atomically(f),
+        }
+    }
+
+    /// Serializes the whole keyspace, calling `at` while nothing can
+    /// write so what it reads matches the snapshot. Blocks the calling
+    /// thread, like `atomically`.
+    pub fn snapshot<T>(&self, at: impl FnOnce() -> T) -> (String, T) {
+        match self {
+            Keyspace::Locking(storage) => storage.snapshot(at),
+            Keyspace::SharedNothing(pool) => pool.snapshot(at),
+        }
+    }
+
+    /// Replaces the whole keyspace with a snapshot's keys, atomically.
+    /// Blocks the calling thread, like `atomically`.
+    pub fn load_snapshot(&self, records: Vec<Record>) {
+        match self {
+            Keyspace::Locking(storage) => storage.load_snapshot(records),
+            Keyspace::SharedNothing(pool) => pool.load_snapshot(records),
+        }
+    }
+
+    /// Runs `f` on the shard holding `key`.
+    async fn with_key_shard<T: Send + 'static>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut Storage) -> T + Send + 'static,
+    ) -> T {
+        match self {
+            Keyspace::Locking(storage) => {
+                let storage = storage.clone();
+                let shard = storage.shard_of(key);
+                unblocked(move || storage.with_shard(shard, f)).await
+            }
+            Keyspace::SharedNothing(pool) => pool.with_shard(pool.shard_of(key), f).await,
+        }
+    }
+
+    /// Whether a key exists, without touching the hit and miss counters.
+    pub async fn contains(&self, key: &str) -> bool {
+        let owned = key.to_string();
+        self.with_key_shard(key, move |storage| storage.contains(&owned))
+            .await
+    }
+
+    /// A key's DUMP payload and absolute expire time, if it exists.
+    pub async fn dump(&self, key: &str) -> Option<(String, Option<u64>)> {
+        let owned = key.to_string();
+        self.with_key_shard(key, move |storage| storage.dump(&owned))
+            .await
+    }
+
+    /// The last ID added to the stream at `key`, if there is one.
+    pub async fn last_stream_id(&self, key: &str) -> StorageResult<Option<StreamId>> {
+        let owned = key.to_string();
+        self.with_key_shard(key, move |storage| storage.last_stream_id(&owned))
+            .await
+    }
+
+    /// Every live key passing `matches`, one shard at a time, so the result
+    /// isn't a consistent view of a keyspace being written to.
+    pub async fn keys_where(
+        &self,
+        matches: impl Fn(&str) -> bool + Clone + Send + 'static,
+    ) -> Vec<String> {
+        let mut keys = Vec::new();
+        match self {
+            Keyspace::Locking(storage) => {
+                for shard in 0..storage.shard_count() {
+                    let (storage, matches) = (storage.clone(), matches.clone());
+                    keys.extend(
+                        unblocked(move || storage.with_shard(shard, |s| s.keys_where(matches)))
+                            .await,
+                    );
+                }
+            }
+            Keyspace::SharedNothing(pool) => {
+                for shard in 0..pool.shard_count() {
+                    let matches = matches.clone();
+                    keys.extend(pool.with_shard(shard, move |s| s.keys_where(matches)).await);
+                }
+            }
+        }
+        keys
+    }
+
+    /// Has writes propagated to `feed` from now on.
+    pub fn set_feed(&self, feed: &Arc<Feed>) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_feed(feed),
+            Keyspace::SharedNothing(pool) => pool.set_feed(feed),
+        }
+    }
+
+    /// Has changed keys reported to `tracking` from now on.
+    pub fn set_tracking(&self, tracking: &Arc<Tracking>) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_tracking(tracking),
+            Keyspace::SharedNothing(pool) => pool.set_tracking(tracking),
+        }
+    }
+
+    /// Has changed keys reported to `watches` from now on.
+    pub fn set_watches(&self, watches: &Arc<Watches>) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_watches(watches),
+            Keyspace::SharedNothing(pool) => pool.set_watches(watches),
+        }
+    }
+
+    /// Has keyspace events published to `pubsub` from now on.
+    pub fn set_pubsub(&self, pubsub: &Arc<PubSub>) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_pubsub(pubsub),
+            Keyspace::SharedNothing(pool) => pool.set_pubsub(pubsub),
+        }
+    }
+
+    /// Has written keys reported to `blocking` from now on.
+    pub fn set_blocking(&self, blocking: &Arc<Blocking>) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_blocking(blocking),
+            Keyspace::SharedNothing(pool) => pool.set_blocking(blocking),
+        }
+    }
+
+    pub fn used_memory(&self) -> usize {
+        match self {
+            Keyspace::Locking(storage) => storage.used_memory(),
+            Keyspace::SharedNothing(pool) => pool.used_memory(),
+        }
+    }
+
+    pub fn peak_memory(&self) -> usize {
+        match self {
+            Keyspace::Locking(storage) => storage.peak_memory(),
+            Keyspace::SharedNothing(pool) => pool.peak_memory(),
+        }
+    }
+
+    pub fn maxmemory(&self) -> usize {
+        match self {
+            Keyspace::Locking(storage) => storage.maxmemory(),
+            Keyspace::SharedNothing(pool) => pool.maxmemory(),
+        }
+    }
+
+    pub fn maxmemory_policy(&self) -> EvictionPolicy {
+        match self {
+            Keyspace::Locking(storage) => storage.maxmemory_policy(),
+            Keyspace::SharedNothing(pool) => pool.maxmemory_policy(),
+        }
+    }
+
+    pub fn keys_count(&self) -> usize {
+        match self {
+            Keyspace::Locking(storage) => storage.keys_count(),
+            Keyspace::SharedNothing(pool) => pool.keys_count(),
+        }
+    }
+
+    pub fn expires_count(&self) -> usize {
+        match self {
+            Keyspace::Locking(storage) => storage.expires_count(),
+            Keyspace::SharedNothing(pool) => pool.expires_count(),
+        }
+    }
+
+    pub fn stats(&self) -> StorageStats {
+        match self {
+            Keyspace::Locking(storage) => storage.stats(),
+            Keyspace::SharedNothing(pool) => pool.stats(),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        match self {
+            Keyspace::Locking(storage) => storage.reset_stats(),
+            Keyspace::SharedNothing(pool) => pool.reset_stats(),
+        }
+    }
+
+    pub fn set_maxmemory(&self, bytes: usize) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_maxmemory(bytes),
+            Keyspace::SharedNothing(pool) => pool.set_maxmemory(bytes),
+        }
+    }
+
+    pub fn set_maxmemory_policy(&self, policy: EvictionPolicy) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_maxmemory_policy(policy),
+            Keyspace::SharedNothing(pool) => pool.set_maxmemory_policy(policy),
+        }
+    }
+
+    pub fn set_maxmemory_samples(&self, samples: usize) {
+        match self {
+            Keyspace::Locking(storage) => storage.set_maxmemory_samples(samples),
+            Keyspace::SharedNothing(pool) => pool.set_maxmemory_samples(samples),
+        }
+    }
+}
+
+/// Runs `f` where it may block on shard locks, held as long as a script
+/// or transaction runs, without stalling the runtime thread the caller's
+/// connection shares with others. A panic carries on in the caller.
+async fn unblocked<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::command::resolve;
+    use crate::test_support::cmd;
+
+    #[tokio::test]
+    async fn test_waiting_for_shards_leaves_the_runtime_free() {
+        let keyspace = Arc::new(Keyspace::default());
+        let (held, is_held) = oneshot::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let holder = keyspace.clone();
+        let script = tokio::task::spawn_blocking(move || {
+            holder.atomically(|_| {
+                held.send(()).unwrap();
+                released.recv().unwrap();
+            })
+        });
+        is_held.await.unwrap();
+        let waiter = keyspace.clone();
+        let waiting = tokio::spawn(async move {
+            let args = cmd(&["set", "a", "1"]);
+            waiter.execute(resolve(&args).unwrap(), &args).await
+        });
+        // The runtime has one thread, which the waiting SET mustn't take
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        release.send(()).unwrap();
+        assert_eq!(
+            waiting.await.unwrap(),
+            Ok(RESP::SimpleString("OK".to_string()))
+        );
+        script.await.unwrap();
+    }
+}
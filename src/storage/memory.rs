@@ -0,0 +1,74 @@
+use std::mem::size_of;
+
+use super::{PrimitiveStorageValue, StorageEntry, StorageValue};
+use crate::ds::list::{Deque, List};
+
+/// Approximate bytes used by a value, including its heap allocations.
+///
+/// This is not what the allocator actually hands out, but it moves in
+/// the same direction and is cheap enough to maintain incrementally.
+pub trait MemoryUsage {
+    fn memory_usage(&self) -> usize;
+}
+
+/// Per-key bookkeeping: the hash map slot, the dense key vector in `Dict`,
+/// and the `StorageEntry` header.
+pub const ENTRY_OVERHEAD: usize =
+    2 * size_of::<String>() + size_of::<usize>() + size_of::<StorageEntry>();
+
+/// Extra bytes for keys with a TTL, which live in a second dict.
+pub const EXPIRE_OVERHEAD: usize = 2 * size_of::<String>() + 2 * size_of::<u64>();
+
+impl MemoryUsage for PrimitiveStorageValue {
+    fn memory_usage(&self) -> usize {
+        match self {
+            PrimitiveStorageValue::String(s) => s.len(),
+            // Integers are stored inline
+            PrimitiveStorageValue::Integer(_) => 0,
+            PrimitiveStorageValue::Bytes(b) => b.len(),
+        }
+    }
+}
+
+impl MemoryUsage for List<PrimitiveStorageValue> {
+    fn memory_usage(&self) -> usize {
+        let elements: usize = self.iter().map(|v| v.memory_usage()).sum();
+        self.pages() * List::<PrimitiveStorageValue>::PAGE_BYTES + elements
+    }
+}
+
+impl MemoryUsage for StorageValue {
+    fn memory_usage(&self) -> usize {
+        match self {
+            StorageValue::Primitive(p) => p.memory_usage(),
+            StorageValue::List(l) => l.memory_usage(),
+            StorageValue::Stream(s) => s.memory_usage(),
+            StorageValue::SortedSet(z) => z.memory_usage(),
+            StorageValue::Json(doc) => doc.memory_usage(),
+            StorageValue::Bloom(filter) => filter.memory_usage(),
+            StorageValue::Cuckoo(filter) => filter.memory_usage(),
+            StorageValue::CountMin(sketch) => sketch.memory_usage(),
+            StorageValue::TopK(topk) => topk.memory_usage(),
+            StorageValue::TimeSeries(series) => series.memory_usage(),
+            StorageValue::VectorSet(set) => set.memory_usage(),
+        }
+    }
+}
+
+/// Like `memory_usage`, but for aggregate values only measures the first
+/// `samples` elements and extrapolates to the rest. 0 samples everything.
+pub fn sampled_memory_usage(value: &StorageValue, samples: usize) -> usize {
+    match value {
+        StorageValue::List(l) if samples > 0 && samples < l.len() => {
+            let sampled: usize = l.iter().take(samples).map(|v| v.memory_usage()).sum();
+            let elements = sampled * l.len() / samples;
+            l.pages() * List::<PrimitiveStorageValue>::PAGE_BYTES + elements
+        }
+        _ => value.memory_usage(),
+    }
+}
+
+/// Bytes charged to `used_memory` for a key holding `value`.
+pub fn entry_memory_usage(key: &str, value: &StorageValue) -> usize {
+    ENTRY_OVERHEAD + key.len() + value.memory_usage()
+}
@@ -1,27 +1,119 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-mod result;
+use rand::{SeedableRng, rngs::SmallRng};
 
+mod bitmap;
+mod eviction;
+mod expire;
+mod filter;
+mod geo;
+mod hyperloglog;
+mod introspection;
+mod json;
+mod keyspace;
+mod memory;
+mod pool;
+pub mod result;
+mod search;
+mod sharded;
+mod sketch;
+mod snapshot;
+mod stream;
+mod timeseries;
+mod vectorset;
+
+pub use self::eviction::EvictionPolicy;
+use self::eviction::{DEFAULT_MAXMEMORY_SAMPLES, EvictionPool, LFU_INIT_VAL};
+use self::expire::Unit;
+pub use self::keyspace::Keyspace;
+use self::memory::{EXPIRE_OVERHEAD, MemoryUsage, entry_memory_usage};
+pub use self::pool::ShardPool;
+use self::search::SearchIndex;
+pub use self::sharded::{DEFAULT_SHARDS, Executor, ShardedStorage};
+pub use self::snapshot::{Record, parse_snapshot};
+pub use self::stream::StreamRead;
 use super::storage::result::{StorageError, StorageResult};
+use crate::blocking::Blocking;
+use crate::command::{self, Command, CommandArg, CommandSpec, flags};
+use crate::ds::bloom::ScalableBloom;
+use crate::ds::countmin::CountMinSketch;
+use crate::ds::cuckoo::CuckooFilter;
+use crate::ds::dict::Dict;
+use crate::ds::json::Document;
 use crate::ds::list::{Deque, List};
+use crate::ds::sortedset::SortedSet;
+use crate::ds::stream::Stream;
+use crate::ds::timeseries::TimeSeries;
+use crate::ds::topk::TopK;
+use crate::ds::vectorset::VectorSet;
+use crate::pubsub::{PubSub, notify};
+use crate::replication::Feed;
 use crate::resp::RESP;
+use crate::tracking::Tracking;
+use crate::transaction::Watches;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum PrimitiveStorageValue {
     String(String),
     Integer(i64),
+    /// A string that isn't valid UTF-8, as bit operations and HyperLogLogs
+    /// write them and clients can send them back
+    Bytes(Vec<u8>),
+}
+
+impl From<CommandArg> for PrimitiveStorageValue {
+    fn from(arg: CommandArg) -> Self {
+        if arg.is_utf8() {
+            PrimitiveStorageValue::String(arg.into_string())
+        } else {
+            PrimitiveStorageValue::Bytes(arg.into_bytes())
+        }
+    }
+}
+
+impl PrimitiveStorageValue {
+    /// The value's bytes, as bit operations see them.
+    pub fn as_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            PrimitiveStorageValue::String(s) => Cow::Borrowed(s.as_bytes()),
+            PrimitiveStorageValue::Integer(n) => Cow::Owned(n.to_string().into_bytes()),
+            PrimitiveStorageValue::Bytes(b) => Cow::Borrowed(b),
+        }
+    }
 }
 
 pub enum StorageValue {
     Primitive(PrimitiveStorageValue),
     List(List<PrimitiveStorageValue>),
+    Stream(Stream),
+    SortedSet(SortedSet),
+    Json(Document),
+    Bloom(ScalableBloom),
+    Cuckoo(CuckooFilter),
+    CountMin(CountMinSketch),
+    TopK(TopK),
+    TimeSeries(TimeSeries),
+    VectorSet(VectorSet),
+}
+
+/// A value plus the access metadata used by eviction.
+pub struct StorageEntry {
+    value: StorageValue,
+    /// Last access, for LRU and for LFU counter decay
+    lru: Instant,
+    /// Logarithmic access frequency counter, for LFU
+    lfu: u8,
 }
 
 impl From<PrimitiveStorageValue> for RESP {
     fn from(value: PrimitiveStorageValue) -> RESP {
         match value {
-            PrimitiveStorageValue::String(s) => RESP::BulkString(s),
+            PrimitiveStorageValue::String(s) => RESP::BulkString(s.into()),
             PrimitiveStorageValue::Integer(i) => RESP::Integer(i),
+            PrimitiveStorageValue::Bytes(b) => RESP::BulkString(b),
         }
     }
 }
@@ -38,189 +130,628 @@ impl From<i64> for StorageValue {
     }
 }
 
+impl From<StorageValue> for StorageEntry {
+    fn from(value: StorageValue) -> Self {
+        StorageEntry {
+            value,
+            lru: Instant::now(),
+            lfu: LFU_INIT_VAL,
+        }
+    }
+}
+
+impl From<String> for StorageEntry {
+    fn from(value: String) -> Self {
+        StorageValue::from(value).into()
+    }
+}
+
+/// Milliseconds since the Unix epoch, the unit TTLs are stored in.
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Counters reported by INFO stats.
+#[derive(Default, Clone)]
+pub struct StorageStats {
+    pub keyspace_hits: u64,
+    pub keyspace_misses: u64,
+    pub expired_keys: u64,
+    pub evicted_keys: u64,
+}
+
 pub struct Storage {
-    store: HashMap<String, StorageValue>,
+    store: Dict<String, StorageEntry>,
+    /// Absolute expire time in unix milliseconds for keys that have a TTL
+    expires: Dict<String, u64>,
+    used_memory: usize,
+    peak_memory: usize,
+    /// 0 means no limit
+    maxmemory: usize,
+    policy: EvictionPolicy,
+    maxmemory_samples: usize,
+    eviction_pool: EvictionPool,
+    stats: StorageStats,
+    rng: SmallRng,
+    /// Where writes are propagated to replicas
+    feed: Option<Arc<Feed>>,
+    /// Where changed keys are reported for client-side caching
+    tracking: Option<Arc<Tracking>>,
+    /// Where changed keys are reported to abort transactions watching them
+    watches: Option<Arc<Watches>>,
+    /// Where keyspace events are published
+    pubsub: Option<Arc<PubSub>>,
+    /// Where written keys are reported to wake blocked clients
+    blocking: Option<Arc<Blocking>>,
+    /// What the running command sends to replicas instead of itself, when
+    /// running it again wouldn't have the same effect
+    propagate_as: Option<Vec<CommandArg>>,
+    /// Set by a write command that turned out to change nothing, like
+    /// EXPIRE on a missing key, so it's neither propagated nor reported
+    unchanged: bool,
+    /// Search indexes by name, updated as the keys they cover are written
+    indexes: BTreeMap<String, SearchIndex>,
+    /// Samples compacted into series this shard doesn't hold, which may be
+    /// on another shard, for the coordinator to pass on
+    foreign_samples: Vec<ForeignSample>,
+}
+
+/// A sample compacted into the series at a key: key, timestamp and value.
+pub(super) type ForeignSample = (String, u64, f64);
+
+/// A key lent to another shard: its value and absolute expire time.
+pub(super) type Lent = (StorageValue, Option<u64>);
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Storage {
     pub fn new() -> Self {
-        let store: HashMap<String, StorageValue> = HashMap::new();
-        Self { store: store }
+        Self {
+            store: Dict::new(),
+            expires: Dict::new(),
+            used_memory: 0,
+            peak_memory: 0,
+            maxmemory: 0,
+            policy: EvictionPolicy::NoEviction,
+            maxmemory_samples: DEFAULT_MAXMEMORY_SAMPLES,
+            eviction_pool: EvictionPool::default(),
+            stats: StorageStats::default(),
+            rng: SmallRng::seed_from_u64(0),
+            feed: None,
+            tracking: None,
+            watches: None,
+            pubsub: None,
+            blocking: None,
+            propagate_as: None,
+            unchanged: false,
+            indexes: BTreeMap::new(),
+            foreign_samples: Vec::new(),
+        }
     }
 
-    pub fn process_command(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
-        match command[0].to_lowercase().as_str() {
-            "get" => self.command_get(&command),
-            "mget" => self.command_mget(&command),
-            "set" => self.command_set(&command),
-            "mset" => self.command_mset(&command),
-            "del" => self.command_del(&command),
-            "incr" => self.command_incr(&command),
-            "llen" => self.command_llen(&command),
-            "lpush" => self.command_lpush(&command),
-            "lpop" => self.command_lpop(&command),
-            "rpush" => self.command_rpush(&command),
-            "rpop" => self.command_rpop(&command),
-            _ => Err(StorageError::CommandNotAvailable(command[0].clone())),
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    pub fn peak_memory(&self) -> usize {
+        self.peak_memory
+    }
+
+    pub fn maxmemory(&self) -> usize {
+        self.maxmemory
+    }
+
+    pub fn maxmemory_policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    pub fn keys_count(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn expires_count(&self) -> usize {
+        self.expires.len()
+    }
+
+    pub fn stats(&self) -> &StorageStats {
+        &self.stats
+    }
+
+    /// Clears counters for CONFIG RESETSTAT. Peak memory restarts from
+    /// the current usage.
+    pub fn reset_stats(&mut self) {
+        self.stats = StorageStats::default();
+        self.peak_memory = self.used_memory;
+    }
+
+    pub fn set_maxmemory(&mut self, bytes: usize) {
+        self.maxmemory = bytes;
+    }
+
+    pub fn set_maxmemory_policy(&mut self, policy: EvictionPolicy) {
+        self.policy = policy;
+        self.eviction_pool.clear();
+    }
+
+    pub fn set_maxmemory_samples(&mut self, samples: usize) {
+        self.maxmemory_samples = samples.max(1);
+    }
+
+    pub fn set_feed(&mut self, feed: Arc<Feed>) {
+        self.feed = Some(feed);
+    }
+
+    pub fn set_tracking(&mut self, tracking: Arc<Tracking>) {
+        self.tracking = Some(tracking);
+    }
+
+    pub fn set_watches(&mut self, watches: Arc<Watches>) {
+        self.watches = Some(watches);
+    }
+
+    /// Tells clients caching or watching `key` that it changed.
+    fn invalidate(&self, key: &str) {
+        if let Some(tracking) = &self.tracking {
+            tracking.invalidate(key);
+        }
+        if let Some(watches) = &self.watches {
+            watches.touch(key);
         }
     }
 
-    fn command_set(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    pub fn set_pubsub(&mut self, pubsub: Arc<PubSub>) {
+        self.pubsub = Some(pubsub);
+    }
+
+    /// Publishes a keyspace event of `class`, if `notify-keyspace-events`
+    /// asks for it.
+    fn notify(&self, class: u32, event: &str, key: &str) {
+        if let Some(pubsub) = &self.pubsub {
+            pubsub.notify_keyspace_event(class, event, key);
+        }
+    }
+
+    pub fn set_blocking(&mut self, blocking: Arc<Blocking>) {
+        self.blocking = Some(blocking);
+    }
+
+    /// Wakes clients blocked on `key`.
+    fn signal_ready(&self, key: &str) {
+        if let Some(blocking) = &self.blocking {
+            blocking.signal(key);
+        }
+    }
+
+    /// Sends a write on to replicas. Runs while this shard is locked, so
+    /// writes to the same key are propagated in the order they happened.
+    fn propagate(&self, command: &[CommandArg]) {
+        if let Some(feed) = &self.feed {
+            feed.propagate(command);
+        }
+    }
+
+    /// Propagates the removal of a key the master decided to drop, so
+    /// replicas don't need clocks or memory limits of their own to agree.
+    fn propagate_del(&self, key: &str) {
+        self.propagate(&["DEL".into(), key.into()]);
+        self.invalidate(key);
+    }
+
+    pub fn process_command(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let Some(spec) = command::lookup(&command[0]) else {
+            return Err(StorageError::CommandNotAvailable(command[0].to_string()));
+        };
+        let spec = command
+            .get(1)
+            .and_then(|sub| spec.subcommand(sub))
+            .unwrap_or(spec);
+        self.execute(spec, command)
+    }
+
+    /// Runs a request already resolved to `spec`.
+    pub fn execute(&mut self, spec: &CommandSpec, command: &[CommandArg]) -> StorageResult<RESP> {
+        // Commands that may grow memory usage are rejected when over
+        // `maxmemory` and eviction can't make room
+        if let Err(e) = self.free_memory_if_needed()
+            && spec.has_flag(flags::DENYOOM)
+        {
+            return Err(e);
+        }
+        let result = match spec.command {
+            Command::Get => self.command_get(command),
+            Command::MGet => self.command_mget(command),
+            Command::Set => self.command_set(command),
+            Command::MSet => self.command_mset(command),
+            Command::Del => self.command_del(command),
+            Command::Incr => self.command_incr(command),
+            Command::LLen => self.command_llen(command),
+            Command::LPush => self.command_lpush(command),
+            Command::LPop => self.command_lpop(command),
+            Command::RPush => self.command_rpush(command),
+            Command::RPop => self.command_rpop(command),
+            Command::Memory => self.command_memory(command),
+            Command::Object => self.command_object(command),
+            Command::Debug => self.command_debug(command),
+            Command::Dump => self.command_dump(command),
+            Command::Restore => self.command_restore(command),
+            Command::Expire => self.command_expire(command, Unit::Seconds, false),
+            Command::PExpire => self.command_expire(command, Unit::Milliseconds, false),
+            Command::ExpireAt => self.command_expire(command, Unit::Seconds, true),
+            Command::PExpireAt => self.command_expire(command, Unit::Milliseconds, true),
+            Command::Ttl => Ok(self.command_ttl(command, Unit::Seconds)),
+            Command::PTtl => Ok(self.command_ttl(command, Unit::Milliseconds)),
+            Command::Persist => Ok(self.command_persist(command)),
+            Command::SetEx => self.command_setex(command, Unit::Seconds),
+            Command::PSetEx => self.command_setex(command, Unit::Milliseconds),
+            Command::SetBit => self.command_setbit(command),
+            Command::GetBit => self.command_getbit(command),
+            Command::BitCount => self.command_bitcount(command),
+            Command::BitPos => self.command_bitpos(command),
+            Command::BitOp => self.command_bitop(command),
+            Command::BitField => self.command_bitfield(command, false),
+            Command::BitFieldRo => self.command_bitfield(command, true),
+            Command::PfAdd => self.command_pfadd(command),
+            Command::PfCount => self.command_pfcount(command),
+            Command::PfMerge => self.command_pfmerge(command),
+            Command::PfDebug => self.command_pfdebug(command),
+            Command::PfSelfTest => self.command_pfselftest(),
+            Command::GeoAdd => self.command_geoadd(command),
+            Command::GeoDist => self.command_geodist(command),
+            Command::GeoPos => self.command_geopos(command),
+            Command::GeoHash => self.command_geohash(command),
+            Command::GeoSearch => self.command_geosearch(command, false),
+            Command::GeoSearchStore => self.command_geosearch(command, true),
+            Command::JsonSet => self.command_json_set(command),
+            Command::JsonGet => self.command_json_get(command),
+            Command::JsonDel => self.command_json_del(command),
+            Command::JsonMGet => self.command_json_mget(command),
+            Command::JsonNumIncrBy => self.command_json_numincrby(command),
+            Command::JsonStrAppend => self.command_json_strappend(command),
+            Command::JsonArrAppend => self.command_json_arrappend(command),
+            Command::JsonArrInsert => self.command_json_arrinsert(command),
+            Command::JsonArrPop => self.command_json_arrpop(command),
+            Command::JsonArrLen => self.command_json_arrlen(command),
+            Command::JsonObjKeys => self.command_json_objkeys(command),
+            Command::JsonType => self.command_json_type(command),
+            Command::BfReserve => self.command_bf_reserve(command),
+            Command::BfAdd => self.command_bf_add(command),
+            Command::BfMAdd => self.command_bf_madd(command),
+            Command::BfExists => self.command_bf_exists(command, false),
+            Command::BfMExists => self.command_bf_exists(command, true),
+            Command::BfInfo => self.command_bf_info(command),
+            Command::CfReserve => self.command_cf_reserve(command),
+            Command::CfAdd => self.command_cf_add(command, false),
+            Command::CfAddNx => self.command_cf_add(command, true),
+            Command::CfInsert => self.command_cf_insert(command, false),
+            Command::CfInsertNx => self.command_cf_insert(command, true),
+            Command::CfExists => self.command_cf_exists(command, false),
+            Command::CfMExists => self.command_cf_exists(command, true),
+            Command::CfDel => self.command_cf_del(command),
+            Command::CfCount => self.command_cf_count(command),
+            Command::CfInfo => self.command_cf_info(command),
+            Command::CmsInitByDim => self.command_cms_initbydim(command),
+            Command::CmsInitByProb => self.command_cms_initbyprob(command),
+            Command::CmsIncrBy => self.command_cms_incrby(command),
+            Command::CmsQuery => self.command_cms_query(command),
+            Command::CmsMerge => self.command_cms_merge(command),
+            Command::CmsInfo => self.command_cms_info(command),
+            Command::TopKReserve => self.command_topk_reserve(command),
+            Command::TopKAdd => self.command_topk_add(command),
+            Command::TopKIncrBy => self.command_topk_incrby(command),
+            Command::TopKQuery => self.command_topk_query(command),
+            Command::TopKList => self.command_topk_list(command),
+            Command::TopKInfo => self.command_topk_info(command),
+            Command::TsCreate => self.command_ts_create(command),
+            Command::TsAlter => self.command_ts_alter(command),
+            Command::TsAdd => self.command_ts_add(command),
+            Command::TsMAdd => self.command_ts_madd(command),
+            Command::TsGet => self.command_ts_get(command),
+            Command::TsRange => self.command_ts_range(command, false),
+            Command::TsRevRange => self.command_ts_range(command, true),
+            Command::TsMRange => self.command_ts_mrange(command, false),
+            Command::TsMRevRange => self.command_ts_mrange(command, true),
+            Command::TsQueryIndex => self.command_ts_queryindex(command),
+            Command::TsCreateRule => self.command_ts_createrule(command),
+            Command::TsDeleteRule => self.command_ts_deleterule(command),
+            Command::TsInfo => self.command_ts_info(command),
+            Command::VAdd => self.command_vadd(command),
+            Command::VRem => self.command_vrem(command),
+            Command::VSim => self.command_vsim(command),
+            Command::VCard => self.command_vcard(command),
+            Command::VDim => self.command_vdim(command),
+            Command::VEmb => self.command_vemb(command),
+            Command::VSetAttr => self.command_vsetattr(command),
+            Command::VGetAttr => self.command_vgetattr(command),
+            Command::VInfo => self.command_vinfo(command),
+            Command::FtCreate => self.command_ft_create(command),
+            Command::FtDropIndex => self.command_ft_dropindex(command),
+            Command::FtList => self.command_ft_list(),
+            Command::FtInfo => self.command_ft_info(command),
+            Command::FtSearch => self.command_ft_search(command),
+            Command::FtAggregate => self.command_ft_aggregate(command),
+            Command::XAdd => self.command_xadd(command),
+            Command::XLen => self.command_xlen(command),
+            Command::XRange => self.command_xrange(command, false),
+            Command::XRevRange => self.command_xrange(command, true),
+            Command::XDel => self.command_xdel(command),
+            Command::XTrim => self.command_xtrim(command),
+            Command::XRead | Command::XReadGroup => self.command_xread(command),
+            Command::XGroup => self.command_xgroup(command),
+            Command::XAck => self.command_xack(command),
+            Command::XPending => self.command_xpending(command),
+            Command::XClaim => self.command_xclaim(command),
+            Command::XAutoClaim => self.command_xautoclaim(command),
+            Command::XInfo => self.command_xinfo(command),
+            _ => Err(StorageError::CommandNotAvailable(command[0].to_string())),
+        };
+        let propagate_as = self.propagate_as.take();
+        let unchanged = std::mem::take(&mut self.unchanged);
+        if result.is_ok() && spec.has_flag(flags::WRITE) && !unchanged {
+            self.propagate(propagate_as.as_deref().unwrap_or(command));
+            for key in spec.keys(command) {
+                self.invalidate(key);
+                self.signal_ready(key);
+                self.reindex(key);
+            }
+        }
+        self.peak_memory = self.peak_memory.max(self.used_memory);
+        result
+    }
+
+    /// Runs a request every shard runs, like FT.CREATE, without sending it
+    /// to replicas. They run it on all of their shards from the one copy
+    /// the first shard sends.
+    pub(super) fn execute_unpropagated(
+        &mut self,
+        spec: &CommandSpec,
+        command: &[CommandArg],
+    ) -> StorageResult<RESP> {
+        let feed = self.feed.take();
+        let result = self.execute(spec, command);
+        self.feed = feed;
+        result
+    }
+
+    /// Looks up a key for reading or writing, expiring it first if its TTL
+    /// has passed and recording the access for eviction.
+    fn lookup(&mut self, key: &str) -> Option<&mut StorageEntry> {
+        self.expire_if_needed(key);
+        let entry = self.store.get_mut(key)?;
+        entry.touch(&mut self.rng);
+        Some(entry)
+    }
+
+    /// Like `lookup`, but counts keyspace hits and misses. Only used by
+    /// commands that read the value without creating it.
+    fn lookup_read(&mut self, key: &str) -> Option<&mut StorageEntry> {
+        if self.lookup(key).is_some() {
+            self.stats.keyspace_hits += 1;
+        } else {
+            self.stats.keyspace_misses += 1;
+            self.notify(notify::KEY_MISS, "keymiss", key);
+        }
+        self.store.get_mut(key)
+    }
+
+    fn expire_if_needed(&mut self, key: &str) {
+        match self.expires.get(key) {
+            Some(at) if *at <= now_ms() => {
+                self.remove(key);
+                self.stats.expired_keys += 1;
+                self.propagate_del(key);
+                self.notify(notify::EXPIRED, "expired", key);
+            }
+            _ => (),
+        }
+    }
+
+    /// Stores `value` under `key`, replacing any previous value and TTL.
+    fn insert(&mut self, key: String, value: StorageValue) {
+        if self.remove(&key).is_none() {
+            self.notify(notify::NEW, "new", &key);
+        }
+        self.used_memory += entry_memory_usage(&key, &value);
+        self.store.insert(key, value.into());
+    }
+
+    fn remove(&mut self, key: &str) -> Option<StorageValue> {
+        let entry = self.store.remove(key)?;
+        let size = entry_memory_usage(key, &entry.value);
+        self.used_memory = self.used_memory.saturating_sub(size);
+        if self.expires.remove(key).is_some() {
+            self.used_memory = self.used_memory.saturating_sub(EXPIRE_OVERHEAD + key.len());
+        }
+        self.unindex(key);
+        Some(entry.value)
+    }
+
+    /// Takes a key out, TTL included, for a command another shard runs.
+    /// Nothing is notified or propagated, as the key is only passing
+    /// through.
+    pub(super) fn lend(&mut self, key: &str) -> Option<Lent> {
+        self.expire_if_needed(key);
+        let expires_at = self.expires.get(key).copied();
+        self.remove(key).map(|value| (value, expires_at))
+    }
+
+    /// Takes in a key another shard lent, or gives back.
+    pub(super) fn receive(&mut self, key: String, (value, expires_at): Lent) {
+        self.used_memory += entry_memory_usage(&key, &value);
+        self.store.insert(key.clone(), value.into());
+        if let Some(at) = expires_at {
+            self.expire_at(&key, at);
+        }
+        self.reindex(&key);
+    }
+
+    /// The samples compacted into series this shard doesn't hold since
+    /// the last call.
+    pub(super) fn take_foreign_samples(&mut self) -> Vec<ForeignSample> {
+        std::mem::take(&mut self.foreign_samples)
+    }
+
+    /// Whether a key exists, without counting it as a hit or a miss.
+    pub(super) fn contains(&mut self, key: &str) -> bool {
+        self.expire_if_needed(key);
+        self.store.contains_key(key)
+    }
+
+    /// The keys that haven't expired and pass `matches`.
+    pub(super) fn keys_where(&self, matches: impl Fn(&str) -> bool) -> Vec<String> {
+        let now = now_ms();
+        self.store
+            .keys()
+            .filter(|key| self.expires.get(*key).is_none_or(|at| *at > now))
+            .filter(|key| matches(key))
+            .cloned()
+            .collect()
+    }
+
+    /// Sets an absolute expire time, in unix milliseconds, on an existing key.
+    pub fn expire_at(&mut self, key: &str, when: u64) -> bool {
+        if !self.store.contains_key(key) {
+            return false;
+        }
+        if self.expires.insert(key.to_string(), when).is_none() {
+            self.used_memory += EXPIRE_OVERHEAD + key.len();
+        }
+        true
+    }
+
+    fn command_set(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 3 {
-            let command = command.join(" ");
-            return Err(StorageError::CommandSyntaxError(
-                command,
-                "Expected 3 arguments".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
-        let _ = self.set(command[1].clone(), command[2].clone());
+        let _ = self.set(command[1].to_string(), command[2].clone());
+        self.notify(notify::STRING, "set", &command[1]);
         Ok(RESP::SimpleString(String::from("OK")))
     }
 
-    fn set(&mut self, key: String, value: String) -> StorageResult<String> {
-        self.store.insert(
-            key,
-            StorageValue::Primitive(PrimitiveStorageValue::String(value)),
-        );
+    fn set(&mut self, key: String, value: CommandArg) -> StorageResult<String> {
+        self.insert(key, StorageValue::Primitive(value.into()));
         Ok(String::from("OK"))
     }
 
-    fn command_mset(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
-        if command.len() == 1 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected arguments".to_string(),
-            ));
-        }
-        if command.len() % 2 != 1 {
-            let command = command.join(" ");
-            return Err(StorageError::CommandSyntaxError(
-                command,
-                "Expected an even number of arguments".to_string(),
-            ));
+    fn command_mset(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if command.len() == 1 || command.len().is_multiple_of(2) {
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
         for i in (1..command.len()).step_by(2) {
-            let _ = self.set(command[i].clone(), command[i + 1].clone());
+            let _ = self.set(command[i].to_string(), command[i + 1].clone());
+            self.notify(notify::STRING, "set", &command[i]);
         }
         Ok(RESP::SimpleString(String::from("OK")))
     }
 
-    fn command_get(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_get(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 2 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected an argument".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
-        let output = self.get(command[1].clone());
-        match output {
-            Ok(Some(v)) => Ok(RESP::BulkString(v)),
-            Ok(None) => Ok(RESP::Null),
-            Err(_) => Err(StorageError::CommandInternalError(command.join(" "))),
+        match self.get(command[1].to_string())? {
+            Some(v) => Ok(RESP::BulkString(v)),
+            None => Ok(RESP::Null),
         }
     }
 
-    fn get(&self, key: String) -> StorageResult<Option<String>> {
-        match self.store.get(&key) {
-            Some(StorageValue::Primitive(p)) => match p {
-                PrimitiveStorageValue::String(v) => return Ok(Some(v.clone())),
-                PrimitiveStorageValue::Integer(v) => return Ok(Some(v.to_string())),
-            },
-            Some(_) => return Err(StorageError::WrongType),
-            None => return Ok(None),
+    fn get(&mut self, key: String) -> StorageResult<Option<Vec<u8>>> {
+        match self.lookup_read(&key).map(|entry| &entry.value) {
+            Some(StorageValue::Primitive(p)) => Ok(Some(p.as_bytes().into_owned())),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
         }
     }
 
-    fn command_mget(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_mget(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() < 2 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected at least one argument".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
         let mut values = Vec::new();
-        for i in 1..command.len() {
-            let key = command.get(i);
-            if let None = key {
-                values.push(RESP::Null);
-            } else {
-                let key = key.unwrap();
-                let value: RESP = match self.get(key.to_string()) {
-                    Ok(None) => RESP::Null,
-                    Ok(Some(v)) => RESP::BulkString(v),
-                    Err(e) => return Err(e),
-                };
-                values.push(value);
-            }
+        for key in command[1..].iter() {
+            let value: RESP = match self.get(key.to_string()) {
+                Ok(None) => RESP::Null,
+                Ok(Some(v)) => RESP::BulkString(v),
+                Err(e) => return Err(e),
+            };
+            values.push(value);
         }
         Ok(RESP::Array(values))
     }
 
-    fn command_del(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_del(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() < 2 {
-            let command = command.join(" ");
-            return Err(StorageError::CommandSyntaxError(
-                command,
-                "Expected at least one argument".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
         let mut count = 0;
-        for i in 1..command.len() {
-            let key = command.get(i).unwrap();
-            match self.store.remove(key) {
-                Some(_) => {
-                    count += 1;
-                }
-                None => continue,
-            };
+        for key in command[1..].iter() {
+            self.expire_if_needed(key);
+            if self.remove(key).is_some() {
+                self.notify(notify::GENERIC, "del", key);
+                count += 1;
+            }
         }
         Ok(RESP::Integer(count))
     }
 
-    fn command_incr(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_incr(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 2 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected exactly one argument".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
-        let key = command.get(1).unwrap();
-        match self.store.get_mut(key) {
-            Some(StorageValue::Primitive(v)) => match v {
-                PrimitiveStorageValue::String(value) => match value.parse::<i64>() {
-                    Ok(parsed_value) => {
-                        let new_value = parsed_value + 1;
-                        *value = new_value.to_string();
-                        Ok(RESP::Integer(new_value))
+        let result = self.incr(&command[1]);
+        if result.is_ok() {
+            self.notify(notify::STRING, "incrby", &command[1]);
+        }
+        result
+    }
+
+    fn incr(&mut self, key: &str) -> StorageResult<RESP> {
+        if self.lookup(key).is_none() {
+            self.insert(key.to_string(), 1.into());
+            return Ok(RESP::Integer(1));
+        }
+        let entry = self.store.get_mut(key).unwrap();
+        match &mut entry.value {
+            StorageValue::Primitive(v) => {
+                let old_size = v.memory_usage();
+                let new_value = match v {
+                    PrimitiveStorageValue::String(value) => match value.parse::<i64>() {
+                        Ok(parsed_value) => {
+                            let new_value = parsed_value
+                                .checked_add(1)
+                                .ok_or(StorageError::IncrementOverflow)?;
+                            *value = new_value.to_string();
+                            new_value
+                        }
+                        Err(_) => return Err(StorageError::ValueNotInteger),
+                    },
+                    PrimitiveStorageValue::Integer(value) => {
+                        *value = value
+                            .checked_add(1)
+                            .ok_or(StorageError::IncrementOverflow)?;
+                        *value
                     }
-                    Err(_) => Err(StorageError::ValueNotInteger(value.clone())),
-                },
-                PrimitiveStorageValue::Integer(value) => {
-                    *value += 1;
-                    Ok(RESP::Integer(*value))
-                }
-            },
-            Some(_) => Err(StorageError::WrongType),
-            None => {
-                self.store.insert(
-                    key.clone(),
-                    StorageValue::Primitive(PrimitiveStorageValue::Integer(1)),
-                );
-                Ok(RESP::Integer(1))
+                    PrimitiveStorageValue::Bytes(_) => return Err(StorageError::ValueNotInteger),
+                };
+                self.used_memory = self.used_memory + v.memory_usage() - old_size;
+                Ok(RESP::Integer(new_value))
             }
+            _ => Err(StorageError::WrongType),
         }
     }
 
-    fn command_llen(&self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_llen(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 2 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "wrong number of arguments for command".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         };
         let key = command.get(1).unwrap();
-        match self.store.get(key) {
-            Some(value) => match value {
+        match self.lookup_read(key) {
+            Some(entry) => match &entry.value {
                 StorageValue::List(list) => Ok(RESP::Integer(list.len() as i64)),
                 _ => Err(StorageError::WrongType),
             },
@@ -228,97 +759,91 @@ impl Storage {
         }
     }
 
-    fn command_lpush(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_lpush(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 3 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected LPUSH [key] [value]".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
         let key = command.get(1).unwrap();
         let value = command.get(2).unwrap();
-        let storage_value = PrimitiveStorageValue::String(value.to_string());
-        match self.store.get_mut(key) {
-            Some(StorageValue::List(l)) => {
-                l.lpush(storage_value);
-                Ok(RESP::Integer(l.len() as i64))
-            }
-            None => {
-                let mut l = List::new();
-                l.lpush(storage_value);
-                let new_len = l.len();
-                self.store.insert(key.to_string(), StorageValue::List(l));
-                Ok(RESP::Integer(new_len as i64))
-            }
-            Some(_) => Err(StorageError::WrongType),
-        }
+        let storage_value = PrimitiveStorageValue::from(value.clone());
+        self.list_push(key, storage_value, true)
     }
 
-    fn command_lpop(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_lpop(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 2 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected LPOP [key]".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
         let key = command.get(1).unwrap();
-        match self.store.get_mut(key) {
-            Some(StorageValue::List(l)) => {
-                let value = l.lpop();
-                if l.len() == 0 {
-                    self.store.remove(key);
-                }
-                Ok(value.into())
-            }
-            None => Ok(RESP::Null),
-            Some(_) => Err(StorageError::WrongType),
-        }
+        self.list_pop(key, true)
     }
 
-    fn command_rpush(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_rpush(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 3 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected RPUSH [key] [value]".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
         let key = command.get(1).unwrap();
         let value = command.get(2).unwrap();
-        let storage_value = PrimitiveStorageValue::String(value.to_string());
-        match self.store.get_mut(key) {
-            Some(StorageValue::List(l)) => {
-                l.rpush(storage_value);
-                Ok(RESP::Integer(l.len() as i64))
-            }
-            None => {
-                let mut l = List::new();
-                l.rpush(storage_value);
-                let new_len = l.len();
-                self.store.insert(key.to_string(), StorageValue::List(l));
-                Ok(RESP::Integer(new_len as i64))
-            }
-            Some(_) => Err(StorageError::WrongType),
-        }
+        let storage_value = PrimitiveStorageValue::from(value.clone());
+        self.list_push(key, storage_value, false)
     }
 
-    fn command_rpop(&mut self, command: &Vec<String>) -> StorageResult<RESP> {
+    fn command_rpop(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
         if command.len() != 2 {
-            return Err(StorageError::CommandSyntaxError(
-                command.join(" "),
-                "Expected RPOP [key]".to_string(),
-            ));
+            return Err(StorageError::WrongArity(command[0].to_lowercase()));
         }
         let key = command.get(1).unwrap();
-        match self.store.get_mut(key) {
-            Some(StorageValue::List(l)) => {
-                let value = l.rpop();
-                if l.len() == 0 {
-                    self.store.remove(key);
+        self.list_pop(key, false)
+    }
+
+    fn list_push(
+        &mut self,
+        key: &str,
+        value: PrimitiveStorageValue,
+        left: bool,
+    ) -> StorageResult<RESP> {
+        if self.lookup(key).is_none() {
+            self.insert(key.to_string(), StorageValue::List(List::new()));
+        }
+        let entry = self.store.get_mut(key).unwrap();
+        match &mut entry.value {
+            StorageValue::List(l) => {
+                let pages = l.pages();
+                self.used_memory += value.memory_usage();
+                if left {
+                    l.lpush(value);
+                } else {
+                    l.rpush(value);
+                }
+                self.used_memory += (l.pages() - pages) * List::<PrimitiveStorageValue>::PAGE_BYTES;
+                let len = l.len();
+                self.notify(notify::LIST, if left { "lpush" } else { "rpush" }, key);
+                Ok(RESP::Integer(len as i64))
+            }
+            _ => Err(StorageError::WrongType),
+        }
+    }
+
+    fn list_pop(&mut self, key: &str, left: bool) -> StorageResult<RESP> {
+        if self.lookup(key).is_none() {
+            return Ok(RESP::Null);
+        }
+        let entry = self.store.get_mut(key).unwrap();
+        match &mut entry.value {
+            StorageValue::List(l) => {
+                let pages = l.pages();
+                let value = if left { l.lpop() } else { l.rpop() };
+                let freed = value.as_ref().map_or(0, |v| v.memory_usage())
+                    + (pages - l.pages()) * List::<PrimitiveStorageValue>::PAGE_BYTES;
+                self.used_memory = self.used_memory.saturating_sub(freed);
+                let emptied = l.is_empty();
+                self.notify(notify::LIST, if left { "lpop" } else { "rpop" }, key);
+                if emptied {
+                    self.remove(key);
+                    self.notify(notify::GENERIC, "del", key);
                 }
                 Ok(value.into())
             }
-            None => Ok(RESP::Null),
-            Some(_) => Err(StorageError::WrongType),
+            _ => Err(StorageError::WrongType),
         }
     }
 }
@@ -326,6 +851,7 @@ impl Storage {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::cmd;
 
     #[test]
     fn test_create_new() {
@@ -337,9 +863,9 @@ mod tests {
     fn test_process_command_set() {
         let mut storage: Storage = Storage::new();
         let command = vec![
-            String::from("set"),
-            String::from("key"),
-            String::from("value"),
+            CommandArg::from("set"),
+            CommandArg::from("key"),
+            CommandArg::from("value"),
         ];
         let output = storage.process_command(&command).unwrap();
         assert_eq!(output, RESP::SimpleString(String::from("OK")));
@@ -352,9 +878,9 @@ mod tests {
         storage
             .store
             .insert(String::from("akey"), String::from("avalue").into());
-        let command = vec![String::from("get"), String::from("akey")];
+        let command = vec![CommandArg::from("get"), CommandArg::from("akey")];
         let output = storage.process_command(&command).unwrap();
-        assert_eq!(output, RESP::BulkString(String::from("avalue")));
+        assert_eq!(output, RESP::BulkString("avalue".into()));
         assert_eq!(storage.store.len(), 1);
     }
 
@@ -362,17 +888,17 @@ mod tests {
     fn test_process_command_set_and_get() {
         let mut storage: Storage = Storage::new();
         let command = vec![
-            String::from("set"),
-            String::from("key"),
-            String::from("value"),
+            CommandArg::from("set"),
+            CommandArg::from("key"),
+            CommandArg::from("value"),
         ];
         let output = storage.process_command(&command).unwrap();
         assert_eq!(output, RESP::SimpleString(String::from("OK")));
         assert_eq!(storage.store.len(), 1);
 
-        let command = vec![String::from("get"), String::from("key")];
+        let command = vec![CommandArg::from("get"), CommandArg::from("key")];
         let output = storage.process_command(&command).unwrap();
-        assert_eq!(output, RESP::BulkString(String::from("value")));
+        assert_eq!(output, RESP::BulkString("value".into()));
         assert_eq!(storage.store.len(), 1);
     }
 
@@ -387,16 +913,16 @@ mod tests {
             .insert(String::from("akey2"), String::from("avalue2").into());
 
         let command = vec![
-            String::from("mget"),
-            String::from("akey1"),
-            String::from("akey2"),
+            CommandArg::from("mget"),
+            CommandArg::from("akey1"),
+            CommandArg::from("akey2"),
         ];
         let output = storage.process_command(&command).unwrap();
         assert_eq!(
             output,
             RESP::Array(vec![
-                RESP::BulkString(String::from("avalue1")),
-                RESP::BulkString(String::from("avalue2"))
+                RESP::BulkString("avalue1".into()),
+                RESP::BulkString("avalue2".into())
             ])
         );
         assert_eq!(storage.store.len(), 2);
@@ -407,11 +933,11 @@ mod tests {
         let mut storage: Storage = Storage::new();
 
         let command = vec![
-            String::from("mset"),
-            String::from("akey1"),
-            String::from("avalue1"),
-            String::from("akey2"),
-            String::from("avalue2"),
+            CommandArg::from("mset"),
+            CommandArg::from("akey1"),
+            CommandArg::from("avalue1"),
+            CommandArg::from("akey2"),
+            CommandArg::from("avalue2"),
         ];
         let output = storage.process_command(&command).unwrap();
         assert_eq!(output, RESP::SimpleString(String::from("OK")));
@@ -421,8 +947,216 @@ mod tests {
     fn test_process_command_lpop_empty() {
         let mut storage: Storage = Storage::new();
 
-        let command = vec![String::from("lpop"), String::from("akey1")];
+        let command = vec![CommandArg::from("lpop"), CommandArg::from("akey1")];
         let output = storage.process_command(&command).unwrap();
         assert_eq!(output, RESP::Null);
     }
+
+    #[test]
+    fn test_process_command_push_binary() {
+        let mut storage: Storage = Storage::new();
+        let value = vec![0xff, 0xfe, b'\r', 0];
+        for push in ["lpush", "rpush"] {
+            let command = vec![
+                CommandArg::from(push),
+                CommandArg::from("akey1"),
+                CommandArg::from(value.clone()),
+            ];
+            storage.process_command(&command).unwrap();
+        }
+        for pop in ["lpop", "rpop"] {
+            let command = vec![CommandArg::from(pop), CommandArg::from("akey1")];
+            let output = storage.process_command(&command).unwrap();
+            assert_eq!(output, RESP::BulkString(value.clone()));
+        }
+    }
+
+    #[test]
+    fn test_used_memory_tracks_writes() {
+        let mut storage: Storage = Storage::new();
+        assert_eq!(storage.used_memory(), 0);
+        storage
+            .process_command(&cmd(&["set", "key", "value"]))
+            .unwrap();
+        let after_set = storage.used_memory();
+        assert!(after_set > 0);
+        storage
+            .process_command(&cmd(&["set", "key", "a much longer value"]))
+            .unwrap();
+        assert!(storage.used_memory() > after_set);
+        storage.process_command(&cmd(&["del", "key"])).unwrap();
+        assert_eq!(storage.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_used_memory_tracks_list_pages() {
+        let mut storage: Storage = Storage::new();
+        storage
+            .process_command(&cmd(&["rpush", "list", "a"]))
+            .unwrap();
+        let one_page = storage.used_memory();
+        assert!(one_page > List::<PrimitiveStorageValue>::PAGE_BYTES);
+        for _ in 0..5000 {
+            storage
+                .process_command(&cmd(&["rpush", "list", "a"]))
+                .unwrap();
+        }
+        assert!(storage.used_memory() > one_page + List::<PrimitiveStorageValue>::PAGE_BYTES);
+        for _ in 0..5001 {
+            storage.process_command(&cmd(&["lpop", "list"])).unwrap();
+        }
+        assert_eq!(storage.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_noeviction_rejects_writes() {
+        let mut storage: Storage = Storage::new();
+        storage
+            .process_command(&cmd(&["set", "key1", "value"]))
+            .unwrap();
+        storage.set_maxmemory(1);
+        let output = storage.process_command(&cmd(&["set", "key2", "value"]));
+        assert!(matches!(output, Err(StorageError::OutOfMemory)));
+        // Reads and deletes are still allowed
+        let output = storage.process_command(&cmd(&["get", "key1"])).unwrap();
+        assert_eq!(output, RESP::BulkString("value".into()));
+        let output = storage.process_command(&cmd(&["del", "key1"])).unwrap();
+        assert_eq!(output, RESP::Integer(1));
+    }
+
+    #[test]
+    fn test_allkeys_lru_evicts_idle_keys() {
+        let mut storage: Storage = Storage::new();
+        storage.set_maxmemory_policy(EvictionPolicy::AllKeysLru);
+        storage.set_maxmemory_samples(10);
+        for n in 0..10 {
+            storage
+                .process_command(&cmd(&["set", &format!("key{}", n), "value"]))
+                .unwrap();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        storage.process_command(&cmd(&["get", "key3"])).unwrap();
+        storage.set_maxmemory(storage.used_memory() - 1);
+        storage.process_command(&cmd(&["get", "key3"])).unwrap();
+        assert_eq!(storage.store.len(), 9);
+        assert_eq!(storage.stats().evicted_keys, 1);
+        assert!(storage.store.contains_key("key3"));
+    }
+
+    #[test]
+    fn test_allkeys_random_frees_enough_memory() {
+        let mut storage: Storage = Storage::new();
+        storage.set_maxmemory_policy(EvictionPolicy::AllKeysRandom);
+        for n in 0..100 {
+            storage
+                .process_command(&cmd(&["set", &format!("key{}", n), "value"]))
+                .unwrap();
+        }
+        let limit = storage.used_memory() / 2;
+        storage.set_maxmemory(limit);
+        storage
+            .process_command(&cmd(&["set", "new", "value"]))
+            .unwrap();
+        assert!(storage.store.len() < 100);
+        assert!(storage.store.contains_key("new"));
+    }
+
+    #[test]
+    fn test_volatile_ttl_evicts_soonest_expiring() {
+        let mut storage: Storage = Storage::new();
+        storage.set_maxmemory_policy(EvictionPolicy::VolatileTtl);
+        storage.set_maxmemory_samples(20);
+        let now = now_ms();
+        for n in 0..5 {
+            let key = format!("key{}", n);
+            storage
+                .process_command(&cmd(&["set", &key, "value"]))
+                .unwrap();
+            assert!(storage.expire_at(&key, now + 100_000 - n * 1000));
+        }
+        storage
+            .process_command(&cmd(&["set", "persistent", "value"]))
+            .unwrap();
+        storage.set_maxmemory(storage.used_memory() - 1);
+        storage
+            .process_command(&cmd(&["get", "persistent"]))
+            .unwrap();
+        assert!(!storage.store.contains_key("key4"));
+        assert!(storage.store.contains_key("persistent"));
+    }
+
+    #[test]
+    fn test_volatile_without_ttls_is_oom() {
+        let mut storage: Storage = Storage::new();
+        storage.set_maxmemory_policy(EvictionPolicy::VolatileLru);
+        storage
+            .process_command(&cmd(&["set", "key", "value"]))
+            .unwrap();
+        storage.set_maxmemory(1);
+        let output = storage.process_command(&cmd(&["set", "key2", "value"]));
+        assert!(matches!(output, Err(StorageError::OutOfMemory)));
+        assert_eq!(storage.store.len(), 1);
+    }
+
+    #[test]
+    fn test_expired_keys_are_removed_on_access() {
+        let mut storage: Storage = Storage::new();
+        storage
+            .process_command(&cmd(&["set", "key", "value"]))
+            .unwrap();
+        assert!(storage.expire_at("key", now_ms() - 1));
+        let output = storage.process_command(&cmd(&["get", "key"])).unwrap();
+        assert_eq!(output, RESP::Null);
+        assert_eq!(storage.store.len(), 0);
+        assert_eq!(storage.used_memory(), 0);
+        assert_eq!(storage.stats().expired_keys, 1);
+    }
+
+    #[test]
+    fn test_keyspace_hits_and_misses() {
+        let mut storage: Storage = Storage::new();
+        storage
+            .process_command(&cmd(&["set", "key", "value"]))
+            .unwrap();
+        storage
+            .process_command(&cmd(&["mget", "key", "missing"]))
+            .unwrap();
+        storage.process_command(&cmd(&["llen", "missing"])).unwrap();
+        assert_eq!(storage.stats().keyspace_hits, 1);
+        assert_eq!(storage.stats().keyspace_misses, 2);
+        storage.reset_stats();
+        assert_eq!(storage.stats().keyspace_hits, 0);
+    }
+
+    #[test]
+    fn test_errors_carry_redis_prefixes() {
+        let mut storage: Storage = Storage::new();
+        storage
+            .process_command(&cmd(&["lpush", "list", "a"]))
+            .unwrap();
+        let error = storage.process_command(&cmd(&["get", "list"])).unwrap_err();
+        assert_eq!(error, StorageError::WrongType);
+        assert!(error.to_string().starts_with("WRONGTYPE "));
+
+        storage.process_command(&cmd(&["set", "n", "x"])).unwrap();
+        let error = storage.process_command(&cmd(&["incr", "n"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "ERR value is not an integer or out of range"
+        );
+
+        storage
+            .process_command(&cmd(&["set", "n", &i64::MAX.to_string()]))
+            .unwrap();
+        let error = storage.process_command(&cmd(&["incr", "n"])).unwrap_err();
+        assert_eq!(error, StorageError::IncrementOverflow);
+
+        let error = storage
+            .process_command(&cmd(&["mset", "a", "1", "b"]))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "ERR wrong number of arguments for 'mset' command"
+        );
+    }
 }
@@ -0,0 +1,773 @@
+use std::collections::BTreeMap;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+
+use tokio::sync::{mpsc, oneshot};
+
+use super::result::{StorageError, StorageResult};
+use super::sharded::{
+    Counters, Executor, batch_indexes, foreign_samples, is_all_shards, is_memory_report,
+    is_multi_shard, join_replies, lent_keys, memory_reply, merge_replies, shard_indexes, shard_of,
+    shard_request, split_request,
+};
+use super::snapshot::{Record, snapshot_header};
+use super::{EvictionPolicy, ForeignSample, Storage, StorageStats};
+use crate::blocking::Blocking;
+use crate::command::{CommandArg, CommandSpec, flags};
+use crate::pubsub::PubSub;
+use crate::replication::Feed;
+use crate::resp::RESP;
+use crate::tracking::Tracking;
+use crate::transaction::Watches;
+
+/// Work for a shard, which publishes its counters once done so anyone it
+/// answers sees them up to date.
+type Job = Box<dyn FnOnce(&mut Storage, &Counters) + Send>;
+
+enum Message {
+    Run(Job),
+    /// Serve nothing but `jobs` until its sender is dropped, so a
+    /// coordinator can run several steps atomically
+    Hold {
+        ready: oneshot::Sender<()>,
+        jobs: mpsc::UnboundedReceiver<Message>,
+    },
+}
+
+struct Shard {
+    inbox: mpsc::UnboundedSender<Message>,
+    counters: Arc<Counters>,
+}
+
+/// Shared-nothing execution: every shard is a thread running its own
+/// event loop, owning its slice of the keyspace outright. Connections
+/// never touch a `Storage`; they send the owning shard a job and await
+/// the reply.
+///
+/// A command spanning several shards holds each of them, in ascending
+/// order so coordinators can't deadlock, and runs its steps while
+/// nothing else can. `execute_batch` does the same for a group of
+/// commands, which is what a transaction needs.
+///
+/// `maxmemory` limits the shards' combined usage, and keys are evicted
+/// from whichever shards have them.
+pub struct ShardPool {
+    shards: Vec<Shard>,
+    maxmemory: AtomicUsize,
+    policy: Mutex<EvictionPolicy>,
+    /// Shard to evict from next, so eviction takes turns between them
+    next_eviction: AtomicUsize,
+}
+
+/// A shard that runs only what its coordinator sends until dropped.
+struct Held {
+    index: usize,
+    jobs: mpsc::UnboundedSender<Message>,
+}
+
+impl ShardPool {
+    /// One shard per available core by default.
+    pub fn default_shards() -> usize {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    pub fn new(shards: usize) -> Self {
+        let shards = (0..shards.max(1))
+            .map(|i| {
+                let (inbox, messages) = mpsc::unbounded_channel();
+                let counters = Arc::new(Counters::default());
+                let published = counters.clone();
+                thread::Builder::new()
+                    .name(format!("shard-{}", i))
+                    .spawn(move || run_shard(messages, &published))
+                    .expect("failed to spawn shard thread");
+                Shard { inbox, counters }
+            })
+            .collect();
+        Self {
+            shards,
+            maxmemory: AtomicUsize::new(0),
+            policy: Mutex::new(EvictionPolicy::NoEviction),
+            next_eviction: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn shard_of(&self, key: &str) -> usize {
+        shard_of(key, self.shards.len())
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Runs `f` on one shard's thread.
+    pub async fn with_shard<T: Send + 'static>(
+        &self,
+        shard: usize,
+        f: impl FnOnce(&mut Storage) -> T + Send + 'static,
+    ) -> T {
+        call(&self.shards[shard].inbox, f).await
+    }
+
+    /// Queues `job` on a shard without waiting for it.
+    fn send(&self, shard: usize, job: impl FnOnce(&mut Storage) + Send + 'static) {
+        let job: Job = Box::new(move |storage, counters| {
+            job(storage);
+            counters.publish(storage);
+        });
+        // The shard thread only stops when the pool is dropped
+        let _ = self.shards[shard].inbox.send(Message::Run(job));
+    }
+
+    fn sum(&self, counter: impl Fn(&Counters) -> usize) -> usize {
+        self.shards.iter().map(|s| counter(&s.counters)).sum()
+    }
+
+    fn sum_u64(&self, counter: impl Fn(&Counters) -> &AtomicU64) -> u64 {
+        self.shards
+            .iter()
+            .map(|s| counter(&s.counters).load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.sum(|c| c.used_memory.load(Ordering::Relaxed))
+    }
+
+    /// The sum of every shard's own peak, an upper bound on the real one.
+    pub fn peak_memory(&self) -> usize {
+        self.sum(|c| c.peak_memory.load(Ordering::Relaxed))
+    }
+
+    pub fn maxmemory(&self) -> usize {
+        self.maxmemory.load(Ordering::Relaxed)
+    }
+
+    pub fn maxmemory_policy(&self) -> EvictionPolicy {
+        *self.policy.lock().unwrap()
+    }
+
+    pub fn keys_count(&self) -> usize {
+        self.sum(|c| c.keys.load(Ordering::Relaxed))
+    }
+
+    pub fn expires_count(&self) -> usize {
+        self.sum(|c| c.expires.load(Ordering::Relaxed))
+    }
+
+    pub fn stats(&self) -> StorageStats {
+        StorageStats {
+            keyspace_hits: self.sum_u64(|c| &c.keyspace_hits),
+            keyspace_misses: self.sum_u64(|c| &c.keyspace_misses),
+            expired_keys: self.sum_u64(|c| &c.expired_keys),
+            evicted_keys: self.sum_u64(|c| &c.evicted_keys),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        for i in 0..self.shards.len() {
+            self.send(i, |storage| storage.reset_stats());
+        }
+    }
+
+    /// Every shard gets the whole limit: one holding more than that on
+    /// its own has the keyspace over it too.
+    pub fn set_maxmemory(&self, bytes: usize) {
+        self.maxmemory.store(bytes, Ordering::Relaxed);
+        for i in 0..self.shards.len() {
+            self.send(i, move |storage| storage.set_maxmemory(bytes));
+        }
+    }
+
+    pub fn set_maxmemory_policy(&self, policy: EvictionPolicy) {
+        *self.policy.lock().unwrap() = policy;
+        for i in 0..self.shards.len() {
+            self.send(i, move |storage| storage.set_maxmemory_policy(policy));
+        }
+    }
+
+    pub fn set_maxmemory_samples(&self, samples: usize) {
+        for i in 0..self.shards.len() {
+            self.send(i, move |storage| storage.set_maxmemory_samples(samples));
+        }
+    }
+
+    pub fn set_feed(&self, feed: &Arc<Feed>) {
+        for i in 0..self.shards.len() {
+            let feed = feed.clone();
+            self.send(i, move |storage| storage.set_feed(feed));
+        }
+    }
+
+    pub fn set_tracking(&self, tracking: &Arc<Tracking>) {
+        for i in 0..self.shards.len() {
+            let tracking = tracking.clone();
+            self.send(i, move |storage| storage.set_tracking(tracking));
+        }
+    }
+
+    pub fn set_watches(&self, watches: &Arc<Watches>) {
+        for i in 0..self.shards.len() {
+            let watches = watches.clone();
+            self.send(i, move |storage| storage.set_watches(watches));
+        }
+    }
+
+    pub fn set_pubsub(&self, pubsub: &Arc<PubSub>) {
+        for i in 0..self.shards.len() {
+            let pubsub = pubsub.clone();
+            self.send(i, move |storage| storage.set_pubsub(pubsub));
+        }
+    }
+
+    pub fn set_blocking(&self, blocking: &Arc<Blocking>) {
+        for i in 0..self.shards.len() {
+            let blocking = blocking.clone();
+            self.send(i, move |storage| storage.set_blocking(blocking));
+        }
+    }
+
+    /// Serializes every shard while holding all of them. `at` runs before
+    /// they are let go, so what it reads lines up exactly with the
+    /// snapshot. Blocks the calling thread, like `atomically`.
+    pub fn snapshot<T>(&self, at: impl FnOnce() -> T) -> (String, T) {
+        let indexes: Vec<usize> = (0..self.shards.len()).collect();
+        let held = block_on(self.hold(&indexes));
+        let mut snapshot = snapshot_header();
+        for (n, shard) in held.iter().enumerate() {
+            snapshot.push_str(&block_on(call(&shard.jobs, move |storage| {
+                let mut keys = String::new();
+                // Every shard has the same indexes
+                if n == 0 {
+                    storage.write_indexes(&mut keys);
+                }
+                storage.write_snapshot(&mut keys);
+                keys
+            })));
+        }
+        (snapshot, at())
+    }
+
+    /// Replaces everything with the keys of a snapshot. Blocks the calling
+    /// thread, like `atomically`.
+    pub fn load_snapshot(&self, records: Vec<Record>) {
+        let mut by_shard: Vec<Vec<Record>> = (0..self.shards.len()).map(|_| Vec::new()).collect();
+        let mut search_indexes = Vec::new();
+        for record in records {
+            match record.index() {
+                Some(arguments) => search_indexes.push((record.key.clone(), arguments.to_vec())),
+                None => by_shard[self.shard_of(&record.key)].push(record),
+            }
+        }
+        let indexes: Vec<usize> = (0..self.shards.len()).collect();
+        let held = block_on(self.hold(&indexes));
+        for (shard, records) in held.iter().zip(by_shard) {
+            let search_indexes = search_indexes.clone();
+            block_on(call(&shard.jobs, move |storage| {
+                storage.flush();
+                for (name, arguments) in search_indexes {
+                    storage.load_index(&name, &arguments);
+                }
+                for record in records {
+                    storage.load(record);
+                }
+            }));
+        }
+    }
+
+    /// Runs a request already resolved to `spec` on the shards owning its
+    /// keys.
+    pub async fn execute(
+        &self,
+        spec: &'static CommandSpec,
+        args: &[CommandArg],
+    ) -> StorageResult<RESP> {
+        // Make room before holding anything, like a single `Storage` does
+        // before every command, so eviction can reach every shard
+        if let Err(e) = self.free_memory_if_needed().await
+            && spec.has_flag(flags::DENYOOM)
+        {
+            return Err(e);
+        }
+        if is_memory_report(spec) {
+            return Ok(self.memory_report(spec.name).await);
+        }
+        let indexes = shard_indexes(spec, args, self.shards.len());
+        if let [index] = indexes[..] {
+            let args = args.to_vec();
+            let (result, samples) = call(&self.shards[index].inbox, move |storage| {
+                (storage.execute(spec, &args), storage.take_foreign_samples())
+            })
+            .await;
+            let samples = foreign_samples(samples, index, self.shards.len());
+            self.forward_samples(samples.collect()).await;
+            return result;
+        }
+        let held = self.hold(&indexes).await;
+        let result = self.execute_held(&held, spec, args).await;
+        self.release(held).await;
+        result
+    }
+
+    /// Runs requests one after the other with every shard they touch held
+    /// throughout, for EXEC. `proceed` is asked once they're all held, and
+    /// when it says no nothing runs and None comes back.
+    pub async fn execute_batch(
+        &self,
+        requests: &[(&'static CommandSpec, Vec<CommandArg>)],
+        proceed: impl FnOnce() -> bool,
+    ) -> Option<Vec<StorageResult<RESP>>> {
+        // Whatever can't be freed here fails the requests that need it
+        let _ = self.free_memory_if_needed().await;
+        let held = self.hold(&batch_indexes(requests, self.shards.len())).await;
+        if !proceed() {
+            return None;
+        }
+        let mut replies = Vec::with_capacity(requests.len());
+        for (spec, args) in requests {
+            replies.push(self.execute_held(&held, spec, args).await);
+        }
+        self.release(held).await;
+        Some(replies)
+    }
+
+    /// Holds every shard and lets `f` run any number of commands while
+    /// nothing else can, for scripts. Blocks the calling thread, so it
+    /// must not be called from async code.
+    pub fn atomically<T>(&self, f: impl FnOnce(&mut Executor<'_>) -> T) -> T {
+        let indexes: Vec<usize> = (0..self.shards.len()).collect();
+        let held = block_on(self.hold(&indexes));
+        let result = f(&mut |spec, args| block_on(self.execute_held(&held, spec, args)));
+        block_on(self.release(held));
+        result
+    }
+
+    /// Holds `indexes`, which must be ascending, one after the other.
+    async fn hold(&self, indexes: &[usize]) -> Vec<Held> {
+        let mut held = Vec::with_capacity(indexes.len());
+        for &index in indexes {
+            let (ready, acquired) = oneshot::channel();
+            let (jobs, receiver) = mpsc::unbounded_channel();
+            let _ = self.shards[index].inbox.send(Message::Hold {
+                ready,
+                jobs: receiver,
+            });
+            let _ = acquired.await;
+            held.push(Held { index, jobs });
+        }
+        held
+    }
+
+    /// Lets go of held shards, then adds the samples they compacted into
+    /// series on other shards to those, which may not have been held.
+    async fn release(&self, held: Vec<Held>) {
+        let mut samples = Vec::new();
+        for shard in &held {
+            let (index, shards) = (shard.index, self.shards.len());
+            let foreign = call(&shard.jobs, move |storage| {
+                foreign_samples(storage.take_foreign_samples(), index, shards).collect::<Vec<_>>()
+            });
+            samples.extend(foreign.await);
+        }
+        drop(held);
+        self.forward_samples(samples).await;
+    }
+
+    async fn forward_samples(&self, samples: Vec<ForeignSample>) {
+        for (key, timestamp, value) in samples {
+            let inbox = &self.shards[self.shard_of(&key)].inbox;
+            call(inbox, move |storage| {
+                storage.add_compacted(&key, timestamp, value)
+            })
+            .await;
+        }
+    }
+
+    async fn execute_held(
+        &self,
+        held: &[Held],
+        spec: &'static CommandSpec,
+        args: &[CommandArg],
+    ) -> StorageResult<RESP> {
+        let jobs = |index: usize| {
+            &held
+                .iter()
+                .find(|h| h.index == index)
+                .expect("every shard of a request is held")
+                .jobs
+        };
+        if is_memory_report(spec) {
+            let mut reports = Vec::with_capacity(held.len());
+            for shard in held {
+                reports.push(call(&shard.jobs, |storage| storage.memory_report()).await);
+            }
+            return Ok(memory_reply(spec.name, reports, self.peak_memory()));
+        }
+        // The keyspace may have grown since the request's shards were
+        // held, by earlier commands of a batch or script or by others
+        if spec.has_flag(flags::DENYOOM) {
+            self.free_held_memory(held).await?;
+        }
+        let indexes = shard_indexes(spec, args, self.shards.len());
+        if let [index] = indexes[..] {
+            let args = args.to_vec();
+            return call(jobs(index), move |storage| storage.execute(spec, &args)).await;
+        }
+        if is_all_shards(spec) {
+            let request = shard_request(spec, args);
+            let mut replies = Vec::with_capacity(indexes.len());
+            for (n, index) in indexes.into_iter().enumerate() {
+                let request = request.clone();
+                let reply = call(jobs(index), move |storage| match n {
+                    0 => storage.execute(spec, &request),
+                    _ => storage.execute_unpropagated(spec, &request),
+                });
+                replies.push(reply.await?);
+            }
+            return join_replies(spec, args, replies);
+        }
+        if !is_multi_shard(spec) {
+            let (home, lent) = lent_keys(spec, args, |key| self.shard_of(key));
+            for (key, owner) in &lent {
+                pass(jobs(*owner), jobs(home), key.clone()).await;
+            }
+            let request = args.to_vec();
+            let result = call(jobs(home), move |storage| storage.execute(spec, &request)).await;
+            for (key, owner) in &lent {
+                pass(jobs(home), jobs(*owner), key.clone()).await;
+            }
+            return result;
+        }
+        let split = split_request(spec, args, |key| self.shard_of(key))?;
+        let mut replies = BTreeMap::new();
+        for (index, request) in split.requests {
+            let reply = call(jobs(index), move |storage| storage.execute(spec, &request)).await?;
+            replies.insert(index, reply);
+        }
+        Ok(merge_replies(replies, &split.positions))
+    }
+
+    /// Evicts keys from one shard after the other until the keyspace is
+    /// back under `maxmemory`. Fails with `OutOfMemory` once none of them
+    /// has anything left to evict.
+    async fn free_memory_if_needed(&self) -> StorageResult<()> {
+        let maxmemory = self.maxmemory();
+        let mut idle = 0;
+        while maxmemory > 0 && self.used_memory() > maxmemory {
+            if idle == self.shards.len() {
+                return Err(StorageError::OutOfMemory);
+            }
+            let index = self.next_eviction.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+            if call(&self.shards[index].inbox, |storage| storage.evict_one()).await {
+                idle = 0;
+            } else {
+                idle += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// `free_memory_if_needed` for a coordinator holding shards, which can
+    /// only evict from those.
+    async fn free_held_memory(&self, held: &[Held]) -> StorageResult<()> {
+        let maxmemory = self.maxmemory();
+        let mut idle = 0;
+        while maxmemory > 0 && self.used_memory() > maxmemory {
+            if idle == held.len() {
+                return Err(StorageError::OutOfMemory);
+            }
+            let n = self.next_eviction.fetch_add(1, Ordering::Relaxed) % held.len();
+            if call(&held[n].jobs, |storage| storage.evict_one()).await {
+                idle = 0;
+            } else {
+                idle += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// MEMORY STATS and MEMORY DOCTOR over every shard.
+    async fn memory_report(&self, name: &str) -> RESP {
+        let mut report = None;
+        for shard in &self.shards {
+            let shard_report = call(&shard.inbox, |storage| storage.memory_report()).await;
+            match &mut report {
+                None => report = Some(shard_report),
+                Some(report) => report.merge(&shard_report),
+            }
+        }
+        let report = report.expect("a pool has at least one shard");
+        if name == "memory|stats" {
+            report.stats()
+        } else {
+            RESP::BulkString(report.doctor().into())
+        }
+    }
+}
+
+/// Runs `job` on whichever shard `inbox` leads to and waits for its result.
+async fn call<T: Send + 'static>(
+    inbox: &mpsc::UnboundedSender<Message>,
+    job: impl FnOnce(&mut Storage) -> T + Send + 'static,
+) -> T {
+    let (reply, result) = oneshot::channel();
+    let job: Job = Box::new(move |storage, counters| {
+        let result = job(storage);
+        counters.publish(storage);
+        let _ = reply.send(result);
+    });
+    inbox
+        .send(Message::Run(job))
+        .unwrap_or_else(|_| panic!("shard thread stopped"));
+    result.await.expect("shard thread stopped")
+}
+
+/// Moves a key from one held shard to another, see `lent_keys`.
+async fn pass(
+    from: &mpsc::UnboundedSender<Message>,
+    to: &mpsc::UnboundedSender<Message>,
+    key: String,
+) {
+    let lent = {
+        let key = key.clone();
+        call(from, move |storage| storage.lend(&key)).await
+    };
+    if let Some(value) = lent {
+        call(to, move |storage| storage.receive(key, value)).await;
+    }
+}
+
+/// Polls `future` on the current thread, parking it between wakeups.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// A shard's event loop, until the pool is dropped.
+fn run_shard(mut messages: mpsc::UnboundedReceiver<Message>, counters: &Counters) {
+    let mut storage = Storage::new();
+    while let Some(message) = messages.blocking_recv() {
+        handle(&mut storage, message, counters);
+    }
+}
+
+fn handle(storage: &mut Storage, message: Message, counters: &Counters) {
+    match message {
+        Message::Run(job) => job(storage, counters),
+        Message::Hold { ready, mut jobs } => {
+            // The coordinator may have given up while waiting in line
+            if ready.send(()).is_ok() {
+                while let Some(job) = jobs.blocking_recv() {
+                    handle(storage, job, counters);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command;
+    use crate::storage::result::StorageError;
+    use crate::test_support::{bulk, cmd};
+
+    fn request(parts: &[&str]) -> (&'static CommandSpec, Vec<CommandArg>) {
+        let args = cmd(parts);
+        (command::resolve(&args).unwrap(), args)
+    }
+
+    async fn run(pool: &ShardPool, parts: &[&str]) -> StorageResult<RESP> {
+        let (spec, args) = request(parts);
+        pool.execute(spec, &args).await
+    }
+
+    #[tokio::test]
+    async fn test_commands_run_on_owning_shards() {
+        let pool = ShardPool::new(4);
+        assert_eq!(
+            run(&pool, &["mset", "a", "1", "b", "2", "c", "3", "d", "4"]).await,
+            Ok(RESP::SimpleString("OK".to_string()))
+        );
+        assert_eq!(
+            run(&pool, &["mget", "d", "x", "a"]).await,
+            Ok(RESP::Array(vec![bulk("4"), RESP::Null, bulk("1")]))
+        );
+        assert_eq!(run(&pool, &["incr", "a"]).await, Ok(RESP::Integer(2)));
+        assert_eq!(
+            run(&pool, &["del", "a", "b", "x"]).await,
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(pool.keys_count(), 2);
+        assert!(pool.used_memory() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_unsplittable_commands_span_shards() {
+        let pool = ShardPool::new(4);
+        let mut keys: Vec<String> = Vec::new();
+        for i in 0.. {
+            let key = format!("key:{}", i);
+            if keys.iter().all(|k| pool.shard_of(k) != pool.shard_of(&key)) {
+                keys.push(key);
+            }
+            if keys.len() == 3 {
+                break;
+            }
+        }
+        let (a, b, dest) = (keys[0].as_str(), keys[1].as_str(), keys[2].as_str());
+        run(&pool, &["set", a, "a"]).await.unwrap();
+        run(&pool, &["set", b, "b"]).await.unwrap();
+        assert_eq!(
+            run(&pool, &["bitop", "or", dest, a, b]).await,
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&pool, &["get", dest]).await, Ok(bulk("c")));
+        assert_eq!(
+            run(&pool, &["pfmerge", a, b]).await,
+            Err(StorageError::InvalidHll)
+        );
+        assert_eq!(pool.keys_count(), 3);
+        run(&pool, &["del", a, b, dest]).await.unwrap();
+
+        run(&pool, &["ts.create", a]).await.unwrap();
+        run(&pool, &["ts.create", dest]).await.unwrap();
+        run(
+            &pool,
+            &["ts.createrule", a, dest, "AGGREGATION", "sum", "10"],
+        )
+        .await
+        .unwrap();
+        for (timestamp, value) in [("1", "1"), ("5", "2"), ("12", "4")] {
+            run(&pool, &["ts.add", a, timestamp, value]).await.unwrap();
+        }
+        assert_eq!(
+            run(&pool, &["ts.range", dest, "-", "+"]).await,
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                RESP::Integer(0),
+                bulk("3")
+            ])]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_runs_in_order() {
+        let pool = ShardPool::new(4);
+        let replies = pool
+            .execute_batch(
+                &[
+                    request(&["set", "a", "1"]),
+                    request(&["incr", "a"]),
+                    request(&["mget", "a", "b"]),
+                ],
+                || true,
+            )
+            .await;
+        assert_eq!(
+            replies,
+            Some(vec![
+                Ok(RESP::SimpleString("OK".to_string())),
+                Ok(RESP::Integer(2)),
+                Ok(RESP::Array(vec![bulk("2"), RESP::Null])),
+            ])
+        );
+        let declined = pool
+            .execute_batch(&[request(&["incr", "a"])], || false)
+            .await;
+        assert_eq!(declined, None);
+        assert_eq!(run(&pool, &["get", "a"]).await, Ok(bulk("2")));
+    }
+
+    #[tokio::test]
+    async fn test_eviction_reaches_every_shard() {
+        let pool = ShardPool::new(4);
+        let value = "x".repeat(100);
+        let keys: Vec<String> = (0..40).map(|i| format!("key:{}", i)).collect();
+        for key in &keys {
+            run(&pool, &["set", key, &value]).await.unwrap();
+        }
+        let limit = pool.used_memory();
+        pool.set_maxmemory(limit);
+        pool.set_maxmemory_policy(EvictionPolicy::AllKeysRandom);
+        // Keys sharing a hash tag all land on one shard
+        for i in 0..40 {
+            let key = format!("{{tag}}:{}", i);
+            assert!(run(&pool, &["set", &key, &value]).await.is_ok());
+        }
+        run(&pool, &["get", "{tag}:0"]).await.unwrap();
+        assert!(pool.used_memory() <= limit);
+        let tagged = pool.shard_of("{tag}");
+        let mut evicted_elsewhere = 0;
+        for key in keys.iter().filter(|key| pool.shard_of(key) != tagged) {
+            if run(&pool, &["get", key]).await == Ok(RESP::Null) {
+                evicted_elsewhere += 1;
+            }
+        }
+        assert!(evicted_elsewhere > 0);
+    }
+
+    #[test]
+    fn test_atomically_sees_its_own_writes() {
+        let pool = ShardPool::new(4);
+        let replies = pool.atomically(|execute| {
+            let mut replies = Vec::new();
+            for parts in [
+                &["mset", "a", "1", "b", "2"][..],
+                &["incr", "a"],
+                &["mget", "a", "b"],
+            ] {
+                let (spec, args) = request(parts);
+                replies.push(execute(spec, &args));
+            }
+            replies
+        });
+        assert_eq!(
+            replies,
+            vec![
+                Ok(RESP::SimpleString("OK".to_string())),
+                Ok(RESP::Integer(2)),
+                Ok(RESP::Array(vec![bulk("2"), bulk("2")])),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_multi_shard_commands() {
+        let pool = Arc::new(ShardPool::new(4));
+        let tasks: Vec<_> = (0..8)
+            .map(|task| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    for i in 0..100 {
+                        let (a, b) = (format!("{}:a:{}", task, i), format!("{}:b:{}", task, i));
+                        run(&pool, &["mset", &a, "1", &b, "2"]).await.unwrap();
+                        run(&pool, &["del", &b, &a]).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(pool.keys_count(), 0);
+    }
+}
@@ -1,40 +1,100 @@
 use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-#[derive(Debug)]
+use crate::command::CommandArg;
+
+/// Errors from running a command against the keyspace. They display as
+/// Redis error replies, prefix included.
+#[derive(Debug, PartialEq)]
 pub enum StorageError {
-    IncorrectRequest,
-    CommandInternalError(String),
-    CommandSyntaxError(String, String),
+    /// A malformed argument, with the message to send after `ERR`
+    CommandSyntaxError(String),
     CommandNotAvailable(String),
-    ValueNotInteger(String),
-    KeyNotFound(String),
+    WrongArity(String),
+    ValueNotInteger,
+    IncrementOverflow,
+    KeyNotFound,
+    BusyKey,
     WrongType,
+    OutOfMemory,
+    /// Keys of one request are in different cluster slots
+    CrossSlot,
+    /// A missing stream consumer group, with the message to send after
+    /// `NOGROUP`
+    NoGroup(String),
+    BusyGroup,
+    /// A string that isn't a HyperLogLog, or is a corrupted one
+    InvalidHll,
+    CorruptedHll,
 }
 
 impl fmt::Display for StorageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            StorageError::IncorrectRequest => write!(f, "Incorrect request"),
-            StorageError::CommandInternalError(command) => {
-                write!(f, "Command internal error: {}", command)
+            StorageError::CommandSyntaxError(message) => write!(f, "ERR {}", message),
+            StorageError::CommandNotAvailable(command) => {
+                write!(f, "ERR unknown command '{}'", command)
             }
-            StorageError::CommandSyntaxError(command, message) => {
-                write!(f, "Command syntax error: {} - {}", command, message)
+            StorageError::WrongArity(command) => {
+                write!(f, "ERR wrong number of arguments for '{}' command", command)
             }
-            StorageError::CommandNotAvailable(command) => {
-                write!(f, "Command not available: {}", command)
+            StorageError::ValueNotInteger => {
+                write!(f, "ERR value is not an integer or out of range")
+            }
+            StorageError::IncrementOverflow => {
+                write!(f, "ERR increment or decrement would overflow")
             }
-            StorageError::ValueNotInteger(value) => {
-                write!(f, "Value not an integer: {}", value)
+            StorageError::KeyNotFound => write!(f, "ERR no such key"),
+            StorageError::BusyKey => write!(f, "BUSYKEY Target key name already exists."),
+            StorageError::WrongType => write!(
+                f,
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ),
+            StorageError::OutOfMemory => {
+                write!(f, "OOM command not allowed when used memory > 'maxmemory'.")
             }
-            StorageError::KeyNotFound(key) => {
-                write!(f, "Key not found: {}", key)
+            StorageError::CrossSlot => {
+                write!(f, "CROSSSLOT Keys in request don't hash to the same slot")
             }
-            StorageError::WrongType => {
-                write!(f, "Operation against a key holding the wrong kind of value")
+            StorageError::NoGroup(message) => write!(f, "NOGROUP {}", message),
+            StorageError::BusyGroup => write!(f, "BUSYGROUP Consumer Group name already exists"),
+            StorageError::InvalidHll => {
+                write!(f, "WRONGTYPE Key is not a valid HyperLogLog string value.")
             }
+            StorageError::CorruptedHll => write!(f, "INVALIDOBJ Corrupted HLL object detected"),
         }
     }
 }
 
+impl StorageError {
+    /// An `ERR` reply with `message`.
+    pub fn syntax(message: &str) -> Self {
+        StorageError::CommandSyntaxError(message.to_string())
+    }
+
+    /// The plain `ERR syntax error`.
+    pub fn syntax_error() -> Self {
+        Self::syntax("syntax error")
+    }
+}
+
 pub type StorageResult<T> = Result<T, StorageError>;
+
+pub(super) fn parse_integer<T: FromStr>(arg: &str) -> StorageResult<T> {
+    arg.parse().map_err(|_| StorageError::ValueNotInteger)
+}
+
+/// An integer within `range`, or `message` as the error. A missing
+/// argument is a syntax error.
+pub(super) fn parse_in<T: FromStr + PartialOrd>(
+    arg: Option<&CommandArg>,
+    range: RangeInclusive<T>,
+    message: &str,
+) -> StorageResult<T> {
+    arg.ok_or_else(StorageError::syntax_error)?
+        .parse::<T>()
+        .ok()
+        .filter(|value| range.contains(value))
+        .ok_or_else(|| StorageError::syntax(message))
+}
@@ -0,0 +1,1095 @@
+use std::cmp::Ordering;
+
+use super::result::{StorageError, StorageResult};
+use super::snapshot::append_record;
+use super::{Storage, StorageValue, now_ms};
+use crate::command::CommandArg;
+use crate::ds::json::{Document, Path, Value};
+use crate::ds::search::{
+    Cell, Field, FieldType, Hit, Index, Load, Pipeline, Query, Row, Schema, SortKey,
+};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+/// Results FT.SEARCH returns unless LIMIT says otherwise.
+const DEFAULT_LIMIT: usize = 10;
+
+fn no_index(command: &[CommandArg]) -> StorageError {
+    StorageError::syntax(&format!("{}: no such index", command[1]))
+}
+
+/// An index along with the FT.CREATE arguments after its name, which
+/// snapshots keep to create it again.
+pub(super) struct SearchIndex {
+    index: Index,
+    arguments: Vec<String>,
+}
+
+/// FT.AGGREGATE's arguments after the query, as the text they're read as.
+fn parse_pipeline(args: &[CommandArg]) -> Result<Pipeline, String> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Pipeline::parse(&args)
+}
+
+/// `count arg...` starting at `command[i]`.
+fn counted(command: &[CommandArg], i: usize) -> StorageResult<&[CommandArg]> {
+    let count: usize = command
+        .get(i)
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(StorageError::syntax_error)?;
+    command
+        .get(i + 1..i + 1 + count)
+        .ok_or_else(StorageError::syntax_error)
+}
+
+/// FT.CREATE index [ON JSON] [PREFIX count prefix...] [STOPWORDS count
+/// word...] SCHEMA field [AS name] type [options]...
+fn parse_schema(command: &[CommandArg]) -> StorageResult<Schema> {
+    let mut schema = Schema {
+        prefixes: Vec::new(),
+        fields: Vec::new(),
+        stopwords: None,
+    };
+    let mut i = 2;
+    loop {
+        let Some(arg) = command.get(i) else {
+            return Err(StorageError::syntax("Fields arguments are missing"));
+        };
+        match arg.to_uppercase().as_str() {
+            "ON" => match command.get(i + 1).map(|on| on.to_uppercase()).as_deref() {
+                Some("JSON") => i += 1,
+                Some("HASH") => {
+                    return Err(StorageError::syntax("Only JSON documents can be indexed"));
+                }
+                _ => return Err(StorageError::syntax_error()),
+            },
+            "PREFIX" => {
+                schema.prefixes = counted(command, i + 1)?
+                    .iter()
+                    .map(|prefix| prefix.to_string())
+                    .collect();
+                i += 1 + schema.prefixes.len();
+            }
+            "STOPWORDS" => {
+                let stopwords = counted(command, i + 1)?;
+                i += 1 + stopwords.len();
+                schema.stopwords = Some(stopwords.iter().map(|s| s.to_lowercase()).collect());
+            }
+            "SCHEMA" => break,
+            _ => return Err(StorageError::syntax_error()),
+        }
+        i += 1;
+    }
+    i += 1;
+    while i < command.len() {
+        let path = &command[i];
+        let mut name = path;
+        if command
+            .get(i + 1)
+            .is_some_and(|arg| arg.eq_ignore_ascii_case("AS"))
+        {
+            name = command.get(i + 2).ok_or_else(StorageError::syntax_error)?;
+            i += 2;
+        }
+        let kind = command.get(i + 1).ok_or_else(StorageError::syntax_error)?;
+        let mut kind = match kind.to_uppercase().as_str() {
+            "TEXT" => FieldType::Text { weight: 1.0 },
+            "TAG" => FieldType::Tag {
+                separator: ',',
+                case_sensitive: false,
+            },
+            "NUMERIC" => FieldType::Numeric,
+            _ => {
+                return Err(StorageError::syntax(&format!(
+                    "Invalid field type for field `{}`",
+                    name
+                )));
+            }
+        };
+        i += 2;
+        let mut sortable = false;
+        while let Some(option) = command.get(i) {
+            match (option.to_uppercase().as_str(), &mut kind) {
+                ("SORTABLE", _) => sortable = true,
+                ("NOSTEM", FieldType::Text { .. }) => {}
+                ("WEIGHT", FieldType::Text { weight }) => {
+                    *weight = command
+                        .get(i + 1)
+                        .and_then(|w| w.parse::<f64>().ok())
+                        .filter(|w| w.is_finite() && *w >= 0.0)
+                        .ok_or_else(|| StorageError::syntax("Bad arguments for WEIGHT"))?;
+                    i += 1;
+                }
+                ("SEPARATOR", FieldType::Tag { separator, .. }) => {
+                    let mut chars = command.get(i + 1).map(|s| s.chars());
+                    *separator = match chars.as_mut().map(|c| (c.next(), c.next())) {
+                        Some((Some(c), None)) => c,
+                        _ => return Err(StorageError::syntax("Bad arguments for SEPARATOR")),
+                    };
+                    i += 1;
+                }
+                ("CASESENSITIVE", FieldType::Tag { case_sensitive, .. }) => {
+                    *case_sensitive = true;
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        if schema.field(name).is_some() {
+            return Err(StorageError::syntax(&format!(
+                "Duplicate field in schema - {}",
+                name
+            )));
+        }
+        let field = Field::new(path, name, kind, sortable).map_err(|e| StorageError::syntax(&e))?;
+        schema.fields.push(field);
+    }
+    if schema.fields.is_empty() {
+        return Err(StorageError::syntax("Fields arguments are missing"));
+    }
+    Ok(schema)
+}
+
+/// FT.SEARCH's options after the query.
+struct SearchOptions {
+    no_content: bool,
+    with_scores: bool,
+    with_sort_keys: bool,
+    /// Fields to return with the names to return them as, instead of the
+    /// whole document
+    returns: Option<Vec<(String, String)>>,
+    /// A field, and whether to sort descending
+    sort_by: Option<(String, bool)>,
+    offset: usize,
+    count: usize,
+}
+
+impl SearchOptions {
+    fn parse(command: &[CommandArg]) -> StorageResult<SearchOptions> {
+        let mut options = SearchOptions {
+            no_content: false,
+            with_scores: false,
+            with_sort_keys: false,
+            returns: None,
+            sort_by: None,
+            offset: 0,
+            count: DEFAULT_LIMIT,
+        };
+        let number = |arg: Option<&CommandArg>| {
+            arg.and_then(|n| n.parse::<usize>().ok())
+                .ok_or_else(StorageError::syntax_error)
+        };
+        let mut i = 3;
+        while i < command.len() {
+            match command[i].to_uppercase().as_str() {
+                "NOCONTENT" => options.no_content = true,
+                "WITHSCORES" => options.with_scores = true,
+                "WITHSORTKEYS" => options.with_sort_keys = true,
+                "VERBATIM" => {}
+                "RETURN" => {
+                    let fields = counted(command, i + 1)?;
+                    i += 1 + fields.len();
+                    let mut returns = Vec::new();
+                    let mut j = 0;
+                    while j < fields.len() {
+                        let field = fields[j].strip_prefix('@').unwrap_or(&fields[j]);
+                        match fields.get(j + 1) {
+                            Some(arg) if arg.eq_ignore_ascii_case("AS") => {
+                                let name =
+                                    fields.get(j + 2).ok_or_else(StorageError::syntax_error)?;
+                                returns.push((field.to_string(), name.to_string()));
+                                j += 3;
+                            }
+                            _ => {
+                                returns.push((field.to_string(), field.to_string()));
+                                j += 1;
+                            }
+                        }
+                    }
+                    options.returns = Some(returns);
+                }
+                "SORTBY" => {
+                    let field = command.get(i + 1).ok_or_else(StorageError::syntax_error)?;
+                    let field = field.strip_prefix('@').unwrap_or(field).to_string();
+                    i += 1;
+                    let order = command.get(i + 1).map(|order| order.to_uppercase());
+                    let descending = order.as_deref() == Some("DESC");
+                    if matches!(order.as_deref(), Some("ASC" | "DESC")) {
+                        i += 1;
+                    }
+                    options.sort_by = Some((field, descending));
+                }
+                "LIMIT" => {
+                    options.offset = number(command.get(i + 1))?;
+                    options.count = number(command.get(i + 2))?;
+                    i += 2;
+                }
+                "DIALECT" | "TIMEOUT" => {
+                    number(command.get(i + 1))?;
+                    i += 1;
+                }
+                _ => {
+                    return Err(StorageError::syntax(&format!(
+                        "Unknown argument `{}`",
+                        command[i]
+                    )));
+                }
+            }
+            i += 1;
+        }
+        Ok(options)
+    }
+
+    fn has_content(&self) -> bool {
+        !self.no_content && self.returns.as_ref().is_none_or(|r| !r.is_empty())
+    }
+}
+
+/// Sort keys in order, or reversed, with documents missing the field last
+/// either way.
+fn compare_sort_keys(a: Option<&SortKey>, b: Option<&SortKey>, descending: bool) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) if descending => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+    }
+}
+
+/// A field of `doc` as FT.SEARCH's RETURN and FT.AGGREGATE's LOAD give
+/// it: a schema field by name or a JSONPath, strings as they are and
+/// anything else as JSON.
+fn property(doc: &Document, schema: &Schema, name: &str) -> Option<String> {
+    let path = match schema.field(name) {
+        Some((_, field)) => Path::parse(&field.path),
+        None if name.starts_with('$') => Path::parse(name),
+        None => return None,
+    };
+    let steps = doc.select(&path.ok()?).into_iter().next()?;
+    match doc.get(&steps)? {
+        Value::String(s) => Some(s.clone()),
+        value => Some(value.to_json()),
+    }
+}
+
+/// FT.AGGREGATE's reply: the row count, then each row as a flat array of
+/// names and values.
+fn rows_reply(total: usize, rows: Vec<Row>) -> RESP {
+    let mut reply = vec![RESP::Integer(total as i64)];
+    for row in rows {
+        let mut fields = Vec::with_capacity(row.len() * 2);
+        for (name, cell) in row {
+            fields.push(RESP::BulkString(name.into()));
+            fields.push(match cell {
+                Cell::Value(value) => RESP::BulkString(value.into()),
+                Cell::List(values) => RESP::Array(
+                    values
+                        .into_iter()
+                        .map(|s| RESP::BulkString(s.into()))
+                        .collect(),
+                ),
+            });
+        }
+        reply.push(RESP::Array(fields));
+    }
+    RESP::Array(reply)
+}
+
+impl Storage {
+    fn search_index(&self, command: &[CommandArg]) -> StorageResult<&Index> {
+        self.indexes
+            .get(command[1].as_str())
+            .map(|index| &index.index)
+            .ok_or_else(|| no_index(command))
+    }
+
+    /// The document at `key` if it hasn't expired. Indexes may still
+    /// have keys whose TTL passed but that nothing has looked at since.
+    fn live_document(&self, key: &str, now: u64) -> Option<&Document> {
+        if self.expires.get(key).is_some_and(|at| *at <= now) {
+            return None;
+        }
+        match &self.store.get(key)?.value {
+            StorageValue::Json(doc) => Some(doc),
+            _ => None,
+        }
+    }
+
+    /// Brings every index covering `key` up to date after it was written.
+    pub(super) fn reindex(&mut self, key: &str) {
+        if self.indexes.is_empty() {
+            return;
+        }
+        let doc = match self.store.get(key).map(|entry| &entry.value) {
+            Some(StorageValue::Json(doc)) => Some(doc),
+            _ => None,
+        };
+        for search in self.indexes.values_mut() {
+            if !search.index.schema().covers(key) {
+                continue;
+            }
+            match doc {
+                Some(doc) => search.index.add(key, doc),
+                None => {
+                    search.index.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Takes a removed key out of every index.
+    pub(super) fn unindex(&mut self, key: &str) {
+        for search in self.indexes.values_mut() {
+            search.index.remove(key);
+        }
+    }
+
+    /// Creates an index from a snapshot, replacing one of the same name.
+    pub(super) fn load_index(&mut self, name: &str, arguments: &[String]) {
+        self.indexes.remove(name);
+        let mut command = vec![CommandArg::from("FT.CREATE"), CommandArg::from(name)];
+        command.extend(arguments.iter().map(|arg| CommandArg::from(arg.as_str())));
+        let _ = self.command_ft_create(&command);
+    }
+
+    /// Appends every index's definition to a snapshot, as a RESP array of
+    /// `ftindex`, the name, an empty expire time and the FT.CREATE
+    /// arguments after the name.
+    pub(super) fn write_indexes(&self, out: &mut String) {
+        for (name, search) in &self.indexes {
+            let fields = ["ftindex", name, ""]
+                .into_iter()
+                .map(str::to_string)
+                .chain(search.arguments.iter().cloned())
+                .map(|s| RESP::BulkString(s.into()))
+                .collect();
+            append_record(out, RESP::Array(fields));
+        }
+    }
+
+    pub(super) fn command_ft_create(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if self.indexes.contains_key(command[1].as_str()) {
+            return Err(StorageError::syntax("Index already exists"));
+        }
+        let mut index = Index::new(parse_schema(command)?);
+        let now = now_ms();
+        for (key, entry) in self.store.iter() {
+            if self.expires.get(key).is_some_and(|at| *at <= now) {
+                continue;
+            }
+            if let StorageValue::Json(doc) = &entry.value
+                && index.schema().covers(key)
+            {
+                index.add(key, doc);
+            }
+        }
+        let arguments = command[2..].iter().map(|arg| arg.to_string()).collect();
+        self.indexes
+            .insert(command[1].to_string(), SearchIndex { index, arguments });
+        Ok(RESP::SimpleString("OK".to_string()))
+    }
+
+    /// FT.DROPINDEX index [DD], DD deleting the documents too.
+    pub(super) fn command_ft_dropindex(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let delete = match &command[2..] {
+            [] => false,
+            [dd] if dd.eq_ignore_ascii_case("DD") => true,
+            _ => return Err(StorageError::syntax_error()),
+        };
+        let search = self
+            .indexes
+            .remove(command[1].as_str())
+            .ok_or_else(|| no_index(command))?;
+        if delete {
+            for key in search.index.keys() {
+                if self.remove(key).is_some() {
+                    self.notify(notify::GENERIC, "del", key);
+                    self.invalidate(key);
+                }
+            }
+        }
+        Ok(RESP::SimpleString("OK".to_string()))
+    }
+
+    pub(super) fn command_ft_list(&mut self) -> StorageResult<RESP> {
+        Ok(RESP::Array(
+            self.indexes
+                .keys()
+                .map(|name| RESP::BulkString(name.clone().into()))
+                .collect(),
+        ))
+    }
+
+    pub(super) fn command_ft_info(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let index = self.search_index(command)?;
+        let schema = index.schema();
+        let bulk = |s: &str| RESP::BulkString(s.into());
+        let attributes = schema
+            .fields
+            .iter()
+            .map(|field| {
+                let mut attribute = vec![
+                    bulk("identifier"),
+                    bulk(&field.path),
+                    bulk("attribute"),
+                    bulk(&field.name),
+                    bulk("type"),
+                    bulk(field.kind.name()),
+                ];
+                match &field.kind {
+                    FieldType::Text { weight } => {
+                        attribute.extend([bulk("WEIGHT"), bulk(&weight.to_string())]);
+                    }
+                    FieldType::Tag {
+                        separator,
+                        case_sensitive,
+                    } => {
+                        attribute.extend([bulk("SEPARATOR"), bulk(&separator.to_string())]);
+                        if *case_sensitive {
+                            attribute.push(bulk("CASESENSITIVE"));
+                        }
+                    }
+                    FieldType::Numeric => {}
+                }
+                if field.sortable {
+                    attribute.push(bulk("SORTABLE"));
+                }
+                RESP::Array(attribute)
+            })
+            .collect();
+        let definition = vec![
+            bulk("key_type"),
+            bulk("JSON"),
+            bulk("prefixes"),
+            RESP::Array(schema.prefixes.iter().map(|p| bulk(p)).collect()),
+        ];
+        let mut info = vec![
+            bulk("index_name"),
+            bulk(&command[1]),
+            bulk("index_definition"),
+            RESP::Array(definition),
+            bulk("attributes"),
+            RESP::Array(attributes),
+        ];
+        if let Some(stopwords) = &schema.stopwords {
+            info.push(bulk("stopwords_list"));
+            info.push(RESP::Array(stopwords.iter().map(|s| bulk(s)).collect()));
+        }
+        info.extend([
+            bulk("num_docs"),
+            RESP::Integer(index.len() as i64),
+            bulk("num_terms"),
+            RESP::Integer(index.terms_count() as i64),
+            bulk("num_records"),
+            RESP::Integer(index.records_count() as i64),
+        ]);
+        Ok(RESP::Array(info))
+    }
+
+    /// The documents `command[2]` matches that haven't expired.
+    fn search_hits(&self, command: &[CommandArg]) -> StorageResult<(&Index, Vec<Hit<'_>>)> {
+        let index = self.search_index(command)?;
+        let query =
+            Query::parse(&command[2], index.schema()).map_err(|e| StorageError::syntax(&e))?;
+        let now = now_ms();
+        let hits = index
+            .search(&query)
+            .into_iter()
+            .filter(|hit| self.live_document(hit.key, now).is_some())
+            .collect();
+        Ok((index, hits))
+    }
+
+    /// FT.SEARCH index query [NOCONTENT] [WITHSCORES] [WITHSORTKEYS]
+    /// [RETURN count field [AS name]...] [SORTBY field [ASC|DESC]]
+    /// [LIMIT offset count]
+    pub(super) fn command_ft_search(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let options = SearchOptions::parse(command)?;
+        let (index, mut hits) = self.search_hits(command)?;
+        let schema = index.schema();
+        let sort_by = match &options.sort_by {
+            Some((name, descending)) => {
+                let (position, _) = schema.field(name).ok_or_else(|| {
+                    StorageError::syntax(&format!("Property `{}` not loaded nor in schema", name))
+                })?;
+                Some((position, *descending))
+            }
+            None => None,
+        };
+        hits.sort_by(|a, b| {
+            let ordering = match sort_by {
+                Some((position, descending)) => {
+                    compare_sort_keys(a.sort_key(position), b.sort_key(position), descending)
+                }
+                None => b.score.total_cmp(&a.score),
+            };
+            ordering.then_with(|| a.key.cmp(b.key))
+        });
+        let now = now_ms();
+        let mut reply = vec![RESP::Integer(hits.len() as i64)];
+        for hit in hits.iter().skip(options.offset).take(options.count) {
+            reply.push(RESP::BulkString(hit.key.into()));
+            if options.with_scores {
+                reply.push(RESP::BulkString(hit.score.to_string().into()));
+            }
+            if options.with_sort_keys {
+                let sort_key = sort_by.and_then(|(position, _)| hit.sort_key(position));
+                reply.push(match sort_key {
+                    Some(sort_key) => RESP::BulkString(sort_key.to_string().into()),
+                    None => RESP::Null,
+                });
+            }
+            if !options.has_content() {
+                continue;
+            }
+            let doc = self
+                .live_document(hit.key, now)
+                .expect("hits are live documents");
+            let content = match &options.returns {
+                None => vec![
+                    RESP::BulkString("$".into()),
+                    RESP::BulkString(doc.root().to_json().into()),
+                ],
+                Some(returns) => returns
+                    .iter()
+                    .filter_map(|(field, name)| {
+                        let value = property(doc, schema, field)?;
+                        Some([
+                            RESP::BulkString(name.clone().into()),
+                            RESP::BulkString(value.into()),
+                        ])
+                    })
+                    .flatten()
+                    .collect(),
+            };
+            reply.push(RESP::Array(content));
+        }
+        Ok(RESP::Array(reply))
+    }
+
+    /// FT.AGGREGATE index query [LOAD count field...|LOAD *] then GROUPBY
+    /// with REDUCE, SORTBY and LIMIT steps in any order.
+    pub(super) fn command_ft_aggregate(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let pipeline = parse_pipeline(&command[3..]).map_err(|e| StorageError::syntax(&e))?;
+        let (index, hits) = self.search_hits(command)?;
+        let schema = index.schema();
+        let fields = match pipeline.load() {
+            Load::All => schema.fields.iter().map(|f| f.name.clone()).collect(),
+            Load::Fields(fields) => fields,
+        };
+        let now = now_ms();
+        let rows = hits
+            .iter()
+            .map(|hit| {
+                let doc = self
+                    .live_document(hit.key, now)
+                    .expect("hits are live documents");
+                fields
+                    .iter()
+                    .filter_map(|name| {
+                        let value = property(doc, schema, name)?;
+                        Some((name.clone(), Cell::Value(value)))
+                    })
+                    .collect()
+            })
+            .collect();
+        let (total, rows) = pipeline.run(rows);
+        Ok(rows_reply(total, rows))
+    }
+}
+
+/// FT.SEARCH as each shard runs it: with scores and sort keys to merge
+/// by, and every result up to the end of the page asked for.
+pub(super) fn search_shard_request(command: &[CommandArg]) -> Vec<CommandArg> {
+    let mut request = command.to_vec();
+    if let Ok(options) = SearchOptions::parse(command) {
+        let end = options.offset.saturating_add(options.count);
+        request.extend(["WITHSCORES", "WITHSORTKEYS", "LIMIT", "0"].map(CommandArg::from));
+        request.push(end.to_string().into());
+    }
+    request
+}
+
+/// Puts the shards' FT.SEARCH results in order and cuts the page out of
+/// them.
+pub(super) fn merge_search(command: &[CommandArg], replies: Vec<RESP>) -> StorageResult<RESP> {
+    let options = SearchOptions::parse(command)?;
+    let stride = 3 + usize::from(options.has_content());
+    let mut total = 0;
+    // Key, score, sort key and content
+    let mut hits: Vec<Vec<RESP>> = Vec::new();
+    for reply in replies {
+        let RESP::Array(elements) = reply else {
+            continue;
+        };
+        let mut elements = elements.into_iter();
+        if let Some(RESP::Integer(count)) = elements.next() {
+            total += count;
+        }
+        loop {
+            let hit: Vec<RESP> = elements.by_ref().take(stride).collect();
+            if hit.is_empty() {
+                break;
+            }
+            hits.push(hit);
+        }
+    }
+    fn text(hit: &[RESP], i: usize) -> Option<&str> {
+        match hit.get(i) {
+            Some(RESP::BulkString(s)) => std::str::from_utf8(s).ok(),
+            _ => None,
+        }
+    }
+    let descending = options.sort_by.as_ref().map(|(_, descending)| *descending);
+    hits.sort_by(|a, b| {
+        let ordering = match descending {
+            Some(descending) => {
+                let a_key = text(a, 2).and_then(SortKey::parse);
+                let b_key = text(b, 2).and_then(SortKey::parse);
+                compare_sort_keys(a_key.as_ref(), b_key.as_ref(), descending)
+            }
+            None => {
+                let score = |hit: &[RESP]| text(hit, 1).and_then(|s| s.parse::<f64>().ok());
+                let (a_score, b_score) = (score(a).unwrap_or(0.0), score(b).unwrap_or(0.0));
+                b_score.total_cmp(&a_score)
+            }
+        };
+        ordering.then_with(|| text(a, 0).cmp(&text(b, 0)))
+    });
+    let mut reply = vec![RESP::Integer(total)];
+    for hit in hits.into_iter().skip(options.offset).take(options.count) {
+        for (i, element) in hit.into_iter().enumerate() {
+            let wanted = match i {
+                1 => options.with_scores,
+                2 => options.with_sort_keys,
+                _ => true,
+            };
+            if wanted {
+                reply.push(element);
+            }
+        }
+    }
+    Ok(RESP::Array(reply))
+}
+
+/// FT.AGGREGATE as each shard runs it: only loading the fields the
+/// pipeline needs, which then runs once over every shard's rows.
+pub(super) fn aggregate_shard_request(command: &[CommandArg]) -> Vec<CommandArg> {
+    let Some(pipeline) = command.get(3..).and_then(|args| parse_pipeline(args).ok()) else {
+        return command.to_vec();
+    };
+    let mut request = command[..3].to_vec();
+    request.push("LOAD".into());
+    match pipeline.load() {
+        Load::All => request.push("*".into()),
+        Load::Fields(fields) => {
+            request.push(fields.len().to_string().into());
+            request.extend(fields.iter().map(|field| format!("@{}", field).into()));
+        }
+    }
+    request
+}
+
+pub(super) fn merge_aggregate(command: &[CommandArg], replies: Vec<RESP>) -> StorageResult<RESP> {
+    let pipeline = parse_pipeline(&command[3..]).map_err(|e| StorageError::syntax(&e))?;
+    let mut rows = Vec::new();
+    for reply in replies {
+        let RESP::Array(elements) = reply else {
+            continue;
+        };
+        for element in elements.into_iter().skip(1) {
+            let RESP::Array(fields) = element else {
+                continue;
+            };
+            let mut row = Row::new();
+            let mut fields = fields.into_iter();
+            // Shards build these replies from strings, so they're UTF-8
+            let text = |bytes: Vec<u8>| String::from_utf8(bytes).ok();
+            while let (Some(RESP::BulkString(name)), Some(value)) = (fields.next(), fields.next()) {
+                let Some(name) = text(name) else {
+                    continue;
+                };
+                match value {
+                    RESP::BulkString(value) => {
+                        if let Some(value) = text(value) {
+                            row.push((name, Cell::Value(value)));
+                        }
+                    }
+                    RESP::Array(values) => {
+                        let values = values
+                            .into_iter()
+                            .filter_map(|value| match value {
+                                RESP::BulkString(value) => text(value),
+                                _ => None,
+                            })
+                            .collect();
+                        row.push((name, Cell::List(values)));
+                    }
+                    _ => {}
+                }
+            }
+            rows.push(row);
+        }
+    }
+    let (total, rows) = pipeline.run(rows);
+    Ok(rows_reply(total, rows))
+}
+
+/// FT.INFO over every shard, with the counts added up.
+pub(super) fn merge_info(replies: Vec<RESP>) -> RESP {
+    let mut replies = replies.into_iter();
+    let Some(mut info) = replies.next() else {
+        return RESP::Null;
+    };
+    for reply in replies {
+        if let (RESP::Array(total), RESP::Array(other)) = (&mut info, reply) {
+            for (total, other) in total.iter_mut().zip(other) {
+                if let (RESP::Integer(total), RESP::Integer(other)) = (total, other) {
+                    *total += other;
+                }
+            }
+        }
+    }
+    info
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::ShardedStorage;
+    use crate::test_support::{bulk, run};
+
+    const CREATE: &[&str] = &[
+        "ft.create",
+        "idx",
+        "ON",
+        "JSON",
+        "PREFIX",
+        "1",
+        "item:",
+        "SCHEMA",
+        "$.name",
+        "AS",
+        "name",
+        "TEXT",
+        "$.tags",
+        "AS",
+        "tags",
+        "TAG",
+        "$.price",
+        "AS",
+        "price",
+        "NUMERIC",
+        "SORTABLE",
+    ];
+
+    const ITEMS: &[(&str, &str)] = &[
+        (
+            "item:1",
+            r#"{"name": "Red apple", "tags": "fruit,red", "price": 3}"#,
+        ),
+        (
+            "item:2",
+            r#"{"name": "Green apple pie", "tags": "baked", "price": 12}"#,
+        ),
+        (
+            "item:3",
+            r#"{"name": "Banana", "tags": "fruit", "price": 1.5}"#,
+        ),
+        ("other:1", r#"{"name": "Apple tree", "price": 100}"#),
+    ];
+
+    fn keys(reply: StorageResult<RESP>) -> Vec<String> {
+        let Ok(RESP::Array(elements)) = reply else {
+            panic!("not an array: {:?}", reply);
+        };
+        elements
+            .into_iter()
+            .skip(1)
+            .filter_map(|element| match element {
+                RESP::BulkString(key) => String::from_utf8(key).ok(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_index_follows_writes() {
+        let mut storage = Storage::new();
+        // Documents written before the index are indexed when it's created
+        run(&mut storage, &["json.set", ITEMS[0].0, "$", ITEMS[0].1]).unwrap();
+        assert_eq!(
+            run(&mut storage, CREATE),
+            Ok(RESP::SimpleString("OK".to_string()))
+        );
+        assert_eq!(
+            run(&mut storage, CREATE),
+            Err(StorageError::syntax("Index already exists"))
+        );
+        for (key, json) in &ITEMS[1..] {
+            run(&mut storage, &["json.set", key, "$", json]).unwrap();
+        }
+        assert_eq!(
+            keys(run(
+                &mut storage,
+                &["ft.search", "idx", "apple", "NOCONTENT"]
+            )),
+            vec!["item:1", "item:2"]
+        );
+        run(
+            &mut storage,
+            &["json.set", "item:3", "$.name", "\"Apple banana\""],
+        )
+        .unwrap();
+        run(&mut storage, &["del", "item:1"]).unwrap();
+        assert_eq!(
+            keys(run(
+                &mut storage,
+                &["ft.search", "idx", "apple", "NOCONTENT", "SORTBY", "price"]
+            )),
+            vec!["item:3", "item:2"]
+        );
+        // Overwriting with another type takes the key out
+        run(&mut storage, &["set", "item:3", "x"]).unwrap();
+        storage.expire_at("item:2", now_ms() - 1);
+        assert_eq!(
+            run(&mut storage, &["ft.search", "idx", "*"]),
+            Ok(RESP::Array(vec![RESP::Integer(0)]))
+        );
+        assert_eq!(
+            run(&mut storage, &["ft._list"]),
+            Ok(RESP::Array(vec![bulk("idx")]))
+        );
+        run(&mut storage, &["json.set", "item:4", "$", ITEMS[0].1]).unwrap();
+        run(&mut storage, &["ft.dropindex", "idx", "DD"]).unwrap();
+        // item:3 is a string now and other:1 is outside the prefix
+        assert_eq!(storage.keys_count(), 2);
+        assert_eq!(
+            run(&mut storage, &["ft.search", "idx", "*"]),
+            Err(StorageError::syntax("idx: no such index"))
+        );
+    }
+
+    #[test]
+    fn test_search_options() {
+        let mut storage = Storage::new();
+        run(&mut storage, CREATE).unwrap();
+        for (key, json) in ITEMS {
+            run(&mut storage, &["json.set", key, "$", json]).unwrap();
+        }
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "ft.search",
+                    "idx",
+                    "@tags:{fruit} @price:[1 (3]",
+                    "RETURN",
+                    "3",
+                    "name",
+                    "AS",
+                    "title",
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                RESP::Integer(1),
+                bulk("item:3"),
+                RESP::Array(vec![bulk("title"), bulk("Banana")]),
+            ]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "ft.search",
+                    "idx",
+                    "-pie",
+                    "SORTBY",
+                    "price",
+                    "DESC",
+                    "WITHSORTKEYS",
+                    "LIMIT",
+                    "0",
+                    "1",
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                RESP::Integer(2),
+                bulk("item:1"),
+                bulk("#3"),
+                RESP::Array(vec![
+                    bulk("$"),
+                    bulk(ITEMS[0].1.replace(", ", ",").replace(": ", ":").as_str())
+                ]),
+            ]))
+        );
+        assert!(run(&mut storage, &["ft.search", "idx", "@nope:x"]).is_err());
+        assert!(run(&mut storage, &["ft.search", "idx", "x", "LIMIT", "0"]).is_err());
+        assert!(run(&mut storage, &["ft.search", "idx", "x", "SORTBY", "nope"]).is_err());
+        assert!(
+            run(
+                &mut storage,
+                &["ft.create", "h", "ON", "HASH", "SCHEMA", "a", "TEXT"]
+            )
+            .is_err()
+        );
+        assert!(run(&mut storage, &["ft.create", "h", "SCHEMA", "$.a", "GEO"]).is_err());
+        assert!(run(&mut storage, &["ft.create", "h", "SCHEMA"]).is_err());
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut storage = Storage::new();
+        run(&mut storage, CREATE).unwrap();
+        for (key, json) in ITEMS {
+            run(&mut storage, &["json.set", key, "$", json]).unwrap();
+        }
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "ft.aggregate",
+                    "idx",
+                    "*",
+                    "GROUPBY",
+                    "1",
+                    "@tags",
+                    "REDUCE",
+                    "SUM",
+                    "1",
+                    "@price",
+                    "AS",
+                    "total",
+                    "SORTBY",
+                    "2",
+                    "@total",
+                    "DESC",
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                RESP::Integer(3),
+                RESP::Array(vec![bulk("tags"), bulk("baked"), bulk("total"), bulk("12")]),
+                RESP::Array(vec![
+                    bulk("tags"),
+                    bulk("fruit,red"),
+                    bulk("total"),
+                    bulk("3")
+                ]),
+                RESP::Array(vec![
+                    bulk("tags"),
+                    bulk("fruit"),
+                    bulk("total"),
+                    bulk("1.5")
+                ]),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_sharded_search() {
+        let storage = ShardedStorage::new(4);
+        run(&storage, CREATE).unwrap();
+        for i in 0..20 {
+            let key = format!("item:{}", i);
+            let json = format!(
+                r#"{{"name": "thing {}", "tags": "t{}", "price": {}}}"#,
+                i,
+                i % 2,
+                i
+            );
+            run(&storage, &["json.set", &key, "$", &json]).unwrap();
+        }
+        assert_eq!(
+            keys(run(
+                &storage,
+                &[
+                    "ft.search",
+                    "idx",
+                    "thing",
+                    "NOCONTENT",
+                    "SORTBY",
+                    "price",
+                    "DESC",
+                    "LIMIT",
+                    "2",
+                    "3",
+                ]
+            )),
+            vec!["item:17", "item:16", "item:15"]
+        );
+        let Ok(RESP::Array(reply)) = run(
+            &storage,
+            &["ft.search", "idx", "@tags:{t1}", "LIMIT", "0", "0"],
+        ) else {
+            panic!("not an array");
+        };
+        assert_eq!(reply, vec![RESP::Integer(10)]);
+        assert_eq!(
+            run(
+                &storage,
+                &[
+                    "ft.aggregate",
+                    "idx",
+                    "*",
+                    "GROUPBY",
+                    "1",
+                    "@tags",
+                    "REDUCE",
+                    "COUNT",
+                    "0",
+                    "AS",
+                    "n",
+                    "REDUCE",
+                    "MAX",
+                    "1",
+                    "@price",
+                    "AS",
+                    "max",
+                    "SORTBY",
+                    "1",
+                    "@tags",
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                RESP::Integer(2),
+                RESP::Array(vec![
+                    bulk("tags"),
+                    bulk("t0"),
+                    bulk("n"),
+                    bulk("10"),
+                    bulk("max"),
+                    bulk("18")
+                ]),
+                RESP::Array(vec![
+                    bulk("tags"),
+                    bulk("t1"),
+                    bulk("n"),
+                    bulk("10"),
+                    bulk("max"),
+                    bulk("19")
+                ]),
+            ]))
+        );
+        let Ok(RESP::Array(info)) = run(&storage, &["ft.info", "idx"]) else {
+            panic!("not an array");
+        };
+        let num_docs = info
+            .iter()
+            .position(|field| *field == bulk("num_docs"))
+            .unwrap();
+        assert_eq!(info[num_docs + 1], RESP::Integer(20));
+
+        // Snapshots keep the index, which every shard loads
+        let (snapshot, _) = storage.snapshot(|| ());
+        let copy = ShardedStorage::new(4);
+        copy.load_snapshot(super::super::parse_snapshot(&snapshot).unwrap());
+        assert_eq!(
+            keys(run(
+                &copy,
+                &["ft.search", "idx", "@price:[18 +inf]", "NOCONTENT"]
+            )),
+            vec!["item:18", "item:19"]
+        );
+    }
+}
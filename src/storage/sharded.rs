@@ -0,0 +1,1033 @@
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::introspection::MemoryReport;
+use super::result::{StorageError, StorageResult};
+use super::search;
+use super::snapshot::{Record, snapshot_header};
+use super::{EvictionPolicy, ForeignSample, Storage, StorageStats};
+use crate::blocking::Blocking;
+use crate::cluster::hash_tag;
+use crate::command::{Command, CommandArg, CommandSpec, flags};
+use crate::pubsub::PubSub;
+use crate::replication::Feed;
+use crate::resp::RESP;
+use crate::tracking::Tracking;
+use crate::transaction::Watches;
+
+/// Shards a server starts with. More shards than cores keeps the odds of
+/// two connections wanting the same lock low.
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// What a shard last published about itself, readable without asking it.
+#[derive(Default)]
+pub(super) struct Counters {
+    pub(super) used_memory: AtomicUsize,
+    pub(super) peak_memory: AtomicUsize,
+    pub(super) keys: AtomicUsize,
+    pub(super) expires: AtomicUsize,
+    pub(super) keyspace_hits: AtomicU64,
+    pub(super) keyspace_misses: AtomicU64,
+    pub(super) expired_keys: AtomicU64,
+    pub(super) evicted_keys: AtomicU64,
+}
+
+impl Counters {
+    pub(super) fn publish(&self, storage: &Storage) {
+        let stats = storage.stats();
+        self.used_memory
+            .store(storage.used_memory(), Ordering::Relaxed);
+        self.peak_memory
+            .store(storage.peak_memory(), Ordering::Relaxed);
+        self.keys.store(storage.keys_count(), Ordering::Relaxed);
+        self.expires
+            .store(storage.expires_count(), Ordering::Relaxed);
+        self.keyspace_hits
+            .store(stats.keyspace_hits, Ordering::Relaxed);
+        self.keyspace_misses
+            .store(stats.keyspace_misses, Ordering::Relaxed);
+        self.expired_keys
+            .store(stats.expired_keys, Ordering::Relaxed);
+        self.evicted_keys
+            .store(stats.evicted_keys, Ordering::Relaxed);
+    }
+}
+
+/// The keyspace split into independently locked `Storage` shards, picked
+/// by a hash of the key, so commands on different keys don't serialize on
+/// one lock.
+///
+/// Multi-key commands lock every shard they touch in ascending index
+/// order, which rules out deadlocks, and hold them all while running so
+/// they stay atomic. `maxmemory` limits the shards' combined usage, and
+/// keys are evicted from whichever shards have them.
+///
+/// Locking blocks the calling thread, so `Keyspace` calls in from threads
+/// that may block. What INFO reads is published by each shard as it's
+/// let go, so reading it never waits.
+pub struct ShardedStorage {
+    shards: Vec<Mutex<Storage>>,
+    counters: Vec<Counters>,
+    /// Sum of the shards' used memory, updated after every command so
+    /// INFO doesn't need to lock every shard
+    used_memory: AtomicUsize,
+    peak_memory: AtomicUsize,
+    maxmemory: AtomicUsize,
+    policy: Mutex<EvictionPolicy>,
+    /// Shard to evict from next, so eviction takes turns between them
+    next_eviction: AtomicUsize,
+}
+
+/// A locked shard, which publishes its counters when let go.
+struct Locked<'a> {
+    storage: MutexGuard<'a, Storage>,
+    counters: &'a Counters,
+}
+
+impl Deref for Locked<'_> {
+    type Target = Storage;
+
+    fn deref(&self) -> &Storage {
+        &self.storage
+    }
+}
+
+impl DerefMut for Locked<'_> {
+    fn deref_mut(&mut self) -> &mut Storage {
+        &mut self.storage
+    }
+}
+
+impl Drop for Locked<'_> {
+    fn drop(&mut self) {
+        self.counters.publish(&self.storage);
+    }
+}
+
+impl Default for ShardedStorage {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl ShardedStorage {
+    pub fn new(shards: usize) -> Self {
+        let shards = shards.max(1);
+        Self {
+            shards: (0..shards).map(|_| Mutex::new(Storage::new())).collect(),
+            counters: (0..shards).map(|_| Counters::default()).collect(),
+            used_memory: AtomicUsize::new(0),
+            peak_memory: AtomicUsize::new(0),
+            maxmemory: AtomicUsize::new(0),
+            policy: Mutex::new(EvictionPolicy::NoEviction),
+            next_eviction: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn shard_of(&self, key: &str) -> usize {
+        shard_of(key, self.shards.len())
+    }
+
+    fn lock(&self, shard: usize) -> Locked<'_> {
+        Locked {
+            storage: self.shards[shard].lock().unwrap(),
+            counters: &self.counters[shard],
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Runs `f` on one shard, with its lock held.
+    pub fn with_shard<T>(&self, shard: usize, f: impl FnOnce(&mut Storage) -> T) -> T {
+        let mut storage = self.lock(shard);
+        let before = storage.used_memory();
+        let result = f(&mut storage);
+        self.account(before, storage.used_memory());
+        result
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn peak_memory(&self) -> usize {
+        self.peak_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn maxmemory(&self) -> usize {
+        self.maxmemory.load(Ordering::Relaxed)
+    }
+
+    pub fn maxmemory_policy(&self) -> EvictionPolicy {
+        *self.policy.lock().unwrap()
+    }
+
+    pub fn keys_count(&self) -> usize {
+        self.counters
+            .iter()
+            .map(|c| c.keys.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn expires_count(&self) -> usize {
+        self.counters
+            .iter()
+            .map(|c| c.expires.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn stats(&self) -> StorageStats {
+        let sum = |counter: fn(&Counters) -> &AtomicU64| -> u64 {
+            self.counters
+                .iter()
+                .map(|c| counter(c).load(Ordering::Relaxed))
+                .sum()
+        };
+        StorageStats {
+            keyspace_hits: sum(|c| &c.keyspace_hits),
+            keyspace_misses: sum(|c| &c.keyspace_misses),
+            expired_keys: sum(|c| &c.expired_keys),
+            evicted_keys: sum(|c| &c.evicted_keys),
+        }
+    }
+
+    /// Clears counters for CONFIG RESETSTAT. Peak memory restarts from
+    /// the current usage.
+    pub fn reset_stats(&self) {
+        for i in 0..self.shards.len() {
+            self.lock(i).reset_stats();
+        }
+        self.peak_memory
+            .store(self.used_memory(), Ordering::Relaxed);
+    }
+
+    /// Every shard gets the whole limit: one holding more than that on
+    /// its own has the keyspace over it too.
+    pub fn set_maxmemory(&self, bytes: usize) {
+        self.maxmemory.store(bytes, Ordering::Relaxed);
+        for i in 0..self.shards.len() {
+            self.lock(i).set_maxmemory(bytes);
+        }
+    }
+
+    pub fn set_maxmemory_policy(&self, policy: EvictionPolicy) {
+        *self.policy.lock().unwrap() = policy;
+        for i in 0..self.shards.len() {
+            self.lock(i).set_maxmemory_policy(policy);
+        }
+    }
+
+    pub fn set_maxmemory_samples(&self, samples: usize) {
+        for i in 0..self.shards.len() {
+            self.lock(i).set_maxmemory_samples(samples);
+        }
+    }
+
+    pub fn set_feed(&self, feed: &Arc<Feed>) {
+        for i in 0..self.shards.len() {
+            self.lock(i).set_feed(feed.clone());
+        }
+    }
+
+    pub fn set_tracking(&self, tracking: &Arc<Tracking>) {
+        for i in 0..self.shards.len() {
+            self.lock(i).set_tracking(tracking.clone());
+        }
+    }
+
+    pub fn set_watches(&self, watches: &Arc<Watches>) {
+        for i in 0..self.shards.len() {
+            self.lock(i).set_watches(watches.clone());
+        }
+    }
+
+    pub fn set_pubsub(&self, pubsub: &Arc<PubSub>) {
+        for i in 0..self.shards.len() {
+            self.lock(i).set_pubsub(pubsub.clone());
+        }
+    }
+
+    pub fn set_blocking(&self, blocking: &Arc<Blocking>) {
+        for i in 0..self.shards.len() {
+            self.lock(i).set_blocking(blocking.clone());
+        }
+    }
+
+    /// Serializes every shard with all of them locked. `at` runs under the
+    /// same locks, so what it reads lines up exactly with the snapshot.
+    pub fn snapshot<T>(&self, at: impl FnOnce() -> T) -> (String, T) {
+        let guards: Vec<Locked<'_>> = (0..self.shards.len()).map(|i| self.lock(i)).collect();
+        let mut snapshot = snapshot_header();
+        guards[0].write_indexes(&mut snapshot);
+        for shard in &guards {
+            shard.write_snapshot(&mut snapshot);
+        }
+        (snapshot, at())
+    }
+
+    /// Replaces everything with the keys of a snapshot.
+    pub fn load_snapshot(&self, records: Vec<Record>) {
+        let mut guards: Vec<Locked<'_>> = (0..self.shards.len()).map(|i| self.lock(i)).collect();
+        let before: usize = guards.iter().map(|shard| shard.used_memory()).sum();
+        for shard in guards.iter_mut() {
+            shard.flush();
+        }
+        for record in records {
+            match record.index() {
+                Some(arguments) => {
+                    for shard in guards.iter_mut() {
+                        shard.load_index(&record.key, arguments);
+                    }
+                }
+                None => guards[self.shard_of(&record.key)].load(record),
+            }
+        }
+        let after: usize = guards.iter().map(|shard| shard.used_memory()).sum();
+        self.account(before, after);
+    }
+
+    /// Runs a request already resolved to `spec` on the shards owning its
+    /// keys.
+    pub fn execute(&self, spec: &CommandSpec, args: &[CommandArg]) -> StorageResult<RESP> {
+        // Make room before locking anything, like a single `Storage` does
+        // before every command, so eviction can reach every shard
+        if let Err(e) = self.free_memory_if_needed()
+            && spec.has_flag(flags::DENYOOM)
+        {
+            return Err(e);
+        }
+        let indexes = if is_memory_report(spec) {
+            (0..self.shards.len()).collect()
+        } else {
+            shard_indexes(spec, args, self.shards.len())
+        };
+        // Ascending order, like every other multi-shard command
+        let mut guards: Vec<(usize, Locked<'_>)> =
+            indexes.into_iter().map(|i| (i, self.lock(i))).collect();
+        let result = self.execute_locked(spec, args, &mut guards);
+        self.forward_samples(guards);
+        result
+    }
+
+    /// Locks every shard and lets `f` run any number of commands while
+    /// nothing else can, for scripts.
+    pub fn atomically<T>(&self, f: impl FnOnce(&mut Executor<'_>) -> T) -> T {
+        let mut guards: Vec<(usize, Locked<'_>)> =
+            (0..self.shards.len()).map(|i| (i, self.lock(i))).collect();
+        let result = f(&mut |spec, args| self.execute_locked(spec, args, &mut guards));
+        self.forward_samples(guards);
+        result
+    }
+
+    /// Runs requests one after the other with every shard they touch
+    /// locked throughout, for EXEC. `proceed` is asked once they're all
+    /// locked, and when it says no nothing runs and None comes back.
+    pub fn execute_batch(
+        &self,
+        requests: &[(&'static CommandSpec, Vec<CommandArg>)],
+        proceed: impl FnOnce() -> bool,
+    ) -> Option<Vec<StorageResult<RESP>>> {
+        // Whatever can't be freed here fails the requests that need it
+        let _ = self.free_memory_if_needed();
+        let mut guards: Vec<(usize, Locked<'_>)> = batch_indexes(requests, self.shards.len())
+            .into_iter()
+            .map(|i| (i, self.lock(i)))
+            .collect();
+        if !proceed() {
+            return None;
+        }
+        let replies = requests
+            .iter()
+            .map(|(spec, args)| self.execute_locked(spec, args, &mut guards))
+            .collect();
+        self.forward_samples(guards);
+        Some(replies)
+    }
+
+    /// Runs a request with at least the shards it needs already locked,
+    /// counting the memory it uses as it goes.
+    fn execute_locked(
+        &self,
+        spec: &CommandSpec,
+        args: &[CommandArg],
+        guards: &mut [(usize, Locked<'_>)],
+    ) -> StorageResult<RESP> {
+        if is_memory_report(spec) {
+            let reports = guards.iter().map(|(_, shard)| shard.memory_report());
+            return Ok(memory_reply(spec.name, reports, self.peak_memory()));
+        }
+        // The keyspace may have grown since the request's shards were
+        // locked, by earlier commands of a batch or script or by others
+        if spec.has_flag(flags::DENYOOM) {
+            self.free_locked_memory(guards)?;
+        }
+        let before: usize = guards.iter().map(|(_, shard)| shard.used_memory()).sum();
+        let result = self.run_locked(spec, args, guards);
+        let after: usize = guards.iter().map(|(_, shard)| shard.used_memory()).sum();
+        self.account(before, after);
+        result
+    }
+
+    fn run_locked(
+        &self,
+        spec: &CommandSpec,
+        args: &[CommandArg],
+        guards: &mut [(usize, Locked<'_>)],
+    ) -> StorageResult<RESP> {
+        fn shard<'g, 's>(guards: &'g mut [(usize, Locked<'s>)], index: usize) -> &'g mut Storage {
+            guards
+                .iter_mut()
+                .find(|(i, _)| *i == index)
+                .map(|(_, shard)| &mut **shard)
+                .expect("every shard of a request is locked")
+        }
+        let indexes = shard_indexes(spec, args, self.shards.len());
+        if let [index] = indexes[..] {
+            return shard(guards, index).execute(spec, args);
+        }
+        if is_all_shards(spec) {
+            let request = shard_request(spec, args);
+            let mut replies = Vec::with_capacity(indexes.len());
+            for (n, index) in indexes.into_iter().enumerate() {
+                let storage = shard(guards, index);
+                replies.push(match n {
+                    0 => storage.execute(spec, &request)?,
+                    _ => storage.execute_unpropagated(spec, &request)?,
+                });
+            }
+            return join_replies(spec, args, replies);
+        }
+        if !is_multi_shard(spec) {
+            let (home, lent) = lent_keys(spec, args, |key| self.shard_of(key));
+            for (key, owner) in &lent {
+                if let Some(value) = shard(guards, *owner).lend(key) {
+                    shard(guards, home).receive(key.clone(), value);
+                }
+            }
+            let result = shard(guards, home).execute(spec, args);
+            for (key, owner) in &lent {
+                if let Some(value) = shard(guards, home).lend(key) {
+                    shard(guards, *owner).receive(key.clone(), value);
+                }
+            }
+            return result;
+        }
+        let split = split_request(spec, args, |key| self.shard_of(key))?;
+        let mut replies = BTreeMap::new();
+        for (index, request) in &split.requests {
+            replies.insert(*index, shard(guards, *index).execute(spec, request)?);
+        }
+        Ok(merge_replies(replies, &split.positions))
+    }
+
+    /// Evicts keys from one shard after the other until the keyspace is
+    /// back under `maxmemory`, locking one at a time. Fails with
+    /// `OutOfMemory` once none of them has anything left to evict.
+    fn free_memory_if_needed(&self) -> StorageResult<()> {
+        let maxmemory = self.maxmemory();
+        let mut idle = 0;
+        while maxmemory > 0 && self.used_memory() > maxmemory {
+            if idle == self.shards.len() {
+                return Err(StorageError::OutOfMemory);
+            }
+            let index = self.next_eviction.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+            if self.with_shard(index, |shard| shard.evict_one()) {
+                idle = 0;
+            } else {
+                idle += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// `free_memory_if_needed` for a request already holding locks, which
+    /// can only evict from the shards it holds.
+    fn free_locked_memory(&self, guards: &mut [(usize, Locked<'_>)]) -> StorageResult<()> {
+        let maxmemory = self.maxmemory();
+        let mut idle = 0;
+        while maxmemory > 0 && self.used_memory() > maxmemory {
+            if idle == guards.len() {
+                return Err(StorageError::OutOfMemory);
+            }
+            let n = self.next_eviction.fetch_add(1, Ordering::Relaxed) % guards.len();
+            let shard = &mut guards[n].1;
+            let before = shard.used_memory();
+            let evicted = shard.evict_one();
+            self.account(before, shard.used_memory());
+            if evicted {
+                idle = 0;
+            } else {
+                idle += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases a request's locks, then adds the samples its shards
+    /// compacted into series on other shards to those. The destinations
+    /// may be on shards it didn't lock, so they're written just after the
+    /// request rather than with it.
+    fn forward_samples(&self, mut guards: Vec<(usize, Locked<'_>)>) {
+        let samples: Vec<ForeignSample> = guards
+            .iter_mut()
+            .flat_map(|(index, shard)| {
+                foreign_samples(shard.take_foreign_samples(), *index, self.shards.len())
+            })
+            .collect();
+        drop(guards);
+        for (key, timestamp, value) in samples {
+            self.with_shard(self.shard_of(&key), |shard| {
+                shard.add_compacted(&key, timestamp, value)
+            });
+        }
+    }
+
+    fn account(&self, before: usize, after: usize) {
+        let used = if after >= before {
+            self.used_memory
+                .fetch_add(after - before, Ordering::Relaxed)
+                + (after - before)
+        } else {
+            self.used_memory
+                .fetch_sub(before - after, Ordering::Relaxed)
+                - (before - after)
+        };
+        self.peak_memory.fetch_max(used, Ordering::Relaxed);
+    }
+}
+
+/// The shard holding `key`. Keys with the same hash tag share a shard,
+/// like they share a cluster slot.
+pub(super) fn shard_of(key: &str, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    hash_tag(key).hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
+/// The samples shard `index` compacted into series it doesn't hold that
+/// belong to other shards. The rest are for series deleted since.
+pub(super) fn foreign_samples(
+    samples: Vec<ForeignSample>,
+    index: usize,
+    shards: usize,
+) -> impl Iterator<Item = ForeignSample> {
+    samples
+        .into_iter()
+        .filter(move |(key, ..)| shard_of(key, shards) != index)
+}
+
+/// Runs one command for `Keyspace::atomically`.
+pub type Executor<'a> = dyn FnMut(&'static CommandSpec, &[CommandArg]) -> StorageResult<RESP> + 'a;
+
+/// The MEMORY STATS or MEMORY DOCTOR reply for a set of shards, with the
+/// peak tracked across all of them.
+pub(super) fn memory_reply(
+    name: &str,
+    reports: impl IntoIterator<Item = MemoryReport>,
+    peak: usize,
+) -> RESP {
+    let mut reports = reports.into_iter();
+    let mut report = reports.next().expect("there is at least one shard");
+    for other in reports {
+        report.merge(&other);
+    }
+    report.peak = peak.max(report.used);
+    if name == "memory|stats" {
+        report.stats()
+    } else {
+        RESP::BulkString(report.doctor().into())
+    }
+}
+
+/// MEMORY STATS and MEMORY DOCTOR look at every shard rather than one.
+pub(super) fn is_memory_report(spec: &CommandSpec) -> bool {
+    matches!(spec.name, "memory|stats" | "memory|doctor")
+}
+
+/// Commands tipped `request_policy:all_shards`, like TS.MRANGE, look
+/// through every shard rather than at keys.
+pub(super) fn is_all_shards(spec: &CommandSpec) -> bool {
+    spec.tips.contains(&ALL_SHARDS)
+}
+
+/// The shards a request runs on, in ascending order. Commands without
+/// keys run on the first one.
+pub(super) fn shard_indexes(spec: &CommandSpec, args: &[CommandArg], shards: usize) -> Vec<usize> {
+    if is_all_shards(spec) {
+        return (0..shards).collect();
+    }
+    let mut indexes: Vec<usize> = routing_keys(spec, args)
+        .iter()
+        .map(|key| shard_of(key, shards))
+        .collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    if indexes.is_empty() {
+        indexes.push(0);
+    }
+    indexes
+}
+
+/// Every shard a batch of requests runs on, in ascending order.
+pub(super) fn batch_indexes(
+    requests: &[(&'static CommandSpec, Vec<CommandArg>)],
+    shards: usize,
+) -> Vec<usize> {
+    let mut indexes: Vec<usize> = requests
+        .iter()
+        .flat_map(|(spec, args)| match is_memory_report(spec) {
+            true => (0..shards).collect(),
+            false => shard_indexes(spec, args, shards),
+        })
+        .collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    indexes
+}
+
+/// A multi-key request split into one request per shard, each with only
+/// that shard's keys.
+pub(super) struct SplitRequest {
+    pub requests: BTreeMap<usize, Vec<CommandArg>>,
+    /// For each key in request order: its shard and index in that shard's
+    /// request
+    pub positions: Vec<(usize, usize)>,
+}
+
+const MULTI_SHARD: &str = "request_policy:multi_shard";
+const ALL_SHARDS: &str = "request_policy:all_shards";
+
+/// Commands tipped `request_policy:multi_shard`, whose keys run to the
+/// end of the request and are served independently like DEL, MGET and
+/// MSET, are split by shard. Others run on one shard with the keys of the
+/// rest lent to it, see `lent_keys`.
+pub(super) fn is_multi_shard(spec: &CommandSpec) -> bool {
+    spec.tips.contains(&MULTI_SHARD)
+}
+
+/// Splits a request tipped `request_policy:multi_shard` by shard.
+pub(super) fn split_request(
+    spec: &CommandSpec,
+    args: &[CommandArg],
+    shard_of: impl Fn(&str) -> usize,
+) -> StorageResult<SplitRequest> {
+    let first = spec.first_key as usize;
+    let step = spec.step.max(1) as usize;
+    if !(args.len() - first).is_multiple_of(step) {
+        return Err(StorageError::WrongArity(spec.name.to_string()));
+    }
+    let mut requests: BTreeMap<usize, Vec<CommandArg>> = BTreeMap::new();
+    let mut positions = Vec::new();
+    for chunk in args[first..].chunks(step) {
+        let shard = shard_of(&chunk[0]);
+        let request = requests
+            .entry(shard)
+            .or_insert_with(|| args[..first].to_vec());
+        positions.push((shard, (request.len() - first) / step));
+        request.extend_from_slice(chunk);
+    }
+    Ok(SplitRequest {
+        requests,
+        positions,
+    })
+}
+
+/// Where a request whose keys span shards but can't be split, like BITOP
+/// or PFMERGE, runs: the shard of its first key. Keys on other shards are
+/// lent to it for the command, then given back along with any the command
+/// created, so sources are read and destinations written with every shard
+/// involved locked. Returns that shard and the lent keys with their own.
+pub(super) fn lent_keys(
+    spec: &CommandSpec,
+    args: &[CommandArg],
+    shard_of: impl Fn(&str) -> usize,
+) -> (usize, Vec<(String, usize)>) {
+    let keys = routing_keys(spec, args);
+    let home = keys.first().map_or(0, |key| shard_of(key));
+    let mut lent: Vec<(String, usize)> = Vec::new();
+    for key in keys {
+        let owner = shard_of(key);
+        if owner != home && !lent.iter().any(|(lent, _)| lent == key) {
+            lent.push((key.clone(), owner));
+        }
+    }
+    (home, lent)
+}
+
+/// The keys deciding which shards a request runs on.
+fn routing_keys<'a>(spec: &CommandSpec, args: &'a [CommandArg]) -> Vec<&'a String> {
+    // DEBUG OBJECT names a key without the table declaring one, like Redis
+    if spec.command == Command::Debug {
+        return args.get(2).into_iter().map(|arg| &**arg).collect();
+    }
+    spec.keys(args)
+}
+
+/// What each shard runs for a request tipped `request_policy:all_shards`.
+/// Searches ask shards for more than the client did, so the replies can
+/// be merged.
+pub(super) fn shard_request(spec: &CommandSpec, args: &[CommandArg]) -> Vec<CommandArg> {
+    match spec.command {
+        Command::FtSearch => search::search_shard_request(args),
+        Command::FtAggregate => search::aggregate_shard_request(args),
+        _ => args.to_vec(),
+    }
+}
+
+/// Combines the replies of every shard to a request tipped
+/// `request_policy:all_shards`.
+pub(super) fn join_replies(
+    spec: &CommandSpec,
+    args: &[CommandArg],
+    replies: Vec<RESP>,
+) -> StorageResult<RESP> {
+    match spec.command {
+        Command::FtSearch => search::merge_search(args, replies),
+        Command::FtAggregate => search::merge_aggregate(args, replies),
+        Command::FtInfo => Ok(search::merge_info(replies)),
+        // Every shard has the same indexes, so they all reply the same
+        Command::FtCreate | Command::FtDropIndex => {
+            Ok(replies.into_iter().next().unwrap_or(RESP::Null))
+        }
+        _ => Ok(concat_replies(replies)),
+    }
+}
+
+/// Joins the array replies of every shard to a command tipped
+/// `request_policy:all_shards`. Each shard replies in key order, with
+/// keys or arrays starting with one, and so does the whole.
+pub(super) fn concat_replies(replies: impl IntoIterator<Item = RESP>) -> RESP {
+    fn key(reply: &RESP) -> Option<&[u8]> {
+        match reply {
+            RESP::BulkString(key) => Some(key),
+            RESP::Array(fields) => key(fields.first()?),
+            _ => None,
+        }
+    }
+    let mut elements: Vec<RESP> = replies
+        .into_iter()
+        .flat_map(|reply| match reply {
+            RESP::Array(elements) => elements,
+            _ => Vec::new(),
+        })
+        .collect();
+    elements.sort_by(|a, b| key(a).cmp(&key(b)));
+    RESP::Array(elements)
+}
+
+/// Combines per-shard replies by shape: counts are added up, arrays are
+/// put back in request order and anything else is the same everywhere.
+pub(super) fn merge_replies(
+    mut replies: BTreeMap<usize, RESP>,
+    positions: &[(usize, usize)],
+) -> RESP {
+    let (_, first) = replies.first_key_value().expect("at least two shards");
+    match first {
+        RESP::Integer(_) => RESP::Integer(
+            replies
+                .values()
+                .map(|reply| match reply {
+                    RESP::Integer(n) => *n,
+                    _ => 0,
+                })
+                .sum(),
+        ),
+        RESP::Array(_) => {
+            let mut elements: BTreeMap<usize, Vec<Option<RESP>>> = replies
+                .iter_mut()
+                .map(|(shard, reply)| match reply {
+                    RESP::Array(elements) => (*shard, elements.drain(..).map(Some).collect()),
+                    _ => (*shard, Vec::new()),
+                })
+                .collect();
+            RESP::Array(
+                positions
+                    .iter()
+                    .map(|(shard, i)| {
+                        elements
+                            .get_mut(shard)
+                            .and_then(|elements| elements.get_mut(*i)?.take())
+                            .unwrap_or(RESP::Null)
+                    })
+                    .collect(),
+            )
+        }
+        _ => replies.into_values().next().unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{bulk, run};
+
+    /// Keys known to land on different shards.
+    fn spread_keys(storage: &ShardedStorage, count: usize) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for i in 0.. {
+            let key = format!("key:{}", i);
+            if keys
+                .iter()
+                .all(|k| storage.shard_of(k) != storage.shard_of(&key))
+            {
+                keys.push(key);
+            }
+            if keys.len() == count {
+                break;
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn test_multi_key_commands_span_shards() {
+        let storage = ShardedStorage::new(4);
+        let keys = spread_keys(&storage, 3);
+        let (a, b, c) = (keys[0].as_str(), keys[1].as_str(), keys[2].as_str());
+        assert_eq!(
+            run(&storage, &["mset", a, "1", b, "2", c, "3"]),
+            Ok(RESP::SimpleString("OK".to_string()))
+        );
+        assert_eq!(
+            run(&storage, &["mget", c, "missing", a, b]),
+            Ok(RESP::Array(vec![
+                bulk("3"),
+                RESP::Null,
+                bulk("1"),
+                bulk("2")
+            ]))
+        );
+        assert_eq!(storage.keys_count(), 3);
+        assert_eq!(
+            run(&storage, &["del", a, c, "missing"]),
+            Ok(RESP::Integer(2))
+        );
+        assert_eq!(storage.keys_count(), 1);
+        assert_eq!(
+            run(&storage, &["mset", a, "1", b]),
+            Err(StorageError::WrongArity("mset".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_unsplittable_commands_span_shards() {
+        let storage = ShardedStorage::new(4);
+        let keys = spread_keys(&storage, 3);
+        let (a, b, dest) = (keys[0].as_str(), keys[1].as_str(), keys[2].as_str());
+        let ok = Ok(RESP::SimpleString("OK".to_string()));
+        let on_own_shard =
+            |key: &str| storage.with_shard(storage.shard_of(key), |s| s.contains(key));
+
+        run(&storage, &["set", a, "a"]).unwrap();
+        run(&storage, &["set", b, "b"]).unwrap();
+        assert_eq!(
+            run(&storage, &["bitop", "or", dest, a, b]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&storage, &["get", dest]), Ok(bulk("c")));
+        assert!([a, b, dest].into_iter().all(on_own_shard));
+        run(&storage, &["del", a, b, dest]).unwrap();
+
+        run(&storage, &["pfadd", a, "x", "y"]).unwrap();
+        run(&storage, &["pfadd", b, "y", "z"]).unwrap();
+        assert_eq!(run(&storage, &["pfcount", a, b]), Ok(RESP::Integer(3)));
+        assert_eq!(run(&storage, &["pfmerge", dest, a, b]), ok);
+        assert_eq!(run(&storage, &["pfcount", dest]), Ok(RESP::Integer(3)));
+        run(&storage, &["del", a, b, dest]).unwrap();
+
+        run(
+            &storage,
+            &["geoadd", a, "13.361389", "38.115556", "Palermo"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(
+                &storage,
+                &[
+                    "geosearchstore",
+                    dest,
+                    a,
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "200",
+                    "km"
+                ]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert!(on_own_shard(dest));
+        run(&storage, &["del", a, dest]).unwrap();
+
+        for key in [a, b, dest] {
+            run(&storage, &["cms.initbydim", key, "10", "5"]).unwrap();
+        }
+        run(&storage, &["cms.incrby", a, "x", "1"]).unwrap();
+        run(&storage, &["cms.incrby", b, "x", "2"]).unwrap();
+        assert_eq!(run(&storage, &["cms.merge", dest, "2", a, b]), ok);
+        assert_eq!(
+            run(&storage, &["cms.query", dest, "x"]),
+            Ok(RESP::Array(vec![RESP::Integer(3)]))
+        );
+        run(&storage, &["del", a, b, dest]).unwrap();
+
+        run(&storage, &["ts.create", a]).unwrap();
+        run(&storage, &["ts.create", dest]).unwrap();
+        assert_eq!(
+            run(
+                &storage,
+                &["ts.createrule", a, dest, "AGGREGATION", "sum", "10"]
+            ),
+            ok
+        );
+        // Compacted samples reach the destination on its own shard
+        for (timestamp, value) in [("1", "1"), ("5", "2"), ("12", "4")] {
+            run(&storage, &["ts.add", a, timestamp, value]).unwrap();
+        }
+        assert_eq!(
+            run(&storage, &["ts.range", dest, "-", "+"]),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                RESP::Integer(0),
+                bulk("3")
+            ])]))
+        );
+        assert_eq!(storage.keys_count(), 2);
+    }
+
+    #[test]
+    fn test_all_shards_commands() {
+        let storage = ShardedStorage::new(4);
+        let keys = spread_keys(&storage, 3);
+        for key in keys.iter().rev() {
+            run(
+                &storage,
+                &["ts.add", key, "1", "1", "LABELS", "kind", "cpu"],
+            )
+            .unwrap();
+        }
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(
+            run(&storage, &["ts.queryindex", "kind=cpu"]),
+            Ok(RESP::Array(sorted.iter().map(|key| bulk(key)).collect()))
+        );
+        let Ok(RESP::Array(series)) = run(&storage, &["ts.mrange", "-", "+", "FILTER", "kind=cpu"])
+        else {
+            panic!("TS.MRANGE replies with an array");
+        };
+        assert_eq!(series.len(), 3);
+        assert_eq!(
+            run(&storage, &["ts.queryindex", "kind!=cpu"]),
+            Err(StorageError::CommandSyntaxError(
+                "TSDB: please provide at least one matcher".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_hash_tags_share_a_shard() {
+        let storage = ShardedStorage::new(16);
+        let shard = storage.shard_of("{cpu}");
+        assert!((0..100).all(|i| storage.shard_of(&format!("{{cpu}}:{}", i)) == shard));
+        run(&storage, &["ts.create", "{cpu}:raw"]).unwrap();
+        run(&storage, &["ts.create", "{cpu}:avg"]).unwrap();
+        assert_eq!(
+            run(
+                &storage,
+                &[
+                    "ts.createrule",
+                    "{cpu}:raw",
+                    "{cpu}:avg",
+                    "AGGREGATION",
+                    "avg",
+                    "10"
+                ]
+            ),
+            Ok(RESP::SimpleString("OK".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_memory_is_summed_across_shards() {
+        let storage = ShardedStorage::new(4);
+        for key in spread_keys(&storage, 4) {
+            run(&storage, &["set", &key, "value"]).unwrap();
+        }
+        let used = storage.used_memory();
+        assert!(used > 0);
+        assert_eq!(storage.peak_memory(), used);
+        let total: usize = (0..4).map(|i| storage.lock(i).used_memory()).sum();
+        assert_eq!(used, total);
+        run(&storage, &["del", "key:0"]).unwrap();
+        assert!(storage.used_memory() < used);
+        assert_eq!(storage.peak_memory(), used);
+    }
+
+    #[test]
+    fn test_maxmemory_limits_the_whole_keyspace() {
+        let storage = ShardedStorage::new(4);
+        let value = "x".repeat(100);
+        // Keys sharing a hash tag all land on one shard
+        let set = |i: usize| run(&storage, &["set", &format!("{{tag}}:{:02}", i), &value]);
+        for i in 0..10 {
+            set(i).unwrap();
+        }
+        storage.set_maxmemory(storage.used_memory() * 2);
+        for i in 10..19 {
+            assert_eq!(set(i), Ok(RESP::SimpleString("OK".to_string())));
+        }
+        let refused = (19..40).map(set).find(|result| result.is_err());
+        assert_eq!(refused, Some(Err(StorageError::OutOfMemory)));
+        assert_eq!(run(&storage, &["get", "{tag}:00"]), Ok(bulk(&value)));
+    }
+
+    #[test]
+    fn test_eviction_reaches_every_shard() {
+        let storage = ShardedStorage::new(4);
+        let value = "x".repeat(100);
+        let keys: Vec<String> = (0..40).map(|i| format!("key:{}", i)).collect();
+        for key in &keys {
+            run(&storage, &["set", key, &value]).unwrap();
+        }
+        let limit = storage.used_memory();
+        storage.set_maxmemory(limit);
+        storage.set_maxmemory_policy(EvictionPolicy::AllKeysRandom);
+        for i in 0..40 {
+            let key = format!("{{tag}}:{}", i);
+            assert!(run(&storage, &["set", &key, &value]).is_ok());
+        }
+        run(&storage, &["get", "{tag}:0"]).unwrap();
+        assert!(storage.used_memory() <= limit);
+        let tagged = storage.shard_of("{tag}");
+        let evicted_elsewhere = keys
+            .iter()
+            .filter(|key| storage.shard_of(key) != tagged)
+            .filter(|key| run(&storage, &["get", key]) == Ok(RESP::Null))
+            .count();
+        assert!(evicted_elsewhere > 0);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let storage = ShardedStorage::new(8);
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let storage = &storage;
+                scope.spawn(move || {
+                    for i in 0..250 {
+                        let key = format!("{}:{}", thread, i);
+                        run(storage, &["mset", &key, "v", "counter", "0"]).unwrap();
+                        run(storage, &["incr", &key]).unwrap_err();
+                    }
+                });
+            }
+        });
+        assert_eq!(storage.keys_count(), 1001);
+    }
+}
@@ -0,0 +1,544 @@
+use super::result::{StorageError, StorageResult};
+use super::{Storage, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::countmin::{self, CountMinSketch};
+use crate::ds::topk::{self, TopK};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+const CMS_NO_KEY: &str = "CMS: key does not exist";
+const CMS_KEY_EXISTS: &str = "CMS: key already exists";
+const CMS_BAD_NUMBER: &str = "CMS: Cannot parse number";
+const TOPK_NO_KEY: &str = "TopK: key does not exist";
+/// The most one TOPK.INCRBY can add, as each unit may decay a bucket.
+const TOPK_MAX_INCREMENT: u32 = 100_000;
+
+fn wrong_arity(command: &[CommandArg]) -> StorageError {
+    StorageError::WrongArity(command[0].to_lowercase())
+}
+
+/// A count or dimension of at least 1, or `message` as the error.
+fn parse_positive(arg: &str, message: &str) -> StorageResult<u32> {
+    arg.parse::<u32>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| StorageError::syntax(message))
+}
+
+/// A rate strictly between 0 and 1, or `message` as the error.
+fn parse_rate(arg: &str, message: &str) -> StorageResult<f64> {
+    arg.parse::<f64>()
+        .ok()
+        .filter(|rate| *rate > 0.0 && *rate < 1.0)
+        .ok_or_else(|| StorageError::syntax(message))
+}
+
+impl Storage {
+    fn read_cms(&mut self, key: &str) -> StorageResult<Option<&CountMinSketch>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::CountMin(sketch)) => Ok(Some(sketch)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the sketch at `key`, keeping `used_memory` in step with
+    /// what it changes. None if there is no sketch.
+    fn write_cms<T>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&mut CountMinSketch) -> T,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            return Ok(None);
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::CountMin(sketch) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = sketch.memory_usage();
+        let result = f(sketch);
+        self.used_memory = (self.used_memory + sketch.memory_usage()).saturating_sub(before);
+        Ok(Some(result))
+    }
+
+    fn read_topk(&mut self, key: &str) -> StorageResult<Option<&TopK>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::TopK(topk)) => Ok(Some(topk)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `write_cms`, for a Top-K sketch.
+    fn write_topk<T>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&mut TopK) -> T,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            return Ok(None);
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::TopK(topk) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = topk.memory_usage();
+        let result = f(topk);
+        self.used_memory = (self.used_memory + topk.memory_usage()).saturating_sub(before);
+        Ok(Some(result))
+    }
+
+    /// Stores a new sketch at `key`, which must not exist yet.
+    fn create_sketch(
+        &mut self,
+        command: &[CommandArg],
+        value: StorageValue,
+        exists: &str,
+    ) -> StorageResult<RESP> {
+        let key = &command[1];
+        if self.lookup(key).is_some() {
+            return Err(StorageError::syntax(exists));
+        }
+        self.insert(key.to_string(), value);
+        self.notify(notify::MODULE, &command[0].to_lowercase(), key);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// CMS.INITBYDIM key width depth
+    pub(super) fn command_cms_initbydim(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let width = parse_positive(&command[2], "CMS: invalid width")?;
+        let depth = parse_positive(&command[3], "CMS: invalid depth")?;
+        let sketch = CountMinSketch::new(width, depth);
+        self.create_sketch(command, StorageValue::CountMin(sketch), CMS_KEY_EXISTS)
+    }
+
+    /// CMS.INITBYPROB key error probability
+    pub(super) fn command_cms_initbyprob(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let error = parse_rate(&command[2], "CMS: invalid overestimation value")?;
+        let probability = parse_rate(&command[3], "CMS: invalid prob value")?;
+        let (width, depth) = countmin::dimensions(error, probability);
+        let sketch = CountMinSketch::new(width, depth);
+        self.create_sketch(command, StorageValue::CountMin(sketch), CMS_KEY_EXISTS)
+    }
+
+    /// CMS.INCRBY key item increment [item increment ...]
+    ///
+    /// Items are added in order, so an overflow leaves the ones before it
+    /// added.
+    pub(super) fn command_cms_incrby(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if !command[2..].len().is_multiple_of(2) {
+            return Err(wrong_arity(command));
+        }
+        let mut increments = Vec::with_capacity(command[2..].len() / 2);
+        for pair in command[2..].chunks(2) {
+            let by = pair[1]
+                .parse::<u32>()
+                .map_err(|_| StorageError::syntax(CMS_BAD_NUMBER))?;
+            increments.push((pair[0].as_bytes(), by));
+        }
+        let key = &command[1];
+        let counts = self
+            .write_cms(key, |sketch| {
+                increments
+                    .into_iter()
+                    .map(|(item, by)| sketch.increment(item, by))
+                    .collect::<Vec<_>>()
+            })?
+            .ok_or_else(|| StorageError::syntax(CMS_NO_KEY))?;
+        self.notify(notify::MODULE, "cms.incrby", key);
+        let counts = counts
+            .into_iter()
+            .map(|count| count.map(|count| RESP::Integer(i64::from(count))))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| StorageError::syntax("CMS: INCRBY overflow"))?;
+        Ok(RESP::Array(counts))
+    }
+
+    /// CMS.QUERY key item [item ...]
+    pub(super) fn command_cms_query(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let sketch = self
+            .read_cms(&command[1])?
+            .ok_or_else(|| StorageError::syntax(CMS_NO_KEY))?;
+        Ok(RESP::Array(
+            command[2..]
+                .iter()
+                .map(|item| RESP::Integer(i64::from(sketch.query(item.as_bytes()))))
+                .collect(),
+        ))
+    }
+
+    /// CMS.MERGE destination numkeys source [source ...] [WEIGHTS weight
+    /// [weight ...]]
+    ///
+    /// The destination must already exist with the same dimensions as
+    /// every source, and is overwritten rather than added to.
+    pub(super) fn command_cms_merge(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let numkeys = command[2]
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| StorageError::syntax("CMS: invalid numkeys"))?;
+        let sources = command
+            .get(3..3 + numkeys)
+            .ok_or_else(|| wrong_arity(command))?;
+        let weights = match &command[3 + numkeys..] {
+            [] => vec![1; numkeys],
+            [token, weights @ ..] if token.eq_ignore_ascii_case("WEIGHTS") => {
+                if weights.len() != numkeys {
+                    return Err(wrong_arity(command));
+                }
+                weights
+                    .iter()
+                    .map(|weight| weight.parse::<i64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| StorageError::syntax(CMS_BAD_NUMBER))?
+            }
+            _ => return Err(StorageError::syntax("syntax error")),
+        };
+
+        let destination = &command[1];
+        let (width, depth) = self
+            .read_cms(destination)?
+            .map(|sketch| (sketch.width(), sketch.depth()))
+            .ok_or_else(|| StorageError::syntax(CMS_NO_KEY))?;
+        let mut sketches = Vec::with_capacity(numkeys);
+        for source in sources {
+            let sketch = self
+                .read_cms(source)?
+                .ok_or_else(|| StorageError::syntax(CMS_NO_KEY))?;
+            if (sketch.width(), sketch.depth()) != (width, depth) {
+                return Err(StorageError::syntax("CMS: width/depth is not equal"));
+            }
+            sketches.push(sketch.clone());
+        }
+        let weighted: Vec<(&CountMinSketch, i64)> = sketches.iter().zip(weights).collect();
+        self.write_cms(destination, |sketch| sketch.merge(&weighted))?
+            .flatten()
+            .ok_or_else(|| StorageError::syntax("CMS: MERGE overflow"))?;
+        self.notify(notify::MODULE, "cms.merge", destination);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// CMS.INFO key
+    pub(super) fn command_cms_info(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let sketch = self
+            .read_cms(&command[1])?
+            .ok_or_else(|| StorageError::syntax(CMS_NO_KEY))?;
+        Ok(RESP::Array(vec![
+            RESP::SimpleString(String::from("width")),
+            RESP::Integer(i64::from(sketch.width())),
+            RESP::SimpleString(String::from("depth")),
+            RESP::Integer(i64::from(sketch.depth())),
+            RESP::SimpleString(String::from("count")),
+            RESP::Integer(sketch.count() as i64),
+        ]))
+    }
+
+    /// TOPK.RESERVE key topk [width depth decay]
+    pub(super) fn command_topk_reserve(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let k = parse_positive(&command[2], "TopK: invalid k")?;
+        let (width, depth, decay) = match &command[3..] {
+            [] => (
+                topk::DEFAULT_WIDTH,
+                topk::DEFAULT_DEPTH,
+                topk::DEFAULT_DECAY,
+            ),
+            [width, depth, decay] => (
+                parse_positive(width, "TopK: invalid width")?,
+                parse_positive(depth, "TopK: invalid depth")?,
+                decay
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|decay| *decay > 0.0 && *decay <= 1.0)
+                    .ok_or_else(|| {
+                        StorageError::syntax("TopK: invalid decay value. must be '<= 1' & '> 0'")
+                    })?,
+            ),
+            _ => return Err(wrong_arity(command)),
+        };
+        let sketch = TopK::new(k, width, depth, decay);
+        self.create_sketch(
+            command,
+            StorageValue::TopK(sketch),
+            "TopK: key already exists",
+        )
+    }
+
+    /// Adds each item by its increment, and replies with the items pushed
+    /// out of the top list, or nulls.
+    fn topk_add(
+        &mut self,
+        command: &[CommandArg],
+        increments: Vec<(&str, u32)>,
+    ) -> StorageResult<RESP> {
+        let key = &command[1];
+        let expelled = self
+            .write_topk(key, |topk| {
+                increments
+                    .into_iter()
+                    .map(|(item, by)| topk.add(item, by))
+                    .collect::<Vec<_>>()
+            })?
+            .ok_or_else(|| StorageError::syntax(TOPK_NO_KEY))?;
+        self.notify(notify::MODULE, &command[0].to_lowercase(), key);
+        Ok(RESP::Array(
+            expelled
+                .into_iter()
+                .map(|item| item.map_or(RESP::Null, |s| RESP::BulkString(s.into())))
+                .collect(),
+        ))
+    }
+
+    /// TOPK.ADD key item [item ...]
+    pub(super) fn command_topk_add(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let increments = command[2..].iter().map(|item| (item.as_str(), 1)).collect();
+        self.topk_add(command, increments)
+    }
+
+    /// TOPK.INCRBY key item increment [item increment ...]
+    pub(super) fn command_topk_incrby(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if !command[2..].len().is_multiple_of(2) {
+            return Err(wrong_arity(command));
+        }
+        let mut increments = Vec::with_capacity(command[2..].len() / 2);
+        for pair in command[2..].chunks(2) {
+            let by = pair[1]
+                .parse::<u32>()
+                .ok()
+                .filter(|by| *by <= TOPK_MAX_INCREMENT)
+                .ok_or_else(|| {
+                    let message = "TopK: increment must be an integer greater or equal to 0 and smaller or equal to 100000";
+                    StorageError::syntax(message)
+                })?;
+            increments.push((pair[0].as_str(), by));
+        }
+        self.topk_add(command, increments)
+    }
+
+    /// TOPK.QUERY key item [item ...]
+    pub(super) fn command_topk_query(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let topk = self
+            .read_topk(&command[1])?
+            .ok_or_else(|| StorageError::syntax(TOPK_NO_KEY))?;
+        Ok(RESP::Array(
+            command[2..]
+                .iter()
+                .map(|item| RESP::Integer(i64::from(topk.contains(item))))
+                .collect(),
+        ))
+    }
+
+    /// TOPK.LIST key [WITHCOUNT]
+    pub(super) fn command_topk_list(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let with_count = match &command[2..] {
+            [] => false,
+            [arg] if arg.eq_ignore_ascii_case("WITHCOUNT") => true,
+            _ => return Err(StorageError::syntax("syntax error")),
+        };
+        let topk = self
+            .read_topk(&command[1])?
+            .ok_or_else(|| StorageError::syntax(TOPK_NO_KEY))?;
+        Ok(RESP::Array(
+            topk.list()
+                .into_iter()
+                .flat_map(|(item, count)| {
+                    let count = with_count.then_some(RESP::Integer(i64::from(count)));
+                    std::iter::once(RESP::BulkString(item.into())).chain(count)
+                })
+                .collect(),
+        ))
+    }
+
+    /// TOPK.INFO key
+    pub(super) fn command_topk_info(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let topk = self
+            .read_topk(&command[1])?
+            .ok_or_else(|| StorageError::syntax(TOPK_NO_KEY))?;
+        Ok(RESP::Array(vec![
+            RESP::SimpleString(String::from("k")),
+            RESP::Integer(i64::from(topk.k())),
+            RESP::SimpleString(String::from("width")),
+            RESP::Integer(i64::from(topk.width())),
+            RESP::SimpleString(String::from("depth")),
+            RESP::Integer(i64::from(topk.depth())),
+            RESP::SimpleString(String::from("decay")),
+            RESP::BulkString(topk.decay().to_string().into()),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{bulk, run};
+
+    fn ints(values: &[i64]) -> RESP {
+        RESP::Array(values.iter().map(|n| RESP::Integer(*n)).collect())
+    }
+
+    #[test]
+    fn test_cms_incrby_and_query() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["cms.incrby", "cms", "a", "1"]),
+            Err(StorageError::syntax(CMS_NO_KEY))
+        );
+        run(&mut storage, &["cms.initbydim", "cms", "100", "5"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["cms.incrby", "cms", "a", "3", "b", "1", "a", "2"]
+            ),
+            Ok(ints(&[3, 1, 5]))
+        );
+        assert_eq!(
+            run(&mut storage, &["cms.query", "cms", "a", "b", "c"]),
+            Ok(ints(&[5, 1, 0]))
+        );
+        assert_eq!(
+            run(&mut storage, &["cms.incrby", "cms", "a", "x"]),
+            Err(StorageError::syntax(CMS_BAD_NUMBER))
+        );
+        assert_eq!(
+            run(&mut storage, &["cms.incrby", "cms", "a", "4294967295"]),
+            Err(StorageError::syntax("CMS: INCRBY overflow"))
+        );
+        assert_eq!(
+            run(&mut storage, &["cms.initbydim", "cms", "10", "2"]),
+            Err(StorageError::syntax(CMS_KEY_EXISTS))
+        );
+        assert_eq!(
+            run(&mut storage, &["cms.info", "cms"]),
+            Ok(RESP::Array(vec![
+                RESP::SimpleString(String::from("width")),
+                RESP::Integer(100),
+                RESP::SimpleString(String::from("depth")),
+                RESP::Integer(5),
+                RESP::SimpleString(String::from("count")),
+                RESP::Integer(6),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_cms_initbyprob() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["cms.initbyprob", "cms", "0.001", "0.01"]).unwrap();
+        let RESP::Array(info) = run(&mut storage, &["cms.info", "cms"]).unwrap() else {
+            panic!("CMS.INFO replies with an array");
+        };
+        assert_eq!(info[1], RESP::Integer(2000));
+        assert_eq!(info[3], RESP::Integer(7));
+        assert_eq!(
+            run(&mut storage, &["cms.initbyprob", "x", "1", "0.01"]),
+            Err(StorageError::syntax("CMS: invalid overestimation value"))
+        );
+    }
+
+    #[test]
+    fn test_cms_merge() {
+        let mut storage = Storage::new();
+        for key in ["a", "b", "dest"] {
+            run(&mut storage, &["cms.initbydim", key, "50", "4"]).unwrap();
+        }
+        run(&mut storage, &["cms.incrby", "a", "x", "2"]).unwrap();
+        run(&mut storage, &["cms.incrby", "b", "x", "5", "y", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["cms.merge", "dest", "2", "a", "b"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(&mut storage, &["cms.query", "dest", "x", "y"]),
+            Ok(ints(&[7, 1]))
+        );
+        run(
+            &mut storage,
+            &["cms.merge", "dest", "2", "a", "b", "WEIGHTS", "3", "1"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["cms.query", "dest", "x"]),
+            Ok(ints(&[11]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["cms.merge", "dest", "2", "a", "b", "WEIGHTS", "1"]
+            ),
+            Err(StorageError::WrongArity(String::from("cms.merge")))
+        );
+        assert_eq!(
+            run(&mut storage, &["cms.merge", "dest", "1", "missing"]),
+            Err(StorageError::syntax(CMS_NO_KEY))
+        );
+        run(&mut storage, &["cms.initbydim", "small", "10", "4"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["cms.merge", "dest", "1", "small"]),
+            Err(StorageError::syntax("CMS: width/depth is not equal"))
+        );
+    }
+
+    #[test]
+    fn test_topk() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(&mut storage, &["topk.reserve", "tk", "2", "20", "4", "0.9"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.add", "tk", "a", "b", "a"]),
+            Ok(RESP::Array(vec![RESP::Null, RESP::Null, RESP::Null]))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.incrby", "tk", "c", "5"]),
+            Ok(RESP::Array(vec![bulk("b")]))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.list", "tk", "WITHCOUNT"]),
+            Ok(RESP::Array(vec![
+                bulk("c"),
+                RESP::Integer(5),
+                bulk("a"),
+                RESP::Integer(2),
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.query", "tk", "a", "b"]),
+            Ok(ints(&[1, 0]))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.incrby", "tk", "c", "100001"]),
+            Err(StorageError::syntax(
+                "TopK: increment must be an integer greater or equal to 0 and smaller or equal to 100000"
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.reserve", "x", "2", "20", "4", "1.5"]),
+            Err(StorageError::syntax(
+                "TopK: invalid decay value. must be '<= 1' & '> 0'"
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.reserve", "x", "2", "20"]),
+            Err(StorageError::WrongArity(String::from("topk.reserve")))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.list", "missing"]),
+            Err(StorageError::syntax(TOPK_NO_KEY))
+        );
+        assert_eq!(
+            run(&mut storage, &["topk.info", "tk"]),
+            Ok(RESP::Array(vec![
+                RESP::SimpleString(String::from("k")),
+                RESP::Integer(2),
+                RESP::SimpleString(String::from("width")),
+                RESP::Integer(20),
+                RESP::SimpleString(String::from("depth")),
+                RESP::Integer(4),
+                RESP::SimpleString(String::from("decay")),
+                bulk("0.9"),
+            ]))
+        );
+    }
+}
@@ -0,0 +1,605 @@
+use super::result::{StorageError, StorageResult};
+use super::{PrimitiveStorageValue, Storage, StorageValue, now_ms};
+use crate::command::CommandArg;
+use crate::ds::bloom::ScalableBloom;
+use crate::ds::countmin::CountMinSketch;
+use crate::ds::cuckoo::CuckooFilter;
+use crate::ds::json::{Document, Value};
+use crate::ds::list::{Deque, List};
+use crate::ds::sortedset::SortedSet;
+use crate::ds::stream::{Consumer, ConsumerGroup, Stream, StreamId};
+use crate::ds::timeseries::TimeSeries;
+use crate::ds::topk::TopK;
+use crate::ds::vectorset::VectorSet;
+use crate::pubsub::notify;
+use crate::resp::{RESP, bytes_to_resp};
+
+/// First line of every snapshot, bumped whenever the format changes.
+const SNAPSHOT_HEADER: &str = "KVSNAPSHOT 1\r\n";
+
+/// One key read back from a snapshot, or one search index, named by
+/// `key`.
+pub struct Record {
+    pub key: String,
+    payload: Payload,
+    /// Absolute expire time in unix milliseconds
+    expires_at: Option<u64>,
+}
+
+enum Payload {
+    Value(StorageValue),
+    /// The FT.CREATE arguments after the index's name
+    Index(Vec<String>),
+}
+
+impl Record {
+    /// The arguments that create the index, if this is one rather than a
+    /// key. Every shard loads those.
+    pub(super) fn index(&self) -> Option<&[String]> {
+        match &self.payload {
+            Payload::Index(arguments) => Some(arguments),
+            Payload::Value(_) => None,
+        }
+    }
+}
+
+/// Appends a record to a snapshot. Records only hold strings, with bytes
+/// hex encoded, so they are always UTF-8.
+pub(super) fn append_record(out: &mut String, record: RESP) {
+    out.push_str(&String::from_utf8(record.to_bytes()).expect("records are UTF-8"));
+}
+
+/// Starts a snapshot, which shards then append their keys to.
+pub(super) fn snapshot_header() -> String {
+    SNAPSHOT_HEADER.to_string()
+}
+
+impl Storage {
+    /// Appends every key that hasn't expired to a snapshot. Each key is a
+    /// RESP array of its type, name, expire time (empty without a TTL)
+    /// and the value's elements.
+    pub(super) fn write_snapshot(&self, out: &mut String) {
+        let now = now_ms();
+        for (key, entry) in self.store.iter() {
+            let expires_at = self.expires.get(key).copied();
+            if expires_at.is_some_and(|at| at <= now) {
+                continue;
+            }
+            append_record(out, record(key, &entry.value, expires_at));
+        }
+    }
+
+    /// A key on its own as a snapshot, without its TTL, along with its
+    /// absolute expire time. This is what DUMP and MIGRATE send.
+    pub(super) fn dump(&mut self, key: &str) -> Option<(String, Option<u64>)> {
+        self.expire_if_needed(key);
+        let entry = self.store.get(key)?;
+        let mut payload = snapshot_header();
+        append_record(&mut payload, record(key, &entry.value, None));
+        Some((payload, self.expires.get(key).copied()))
+    }
+
+    pub(super) fn command_dump(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if self.lookup_read(&command[1]).is_none() {
+            return Ok(RESP::Null);
+        }
+        let (payload, _) = self.dump(&command[1]).expect("the key was just found");
+        Ok(RESP::BulkString(payload.into()))
+    }
+
+    /// RESTORE key ttl serialized-value [REPLACE] [ABSTTL]
+    pub(super) fn command_restore(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let ttl: i64 = command[2]
+            .parse()
+            .ok()
+            .filter(|ttl| *ttl >= 0)
+            .ok_or_else(|| StorageError::syntax("Invalid TTL value, must be >= 0"))?;
+        let (mut replace, mut absolute) = (false, false);
+        for option in &command[4..] {
+            match option.to_uppercase().as_str() {
+                "REPLACE" => replace = true,
+                "ABSTTL" => absolute = true,
+                _ => return Err(StorageError::syntax_error()),
+            }
+        }
+        let mut records = parse_snapshot(&command[3])
+            .filter(|records| records.len() == 1 && records[0].index().is_none())
+            .ok_or_else(|| StorageError::syntax("DUMP payload version or checksum are wrong"))?;
+        let key = command[1].as_str();
+        self.expire_if_needed(key);
+        if !replace && self.store.contains_key(key) {
+            return Err(StorageError::BusyKey);
+        }
+        let mut record = records.remove(0);
+        record.key = key.to_string();
+        record.expires_at = match ttl as u64 {
+            0 => None,
+            at if absolute => Some(at),
+            ttl => Some(now_ms() + ttl),
+        };
+        // Already expired, so all that's left to do is drop the old value
+        if record.expires_at.is_some_and(|at| at <= now_ms()) {
+            self.remove(key);
+        } else {
+            self.load(record);
+            self.notify(notify::GENERIC, "restore", key);
+        }
+        Ok(RESP::SimpleString("OK".to_string()))
+    }
+
+    /// Drops every key, before loading a snapshot.
+    pub(super) fn flush(&mut self) {
+        // The snapshot brings its own indexes
+        self.indexes.clear();
+        let keys: Vec<String> = self.store.keys().cloned().collect();
+        for key in keys {
+            self.remove(&key);
+        }
+        self.eviction_pool.clear();
+    }
+
+    pub(super) fn load(&mut self, record: Record) {
+        let value = match record.payload {
+            Payload::Value(value) => value,
+            Payload::Index(arguments) => return self.load_index(&record.key, &arguments),
+        };
+        self.insert(record.key.clone(), value);
+        if let Some(at) = record.expires_at {
+            self.expire_at(&record.key, at);
+        }
+        self.reindex(&record.key);
+    }
+}
+
+fn record(key: &str, value: &StorageValue, expires_at: Option<u64>) -> RESP {
+    let (kind, elements): (&str, Vec<String>) = match value {
+        StorageValue::Primitive(PrimitiveStorageValue::String(s)) => ("string", vec![s.clone()]),
+        StorageValue::Primitive(PrimitiveStorageValue::Integer(n)) => {
+            ("integer", vec![n.to_string()])
+        }
+        // Snapshots are RESP strings too, so raw bytes go in as hex
+        StorageValue::Primitive(PrimitiveStorageValue::Bytes(b)) => ("bytes", vec![hex(b)]),
+        // Lists holding raw bytes go in as hex, like byte strings
+        StorageValue::List(list)
+            if list
+                .iter()
+                .any(|element| matches!(element, PrimitiveStorageValue::Bytes(_))) =>
+        {
+            (
+                "bytelist",
+                list.iter()
+                    .map(|element| hex(&element.as_bytes()))
+                    .collect(),
+            )
+        }
+        StorageValue::List(list) => ("list", list.iter().map(element_string).collect()),
+        StorageValue::Stream(stream) => ("stream", stream_elements(stream)),
+        StorageValue::Json(doc) => ("json", vec![doc.root().to_json()]),
+        // Filters and sketches are mostly bits and counters, kept as hex
+        StorageValue::Bloom(filter) => ("bloom", vec![hex(&filter.to_bytes())]),
+        StorageValue::Cuckoo(filter) => ("cuckoo", vec![hex(&filter.to_bytes())]),
+        StorageValue::CountMin(sketch) => ("cms", vec![hex(&sketch.to_bytes())]),
+        StorageValue::TopK(topk) => ("topk", vec![hex(&topk.to_bytes())]),
+        // Chunks are compressed already, so they go in as they are
+        StorageValue::TimeSeries(series) => ("timeseries", vec![hex(&series.to_bytes())]),
+        // The graph's links go in too, rather than being rebuilt on load
+        StorageValue::VectorSet(set) => ("vectorset", vec![hex(&set.to_bytes())]),
+        StorageValue::SortedSet(set) => (
+            "zset",
+            set.iter()
+                .flat_map(|(member, score)| [member.to_string(), score.to_string()])
+                .collect(),
+        ),
+    };
+    let fields = [
+        kind.to_string(),
+        key.to_string(),
+        expires_at.map(|at| at.to_string()).unwrap_or_default(),
+    ];
+    RESP::Array(
+        fields
+            .into_iter()
+            .chain(elements)
+            .map(|s| RESP::BulkString(s.into()))
+            .collect(),
+    )
+}
+
+fn element_string(value: &PrimitiveStorageValue) -> String {
+    match value {
+        PrimitiveStorageValue::String(s) => s.clone(),
+        PrimitiveStorageValue::Integer(n) => n.to_string(),
+        PrimitiveStorageValue::Bytes(b) => String::from_utf8_lossy(b).into_owned(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// A stream flattened as its counters, its entries as `id nfields
+/// fields...`, then each group with its consumers and pending entries.
+fn stream_elements(stream: &Stream) -> Vec<String> {
+    let mut elements = vec![
+        stream.last_id().to_string(),
+        stream.entries_added().to_string(),
+        stream.max_deleted_id().to_string(),
+        stream.len().to_string(),
+    ];
+    for (id, fields) in stream.range(StreamId::MIN, StreamId::MAX) {
+        elements.push(id.to_string());
+        elements.push(fields.len().to_string());
+        elements.extend(fields.iter().cloned());
+    }
+    let groups: Vec<(&str, &ConsumerGroup)> = stream.groups().collect();
+    elements.push(groups.len().to_string());
+    for (name, group) in groups {
+        elements.push(name.to_string());
+        elements.push(group.last_id.to_string());
+        elements.push(optional_string(group.entries_read));
+        let consumers: Vec<(&str, &Consumer)> = group.consumers().collect();
+        elements.push(consumers.len().to_string());
+        for (name, consumer) in consumers {
+            elements.push(name.to_string());
+            elements.push(consumer.seen_time.to_string());
+            elements.push(optional_string(consumer.active_time));
+        }
+        elements.push(group.pending_len().to_string());
+        for (id, entry) in group.pending_range(StreamId::MIN, StreamId::MAX) {
+            elements.push(id.to_string());
+            elements.push(entry.consumer.clone());
+            elements.push(entry.delivery_time.to_string());
+            elements.push(entry.delivery_count.to_string());
+        }
+    }
+    elements
+}
+
+fn optional_string(value: Option<u64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn parse_stream(fields: &mut impl Iterator<Item = String>) -> Option<Stream> {
+    fn number<T: std::str::FromStr>(fields: &mut impl Iterator<Item = String>) -> Option<T> {
+        fields.next()?.parse().ok()
+    }
+    fn id(fields: &mut impl Iterator<Item = String>) -> Option<StreamId> {
+        StreamId::parse(&fields.next()?, 0)
+    }
+    fn optional(fields: &mut impl Iterator<Item = String>) -> Option<Option<u64>> {
+        match fields.next()?.as_str() {
+            "" => Some(None),
+            value => Some(Some(value.parse().ok()?)),
+        }
+    }
+
+    let mut stream = Stream::new();
+    let (last_id, added, max_deleted) = (id(fields)?, number(fields)?, id(fields)?);
+    for _ in 0..number::<usize>(fields)? {
+        let entry_id = id(fields)?;
+        let count: usize = number(fields)?;
+        let entry: Vec<String> = fields.by_ref().take(count).collect();
+        if entry.len() != count || entry_id <= stream.last_id() && !stream.is_empty() {
+            return None;
+        }
+        stream.add(entry_id, entry);
+    }
+    stream.set_counters(last_id, max_deleted, added);
+    for _ in 0..number::<usize>(fields)? {
+        let name = fields.next()?;
+        let mut group = ConsumerGroup::new(id(fields)?, optional(fields)?);
+        let mut active_times = Vec::new();
+        for _ in 0..number::<usize>(fields)? {
+            let consumer = fields.next()?;
+            group.create_consumer(&consumer, number(fields)?);
+            active_times.push((consumer, optional(fields)?));
+        }
+        for _ in 0..number::<usize>(fields)? {
+            let (pending_id, consumer) = (id(fields)?, fields.next()?);
+            group.consumer(&consumer)?;
+            group.claim(pending_id, &consumer, number(fields)?, number(fields)?);
+        }
+        for (consumer, active_time) in active_times {
+            group.consumer_mut(&consumer)?.active_time = active_time;
+        }
+        stream.create_group(&name, group);
+    }
+    Some(stream)
+}
+
+/// Reads a snapshot back, or None if it isn't one.
+pub fn parse_snapshot(payload: &str) -> Option<Vec<Record>> {
+    let body = payload.strip_prefix(SNAPSHOT_HEADER)?.as_bytes();
+    let mut records = Vec::new();
+    let mut index = 0;
+    while index < body.len() {
+        if body[index] != b'*' {
+            return None;
+        }
+        let mut consumed = 0;
+        let RESP::Array(fields) = bytes_to_resp(&body[index..], &mut consumed).ok()? else {
+            return None;
+        };
+        index += consumed;
+        let mut strings = Vec::with_capacity(fields.len());
+        for field in fields {
+            let RESP::BulkString(s) = field else {
+                return None;
+            };
+            strings.push(String::from_utf8(s).ok()?);
+        }
+        records.push(parse_record(strings)?);
+    }
+    Some(records)
+}
+
+fn parse_record(fields: Vec<String>) -> Option<Record> {
+    let mut fields = fields.into_iter();
+    let kind = fields.next()?;
+    let key = fields.next()?;
+    let expires_at = match fields.next()?.as_str() {
+        "" => None,
+        at => Some(at.parse().ok()?),
+    };
+    let value = match kind.as_str() {
+        "ftindex" => {
+            return Some(Record {
+                key,
+                payload: Payload::Index(fields.collect()),
+                expires_at: None,
+            });
+        }
+        "string" => StorageValue::from(fields.next()?),
+        "integer" => StorageValue::from(fields.next()?.parse::<i64>().ok()?),
+        "bytes" => {
+            StorageValue::Primitive(PrimitiveStorageValue::Bytes(parse_hex(&fields.next()?)?))
+        }
+        "list" => {
+            let mut list = List::new();
+            for element in fields {
+                list.rpush(PrimitiveStorageValue::String(element));
+            }
+            StorageValue::List(list)
+        }
+        "bytelist" => {
+            let mut list = List::new();
+            for element in fields {
+                list.rpush(CommandArg::from(parse_hex(&element)?).into());
+            }
+            StorageValue::List(list)
+        }
+        "stream" => {
+            let stream = parse_stream(&mut fields)?;
+            if fields.next().is_some() {
+                return None;
+            }
+            StorageValue::Stream(stream)
+        }
+        "json" => StorageValue::Json(Document::new(Value::parse(&fields.next()?).ok()?)),
+        "bloom" => StorageValue::Bloom(ScalableBloom::from_bytes(&parse_hex(&fields.next()?)?)?),
+        "cuckoo" => StorageValue::Cuckoo(CuckooFilter::from_bytes(&parse_hex(&fields.next()?)?)?),
+        "cms" => StorageValue::CountMin(CountMinSketch::from_bytes(&parse_hex(&fields.next()?)?)?),
+        "topk" => StorageValue::TopK(TopK::from_bytes(&parse_hex(&fields.next()?)?)?),
+        "timeseries" => {
+            StorageValue::TimeSeries(TimeSeries::from_bytes(&parse_hex(&fields.next()?)?)?)
+        }
+        "vectorset" => {
+            StorageValue::VectorSet(VectorSet::from_bytes(&parse_hex(&fields.next()?)?)?)
+        }
+        "zset" => {
+            let mut set = SortedSet::new();
+            while let Some(member) = fields.next() {
+                set.insert(&member, fields.next()?.parse().ok()?);
+            }
+            StorageValue::SortedSet(set)
+        }
+        _ => return None,
+    };
+    Some(Record {
+        key,
+        payload: Payload::Value(value),
+        expires_at,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{cmd, run};
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["set", "s", "a b\r\nc"]).unwrap();
+        run(&mut storage, &["incr", "n"]).unwrap();
+        run(&mut storage, &["rpush", "l", "x"]).unwrap();
+        run(&mut storage, &["rpush", "l", "y"]).unwrap();
+        let binary = CommandArg::from(vec![0xff, b'\n', 0]);
+        storage
+            .process_command(&[CommandArg::from("rpush"), CommandArg::from("bl"), binary])
+            .unwrap();
+        run(&mut storage, &["setbit", "bits", "0", "1"]).unwrap();
+        run(
+            &mut storage,
+            &["geoadd", "geo", "13.361389", "38.115556", "p"],
+        )
+        .unwrap();
+        run(&mut storage, &["json.set", "doc", "$", r#"{"a":[1,"x"]}"#]).unwrap();
+        run(&mut storage, &["bf.madd", "bf", "a", "b"]).unwrap();
+        run(&mut storage, &["cf.add", "cf", "a"]).unwrap();
+        run(&mut storage, &["cms.initbydim", "cms", "10", "2"]).unwrap();
+        run(&mut storage, &["cms.incrby", "cms", "a", "3"]).unwrap();
+        run(&mut storage, &["topk.reserve", "topk", "2"]).unwrap();
+        run(&mut storage, &["topk.add", "topk", "a"]).unwrap();
+        run(
+            &mut storage,
+            &["ts.add", "ts", "1000", "2.5", "LABELS", "a", "b"],
+        )
+        .unwrap();
+        run(
+            &mut storage,
+            &["vadd", "vset", "VALUES", "2", "1", "0", "e"],
+        )
+        .unwrap();
+        run(&mut storage, &["set", "gone", "v"]).unwrap();
+        storage.expire_at("gone", now_ms() - 1);
+        run(&mut storage, &["set", "ttl", "v"]).unwrap();
+        storage.expire_at("ttl", now_ms() + 60_000);
+        let mut payload = snapshot_header();
+        storage.write_snapshot(&mut payload);
+
+        let mut copy = Storage::new();
+        run(&mut copy, &["set", "stale", "v"]).unwrap();
+        copy.flush();
+        for record in parse_snapshot(&payload).unwrap() {
+            copy.load(record);
+        }
+        assert_eq!(copy.keys_count(), 14);
+        assert_eq!(copy.expires_count(), 1);
+        // The expired key is left behind
+        run(&mut storage, &["get", "gone"]).unwrap();
+        assert_eq!(copy.used_memory(), storage.used_memory());
+        assert_eq!(
+            run(&mut copy, &["get", "s"]).unwrap(),
+            RESP::BulkString("a b\r\nc".into())
+        );
+        assert_eq!(copy.store.get("n").unwrap().value.encoding(), "int");
+        assert_eq!(
+            run(&mut copy, &["bitcount", "bits"]).unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            run(&mut copy, &["geohash", "geo", "p"]).unwrap(),
+            RESP::Array(vec![RESP::BulkString("sqc8b49rny0".into())])
+        );
+        assert_eq!(
+            run(&mut copy, &["json.get", "doc"]).unwrap(),
+            RESP::BulkString(r#"{"a":[1,"x"]}"#.into())
+        );
+        assert_eq!(
+            run(&mut copy, &["bf.mexists", "bf", "a", "b"]).unwrap(),
+            RESP::Array(vec![RESP::Integer(1), RESP::Integer(1)])
+        );
+        assert_eq!(
+            run(&mut copy, &["cf.count", "cf", "a"]).unwrap(),
+            RESP::Integer(1)
+        );
+        assert_eq!(
+            run(&mut copy, &["cms.query", "cms", "a"]).unwrap(),
+            RESP::Array(vec![RESP::Integer(3)])
+        );
+        assert_eq!(
+            run(&mut copy, &["topk.list", "topk"]).unwrap(),
+            RESP::Array(vec![RESP::BulkString("a".into())])
+        );
+        assert_eq!(
+            run(&mut copy, &["ts.queryindex", "a=b"]).unwrap(),
+            RESP::Array(vec![RESP::BulkString("ts".into())])
+        );
+        assert_eq!(
+            run(&mut copy, &["vsim", "vset", "VALUES", "2", "0", "1"]).unwrap(),
+            RESP::Array(vec![RESP::BulkString("e".into())])
+        );
+        assert_eq!(
+            run(&mut copy, &["lpop", "l"]).unwrap(),
+            RESP::BulkString("x".into())
+        );
+        assert_eq!(
+            run(&mut copy, &["lpop", "bl"]).unwrap(),
+            RESP::BulkString(vec![0xff, b'\n', 0])
+        );
+        assert!(parse_snapshot("not a snapshot").is_none());
+        assert!(parse_snapshot(&format!("{}*1\r\n$4\r\nlist\r\n", SNAPSHOT_HEADER)).is_none());
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let mut storage = Storage::new();
+        for id in ["1", "2", "3"] {
+            run(&mut storage, &["xadd", "s", id, "f", id]).unwrap();
+        }
+        run(&mut storage, &["xdel", "s", "3"]).unwrap();
+        run(&mut storage, &["xgroup", "create", "s", "g", "0"]).unwrap();
+        run(
+            &mut storage,
+            &[
+                "xreadgroup",
+                "group",
+                "g",
+                "c",
+                "count",
+                "1",
+                "streams",
+                "s",
+                ">",
+            ],
+        )
+        .unwrap();
+        let mut payload = snapshot_header();
+        storage.write_snapshot(&mut payload);
+
+        let mut copy = Storage::new();
+        for record in parse_snapshot(&payload).unwrap() {
+            copy.load(record);
+        }
+        // Deleted entries are left out rather than reloaded as tombstones
+        assert!(copy.used_memory() < storage.used_memory());
+        for command in [
+            cmd(&["xrange", "s", "-", "+"]),
+            cmd(&["xpending", "s", "g"]),
+            cmd(&["xinfo", "stream", "s"]),
+        ] {
+            assert_eq!(
+                copy.process_command(&command),
+                storage.process_command(&command)
+            );
+        }
+        // The last ID survives even though its entry was deleted
+        assert!(run(&mut copy, &["xadd", "s", "3", "f", "v"]).is_err());
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["rpush", "l", "x"]).unwrap();
+        storage.expire_at("l", now_ms() + 60_000);
+        let RESP::BulkString(payload) = run(&mut storage, &["dump", "l"]).unwrap() else {
+            panic!("DUMP replies with a bulk string");
+        };
+        let payload = String::from_utf8(payload).unwrap();
+        assert_eq!(run(&mut storage, &["dump", "missing"]), Ok(RESP::Null));
+        assert_eq!(
+            run(&mut storage, &["restore", "l", "0", &payload]),
+            Err(StorageError::BusyKey)
+        );
+        run(&mut storage, &["restore", "copy", "0", &payload]).unwrap();
+        assert_eq!(storage.expires_count(), 1);
+        run(&mut storage, &["restore", "l", "5000", &payload, "REPLACE"]).unwrap();
+        assert!(
+            storage
+                .expires
+                .get("l")
+                .is_some_and(|at| *at <= now_ms() + 5000)
+        );
+        assert_eq!(
+            run(&mut storage, &["lpop", "copy"]),
+            Ok(RESP::BulkString("x".into()))
+        );
+        assert_eq!(
+            run(&mut storage, &["restore", "k", "0", "garbage"])
+                .unwrap_err()
+                .to_string(),
+            "ERR DUMP payload version or checksum are wrong"
+        );
+    }
+}
@@ -0,0 +1,1432 @@
+use super::result::{StorageError, StorageResult, parse_integer};
+use super::{Storage, StorageValue, now_ms};
+use crate::command::CommandArg;
+use crate::ds::stream::{ConsumerGroup, NODE_MAX_ENTRIES, Stream, StreamId, Trim};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+/// Entries an approximate trim drops at most when LIMIT isn't given.
+const DEFAULT_TRIM_LIMIT: usize = 100 * NODE_MAX_ENTRIES;
+/// Pending entries XAUTOCLAIM claims when COUNT isn't given.
+const DEFAULT_AUTOCLAIM_COUNT: usize = 100;
+/// XAUTOCLAIM looks at up to this many pending entries per one it may
+/// claim, so a PEL full of busy entries doesn't stall the server.
+const AUTOCLAIM_ATTEMPTS_FACTOR: usize = 10;
+/// Entries and PEL items XINFO STREAM FULL lists when COUNT isn't given.
+const DEFAULT_XINFO_COUNT: usize = 10;
+
+const INVALID_ID: &str = "Invalid stream ID specified as stream command argument";
+const NO_KEY_FOR_XGROUP: &str = "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+
+fn parse_id(arg: &str, missing_seq: u64) -> StorageResult<StreamId> {
+    StreamId::parse(arg, missing_seq).ok_or_else(|| StorageError::syntax(INVALID_ID))
+}
+
+/// The start of an XRANGE-style interval: `-`, an ID, or `(` and an ID
+/// to leave it out.
+fn parse_start(arg: &str) -> StorageResult<StreamId> {
+    match arg {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ => match arg.strip_prefix('(') {
+            Some(id) => parse_id(id, 0)?
+                .next()
+                .ok_or_else(|| StorageError::syntax("invalid start ID for the interval")),
+            None => parse_id(arg, 0),
+        },
+    }
+}
+
+/// The end of an XRANGE-style interval, where a bare time takes in
+/// every sequence.
+fn parse_end(arg: &str) -> StorageResult<StreamId> {
+    match arg {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ => match arg.strip_prefix('(') {
+            Some(id) => parse_id(id, u64::MAX)?
+                .prev()
+                .ok_or_else(|| StorageError::syntax("invalid end ID for the interval")),
+            None => parse_id(arg, u64::MAX),
+        },
+    }
+}
+
+/// A COUNT argument, where anything below 1 means no limit.
+fn parse_count(arg: &str) -> StorageResult<Option<usize>> {
+    let count: i64 = parse_integer(arg)?;
+    Ok((count > 0).then_some(count as usize))
+}
+
+fn id_reply(id: StreamId) -> RESP {
+    RESP::BulkString(id.to_string().into())
+}
+
+fn fields_reply(fields: &[String]) -> RESP {
+    RESP::Array(
+        fields
+            .iter()
+            .cloned()
+            .map(|s| RESP::BulkString(s.into()))
+            .collect(),
+    )
+}
+
+fn entry_reply(id: StreamId, fields: Option<&[String]>) -> RESP {
+    RESP::Array(vec![id_reply(id), fields.map_or(RESP::Null, fields_reply)])
+}
+
+/// Flattens name-value pairs the way RESP2 sends maps.
+fn map_reply(pairs: Vec<(&str, RESP)>) -> RESP {
+    RESP::Array(
+        pairs
+            .into_iter()
+            .flat_map(|(name, value)| [RESP::BulkString(name.into()), value])
+            .collect(),
+    )
+}
+
+fn no_group(key: &str, group: &str) -> StorageError {
+    StorageError::NoGroup(format!(
+        "No such key '{}' or consumer group '{}'",
+        key, group
+    ))
+}
+
+/// MAXLEN/MINID and LIMIT, shared by XADD and XTRIM.
+#[derive(Default)]
+struct TrimOptions {
+    trim: Option<Trim>,
+    approximate: bool,
+    limit: Option<usize>,
+}
+
+impl TrimOptions {
+    /// Reads the option at `command[i]`, returning where the next one
+    /// starts.
+    fn parse(&mut self, command: &[CommandArg], mut i: usize) -> StorageResult<usize> {
+        let option = command[i].to_uppercase();
+        i += 1;
+        if option == "LIMIT" {
+            let limit: i64 = parse_integer(command.get(i).ok_or_else(StorageError::syntax_error)?)?;
+            if limit < 0 {
+                return Err(StorageError::syntax("The LIMIT argument must be >= 0."));
+            }
+            self.limit = Some(limit as usize);
+            return Ok(i + 1);
+        }
+        match command.get(i).map(|arg| arg.as_str()) {
+            Some("~") => {
+                self.approximate = true;
+                i += 1;
+            }
+            Some("=") => i += 1,
+            _ => (),
+        }
+        let threshold = command.get(i).ok_or_else(StorageError::syntax_error)?;
+        self.trim = Some(if option == "MAXLEN" {
+            let maxlen: i64 = parse_integer(threshold)?;
+            if maxlen < 0 {
+                return Err(StorageError::syntax("The MAXLEN argument must be >= 0."));
+            }
+            Trim::MaxLen(maxlen as usize)
+        } else {
+            Trim::MinId(parse_id(threshold, 0)?)
+        });
+        Ok(i + 1)
+    }
+
+    /// Checks the options go together, filling in the default LIMIT.
+    fn validate(&mut self) -> StorageResult<()> {
+        if self.limit.is_some() && !self.approximate {
+            return Err(StorageError::syntax(
+                "syntax error, LIMIT cannot be used without the special ~ option",
+            ));
+        }
+        self.limit = match self.limit {
+            _ if !self.approximate => None,
+            Some(0) => None,
+            Some(limit) => Some(limit),
+            None => Some(DEFAULT_TRIM_LIMIT),
+        };
+        Ok(())
+    }
+
+    fn apply(&self, stream: &mut Stream) -> usize {
+        match self.trim {
+            Some(trim) => stream.trim(trim, self.approximate, self.limit),
+            None => 0,
+        }
+    }
+}
+
+/// The ID argument of XADD.
+enum NewId {
+    /// `*`
+    Auto,
+    /// `ms-*`
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+/// The arguments of XREAD and XREADGROUP, which the server also looks
+/// at to block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamRead {
+    /// Group and consumer, for XREADGROUP
+    pub group: Option<(String, String)>,
+    pub count: Option<usize>,
+    /// Milliseconds to wait for entries, 0 for as long as it takes
+    pub block: Option<u64>,
+    pub noack: bool,
+    /// Keys and the IDs to read after, as given
+    pub streams: Vec<(String, String)>,
+}
+
+impl StreamRead {
+    pub fn parse(command: &[CommandArg]) -> StorageResult<StreamRead> {
+        let name = command[0].to_lowercase();
+        let grouped = name == "xreadgroup";
+        let mut read = StreamRead {
+            group: None,
+            count: None,
+            block: None,
+            noack: false,
+            streams: Vec::new(),
+        };
+        let mut i = 1;
+        let streams = loop {
+            let Some(option) = command.get(i) else {
+                return Err(StorageError::syntax_error());
+            };
+            let value = command.get(i + 1);
+            match option.to_uppercase().as_str() {
+                "COUNT" => {
+                    read.count = parse_count(value.ok_or_else(StorageError::syntax_error)?)?;
+                    i += 2;
+                }
+                "BLOCK" => {
+                    let timeout: i64 = value
+                        .ok_or_else(StorageError::syntax_error)?
+                        .parse()
+                        .map_err(|_| {
+                            StorageError::syntax("timeout is not an integer or out of range")
+                        })?;
+                    if timeout < 0 {
+                        return Err(StorageError::syntax("timeout is negative"));
+                    }
+                    read.block = Some(timeout as u64);
+                    i += 2;
+                }
+                "GROUP" if grouped => {
+                    let consumer = command.get(i + 2).ok_or_else(StorageError::syntax_error)?;
+                    read.group = Some((value.unwrap().to_string(), consumer.to_string()));
+                    i += 3;
+                }
+                "NOACK" if grouped => {
+                    read.noack = true;
+                    i += 1;
+                }
+                "STREAMS" => break &command[i + 1..],
+                _ => return Err(StorageError::syntax_error()),
+            }
+        };
+        if grouped && read.group.is_none() {
+            return Err(StorageError::syntax("Missing GROUP option for XREADGROUP"));
+        }
+        if streams.is_empty() || !streams.len().is_multiple_of(2) {
+            let message = if grouped {
+                "Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified."
+            } else {
+                "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."
+            };
+            return Err(StorageError::syntax(message));
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        for id in ids {
+            match id.as_str() {
+                ">" if !grouped => {
+                    return Err(StorageError::syntax(
+                        "The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.",
+                    ));
+                }
+                "$" if grouped => {
+                    return Err(StorageError::syntax(
+                        "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.",
+                    ));
+                }
+                ">" | "$" | "+" => (),
+                id => {
+                    parse_id(id, 0)?;
+                }
+            }
+        }
+        read.streams = keys
+            .iter()
+            .map(|key| key.to_string())
+            .zip(ids.iter().map(|id| id.to_string()))
+            .collect();
+        Ok(read)
+    }
+
+    /// The request for `streams`, without BLOCK, which is how a read that
+    /// may block is run against the keyspace and sent to replicas.
+    pub fn request(&self, streams: &[(String, String)]) -> Vec<CommandArg> {
+        let mut args = Vec::new();
+        if let Some((group, consumer)) = &self.group {
+            args.extend(["XREADGROUP", "GROUP", group, consumer].map(CommandArg::from));
+        } else {
+            args.push("XREAD".into());
+        }
+        if let Some(count) = self.count {
+            args.extend(["COUNT".into(), count.to_string().into()]);
+        }
+        if self.noack {
+            args.push("NOACK".into());
+        }
+        args.push("STREAMS".into());
+        args.extend(streams.iter().map(|(key, _)| key.as_str().into()));
+        args.extend(streams.iter().map(|(_, id)| id.as_str().into()));
+        args
+    }
+}
+
+impl Storage {
+    /// The stream at `key` if there is one, counted as a read.
+    fn read_stream(&mut self, key: &str) -> StorageResult<Option<&Stream>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the stream at `key`, creating an empty one first if
+    /// `create` is set, and keeps `used_memory` in step with what it
+    /// changes. None if there is no stream to run on.
+    fn write_stream<T>(
+        &mut self,
+        key: &str,
+        create: bool,
+        f: impl FnOnce(&mut Stream) -> StorageResult<T>,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            if !create {
+                return Ok(None);
+            }
+            self.insert(key.to_string(), StorageValue::Stream(Stream::new()));
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::Stream(stream) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = stream.memory_usage();
+        let result = f(stream);
+        self.used_memory = (self.used_memory + stream.memory_usage()).saturating_sub(before);
+        result.map(Some)
+    }
+
+    /// The last ID added to the stream at `key`, which is what `$` reads
+    /// after.
+    pub(super) fn last_stream_id(&mut self, key: &str) -> StorageResult<Option<StreamId>> {
+        self.expire_if_needed(key);
+        match self.store.get(key).map(|entry| &entry.value) {
+            Some(StorageValue::Stream(stream)) => Ok(Some(stream.last_id())),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]]
+    /// *|id field value [field value ...]
+    pub(super) fn command_xadd(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let key = &command[1];
+        let mut nomkstream = false;
+        let mut trim = TrimOptions::default();
+        let mut i = 2;
+        while i < command.len() {
+            match command[i].to_uppercase().as_str() {
+                "NOMKSTREAM" => {
+                    nomkstream = true;
+                    i += 1;
+                }
+                "MAXLEN" | "MINID" | "LIMIT" => i = trim.parse(command, i)?,
+                _ => break,
+            }
+        }
+        trim.validate()?;
+        let fields = command.get(i + 1..).unwrap_or_default();
+        if fields.is_empty() || !fields.len().is_multiple_of(2) {
+            return Err(StorageError::WrongArity("xadd".to_string()));
+        }
+        let new_id = match command[i].as_str() {
+            "*" => NewId::Auto,
+            arg => match arg.strip_suffix("-*") {
+                Some(ms) => {
+                    NewId::AutoSeq(ms.parse().map_err(|_| StorageError::syntax(INVALID_ID))?)
+                }
+                None => NewId::Explicit(parse_id(arg, 0)?),
+            },
+        };
+        if matches!(new_id, NewId::Explicit(StreamId::MIN)) {
+            return Err(StorageError::syntax(
+                "The ID specified in XADD must be greater than 0-0",
+            ));
+        }
+        if nomkstream && self.lookup(key).is_none() {
+            return Ok(RESP::Null);
+        }
+        let added = self.write_stream(key, true, |stream| {
+            let id = match new_id {
+                NewId::Auto => stream.auto_id(now_ms()).ok_or_else(|| {
+                    StorageError::syntax(
+                        "The stream has exhausted the last possible ID, unable to add more items",
+                    )
+                })?,
+                NewId::AutoSeq(ms) => stream.auto_seq_id(ms).unwrap_or(StreamId::MIN),
+                NewId::Explicit(id) => id,
+            };
+            if id <= stream.last_id() {
+                return Err(StorageError::syntax(
+                    "The ID specified in XADD is equal or smaller than the target stream top item",
+                ));
+            }
+            stream.add(id, fields.iter().map(|field| field.to_string()).collect());
+            Ok((id, trim.apply(stream)))
+        })?;
+        let (id, trimmed) = added.expect("XADD creates the stream");
+        self.notify(notify::STREAM, "xadd", key);
+        if trimmed > 0 {
+            self.notify(notify::STREAM, "xtrim", key);
+        }
+        // Replicas must add the entry under the same ID
+        let mut rewritten = command.to_vec();
+        rewritten[i] = id.to_string().into();
+        self.propagate_as = Some(rewritten);
+        Ok(id_reply(id))
+    }
+
+    pub(super) fn command_xlen(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let len = self.read_stream(&command[1])?.map_or(0, Stream::len);
+        Ok(RESP::Integer(len as i64))
+    }
+
+    /// XRANGE key start end [COUNT count], and XREVRANGE with the bounds
+    /// swapped.
+    pub(super) fn command_xrange(
+        &mut self,
+        command: &[CommandArg],
+        rev: bool,
+    ) -> StorageResult<RESP> {
+        let (start, end) = if rev {
+            (parse_start(&command[3])?, parse_end(&command[2])?)
+        } else {
+            (parse_start(&command[2])?, parse_end(&command[3])?)
+        };
+        let count = match &command[4..] {
+            [] => None,
+            [option, count] if option.eq_ignore_ascii_case("COUNT") => {
+                let count: i64 = parse_integer(count)?;
+                Some(count.max(0) as usize)
+            }
+            _ => return Err(StorageError::syntax_error()),
+        };
+        let Some(stream) = self.read_stream(&command[1])? else {
+            return Ok(RESP::Array(Vec::new()));
+        };
+        let count = count.unwrap_or(usize::MAX);
+        let entries = stream.range(start, end);
+        let entries: Vec<RESP> = if rev {
+            entries
+                .rev()
+                .take(count)
+                .map(|(id, fields)| entry_reply(id, Some(fields)))
+                .collect()
+        } else {
+            entries
+                .take(count)
+                .map(|(id, fields)| entry_reply(id, Some(fields)))
+                .collect()
+        };
+        Ok(RESP::Array(entries))
+    }
+
+    pub(super) fn command_xdel(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let ids = command[2..]
+            .iter()
+            .map(|arg| parse_id(arg, 0))
+            .collect::<StorageResult<Vec<_>>>()?;
+        let key = &command[1];
+        let deleted = self
+            .write_stream(key, false, |stream| {
+                Ok(ids.iter().filter(|id| stream.delete(**id)).count())
+            })?
+            .unwrap_or(0);
+        if deleted > 0 {
+            self.notify(notify::STREAM, "xdel", key);
+        }
+        Ok(RESP::Integer(deleted as i64))
+    }
+
+    /// XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]
+    pub(super) fn command_xtrim(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let mut trim = TrimOptions::default();
+        let mut i = 2;
+        while i < command.len() {
+            match command[i].to_uppercase().as_str() {
+                "MAXLEN" | "MINID" | "LIMIT" => i = trim.parse(command, i)?,
+                _ => return Err(StorageError::syntax_error()),
+            }
+        }
+        if trim.trim.is_none() {
+            return Err(StorageError::syntax_error());
+        }
+        trim.validate()?;
+        let key = &command[1];
+        let trimmed = self
+            .write_stream(key, false, |stream| Ok(trim.apply(stream)))?
+            .unwrap_or(0);
+        if trimmed > 0 {
+            self.notify(notify::STREAM, "xtrim", key);
+        }
+        Ok(RESP::Integer(trimmed as i64))
+    }
+
+    /// XREAD and XREADGROUP, without blocking: the server waits for
+    /// entries itself and comes back.
+    pub(super) fn command_xread(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let read = StreamRead::parse(command)?;
+        let mut replies = Vec::new();
+        for (key, id) in &read.streams {
+            let entries = match &read.group {
+                Some((group, consumer)) => {
+                    self.xreadgroup_stream(key, group, consumer, id, &read)?
+                }
+                None => self.xread_stream(key, id, read.count)?,
+            };
+            if let Some(entries) = entries {
+                replies.push(RESP::Array(vec![
+                    RESP::BulkString(key.clone().into()),
+                    RESP::Array(entries),
+                ]));
+            }
+        }
+        if read.group.is_some() && read.block.is_some() {
+            self.propagate_as = Some(read.request(&read.streams));
+        }
+        if replies.is_empty() {
+            Ok(RESP::NullArray)
+        } else {
+            Ok(RESP::Array(replies))
+        }
+    }
+
+    /// Entries of one stream for XREAD, None if there are none.
+    fn xread_stream(
+        &mut self,
+        key: &str,
+        id: &str,
+        count: Option<usize>,
+    ) -> StorageResult<Option<Vec<RESP>>> {
+        let Some(stream) = self.read_stream(key)? else {
+            return Ok(None);
+        };
+        let entries: Vec<RESP> = match id {
+            // Only entries added from now on, which never exist yet
+            "$" => Vec::new(),
+            "+" => stream
+                .last()
+                .map(|(id, fields)| entry_reply(id, Some(fields)))
+                .into_iter()
+                .collect(),
+            id => match parse_id(id, 0)?.next() {
+                Some(start) => stream
+                    .range(start, StreamId::MAX)
+                    .take(count.unwrap_or(usize::MAX))
+                    .map(|(id, fields)| entry_reply(id, Some(fields)))
+                    .collect(),
+                None => Vec::new(),
+            },
+        };
+        Ok((!entries.is_empty()).then_some(entries))
+    }
+
+    /// Entries of one stream for XREADGROUP: new ones for `>`, the
+    /// consumer's pending ones after `id` otherwise.
+    fn xreadgroup_stream(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        id: &str,
+        read: &StreamRead,
+    ) -> StorageResult<Option<Vec<RESP>>> {
+        let missing = || {
+            StorageError::NoGroup(format!(
+                "No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                key, group
+            ))
+        };
+        let history = StreamId::parse(id, 0);
+        let count = read.count.unwrap_or(usize::MAX);
+        let now = now_ms();
+        let (created, entries) = self
+            .write_stream(key, false, |stream| {
+                let last_id = stream.group(group).ok_or_else(missing)?.last_id;
+                let Some(after) = history else {
+                    let delivered: Vec<(StreamId, Vec<String>)> = last_id
+                        .next()
+                        .map(|start| {
+                            stream
+                                .range(start, StreamId::MAX)
+                                .take(count)
+                                .map(|(id, fields)| (id, fields.to_vec()))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let entries_read = match delivered.last() {
+                        Some((id, _)) => {
+                            let previous = stream.group(group).and_then(|g| g.entries_read);
+                            stream.entries_read_at(*id, previous, last_id)
+                        }
+                        None => None,
+                    };
+                    let cg = stream.group_mut(group).expect("the group was just found");
+                    let created = cg.seen(consumer, now);
+                    if let Some((id, _)) = delivered.last() {
+                        cg.last_id = *id;
+                        cg.entries_read = entries_read;
+                    }
+                    if !read.noack {
+                        for (id, _) in &delivered {
+                            cg.deliver(*id, consumer, now);
+                        }
+                    }
+                    let entries: Vec<RESP> = delivered
+                        .iter()
+                        .map(|(id, fields)| entry_reply(*id, Some(fields)))
+                        .collect();
+                    return Ok((created, (!entries.is_empty()).then_some(entries)));
+                };
+                let cg = stream.group_mut(group).expect("the group was just found");
+                let created = cg.seen(consumer, now);
+                let ids: Vec<StreamId> = cg
+                    .consumer(consumer)
+                    .map(|c| c.pending_after(after).take(count).collect())
+                    .unwrap_or_default();
+                let entries = ids
+                    .into_iter()
+                    .map(|id| entry_reply(id, stream.get(id)))
+                    .collect();
+                Ok((created, Some(entries)))
+            })?
+            .ok_or_else(missing)?;
+        if created {
+            self.notify(notify::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(entries)
+    }
+
+    pub(super) fn command_xgroup(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let subcommand = command[1].to_lowercase();
+        if subcommand == "help" {
+            return Ok(RESP::Array(
+                [
+                    "XGROUP <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                    "CREATE <key> <groupname> <id|$> [option]",
+                    "    Create a new consumer group. Options are:",
+                    "    * MKSTREAM",
+                    "      Create the empty stream if it does not exist.",
+                    "    * ENTRIESREAD entries_read",
+                    "      Set the group's entries_read counter (internal use).",
+                    "CREATECONSUMER <key> <groupname> <consumer>",
+                    "    Create a new consumer in the specified group.",
+                    "DELCONSUMER <key> <groupname> <consumer>",
+                    "    Remove the specified consumer.",
+                    "DESTROY <key> <groupname>",
+                    "    Remove the specified group.",
+                    "SETID <key> <groupname> <id|$> [ENTRIESREAD entries_read]",
+                    "    Set the current group ID and entries_read counter.",
+                    "HELP",
+                    "    Print this help.",
+                ]
+                .iter()
+                .map(|line| RESP::SimpleString(line.to_string()))
+                .collect(),
+            ));
+        }
+        let key = &command[2];
+        let group = &command[3];
+        let mut mkstream = false;
+        let mut entries_read = None;
+        if matches!(subcommand.as_str(), "create" | "setid") {
+            let mut i = 5;
+            while i < command.len() {
+                match command[i].to_uppercase().as_str() {
+                    "MKSTREAM" if subcommand == "create" => mkstream = true,
+                    "ENTRIESREAD" => {
+                        i += 1;
+                        let value: i64 =
+                            parse_integer(command.get(i).ok_or_else(StorageError::syntax_error)?)?;
+                        if value < -1 {
+                            return Err(StorageError::syntax(
+                                "value for ENTRIESREAD must be positive or -1",
+                            ));
+                        }
+                        entries_read = u64::try_from(value).ok();
+                    }
+                    _ => return Err(StorageError::syntax_error()),
+                }
+                i += 1;
+            }
+        }
+        let target = match command.get(4).map(|arg| arg.as_str()) {
+            Some("$") | None => None,
+            Some(id) if matches!(subcommand.as_str(), "create" | "setid") => Some(parse_id(id, 0)?),
+            Some(_) => None,
+        };
+        let now = now_ms();
+        let reply = self.write_stream(key, mkstream, |stream| {
+            let missing = || {
+                StorageError::NoGroup(format!(
+                    "No such consumer group '{}' for key name '{}'",
+                    group, key
+                ))
+            };
+            let position = |stream: &Stream| {
+                let id = target.unwrap_or(stream.last_id());
+                let read = entries_read.or_else(|| stream.entries_read_at(id, None, id));
+                (id, read)
+            };
+            match subcommand.as_str() {
+                "create" => {
+                    let (id, read) = position(stream);
+                    if !stream.create_group(group, ConsumerGroup::new(id, read)) {
+                        return Err(StorageError::BusyGroup);
+                    }
+                    Ok((RESP::SimpleString("OK".to_string()), Some("xgroup-create")))
+                }
+                "setid" => {
+                    let (id, read) = position(stream);
+                    let cg = stream.group_mut(group).ok_or_else(missing)?;
+                    cg.last_id = id;
+                    cg.entries_read = read;
+                    Ok((RESP::SimpleString("OK".to_string()), Some("xgroup-setid")))
+                }
+                "destroy" => {
+                    let destroyed = stream.destroy_group(group);
+                    let event = destroyed.then_some("xgroup-destroy");
+                    Ok((RESP::Integer(destroyed as i64), event))
+                }
+                "createconsumer" => {
+                    let cg = stream.group_mut(group).ok_or_else(missing)?;
+                    let created = cg.create_consumer(&command[4], now);
+                    let event = created.then_some("xgroup-createconsumer");
+                    Ok((RESP::Integer(created as i64), event))
+                }
+                _ => {
+                    let cg = stream.group_mut(group).ok_or_else(missing)?;
+                    let deleted = cg.delete_consumer(&command[4]);
+                    let event = deleted.map(|_| "xgroup-delconsumer");
+                    Ok((RESP::Integer(deleted.unwrap_or(0) as i64), event))
+                }
+            }
+        })?;
+        let (reply, event) = reply.ok_or_else(|| StorageError::syntax(NO_KEY_FOR_XGROUP))?;
+        if let Some(event) = event {
+            self.notify(notify::STREAM, event, key);
+        }
+        Ok(reply)
+    }
+
+    pub(super) fn command_xack(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let ids = command[3..]
+            .iter()
+            .map(|arg| parse_id(arg, 0))
+            .collect::<StorageResult<Vec<_>>>()?;
+        let group = &command[2];
+        let acked = self.write_stream(&command[1], false, |stream| {
+            Ok(match stream.group_mut(group) {
+                Some(cg) => ids.iter().filter(|id| cg.ack(**id)).count(),
+                None => 0,
+            })
+        })?;
+        Ok(RESP::Integer(acked.unwrap_or(0) as i64))
+    }
+
+    /// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+    pub(super) fn command_xpending(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let (key, group) = (&command[1], &command[2]);
+        let mut rest = &command[3..];
+        let mut min_idle = None;
+        if rest
+            .first()
+            .is_some_and(|arg| arg.eq_ignore_ascii_case("IDLE"))
+        {
+            let idle: i64 = parse_integer(rest.get(1).ok_or_else(StorageError::syntax_error)?)?;
+            min_idle = Some(idle.max(0) as u64);
+            rest = &rest[2..];
+        }
+        let extended = match rest {
+            [] if min_idle.is_none() => None,
+            [start, end, count, consumer @ ..] if consumer.len() <= 1 => {
+                let count: i64 = parse_integer(count)?;
+                Some((
+                    parse_start(start)?,
+                    parse_end(end)?,
+                    count.max(0) as usize,
+                    consumer.first(),
+                ))
+            }
+            _ => return Err(StorageError::syntax_error()),
+        };
+        let Some(stream) = self.read_stream(key)? else {
+            return Err(no_group(key, group));
+        };
+        let cg = stream.group(group).ok_or_else(|| no_group(key, group))?;
+        let Some((start, end, count, consumer)) = extended else {
+            let mut pending = cg.pending_range(StreamId::MIN, StreamId::MAX);
+            let Some((first, _)) = pending.next() else {
+                return Ok(RESP::Array(vec![
+                    RESP::Integer(0),
+                    RESP::Null,
+                    RESP::Null,
+                    RESP::Null,
+                ]));
+            };
+            let last = pending.last().map_or(first, |(id, _)| id);
+            let consumers = cg
+                .consumers()
+                .filter(|(_, c)| c.pending() > 0)
+                .map(|(name, c)| {
+                    RESP::Array(vec![
+                        RESP::BulkString(name.into()),
+                        RESP::BulkString(c.pending().to_string().into()),
+                    ])
+                })
+                .collect();
+            return Ok(RESP::Array(vec![
+                RESP::Integer(cg.pending_len() as i64),
+                id_reply(first),
+                id_reply(last),
+                RESP::Array(consumers),
+            ]));
+        };
+        let now = now_ms();
+        let entries = cg
+            .pending_range(start, end)
+            .filter(|(_, entry)| consumer.is_none_or(|c| *c == entry.consumer))
+            .map(|(id, entry)| (id, entry, now.saturating_sub(entry.delivery_time)))
+            .filter(|(_, _, idle)| min_idle.is_none_or(|min| *idle >= min))
+            .take(count)
+            .map(|(id, entry, idle)| {
+                RESP::Array(vec![
+                    id_reply(id),
+                    RESP::BulkString(entry.consumer.clone().into()),
+                    RESP::Integer(idle as i64),
+                    RESP::Integer(entry.delivery_count as i64),
+                ])
+            })
+            .collect();
+        Ok(RESP::Array(entries))
+    }
+
+    /// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms]
+    /// [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]
+    /// [LASTID lastid]
+    pub(super) fn command_xclaim(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let (key, group, consumer) = (&command[1], &command[2], &command[3]);
+        let min_idle: i64 = parse_integer(&command[4])?;
+        let min_idle = min_idle.max(0) as u64;
+        let mut ids = Vec::new();
+        let mut i = 5;
+        while let Some(id) = command.get(i).and_then(|arg| StreamId::parse(arg, 0)) {
+            ids.push(id);
+            i += 1;
+        }
+        if ids.is_empty() {
+            return Err(StorageError::syntax(INVALID_ID));
+        }
+        let now = now_ms();
+        let (mut delivery_time, mut retry_count) = (now, None);
+        let (mut force, mut justid, mut last_id) = (false, false, None);
+        while i < command.len() {
+            let option = command[i].to_uppercase();
+            let value = command.get(i + 1);
+            let integer = || -> StorageResult<u64> {
+                let value: i64 = parse_integer(value.ok_or_else(StorageError::syntax_error)?)?;
+                Ok(value.max(0) as u64)
+            };
+            match option.as_str() {
+                "IDLE" => delivery_time = now.saturating_sub(integer()?),
+                "TIME" => delivery_time = integer()?,
+                "RETRYCOUNT" => retry_count = Some(integer()?),
+                "LASTID" => {
+                    let value = value.ok_or_else(StorageError::syntax_error)?;
+                    last_id = Some(parse_id(value, 0)?);
+                }
+                "FORCE" => force = true,
+                "JUSTID" => justid = true,
+                _ => {
+                    return Err(StorageError::syntax(&format!(
+                        "Unrecognized XCLAIM option '{}'",
+                        command[i]
+                    )));
+                }
+            }
+            // Options with a value skip over it
+            i += if matches!(option.as_str(), "FORCE" | "JUSTID") {
+                1
+            } else {
+                2
+            };
+        }
+        let claimed = self.write_stream(key, false, |stream| {
+            let exists: Vec<bool> = ids.iter().map(|id| stream.get(*id).is_some()).collect();
+            let cg = stream
+                .group_mut(group)
+                .ok_or_else(|| no_group(key, group))?;
+            if let Some(last_id) = last_id
+                && last_id > cg.last_id
+            {
+                cg.last_id = last_id;
+            }
+            let created = cg.seen(consumer, now);
+            let mut claimed = Vec::new();
+            for (id, exists) in ids.iter().zip(exists) {
+                let previous = cg
+                    .pending(*id)
+                    .map(|entry| (entry.delivery_time, entry.delivery_count));
+                let count = match previous {
+                    // Deleted from the stream since it was delivered
+                    Some(_) if !exists => {
+                        cg.ack(*id);
+                        continue;
+                    }
+                    Some((time, _)) if min_idle > 0 && now.saturating_sub(time) < min_idle => {
+                        continue;
+                    }
+                    Some((_, count)) => count,
+                    None if force && exists => 0,
+                    None => continue,
+                };
+                let count = retry_count.unwrap_or(count + u64::from(!justid));
+                cg.claim(*id, consumer, delivery_time, count);
+                claimed.push(*id);
+            }
+            let replies = claimed
+                .into_iter()
+                .map(|id| {
+                    if justid {
+                        id_reply(id)
+                    } else {
+                        entry_reply(id, stream.get(id))
+                    }
+                })
+                .collect();
+            Ok((created, RESP::Array(replies)))
+        })?;
+        let (created, reply) = claimed.ok_or_else(|| no_group(key, group))?;
+        if created {
+            self.notify(notify::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(reply)
+    }
+
+    /// XAUTOCLAIM key group consumer min-idle-time start [COUNT count]
+    /// [JUSTID]
+    pub(super) fn command_xautoclaim(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let (key, group, consumer) = (&command[1], &command[2], &command[3]);
+        let min_idle: i64 = parse_integer(&command[4])?;
+        let min_idle = min_idle.max(0) as u64;
+        let start = parse_start(&command[5])?;
+        let (mut count, mut justid) = (DEFAULT_AUTOCLAIM_COUNT, false);
+        let mut i = 6;
+        while i < command.len() {
+            match command[i].to_uppercase().as_str() {
+                "COUNT" => {
+                    let value: i64 =
+                        parse_integer(command.get(i + 1).ok_or_else(StorageError::syntax_error)?)?;
+                    if value < 1 || value as usize > usize::MAX / AUTOCLAIM_ATTEMPTS_FACTOR {
+                        return Err(StorageError::syntax("COUNT must be > 0"));
+                    }
+                    count = value as usize;
+                    i += 1;
+                }
+                "JUSTID" => justid = true,
+                _ => return Err(StorageError::syntax_error()),
+            }
+            i += 1;
+        }
+        let now = now_ms();
+        let claimed = self.write_stream(key, false, |stream| {
+            let cg = stream.group(group).ok_or_else(|| no_group(key, group))?;
+            let mut candidates: Vec<(StreamId, u64, u64)> = cg
+                .pending_range(start, StreamId::MAX)
+                .take(count * AUTOCLAIM_ATTEMPTS_FACTOR + 1)
+                .map(|(id, entry)| (id, entry.delivery_time, entry.delivery_count))
+                .collect();
+            let next = if candidates.len() > count * AUTOCLAIM_ATTEMPTS_FACTOR {
+                candidates.pop().map(|(id, _, _)| id)
+            } else {
+                None
+            };
+            let exists: Vec<bool> = candidates
+                .iter()
+                .map(|(id, _, _)| stream.get(*id).is_some())
+                .collect();
+            let cg = stream.group_mut(group).expect("the group was just found");
+            let created = cg.seen(consumer, now);
+            let (mut claimed, mut deleted) = (Vec::new(), Vec::new());
+            let mut cursor = next;
+            for ((id, time, delivery_count), exists) in candidates.into_iter().zip(exists) {
+                if claimed.len() == count {
+                    cursor = Some(id);
+                    break;
+                }
+                if !exists {
+                    cg.ack(id);
+                    deleted.push(id);
+                    continue;
+                }
+                if min_idle > 0 && now.saturating_sub(time) < min_idle {
+                    continue;
+                }
+                cg.claim(id, consumer, now, delivery_count + u64::from(!justid));
+                claimed.push(id);
+            }
+            let claimed = claimed
+                .into_iter()
+                .map(|id| {
+                    if justid {
+                        id_reply(id)
+                    } else {
+                        entry_reply(id, stream.get(id))
+                    }
+                })
+                .collect();
+            let reply = RESP::Array(vec![
+                id_reply(cursor.unwrap_or(StreamId::MIN)),
+                RESP::Array(claimed),
+                RESP::Array(deleted.into_iter().map(id_reply).collect()),
+            ]);
+            Ok((created, reply))
+        })?;
+        let (created, reply) = claimed.ok_or_else(|| no_group(key, group))?;
+        if created {
+            self.notify(notify::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(reply)
+    }
+
+    pub(super) fn command_xinfo(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let subcommand = command[1].to_lowercase();
+        if subcommand == "help" {
+            return Ok(RESP::Array(
+                [
+                    "XINFO <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                    "CONSUMERS <key> <groupname>",
+                    "    Show consumers of <groupname>.",
+                    "GROUPS <key>",
+                    "    Show the stream consumer groups.",
+                    "STREAM <key> [FULL [COUNT <count>]",
+                    "    Show information about the stream.",
+                    "HELP",
+                    "    Print this help.",
+                ]
+                .iter()
+                .map(|line| RESP::SimpleString(line.to_string()))
+                .collect(),
+            ));
+        }
+        let full = match (subcommand.as_str(), &command[3..]) {
+            ("stream", []) => None,
+            ("stream", [option]) if option.eq_ignore_ascii_case("FULL") => {
+                Some(DEFAULT_XINFO_COUNT)
+            }
+            ("stream", [option, count_option, count])
+                if option.eq_ignore_ascii_case("FULL")
+                    && count_option.eq_ignore_ascii_case("COUNT") =>
+            {
+                Some(parse_count(count)?.unwrap_or(usize::MAX))
+            }
+            ("stream", _) => return Err(StorageError::syntax_error()),
+            _ => None,
+        };
+        let key = &command[2];
+        let stream = self.read_stream(key)?.ok_or(StorageError::KeyNotFound)?;
+        let now = now_ms();
+        match subcommand.as_str() {
+            "groups" => Ok(RESP::Array(
+                stream
+                    .groups()
+                    .map(|(name, group)| group_info(stream, name, group))
+                    .collect(),
+            )),
+            "consumers" => {
+                let group = stream.group(&command[3]).ok_or_else(|| {
+                    StorageError::NoGroup(format!(
+                        "No such consumer group '{}' for key name '{}'",
+                        command[3], key
+                    ))
+                })?;
+                Ok(RESP::Array(
+                    group
+                        .consumers()
+                        .map(|(name, consumer)| {
+                            let inactive = consumer
+                                .active_time
+                                .map_or(-1, |at| now.saturating_sub(at) as i64);
+                            map_reply(vec![
+                                ("name", RESP::BulkString(name.into())),
+                                ("pending", RESP::Integer(consumer.pending() as i64)),
+                                (
+                                    "idle",
+                                    RESP::Integer(now.saturating_sub(consumer.seen_time) as i64),
+                                ),
+                                ("inactive", RESP::Integer(inactive)),
+                            ])
+                        })
+                        .collect(),
+                ))
+            }
+            _ => Ok(stream_info(stream, full)),
+        }
+    }
+}
+
+/// An XINFO GROUPS element.
+fn group_info(stream: &Stream, name: &str, group: &ConsumerGroup) -> RESP {
+    map_reply(vec![
+        ("name", RESP::BulkString(name.into())),
+        ("consumers", RESP::Integer(group.consumers().count() as i64)),
+        ("pending", RESP::Integer(group.pending_len() as i64)),
+        ("last-delivered-id", id_reply(group.last_id)),
+        (
+            "entries-read",
+            group
+                .entries_read
+                .map_or(RESP::Null, |n| RESP::Integer(n as i64)),
+        ),
+        (
+            "lag",
+            stream
+                .lag(group)
+                .map_or(RESP::Null, |n| RESP::Integer(n as i64)),
+        ),
+    ])
+}
+
+/// The XINFO STREAM reply, with FULL's details up to `full` entries.
+fn stream_info(stream: &Stream, full: Option<usize>) -> RESP {
+    let first = stream.first();
+    let mut info = vec![
+        ("length", RESP::Integer(stream.len() as i64)),
+        ("radix-tree-keys", RESP::Integer(stream.nodes() as i64)),
+        ("radix-tree-nodes", RESP::Integer(stream.nodes() as i64 + 1)),
+        ("last-generated-id", id_reply(stream.last_id())),
+        ("max-deleted-entry-id", id_reply(stream.max_deleted_id())),
+        (
+            "entries-added",
+            RESP::Integer(stream.entries_added() as i64),
+        ),
+        (
+            "recorded-first-entry-id",
+            id_reply(first.map_or(StreamId::MIN, |(id, _)| id)),
+        ),
+    ];
+    let Some(count) = full else {
+        let entry = |entry: Option<(StreamId, &[String])>| {
+            entry.map_or(RESP::Null, |(id, fields)| entry_reply(id, Some(fields)))
+        };
+        info.push(("groups", RESP::Integer(stream.groups().count() as i64)));
+        info.push(("first-entry", entry(first)));
+        info.push(("last-entry", entry(stream.last())));
+        return map_reply(info);
+    };
+    let entries = stream
+        .range(StreamId::MIN, StreamId::MAX)
+        .take(count)
+        .map(|(id, fields)| entry_reply(id, Some(fields)))
+        .collect();
+    info.push(("entries", RESP::Array(entries)));
+    let groups = stream
+        .groups()
+        .map(|(name, group)| {
+            let pel = group
+                .pending_range(StreamId::MIN, StreamId::MAX)
+                .take(count)
+                .map(|(id, entry)| {
+                    RESP::Array(vec![
+                        id_reply(id),
+                        RESP::BulkString(entry.consumer.clone().into()),
+                        RESP::Integer(entry.delivery_time as i64),
+                        RESP::Integer(entry.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            let consumers = group
+                .consumers()
+                .map(|(consumer_name, consumer)| {
+                    let pel = consumer
+                        .pending_after(StreamId::MIN)
+                        .take(count)
+                        .filter_map(|id| {
+                            let entry = group.pending(id)?;
+                            Some(RESP::Array(vec![
+                                id_reply(id),
+                                RESP::Integer(entry.delivery_time as i64),
+                                RESP::Integer(entry.delivery_count as i64),
+                            ]))
+                        })
+                        .collect();
+                    map_reply(vec![
+                        ("name", RESP::BulkString(consumer_name.into())),
+                        ("seen-time", RESP::Integer(consumer.seen_time as i64)),
+                        (
+                            "active-time",
+                            RESP::Integer(consumer.active_time.map_or(-1, |at| at as i64)),
+                        ),
+                        ("pel-count", RESP::Integer(consumer.pending() as i64)),
+                        ("pending", RESP::Array(pel)),
+                    ])
+                })
+                .collect();
+            map_reply(vec![
+                ("name", RESP::BulkString(name.into())),
+                ("last-delivered-id", id_reply(group.last_id)),
+                (
+                    "entries-read",
+                    group
+                        .entries_read
+                        .map_or(RESP::Null, |n| RESP::Integer(n as i64)),
+                ),
+                (
+                    "lag",
+                    stream
+                        .lag(group)
+                        .map_or(RESP::Null, |n| RESP::Integer(n as i64)),
+                ),
+                ("pel-count", RESP::Integer(group.pending_len() as i64)),
+                ("pending", RESP::Array(pel)),
+                ("consumers", RESP::Array(consumers)),
+            ])
+        })
+        .collect();
+    info.push(("groups", RESP::Array(groups)));
+    map_reply(info)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::replication::Feed;
+    use crate::test_support::{bulk, cmd, run};
+
+    fn entry(id: &str, fields: &[&str]) -> RESP {
+        RESP::Array(vec![
+            bulk(id),
+            RESP::Array(fields.iter().map(|f| bulk(f)).collect()),
+        ])
+    }
+
+    #[test]
+    fn test_xadd_and_ranges() {
+        let mut storage = Storage::new();
+        let output = run(&mut storage, &["xadd", "s", "1-1", "a", "1"]);
+        assert_eq!(output, Ok(bulk("1-1")));
+        let output = run(&mut storage, &["xadd", "s", "1-*", "b", "2"]);
+        assert_eq!(output, Ok(bulk("1-2")));
+        let output = run(&mut storage, &["xadd", "s", "5", "c", "3"]);
+        assert_eq!(output, Ok(bulk("5-0")));
+        assert!(run(&mut storage, &["xadd", "s", "1-1", "d", "4"]).is_err());
+        assert_eq!(
+            run(&mut storage, &["xadd", "s", "*", "odd"]),
+            Err(StorageError::WrongArity("xadd".to_string()))
+        );
+        assert_eq!(run(&mut storage, &["xlen", "s"]), Ok(RESP::Integer(3)));
+
+        let output = run(&mut storage, &["xrange", "s", "-", "+", "COUNT", "2"]);
+        assert_eq!(
+            output,
+            Ok(RESP::Array(vec![
+                entry("1-1", &["a", "1"]),
+                entry("1-2", &["b", "2"])
+            ]))
+        );
+        let output = run(&mut storage, &["xrevrange", "s", "+", "(1-2"]);
+        assert_eq!(output, Ok(RESP::Array(vec![entry("5-0", &["c", "3"])])));
+
+        assert_eq!(
+            run(&mut storage, &["xdel", "s", "1-2", "9-9"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["xtrim", "s", "MAXLEN", "1"]),
+            Ok(RESP::Integer(1))
+        );
+        let output = run(&mut storage, &["xread", "streams", "s", "0"]);
+        assert_eq!(
+            output,
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("s"),
+                RESP::Array(vec![entry("5-0", &["c", "3"])])
+            ])]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xread", "streams", "s", "5"]),
+            Ok(RESP::NullArray)
+        );
+
+        run(&mut storage, &["del", "s"]).unwrap();
+        assert_eq!(storage.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_xadd_propagates_the_id_it_picked() {
+        let mut storage = Storage::new();
+        let feed = Arc::new(Feed::new());
+        feed.start_propagating();
+        storage.set_feed(feed.clone());
+        let Ok(RESP::BulkString(id)) = run(&mut storage, &["xadd", "s", "*", "f", "v"]) else {
+            panic!("XADD replies with the new ID");
+        };
+        let propagated = String::from_utf8(feed.read_from(0).unwrap()).unwrap();
+        let id = String::from_utf8(id).unwrap();
+        assert!(propagated.contains(&format!("${}\r\n{}\r\n", id.len(), id)));
+        assert!(!propagated.contains("$1\r\n*\r\n"));
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let mut storage = Storage::new();
+        let output = run(&mut storage, &["xgroup", "create", "s", "g", "$"]);
+        assert!(matches!(
+            output,
+            Err(StorageError::CommandSyntaxError(message)) if message == NO_KEY_FOR_XGROUP
+        ));
+        run(
+            &mut storage,
+            &["xgroup", "create", "s", "g", "$", "MKSTREAM"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["xgroup", "create", "s", "g", "0"]),
+            Err(StorageError::BusyGroup)
+        );
+        for id in ["1", "2", "3"] {
+            run(&mut storage, &["xadd", "s", id, "f", id]).unwrap();
+        }
+
+        let read = cmd(&[
+            "xreadgroup",
+            "group",
+            "g",
+            "alice",
+            "count",
+            "2",
+            "streams",
+            "s",
+            ">",
+        ]);
+        let output = storage.process_command(&read);
+        assert_eq!(
+            output,
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("s"),
+                RESP::Array(vec![entry("1-0", &["f", "1"]), entry("2-0", &["f", "2"])])
+            ])]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xpending", "s", "g"]),
+            Ok(RESP::Array(vec![
+                RESP::Integer(2),
+                bulk("1-0"),
+                bulk("2-0"),
+                RESP::Array(vec![RESP::Array(vec![bulk("alice"), bulk("2")])])
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xack", "s", "g", "1-0", "1-0"]),
+            Ok(RESP::Integer(1))
+        );
+
+        let output = run(
+            &mut storage,
+            &["xclaim", "s", "g", "bob", "0", "2-0", "JUSTID"],
+        );
+        assert_eq!(output, Ok(RESP::Array(vec![bulk("2-0")])));
+        let output = run(&mut storage, &["xpending", "s", "g", "-", "+", "10", "bob"]);
+        let Ok(RESP::Array(pending)) = output else {
+            panic!("XPENDING replies with an array");
+        };
+        assert_eq!(pending.len(), 1);
+        let RESP::Array(fields) = &pending[0] else {
+            panic!("pending entries are arrays");
+        };
+        // JUSTID leaves the delivery count alone
+        assert_eq!(fields[3], RESP::Integer(1));
+
+        run(&mut storage, &["xdel", "s", "2-0"]).unwrap();
+        let output = run(&mut storage, &["xautoclaim", "s", "g", "alice", "0", "0"]);
+        assert_eq!(
+            output,
+            Ok(RESP::Array(vec![
+                bulk("0-0"),
+                RESP::Array(vec![]),
+                RESP::Array(vec![bulk("2-0")])
+            ]))
+        );
+        assert_eq!(
+            run(&mut storage, &["xgroup", "delconsumer", "s", "g", "bob"]),
+            Ok(RESP::Integer(0))
+        );
+        assert!(matches!(
+            run(&mut storage, &["xack", "nope", "g", "1-0"]),
+            Ok(RESP::Integer(0))
+        ));
+        assert!(matches!(
+            run(&mut storage, &["xpending", "s", "missing"]),
+            Err(StorageError::NoGroup(_))
+        ));
+    }
+
+    #[test]
+    fn test_xinfo_groups_reports_lag() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["xadd", "s", "1", "f", "v"]).unwrap();
+        run(&mut storage, &["xadd", "s", "2", "f", "v"]).unwrap();
+        run(&mut storage, &["xgroup", "create", "s", "g", "0"]).unwrap();
+        run(
+            &mut storage,
+            &[
+                "xreadgroup",
+                "group",
+                "g",
+                "c",
+                "count",
+                "1",
+                "streams",
+                "s",
+                ">",
+            ],
+        )
+        .unwrap();
+        let Ok(RESP::Array(groups)) = run(&mut storage, &["xinfo", "groups", "s"]) else {
+            panic!("XINFO GROUPS replies with an array");
+        };
+        let RESP::Array(info) = &groups[0] else {
+            panic!("each group is a map");
+        };
+        let field = |name: &str| {
+            let at = info.iter().position(|v| *v == bulk(name)).unwrap();
+            &info[at + 1]
+        };
+        assert_eq!(field("name"), &bulk("g"));
+        assert_eq!(field("pending"), &RESP::Integer(1));
+        assert_eq!(field("entries-read"), &RESP::Integer(1));
+        assert_eq!(field("lag"), &RESP::Integer(1));
+    }
+}
@@ -0,0 +1,1119 @@
+use super::result::{StorageError, StorageResult};
+use super::{Storage, StorageValue, now_ms};
+use crate::command::CommandArg;
+use crate::ds::timeseries::{
+    self, AddError, Aggregation, DuplicatePolicy, LabelFilter, Rule, Sample, TimeSeries,
+};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+const KEY_EXISTS: &str = "TSDB: key already exists";
+const NO_KEY: &str = "TSDB: the key does not exist";
+const BAD_TIMESTAMP: &str = "TSDB: invalid timestamp";
+const BAD_VALUE: &str = "TSDB: invalid value";
+const BAD_AGGREGATION: &str = "TSDB: Unknown aggregation type";
+const BAD_BUCKET: &str = "TSDB: bucketDuration must be greater than zero";
+const MIN_CHUNK_SIZE: usize = 48;
+const MAX_CHUNK_SIZE: usize = 1_048_576;
+
+fn wrong_arity(command: &[CommandArg]) -> StorageError {
+    StorageError::WrongArity(command[0].to_lowercase())
+}
+
+/// A sample's timestamp, `*` for now.
+fn parse_timestamp(arg: &str) -> StorageResult<u64> {
+    if arg == "*" {
+        return Ok(now_ms());
+    }
+    arg.parse().map_err(|_| StorageError::syntax(BAD_TIMESTAMP))
+}
+
+fn parse_value(arg: &str) -> StorageResult<f64> {
+    arg.parse::<f64>()
+        .ok()
+        .filter(|value| !value.is_nan())
+        .ok_or_else(|| StorageError::syntax(BAD_VALUE))
+}
+
+/// One end of a range, `-` and `+` for the earliest and latest.
+fn parse_bound(arg: &str, message: &str) -> StorageResult<u64> {
+    match arg {
+        "-" => Ok(0),
+        "+" => Ok(u64::MAX),
+        _ => arg.parse().map_err(|_| StorageError::syntax(message)),
+    }
+}
+
+/// `AGGREGATION aggregator bucketDuration`, from the aggregator on.
+fn parse_aggregation(
+    command: &[CommandArg],
+    args: &[CommandArg],
+) -> StorageResult<(Aggregation, u64)> {
+    let [aggregation, bucket, ..] = args else {
+        return Err(wrong_arity(command));
+    };
+    let aggregation =
+        Aggregation::parse(aggregation).ok_or_else(|| StorageError::syntax(BAD_AGGREGATION))?;
+    let bucket = bucket
+        .parse::<u64>()
+        .ok()
+        .filter(|bucket| *bucket > 0)
+        .ok_or_else(|| StorageError::syntax(BAD_BUCKET))?;
+    Ok((aggregation, bucket))
+}
+
+fn sample_reply((timestamp, value): Sample) -> RESP {
+    RESP::Array(vec![
+        RESP::Integer(timestamp as i64),
+        RESP::BulkString(value.to_string().into()),
+    ])
+}
+
+fn samples_reply(samples: Vec<Sample>) -> RESP {
+    RESP::Array(samples.into_iter().map(sample_reply).collect())
+}
+
+fn labels_reply<'a>(labels: impl IntoIterator<Item = (&'a str, Option<&'a str>)>) -> RESP {
+    RESP::Array(
+        labels
+            .into_iter()
+            .map(|(label, value)| {
+                RESP::Array(vec![
+                    RESP::BulkString(label.into()),
+                    value.map_or(RESP::Null, |value| RESP::BulkString(value.into())),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// The settings TS.CREATE, TS.ALTER and TS.ADD share, None where not
+/// given.
+#[derive(Default)]
+struct Options {
+    retention: Option<u64>,
+    chunk_size: Option<usize>,
+    duplicate_policy: Option<DuplicatePolicy>,
+    on_duplicate: Option<DuplicatePolicy>,
+    labels: Option<Vec<(String, String)>>,
+}
+
+impl Options {
+    /// `ON_DUPLICATE` is only taken by TS.ADD.
+    fn parse(
+        command: &[CommandArg],
+        args: &[CommandArg],
+        on_duplicate: bool,
+    ) -> StorageResult<Options> {
+        let parse_policy = |arg: &str| {
+            DuplicatePolicy::parse(arg)
+                .ok_or_else(|| StorageError::syntax("TSDB: Unknown DUPLICATE_POLICY"))
+        };
+        let mut options = Options::default();
+        let mut i = 0;
+        while i < args.len() {
+            let token = args[i].to_uppercase();
+            // Labels run to the end of the request
+            if token == "LABELS" {
+                let pairs = &args[i + 1..];
+                if !pairs.len().is_multiple_of(2) {
+                    return Err(wrong_arity(command));
+                }
+                let labels = pairs
+                    .chunks(2)
+                    .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                    .collect();
+                options.labels = Some(labels);
+                break;
+            }
+            let value = args.get(i + 1).ok_or_else(|| wrong_arity(command))?;
+            match token.as_str() {
+                "RETENTION" => {
+                    let retention = value
+                        .parse()
+                        .map_err(|_| StorageError::syntax("TSDB: Couldn't parse RETENTION"))?;
+                    options.retention = Some(retention);
+                }
+                "CHUNK_SIZE" => {
+                    let chunk_size = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|size| {
+                            size.is_multiple_of(8)
+                                && (MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(size)
+                        })
+                        .ok_or_else(|| {
+                            let message = "TSDB: CHUNK_SIZE value must be a multiple of 8 in the range [48 .. 1048576]";
+                            StorageError::syntax(message)
+                        })?;
+                    options.chunk_size = Some(chunk_size);
+                }
+                "DUPLICATE_POLICY" => options.duplicate_policy = Some(parse_policy(value)?),
+                "ON_DUPLICATE" if on_duplicate => options.on_duplicate = Some(parse_policy(value)?),
+                _ => return Err(StorageError::syntax("syntax error")),
+            }
+            i += 2;
+        }
+        Ok(options)
+    }
+
+    /// A new empty series with these settings.
+    fn create(&self) -> TimeSeries {
+        TimeSeries::new(
+            self.retention.unwrap_or(0),
+            self.chunk_size.unwrap_or(timeseries::DEFAULT_CHUNK_SIZE),
+            self.duplicate_policy.unwrap_or(DuplicatePolicy::Block),
+            self.labels.clone().unwrap_or_default(),
+        )
+    }
+}
+
+/// Where aggregation buckets start.
+enum Align {
+    Start,
+    End,
+    At(u64),
+}
+
+/// The arguments of TS.RANGE and TS.MRANGE, minus the key of one and the
+/// label filters of the other.
+struct RangeQuery {
+    from: u64,
+    to: u64,
+    filter_by_ts: Option<Vec<u64>>,
+    filter_by_value: Option<(f64, f64)>,
+    count: Option<usize>,
+    align: Align,
+    aggregation: Option<(Aggregation, u64)>,
+    with_labels: bool,
+    selected_labels: Option<Vec<String>>,
+    filters: Vec<LabelFilter>,
+}
+
+impl RangeQuery {
+    /// `from to` and the options after them. The label options and
+    /// `FILTER` are only taken for several series.
+    fn parse(
+        command: &[CommandArg],
+        args: &[CommandArg],
+        multiple: bool,
+    ) -> StorageResult<RangeQuery> {
+        let [from, to, args @ ..] = args else {
+            return Err(wrong_arity(command));
+        };
+        let mut query = RangeQuery {
+            from: parse_bound(from, "TSDB: wrong fromTimestamp")?,
+            to: parse_bound(to, "TSDB: wrong toTimestamp")?,
+            filter_by_ts: None,
+            filter_by_value: None,
+            count: None,
+            align: Align::At(0),
+            aggregation: None,
+            with_labels: false,
+            selected_labels: None,
+            filters: Vec::new(),
+        };
+        let mut i = 0;
+        while i < args.len() {
+            let token = args[i].to_uppercase();
+            i += 1;
+            match token.as_str() {
+                "FILTER_BY_TS" => {
+                    let timestamps: Vec<u64> =
+                        args[i..].iter().map_while(|arg| arg.parse().ok()).collect();
+                    if timestamps.is_empty() {
+                        return Err(StorageError::syntax(
+                            "TSDB: FILTER_BY_TS one or more arguments are missing",
+                        ));
+                    }
+                    i += timestamps.len();
+                    query.filter_by_ts = Some(timestamps);
+                }
+                "FILTER_BY_VALUE" => {
+                    let (Some(min), Some(max)) = (args.get(i), args.get(i + 1)) else {
+                        return Err(wrong_arity(command));
+                    };
+                    let parse = |arg: &str| {
+                        arg.parse::<f64>()
+                            .map_err(|_| StorageError::syntax("TSDB: Couldn't parse MIN or MAX"))
+                    };
+                    query.filter_by_value = Some((parse(min)?, parse(max)?));
+                    i += 2;
+                }
+                "COUNT" => {
+                    let count = args
+                        .get(i)
+                        .and_then(|count| count.parse().ok())
+                        .ok_or_else(|| StorageError::syntax("TSDB: Couldn't parse COUNT"))?;
+                    query.count = Some(count);
+                    i += 1;
+                }
+                "ALIGN" => {
+                    let align = args.get(i).ok_or_else(|| wrong_arity(command))?;
+                    query.align = match align.as_str() {
+                        "-" => Align::Start,
+                        "+" => Align::End,
+                        _ if align.eq_ignore_ascii_case("start") => Align::Start,
+                        _ if align.eq_ignore_ascii_case("end") => Align::End,
+                        _ => Align::At(
+                            align
+                                .parse()
+                                .map_err(|_| StorageError::syntax("TSDB: Couldn't parse ALIGN"))?,
+                        ),
+                    };
+                    i += 1;
+                }
+                "AGGREGATION" => {
+                    query.aggregation = Some(parse_aggregation(command, &args[i..])?);
+                    i += 2;
+                }
+                "WITHLABELS" if multiple => query.with_labels = true,
+                "SELECTED_LABELS" if multiple => {
+                    let labels = args[i..]
+                        .iter()
+                        .take_while(|arg| !arg.eq_ignore_ascii_case("FILTER"))
+                        .map(|arg| arg.to_string())
+                        .collect::<Vec<_>>();
+                    i += labels.len();
+                    query.selected_labels = Some(labels);
+                }
+                // The filters run to the end of the request
+                "FILTER" if multiple => {
+                    query.filters = parse_filters(&args[i..])?;
+                    i = args.len();
+                }
+                _ => return Err(StorageError::syntax("syntax error")),
+            }
+        }
+        if multiple && query.filters.is_empty() {
+            return Err(StorageError::syntax("TSDB: missing FILTER argument"));
+        }
+        if query.with_labels && query.selected_labels.is_some() {
+            return Err(StorageError::syntax(
+                "TSDB: WITHLABELS and SELECTED_LABELS are mutually exclusive",
+            ));
+        }
+        Ok(query)
+    }
+
+    /// The samples of `series` this query asks for, newest first when
+    /// `reverse`.
+    fn run(&self, series: &TimeSeries, reverse: bool) -> Vec<Sample> {
+        let mut samples = series.range(self.from, self.to);
+        if let Some(timestamps) = &self.filter_by_ts {
+            samples.retain(|(timestamp, _)| timestamps.contains(timestamp));
+        }
+        if let Some((min, max)) = self.filter_by_value {
+            samples.retain(|(_, value)| (min..=max).contains(value));
+        }
+        if let Some((aggregation, bucket)) = self.aggregation {
+            let align = match self.align {
+                Align::Start => self.from,
+                Align::End => self.to,
+                Align::At(align) => align,
+            };
+            samples = timeseries::aggregate(&samples, aggregation, bucket, align);
+        }
+        if reverse {
+            samples.reverse();
+        }
+        if let Some(count) = self.count {
+            samples.truncate(count);
+        }
+        samples
+    }
+
+    /// The labels of `series` to reply with.
+    fn labels(&self, series: &TimeSeries) -> RESP {
+        let value = |label: &str| {
+            series
+                .labels()
+                .iter()
+                .find(|(name, _)| name == label)
+                .map(|(_, value)| value.as_str())
+        };
+        match &self.selected_labels {
+            Some(selected) => {
+                labels_reply(selected.iter().map(|label| (label.as_str(), value(label))))
+            }
+            None if self.with_labels => labels_reply(
+                series
+                    .labels()
+                    .iter()
+                    .map(|(label, value)| (label.as_str(), Some(value.as_str()))),
+            ),
+            None => RESP::Array(Vec::new()),
+        }
+    }
+}
+
+/// Label filters, of which at least one must pick series out.
+fn parse_filters(args: &[CommandArg]) -> StorageResult<Vec<LabelFilter>> {
+    let filters = args
+        .iter()
+        .map(|arg| LabelFilter::parse(arg))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| StorageError::syntax("TSDB: failed parsing labels"))?;
+    if !filters.iter().any(LabelFilter::is_matcher) {
+        return Err(StorageError::syntax(
+            "TSDB: please provide at least one matcher",
+        ));
+    }
+    Ok(filters)
+}
+
+impl Storage {
+    fn read_series(&mut self, key: &str) -> StorageResult<Option<&TimeSeries>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::TimeSeries(series)) => Ok(Some(series)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the series at `key`, keeping `used_memory` in step with
+    /// what it changes. None if there is no series.
+    fn write_series<T>(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&mut TimeSeries) -> T,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            return Ok(None);
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::TimeSeries(series) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = series.memory_usage();
+        let result = f(series);
+        self.used_memory = (self.used_memory + series.memory_usage()).saturating_sub(before);
+        Ok(Some(result))
+    }
+
+    /// The keys of the series every filter matches, in order.
+    fn series_matching(&self, filters: &[LabelFilter]) -> Vec<String> {
+        let mut keys = self.keys_where(|key| {
+            matches!(
+                self.store.get(key).map(|entry| &entry.value),
+                Some(StorageValue::TimeSeries(series))
+                    if filters.iter().all(|filter| filter.matches(series.labels()))
+            )
+        });
+        keys.sort_unstable();
+        keys
+    }
+
+    /// The series at `key` without counting a hit or a miss, for series
+    /// found by their labels.
+    fn peek_series(&self, key: &str) -> Option<&TimeSeries> {
+        match self.store.get(key).map(|entry| &entry.value) {
+            Some(StorageValue::TimeSeries(series)) => Some(series),
+            _ => None,
+        }
+    }
+
+    /// Adds a sample to the series at `key`, and what its compaction rules
+    /// make of it to their destinations.
+    fn add_sample(
+        &mut self,
+        key: &str,
+        timestamp: u64,
+        value: f64,
+        on_duplicate: Option<DuplicatePolicy>,
+    ) -> StorageResult<u64> {
+        let compacted = self
+            .write_series(key, |series| series.add(timestamp, value, on_duplicate))?
+            .ok_or_else(|| StorageError::syntax(NO_KEY))?
+            .map_err(|e| match e {
+                AddError::TooOld => StorageError::syntax("TSDB: Timestamp is older than retention"),
+                AddError::Duplicate => StorageError::syntax("TSDB: Error at upsert, update is not supported when DUPLICATE_POLICY is set to BLOCK mode",
+                ),
+            })?;
+        self.notify(notify::MODULE, "ts.add", key);
+        for (destination, (timestamp, value)) in compacted {
+            if !self.add_compacted(&destination, timestamp, value) {
+                self.foreign_samples.push((destination, timestamp, value));
+            }
+        }
+        Ok(timestamp)
+    }
+
+    /// Adds a compacted sample to the series at `destination`, unless it
+    /// isn't here. A destination replaced since the rule was made is
+    /// passed over.
+    pub(super) fn add_compacted(&mut self, destination: &str, timestamp: u64, value: f64) -> bool {
+        let added = self.write_series(destination, |series| {
+            series.add(timestamp, value, Some(DuplicatePolicy::Last))
+        });
+        match added {
+            Ok(None) => return false,
+            Ok(Some(Ok(_))) => {
+                self.notify(notify::MODULE, "ts.add:dest", destination);
+                self.invalidate(destination);
+            }
+            _ => (),
+        }
+        true
+    }
+
+    /// TS.CREATE key [RETENTION retentionPeriod] [CHUNK_SIZE size]
+    /// [DUPLICATE_POLICY policy] [LABELS label value ...]
+    pub(super) fn command_ts_create(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let options = Options::parse(command, &command[2..], false)?;
+        let key = &command[1];
+        if self.lookup(key).is_some() {
+            return Err(StorageError::syntax(KEY_EXISTS));
+        }
+        self.insert(key.to_string(), StorageValue::TimeSeries(options.create()));
+        self.notify(notify::MODULE, "ts.create", key);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// TS.ALTER key [RETENTION retentionPeriod] [CHUNK_SIZE size]
+    /// [DUPLICATE_POLICY policy] [LABELS label value ...]
+    pub(super) fn command_ts_alter(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let options = Options::parse(command, &command[2..], false)?;
+        let key = &command[1];
+        self.write_series(key, |series| {
+            if let Some(retention) = options.retention {
+                series.set_retention(retention);
+            }
+            if let Some(chunk_size) = options.chunk_size {
+                series.set_chunk_size(chunk_size);
+            }
+            if let Some(policy) = options.duplicate_policy {
+                series.set_duplicate_policy(policy);
+            }
+            if let Some(labels) = options.labels {
+                series.set_labels(labels);
+            }
+        })?
+        .ok_or_else(|| StorageError::syntax(NO_KEY))?;
+        self.notify(notify::MODULE, "ts.alter", key);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// TS.ADD key timestamp value [RETENTION retentionPeriod] [CHUNK_SIZE
+    /// size] [DUPLICATE_POLICY policy] [ON_DUPLICATE policy] [LABELS label
+    /// value ...]
+    ///
+    /// Creates the series with the options given if it doesn't exist.
+    /// Otherwise only ON_DUPLICATE applies.
+    pub(super) fn command_ts_add(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let timestamp = parse_timestamp(&command[2])?;
+        let value = parse_value(&command[3])?;
+        let options = Options::parse(command, &command[4..], true)?;
+        let key = &command[1];
+        if self.lookup(key).is_none() {
+            self.insert(key.to_string(), StorageValue::TimeSeries(options.create()));
+            self.notify(notify::MODULE, "ts.create", key);
+        }
+        self.add_sample(key, timestamp, value, options.on_duplicate)?;
+        // Replicas must add the sample at the same time
+        let mut rewritten = command.to_vec();
+        rewritten[2] = timestamp.to_string().into();
+        self.propagate_as = Some(rewritten);
+        Ok(RESP::Integer(timestamp as i64))
+    }
+
+    /// TS.MADD key timestamp value [key timestamp value ...]
+    ///
+    /// Each sample is added on its own, and replied to with its timestamp
+    /// or the error adding it gave.
+    pub(super) fn command_ts_madd(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if !command[1..].len().is_multiple_of(3) {
+            return Err(wrong_arity(command));
+        }
+        let mut rewritten = command.to_vec();
+        let mut replies = Vec::with_capacity(command.len() / 3);
+        for (i, sample) in command[1..].chunks(3).enumerate() {
+            let added = parse_timestamp(&sample[1]).and_then(|timestamp| {
+                rewritten[3 * i + 2] = timestamp.to_string().into();
+                let value = parse_value(&sample[2])?;
+                self.add_sample(&sample[0], timestamp, value, None)
+            });
+            replies.push(match added {
+                Ok(timestamp) => RESP::Integer(timestamp as i64),
+                Err(e) => RESP::Error(e.to_string()),
+            });
+        }
+        self.propagate_as = Some(rewritten);
+        Ok(RESP::Array(replies))
+    }
+
+    /// TS.GET key
+    pub(super) fn command_ts_get(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let series = self
+            .read_series(&command[1])?
+            .ok_or_else(|| StorageError::syntax(NO_KEY))?;
+        Ok(series.last().map_or(RESP::Array(Vec::new()), sample_reply))
+    }
+
+    /// TS.RANGE key fromTimestamp toTimestamp [FILTER_BY_TS ts ...]
+    /// [FILTER_BY_VALUE min max] [COUNT count] [ALIGN align]
+    /// [AGGREGATION aggregator bucketDuration], and TS.REVRANGE
+    pub(super) fn command_ts_range(
+        &mut self,
+        command: &[CommandArg],
+        reverse: bool,
+    ) -> StorageResult<RESP> {
+        let query = RangeQuery::parse(command, &command[2..], false)?;
+        let series = self
+            .read_series(&command[1])?
+            .ok_or_else(|| StorageError::syntax(NO_KEY))?;
+        Ok(samples_reply(query.run(series, reverse)))
+    }
+
+    /// TS.MRANGE fromTimestamp toTimestamp [the options of TS.RANGE]
+    /// [WITHLABELS | SELECTED_LABELS label ...] FILTER filterExpr ...,
+    /// and TS.MREVRANGE
+    ///
+    /// Replies with the key, labels and samples of every series matching
+    /// the filters, by key.
+    pub(super) fn command_ts_mrange(
+        &mut self,
+        command: &[CommandArg],
+        reverse: bool,
+    ) -> StorageResult<RESP> {
+        let query = RangeQuery::parse(command, &command[1..], true)?;
+        let replies = self
+            .series_matching(&query.filters)
+            .into_iter()
+            .filter_map(|key| {
+                let series = self.peek_series(&key)?;
+                let labels = query.labels(series);
+                let samples = samples_reply(query.run(series, reverse));
+                Some(RESP::Array(vec![
+                    RESP::BulkString(key.into()),
+                    labels,
+                    samples,
+                ]))
+            })
+            .collect();
+        Ok(RESP::Array(replies))
+    }
+
+    /// TS.QUERYINDEX filterExpr ...
+    pub(super) fn command_ts_queryindex(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let filters = parse_filters(&command[1..])?;
+        Ok(RESP::Array(
+            self.series_matching(&filters)
+                .into_iter()
+                .map(|s| RESP::BulkString(s.into()))
+                .collect(),
+        ))
+    }
+
+    /// The series `key`'s samples are compacted into, if that series
+    /// still has the rule. Deleting a source leaves its destinations
+    /// naming it.
+    fn live_source(&self, key: &str) -> Option<&str> {
+        let source = self.peek_series(key)?.source()?;
+        self.peek_series(source)?
+            .rules()
+            .iter()
+            .any(|rule| rule.destination == key)
+            .then_some(source)
+    }
+
+    /// TS.CREATERULE sourceKey destKey AGGREGATION aggregator
+    /// bucketDuration [alignTimestamp]
+    ///
+    /// Both series must exist. Rules don't chain: a destination can't
+    /// have rules of its own, nor a source be a destination.
+    pub(super) fn command_ts_createrule(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        if command.len() > 7 {
+            return Err(wrong_arity(command));
+        }
+        if !command[3].eq_ignore_ascii_case("AGGREGATION") {
+            return Err(StorageError::syntax("syntax error"));
+        }
+        let (aggregation, bucket) = parse_aggregation(command, &command[4..])?;
+        let align = match command.get(6) {
+            Some(align) => align
+                .parse()
+                .map_err(|_| StorageError::syntax("TSDB: Couldn't parse alignTimestamp"))?,
+            None => 0,
+        };
+        let (source, destination) = (&command[1], &command[2]);
+        if source == destination {
+            return Err(StorageError::syntax(
+                "TSDB: the source key and destination key should be different",
+            ));
+        }
+        self.read_series(source)?
+            .ok_or_else(|| StorageError::syntax(NO_KEY))?;
+        let rules = self
+            .read_series(destination)?
+            .ok_or_else(|| StorageError::syntax(NO_KEY))?
+            .rules()
+            .len();
+        if self.live_source(source).is_some() {
+            return Err(StorageError::syntax(
+                "TSDB: the source key already has a source rule",
+            ));
+        }
+        if self.live_source(destination).is_some() {
+            return Err(StorageError::syntax(
+                "TSDB: the destination key already has a src rule",
+            ));
+        }
+        if rules > 0 {
+            return Err(StorageError::syntax(
+                "TSDB: the destination key already has a dst rule",
+            ));
+        }
+        let rule = Rule::new(destination.to_string(), aggregation, bucket, align);
+        self.write_series(source, |series| series.add_rule(rule))?;
+        self.write_series(destination, |series| {
+            series.set_source(Some(source.to_string()))
+        })?;
+        self.notify(notify::MODULE, "ts.createrule:src", source);
+        self.notify(notify::MODULE, "ts.createrule:dest", destination);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// TS.DELETERULE sourceKey destKey
+    pub(super) fn command_ts_deleterule(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let (source, destination) = (&command[1], &command[2]);
+        let removed = self
+            .write_series(source, |series| series.remove_rule(destination))?
+            .ok_or_else(|| StorageError::syntax(NO_KEY))?;
+        if !removed {
+            return Err(StorageError::syntax("TSDB: compaction rule does not exist"));
+        }
+        // The destination may have been deleted or replaced since
+        if self.peek_series(destination).and_then(TimeSeries::source) == Some(source.as_str()) {
+            self.write_series(destination, |series| series.set_source(None))?;
+        }
+        self.notify(notify::MODULE, "ts.deleterule:src", source);
+        self.notify(notify::MODULE, "ts.deleterule:dest", destination);
+        Ok(RESP::SimpleString(String::from("OK")))
+    }
+
+    /// TS.INFO key
+    pub(super) fn command_ts_info(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let series = self
+            .read_series(&command[1])?
+            .ok_or_else(|| StorageError::syntax(NO_KEY))?;
+        let field = |name: &str| RESP::SimpleString(name.to_string());
+        let integer = |n: u64| RESP::Integer(n as i64);
+        let rules = series
+            .rules()
+            .iter()
+            .map(|rule| {
+                RESP::Array(vec![
+                    RESP::BulkString(rule.destination.clone().into()),
+                    integer(rule.bucket),
+                    RESP::SimpleString(rule.aggregation.name().to_uppercase()),
+                    integer(rule.align),
+                ])
+            })
+            .collect();
+        Ok(RESP::Array(vec![
+            field("totalSamples"),
+            integer(series.samples()),
+            field("memoryUsage"),
+            integer(series.memory_usage() as u64),
+            field("firstTimestamp"),
+            integer(series.first_timestamp().unwrap_or(0)),
+            field("lastTimestamp"),
+            integer(series.last().map_or(0, |(timestamp, _)| timestamp)),
+            field("retentionTime"),
+            integer(series.retention()),
+            field("chunkCount"),
+            integer(series.chunks() as u64),
+            field("chunkSize"),
+            integer(series.chunk_size() as u64),
+            field("duplicatePolicy"),
+            RESP::BulkString(series.duplicate_policy().name().to_string().into()),
+            field("labels"),
+            labels_reply(
+                series
+                    .labels()
+                    .iter()
+                    .map(|(label, value)| (label.as_str(), Some(value.as_str()))),
+            ),
+            field("sourceKey"),
+            series
+                .source()
+                .map_or(RESP::Null, |source| RESP::BulkString(source.into())),
+            field("rules"),
+            RESP::Array(rules),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{bulk, cmd, run};
+
+    fn samples(samples: &[(i64, &str)]) -> RESP {
+        RESP::Array(
+            samples
+                .iter()
+                .map(|(timestamp, value)| RESP::Array(vec![RESP::Integer(*timestamp), bulk(value)]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_add_and_range() {
+        let mut storage = Storage::new();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.create", "ts", "DUPLICATE_POLICY", "sum"]
+            ),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.create", "ts"]),
+            Err(StorageError::syntax(KEY_EXISTS))
+        );
+        for (timestamp, value) in [("10", "1"), ("20", "2.5"), ("30", "4"), ("15", "3")] {
+            run(&mut storage, &["ts.add", "ts", timestamp, value]).unwrap();
+        }
+        assert_eq!(
+            run(&mut storage, &["ts.add", "ts", "20", "1"]),
+            Ok(RESP::Integer(20))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.range", "ts", "-", "+"]),
+            Ok(samples(&[(10, "1"), (15, "3"), (20, "3.5"), (30, "4")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.revrange", "ts", "12", "+", "COUNT", "2"]
+            ),
+            Ok(samples(&[(30, "4"), (20, "3.5")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.range", "ts", "-", "+", "AGGREGATION", "avg", "10"]
+            ),
+            Ok(samples(&[(10, "2"), (20, "3.5"), (30, "4")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "ts.range",
+                    "ts",
+                    "-",
+                    "+",
+                    "ALIGN",
+                    "5",
+                    "AGGREGATION",
+                    "max",
+                    "10"
+                ]
+            ),
+            Ok(samples(&[(5, "1"), (15, "3.5"), (25, "4")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "ts.range",
+                    "ts",
+                    "-",
+                    "+",
+                    "FILTER_BY_TS",
+                    "10",
+                    "30",
+                    "FILTER_BY_VALUE",
+                    "2",
+                    "10"
+                ]
+            ),
+            Ok(samples(&[(30, "4")]))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.get", "ts"]),
+            Ok(RESP::Array(vec![RESP::Integer(30), bulk("4")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.range", "ts", "-", "+", "AGGREGATION", "median", "10"]
+            ),
+            Err(StorageError::syntax(BAD_AGGREGATION))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.range", "missing", "-", "+"]),
+            Err(StorageError::syntax(NO_KEY))
+        );
+    }
+
+    #[test]
+    fn test_add_options() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["ts.add", "ts", "100", "1", "RETENTION", "50"],
+        )
+        .unwrap();
+        run(&mut storage, &["ts.add", "ts", "200", "1"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["ts.add", "ts", "100", "1"]),
+            Err(StorageError::syntax(
+                "TSDB: Timestamp is older than retention"
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.add", "ts", "200", "5"]),
+            Err(StorageError::syntax(
+                "TSDB: Error at upsert, update is not supported when DUPLICATE_POLICY is set to BLOCK mode"
+            ))
+        );
+        run(
+            &mut storage,
+            &["ts.add", "ts", "200", "5", "ON_DUPLICATE", "max"],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["ts.range", "ts", "-", "+"]),
+            Ok(samples(&[(200, "5")]))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.add", "ts", "x", "1"]),
+            Err(StorageError::syntax(BAD_TIMESTAMP))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.add", "ts", "300", "nan"]),
+            Err(StorageError::syntax(BAD_VALUE))
+        );
+        run(&mut storage, &["set", "s", "v"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["ts.add", "s", "1", "1"]),
+            Err(StorageError::WrongType)
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "ts.madd", "ts", "300", "1", "missing", "1", "1", "ts", "x", "1"
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                RESP::Integer(300),
+                RESP::Error(format!("ERR {}", NO_KEY)),
+                RESP::Error(format!("ERR {}", BAD_TIMESTAMP)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_auto_timestamp_is_propagated() {
+        let mut storage = Storage::new();
+        let Ok(RESP::Integer(timestamp)) =
+            storage.command_ts_add(&cmd(&["ts.add", "ts", "*", "1"]))
+        else {
+            panic!("TS.ADD replies with the timestamp");
+        };
+        let rewritten = storage.propagate_as.take().unwrap();
+        assert_eq!(
+            rewritten,
+            cmd(&["ts.add", "ts", &timestamp.to_string(), "1"])
+        );
+    }
+
+    #[test]
+    fn test_compaction_rules() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["ts.create", "raw"]).unwrap();
+        run(&mut storage, &["ts.create", "sum"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.createrule", "raw", "sum", "AGGREGATION", "sum", "10"]
+            ),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        for (timestamp, value) in [("1", "1"), ("5", "2"), ("12", "4"), ("25", "1")] {
+            run(&mut storage, &["ts.add", "raw", timestamp, value]).unwrap();
+        }
+        assert_eq!(
+            run(&mut storage, &["ts.range", "sum", "-", "+"]),
+            Ok(samples(&[(0, "3"), (10, "4")]))
+        );
+        // A late sample updates the bucket it falls in
+        run(&mut storage, &["ts.add", "raw", "3", "10"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["ts.range", "sum", "-", "+"]),
+            Ok(samples(&[(0, "13"), (10, "4")]))
+        );
+
+        run(&mut storage, &["ts.create", "other"]).unwrap();
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.createrule", "sum", "other", "AGGREGATION", "max", "100"]
+            ),
+            Err(StorageError::syntax(
+                "TSDB: the source key already has a source rule"
+            ))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.createrule", "other", "sum", "AGGREGATION", "max", "100"]
+            ),
+            Err(StorageError::syntax(
+                "TSDB: the destination key already has a src rule"
+            ))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.createrule", "other", "raw", "AGGREGATION", "max", "100"]
+            ),
+            Err(StorageError::syntax(
+                "TSDB: the destination key already has a dst rule"
+            ))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.createrule", "raw", "other", "AGGREGATION", "max", "0"]
+            ),
+            Err(StorageError::syntax(BAD_BUCKET))
+        );
+
+        assert_eq!(
+            run(&mut storage, &["ts.deleterule", "raw", "sum"]),
+            Ok(RESP::SimpleString(String::from("OK")))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.deleterule", "raw", "sum"]),
+            Err(StorageError::syntax("TSDB: compaction rule does not exist"))
+        );
+        // With the rule gone, `sum` can be compacted from another series
+        run(
+            &mut storage,
+            &["ts.createrule", "other", "sum", "AGGREGATION", "max", "100"],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_mrange_and_queryindex() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["ts.create", "b", "LABELS", "kind", "cpu", "host", "b"],
+        )
+        .unwrap();
+        run(
+            &mut storage,
+            &["ts.create", "a", "LABELS", "kind", "cpu", "host", "a"],
+        )
+        .unwrap();
+        run(&mut storage, &["ts.create", "c", "LABELS", "kind", "mem"]).unwrap();
+        for key in ["a", "b", "c"] {
+            run(&mut storage, &["ts.madd", key, "1", "1", key, "2", "3"]).unwrap();
+        }
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.queryindex", "kind=(cpu,mem)", "host!=b"]
+            ),
+            Ok(RESP::Array(vec![bulk("a"), bulk("c")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "ts.mrevrange",
+                    "-",
+                    "+",
+                    "AGGREGATION",
+                    "sum",
+                    "10",
+                    "SELECTED_LABELS",
+                    "host",
+                    "FILTER",
+                    "kind=cpu"
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                RESP::Array(vec![
+                    bulk("a"),
+                    RESP::Array(vec![RESP::Array(vec![bulk("host"), bulk("a")])]),
+                    samples(&[(0, "4")]),
+                ]),
+                RESP::Array(vec![
+                    bulk("b"),
+                    RESP::Array(vec![RESP::Array(vec![bulk("host"), bulk("b")])]),
+                    samples(&[(0, "4")]),
+                ]),
+            ]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["ts.mrange", "-", "+", "WITHLABELS", "FILTER", "kind=mem"]
+            ),
+            Ok(RESP::Array(vec![RESP::Array(vec![
+                bulk("c"),
+                RESP::Array(vec![RESP::Array(vec![bulk("kind"), bulk("mem")])]),
+                samples(&[(1, "1"), (2, "3")]),
+            ])]))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.mrange", "-", "+", "COUNT", "1"]),
+            Err(StorageError::syntax("TSDB: missing FILTER argument"))
+        );
+        assert_eq!(
+            run(&mut storage, &["ts.mrange", "-", "+", "FILTER", "kind"]),
+            Err(StorageError::syntax("TSDB: failed parsing labels"))
+        );
+    }
+
+    #[test]
+    fn test_alter_and_info() {
+        let mut storage = Storage::new();
+        run(&mut storage, &["ts.create", "ts", "LABELS", "a", "1"]).unwrap();
+        run(&mut storage, &["ts.add", "ts", "10", "1"]).unwrap();
+        run(&mut storage, &["ts.add", "ts", "20", "1"]).unwrap();
+        run(
+            &mut storage,
+            &[
+                "ts.alter",
+                "ts",
+                "RETENTION",
+                "5",
+                "CHUNK_SIZE",
+                "128",
+                "LABELS",
+                "b",
+                "2",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut storage, &["ts.alter", "ts", "CHUNK_SIZE", "100"]),
+            Err(StorageError::syntax(
+                "TSDB: CHUNK_SIZE value must be a multiple of 8 in the range [48 .. 1048576]"
+            ))
+        );
+        let Ok(RESP::Array(info)) = run(&mut storage, &["ts.info", "ts"]) else {
+            panic!("TS.INFO replies with an array");
+        };
+        assert_eq!(info[1], RESP::Integer(1));
+        assert_eq!(info[5], RESP::Integer(20));
+        assert_eq!(info[9], RESP::Integer(5));
+        assert_eq!(info[13], RESP::Integer(128));
+        assert_eq!(info[15], bulk("block"));
+        assert_eq!(
+            info[17],
+            RESP::Array(vec![RESP::Array(vec![bulk("b"), bulk("2")])])
+        );
+        assert_eq!(info[19], RESP::Null);
+    }
+}
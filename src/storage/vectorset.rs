@@ -0,0 +1,687 @@
+use super::result::{StorageError, StorageResult, parse_in};
+use super::{Storage, StorageValue};
+use crate::command::CommandArg;
+use crate::ds::json::Value;
+use crate::ds::vectorset::{self, Filter, Metric, Quantization, Search, VectorSet};
+use crate::pubsub::notify;
+use crate::resp::RESP;
+
+const BAD_VECTOR: &str = "invalid vector specification";
+const MAX_DIM: usize = 65536;
+/// Nodes a filtered search looks at for each result asked for, unless
+/// FILTER-EF says otherwise.
+const FILTER_EF_PER_RESULT: usize = 100;
+
+/// `VALUES num v1 v2 ...` starting at `command[i]`, and where the
+/// arguments after it begin.
+fn parse_values(command: &[CommandArg], i: usize) -> StorageResult<(Vec<f32>, usize)> {
+    match command.get(i).map(|arg| arg.to_uppercase()).as_deref() {
+        Some("VALUES") => {}
+        _ => return Err(StorageError::syntax(BAD_VECTOR)),
+    }
+    let dim = parse_in(command.get(i + 1), 1..=MAX_DIM, BAD_VECTOR)?;
+    let values = command
+        .get(i + 2..i + 2 + dim)
+        .ok_or_else(|| StorageError::syntax(BAD_VECTOR))?;
+    let vector = values
+        .iter()
+        .map(|value| value.parse::<f32>().ok().filter(|v| v.is_finite()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| StorageError::syntax(BAD_VECTOR))?;
+    Ok((vector, i + 2 + dim))
+}
+
+fn parse_attributes(arg: Option<&CommandArg>) -> StorageResult<Option<Value>> {
+    let arg = arg.ok_or_else(StorageError::syntax_error)?;
+    if arg.is_empty() {
+        return Ok(None);
+    }
+    match Value::parse(arg) {
+        Ok(value @ Value::Object(_)) => Ok(Some(value)),
+        Ok(_) => Err(StorageError::syntax("attributes must be a JSON object")),
+        Err(message) => Err(StorageError::syntax(&format!("invalid JSON: {}", message))),
+    }
+}
+
+fn dimension_mismatch(got: usize, set: &VectorSet) -> StorageError {
+    let message = format!(
+        "Vector dimension mismatch - got {} but set has {}",
+        got,
+        set.dim()
+    );
+    StorageError::syntax(&message)
+}
+
+fn info(fields: Vec<(&str, RESP)>) -> RESP {
+    RESP::Array(
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| [RESP::SimpleString(name.to_string()), value])
+            .collect(),
+    )
+}
+
+/// VADD's vector, element and options. The graph options only apply when
+/// the set is created; given for an existing set they must agree with it.
+struct Add<'a> {
+    vector: Vec<f32>,
+    element: &'a str,
+    quantization: Option<Quantization>,
+    metric: Option<Metric>,
+    m: Option<usize>,
+    ef: usize,
+    /// Some(None) to clear the attributes
+    attributes: Option<Option<Value>>,
+}
+
+impl<'a> Add<'a> {
+    fn parse(command: &'a [CommandArg]) -> StorageResult<Add<'a>> {
+        let (vector, i) = parse_values(command, 2)?;
+        let element = command.get(i).ok_or_else(StorageError::syntax_error)?;
+        let mut add = Add {
+            vector,
+            element,
+            quantization: None,
+            metric: None,
+            m: None,
+            ef: vectorset::DEFAULT_EF_CONSTRUCTION,
+            attributes: None,
+        };
+        let mut i = i + 1;
+        while let Some(arg) = command.get(i) {
+            let value = command.get(i + 1);
+            match arg.to_uppercase().as_str() {
+                // Inserts run on the shard's thread, so there is nothing
+                // to check and set
+                "CAS" => {}
+                "NOQUANT" | "Q8" if add.quantization.is_some() => {
+                    return Err(StorageError::syntax(
+                        "only one quantization type can be given",
+                    ));
+                }
+                "NOQUANT" => add.quantization = Some(Quantization::None),
+                "Q8" => add.quantization = Some(Quantization::Int8),
+                "METRIC" => {
+                    let metric = value.and_then(|value| Metric::parse(value));
+                    add.metric =
+                        Some(metric.ok_or_else(|| StorageError::syntax("unknown METRIC"))?);
+                    i += 1;
+                }
+                "EF" => {
+                    let message = "invalid EF";
+                    add.ef = parse_in(value, 1..=1_000_000, message)?;
+                    i += 1;
+                }
+                "M" => {
+                    let message = "invalid M, must be between 2 and 1024";
+                    add.m = Some(parse_in(value, 2..=1024, message)?);
+                    i += 1;
+                }
+                "SETATTR" => {
+                    add.attributes = Some(parse_attributes(value)?);
+                    i += 1;
+                }
+                _ => return Err(StorageError::syntax_error()),
+            }
+            i += 1;
+        }
+        Ok(add)
+    }
+
+    /// An error if the options disagree with the existing `set`.
+    fn check(&self, set: &VectorSet) -> StorageResult<()> {
+        if self.vector.len() != set.dim() {
+            return Err(dimension_mismatch(self.vector.len(), set));
+        }
+        if self.quantization.is_some_and(|q| q != set.quantization()) {
+            let message = "asked quantization mismatch with existing vector set";
+            return Err(StorageError::syntax(message));
+        }
+        if self.metric.is_some_and(|metric| metric != set.metric()) {
+            return Err(StorageError::syntax(
+                "asked metric mismatch with existing vector set",
+            ));
+        }
+        if self.m.is_some_and(|m| m != set.m()) {
+            return Err(StorageError::syntax(
+                "asked M value mismatch with existing vector set",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What VSIM searches near.
+enum Query<'a> {
+    Element(&'a str),
+    Vector(Vec<f32>),
+}
+
+struct Similar<'a> {
+    query: Query<'a>,
+    with_scores: bool,
+    with_attributes: bool,
+    count: usize,
+    epsilon: Option<f64>,
+    ef: Option<usize>,
+    filter: Option<Filter>,
+    filter_ef: Option<usize>,
+    truth: bool,
+}
+
+impl<'a> Similar<'a> {
+    fn parse(command: &'a [CommandArg]) -> StorageResult<Similar<'a>> {
+        let (query, mut i) = match command[2].to_uppercase().as_str() {
+            "ELE" => {
+                let element = command.get(3).ok_or_else(StorageError::syntax_error)?;
+                (Query::Element(element), 4)
+            }
+            _ => {
+                let (vector, i) = parse_values(command, 2)?;
+                (Query::Vector(vector), i)
+            }
+        };
+        let mut similar = Similar {
+            query,
+            with_scores: false,
+            with_attributes: false,
+            count: 10,
+            epsilon: None,
+            ef: None,
+            filter: None,
+            filter_ef: None,
+            truth: false,
+        };
+        while let Some(arg) = command.get(i) {
+            let value = command.get(i + 1);
+            match arg.to_uppercase().as_str() {
+                "WITHSCORES" => similar.with_scores = true,
+                "WITHATTRIBS" => similar.with_attributes = true,
+                "TRUTH" => similar.truth = true,
+                // Searches run on the shard's thread either way
+                "NOTHREAD" => {}
+                "COUNT" => {
+                    let message = "COUNT must be a positive integer";
+                    similar.count = parse_in(value, 1..=usize::MAX, message)?;
+                    i += 1;
+                }
+                "EPSILON" => {
+                    let epsilon = value
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .filter(|epsilon| (0.0..=1.0).contains(epsilon))
+                        .ok_or_else(|| StorageError::syntax("EPSILON must be between 0 and 1"))?;
+                    similar.epsilon = Some(epsilon);
+                    i += 1;
+                }
+                "EF" => {
+                    let message = "invalid EF";
+                    similar.ef = Some(parse_in(value, 1..=1_000_000, message)?);
+                    i += 1;
+                }
+                "FILTER" => {
+                    let expression = value.ok_or_else(StorageError::syntax_error)?;
+                    let filter = Filter::parse(expression).map_err(|message| {
+                        StorageError::syntax(&format!("invalid FILTER expression: {}", message))
+                    })?;
+                    similar.filter = Some(filter);
+                    i += 1;
+                }
+                "FILTER-EF" => {
+                    let message = "invalid FILTER-EF";
+                    similar.filter_ef = Some(parse_in(value, 1..=usize::MAX, message)?);
+                    i += 1;
+                }
+                _ => return Err(StorageError::syntax_error()),
+            }
+            i += 1;
+        }
+        Ok(similar)
+    }
+}
+
+impl Storage {
+    fn read_vectorset(&mut self, key: &str) -> StorageResult<Option<&VectorSet>> {
+        match self.lookup_read(key).map(|entry| &entry.value) {
+            Some(StorageValue::VectorSet(set)) => Ok(Some(set)),
+            Some(_) => Err(StorageError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Runs `f` on the set at `key`, creating it with `create` first if
+    /// there is none, and keeps `used_memory` in step with what it
+    /// changes. None if there is no set to run on. An emptied set is
+    /// removed.
+    fn write_vectorset<T>(
+        &mut self,
+        key: &str,
+        create: Option<VectorSet>,
+        f: impl FnOnce(&mut VectorSet) -> T,
+    ) -> StorageResult<Option<T>> {
+        if self.lookup(key).is_none() {
+            let Some(set) = create else {
+                return Ok(None);
+            };
+            self.insert(key.to_string(), StorageValue::VectorSet(set));
+        }
+        let entry = self.store.get_mut(key).expect("the key was just found");
+        let StorageValue::VectorSet(set) = &mut entry.value else {
+            return Err(StorageError::WrongType);
+        };
+        let before = set.memory_usage();
+        let result = f(set);
+        self.used_memory = (self.used_memory + set.memory_usage()).saturating_sub(before);
+        if set.is_empty() {
+            self.remove(key);
+            self.notify(notify::GENERIC, "del", key);
+        }
+        Ok(Some(result))
+    }
+
+    /// VADD key VALUES num vector element [CAS] [NOQUANT | Q8]
+    /// [METRIC COSINE | L2] [EF build-exploration-factor]
+    /// [SETATTR attributes] [M numlinks]
+    pub(super) fn command_vadd(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let add = Add::parse(command)?;
+        let key = &command[1];
+        let create = match self.read_vectorset(key)? {
+            Some(set) => {
+                add.check(set)?;
+                None
+            }
+            None => Some(VectorSet::new(
+                add.vector.len(),
+                add.metric.unwrap_or(Metric::Cosine),
+                add.quantization.unwrap_or(Quantization::Int8),
+                add.m.unwrap_or(vectorset::DEFAULT_M),
+                add.ef,
+            )),
+        };
+        let added = self
+            .write_vectorset(key, create, |set| {
+                let added = set.add(add.element, &add.vector);
+                if let Some(attributes) = add.attributes {
+                    set.set_attributes(add.element, attributes);
+                }
+                added
+            })?
+            .expect("the set is created");
+        self.notify(notify::MODULE, "vadd", key);
+        Ok(RESP::Integer(i64::from(added)))
+    }
+
+    /// VREM key element
+    pub(super) fn command_vrem(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let key = &command[1];
+        let removed = self
+            .write_vectorset(key, None, |set| set.remove(&command[2]))?
+            .unwrap_or(false);
+        if removed {
+            self.notify(notify::MODULE, "vrem", key);
+        }
+        Ok(RESP::Integer(i64::from(removed)))
+    }
+
+    /// VSIM key (ELE element | VALUES num vector) [WITHSCORES]
+    /// [WITHATTRIBS] [COUNT num] [EPSILON delta] [EF search-exploration-factor]
+    /// [FILTER expression] [FILTER-EF max-filtering-effort] [TRUTH]
+    /// [NOTHREAD]
+    pub(super) fn command_vsim(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let similar = Similar::parse(command)?;
+        let Some(set) = self.read_vectorset(&command[1])? else {
+            return Ok(RESP::Array(vec![]));
+        };
+        let vector = match similar.query {
+            Query::Element(element) => set
+                .vector(element)
+                .ok_or_else(|| StorageError::syntax("element not found in set"))?,
+            Query::Vector(vector) if vector.len() != set.dim() => {
+                return Err(dimension_mismatch(vector.len(), set));
+            }
+            Query::Vector(vector) => vector,
+        };
+        let search = Search {
+            count: similar.count,
+            ef: similar.ef.unwrap_or(vectorset::DEFAULT_EF),
+            filter: similar.filter.as_ref(),
+            filter_ef: similar
+                .filter_ef
+                .unwrap_or(similar.count.saturating_mul(FILTER_EF_PER_RESULT)),
+            exhaustive: similar.truth,
+        };
+        let found = set.search(&vector, &search);
+        let mut reply = Vec::new();
+        for (element, score) in found {
+            if similar.epsilon.is_some_and(|epsilon| score < 1.0 - epsilon) {
+                continue;
+            }
+            reply.push(RESP::BulkString(element.into()));
+            if similar.with_scores {
+                reply.push(RESP::BulkString(score.to_string().into()));
+            }
+            if similar.with_attributes {
+                let attributes = set.attributes(element).flatten();
+                reply.push(attributes.map_or(RESP::Null, |a| RESP::BulkString(a.to_json().into())));
+            }
+        }
+        Ok(RESP::Array(reply))
+    }
+
+    /// VCARD key
+    pub(super) fn command_vcard(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let len = self.read_vectorset(&command[1])?.map_or(0, VectorSet::len);
+        Ok(RESP::Integer(len as i64))
+    }
+
+    /// VDIM key
+    pub(super) fn command_vdim(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let set = self
+            .read_vectorset(&command[1])?
+            .ok_or_else(|| StorageError::syntax("key does not exist"))?;
+        Ok(RESP::Integer(set.dim() as i64))
+    }
+
+    /// VEMB key element
+    pub(super) fn command_vemb(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let vector = self
+            .read_vectorset(&command[1])?
+            .and_then(|set| set.vector(&command[2]));
+        Ok(vector.map_or(RESP::Null, |vector| {
+            RESP::Array(
+                vector
+                    .into_iter()
+                    .map(|x| RESP::BulkString(x.to_string().into()))
+                    .collect(),
+            )
+        }))
+    }
+
+    /// VSETATTR key element attributes, where empty attributes clear them
+    pub(super) fn command_vsetattr(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let attributes = parse_attributes(command.get(3))?;
+        let key = &command[1];
+        let set = self
+            .write_vectorset(key, None, |set| set.set_attributes(&command[2], attributes))?
+            .unwrap_or(false);
+        if set {
+            self.notify(notify::MODULE, "vsetattr", key);
+        }
+        Ok(RESP::Integer(i64::from(set)))
+    }
+
+    /// VGETATTR key element
+    pub(super) fn command_vgetattr(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let attributes = self
+            .read_vectorset(&command[1])?
+            .and_then(|set| set.attributes(&command[2]).flatten());
+        Ok(attributes.map_or(RESP::Null, |attributes| {
+            RESP::BulkString(attributes.to_json().into())
+        }))
+    }
+
+    /// VINFO key
+    pub(super) fn command_vinfo(&mut self, command: &[CommandArg]) -> StorageResult<RESP> {
+        let Some(set) = self.read_vectorset(&command[1])? else {
+            return Ok(RESP::Null);
+        };
+        Ok(info(vec![
+            (
+                "quant-type",
+                RESP::BulkString(set.quantization().name().to_string().into()),
+            ),
+            (
+                "metric",
+                RESP::BulkString(set.metric().name().to_string().into()),
+            ),
+            ("hnsw-m", RESP::Integer(set.m() as i64)),
+            ("vector-dim", RESP::Integer(set.dim() as i64)),
+            ("size", RESP::Integer(set.len() as i64)),
+            ("max-level", RESP::Integer(set.max_level() as i64)),
+            (
+                "attributes-count",
+                RESP::Integer(set.attributes_count() as i64),
+            ),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{bulk, run};
+
+    fn points(storage: &mut Storage) {
+        let points = [
+            ("origin", "0", "0", r#"{"kind": "center", "size": 1}"#),
+            ("right", "2", "0", r#"{"kind": "edge", "size": 3}"#),
+            ("up", "0", "1", r#"{"kind": "edge", "size": 2}"#),
+            ("far", "10", "10", ""),
+        ];
+        for (name, x, y, attributes) in points {
+            let added = run(
+                &mut *storage,
+                &[
+                    "vadd", "v", "VALUES", "2", x, y, name, "NOQUANT", "METRIC", "L2", "SETATTR",
+                    attributes,
+                ],
+            );
+            assert_eq!(added, Ok(RESP::Integer(1)));
+        }
+    }
+
+    #[test]
+    fn test_vadd_and_vsim() {
+        let mut storage = Storage::new();
+        points(&mut storage);
+        assert_eq!(run(&mut storage, &["vcard", "v"]), Ok(RESP::Integer(4)));
+        assert_eq!(run(&mut storage, &["vdim", "v"]), Ok(RESP::Integer(2)));
+        assert_eq!(
+            run(&mut storage, &["vsim", "v", "ELE", "origin", "COUNT", "3"]),
+            Ok(RESP::Array(vec![bulk("origin"), bulk("up"), bulk("right")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "vsim",
+                    "v",
+                    "VALUES",
+                    "2",
+                    "0",
+                    "3",
+                    "WITHSCORES",
+                    "COUNT",
+                    "1"
+                ]
+            ),
+            Ok(RESP::Array(vec![bulk("up"), bulk("0.3333333333333333")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["vsim", "v", "ELE", "origin", "EPSILON", "0.6"]
+            ),
+            Ok(RESP::Array(vec![bulk("origin"), bulk("up")]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &[
+                    "vsim",
+                    "v",
+                    "ELE",
+                    "origin",
+                    "WITHATTRIBS",
+                    "FILTER",
+                    ".kind == 'edge' and .size > 2"
+                ]
+            ),
+            Ok(RESP::Array(vec![
+                bulk("right"),
+                bulk(r#"{"kind":"edge","size":3}"#)
+            ]))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["vsim", "v", "ELE", "far", "COUNT", "2", "TRUTH"]
+            ),
+            Ok(RESP::Array(vec![bulk("far"), bulk("right")]))
+        );
+        assert_eq!(
+            run(&mut storage, &["vsim", "missing", "ELE", "far"]),
+            Ok(RESP::Array(vec![]))
+        );
+        assert_eq!(
+            run(&mut storage, &["vsim", "v", "ELE", "nobody"]),
+            Err(StorageError::syntax("element not found in set"))
+        );
+        assert_eq!(
+            run(&mut storage, &["vsim", "v", "VALUES", "3", "0", "0", "0"]),
+            Err(StorageError::syntax(
+                "Vector dimension mismatch - got 3 but set has 2"
+            ))
+        );
+        assert!(
+            run(
+                &mut storage,
+                &["vsim", "v", "ELE", "far", "FILTER", ".size >"]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_vadd_checks_the_existing_set() {
+        let mut storage = Storage::new();
+        points(&mut storage);
+        assert_eq!(
+            run(&mut storage, &["vadd", "v", "VALUES", "1", "0", "x"]),
+            Err(StorageError::syntax(
+                "Vector dimension mismatch - got 1 but set has 2"
+            ))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["vadd", "v", "VALUES", "2", "0", "0", "x", "Q8"]
+            ),
+            Err(StorageError::syntax(
+                "asked quantization mismatch with existing vector set"
+            ))
+        );
+        assert_eq!(
+            run(&mut storage, &["vadd", "v", "VALUES", "2", "a", "0", "x"]),
+            Err(StorageError::syntax(BAD_VECTOR))
+        );
+        assert_eq!(
+            run(
+                &mut storage,
+                &["vadd", "v", "VALUES", "2", "0", "0", "x", "SETATTR", "[1]"]
+            ),
+            Err(StorageError::syntax("attributes must be a JSON object"))
+        );
+        // Updating an element replaces its vector
+        assert_eq!(
+            run(&mut storage, &["vadd", "v", "VALUES", "2", "5", "5", "far"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["vemb", "v", "far"]),
+            Ok(RESP::Array(vec![bulk("5"), bulk("5")]))
+        );
+        run(&mut storage, &["set", "s", "x"]).unwrap();
+        assert_eq!(
+            run(&mut storage, &["vadd", "s", "VALUES", "1", "0", "x"]),
+            Err(StorageError::WrongType)
+        );
+    }
+
+    #[test]
+    fn test_quantized_cosine_by_default() {
+        let mut storage = Storage::new();
+        run(
+            &mut storage,
+            &["vadd", "q", "VALUES", "3", "1", "2", "3", "a"],
+        )
+        .unwrap();
+        let Ok(RESP::Array(info)) = run(&mut storage, &["vinfo", "q"]) else {
+            panic!("VINFO replies with an array");
+        };
+        assert_eq!(
+            &info[..4],
+            &[
+                RESP::SimpleString("quant-type".to_string()),
+                bulk("int8"),
+                RESP::SimpleString("metric".to_string()),
+                bulk("cosine"),
+            ]
+        );
+        let Ok(RESP::Array(vector)) = run(&mut storage, &["vemb", "q", "a"]) else {
+            panic!("VEMB replies with an array");
+        };
+        let vector: Vec<f32> = vector
+            .iter()
+            .map(|x| match x {
+                RESP::BulkString(x) => std::str::from_utf8(x).unwrap().parse().unwrap(),
+                _ => panic!("components are bulk strings"),
+            })
+            .collect();
+        for (got, want) in vector.iter().zip([1.0, 2.0, 3.0]) {
+            assert!((got - want).abs() < 0.02, "{:?}", vector);
+        }
+        assert_eq!(run(&mut storage, &["vemb", "q", "b"]), Ok(RESP::Null));
+        assert_eq!(run(&mut storage, &["vinfo", "none"]), Ok(RESP::Null));
+    }
+
+    #[test]
+    fn test_attributes_and_removal() {
+        let mut storage = Storage::new();
+        points(&mut storage);
+        assert_eq!(run(&mut storage, &["vgetattr", "v", "far"]), Ok(RESP::Null));
+        assert_eq!(
+            run(
+                &mut storage,
+                &["vsetattr", "v", "far", r#"{"kind": "edge"}"#]
+            ),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(
+            run(&mut storage, &["vgetattr", "v", "far"]),
+            Ok(bulk(r#"{"kind":"edge"}"#))
+        );
+        assert_eq!(
+            run(&mut storage, &["vsetattr", "v", "nobody", "{}"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["vsetattr", "v", "far", ""]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(run(&mut storage, &["vgetattr", "v", "far"]), Ok(RESP::Null));
+        for name in ["origin", "right", "up"] {
+            assert_eq!(
+                run(&mut storage, &["vrem", "v", name]),
+                Ok(RESP::Integer(1))
+            );
+        }
+        assert_eq!(
+            run(&mut storage, &["vrem", "v", "up"]),
+            Ok(RESP::Integer(0))
+        );
+        assert_eq!(
+            run(&mut storage, &["vsim", "v", "ELE", "far"]),
+            Ok(RESP::Array(vec![bulk("far")]))
+        );
+        // The last removal takes the key with it
+        assert_eq!(
+            run(&mut storage, &["vrem", "v", "far"]),
+            Ok(RESP::Integer(1))
+        );
+        assert_eq!(storage.keys_count(), 0);
+        assert_eq!(
+            run(&mut storage, &["vdim", "v"]),
+            Err(StorageError::syntax("key does not exist"))
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Helpers shared by the unit tests: building requests and replies, and
+//! running requests against a server, with or without a socket, or
+//! straight on storage.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::client::Client;
+use crate::command::{CommandArg, resolve};
+use crate::resp::{RESP, RESPError, bytes_to_resp};
+use crate::server::{Server, process_request, serve};
+use crate::storage::result::StorageResult;
+use crate::storage::{Keyspace, ShardedStorage, Storage};
+
+/// A request's arguments, the way storage takes them.
+pub fn cmd(parts: &[&str]) -> Vec<CommandArg> {
+    parts.iter().map(|s| CommandArg::from(*s)).collect()
+}
+
+pub fn bulk(s: &str) -> RESP {
+    RESP::BulkString(s.into())
+}
+
+/// Storage a request can run on without a server: one `Storage`, or all
+/// the shards of a `ShardedStorage`.
+pub trait Runner {
+    fn run_args(self, args: &[CommandArg]) -> StorageResult<RESP>;
+}
+
+impl Runner for &mut Storage {
+    fn run_args(self, args: &[CommandArg]) -> StorageResult<RESP> {
+        self.process_command(args)
+    }
+}
+
+impl Runner for &ShardedStorage {
+    fn run_args(self, args: &[CommandArg]) -> StorageResult<RESP> {
+        self.execute(resolve(args).unwrap(), args)
+    }
+}
+
+/// Runs a request straight on `storage`.
+pub fn run(storage: impl Runner, parts: &[&str]) -> StorageResult<RESP> {
+    storage.run_args(&cmd(parts))
+}
+
+/// A request the way it arrives over the wire.
+fn request(parts: &[&str]) -> RESP {
+    RESP::Array(parts.iter().map(|part| bulk(part)).collect())
+}
+
+/// A client logged in as the default user.
+pub fn connect(server: &Server) -> Arc<Client> {
+    let client = server.clients.register(String::new(), String::new());
+    client.login("default");
+    client
+}
+
+/// Runs a request as `client`, with errors turned into error replies.
+pub async fn send(server: &Arc<Server>, client: &Arc<Client>, parts: &[&str]) -> RESP {
+    process_request(request(parts), server.clone(), client)
+        .await
+        .unwrap_or_else(|e| RESP::Error(e.to_string()))
+}
+
+/// A server listening on a free port, and a connection to it.
+pub async fn listen() -> (Arc<Server>, TcpStream) {
+    listen_with(Keyspace::default()).await
+}
+
+/// Like `listen`, for a server running on `storage`.
+pub async fn listen_with(storage: Keyspace) -> (Arc<Server>, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let config = HashMap::from([("port".to_string(), port.to_string())]);
+    let server = Arc::new(Server::new(config, storage));
+    tokio::spawn(serve(listener, server.clone()));
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    (server, stream)
+}
+
+/// Another connection to a server started by `listen`.
+pub async fn reconnect(server: &Server) -> TcpStream {
+    TcpStream::connect(("127.0.0.1", server.port().parse::<u16>().unwrap()))
+        .await
+        .unwrap()
+}
+
+/// Sends a request over `stream` and returns the reply's bytes as they
+/// came off the wire.
+pub async fn exchange(stream: &mut TcpStream, parts: &[&[u8]]) -> Vec<u8> {
+    let request = RESP::Array(
+        parts
+            .iter()
+            .map(|part| RESP::BulkString(part.to_vec()))
+            .collect(),
+    );
+    stream.write_all(&request.to_bytes()).await.unwrap();
+    let mut reply = Vec::new();
+    loop {
+        let mut buffer = [0; 1024];
+        let read = stream.read(&mut buffer).await.unwrap();
+        assert!(read > 0, "connection closed");
+        reply.extend_from_slice(&buffer[..read]);
+        // The parser reads requests, which are arrays, so a bulk reply is
+        // checked as the only element of one
+        let complete = match reply[0] {
+            b'$' | b'*' => !matches!(
+                bytes_to_resp(&[b"*1\r\n", &reply[..]].concat(), &mut 0),
+                Err(RESPError::OutOfBounds(_))
+            ),
+            _ => reply.ends_with(b"\r\n"),
+        };
+        if complete {
+            return reply;
+        }
+    }
+}
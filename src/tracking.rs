@@ -0,0 +1,502 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::client::Client;
+use crate::command::{CommandArg, CommandSpec, flags};
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult};
+
+/// The pub/sub channel redirected invalidations are sent on.
+const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// Which reads a default mode client has remembered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Caching {
+    /// Every read
+    Always,
+    /// Only reads right after CLIENT CACHING YES
+    OptIn,
+    /// Every read except right after CLIENT CACHING NO
+    OptOut,
+}
+
+/// A client with CLIENT TRACKING on.
+struct Tracker {
+    client: Arc<Client>,
+    /// The REDIRECT client id, and that client while it's connected
+    redirect: Option<(u64, Option<Arc<Client>>)>,
+    bcast: bool,
+    /// BCAST prefixes, where none means every key
+    prefixes: Vec<String>,
+    caching: Caching,
+    /// What CLIENT CACHING said about the next command
+    next: Option<bool>,
+}
+
+impl Tracker {
+    /// Sends an invalidation for `keys`, as a push to the client itself or
+    /// as a pub/sub message to its redirect.
+    fn invalidate(&self, keys: Vec<String>) {
+        let keys = RESP::Array(
+            keys.into_iter()
+                .map(|s| RESP::BulkString(s.into()))
+                .collect(),
+        );
+        match &self.redirect {
+            None => self.client.push(RESP::Push(vec![bulk("invalidate"), keys])),
+            Some((_, Some(target))) => target.push(RESP::Array(vec![
+                bulk("message"),
+                bulk(INVALIDATE_CHANNEL),
+                keys,
+            ])),
+            Some((id, None)) => self.client.push(RESP::Push(vec![
+                bulk("tracking-redir-broken"),
+                RESP::Integer(*id as i64),
+            ])),
+        }
+    }
+
+    fn matches_prefix(&self, key: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| key.starts_with(p))
+    }
+}
+
+#[derive(Default)]
+struct TrackingState {
+    trackers: HashMap<u64, Tracker>,
+    /// The clients that read each key since it last changed. Entries for
+    /// clients that stopped tracking are dropped when the key changes.
+    keys: HashMap<String, HashSet<u64>>,
+}
+
+/// The server-side invalidation table behind CLIENT TRACKING. Storage
+/// reports every key it changes here, and the clients that read the key,
+/// or asked about its prefix, are told to drop it from their caches.
+#[derive(Default)]
+pub struct Tracking {
+    state: Mutex<TrackingState>,
+    /// Whether any client is tracking, so writes skip the lock otherwise
+    active: AtomicBool,
+}
+
+impl Tracking {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set_active(&self, state: &TrackingState) {
+        self.active
+            .store(!state.trackers.is_empty(), Ordering::Relaxed);
+    }
+
+    /// Remembers the keys a read-only request is about to read, for a
+    /// client tracking them. Done before the read so a write racing with
+    /// it still sends an invalidation. Also uses up CLIENT CACHING.
+    pub fn before_command(&self, client: &Client, spec: &CommandSpec, args: &[CommandArg]) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let Some(tracker) = state.trackers.get_mut(&client.id()) else {
+            return;
+        };
+        if spec.name == "client|caching" {
+            return;
+        }
+        let next = tracker.next.take();
+        let remember = match tracker.caching {
+            _ if tracker.bcast => false,
+            Caching::Always => true,
+            Caching::OptIn => next == Some(true),
+            Caching::OptOut => next != Some(false),
+        };
+        if !remember || !spec.has_flag(flags::READONLY) {
+            return;
+        }
+        for key in spec.keys(args) {
+            state
+                .keys
+                .entry(key.clone())
+                .or_default()
+                .insert(client.id());
+        }
+    }
+
+    /// Tells the clients tracking `key` that it changed.
+    pub fn invalidate(&self, key: &str) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let readers = state.keys.remove(key).unwrap_or_default();
+        for tracker in state.trackers.values() {
+            let interested = if tracker.bcast {
+                tracker.matches_prefix(key)
+            } else {
+                readers.contains(&tracker.client.id())
+            };
+            if interested {
+                tracker.invalidate(vec![key.to_string()]);
+            }
+        }
+    }
+
+    /// Stops tracking for a client that disconnected, and breaks the
+    /// redirects pointing at it.
+    pub fn forget(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.trackers.remove(&id);
+        for tracker in state.trackers.values_mut() {
+            if let Some((redirect, target)) = &mut tracker.redirect
+                && *redirect == id
+            {
+                *target = None;
+            }
+        }
+        self.set_active(&state);
+    }
+}
+
+fn bulk(s: &str) -> RESP {
+    RESP::BulkString(s.into())
+}
+
+fn invalid(message: &str) -> ServerError {
+    ServerError::InvalidArgument(message.to_string())
+}
+
+/// CLIENT TRACKING (ON|OFF) [REDIRECT id] [PREFIX prefix ...] [BCAST]
+/// [OPTIN] [OPTOUT], with `args` starting after TRACKING.
+pub fn client_tracking(
+    server: &Server,
+    client: &Arc<Client>,
+    args: &[CommandArg],
+) -> ServerResult<RESP> {
+    let syntax = || invalid("syntax error");
+    let on = match args[0].to_lowercase().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Err(syntax()),
+    };
+    let mut redirect = None;
+    let mut prefixes = Vec::new();
+    let (mut bcast, mut optin, mut optout) = (false, false, false);
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_lowercase().as_str() {
+            "redirect" => {
+                let id = args.get(i + 1).ok_or_else(syntax)?;
+                if redirect.is_some() {
+                    return Err(invalid(
+                        "A client can only redirect to a single other client",
+                    ));
+                }
+                redirect = Some(
+                    id.parse::<u64>()
+                        .map_err(|_| invalid("value is not an integer or out of range"))?,
+                );
+                i += 1;
+            }
+            "prefix" => {
+                prefixes.push(args.get(i + 1).ok_or_else(syntax)?.to_string());
+                i += 1;
+            }
+            "bcast" => bcast = true,
+            "optin" => optin = true,
+            "optout" => optout = true,
+            _ => return Err(syntax()),
+        }
+        i += 1;
+    }
+
+    let tracking = &server.tracking;
+    let mut state = tracking.state.lock().unwrap();
+    if !on {
+        state.trackers.remove(&client.id());
+        tracking.set_active(&state);
+        return Ok(RESP::SimpleString("OK".to_string()));
+    }
+    if !bcast && !prefixes.is_empty() {
+        return Err(invalid("PREFIX option requires BCAST mode to be enabled"));
+    }
+    if bcast && (optin || optout) {
+        return Err(invalid("OPTIN and OPTOUT are not compatible with BCAST"));
+    }
+    if optin && optout {
+        return Err(invalid("You can't use both OPTIN and OPTOUT"));
+    }
+    let caching = match (optin, optout) {
+        (true, _) => Caching::OptIn,
+        (_, true) => Caching::OptOut,
+        _ => Caching::Always,
+    };
+    let current = state.trackers.get(&client.id());
+    if let Some(current) = current {
+        if current.bcast != bcast {
+            return Err(invalid(
+                "You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode.",
+            ));
+        }
+        if current.caching != caching {
+            return Err(invalid(
+                "You can't switch OPTIN/OPTOUT mode before disabling tracking for this client, and then re-enabling it with a different mode.",
+            ));
+        }
+    }
+    // Prefixes add to the ones already registered, and none may contain
+    // another, so a key is only ever reported once
+    let mut all = current.map(|c| c.prefixes.clone()).unwrap_or_default();
+    for prefix in prefixes {
+        if let Some(other) = all
+            .iter()
+            .find(|p| p.starts_with(&prefix) || prefix.starts_with(p.as_str()))
+        {
+            return Err(ServerError::InvalidArgument(format!(
+                "Prefix '{}' overlaps with an existing prefix '{}'. Prefixes for a single client must not overlap.",
+                prefix, other
+            )));
+        }
+        all.push(prefix.to_string());
+    }
+    let redirect = match redirect {
+        Some(id) if id != client.id() => match server.clients.get(id) {
+            Some(target) => Some((id, Some(target))),
+            None => {
+                return Err(invalid("The client ID you want redirect to does not exist"));
+            }
+        },
+        // Redirecting to itself is the same as not redirecting
+        _ => None,
+    };
+    state.trackers.insert(
+        client.id(),
+        Tracker {
+            client: client.clone(),
+            redirect,
+            bcast,
+            prefixes: all,
+            caching,
+            next: None,
+        },
+    );
+    tracking.set_active(&state);
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+/// CLIENT CACHING (YES|NO), deciding whether the next command's reads
+/// are tracked in OPTIN or OPTOUT mode.
+pub fn client_caching(server: &Server, client: &Client, value: &str) -> ServerResult<RESP> {
+    let yes = match value.to_lowercase().as_str() {
+        "yes" => true,
+        "no" => false,
+        _ => return Err(invalid("syntax error")),
+    };
+    let mut state = server.tracking.state.lock().unwrap();
+    let tracker = state
+        .trackers
+        .get_mut(&client.id())
+        .filter(|t| t.caching != Caching::Always)
+        .ok_or_else(|| {
+            invalid("CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled")
+        })?;
+    match (tracker.caching, yes) {
+        (Caching::OptOut, true) => Err(invalid(
+            "CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.",
+        )),
+        (Caching::OptIn, false) => Err(invalid(
+            "CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.",
+        )),
+        _ => {
+            tracker.next = Some(yes);
+            Ok(RESP::SimpleString("OK".to_string()))
+        }
+    }
+}
+
+/// CLIENT GETREDIR: -1 when not tracking, 0 when not redirecting.
+pub fn client_getredir(server: &Server, client: &Client) -> RESP {
+    let state = server.tracking.state.lock().unwrap();
+    let redirect = match state.trackers.get(&client.id()) {
+        None => -1,
+        Some(tracker) => tracker.redirect.as_ref().map_or(0, |(id, _)| *id as i64),
+    };
+    RESP::Integer(redirect)
+}
+
+/// CLIENT TRACKINGINFO, as the flat RESP2 form of its map.
+pub fn client_trackinginfo(server: &Server, client: &Client) -> RESP {
+    let state = server.tracking.state.lock().unwrap();
+    let Some(tracker) = state.trackers.get(&client.id()) else {
+        return RESP::Array(vec![
+            bulk("flags"),
+            RESP::Array(vec![bulk("off")]),
+            bulk("redirect"),
+            RESP::Integer(-1),
+            bulk("prefixes"),
+            RESP::Array(Vec::new()),
+        ]);
+    };
+    let mut tracking_flags = vec![bulk("on")];
+    if tracker.bcast {
+        tracking_flags.push(bulk("bcast"));
+    }
+    match tracker.caching {
+        Caching::Always => (),
+        Caching::OptIn => tracking_flags.push(bulk("optin")),
+        Caching::OptOut => tracking_flags.push(bulk("optout")),
+    }
+    match tracker.next {
+        Some(true) => tracking_flags.push(bulk("caching-yes")),
+        Some(false) => tracking_flags.push(bulk("caching-no")),
+        None => (),
+    }
+    if let Some((_, None)) = tracker.redirect {
+        tracking_flags.push(bulk("broken_redirect"));
+    }
+    let redirect = tracker.redirect.as_ref().map_or(0, |(id, _)| *id as i64);
+    RESP::Array(vec![
+        bulk("flags"),
+        RESP::Array(tracking_flags),
+        bulk("redirect"),
+        RESP::Integer(redirect),
+        bulk("prefixes"),
+        RESP::Array(tracker.prefixes.iter().map(|p| bulk(p)).collect()),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{connect, send};
+
+    fn invalidate(keys: &[&str]) -> RESP {
+        RESP::Push(vec![
+            bulk("invalidate"),
+            RESP::Array(keys.iter().map(|key| bulk(key)).collect()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_default_mode_invalidates_keys_read() {
+        let server = Arc::new(Server::default());
+        let reader = connect(&server);
+        let writer = connect(&server);
+        send(&server, &reader, &["client", "tracking", "on"]).await;
+        send(&server, &writer, &["set", "a", "1"]).await;
+        send(&server, &writer, &["set", "b", "1"]).await;
+        assert!(reader.take_pushes().is_empty());
+
+        send(&server, &reader, &["get", "a"]).await;
+        send(&server, &writer, &["set", "a", "2"]).await;
+        send(&server, &writer, &["set", "b", "2"]).await;
+        assert_eq!(reader.take_pushes(), vec![invalidate(&["a"])]);
+        // Until it's read again, the key isn't tracked anymore
+        send(&server, &writer, &["del", "a"]).await;
+        assert!(reader.take_pushes().is_empty());
+
+        send(&server, &reader, &["get", "a"]).await;
+        send(&server, &reader, &["client", "tracking", "off"]).await;
+        send(&server, &writer, &["set", "a", "3"]).await;
+        assert!(reader.take_pushes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bcast_prefixes() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        assert_eq!(
+            send(
+                &server,
+                &client,
+                &["client", "tracking", "on", "prefix", "user:"]
+            )
+            .await,
+            RESP::Error("ERR PREFIX option requires BCAST mode to be enabled".to_string())
+        );
+        let on = ["client", "tracking", "on", "bcast", "prefix", "user:"];
+        send(&server, &client, &on).await;
+        assert_eq!(
+            send(&server, &client, &["client", "tracking", "on", "bcast", "prefix", "user:1"]).await,
+            RESP::Error("ERR Prefix 'user:1' overlaps with an existing prefix 'user:'. Prefixes for a single client must not overlap.".to_string())
+        );
+        send(&server, &client, &["mset", "user:1", "a", "order:1", "b"]).await;
+        assert_eq!(client.take_pushes(), vec![invalidate(&["user:1"])]);
+        send(&server, &client, &["set", "user:1", "c"]).await;
+        assert_eq!(client.take_pushes(), vec![invalidate(&["user:1"])]);
+    }
+
+    #[tokio::test]
+    async fn test_optin_needs_caching_yes() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        send(&server, &client, &["client", "tracking", "on", "optin"]).await;
+        send(&server, &client, &["get", "a"]).await;
+        send(&server, &client, &["set", "a", "1"]).await;
+        assert!(client.take_pushes().is_empty());
+
+        let ok = RESP::SimpleString("OK".to_string());
+        assert_eq!(
+            send(&server, &client, &["client", "caching", "yes"]).await,
+            ok
+        );
+        send(&server, &client, &["get", "a"]).await;
+        send(&server, &client, &["set", "a", "2"]).await;
+        assert_eq!(client.take_pushes(), vec![invalidate(&["a"])]);
+        assert!(matches!(
+            send(&server, &client, &["client", "caching", "no"]).await,
+            RESP::Error(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_redirect() {
+        let server = Arc::new(Server::default());
+        let client = connect(&server);
+        let target = connect(&server);
+        let id = target.id().to_string();
+        assert!(matches!(
+            send(
+                &server,
+                &client,
+                &["client", "tracking", "on", "redirect", "999"]
+            )
+            .await,
+            RESP::Error(_)
+        ));
+        send(
+            &server,
+            &client,
+            &["client", "tracking", "on", "redirect", &id],
+        )
+        .await;
+        assert_eq!(
+            send(&server, &client, &["client", "getredir"]).await,
+            RESP::Integer(target.id() as i64)
+        );
+        send(&server, &client, &["get", "a"]).await;
+        send(&server, &client, &["set", "a", "1"]).await;
+        assert!(client.take_pushes().is_empty());
+        assert_eq!(
+            target.take_pushes(),
+            vec![RESP::Array(vec![
+                bulk("message"),
+                bulk(INVALIDATE_CHANNEL),
+                RESP::Array(vec![bulk("a")]),
+            ])]
+        );
+
+        // Once the target is gone, the client is told instead
+        server.clients.unregister(target.id());
+        server.tracking.forget(target.id());
+        send(&server, &client, &["get", "a"]).await;
+        send(&server, &client, &["set", "a", "2"]).await;
+        assert_eq!(
+            client.take_pushes(),
+            vec![RESP::Push(vec![
+                bulk("tracking-redir-broken"),
+                RESP::Integer(target.id() as i64),
+            ])]
+        );
+    }
+}
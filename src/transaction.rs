@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::client::Client;
+use crate::command::{Command, CommandArg, CommandSpec, flags};
+use crate::resp::RESP;
+use crate::server::{Server, ServerError, ServerResult, command_ping};
+
+/// The keys clients WATCH, each with the flags of the clients watching it.
+/// Storage touches a key here whenever it changes, which makes the
+/// watchers' next EXEC fail.
+#[derive(Default)]
+pub struct Watches {
+    keys: Mutex<HashMap<String, Vec<Arc<AtomicBool>>>>,
+    /// Whether any key is watched, so writes skip the lock otherwise
+    active: AtomicBool,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tells the clients watching `key` that it changed.
+    pub fn touch(&self, key: &str) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(watchers) = self.keys.lock().unwrap().get(key) {
+            for touched in watchers {
+                touched.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn watch(&self, key: &str, touched: &Arc<AtomicBool>) {
+        let mut keys = self.keys.lock().unwrap();
+        keys.entry(key.to_string())
+            .or_default()
+            .push(touched.clone());
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    fn unwatch(&self, watched: &[String], touched: &Arc<AtomicBool>) {
+        let mut keys = self.keys.lock().unwrap();
+        for key in watched {
+            if let Some(watchers) = keys.get_mut(key) {
+                watchers.retain(|w| !Arc::ptr_eq(w, touched));
+                if watchers.is_empty() {
+                    keys.remove(key);
+                }
+            }
+        }
+        self.active.store(!keys.is_empty(), Ordering::Relaxed);
+    }
+}
+
+/// A connection's MULTI state and the keys it watches.
+#[derive(Default)]
+pub struct Transaction {
+    /// Commands queued since MULTI, None outside one
+    queued: Option<Vec<(&'static CommandSpec, Vec<CommandArg>)>>,
+    /// Set when a command was refused while queueing, so EXEC aborts
+    failed: bool,
+    watched: Vec<String>,
+    /// Set once any watched key changes
+    touched: Arc<AtomicBool>,
+}
+
+impl Transaction {
+    pub fn is_open(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// How many commands are queued, or None outside MULTI.
+    pub fn queued(&self) -> Option<usize> {
+        self.queued.as_ref().map(Vec::len)
+    }
+
+    /// Makes EXEC abort after a command was refused. Does nothing outside
+    /// MULTI.
+    pub fn fail(&mut self) {
+        if self.is_open() {
+            self.failed = true;
+        }
+    }
+
+    /// Whether EXEC would write, which CLIENT PAUSE WRITE holds back.
+    pub fn writes(&self) -> bool {
+        self.queued
+            .iter()
+            .flatten()
+            .any(|(spec, _)| spec.has_flag(flags::WRITE))
+    }
+
+    fn unwatch(&mut self, watches: &Watches) {
+        watches.unwatch(&self.watched, &self.touched);
+        self.watched.clear();
+        self.touched.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Whether EXEC can run a command: anything served by the keyspace, which
+/// runs as one batch, plus PING and ECHO. What the server handles itself
+/// is refused at MULTI time.
+fn runs_in_transaction(command: Command) -> bool {
+    !matches!(
+        command,
+        Command::Command
+            | Command::Config
+            | Command::Quit
+            | Command::Info
+            | Command::Client
+            | Command::Auth
+            | Command::Acl
+            | Command::Eval
+            | Command::EvalSha
+            | Command::EvalRo
+            | Command::EvalShaRo
+            | Command::Script
+            | Command::Function
+            | Command::FCall
+            | Command::FCallRo
+            | Command::ReplicaOf
+            | Command::SlaveOf
+            | Command::ReplConf
+            | Command::PSync
+            | Command::Wait
+            | Command::Cluster
+            | Command::Asking
+            | Command::Migrate
+            | Command::Subscribe
+            | Command::Unsubscribe
+            | Command::PSubscribe
+            | Command::PUnsubscribe
+            | Command::Publish
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Watch
+            | Command::Unwatch
+            | Command::XRead
+            | Command::XReadGroup
+    )
+}
+
+pub fn command_multi(client: &Client) -> ServerResult<RESP> {
+    let mut transaction = client.transaction();
+    if transaction.is_open() {
+        return Err(ServerError::InvalidArgument(
+            "MULTI calls can not be nested".to_string(),
+        ));
+    }
+    transaction.queued = Some(Vec::new());
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+/// Queues a command sent between MULTI and EXEC. One EXEC couldn't run is
+/// refused, and makes EXEC abort.
+pub fn queue(
+    client: &Client,
+    spec: &'static CommandSpec,
+    command: Vec<CommandArg>,
+) -> ServerResult<RESP> {
+    let mut transaction = client.transaction();
+    if !runs_in_transaction(spec.command) {
+        transaction.fail();
+        return Err(ServerError::InvalidArgument(format!(
+            "Command not allowed inside a transaction: '{}'",
+            spec.name
+        )));
+    }
+    transaction
+        .queued
+        .get_or_insert_default()
+        .push((spec, command));
+    Ok(RESP::SimpleString("QUEUED".to_string()))
+}
+
+/// EXEC: runs the queued commands as one batch, holding every shard they
+/// touch, unless a watched key changed first.
+pub async fn command_exec(server: &Server, client: &Client) -> ServerResult<RESP> {
+    let (queued, failed, touched) = {
+        let mut transaction = client.transaction();
+        let Some(queued) = transaction.queued.take() else {
+            return Err(ServerError::InvalidArgument(
+                "EXEC without MULTI".to_string(),
+            ));
+        };
+        let failed = std::mem::take(&mut transaction.failed);
+        (queued, failed, transaction.touched.clone())
+    };
+    let result = if failed {
+        Err(ServerError::ExecAbort)
+    } else {
+        exec(server, queued, touched).await
+    };
+    client.transaction().unwatch(&server.watches);
+    result
+}
+
+async fn exec(
+    server: &Server,
+    queued: Vec<(&'static CommandSpec, Vec<CommandArg>)>,
+    touched: Arc<AtomicBool>,
+) -> ServerResult<RESP> {
+    // PING and ECHO don't need the keyspace, so only the rest is batched
+    let mut replies = Vec::with_capacity(queued.len());
+    let mut specs = Vec::with_capacity(queued.len());
+    let mut requests = Vec::new();
+    for (spec, command) in queued {
+        specs.push(spec);
+        replies.push(match spec.command {
+            Command::Ping => Some(command_ping(&command)),
+            Command::Echo => Some(RESP::BulkString(command[1].as_bytes().to_vec())),
+            _ => {
+                requests.push((spec, command));
+                None
+            }
+        });
+    }
+    let start = Instant::now();
+    let Some(results) = server
+        .storage
+        .execute_batch(&requests, move || !touched.load(Ordering::Relaxed))
+        .await
+    else {
+        return Ok(RESP::Null);
+    };
+    // The batch runs as a whole, so its commands share its time equally
+    let elapsed = start.elapsed() / specs.len().max(1) as u32;
+    let mut results = results.into_iter();
+    let replies = replies
+        .into_iter()
+        .zip(specs)
+        .map(|(reply, spec)| {
+            let result = match reply {
+                Some(reply) => Ok(reply),
+                None => results
+                    .next()
+                    .expect("a result for every batched request")
+                    .map_err(ServerError::from),
+            };
+            server.stats.record_command(spec, elapsed, result.is_err());
+            result.unwrap_or_else(|e| RESP::Error(e.to_string()))
+        })
+        .collect();
+    Ok(RESP::Array(replies))
+}
+
+pub fn command_discard(server: &Server, client: &Client) -> ServerResult<RESP> {
+    let mut transaction = client.transaction();
+    if transaction.queued.take().is_none() {
+        return Err(ServerError::InvalidArgument(
+            "DISCARD without MULTI".to_string(),
+        ));
+    }
+    transaction.failed = false;
+    transaction.unwatch(&server.watches);
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+pub fn command_watch(server: &Server, client: &Client, keys: &[CommandArg]) -> ServerResult<RESP> {
+    let mut transaction = client.transaction();
+    if transaction.is_open() {
+        return Err(ServerError::InvalidArgument(
+            "WATCH inside MULTI is not allowed".to_string(),
+        ));
+    }
+    for key in keys {
+        if !transaction.watched.contains(key) {
+            server.watches.watch(key, &transaction.touched);
+            transaction.watched.push(key.to_string());
+        }
+    }
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+pub fn command_unwatch(server: &Server, client: &Client) -> ServerResult<RESP> {
+    client.transaction().unwatch(&server.watches);
+    Ok(RESP::SimpleString("OK".to_string()))
+}
+
+/// Drops a closing connection's watched keys.
+pub fn forget(server: &Server, client: &Client) {
+    client.transaction().unwatch(&server.watches);
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::storage::{Keyspace, ShardPool};
+    use crate::test_support::{exchange, listen, listen_with, reconnect};
+
+    async fn exec_runs_queued_commands(storage: Keyspace) {
+        let (_server, mut stream) = listen_with(storage).await;
+        assert_eq!(exchange(&mut stream, &[b"multi"]).await, b"+OK\r\n");
+        for request in [
+            &[&b"set"[..], b"a", b"1"][..],
+            &[b"incr", b"a"],
+            &[b"mset", b"b", b"2", b"c", b"3"],
+            &[b"ping"],
+            &[b"mget", b"a", b"b", b"c"],
+        ] {
+            assert_eq!(exchange(&mut stream, request).await, b"+QUEUED\r\n");
+        }
+        assert_eq!(
+            exchange(&mut stream, &[b"exec"]).await,
+            b"*5\r\n+OK\r\n:2\r\n+OK\r\n+PONG\r\n*3\r\n$1\r\n2\r\n$1\r\n2\r\n$1\r\n3\r\n"
+        );
+        assert_eq!(
+            exchange(&mut stream, &[b"exec"]).await,
+            b"-ERR EXEC without MULTI\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exec_runs_queued_commands() {
+        exec_runs_queued_commands(Keyspace::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_exec_runs_queued_commands_shared_nothing() {
+        exec_runs_queued_commands(Keyspace::SharedNothing(ShardPool::new(4))).await;
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_transaction() {
+        let (_server, mut stream) = listen().await;
+        stream
+            .write_all(
+                b"*1\r\n$5\r\nMULTI\r\n*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n\
+                  *2\r\n$4\r\nINCR\r\n$1\r\na\r\n*1\r\n$4\r\nEXEC\r\n",
+            )
+            .await
+            .unwrap();
+        let expected = b"+OK\r\n+QUEUED\r\n+QUEUED\r\n*2\r\n+OK\r\n:2\r\n";
+        let mut reply = vec![0; expected.len()];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    #[tokio::test]
+    async fn test_discard_drops_queued_commands() {
+        let (_server, mut stream) = listen().await;
+        exchange(&mut stream, &[b"multi"]).await;
+        exchange(&mut stream, &[b"set", b"a", b"1"]).await;
+        assert_eq!(exchange(&mut stream, &[b"discard"]).await, b"+OK\r\n");
+        assert_eq!(exchange(&mut stream, &[b"get", b"a"]).await, b"$-1\r\n");
+        assert_eq!(
+            exchange(&mut stream, &[b"discard"]).await,
+            b"-ERR DISCARD without MULTI\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refused_command_aborts_exec() {
+        let (_server, mut stream) = listen().await;
+        exchange(&mut stream, &[b"multi"]).await;
+        exchange(&mut stream, &[b"set", b"a", b"1"]).await;
+        assert!(exchange(&mut stream, &[b"get"]).await.starts_with(b"-ERR"));
+        assert_eq!(
+            exchange(&mut stream, &[b"publish", b"c", b"m"]).await,
+            b"-ERR Command not allowed inside a transaction: 'publish'\r\n"
+        );
+        assert_eq!(
+            exchange(&mut stream, &[b"exec"]).await,
+            b"-EXECABORT Transaction discarded because of previous errors.\r\n"
+        );
+        assert_eq!(exchange(&mut stream, &[b"get", b"a"]).await, b"$-1\r\n");
+    }
+
+    async fn watched_key_change_aborts_exec(storage: Keyspace) {
+        let (server, mut stream) = listen_with(storage).await;
+        let mut other = reconnect(&server).await;
+        assert_eq!(
+            exchange(&mut stream, &[b"watch", b"a", b"b"]).await,
+            b"+OK\r\n"
+        );
+        exchange(&mut other, &[b"set", b"b", b"1"]).await;
+        exchange(&mut stream, &[b"multi"]).await;
+        exchange(&mut stream, &[b"set", b"a", b"1"]).await;
+        assert_eq!(exchange(&mut stream, &[b"exec"]).await, b"$-1\r\n");
+        assert_eq!(exchange(&mut stream, &[b"get", b"a"]).await, b"$-1\r\n");
+
+        // EXEC unwatched everything, so the next transaction goes through
+        exchange(&mut other, &[b"set", b"b", b"2"]).await;
+        exchange(&mut stream, &[b"multi"]).await;
+        exchange(&mut stream, &[b"set", b"a", b"1"]).await;
+        assert_eq!(exchange(&mut stream, &[b"exec"]).await, b"*1\r\n+OK\r\n");
+
+        exchange(&mut stream, &[b"watch", b"a"]).await;
+        assert_eq!(exchange(&mut stream, &[b"unwatch"]).await, b"+OK\r\n");
+        exchange(&mut other, &[b"set", b"a", b"2"]).await;
+        exchange(&mut stream, &[b"multi"]).await;
+        assert_eq!(
+            exchange(&mut stream, &[b"watch", b"a"]).await,
+            b"-ERR WATCH inside MULTI is not allowed\r\n"
+        );
+        exchange(&mut stream, &[b"get", b"a"]).await;
+        assert_eq!(
+            exchange(&mut stream, &[b"exec"]).await,
+            b"*1\r\n$1\r\n2\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watched_key_change_aborts_exec() {
+        watched_key_change_aborts_exec(Keyspace::default()).await;
+    }
+
+    #[tokio::test]
+    async fn test_watched_key_change_aborts_exec_shared_nothing() {
+        watched_key_change_aborts_exec(Keyspace::SharedNothing(ShardPool::new(4))).await;
+    }
+}